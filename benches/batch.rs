@@ -0,0 +1,54 @@
+//! A thousand commits made through one `wk batch` vs a thousand `wk c`
+//! processes, each of which loads the whole wiki again.
+//!
+//! cargo bench --bench batch
+
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use twk::fixture::{Fixture, FixtureWiki};
+
+const COMMITS: usize = 1_000;
+
+fn wk(fixture: &Fixture) -> Command {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_wk"));
+    cmd.arg("-g").arg("-q").envs(fixture.env()).current_dir(fixture.scratch());
+    cmd
+}
+
+fn commits(c: &mut Criterion) {
+    let mut group = c.benchmark_group("1000 commits");
+    group.sample_size(10);
+    let empty = || FixtureWiki::new().facts(0).build().unwrap();
+
+    group.bench_function("wk batch", |b| {
+        b.iter_batched(
+            empty,
+            |fixture| {
+                let input: String = (0..COMMITS).map(|i| format!("c {{\"text\": \"fact {}\", \"tags\": [\"bench\"]}}\n", i)).collect();
+                let mut child = wk(&fixture).arg("batch").stdin(Stdio::piped()).stdout(Stdio::null()).spawn().unwrap();
+                child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+                assert!(child.wait().unwrap().success());
+                fixture
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    group.bench_function("wk c each", |b| {
+        b.iter_batched(
+            empty,
+            |fixture| {
+                for i in 0..COMMITS {
+                    let status = wk(&fixture).args(["c", &format!("fact {}", i), "bench", "--no-dup-check"]).status().unwrap();
+                    assert!(status.success());
+                }
+                fixture
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, commits);
+criterion_main!(benches);
@@ -0,0 +1,106 @@
+//! Cloning vs borrowing recall on a generated corpus of large facts, and
+//! scoring on one thread vs all of them over many small ones.
+//!
+//! cargo bench --bench recall
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+use twk::{Fields, TimeWindow, Wiki};
+use twk::storage::MemoryStorage;
+use uuid::Uuid;
+
+fn corpus() -> (Wiki, std::path::PathBuf) {
+    let dir = std::env::temp_dir().join(format!("twk-bench-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut wiki = Wiki::with_storage("bench".to_string(), dir.clone(), Box::new(MemoryStorage::new())).unwrap();
+
+    // Notes the size of an embedded config dump
+    let facts = (0..2_000)
+        .map(|i| {
+            let body = format!("service-{} listen 0.0.0.0:{} upstream pool\n", i, 8000 + i).repeat(200);
+            (format!("config {}\n{}", i, body), vec![format!("tag{}", i % 10)])
+        })
+        .collect();
+    wiki.commit_many(facts).unwrap();
+    (wiki, dir)
+}
+
+fn recall(c: &mut Criterion) {
+    let (wiki, dir) = corpus();
+    let mut group = c.benchmark_group("recall");
+    group.sample_size(20);
+
+    group.bench_function("clone all", |b| b.iter(|| black_box(wiki.recall(black_box("upstream"), None))));
+    group.bench_function("refs", |b| {
+        b.iter(|| black_box(wiki.recall_refs(black_box("upstream"), None, Fields::ALL, TimeWindow::ANY, None).len()))
+    });
+    group.bench_function("refs top 10", |b| {
+        b.iter(|| black_box(wiki.recall_refs(black_box("upstream"), None, Fields::ALL, TimeWindow::ANY, Some(10)).len()))
+    });
+    group.bench_function("by tag, clone", |b| b.iter(|| black_box(wiki.recall_by_tag(black_box("tag3")))));
+    group.bench_function("by tag, refs", |b| {
+        b.iter(|| black_box(wiki.recall_by_tag_refs(black_box("tag3"), TimeWindow::ANY).len()))
+    });
+    group.finish();
+
+    drop(wiki);
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+/// Ten thousand facts of a few lines each, in plain ASCII or not
+fn many_facts(ascii: bool) -> (Wiki, std::path::PathBuf) {
+    let dir = std::env::temp_dir().join(format!("twk-bench-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut wiki = Wiki::with_storage("bench".to_string(), dir.clone(), Box::new(MemoryStorage::new())).unwrap();
+    let facts = (0..10_000)
+        .map(|i| {
+            let line = match ascii {
+                true => format!("host-{} runs service-{} behind the upstream pool\n", i, i % 97),
+                false => format!("hôte-{} sert le service-{} derrière le pool en amont\n", i, i % 97),
+            };
+            let body = line.repeat(4);
+            (format!("note {}\n{}", i, body), vec![format!("tag{}", i % 10)])
+        })
+        .collect();
+    wiki.commit_many(facts).unwrap();
+    (wiki, dir)
+}
+
+fn parallel(c: &mut Criterion) {
+    let (wiki, dir) = many_facts(true);
+    let mut group = c.benchmark_group("recall 10k");
+    group.sample_size(20);
+
+    let threads = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    for (label, n) in [("one thread", 1), ("every thread", threads)] {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(n).build().unwrap();
+        group.bench_function(label, |b| {
+            b.iter(|| {
+                pool.install(|| {
+                    black_box(wiki.recall_refs(black_box("service upstream"), None, Fields::ALL, TimeWindow::ANY, None).len())
+                })
+            })
+        });
+    }
+    group.finish();
+
+    drop(wiki);
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+/// Recall over text the matcher has to convert to UTF-32 first
+fn unicode(c: &mut Criterion) {
+    let (wiki, dir) = many_facts(false);
+    let mut group = c.benchmark_group("recall 10k unicode");
+    group.sample_size(20);
+    group.bench_function("refs", |b| {
+        b.iter(|| black_box(wiki.recall_refs(black_box("service amont"), None, Fields::ALL, TimeWindow::ANY, None).len()))
+    });
+    group.finish();
+
+    drop(wiki);
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+criterion_group!(benches, recall, parallel, unicode);
+criterion_main!(benches);
@@ -0,0 +1,50 @@
+//! Compare eager and lazy cold-start on a generated files-backed wiki.
+//!
+//! cargo run --release --example cold_start -- [facts]
+
+use std::time::Instant;
+use twk::Information;
+use twk::storage::{FsStorage, Storage};
+use uuid::Uuid;
+
+fn main() -> std::io::Result<()> {
+    let count: usize = std::env::args()
+        .nth(1)
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(10_000);
+
+    let dir = std::env::temp_dir().join(format!("twk-cold-start-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir)?;
+
+    // Plain writes; the atomic, fsynced path would dominate setup time
+    for i in 0..count {
+        let info = Information {
+            id: Uuid::new_v4(),
+            tags: vec![format!("tag{}", i % 20)],
+            name: format!("Fact {}", i),
+            data: format!("Fact {}\n{}", i, "Lorem ipsum dolor sit amet. ".repeat(40)),
+            created: None,
+            updated: None,
+            source: None,
+            name_is_derived: false,
+            extra: Default::default(),
+        };
+        let json = serde_json::to_string_pretty(&info).map_err(std::io::Error::other)?;
+        std::fs::write(dir.join(format!("{}.json", info.id)), json)?;
+    }
+
+    let storage = FsStorage::new(&dir);
+    let time = |label: &str, load: &dyn Fn() -> std::io::Result<usize>| -> std::io::Result<()> {
+        let start = Instant::now();
+        let n = load()?;
+        println!("{:<24} {:>8.1?} ({} facts)", label, start.elapsed(), n);
+        Ok(())
+    };
+
+    time("eager load_all", &|| Ok(storage.load_all()?.facts.len()))?;
+    time("lazy, building index", &|| Ok(storage.load_lazy()?.facts.len()))?;
+    time("lazy, warm index", &|| Ok(storage.load_lazy()?.facts.len()))?;
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
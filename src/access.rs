@@ -0,0 +1,92 @@
+//! Which facts were changed or looked at lately, for `wk recent`. Looks are
+//! kept in a small log of their own so reading a fact never rewrites it.
+
+use chrono::{DateTime, Utc};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::helpers::write_atomic;
+use crate::wiki::{Information, Wiki};
+
+/// Name of the access log inside a wiki directory
+pub const ACCESS_LOG_FILE: &str = ".access.log";
+/// Most looks the access log keeps; older ones drop off the front
+pub const ACCESS_LOG_LEN: usize = 500;
+/// How many facts `wk recent` and the TUI's `:recent` list by default
+pub const DEFAULT_RECENT: usize = 10;
+
+impl Wiki {
+    fn access_log_path(&self) -> PathBuf {
+        self.path.join(ACCESS_LOG_FILE)
+    }
+
+    /// Note that the facts `ids` were just recalled or opened. Nothing is
+    /// recorded in a read-only wiki, and failing to write the log is ignored.
+    pub fn record_access(&self, ids: &[Uuid]) {
+        if self.readonly || self.is_dry_run() || ids.is_empty() {
+            return;
+        }
+        let path = self.access_log_path();
+        let now = Utc::now();
+        let mut log = read_log(&path);
+        log.extend(ids.iter().map(|&id| (now, id)));
+        let start = log.len().saturating_sub(ACCESS_LOG_LEN);
+        let text: String = log[start..]
+            .iter()
+            .map(|(at, id)| format!("{} {}\n", at.to_rfc3339(), id))
+            .collect();
+        // Only feeds `wk recent --accessed`, so not worth failing a read over
+        if let Err(e) = write_atomic(&path, text.as_bytes()) {
+            tracing::warn!(path = %path.display(), "couldn't record access: {}", e);
+        }
+    }
+
+    /// The `n` facts changed most recently, newest first. Facts from before
+    /// timestamps were recorded are left out.
+    pub fn recent(&self, n: usize) -> Vec<Information> {
+        let mut changed: Vec<(DateTime<Utc>, Uuid)> = self
+            .info
+            .iter()
+            .filter_map(|locked| {
+                let info = locked.read();
+                Some((info.updated.or(info.created)?, info.id))
+            })
+            .collect();
+        changed.sort_by_key(|&(at, _)| std::cmp::Reverse(at));
+        // Only the facts listed need their data loaded
+        changed.into_iter().take(n).filter_map(|(_, id)| self.get(id).ok()).collect()
+    }
+
+    /// The `n` facts recalled or opened most recently, with when, newest
+    /// first. Each fact is listed once; deleted ones are skipped.
+    pub fn recently_accessed(&self, n: usize) -> Vec<(DateTime<Utc>, Information)> {
+        let mut seen = HashSet::new();
+        let mut out = Vec::new();
+        for (at, id) in read_log(&self.access_log_path()).into_iter().rev() {
+            if out.len() == n {
+                break;
+            }
+            if seen.insert(id)
+                && let Ok(info) = self.get(id)
+            {
+                out.push((at, info));
+            }
+        }
+        out
+    }
+}
+
+/// Every look in the log at `path`, oldest first; lines that can't be read
+/// are skipped and a missing log is empty
+fn read_log(path: &Path) -> Vec<(DateTime<Utc>, Uuid)> {
+    let Ok(text) = std::fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    text.lines()
+        .filter_map(|line| {
+            let (at, id) = line.split_once(' ')?;
+            Some((DateTime::parse_from_rfc3339(at).ok()?.with_timezone(&Utc), id.parse().ok()?))
+        })
+        .collect()
+}
@@ -0,0 +1,1545 @@
+//! The TUI's state and how it changes, kept apart from the terminal.
+//!
+//! The keymap, [`App::key_msg`], turns key presses into [`AppMsg`]s, and
+//! [`App::update`] applies them. Anything that needs the terminal or could
+//! block on the user, such as running `$EDITOR` or asking for a passphrase,
+//! comes back from `update` as an [`Effect`] for the event loop to carry out;
+//! the loop reports how it went with another message. Drawing is left to the
+//! binary.
+
+use chrono::{DateTime, Utc};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::widgets::ListState;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+use crate::access::DEFAULT_RECENT;
+use crate::dirsync::conflict_fact;
+use crate::editor::Edited;
+use crate::preview::{LineMatcher, preview_line};
+use crate::secrets::{SecretMatch, SecretPolicy};
+use crate::tags::{TagNode, tag_matches};
+use crate::usage::frecency;
+use crate::wiki::{Fields, Information, RecallOptions, Wiki, derive_title, derived_name};
+use crate::{Snapshot, WikiError, snippets, wikis};
+
+/// Most facts the fuzzy filter lists, a few screenfuls' worth
+const FILTER_RESULTS: usize = 500;
+
+/// Narrowest terminal that fits two panes side by side; narrower ones stack
+/// them, as two half-width lists would leave no room for previews
+pub const SIDE_BY_SIDE_WIDTH: u16 = 100;
+
+/// Name, Preview, Tags, ID, Path, when it last changed
+pub type ListEntry = (String, String, Vec<String>, Uuid, PathBuf, Option<DateTime<Utc>>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputMode {
+    Normal,
+    Command,
+    Edit,
+    Tags,
+    Retag,
+}
+
+/// Order of the list, set with `:sort`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ListSort {
+    Wiki,
+    Modified,
+    /// Most used first, by [`frecency`]
+    Frecency,
+}
+
+/// Where the first and second of two panes go in `area`: side by side, or
+/// one above the other if `area` is narrower than [`SIDE_BY_SIDE_WIDTH`]
+pub fn split_panes(area: Rect) -> (Rect, Rect) {
+    let direction = match area.width >= SIDE_BY_SIDE_WIDTH {
+        true => Direction::Horizontal,
+        false => Direction::Vertical,
+    };
+    let halves = Layout::default()
+        .direction(direction)
+        .constraints([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)])
+        .split(area);
+    (halves[0], halves[1])
+}
+
+/// Something that happened in the TUI, from a key press or the event loop
+pub enum AppMsg {
+    /// Move down the list, or the tag picker while it is open
+    Next,
+    /// Move up the list, or the tag picker while it is open
+    Previous,
+    /// Scroll the list itself, whatever is open
+    Scroll { down: bool },
+    /// Select the fact at this index of the list, e.g. when clicked
+    Select(usize),
+    /// Start typing a `:` command
+    StartCommand,
+    /// Type a character into whatever is being typed
+    Type(char),
+    Backspace,
+    /// Run the command, apply the picked tag or save the tags being typed
+    Submit,
+    /// Leave the current mode without saving
+    Cancel,
+    /// Save the inline edit
+    Save,
+    /// Earlier and later commands
+    HistoryBack,
+    HistoryForward,
+    /// Fill in the first tag completion
+    Complete,
+    /// Expand or collapse the selected tag in the picker
+    Fold { expand: bool },
+    StartInlineEdit,
+    /// Edit the selected fact in `$EDITOR`
+    OpenEditor,
+    /// Create a fact named after a fuzzy filter that matched nothing, and
+    /// start writing it inline
+    CreateFromFilter,
+    OpenTagPicker,
+    StartRetag,
+    ShowSnapshots,
+    ShowDetails,
+    ToggleHelp,
+    CloseHelp,
+    /// Close the popup on top
+    DismissPopup,
+    /// Answer the question in the status bar
+    Answer(Reply),
+    Quit,
+    /// The fact sent to `$EDITOR` as it was saved there, with when it was
+    /// read for it
+    Edited { id: Uuid, read_updated: Option<DateTime<Utc>>, edited: Edited },
+    /// A wiki asked for with [`Effect::OpenWiki`], opened or not
+    WikiOpened(Result<Box<Wiki>, WikiError>),
+    /// Ctrl-W, the first key of Ctrl-W w
+    WindowPrefix,
+    /// Move focus to the other pane
+    FocusOther,
+    /// A wiki asked for with [`Effect::OpenSplit`], opened or not
+    SplitOpened(Result<Box<Wiki>, WikiError>),
+    /// How many facts [`Effect::Reindex`] indexed
+    Reindexed(Result<usize, WikiError>),
+    /// The end of the log, for [`Effect::ShowLog`]
+    LogRead(std::io::Result<Vec<String>>),
+    /// Show this in the status bar, e.g. why an effect failed
+    Status(String),
+}
+
+/// A key pressed to answer a question in the status bar
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reply {
+    Yes,
+    No,
+    /// `c`, to create a wiki rather than open the one suggested, or a
+    /// conflict copy rather than overwrite
+    Create,
+    /// `o`, to save over a change made elsewhere
+    Overwrite,
+    /// `r`, to take a change made elsewhere and drop one's own
+    Reload,
+    /// Enter, taking whatever the question suggests
+    Default,
+}
+
+/// Work [`App::update`] leaves to the event loop
+#[derive(Debug, PartialEq)]
+pub enum Effect {
+    Quit,
+    /// Open this fact in `$EDITOR`, then send [`AppMsg::Edited`]
+    OpenEditor(Information),
+    /// Load or create this wiki, which may ask for a passphrase on the
+    /// terminal, then send [`AppMsg::WikiOpened`]
+    OpenWiki(String),
+    /// Open this existing wiki, which may ask for a passphrase, then send
+    /// [`AppMsg::SplitOpened`]
+    OpenSplit(String),
+    /// Rebuild the search index of `App::wiki`, showing progress, then send
+    /// [`AppMsg::Reindexed`]
+    Reindex,
+    /// Read the end of this log, then send [`AppMsg::LogRead`]
+    ShowLog(PathBuf),
+}
+
+pub struct App {
+    pub wiki: Wiki,
+    pub items: Vec<ListEntry>,
+    pub state: ListState,
+    pub input_mode: InputMode,
+    pub input: String,
+    pub status_msg: String,
+    status_timer: Option<Instant>,
+    status_duration: Duration,
+    use_global: bool,
+    history: Vec<String>,
+    history_pos: Option<usize>,
+    filter: Option<String>,
+    filter_regex: Option<Regex>,
+    /// What the filter is matched against; only the first line of data is
+    filter_fields: Fields,
+    pub show_help: bool,
+    // Inline edit state
+    pub edit_buffer: String,
+    editing_id: Option<Uuid>,
+    /// The inline editor holds a new fact started by `:n` without a name
+    drafting: bool,
+    /// Whether the wiki's git repository has changes not yet synced
+    pub unsynced: bool,
+    // Tag picker state
+    tag_tree: Vec<TagNode>,
+    pub expanded_tags: HashSet<String>,
+    pub tag_state: ListState,
+    /// Only list facts with this tag or one nested under it
+    pub tag_filter: Option<String>,
+    /// Name of the fact whose snapshots are shown, with the snapshots
+    pub snapshot_popup: Option<(String, Vec<Snapshot>)>,
+    /// Fact whose details are shown
+    pub detail_popup: Option<Information>,
+    /// Where `--log-file` is logging to, if anywhere
+    pub log_file: Option<PathBuf>,
+    /// The end of the log, shown by `:log`
+    pub log_popup: Option<Vec<String>>,
+    /// Show how long ago each fact changed on the right of the list
+    pub show_age: bool,
+    /// Order of the list, set with `:sort`
+    sort: ListSort,
+    /// Only list this many of the most recently changed facts, newest first;
+    /// set with `:recent`
+    pub recent: Option<usize>,
+    // Tag editor state
+    pub retag_input: String,
+    retag_id: Option<Uuid>,
+    /// Tags that fit the fact being retagged, best first
+    retag_suggestions: Vec<String>,
+    /// A wiki asked for by `:wiki` that doesn't exist, with the closest
+    /// existing name, waiting for the next key to say which to open
+    pending_switch: Option<(String, Option<String>)>,
+    /// A fact `:delete --hard` would remove for good, with its name, waiting
+    /// for `y` to go ahead
+    pending_delete: Option<(Uuid, String)>,
+    /// Look for secrets in what is saved, unless `--no-scan` was given
+    scan: bool,
+    /// A save that looks like it holds a secret, waiting for `y` to go ahead
+    pending_secret: Option<PendingSave>,
+    /// An edit to a fact saved elsewhere since it was read, waiting to hear
+    /// whether to overwrite, reload or keep it as a conflict copy
+    pending_conflict: Option<PendingSave>,
+    /// A fact whose first line an inline edit changed, with the name that
+    /// line gives it, waiting for `y` to rename it
+    pending_rename: Option<(Uuid, String)>,
+    /// The pane opened by `:split`, which keys don't reach until focus
+    /// moves to it and this pane takes its place here
+    pub other: Option<Box<App>>,
+    /// Whether this pane is drawn first, left of or above the other one
+    pub first: bool,
+    /// Ctrl-W was pressed, and `w` would move focus to the other pane
+    window_prefix: bool,
+}
+
+/// A save held back by [`App::screen_secrets`]
+enum PendingSave {
+    Create(Information),
+    Inline(Uuid, String),
+    /// From the external editor, with when the fact was read for it
+    Editor(Uuid, Option<DateTime<Utc>>, Edited),
+}
+
+impl PendingSave {
+    /// Make the change to `info`; a new fact has nothing to change
+    fn apply(self, info: &mut Information) {
+        match self {
+            PendingSave::Create(_) => {}
+            PendingSave::Inline(_, data) => info.data = data,
+            PendingSave::Editor(_, _, edited) => apply_edited(info, edited),
+        }
+    }
+}
+
+/// Write what was saved in the external editor into `info`
+fn apply_edited(info: &mut Information, edited: Edited) {
+    let title = edited.title.trim();
+    if !title.is_empty() && title != info.name.trim() {
+        info.name = title.to_string();
+        info.name_is_derived = false;
+    }
+    if let Some(tags) = edited.tags {
+        info.tags = tags;
+    }
+    if let Some(source) = edited.source {
+        info.source = source;
+    }
+    info.data = edited.body;
+}
+
+/// What [`App::screen_secrets`] made of text about to be saved
+enum Screened {
+    /// Save it as it is now, secrets redacted if there were any
+    Save,
+    /// Ask first; the question is in the status bar
+    Ask,
+    /// Don't save it
+    Refuse,
+}
+
+impl App {
+    pub fn new(wiki: Wiki, use_global: bool, log_file: Option<PathBuf>, scan: bool) -> App {
+        let show_age = wiki.config.date_column;
+        let mut app = App {
+            wiki,
+            items: Vec::new(),
+            state: ListState::default(),
+            input_mode: InputMode::Normal,
+            input: String::new(),
+            status_msg: String::new(),
+            status_timer: None,
+            status_duration: Duration::from_secs(3),
+            use_global,
+            history: Vec::new(),
+            history_pos: None,
+            filter: None,
+            filter_regex: None,
+            filter_fields: Fields::ALL,
+            show_help: false,
+            edit_buffer: String::new(),
+            editing_id: None,
+            drafting: false,
+            unsynced: false,
+            tag_tree: Vec::new(),
+            expanded_tags: HashSet::new(),
+            tag_state: ListState::default(),
+            tag_filter: None,
+            snapshot_popup: None,
+            detail_popup: None,
+            log_file,
+            log_popup: None,
+            show_age,
+            sort: ListSort::Wiki,
+            recent: None,
+            retag_input: String::new(),
+            retag_id: None,
+            retag_suggestions: Vec::new(),
+            pending_switch: None,
+            pending_delete: None,
+            scan,
+            pending_secret: None,
+            pending_conflict: None,
+            pending_rename: None,
+            other: None,
+            first: true,
+            window_prefix: false,
+        };
+        app.refresh_items();
+        if !app.items.is_empty() {
+            app.state.select(Some(0));
+        }
+        app.report_load_warnings();
+        app
+    }
+
+    /// The message a key press stands for, given what is open; `None` if
+    /// it means nothing right now
+    pub fn key_msg(&self, key: KeyEvent) -> Option<AppMsg> {
+        // Any key closes a popup
+        if self.snapshot_popup.is_some() || self.detail_popup.is_some() || self.log_popup.is_some() {
+            return Some(AppMsg::DismissPopup);
+        }
+        if self.asking() {
+            return Some(AppMsg::Answer(match key.code {
+                KeyCode::Enter => Reply::Default,
+                KeyCode::Char('y' | 'Y') => Reply::Yes,
+                KeyCode::Char('c' | 'C') => Reply::Create,
+                KeyCode::Char('o' | 'O') => Reply::Overwrite,
+                KeyCode::Char('r' | 'R') => Reply::Reload,
+                _ => Reply::No,
+            }));
+        }
+        // While help is shown only a few keys do anything, and that's close it
+        if self.show_help {
+            return matches!(key.code, KeyCode::F(1) | KeyCode::Esc | KeyCode::Char('?' | 'h')).then_some(AppMsg::CloseHelp);
+        }
+
+        Some(match self.input_mode {
+            InputMode::Normal if self.window_prefix => match key.code {
+                KeyCode::Char('w') => AppMsg::FocusOther,
+                // Anything else just forgets the Ctrl-W
+                _ => AppMsg::Cancel,
+            },
+            InputMode::Normal => match key.code {
+                KeyCode::Char('q') => AppMsg::Quit,
+                KeyCode::Char('w') if key.modifiers.contains(KeyModifiers::CONTROL) => AppMsg::WindowPrefix,
+                KeyCode::Tab => AppMsg::FocusOther,
+                KeyCode::Char(':') => AppMsg::StartCommand,
+                KeyCode::Char('j') | KeyCode::Down => AppMsg::Next,
+                KeyCode::Char('k') | KeyCode::Up => AppMsg::Previous,
+                KeyCode::Char('i') => AppMsg::StartInlineEdit,
+                KeyCode::Char('t') => AppMsg::OpenTagPicker,
+                KeyCode::Char('T') => AppMsg::StartRetag,
+                KeyCode::Char('S') => AppMsg::ShowSnapshots,
+                KeyCode::Char('I') => AppMsg::ShowDetails,
+                KeyCode::Enter if self.create_hint().is_some() => AppMsg::CreateFromFilter,
+                KeyCode::Enter | KeyCode::Char('e') => AppMsg::OpenEditor,
+                KeyCode::F(1) => AppMsg::ToggleHelp,
+                _ => return None,
+            },
+            InputMode::Command => match key.code {
+                KeyCode::Enter => AppMsg::Submit,
+                KeyCode::Char(c) => AppMsg::Type(c),
+                KeyCode::Up => AppMsg::HistoryBack,
+                KeyCode::Down => AppMsg::HistoryForward,
+                KeyCode::Backspace => AppMsg::Backspace,
+                KeyCode::Esc => AppMsg::Cancel,
+                _ => return None,
+            },
+            InputMode::Tags => match key.code {
+                KeyCode::Char('j') | KeyCode::Down => AppMsg::Next,
+                KeyCode::Char('k') | KeyCode::Up => AppMsg::Previous,
+                KeyCode::Char('l') | KeyCode::Right => AppMsg::Fold { expand: true },
+                KeyCode::Char('h') | KeyCode::Left => AppMsg::Fold { expand: false },
+                KeyCode::Enter => AppMsg::Submit,
+                KeyCode::Backspace => AppMsg::Backspace,
+                KeyCode::Esc | KeyCode::Char('q') => AppMsg::Cancel,
+                _ => return None,
+            },
+            InputMode::Retag => match key.code {
+                KeyCode::Enter => AppMsg::Submit,
+                KeyCode::Tab => AppMsg::Complete,
+                KeyCode::Char(c) => AppMsg::Type(c),
+                KeyCode::Backspace => AppMsg::Backspace,
+                KeyCode::Esc => AppMsg::Cancel,
+                _ => return None,
+            },
+            InputMode::Edit => match key.code {
+                KeyCode::Enter => AppMsg::Type('\n'),
+                KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) => AppMsg::Save,
+                KeyCode::Char(c) => AppMsg::Type(c),
+                KeyCode::Backspace => AppMsg::Backspace,
+                KeyCode::Esc => AppMsg::Cancel,
+                _ => return None,
+            },
+        })
+    }
+
+    /// Apply `msg`, returning what's left for the event loop to do
+    pub fn update(&mut self, msg: AppMsg) -> Vec<Effect> {
+        let mode = self.input_mode;
+        self.window_prefix = matches!(msg, AppMsg::WindowPrefix);
+        match msg {
+            AppMsg::Next if mode == InputMode::Tags => self.move_tag_selection(true),
+            AppMsg::Previous if mode == InputMode::Tags => self.move_tag_selection(false),
+            AppMsg::Next | AppMsg::Scroll { down: true } => self.next(),
+            AppMsg::Previous | AppMsg::Scroll { down: false } => self.previous(),
+            AppMsg::Select(i) => {
+                if i < self.items.len() {
+                    self.state.select(Some(i));
+                }
+            }
+            AppMsg::StartCommand => {
+                self.input_mode = InputMode::Command;
+                self.input.push(':');
+            }
+            AppMsg::Type(c) => match mode {
+                InputMode::Command => {
+                    self.input.push(c);
+                    self.history_pos = None;
+                }
+                InputMode::Retag => self.retag_input.push(c),
+                InputMode::Edit => {
+                    if c == ' ' {
+                        self.expand_snippet();
+                    }
+                    self.edit_buffer.push(c);
+                }
+                InputMode::Normal | InputMode::Tags => {}
+            },
+            AppMsg::Backspace => match mode {
+                InputMode::Command => {
+                    self.input.pop();
+                    self.history_pos = None;
+                    if self.input.is_empty() {
+                        self.input_mode = InputMode::Normal;
+                    }
+                }
+                InputMode::Retag => {
+                    self.retag_input.pop();
+                }
+                InputMode::Edit => {
+                    self.edit_buffer.pop();
+                }
+                InputMode::Tags => self.apply_tag_filter(None),
+                InputMode::Normal => {}
+            },
+            AppMsg::Submit => match mode {
+                InputMode::Command => {
+                    let input: String = self.input.drain(..).collect();
+                    // record history
+                    if !input.trim().is_empty() {
+                        self.history.push(input.clone());
+                    }
+                    self.history_pos = None;
+                    // Before running it, as commands like `:edit` enter another mode
+                    self.input_mode = InputMode::Normal;
+                    return self.process_command(&input);
+                }
+                InputMode::Tags => {
+                    let tag = self.selected_tag().map(|(_, node)| node.path.clone());
+                    self.apply_tag_filter(tag);
+                }
+                InputMode::Retag => self.save_retag(),
+                InputMode::Normal | InputMode::Edit => {}
+            },
+            AppMsg::Cancel => match mode {
+                InputMode::Command => {
+                    self.input.clear();
+                    self.history_pos = None;
+                    self.input_mode = InputMode::Normal;
+                }
+                InputMode::Retag => {
+                    self.retag_id = None;
+                    self.input_mode = InputMode::Normal;
+                }
+                InputMode::Edit => self.cancel_inline_edit(),
+                InputMode::Tags => self.input_mode = InputMode::Normal,
+                InputMode::Normal => {}
+            },
+            AppMsg::Save => self.save_inline_edit(),
+            AppMsg::HistoryBack => self.history_back(),
+            AppMsg::HistoryForward => self.history_forward(),
+            AppMsg::Complete => self.complete_retag(),
+            AppMsg::Fold { expand } => self.set_tag_expanded(expand),
+            AppMsg::StartInlineEdit => self.start_inline_edit(),
+            AppMsg::OpenEditor => return self.open_editor().into_iter().collect(),
+            AppMsg::CreateFromFilter => self.create_from_filter(),
+            AppMsg::OpenTagPicker => self.open_tag_picker(),
+            AppMsg::StartRetag => self.start_retag(),
+            AppMsg::ShowSnapshots => self.show_snapshots(),
+            AppMsg::ShowDetails => self.show_details(),
+            AppMsg::ToggleHelp => self.show_help = !self.show_help,
+            AppMsg::CloseHelp => self.show_help = false,
+            AppMsg::DismissPopup => {
+                if self.snapshot_popup.take().is_none() && self.detail_popup.take().is_none() {
+                    self.log_popup = None;
+                }
+            }
+            AppMsg::Answer(reply) => return self.answer(reply),
+            AppMsg::Quit => return vec![Effect::Quit],
+            AppMsg::Edited { id, read_updated, edited } => self.save_editor_edit(id, read_updated, edited),
+            AppMsg::WikiOpened(Ok(wiki)) => {
+                self.wiki = *wiki;
+                self.refresh_items();
+                self.state.select(Some(0));
+                self.set_status(format!("Switched to wiki: {}", self.wiki.name));
+                self.report_load_warnings();
+            }
+            AppMsg::WikiOpened(Err(e)) => self.set_status(format!("Failed to open wiki: {}", e)),
+            AppMsg::WindowPrefix => {}
+            AppMsg::FocusOther => self.focus_other(),
+            AppMsg::SplitOpened(Ok(wiki)) => {
+                let mut pane = App::new(*wiki, self.use_global, self.log_file.clone(), self.scan);
+                pane.first = !self.first;
+                self.set_status(format!("Opened {} alongside; Tab or Ctrl-W w switches panes", pane.wiki.name));
+                self.other = Some(Box::new(pane));
+            }
+            AppMsg::SplitOpened(Err(e)) => self.set_status(format!("Failed to open wiki: {}", e)),
+            AppMsg::Reindexed(Ok(n)) => self.set_status(format!("Rebuilt search index of {} facts", n)),
+            AppMsg::Reindexed(Err(e)) => self.set_status(format!("Reindex failed: {}", e)),
+            AppMsg::LogRead(Ok(lines)) => self.log_popup = Some(lines),
+            AppMsg::LogRead(Err(e)) => self.set_status(format!("Couldn't read the log: {}", e)),
+            AppMsg::Status(status) => self.set_status(status),
+        }
+        Vec::new()
+    }
+
+    /// Whether a question in the status bar is waiting for an answer
+    pub fn asking(&self) -> bool {
+        self.pending_switch.is_some()
+            || self.pending_delete.is_some()
+            || self.pending_secret.is_some()
+            || self.pending_conflict.is_some()
+            || self.pending_rename.is_some()
+    }
+
+    /// What a fuzzy filter that matched nothing was looking for, to offer
+    /// to create it; never for regex filters, which aren't names
+    pub fn create_hint(&self) -> Option<&str> {
+        let query = self.filter.as_deref().filter(|_| self.filter_regex.is_none() && self.items.is_empty())?;
+        (!query.trim().is_empty()).then_some(query)
+    }
+
+    /// Whether the status message is recent enough to still show
+    pub fn status_showing(&self) -> bool {
+        self.status_timer.is_some_and(|t| t.elapsed() < self.status_duration)
+    }
+
+    pub fn refresh_items(&mut self) {
+        #[cfg(feature = "git")]
+        {
+            self.unsynced = self.wiki.config.git && self.wiki.unsynced();
+        }
+        self.items.clear();
+        // Previews show the line that matched, so the whole of each fact is needed
+        let fields = self.filter_fields;
+        let matcher = match (&self.filter, &self.filter_regex) {
+            (Some(_), Some(re)) => Some(LineMatcher::regex(re.clone())),
+            (Some(query), None) => Some(LineMatcher::fuzzy_with(query, &self.wiki.config.matching)),
+            (None, _) => None,
+        }
+        .filter(|_| fields.data);
+        if matcher.is_some()
+            && let Err(e) = self.wiki.hydrate_all()
+        {
+            tracing::warn!("couldn't load facts in full for previews: {}", e);
+        }
+        for locked_info in &self.wiki.info {
+            let info = locked_info.read();
+            let preview = preview_line(&info.data, matcher.as_ref());
+            let path = info.path(&self.wiki);
+            let changed = info.updated.or(info.created);
+            self.items.push((info.name.clone(), preview, info.tags.clone(), info.id, path, changed));
+        }
+        match self.sort {
+            _ if self.recent.is_some() => self.items.sort_by_key(|entry| std::cmp::Reverse(entry.5)),
+            ListSort::Wiki => {}
+            ListSort::Modified => self.items.sort_by_key(|entry| std::cmp::Reverse(entry.5)),
+            ListSort::Frecency => {
+                let usage = self.wiki.usage().unwrap_or_default();
+                let now = Utc::now();
+                let score = |entry: &ListEntry| usage.get(&entry.3).map_or(0.0, |u| frecency(u, now));
+                self.items.sort_by(|a, b| score(b).total_cmp(&score(a)));
+            }
+        }
+
+        if let Some(filter) = &self.tag_filter {
+            self.items.retain(|(_, _, tags, _, _, _)| tags.iter().any(|t| tag_matches(filter, t)));
+        }
+        if let Some(n) = self.recent {
+            self.items.retain(|entry| entry.5.is_some());
+            self.items.truncate(n);
+        }
+
+        // Apply filter if present
+        if let Some(pattern) = &self.filter {
+            if let Some(re) = &self.filter_regex {
+                let matching = &self.wiki.config.matching;
+                let is_match = |text: &str| re.is_match(&matching.pattern_text(text));
+                self.items.retain(|(name, preview, tags, _id, _path, _)| {
+                    (fields.name && is_match(name))
+                        || (fields.data && is_match(preview))
+                        || (fields.tags && tags.iter().any(|t| is_match(t)))
+                });
+            } else {
+                // Fuzzy recall, keeping only the best few screenfuls in score order
+                let opts = RecallOptions { tag: self.tag_filter.as_deref(), fields, ..Default::default() };
+                let rank: HashMap<Uuid, usize> = self
+                    .wiki
+                    .recall_top_n(pattern, FILTER_RESULTS, opts)
+                    .iter()
+                    .enumerate()
+                    .map(|(i, hit)| (hit.id, i))
+                    .collect();
+                self.items.retain(|entry| rank.contains_key(&entry.3));
+                self.items.sort_by_key(|entry| rank[&entry.3]);
+            }
+        }
+    }
+
+    pub fn next(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
+        let i = match self.state.selected() {
+            Some(i) => {
+                if i >= self.items.len() - 1 {
+                    0
+                } else {
+                    i + 1
+                }
+            }
+            None => 0,
+        };
+        self.state.select(Some(i));
+    }
+
+    pub fn previous(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
+        let i = match self.state.selected() {
+            Some(i) => {
+                if i == 0 {
+                    self.items.len() - 1
+                } else {
+                    i - 1
+                }
+            }
+            None => 0,
+        };
+        self.state.select(Some(i));
+    }
+
+    fn history_back(&mut self) {
+        if self.history.is_empty() {
+            return;
+        }
+        match self.history_pos {
+            Some(0) => {}
+            Some(n) => {
+                let new = n - 1;
+                self.history_pos = Some(new);
+                self.input = self.history[new].clone();
+            }
+            None => {
+                let last = self.history.len() - 1;
+                self.history_pos = Some(last);
+                self.input = self.history[last].clone();
+            }
+        }
+    }
+
+    fn history_forward(&mut self) {
+        let Some(n) = self.history_pos else {
+            return;
+        };
+        if n + 1 < self.history.len() {
+            let new = n + 1;
+            self.history_pos = Some(new);
+            self.input = self.history[new].clone();
+        } else {
+            self.history_pos = None;
+            self.input.clear();
+        }
+    }
+
+    /// Switch to `name` if it exists, otherwise ask first, offering the
+    /// closest existing wiki in case the name was mistyped
+    fn request_switch(&mut self, name: String) -> Vec<Effect> {
+        if self.refuse_if_in_other(&name) {
+            return Vec::new();
+        }
+        if wikis::exists(&name, self.use_global) {
+            return vec![Effect::OpenWiki(name)];
+        }
+        let suggestion = wikis::near_misses(&name, self.use_global).ok().and_then(|n| n.into_iter().next());
+        self.status_msg = match &suggestion {
+            Some(s) => format!("No wiki named '{}'. Did you mean '{}'? [Y/n/c(reate)]", name, s),
+            None => format!("No wiki named '{}'. Create it? [y/N]", name),
+        };
+        self.pending_switch = Some((name, suggestion));
+        Vec::new()
+    }
+
+    /// Answer the question waiting in the status bar
+    fn answer(&mut self, reply: Reply) -> Vec<Effect> {
+        if let Some((name, suggestion)) = self.pending_switch.take() {
+            return match (reply, suggestion) {
+                (Reply::Default | Reply::Yes, Some(existing)) => vec![Effect::OpenWiki(existing)],
+                (Reply::Create, Some(_)) | (Reply::Yes, None) => vec![Effect::OpenWiki(name)],
+                _ => {
+                    self.set_status("Switch cancelled".to_string());
+                    Vec::new()
+                }
+            };
+        }
+        if let Some((id, name)) = self.pending_delete.take() {
+            match reply {
+                Reply::Yes => self.delete(id, &name, true),
+                _ => self.set_status("Delete cancelled".to_string()),
+            }
+        } else if let Some(save) = self.pending_secret.take() {
+            self.answer_secret(reply, save);
+        } else if let Some(save) = self.pending_conflict.take() {
+            self.answer_conflict(reply, save);
+        } else if let Some((id, name)) = self.pending_rename.take() {
+            self.answer_rename(reply, id, name);
+        }
+        Vec::new()
+    }
+
+    /// Whether the other pane already shows `name`, saying so if it does;
+    /// two panes on one wiki would each miss what the other saves
+    fn refuse_if_in_other(&mut self, name: &str) -> bool {
+        let shown = self.other.as_ref().is_some_and(|other| other.wiki.name == name);
+        if shown {
+            self.set_status(format!("{} is open in the other pane; Tab switches to it", name));
+        }
+        shown
+    }
+
+    /// Open the wiki `name` in a second pane, in place of any already open
+    fn request_split(&mut self, name: &str) -> Vec<Effect> {
+        if name == self.wiki.name {
+            self.set_status(format!("{} is already open here", name));
+        } else if !wikis::exists(name, self.use_global) {
+            self.set_status(format!("No wiki named '{}'", name));
+        } else {
+            return vec![Effect::OpenSplit(name.to_string())];
+        }
+        Vec::new()
+    }
+
+    /// Swap this pane for the other one, so keys go to it. Command history
+    /// is shared, so it comes along.
+    fn focus_other(&mut self) {
+        let Some(mut other) = self.other.take() else {
+            self.set_status("Only one pane; :split <wiki> opens another".to_string());
+            return;
+        };
+        std::mem::swap(self, &mut other);
+        self.history = std::mem::take(&mut other.history);
+        self.other = Some(other);
+        self.set_status(format!("Focused {}", self.wiki.name));
+    }
+
+    /// Close the other pane, leaving this one to fill the screen
+    fn close_other(&mut self) {
+        self.first = true;
+        match self.other.take() {
+            Some(other) => self.set_status(format!("Closed {}", other.wiki.name)),
+            None => self.set_status("Only one pane open".to_string()),
+        }
+    }
+
+    /// Move the selected fact to the other pane's wiki
+    fn move_to_other(&mut self) {
+        let Some(mut other) = self.other.take() else {
+            self.set_status("No other pane; :split <wiki> opens one".to_string());
+            return;
+        };
+        if let Some((name, id)) = self.state.selected().and_then(|i| self.items.get(i)).map(|e| (e.0.clone(), e.3)) {
+            match self.wiki.move_to(id, &mut other.wiki) {
+                Ok(_) => {
+                    self.refresh_items();
+                    let last = self.items.len().checked_sub(1);
+                    self.state.select(self.state.selected().and_then(|i| last.map(|last| i.min(last))));
+                    other.refresh_items();
+                    self.set_status(format!("Moved '{}' to {}", name, other.wiki.name));
+                }
+                Err(e) => self.set_status(format!("Failed to move: {}", e)),
+            }
+        }
+        self.other = Some(other);
+    }
+
+    /// Move the selected fact to the trash, or if `hard` ask before removing
+    /// it for good
+    fn delete_selected(&mut self, hard: bool) {
+        if self.refuse_if_readonly() {
+            return;
+        }
+        let Some((name, id)) = self.state.selected().and_then(|i| self.items.get(i)).map(|e| (e.0.clone(), e.3)) else {
+            return;
+        };
+        if hard {
+            self.status_msg = format!("Delete '{}' and its snapshots for good? [y/N]", name);
+            self.pending_delete = Some((id, name));
+            return;
+        }
+        self.delete(id, &name, false);
+    }
+
+    fn delete(&mut self, id: Uuid, name: &str, hard: bool) {
+        match self.wiki.delete(id, hard) {
+            Ok(_) => {
+                self.refresh_items();
+                let last = self.items.len().checked_sub(1);
+                self.state.select(self.state.selected().and_then(|i| last.map(|last| i.min(last))));
+                self.set_status(if hard {
+                    format!("Deleted '{}'", name)
+                } else {
+                    format!("Moved '{}' to the trash; `wk trash restore {}` brings it back", name, id)
+                });
+            }
+            Err(e) => self.set_status(format!("Failed to delete: {}", e)),
+        }
+    }
+
+    fn report_load_warnings(&mut self) {
+        if !self.wiki.warnings.is_empty() {
+            self.set_status(format!(
+                "{} entries could not be loaded; run `wk doctor`",
+                self.wiki.warnings.len()
+            ));
+        }
+    }
+
+    /// Whether the wiki is read-only, saying so in the status bar if it is
+    fn refuse_if_readonly(&mut self) -> bool {
+        if self.wiki.readonly {
+            self.set_status("Wiki is read-only".to_string());
+        }
+        self.wiki.readonly
+    }
+
+    /// Create a fact named `name` holding `data`, returning its id if it was
+    /// saved straight away rather than held back to ask about a secret
+    fn create_entry(&mut self, name: String, data: String) -> Option<Uuid> {
+        if self.refuse_if_readonly() {
+            return None;
+        }
+        let id = Uuid::new_v4();
+        let now = Utc::now();
+        let mut info = Information {
+            id,
+            tags: Vec::new(),
+            name_is_derived: !data.is_empty() && name == derived_name(&data),
+            name,
+            data,
+            created: Some(now),
+            updated: Some(now),
+            source: None,
+            extra: Default::default(),
+        };
+        match self.screen_secrets(&mut [&mut info.name, &mut info.data]) {
+            Screened::Save => self.insert_entry(info),
+            Screened::Ask => {
+                self.pending_secret = Some(PendingSave::Create(info));
+                None
+            }
+            Screened::Refuse => None,
+        }
+    }
+
+    fn insert_entry(&mut self, info: Information) -> Option<Uuid> {
+        let name = info.name.clone();
+        match self.wiki.insert(info) {
+            Ok(id) => {
+                self.refresh_items();
+                self.set_status(format!("Created entry: {}", name));
+                Some(id)
+            }
+            Err(e) => {
+                self.set_status(format!("Failed to create entry {}: {}", name, e));
+                None
+            }
+        }
+    }
+
+    /// Create the fact a fuzzy filter found nothing for, as `:n` would,
+    /// then clear the filter and start writing it inline
+    fn create_from_filter(&mut self) {
+        let Some(name) = self.create_hint().map(str::to_string) else {
+            return;
+        };
+        let Some(id) = self.create_entry(name.clone(), String::new()) else {
+            return;
+        };
+        self.filter = None;
+        self.filter_regex = None;
+        self.filter_fields = Fields::ALL;
+        self.refresh_items();
+        self.state.select(self.items.iter().position(|entry| entry.3 == id));
+        self.start_inline_edit();
+        self.set_status(format!("Created '{}'; Ctrl+S saves its text, Esc leaves it empty", name));
+    }
+
+    /// Look for secrets in `texts` before they are saved and deal with them
+    /// as the wiki's `[secrets]` policy says, redacting them in place if it
+    /// says to
+    fn screen_secrets(&mut self, texts: &mut [&mut String]) -> Screened {
+        if !self.scan {
+            return Screened::Save;
+        }
+        let scanner = match self.wiki.secret_scanner() {
+            Ok(scanner) => scanner,
+            Err(e) => {
+                self.set_status(format!("Not saved: {}", e));
+                return Screened::Refuse;
+            }
+        };
+        let found: Vec<SecretMatch> = texts.iter().flat_map(|text| scanner.scan(text)).collect();
+        let Some(first) = found.first() else {
+            return Screened::Save;
+        };
+        let what = format!(
+            "Line {} looks like it holds a secret ({}){}",
+            first.line,
+            first.pattern,
+            if found.len() > 1 { format!(" and {} more", found.len() - 1) } else { String::new() }
+        );
+        match self.wiki.config.secrets.policy {
+            SecretPolicy::Redact => {
+                for text in texts.iter_mut() {
+                    **text = scanner.redact(text).0;
+                }
+                tracing::info!(secrets = found.len(), "redacted before saving");
+                Screened::Save
+            }
+            SecretPolicy::Block => {
+                self.set_status(format!("Not saved: {}", what));
+                Screened::Refuse
+            }
+            SecretPolicy::Warn => {
+                self.status_msg = format!("{}. Save anyway? [y/N]", what);
+                Screened::Ask
+            }
+        }
+    }
+
+    /// Answer the question asked by [`App::screen_secrets`]
+    fn answer_secret(&mut self, reply: Reply, save: PendingSave) {
+        if reply != Reply::Yes {
+            self.set_status("Not saved".to_string());
+            return;
+        }
+        match save {
+            PendingSave::Create(info) => {
+                self.insert_entry(info);
+            }
+            PendingSave::Inline(id, data) => self.write_inline_edit(id, data),
+            PendingSave::Editor(id, read_updated, edited) => self.write_editor_edit(id, read_updated, edited),
+        }
+    }
+
+    fn find_locked_index_by_id(&self, id: Uuid) -> Option<usize> {
+        for (i, locked) in self.wiki.info.iter().enumerate() {
+            if locked.read().id == id {
+                return Some(i);
+            }
+        }
+        None
+    }
+
+    fn start_inline_edit(&mut self) {
+        if self.refuse_if_readonly() {
+            return;
+        }
+        if let Some(sel) = self.state.selected()
+            && sel < self.items.len()
+        {
+            let id = self.items[sel].3;
+            if let Err(e) = self.wiki.hydrate(id) {
+                self.set_status(format!("Failed to load: {}", e));
+                return;
+            }
+            self.wiki.record_access(&[id]);
+            self.wiki.record_use(&[id]);
+            if let Some(li) = self.find_locked_index_by_id(id) {
+                let info = self.wiki.info[li].read();
+                let name_clone = info.name.clone();
+                self.edit_buffer = info.data.clone();
+                drop(info);
+                self.editing_id = Some(id);
+                self.input_mode = InputMode::Edit;
+                self.set_status(format!("Editing: {}", name_clone));
+            }
+        }
+    }
+
+    /// Start writing a new fact inline, to be named by [`derive_title`]
+    /// once it is saved
+    fn start_draft(&mut self) {
+        if self.refuse_if_readonly() {
+            return;
+        }
+        self.editing_id = None;
+        self.drafting = true;
+        self.edit_buffer.clear();
+        self.input_mode = InputMode::Edit;
+        self.set_status("New fact; Ctrl+S saves it, named after its heading or first wordy line".to_string());
+    }
+
+    fn save_draft(&mut self) {
+        let data = self.edit_buffer.trim_end().to_string();
+        if data.trim().is_empty() {
+            self.set_status("Nothing written yet; Esc leaves without saving".to_string());
+            return;
+        }
+        self.drafting = false;
+        self.edit_buffer.clear();
+        self.input_mode = InputMode::Normal;
+        if let Some(id) = self.create_entry(derive_title(&data), data) {
+            self.state.select(self.items.iter().position(|entry| entry.3 == id));
+        }
+    }
+
+    fn save_inline_edit(&mut self) {
+        if self.drafting {
+            self.save_draft();
+            return;
+        }
+        if let Some(edit_id) = self.editing_id {
+            let mut data = self.edit_buffer.clone();
+            match self.screen_secrets(&mut [&mut data]) {
+                Screened::Save => self.write_inline_edit(edit_id, data),
+                Screened::Ask => self.pending_secret = Some(PendingSave::Inline(edit_id, data)),
+                Screened::Refuse => {}
+            }
+        }
+    }
+
+    fn write_inline_edit(&mut self, id: Uuid, data: String) {
+        let before = self.wiki.get(id);
+        let read_updated = before.as_ref().ok().and_then(|before| before.updated);
+        let first_line = before.map(|before| derived_name(&before.data));
+        match self.wiki.update_if(id, read_updated, |info| info.data = data.clone()) {
+            Ok(after) => {
+                self.refresh_items();
+                self.input_mode = InputMode::Normal;
+                self.editing_id = None;
+                // A name taken from the first line goes stale when it changes
+                let name = derived_name(&after.data);
+                let changed = first_line.is_ok_and(|line| line != name);
+                if after.name_is_derived && changed && !name.is_empty() && name != after.name {
+                    self.status_msg = format!("Saved. Rename it '{}' after its new first line? [y/N]", name);
+                    self.pending_rename = Some((id, name));
+                } else {
+                    self.set_status("Saved.".to_string());
+                }
+            }
+            Err(WikiError::Conflict { .. }) => self.ask_conflict(PendingSave::Inline(id, data)),
+            Err(e) => self.set_status(format!("Failed to save: {}", e)),
+        }
+    }
+
+    /// Rename a fact after its new first line, or on any other answer keep
+    /// its name from then on
+    fn answer_rename(&mut self, reply: Reply, id: Uuid, name: String) {
+        let yes = reply == Reply::Yes;
+        let renamed = self.wiki.update(id, |info| match yes {
+            true => info.name = name,
+            false => info.name_is_derived = false,
+        });
+        self.refresh_items();
+        match renamed {
+            Ok(info) if yes => self.set_status(format!("Renamed to '{}'", info.name)),
+            Ok(_) => self.set_status("Kept the name".to_string()),
+            Err(e) => self.set_status(format!("Failed to rename: {}", e)),
+        }
+    }
+
+    /// The selected fact as it is now, to open in the external editor
+    fn open_editor(&mut self) -> Option<Effect> {
+        let idx = self.state.selected().filter(|&idx| idx < self.items.len())?;
+        if self.refuse_if_readonly() {
+            return None;
+        }
+        let id = self.items[idx].3;
+        if let Err(e) = self.wiki.hydrate(id) {
+            self.set_status(format!("Failed to load: {}", e));
+            return None;
+        }
+        self.wiki.record_access(&[id]);
+        self.wiki.record_use(&[id]);
+        let li = self.find_locked_index_by_id(id)?;
+        let info = self.wiki.info[li].read().clone();
+        Some(Effect::OpenEditor(info))
+    }
+
+    /// Save what was written in the external editor, unless it holds secrets
+    /// the wiki won't take as they are
+    fn save_editor_edit(&mut self, id: Uuid, read_updated: Option<DateTime<Utc>>, mut edited: Edited) {
+        match self.screen_secrets(&mut [&mut edited.title, &mut edited.body]) {
+            Screened::Save => self.write_editor_edit(id, read_updated, edited),
+            Screened::Ask => self.pending_secret = Some(PendingSave::Editor(id, read_updated, edited)),
+            Screened::Refuse => {}
+        }
+    }
+
+    /// Write back into the wiki, asking what to do if the fact changed while
+    /// the editor was open
+    fn write_editor_edit(&mut self, id: Uuid, read_updated: Option<DateTime<Utc>>, edited: Edited) {
+        let saved = self.wiki.update_if(id, read_updated, |info| apply_edited(info, edited.clone()));
+        self.refresh_items();
+        match saved {
+            Ok(_) => self.set_status("Saved from editor".to_string()),
+            Err(WikiError::Conflict { .. }) => self.ask_conflict(PendingSave::Editor(id, read_updated, edited)),
+            Err(e) => self.set_status(format!("Failed to save: {}", e)),
+        }
+    }
+
+    /// Hold back an edit to a fact that was saved elsewhere since it was
+    /// read, e.g. by another TUI on the same wiki, and ask what to do
+    fn ask_conflict(&mut self, save: PendingSave) {
+        let (PendingSave::Inline(id, _) | PendingSave::Editor(id, _, _)) = save else {
+            return;
+        };
+        self.editing_id = None;
+        self.input_mode = InputMode::Normal;
+        let name = self.wiki.get(id).map(|info| info.name).unwrap_or_default();
+        self.status_msg = format!(
+            "'{}' was changed elsewhere since you opened it: [o]verwrite, [r]eload theirs, save yours as a [c]onflict copy? [o/r/c/N]",
+            name.lines().next().unwrap_or_default()
+        );
+        self.pending_conflict = Some(save);
+    }
+
+    fn answer_conflict(&mut self, reply: Reply, save: PendingSave) {
+        let id = match &save {
+            PendingSave::Inline(id, _) | PendingSave::Editor(id, _, _) => *id,
+            PendingSave::Create(_) => return,
+        };
+        let done = match (reply, save) {
+            (Reply::Overwrite, save) => self.wiki.update(id, |info| save.apply(info)).map(|_| "Saved over their changes".to_string()),
+            (Reply::Reload, _) => self.wiki.reload(id).map(|_| "Reloaded their version; yours was dropped".to_string()),
+            (Reply::Create, save) => self.save_conflict_copy(id, save),
+            // An inline edit goes back to the editor rather than being lost
+            (_, PendingSave::Inline(_, data)) => {
+                self.edit_buffer = data;
+                self.editing_id = Some(id);
+                self.input_mode = InputMode::Edit;
+                Ok("Not saved; still editing".to_string())
+            }
+            _ => Ok("Not saved".to_string()),
+        };
+        self.refresh_items();
+        match done {
+            Ok(status) => self.set_status(status),
+            Err(e) => self.set_status(format!("Failed to save: {}", e)),
+        }
+    }
+
+    /// Take the version saved elsewhere, keeping both it and `save` in a new
+    /// fact tagged `conflict`
+    fn save_conflict_copy(&mut self, id: Uuid, save: PendingSave) -> Result<String, WikiError> {
+        let mut ours = self.wiki.get(id)?;
+        save.apply(&mut ours);
+        let theirs = self.wiki.reload(id)?;
+        let copy = conflict_fact(&ours, "edited here", &theirs, "saved elsewhere");
+        let name = copy.name.clone();
+        self.wiki.insert(copy)?;
+        Ok(format!("Took their version and kept both in '{}'", name))
+    }
+
+    /// Expand the word just typed in the inline editor if it's a snippet trigger
+    fn expand_snippet(&mut self) {
+        if let Some((text, _)) = snippets::expand_last(&self.edit_buffer, &self.wiki.config.snippets) {
+            self.edit_buffer = text;
+        }
+    }
+
+    fn open_tag_picker(&mut self) {
+        self.tag_tree = self.wiki.tag_tree();
+        if self.tag_tree.is_empty() {
+            self.set_status("No tags yet".to_string());
+            return;
+        }
+        self.tag_state.select(Some(0));
+        self.input_mode = InputMode::Tags;
+    }
+
+    /// Tag nodes showing in the picker, skipping children of collapsed ones
+    pub fn visible_tags(&self) -> Vec<(usize, &TagNode)> {
+        fn visit<'a>(nodes: &'a [TagNode], depth: usize, expanded: &HashSet<String>, out: &mut Vec<(usize, &'a TagNode)>) {
+            for node in nodes {
+                out.push((depth, node));
+                if expanded.contains(&node.path) {
+                    visit(&node.children, depth + 1, expanded, out);
+                }
+            }
+        }
+        let mut out = Vec::new();
+        visit(&self.tag_tree, 0, &self.expanded_tags, &mut out);
+        out
+    }
+
+    fn selected_tag(&self) -> Option<(usize, &TagNode)> {
+        self.tag_state.selected().and_then(|i| self.visible_tags().get(i).copied())
+    }
+
+    fn move_tag_selection(&mut self, down: bool) {
+        let len = self.visible_tags().len();
+        if len == 0 {
+            return;
+        }
+        let i = self.tag_state.selected().unwrap_or(0);
+        self.tag_state.select(Some(if down { (i + 1) % len } else { (i + len - 1) % len }));
+    }
+
+    /// Expand or collapse the selected tag; collapsing a leaf or a collapsed
+    /// node moves to its parent instead
+    fn set_tag_expanded(&mut self, expand: bool) {
+        let Some((depth, node)) = self.selected_tag() else {
+            return;
+        };
+        let path = node.path.clone();
+        let has_children = !node.children.is_empty();
+        if expand {
+            if has_children {
+                self.expanded_tags.insert(path);
+            }
+        } else if !self.expanded_tags.remove(&path) && depth > 0 {
+            let visible = self.visible_tags();
+            let selected = self.tag_state.selected().unwrap_or(0);
+            let parent = visible[..selected].iter().rposition(|(d, _)| *d < depth);
+            self.tag_state.select(parent);
+        }
+    }
+
+    fn apply_tag_filter(&mut self, tag: Option<String>) {
+        self.set_status(match &tag {
+            Some(tag) => format!("Showing facts tagged {}", tag),
+            None => "Tag filter cleared".to_string(),
+        });
+        self.tag_filter = tag;
+        self.input_mode = InputMode::Normal;
+        self.refresh_items();
+        self.state.select((!self.items.is_empty()).then_some(0));
+    }
+
+    fn show_snapshots(&mut self) {
+        let Some((name, id)) = self.state.selected().and_then(|i| self.items.get(i)).map(|e| (e.0.clone(), e.3)) else {
+            return;
+        };
+        match self.wiki.snapshots(id) {
+            Ok(snapshots) => self.snapshot_popup = Some((name, snapshots)),
+            Err(e) => self.set_status(format!("Failed to list snapshots: {}", e)),
+        }
+    }
+
+    fn show_details(&mut self) {
+        let Some(id) = self.state.selected().and_then(|i| self.items.get(i)).map(|e| e.3) else {
+            return;
+        };
+        match self.wiki.get(id) {
+            Ok(info) => {
+                self.wiki.record_access(&[id]);
+                self.wiki.record_use(&[id]);
+                self.detail_popup = Some(info);
+            }
+            Err(e) => self.set_status(format!("Failed to load: {}", e)),
+        }
+    }
+
+    fn start_retag(&mut self) {
+        if self.refuse_if_readonly() {
+            return;
+        }
+        let Some(&(ref name, _, ref tags, id, _, _)) = self.state.selected().and_then(|i| self.items.get(i)) else {
+            return;
+        };
+        let data = match self.wiki.get(id) {
+            Ok(info) => info.data,
+            Err(e) => {
+                self.set_status(format!("Failed to load: {}", e));
+                return;
+            }
+        };
+        self.retag_input = tags.iter().map(|t| format!("{} ", t)).collect();
+        // Fitting tags first, then the rest of the vocabulary by use
+        let mut by_use: Vec<(String, usize)> = self.wiki.tags().into_iter().collect();
+        by_use.sort_by_key(|(_, n)| std::cmp::Reverse(*n));
+        let mut suggestions: Vec<String> = self
+            .wiki
+            .suggest_tags(&format!("{}\n{}", name, data), 10)
+            .into_iter()
+            .map(|(tag, _)| tag)
+            .collect();
+        suggestions.extend(by_use.into_iter().map(|(tag, _)| tag).filter(|t| !suggestions.contains(t)).collect::<Vec<_>>());
+        self.retag_suggestions = suggestions;
+        self.retag_id = Some(id);
+        self.input_mode = InputMode::Retag;
+    }
+
+    /// Suggestions completing the word being typed, leaving out tags already entered
+    pub fn retag_completions(&self) -> Vec<&str> {
+        let entered: Vec<&str> = self.retag_input.split_whitespace().collect();
+        let partial = if self.retag_input.ends_with(' ') || self.retag_input.is_empty() { "" } else { entered.last().copied().unwrap_or("") };
+        self.retag_suggestions
+            .iter()
+            .map(String::as_str)
+            .filter(|t| t.starts_with(partial) && !entered.iter().any(|e| e == t))
+            .take(5)
+            .collect()
+    }
+
+    fn complete_retag(&mut self) {
+        let Some(tag) = self.retag_completions().first().map(|t| t.to_string()) else {
+            return;
+        };
+        if !self.retag_input.is_empty() && !self.retag_input.ends_with(' ') {
+            let keep = self.retag_input.trim_end_matches(|c: char| !c.is_whitespace()).len();
+            self.retag_input.truncate(keep);
+        }
+        self.retag_input.push_str(&tag);
+        self.retag_input.push(' ');
+    }
+
+    fn save_retag(&mut self) {
+        let Some(id) = self.retag_id.take() else {
+            return;
+        };
+        let tags: Vec<String> = self.retag_input.split_whitespace().map(str::to_string).collect();
+        self.input_mode = InputMode::Normal;
+        match self.wiki.retag(id, tags) {
+            Ok(_) => {
+                self.refresh_items();
+                self.set_status("Tags saved.".to_string());
+            }
+            Err(e) => self.set_status(format!("Failed to save tags: {}", e)),
+        }
+    }
+
+    fn cancel_inline_edit(&mut self) {
+        self.editing_id = None;
+        self.drafting = false;
+        self.edit_buffer.clear();
+        self.input_mode = InputMode::Normal;
+        self.set_status("Edit cancelled.".to_string());
+    }
+
+    fn process_command(&mut self, command: &str) -> Vec<Effect> {
+        let parts: Vec<&str> = command.trim_start_matches(':').split_whitespace().collect();
+        if parts.is_empty() {
+            return Vec::new();
+        }
+        tracing::debug!(command, "TUI command");
+        match parts[0] {
+            "q" | "quit" => return vec![Effect::Quit],
+            "wiki" | "switch" => {
+                if parts.len() > 1 {
+                    return self.request_switch(parts[1].to_string());
+                } else {
+                    self.status_msg = "Usage: :wiki <wiki_name>".to_string();
+                }
+            }
+            "split" | "sp" => match parts.get(1) {
+                Some(name) if !self.refuse_if_in_other(name) => return self.request_split(name),
+                Some(_) => {}
+                None => self.status_msg = "Usage: :split <wiki_name>".to_string(),
+            },
+            "only" => self.close_other(),
+            "mv-to-other" => self.move_to_other(),
+            "n" | "new" => {
+                if parts.len() > 1 {
+                    self.create_entry(parts[1..].join(" "), String::new());
+                } else {
+                    self.start_draft();
+                }
+            }
+            "s" | "search" => {
+                if parts.len() > 1 {
+                    let pat = parts[1..].join(" ");
+                    let (fields, pat) = search_fields(&pat);
+                    if let Some(raw) = pat.strip_prefix("re:") {
+                        match self.wiki.config.matching.regex(raw, false) {
+                            Ok(r) => {
+                                self.filter = Some(raw.to_string());
+                                self.filter_regex = Some(r);
+                                self.filter_fields = fields;
+                                self.refresh_items();
+                            }
+                            Err(e) => self.status_msg = format!("Invalid regex: {}", e),
+                        }
+                    } else {
+                        self.filter = Some(pat.to_string());
+                        self.filter_regex = None;
+                        self.filter_fields = fields;
+                        self.refresh_items();
+                    }
+                } else {
+                    // clear filter
+                    self.filter = None;
+                    self.filter_regex = None;
+                    self.filter_fields = Fields::ALL;
+                    self.refresh_items();
+                }
+            }
+            "edit" => {
+                self.start_inline_edit();
+            }
+            "delete" | "rm" => self.delete_selected(parts.get(1) == Some(&"--hard")),
+            "doctor" => match self.wiki.diagnose() {
+                Ok(findings) if findings.is_empty() => self.set_status("No problems found".to_string()),
+                Ok(findings) => {
+                    let mut counts: Vec<(&str, usize)> = Vec::new();
+                    for finding in &findings {
+                        match counts.iter_mut().find(|(c, _)| *c == finding.category()) {
+                            Some((_, n)) => *n += 1,
+                            None => counts.push((finding.category(), 1)),
+                        }
+                    }
+                    let summary: Vec<String> = counts.iter().map(|(c, n)| format!("{}: {}", c, n)).collect();
+                    self.set_status(format!("{}; run `wk doctor --fix`", summary.join(", ")));
+                }
+                Err(e) => self.set_status(format!("Doctor failed: {}", e)),
+            },
+            "cols" => match parts.get(1) {
+                Some(&"date") => {
+                    self.show_age = !self.show_age;
+                    self.set_status(format!("Date column {}", if self.show_age { "shown" } else { "hidden" }));
+                }
+                _ => self.status_msg = "Usage: :cols date".to_string(),
+            },
+            "sort" => {
+                self.sort = match parts.get(1) {
+                    Some(&"modified") => ListSort::Modified,
+                    Some(&"frecency") if !self.wiki.config.track_usage => {
+                        self.set_status("Usage isn't tracked; set track_usage = true in the config".to_string());
+                        return Vec::new();
+                    }
+                    Some(&"frecency") => ListSort::Frecency,
+                    Some(&"default") => ListSort::Wiki,
+                    _ => {
+                        self.status_msg = "Usage: :sort modified|frecency|default".to_string();
+                        return Vec::new();
+                    }
+                };
+                self.refresh_items();
+                self.state.select((!self.items.is_empty()).then_some(0));
+                self.set_status(
+                    match self.sort {
+                        ListSort::Wiki => "Sorted in the wiki's order",
+                        ListSort::Modified => "Most recently changed first",
+                        ListSort::Frecency => "Most used first",
+                    }
+                    .to_string(),
+                );
+            }
+            "recent" => {
+                self.recent = match parts.get(1) {
+                    None if self.recent.is_some() => None,
+                    None => Some(DEFAULT_RECENT),
+                    Some(&"off") => None,
+                    Some(n) => match n.parse() {
+                        Ok(n) => Some(n),
+                        Err(_) => {
+                            self.status_msg = "Usage: :recent [count|off]".to_string();
+                            return Vec::new();
+                        }
+                    },
+                };
+                self.refresh_items();
+                self.state.select((!self.items.is_empty()).then_some(0));
+                self.set_status(match self.recent {
+                    Some(n) => format!("Showing the {} most recently changed facts", n),
+                    None => "Showing every fact".to_string(),
+                });
+            }
+            "reindex" => return vec![Effect::Reindex],
+            "log" => match &self.log_file {
+                Some(path) => return vec![Effect::ShowLog(path.clone())],
+                None => self.status_msg = "Not logging; start with `wk --log-file <path> tui`".to_string(),
+            },
+            "help" | "?" => {
+                self.show_help = !self.show_help;
+            }
+            _ => {
+                self.status_msg = format!("Unknown command: {}", parts[0]);
+            }
+        }
+        Vec::new()
+    }
+
+    fn set_status(&mut self, s: String) {
+        self.status_msg = s;
+        self.status_timer = Some(Instant::now());
+    }
+}
+
+/// The fields a `:s` query names with a `name:`, `data:` or `tag:` prefix, and
+/// the query without it; every field if it has none. The prefix comes before
+/// any `re:`, as in `:s name:re:^todo`.
+fn search_fields(query: &str) -> (Fields, &str) {
+    for (prefix, fields) in [("name:", Fields::NAME), ("data:", Fields::DATA), ("tag:", Fields::TAGS)] {
+        if let Some(rest) = query.strip_prefix(prefix) {
+            return (fields, rest);
+        }
+    }
+    (Fields::ALL, query)
+}
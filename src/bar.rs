@@ -0,0 +1,81 @@
+//! A progress bar on stderr for long operations like `wk book` and `wk reindex`
+
+use crossterm::{
+    cursor::MoveToColumn,
+    queue,
+    style::Print,
+    terminal::{Clear, ClearType},
+};
+use std::io::{IsTerminal, Write};
+use std::time::{Duration, Instant};
+use twk::{NoProgress, Progress};
+
+use crate::output::{self, Level};
+use crate::table;
+
+/// Cells of the bar itself, between the brackets
+const BAR_WIDTH: usize = 24;
+/// Redraw at most this often, so a fast loop isn't slowed down by the terminal
+const REDRAW_EVERY: Duration = Duration::from_millis(50);
+
+/// `label [#######-----] 120/3000 step` on one line, redrawn in place and
+/// cleared when finished
+pub struct Bar {
+    label: &'static str,
+    total: usize,
+    done: usize,
+    drawn: Option<Instant>,
+}
+
+/// A bar labelled `label` if stderr is a terminal, otherwise nothing; nothing
+/// with `--quiet` either
+pub fn progress(label: &'static str) -> Box<dyn Progress> {
+    if std::io::stderr().is_terminal() && output::level() != Level::Quiet {
+        Box::new(Bar { label, total: 0, done: 0, drawn: None })
+    } else {
+        Box::new(NoProgress)
+    }
+}
+
+impl Bar {
+    fn draw(&mut self, msg: &str) {
+        let filled = (self.done * BAR_WIDTH).checked_div(self.total).unwrap_or(BAR_WIDTH);
+        let line = format!(
+            "{} [{}{}] {}/{} {}",
+            self.label,
+            "#".repeat(filled),
+            "-".repeat(BAR_WIDTH - filled),
+            self.done,
+            self.total,
+            msg
+        );
+        let width = crossterm::terminal::size().map(|(w, _)| w as usize).ok().filter(|&w| w > 0).unwrap_or(80);
+        let mut stderr = std::io::stderr();
+        queue!(stderr, MoveToColumn(0), Clear(ClearType::CurrentLine), Print(table::truncate(&line, width.saturating_sub(1)))).ok();
+        stderr.flush().ok();
+        self.drawn = Some(Instant::now());
+    }
+}
+
+impl Progress for Bar {
+    fn start(&mut self, total: usize) {
+        self.total = total;
+        self.done = 0;
+        self.draw("");
+    }
+
+    fn tick(&mut self, msg: &str) {
+        self.done += 1;
+        if self.done == self.total || self.drawn.is_none_or(|t| t.elapsed() >= REDRAW_EVERY) {
+            self.draw(msg);
+        }
+    }
+
+    fn finish(&mut self) {
+        if self.drawn.take().is_some() {
+            let mut stderr = std::io::stderr();
+            queue!(stderr, MoveToColumn(0), Clear(ClearType::CurrentLine)).ok();
+            stderr.flush().ok();
+        }
+    }
+}
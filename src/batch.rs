@@ -0,0 +1,270 @@
+//! `wk batch`: many commands against one loaded wiki, read from stdin.
+//!
+//! Each line is one command, either written out like
+//!
+//! ```text
+//! c {"text": "kubectl rollout undo", "tags": ["k8s"]}
+//! tag 1b4e28ba-2fa1-11d2-883f-0016d3cca427 +ops -draft
+//! d 1b4e28ba-2fa1-11d2-883f-0016d3cca427
+//! r rollout
+//! get 1b4e28ba-2fa1-11d2-883f-0016d3cca427
+//! ```
+//!
+//! or as a JSON object naming the command in `cmd`, with the same fields:
+//! `{"cmd": "tag", "id": "…", "add": ["ops"], "remove": ["draft"]}`.
+//! A commit takes a JSON object with `text` and optionally `name`, `tags`
+//! and `source`, or just a JSON string. `d` moves a fact to the trash, or
+//! deletes it for good with `--hard` (`"hard": true`). `r` returns the best
+//! [`DEFAULT_LIMIT`] matches, or `limit`, optionally only those with `tag`.
+//! Blank lines and lines starting with `#` are skipped.
+//!
+//! Every command gets one line of JSON back, in order: `{"line": 3, "ok":
+//! true, "result": …}` with the fact, or facts for `r`, or `{"line": 3,
+//! "ok": false, "error": {"kind": …, "message": …}}`. A failed command
+//! doesn't stop the batch unless [`BatchOptions::fail_fast`].
+//!
+//! Commits are made as by `wk c` without asking anything: snippets are
+//! expanded, similar facts aren't looked for, and a fact holding a secret
+//! is redacted if the wiki's policy says so and refused otherwise.
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::io::{BufRead, Write};
+use uuid::Uuid;
+
+use crate::error::WikiError;
+use crate::secrets::{Scanner, SecretPolicy};
+use crate::snippets::expand_snippets;
+use crate::wiki::{Fields, Wiki};
+use crate::window::TimeWindow;
+
+/// Facts returned by `r` when no limit is given
+pub const DEFAULT_LIMIT: usize = 20;
+
+/// How [`run`] goes about a batch
+#[derive(Debug, Clone, Copy)]
+pub struct BatchOptions {
+    /// Stop at the first command that fails
+    pub fail_fast: bool,
+    /// Look for secrets in facts being committed
+    pub scan: bool,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        BatchOptions { fail_fast: false, scan: true }
+    }
+}
+
+/// How many commands [`run`] ran and how many of those failed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BatchReport {
+    pub ran: usize,
+    pub failed: usize,
+}
+
+/// One command of a batch
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "cmd")]
+pub enum Command {
+    #[serde(rename = "c", alias = "commit")]
+    Commit(CommitArgs),
+    #[serde(rename = "tag")]
+    Tag {
+        id: Uuid,
+        #[serde(default)]
+        add: Vec<String>,
+        #[serde(default)]
+        remove: Vec<String>,
+    },
+    #[serde(rename = "d", alias = "delete")]
+    Delete {
+        id: Uuid,
+        #[serde(default)]
+        hard: bool,
+    },
+    #[serde(rename = "r", alias = "recall")]
+    Recall {
+        query: String,
+        #[serde(default)]
+        tag: Option<String>,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+    #[serde(rename = "get")]
+    Get { id: Uuid },
+}
+
+/// A fact to commit; its name is its text unless given
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct CommitArgs {
+    pub text: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub source: Option<String>,
+}
+
+/// Why a command of a batch failed, as reported on its line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchError {
+    /// [`WikiError::kind`] for errors from the wiki, else `invalid` for a
+    /// line that couldn't be read or `secret` for a refused commit
+    pub kind: &'static str,
+    pub message: String,
+}
+
+impl BatchError {
+    fn invalid(message: impl Into<String>) -> Self {
+        BatchError { kind: "invalid", message: message.into() }
+    }
+}
+
+impl From<WikiError> for BatchError {
+    fn from(e: WikiError) -> Self {
+        BatchError { kind: e.kind(), message: e.to_string() }
+    }
+}
+
+/// The command on `line`, or `None` for a blank line or comment
+pub fn parse_line(line: &str) -> Result<Option<Command>, BatchError> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    if line.starts_with('{') {
+        return serde_json::from_str(line).map(Some).map_err(|e| BatchError::invalid(e.to_string()));
+    }
+
+    let (op, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let rest = rest.trim();
+    let id = |text: &str| text.parse::<Uuid>().map_err(|_| BatchError::invalid(format!("'{}' isn't a fact id", text)));
+    let command = match op {
+        "c" | "commit" => {
+            let args = match serde_json::from_str::<Value>(rest) {
+                Ok(Value::String(text)) => CommitArgs { text, name: None, tags: Vec::new(), source: None },
+                Ok(object @ Value::Object(_)) => {
+                    serde_json::from_value(object).map_err(|e| BatchError::invalid(e.to_string()))?
+                }
+                _ => return Err(BatchError::invalid("expected a JSON object or string to commit")),
+            };
+            Command::Commit(args)
+        }
+        "tag" => {
+            let mut words = rest.split_whitespace();
+            let id = id(words.next().unwrap_or_default())?;
+            let (mut add, mut remove) = (Vec::new(), Vec::new());
+            for word in words {
+                match word.strip_prefix('-') {
+                    Some(tag) => remove.push(tag.to_string()),
+                    None => add.push(word.strip_prefix('+').unwrap_or(word).to_string()),
+                }
+            }
+            Command::Tag { id, add, remove }
+        }
+        "d" | "delete" => match rest.split_whitespace().collect::<Vec<_>>()[..] {
+            [fact] => Command::Delete { id: id(fact)?, hard: false },
+            [fact, "--hard"] | ["--hard", fact] => Command::Delete { id: id(fact)?, hard: true },
+            _ => return Err(BatchError::invalid("expected `d <id>` or `d <id> --hard`")),
+        },
+        "r" | "recall" if !rest.is_empty() => Command::Recall { query: rest.to_string(), tag: None, limit: None },
+        "r" | "recall" => return Err(BatchError::invalid("expected a query after `r`")),
+        "get" => Command::Get { id: id(rest)? },
+        _ => return Err(BatchError::invalid(format!("unknown command '{}'", op))),
+    };
+    Ok(Some(command))
+}
+
+/// Run every command read from `input` against `wiki`, writing a line of
+/// JSON for each to `output`; see the [module docs](self). Only failing to
+/// read `input` or write `output` ends the batch with an error.
+pub fn run(
+    wiki: &mut Wiki,
+    input: impl BufRead,
+    mut output: impl Write,
+    options: BatchOptions,
+) -> Result<BatchReport, WikiError> {
+    let scanner = options.scan.then(|| wiki.secret_scanner()).transpose()?;
+    let mut report = BatchReport::default();
+    for (i, line) in input.lines().enumerate() {
+        let result = match parse_line(&line?) {
+            Ok(None) => continue,
+            Ok(Some(command)) => execute(wiki, command, scanner.as_ref()),
+            Err(e) => Err(e),
+        };
+        report.ran += 1;
+        let reply = match &result {
+            Ok(result) => json!({ "line": i + 1, "ok": true, "result": result }),
+            Err(e) => {
+                report.failed += 1;
+                json!({ "line": i + 1, "ok": false, "error": { "kind": e.kind, "message": e.message } })
+            }
+        };
+        serde_json::to_writer(&mut output, &reply).map_err(std::io::Error::other)?;
+        output.write_all(b"\n")?;
+        if result.is_err() && options.fail_fast {
+            break;
+        }
+    }
+    output.flush()?;
+    Ok(report)
+}
+
+/// Carry out one command, returning what to report for it
+pub fn execute(wiki: &mut Wiki, command: Command, scanner: Option<&Scanner>) -> Result<Value, BatchError> {
+    match command {
+        Command::Commit(args) => {
+            let (text, _) = expand_snippets(&args.text, &wiki.config.snippets);
+            let name = args.name.unwrap_or_else(|| text.clone());
+            let (name, text) = match scanner {
+                Some(scanner) => check_secrets(scanner, wiki.config.secrets.policy, name, text)?,
+                None => (name, text),
+            };
+            let id = wiki.commit_named(name, text, args.tags, args.source)?;
+            Ok(json!(wiki.get(id)?))
+        }
+        Command::Tag { id, add, remove } => {
+            let mut tags = wiki.get(id)?.tags;
+            tags.retain(|tag| !remove.contains(tag));
+            for tag in add {
+                if !tags.contains(&tag) {
+                    tags.push(tag);
+                }
+            }
+            Ok(json!(wiki.retag(id, tags)?))
+        }
+        Command::Delete { id, hard } => Ok(json!(wiki.delete(id, hard)?)),
+        Command::Recall { query, tag, limit } => {
+            let limit = limit.unwrap_or(DEFAULT_LIMIT);
+            let hits = wiki.recall_refs(&query, tag.as_deref(), Fields::ALL, TimeWindow::ANY, Some(limit));
+            Ok(json!(hits.iter().map(|hit| &**hit).collect::<Vec<_>>()))
+        }
+        Command::Get { id } => Ok(json!(wiki.get(id)?)),
+    }
+}
+
+/// The name and text to commit under `policy`, which with nobody to ask
+/// either redacts secrets or refuses the commit
+fn check_secrets(
+    scanner: &Scanner,
+    policy: SecretPolicy,
+    name: String,
+    text: String,
+) -> Result<(String, String), BatchError> {
+    let found: Vec<_> = scanner.scan(&name).into_iter().chain(scanner.scan(&text)).collect();
+    let Some(first) = found.first() else {
+        return Ok((name, text));
+    };
+    match policy {
+        SecretPolicy::Redact => Ok((scanner.redact(&name).0, scanner.redact(&text).0)),
+        SecretPolicy::Block | SecretPolicy::Warn => Err(BatchError {
+            kind: "secret",
+            message: format!(
+                "looks like it holds a secret ({}); run with --no-scan if it's meant to be there",
+                first.pattern
+            ),
+        }),
+    }
+}
@@ -0,0 +1,64 @@
+//! The one way twk writes its JSON files, so a file only changes where its
+//! content does and diffs stay small in a wiki tracked by git.
+//!
+//! Every object starts with whichever of `id`, `name`, `tags`, `created`
+//! and `updated` it has, in that order, followed by its other keys in
+//! alphabetical order. Nesting is indented by two spaces, and the file ends
+//! with a newline. Adding a field to [`Information`](crate::Information)
+//! only ever adds lines.
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// Keys that come first in every object, in this order
+pub const LEADING_KEYS: [&str; 5] = ["id", "name", "tags", "created", "updated"];
+
+/// `value` as JSON in the canonical format
+pub fn to_vec<T: Serialize + ?Sized>(value: &T) -> std::io::Result<Vec<u8>> {
+    let value = serde_json::to_value(value).map_err(std::io::Error::other)?;
+    let mut out = String::new();
+    write_value(&mut out, &value, 0);
+    out.push('\n');
+    Ok(out.into_bytes())
+}
+
+fn write_value(out: &mut String, value: &Value, depth: usize) {
+    match value {
+        Value::Array(items) if !items.is_empty() => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                out.push_str(if i == 0 { "\n" } else { ",\n" });
+                indent(out, depth + 1);
+                write_value(out, item, depth + 1);
+            }
+            out.push('\n');
+            indent(out, depth);
+            out.push(']');
+        }
+        Value::Object(fields) if !fields.is_empty() => {
+            out.push('{');
+            for (i, (key, field)) in ordered(fields).enumerate() {
+                out.push_str(if i == 0 { "\n" } else { ",\n" });
+                indent(out, depth + 1);
+                out.push_str(&Value::from(key.as_str()).to_string());
+                out.push_str(": ");
+                write_value(out, field, depth + 1);
+            }
+            out.push('\n');
+            indent(out, depth);
+            out.push('}');
+        }
+        // Scalars, `[]` and `{}` are written the same at any depth
+        other => out.push_str(&other.to_string()),
+    }
+}
+
+/// The object's fields in canonical order; `Map` already sorts its keys
+fn ordered(fields: &Map<String, Value>) -> impl Iterator<Item = (&String, &Value)> {
+    let leading = LEADING_KEYS.iter().filter_map(|key| fields.get_key_value(*key));
+    leading.chain(fields.iter().filter(|(key, _)| !LEADING_KEYS.contains(&key.as_str())))
+}
+
+fn indent(out: &mut String, depth: usize) {
+    out.extend(std::iter::repeat_n("  ", depth));
+}
@@ -0,0 +1,49 @@
+//! Sparklines and bar charts drawn with Unicode blocks, for `wk stats --history`
+
+use unicode_width::UnicodeWidthStr;
+
+/// Column heights from lowest to highest; a sparkline's zero is the lowest
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+/// Partial cells of a bar, from an eighth to seven eighths of a cell wide
+const EIGHTHS: [char; 7] = ['▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+/// Spaces between a bar chart's labels, bars and counts
+const GAP: usize = 1;
+
+/// One character per count, scaled so the largest is a full block. Zero is
+/// always the lowest block and anything above zero at least the next one up.
+pub fn sparkline(counts: &[usize]) -> String {
+    let max = counts.iter().copied().max().unwrap_or(0).max(1);
+    counts.iter().map(|&n| SPARKS[(n * (SPARKS.len() - 1)).div_ceil(max)]).collect()
+}
+
+/// A bar `count / max` of `width` cells long, to the nearest eighth of a cell
+pub fn bar(count: usize, max: usize, width: usize) -> String {
+    if max == 0 {
+        return String::new();
+    }
+    let eighths = count * width * 8 / max;
+    let mut out = "█".repeat(eighths / 8);
+    match eighths % 8 {
+        0 => {}
+        part => out.push(EIGHTHS[part - 1]),
+    }
+    out
+}
+
+/// A labelled bar per row, followed by its count, fitted into `width`
+/// columns. Bars are scaled against the largest count; a row counting zero
+/// has no bar but still shows its `0`.
+pub fn bar_chart(rows: &[(String, usize)], width: usize) -> Vec<String> {
+    let label_width = rows.iter().map(|(label, _)| label.width()).max().unwrap_or(0);
+    let max = rows.iter().map(|&(_, n)| n).max().unwrap_or(0);
+    let count_width = max.to_string().len();
+    let bar_width = width.saturating_sub(label_width + count_width + 2 * GAP).max(1);
+    rows.iter()
+        .map(|(label, n)| {
+            let bar = bar(*n, max, bar_width);
+            let pad = label_width - label.width();
+            let gap = " ".repeat(GAP);
+            format!("{}{}{}{}{}{}", label, " ".repeat(pad), gap, bar, if bar.is_empty() { "" } else { &gap }, n)
+        })
+        .collect()
+}
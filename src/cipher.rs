@@ -0,0 +1,160 @@
+//! The ciphers behind [`crate::encryption`]: argon2id to derive a key from
+//! the passphrase, XChaCha20-Poly1305 to seal each file with it
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::encryption::ENCRYPTION_FILE;
+use crate::helpers::write_atomic;
+
+/// Plaintext sealed into the marker so a wrong passphrase is caught on unlock
+const CHECK: &[u8] = b"twk";
+
+#[derive(Serialize, Deserialize)]
+struct Marker {
+    kdf: KdfParams,
+    /// [`CHECK`] sealed with the derived key
+    check: Envelope,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct KdfParams {
+    /// Always `argon2id`
+    algorithm: String,
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+    salt: String,
+}
+
+/// What an encrypted fact file holds instead of the fact
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Envelope {
+    nonce: String,
+    ciphertext: String,
+}
+
+/// The key of an unlocked wiki
+pub struct Cipher {
+    aead: XChaCha20Poly1305,
+}
+
+impl Cipher {
+    fn derive(passphrase: &str, kdf: &KdfParams) -> std::io::Result<Self> {
+        if kdf.algorithm != "argon2id" {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("unsupported key derivation '{}'", kdf.algorithm),
+            ));
+        }
+        let params = Params::new(kdf.m_cost, kdf.t_cost, kdf.p_cost, Some(32))
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+        let salt = decode(&kdf.salt)?;
+
+        let mut key = [0u8; 32];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+        Ok(Cipher {
+            aead: XChaCha20Poly1305::new(&key.into()),
+        })
+    }
+
+    /// Seal `plaintext` under a fresh random nonce, as JSON
+    pub fn encrypt(&self, plaintext: &[u8]) -> std::io::Result<Vec<u8>> {
+        serde_json::to_vec_pretty(&self.seal(plaintext)?).map_err(Error::other)
+    }
+
+    /// Open what [`Cipher::encrypt`] produced; `None` if `bytes` isn't an
+    /// encrypted file at all
+    pub fn decrypt(&self, bytes: &[u8]) -> Option<std::io::Result<Vec<u8>>> {
+        let envelope: Envelope = serde_json::from_slice(bytes).ok()?;
+        Some(self.open(&envelope))
+    }
+
+    fn seal(&self, plaintext: &[u8]) -> std::io::Result<Envelope> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .aead
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| Error::other("encryption failed"))?;
+        Ok(Envelope {
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+        })
+    }
+
+    fn open(&self, envelope: &Envelope) -> std::io::Result<Vec<u8>> {
+        let nonce = decode(&envelope.nonce)?;
+        if nonce.len() != 24 {
+            return Err(Error::new(ErrorKind::InvalidData, "bad nonce"));
+        }
+        self.aead
+            .decrypt(XNonce::from_slice(&nonce), decode(&envelope.ciphertext)?.as_slice())
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "decryption failed; wrong key or damaged file"))
+    }
+}
+
+fn decode(text: &str) -> std::io::Result<Vec<u8>> {
+    BASE64
+        .decode(text)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))
+}
+
+/// Derive the key of the encrypted wiki at `path` from `passphrase`, failing
+/// if it is the wrong one
+pub fn unlock_with(path: &Path, passphrase: &str) -> std::io::Result<Cipher> {
+    let bytes = std::fs::read(path.join(ENCRYPTION_FILE))?;
+    let marker: Marker =
+        serde_json::from_slice(&bytes).map_err(|e| Error::new(ErrorKind::InvalidData, e.to_string()))?;
+
+    let cipher = Cipher::derive(passphrase, &marker.kdf)?;
+    match cipher.open(&marker.check) {
+        Ok(check) if check == CHECK => Ok(cipher),
+        _ => Err(Error::new(ErrorKind::PermissionDenied, "wrong passphrase")),
+    }
+}
+
+/// Key settings for a wiki about to be encrypted; nothing is written until
+/// [`Setup::write_marker`]
+pub struct Setup {
+    kdf: KdfParams,
+    pub cipher: Arc<Cipher>,
+}
+
+impl Setup {
+    /// Derive a key from `passphrase` with a fresh salt
+    pub fn new(passphrase: &str) -> std::io::Result<Self> {
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let kdf = KdfParams {
+            algorithm: "argon2id".to_string(),
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+            salt: BASE64.encode(salt),
+        };
+        let cipher = Arc::new(Cipher::derive(passphrase, &kdf)?);
+        Ok(Setup { kdf, cipher })
+    }
+
+    /// Mark the wiki at `path` as encrypted with this key
+    pub fn write_marker(&self, path: &Path) -> std::io::Result<()> {
+        let marker = Marker {
+            kdf: self.kdf.clone(),
+            check: self.cipher.seal(CHECK)?,
+        };
+        let json = serde_json::to_vec_pretty(&marker).map_err(Error::other)?;
+        write_atomic(&path.join(ENCRYPTION_FILE), &json)
+    }
+}
+
@@ -0,0 +1,103 @@
+use arboard::Clipboard;
+use std::io::{Error, ErrorKind};
+
+/// Hidden `wk` subcommand that keeps offering copied text to other programs.
+///
+/// On X11 and Wayland the clipboard is served by the program that set it, so
+/// [`write`] hands the text to a detached `wk` running this until something
+/// else is copied.
+pub const HOLD_COMMAND: &str = "hold-clipboard";
+
+/// The text currently on the system clipboard
+pub fn read() -> std::io::Result<String> {
+    open()?.get_text().map_err(describe)
+}
+
+/// A connection to the clipboard held open to read it again and again, as
+/// `wk watch-clipboard` does
+pub struct Reader(Clipboard);
+
+impl Reader {
+    pub fn open() -> std::io::Result<Self> {
+        Ok(Reader(open()?))
+    }
+
+    /// The text on the clipboard; `None` if it holds something else or nothing
+    pub fn text(&mut self) -> std::io::Result<Option<String>> {
+        match self.0.get_text() {
+            Ok(text) => Ok(Some(text)),
+            Err(arboard::Error::ContentNotAvailable) => Ok(None),
+            Err(e) => Err(describe(e)),
+        }
+    }
+}
+
+/// Put `text` on the system clipboard so it outlives this process
+pub fn write(text: &str) -> std::io::Result<()> {
+    #[cfg(all(unix, not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))))]
+    {
+        use std::io::Write;
+        use std::process::{Command, Stdio};
+
+        check_display()?;
+        let mut child = Command::new(std::env::current_exe()?)
+            .arg(HOLD_COMMAND)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        child
+            .stdin
+            .take()
+            .ok_or_else(|| Error::other("couldn't pass the text on"))?
+            .write_all(text.as_bytes())?;
+        Ok(())
+    }
+    #[cfg(not(all(unix, not(any(target_os = "macos", target_os = "android", target_os = "emscripten")))))]
+    {
+        open()?.set_text(text).map_err(describe)
+    }
+}
+
+/// Set the clipboard to `text` and block until another program replaces
+/// it; what [`HOLD_COMMAND`] runs
+pub fn hold(text: &str) -> std::io::Result<()> {
+    let mut clipboard = open()?;
+    #[cfg(all(unix, not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))))]
+    {
+        use arboard::SetExtLinux;
+        clipboard.set().wait().text(text).map_err(describe)
+    }
+    #[cfg(not(all(unix, not(any(target_os = "macos", target_os = "android", target_os = "emscripten")))))]
+    {
+        clipboard.set_text(text).map_err(describe)
+    }
+}
+
+fn open() -> std::io::Result<Clipboard> {
+    check_display()?;
+    Clipboard::new().map_err(describe)
+}
+
+/// Fail clearly when there's no X11 or Wayland display to reach the clipboard
+/// through, as over SSH or on a bare console
+fn check_display() -> std::io::Result<()> {
+    #[cfg(all(unix, not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))))]
+    if std::env::var_os("WAYLAND_DISPLAY").is_none() && std::env::var_os("DISPLAY").is_none() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            "no display to reach the clipboard through: neither WAYLAND_DISPLAY nor DISPLAY is set",
+        ));
+    }
+    Ok(())
+}
+
+fn describe(e: arboard::Error) -> Error {
+    match e {
+        arboard::Error::ContentNotAvailable => Error::new(ErrorKind::NotFound, "the clipboard doesn't hold any text"),
+        arboard::Error::ClipboardNotSupported => {
+            Error::new(ErrorKind::Unsupported, "the clipboard isn't supported on this system")
+        }
+        e => Error::other(format!("couldn't use the clipboard: {}", e)),
+    }
+}
@@ -0,0 +1,124 @@
+//! Commands of one's own, from the `[commands]` table of the global config.
+//!
+//! Each maps a name to the arguments it stands for, like
+//! `todo = "c {args} todo"`. Before the command line is parsed, a custom
+//! command in the place of the subcommand is replaced by its template, with
+//! the arguments given after it put where `{args}` is, or at the end if the
+//! template has no `{args}`. Templates are split into words as a shell
+//! would, so `"two words"` and `'two words'` are one argument. A template
+//! may start with another custom command, but not lead back to one already
+//! expanded.
+
+use std::collections::BTreeMap;
+
+/// Where a template takes the arguments given after the command
+pub const ARGS: &str = "{args}";
+
+/// Check a `[commands]` table can be used: names must be single words that
+/// don't look like options, and templates must split into words
+pub(crate) fn check_commands(commands: &BTreeMap<String, String>) -> Result<(), String> {
+    for (name, template) in commands {
+        if name.is_empty() || name.starts_with('-') || name.contains(char::is_whitespace) {
+            return Err(format!("command name '{}' must be a single word not starting with '-'", name));
+        }
+        match split_words(template) {
+            Ok(words) if words.is_empty() => return Err(format!("command '{}' has an empty template", name)),
+            Ok(_) => {}
+            Err(e) => return Err(format!("command '{}': {}", name, e)),
+        }
+    }
+    Ok(())
+}
+
+/// Refuse custom commands named like one of `builtins`, which they'd hide
+pub fn check_builtins(commands: &BTreeMap<String, String>, builtins: &[String]) -> Result<(), String> {
+    match commands.keys().find(|name| builtins.contains(name)) {
+        Some(name) => Err(format!("[commands] can't redefine the built-in command '{}'", name)),
+        None => Ok(()),
+    }
+}
+
+/// `template` split into words: whitespace separates them except inside
+/// single or double quotes, and a backslash outside single quotes takes the
+/// next character as it is
+pub fn split_words(template: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote: Option<char> = None;
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('\''), c) => word.get_or_insert_default().push(c),
+            (_, '\\') => match chars.next() {
+                Some(next) => word.get_or_insert_default().push(next),
+                None => return Err("ends in a lone backslash".to_string()),
+            },
+            (Some(_), c) => word.get_or_insert_default().push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                word.get_or_insert_default();
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_default().push(c),
+        }
+    }
+    if let Some(q) = quote {
+        return Err(format!("has an unclosed {}", q));
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// The words of a template with `args` in place of `{args}`: spliced in
+/// where it's a word of its own, joined with spaces where it's part of
+/// one, and added at the end if it's nowhere
+pub fn fill(words: &[String], args: &[String]) -> Vec<String> {
+    if !words.iter().any(|word| word.contains(ARGS)) {
+        return words.iter().chain(args).cloned().collect();
+    }
+    let mut filled = Vec::new();
+    for word in words {
+        match word.as_str() {
+            ARGS => filled.extend(args.iter().cloned()),
+            _ => filled.push(word.replace(ARGS, &args.join(" "))),
+        }
+    }
+    filled
+}
+
+/// `argv`, program name first, with a custom command in the place of the
+/// subcommand expanded. Options before it are skipped, along with the value
+/// following any for which `takes_value` is true. Nothing after `--` is a
+/// command.
+pub fn rewrite(
+    mut argv: Vec<String>,
+    commands: &BTreeMap<String, String>,
+    takes_value: impl Fn(&str) -> bool,
+) -> Result<Vec<String>, String> {
+    let mut at = 1;
+    while let Some(arg) = argv.get(at) {
+        match arg.as_str() {
+            "--" => return Ok(argv),
+            option if option.starts_with('-') => {
+                at += if !option.contains('=') && takes_value(option) { 2 } else { 1 };
+            }
+            _ => break,
+        }
+    }
+
+    let mut expanded: Vec<String> = Vec::new();
+    while let Some(template) = argv.get(at).and_then(|name| commands.get(name)) {
+        let name = argv[at].clone();
+        if expanded.contains(&name) {
+            expanded.push(name);
+            return Err(format!("custom commands lead back to themselves: {}", expanded.join(" -> ")));
+        }
+        let words = split_words(template).map_err(|e| format!("command '{}': {}", name, e))?;
+        let args = argv.split_off(at + 1);
+        argv.truncate(at);
+        argv.extend(fill(&words, &args));
+        expanded.push(name);
+    }
+    Ok(argv)
+}
@@ -0,0 +1,261 @@
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::commands;
+use crate::matching::MatchConfig;
+use crate::secrets::{self, SecretPolicy};
+use crate::size;
+use crate::snippets;
+use crate::tags::{self, TagColor};
+use crate::wiki::Information;
+
+/// Name of the per-wiki config file inside a wiki directory
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// Settings from the global `config.toml`, overridden key by key by the
+/// wiki's own `config.toml`
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// How facts are ordered in `Wiki::info`
+    pub order: Order,
+    /// Wiki to use when `TWK_WIKI` isn't set; only read from the global config
+    pub wiki: Option<String>,
+    /// Root of global wikis; only read from the global config
+    pub data_dir: Option<PathBuf>,
+    /// Commit every change to the git repository the wiki lives in; needs
+    /// the `git` feature
+    pub git: bool,
+    /// Similarity from 0 to 1 at which `wk c` asks before committing a fact
+    /// much like an existing one; [`DEFAULT_DUPLICATE_THRESHOLD`] if unset
+    pub duplicate_threshold: Option<f32>,
+    /// Refuse every change to the wiki, e.g. for viewers of a shared copy
+    pub readonly: bool,
+    /// Show how long ago each fact changed in a column of the TUI list;
+    /// toggled there with `:cols date`
+    pub date_column: bool,
+    /// How long deleted facts stay in the trash, as an age like `30d`; older
+    /// ones are purged whenever the wiki is opened. Kept until `wk trash
+    /// empty` if unset.
+    pub trash_retention: Option<String>,
+    /// Tags that stand for others, like `js = "javascript"`. Facts are tagged
+    /// with the canonical name when committed or changed, and filtering by
+    /// either name finds both. Aliases must name a canonical tag, not another alias.
+    pub aliases: BTreeMap<String, String>,
+    /// Colours for tags in the TUI, like `bug = "red"`, also applying to tags
+    /// nested under them; others get one picked from their name
+    pub tag_colors: BTreeMap<String, TagColor>,
+    /// Multipliers for the recall scores of facts with these tags, or tags
+    /// nested under them, like `{ important = 1.5, archive = 0.5 }`. They
+    /// reorder matches but never make a fact match.
+    pub tag_weights: BTreeMap<String, f32>,
+    /// Tags added to every fact committed, like `["recipe"]` for a recipes wiki
+    pub default_tags: Vec<String>,
+    /// Refuse to commit a fact that ends up with no tags, default ones included
+    pub require_tags: bool,
+    /// Longest a tag may be, in characters;
+    /// [`crate::validate::DEFAULT_MAX_TAG_LEN`] if unset
+    pub max_tag_len: Option<usize>,
+    /// Bytes of data past which `wk c` warns about a fact and recall matches
+    /// only its name and tags; [`crate::size::DEFAULT_LARGE_FACT_BYTES`] if unset
+    pub large_fact_bytes: Option<usize>,
+    /// Most bytes of data a fact may be committed or changed to;
+    /// [`crate::size::DEFAULT_MAX_FACT_BYTES`] if unset
+    pub max_fact_bytes: Option<usize>,
+    /// Scripts to run around each commit; only read from the global config,
+    /// see [`crate::hooks`]
+    pub hooks: Hooks,
+    /// Words that stand for longer text, like `";;k8s" = "kubernetes"`,
+    /// expanded in facts committed with `wk c` and as they're typed in the
+    /// TUI editor
+    pub snippets: BTreeMap<String, String>,
+    /// What to look for and do about secrets in facts being committed
+    pub secrets: Secrets,
+    /// Count how often and how lately each fact is used, on this machine
+    /// only, for `--sort frecency`
+    pub track_usage: bool,
+    /// Rename facts named after their data whenever the wiki is opened, if
+    /// the data has changed since, as `wk doctor --fix` does. Reads every
+    /// fact in full on each open.
+    pub refresh_names: bool,
+    /// Where the wiki's book is published, like `https://wiki.example.com/`,
+    /// for the links in its Atom feed; no feed is written without it
+    pub book_url: Option<String>,
+    /// What facts are edited with when `$EDITOR` isn't set, like `code
+    /// --wait`; only read from the global config
+    pub editor: Option<String>,
+    /// Commands of one's own, like `todo = "c {args} todo"`; only read from
+    /// the global config, see [`crate::commands`]
+    pub commands: BTreeMap<String, String>,
+    /// How queries are fuzzy matched, see [`crate::matching`]
+    #[serde(rename = "match")]
+    pub matching: MatchConfig,
+}
+
+/// The `[hooks]` table: scripts run with a fact as JSON on stdin, found
+/// relative to the global config's directory and run in the wiki directory
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct Hooks {
+    /// Run before a fact is committed; exiting non-zero refuses the commit,
+    /// with what the script wrote to stderr as the reason
+    pub pre_commit: Option<PathBuf>,
+    /// Run after a fact is committed, id and all; failing only warns
+    pub post_commit: Option<PathBuf>,
+    /// Seconds a hook may run before it's killed and counts as failed;
+    /// [`crate::hooks::DEFAULT_HOOK_TIMEOUT`] if unset
+    pub timeout: Option<u64>,
+}
+
+/// The `[secrets]` table, see [`crate::secrets`]
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct Secrets {
+    /// Whether a fact with a secret in it is committed after asking, refused
+    /// or redacted; `warn` if unset
+    pub policy: SecretPolicy,
+    /// More regular expressions to look for, by name, like
+    /// `internal_token = "itk_[a-z0-9]{32}"`; one named like a built-in
+    /// pattern replaces it
+    pub patterns: BTreeMap<String, String>,
+}
+
+/// Similarity at which a new fact counts as a likely duplicate
+pub const DEFAULT_DUPLICATE_THRESHOLD: f32 = 0.8;
+
+/// Sort order of a wiki's facts; ties are broken by id so it is total
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Order {
+    /// Oldest first; facts without a creation time come first
+    #[default]
+    Created,
+    /// Alphabetical by name, ignoring case
+    Name,
+    /// By id only
+    Id,
+}
+
+impl Order {
+    pub fn compare(self, a: &Information, b: &Information) -> Ordering {
+        match self {
+            Order::Created => a.created.cmp(&b.created),
+            Order::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            Order::Id => Ordering::Equal,
+        }
+        .then(a.id.cmp(&b.id))
+    }
+}
+
+/// Location of the global config file, `<config dir>/twk/config.toml`
+pub fn global_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("twk").join(CONFIG_FILE))
+}
+
+impl Config {
+    /// Load the config that applies to the wiki at `wiki_path`
+    pub fn load(wiki_path: &Path) -> std::io::Result<Self> {
+        let mut table = global_table()?;
+        let mut own = read_table(&wiki_path.join(CONFIG_FILE))?;
+        own.remove(HOOKS_KEY);
+        table.extend(own);
+        Self::from_table(table)
+    }
+
+    /// Load only the global config, for settings that apply before a wiki is chosen
+    pub fn load_global() -> std::io::Result<Self> {
+        Self::from_table(global_table()?)
+    }
+
+    fn from_table(table: toml::Table) -> std::io::Result<Self> {
+        let invalid = |e: String| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("invalid config: {}", e));
+        let config = Config::deserialize(table).map_err(|e| invalid(e.to_string()))?;
+        tags::check_aliases(&config.aliases).map_err(invalid)?;
+        snippets::check_snippets(&config.snippets).map_err(invalid)?;
+        commands::check_commands(&config.commands).map_err(invalid)?;
+        secrets::check_patterns(&config.secrets.patterns).map_err(invalid)?;
+        size::check_limits(&config).map_err(invalid)?;
+        Ok(config)
+    }
+}
+
+/// Key of the `[hooks]` table, left out of a wiki's own config
+const HOOKS_KEY: &str = "hooks";
+
+/// Why the `[hooks]` in the own config of the wiki at `wiki_path` are
+/// ignored, if it has any
+pub(crate) fn ignored_hooks(wiki_path: &Path) -> Option<String> {
+    let own = read_table(&wiki_path.join(CONFIG_FILE)).ok()?;
+    own.contains_key(HOOKS_KEY).then(|| {
+        "[hooks] is only read from the global config, so these hooks don't run; move them there to use them".to_string()
+    })
+}
+
+/// The config file that sets `key` for the wiki at `wiki_path`: its own if
+/// it does, else the global one, or `None` if neither does (or can be read)
+pub fn defined_in(wiki_path: &Path, key: &str) -> Option<PathBuf> {
+    let own = wiki_path.join(CONFIG_FILE);
+    if read_table(&own).is_ok_and(|table| table.contains_key(key)) {
+        return Some(own);
+    }
+    global_config_path().filter(|path| read_table(path).is_ok_and(|table| table.contains_key(key)))
+}
+
+/// What the first-run wizard puts in a new global config
+#[derive(Debug, Default, Clone)]
+pub struct Initial {
+    pub wiki: Option<String>,
+    pub default_tags: Vec<String>,
+    pub editor: Option<String>,
+}
+
+/// Write a config file at `path` holding `initial`, unless one is already
+/// there; whether it was written. An existing config is never replaced.
+pub fn write_initial(path: &Path, initial: &Initial) -> std::io::Result<bool> {
+    let mut table = toml::Table::new();
+    if let Some(wiki) = &initial.wiki {
+        table.insert("wiki".to_string(), wiki.clone().into());
+    }
+    if !initial.default_tags.is_empty() {
+        table.insert("default_tags".to_string(), initial.default_tags.clone().into());
+    }
+    if let Some(editor) = &initial.editor {
+        table.insert("editor".to_string(), editor.clone().into());
+    }
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    // create_new rather than a check first, so a config written meanwhile survives
+    let mut file = match std::fs::OpenOptions::new().write(true).create_new(true).open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    std::io::Write::write_all(&mut file, table.to_string().as_bytes())?;
+    Ok(true)
+}
+
+fn global_table() -> std::io::Result<toml::Table> {
+    match global_config_path() {
+        Some(path) => read_table(&path),
+        None => Ok(toml::Table::new()),
+    }
+}
+
+/// Parse a TOML file into a table; a missing file is an empty table
+fn read_table(path: &Path) -> std::io::Result<toml::Table> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(toml::Table::new()),
+        Err(e) => return Err(e),
+    };
+    text.parse().map_err(|e| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), e),
+        )
+    })
+}
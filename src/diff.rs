@@ -0,0 +1,110 @@
+//! Line diffs between two versions of a fact's text, for `wk diff`.
+//!
+//! Lines both versions share are found with Myers' algorithm in linear
+//! space, so a long fact with scattered edits costs little more memory
+//! than its own lines.
+
+use similar::{Algorithm, DiffTag};
+
+/// One line of a diff, from the old text, the new one or both
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Line<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+impl Line<'_> {
+    pub fn is_change(&self) -> bool {
+        !matches!(self, Line::Same(_))
+    }
+}
+
+/// Changed lines with up to [`CONTEXT`] unchanged lines around them, as a
+/// unified diff numbers them: from 1, with an empty range starting at the
+/// line before it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk<'a> {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+    pub lines: Vec<Line<'a>>,
+}
+
+/// Unchanged lines kept either side of a change, as `diff -u` does
+pub const CONTEXT: usize = 3;
+
+/// How many lines a diff adds and removes
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DiffStat {
+    pub added: usize,
+    pub removed: usize,
+}
+
+/// Every line of `old` and `new` in order, marked as kept, removed or added
+pub fn diff_lines<'a>(old: &'a str, new: &'a str) -> Vec<Line<'a>> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let mut lines = Vec::with_capacity(old.len().max(new.len()));
+    for op in similar::capture_diff_slices(Algorithm::Myers, &old, &new) {
+        let (tag, a, b) = op.as_tag_tuple();
+        match tag {
+            DiffTag::Equal => lines.extend(old[a].iter().map(|line| Line::Same(line))),
+            DiffTag::Delete => lines.extend(old[a].iter().map(|line| Line::Removed(line))),
+            DiffTag::Insert => lines.extend(new[b].iter().map(|line| Line::Added(line))),
+            DiffTag::Replace => {
+                lines.extend(old[a].iter().map(|line| Line::Removed(line)));
+                lines.extend(new[b].iter().map(|line| Line::Added(line)));
+            }
+        }
+    }
+    lines
+}
+
+/// The changes of `lines` gathered into hunks, merging those whose context
+/// would overlap; none if nothing changed
+pub fn hunks<'a>(lines: &[Line<'a>]) -> Vec<Hunk<'a>> {
+    let changes: Vec<usize> = (0..lines.len()).filter(|&i| lines[i].is_change()).collect();
+    let mut groups: Vec<(usize, usize)> = Vec::new();
+    for &i in &changes {
+        match groups.last_mut() {
+            Some((_, last)) if i - *last <= 2 * CONTEXT => *last = i,
+            _ => groups.push((i, i)),
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|(first, last)| {
+            let start = first.saturating_sub(CONTEXT);
+            let end = (last + CONTEXT + 1).min(lines.len());
+            let before = &lines[..start];
+            let old_before = before.iter().filter(|l| !matches!(l, Line::Added(_))).count();
+            let new_before = before.iter().filter(|l| !matches!(l, Line::Removed(_))).count();
+            let hunk = &lines[start..end];
+            let old_len = hunk.iter().filter(|l| !matches!(l, Line::Added(_))).count();
+            let new_len = hunk.iter().filter(|l| !matches!(l, Line::Removed(_))).count();
+            Hunk {
+                old_start: old_before + (old_len > 0) as usize,
+                old_len,
+                new_start: new_before + (new_len > 0) as usize,
+                new_len,
+                lines: hunk.to_vec(),
+            }
+        })
+        .collect()
+}
+
+/// Lines added and removed across `lines`
+pub fn stat(lines: &[Line]) -> DiffStat {
+    let mut stat = DiffStat::default();
+    for line in lines {
+        match line {
+            Line::Added(_) => stat.added += 1,
+            Line::Removed(_) => stat.removed += 1,
+            Line::Same(_) => {}
+        }
+    }
+    stat
+}
@@ -0,0 +1,273 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::error::WikiError;
+use crate::helpers::{FileLock, LOCK_TIMEOUT, write_atomic};
+use crate::storage::{Backend, Storage};
+use crate::wiki::{Information, Wiki};
+
+/// File in each synced directory recording, per peer, the `updated` time of
+/// every fact as of the last sync; the common base conflicts are judged from
+pub const SYNC_STATE_FILE: &str = ".sync-state.json";
+
+/// Tag given to facts holding both sides of a conflicting edit
+pub const CONFLICT_TAG: &str = "conflict";
+
+#[derive(Serialize, Deserialize, Default)]
+struct SyncState {
+    /// By canonical path of the other directory
+    peers: BTreeMap<String, BTreeMap<Uuid, Option<DateTime<Utc>>>>,
+}
+
+impl SyncState {
+    fn load(dir: &Path) -> Self {
+        std::fs::read(dir.join(SYNC_STATE_FILE))
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self, dir: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_vec_pretty(self).map_err(Error::other)?;
+        write_atomic(&dir.join(SYNC_STATE_FILE), &json)
+    }
+}
+
+/// One step of reconciling a wiki with another copy of it
+#[derive(Debug, Clone)]
+pub enum DirSyncAction {
+    /// Copy a fact that is new or newer here to the other copy
+    Push(Information),
+    /// Copy a fact that is new or newer in the other copy here
+    Pull(Information),
+    /// Move a fact here that the other copy deleted to the trash
+    DeleteLocal(Information),
+    /// Move a fact in the other copy that was deleted here to its trash
+    DeletePeer(Information),
+    /// Both copies edited the fact since they last synced
+    Conflict(Box<DirSyncConflict>),
+}
+
+/// A fact edited in both copies. The newer edit replaces the older one, and
+/// `conflict` is added to both copies so the older edit isn't lost.
+#[derive(Debug, Clone)]
+pub struct DirSyncConflict {
+    pub local: Information,
+    pub peer: Information,
+    pub conflict: Information,
+}
+
+/// What [`Wiki::sync_dir`] did, or would do on a dry run
+#[derive(Debug)]
+pub struct DirSyncPlan {
+    pub peer: PathBuf,
+    pub actions: Vec<DirSyncAction>,
+}
+
+impl Wiki {
+    /// Reconcile this wiki with another copy of it in the directory `other`,
+    /// such as one kept in step by Syncthing, and return what was done.
+    ///
+    /// Facts are matched by id. One that changed on only one side since the
+    /// last sync is copied to the other, as is one that only exists on one
+    /// side; if the other side deleted it unchanged, it is deleted instead.
+    /// One edited on both sides becomes a [`DirSyncAction::Conflict`].
+    /// Without a previous sync the newer version wins. With `dry_run`, or if
+    /// the wiki is a dry run, nothing is written.
+    pub fn sync_dir(&mut self, other: &Path, dry_run: bool) -> Result<DirSyncPlan, WikiError> {
+        let dry_run = dry_run || self.is_dry_run();
+        if !other.is_dir() {
+            return Err(Error::new(ErrorKind::NotFound, format!("no wiki at {}", other.display())).into());
+        }
+        let here = self.path.canonicalize()?;
+        let peer_path = other.canonicalize()?;
+        if here == peer_path {
+            return Err(Error::new(ErrorKind::InvalidInput, "can't sync a wiki with itself").into());
+        }
+
+        if !dry_run {
+            self.check_writable()?;
+        }
+        let _lock = self.lock_exclusive()?;
+        let _peer_lock = FileLock::exclusive(&peer_path.join(".lock"), LOCK_TIMEOUT)?;
+
+        let peer = Backend::detect(&peer_path).open(&peer_path)?;
+        if peer.is_encrypted() != self.is_encrypted() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "can't sync an encrypted wiki with an unencrypted one",
+            )
+            .into());
+        }
+
+        let local_facts = self.storage.load_all()?;
+        let peer_facts = peer.load_all()?;
+        // An unreadable fact would look deleted and be deleted on the other side
+        for (path, loaded) in [(&here, &local_facts), (&peer_path, &peer_facts)] {
+            if !loaded.warnings.is_empty() {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "{} entries in {} could not be loaded; run `wk doctor` on it first",
+                        loaded.warnings.len(),
+                        path.display()
+                    ),
+                )
+                .into());
+            }
+        }
+
+        let local_state = SyncState::load(&here);
+        let peer_state = SyncState::load(&peer_path);
+        let empty = BTreeMap::new();
+        let base = local_state
+            .peers
+            .get(&key(&peer_path))
+            .or_else(|| peer_state.peers.get(&key(&here)))
+            .unwrap_or(&empty);
+
+        let actions = plan(
+            &by_id(local_facts.facts),
+            &by_id(peer_facts.facts),
+            base,
+            (&here.display().to_string(), &peer_path.display().to_string()),
+        );
+        let plan = DirSyncPlan {
+            peer: peer_path.clone(),
+            actions,
+        };
+        if dry_run {
+            return Ok(plan);
+        }
+
+        self.apply_dir_sync(peer.as_ref(), &plan.actions)?;
+
+        let synced: BTreeMap<Uuid, Option<DateTime<Utc>>> = self
+            .info
+            .iter()
+            .map(|l| {
+                let info = l.read();
+                (info.id, info.updated)
+            })
+            .collect();
+        let mut local_state = local_state;
+        local_state.peers.insert(key(&peer_path), synced.clone());
+        local_state.save(&here)?;
+        let mut peer_state = peer_state;
+        peer_state.peers.insert(key(&here), synced);
+        peer_state.save(&peer_path)?;
+
+        Ok(plan)
+    }
+
+    fn apply_dir_sync(&mut self, peer: &dyn Storage, actions: &[DirSyncAction]) -> Result<(), WikiError> {
+        for action in actions {
+            match action {
+                DirSyncAction::Push(info) => peer.write(info)?,
+                DirSyncAction::Pull(info) => self.put(info.clone())?,
+                DirSyncAction::DeleteLocal(info) => {
+                    self.delete(info.id, false)?;
+                }
+                DirSyncAction::DeletePeer(info) => peer.trash(info.id, Utc::now())?,
+                DirSyncAction::Conflict(c) => {
+                    if newer(&c.local, &c.peer) {
+                        peer.write(&c.local)?;
+                    } else {
+                        self.put(c.peer.clone())?;
+                    }
+                    // As committed here, default tags and all, so both sides hold the same fact
+                    let id = self.insert(c.conflict.clone())?;
+                    peer.write(&self.get(id)?)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Work out the actions for every id in either copy, in id order
+fn plan(
+    local: &HashMap<Uuid, Information>,
+    peer: &HashMap<Uuid, Information>,
+    base: &BTreeMap<Uuid, Option<DateTime<Utc>>>,
+    (local_label, peer_label): (&str, &str),
+) -> Vec<DirSyncAction> {
+    let ids: BTreeSet<Uuid> = local.keys().chain(peer.keys()).copied().collect();
+    let changed = |info: &Information| base.get(&info.id) != Some(&info.updated);
+
+    let mut actions = Vec::new();
+    for id in ids {
+        match (local.get(&id), peer.get(&id)) {
+            (Some(l), Some(p)) if l == p => {}
+            (Some(l), Some(p)) => {
+                let same_content = l.data == p.data && l.tags == p.tags;
+                let synced = base.contains_key(&id);
+                let both_changed = if synced { changed(l) && changed(p) } else { l.updated == p.updated };
+                // Only one side changed since the last sync, or there was none
+                let push = if synced && !both_changed { changed(l) } else { newer(l, p) };
+                if both_changed && !same_content {
+                    actions.push(DirSyncAction::Conflict(Box::new(DirSyncConflict {
+                        local: l.clone(),
+                        peer: p.clone(),
+                        conflict: conflict_fact(l, local_label, p, peer_label),
+                    })));
+                } else if push {
+                    actions.push(DirSyncAction::Push(l.clone()));
+                } else {
+                    actions.push(DirSyncAction::Pull(p.clone()));
+                }
+            }
+            // Deleted on the other side unless it was never synced; an edit
+            // since the last sync outweighs the deletion
+            (Some(l), None) if base.contains_key(&id) && !changed(l) => {
+                actions.push(DirSyncAction::DeleteLocal(l.clone()))
+            }
+            (Some(l), None) => actions.push(DirSyncAction::Push(l.clone())),
+            (None, Some(p)) if base.contains_key(&id) && !changed(p) => {
+                actions.push(DirSyncAction::DeletePeer(p.clone()))
+            }
+            (None, Some(p)) => actions.push(DirSyncAction::Pull(p.clone())),
+            (None, None) => {}
+        }
+    }
+    actions
+}
+
+/// A new fact tagged [`CONFLICT_TAG`] holding both versions of a fact between
+/// conflict markers, each labelled with where it came from
+pub(crate) fn conflict_fact(ours: &Information, ours_label: &str, theirs: &Information, theirs_label: &str) -> Information {
+    let stamp = |info: &Information| match info.updated.or(info.created) {
+        Some(at) => format!(" (updated {})", at.format("%Y-%m-%d %H:%M:%S UTC")),
+        None => String::new(),
+    };
+    let data = format!(
+        "Conflicting edits to {}\n<<<<<<< {}{}\n{}\n=======\n{}\n>>>>>>> {}{}",
+        ours.id,
+        ours_label,
+        stamp(ours),
+        ours.data,
+        theirs.data,
+        theirs_label,
+        stamp(theirs),
+    );
+    let mut info = Wiki::new_fact(data, vec![CONFLICT_TAG.to_string()]);
+    info.name = format!("Conflicting edits to {}", ours.id);
+    info
+}
+
+/// Whether `a` was updated after `b`; ties go to `a`
+fn newer(a: &Information, b: &Information) -> bool {
+    a.updated.or(a.created) >= b.updated.or(b.created)
+}
+
+fn by_id(facts: Vec<Information>) -> HashMap<Uuid, Information> {
+    facts.into_iter().map(|info| (info.id, info)).collect()
+}
+
+fn key(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
@@ -0,0 +1,425 @@
+use std::collections::BTreeMap;
+use std::fs::create_dir_all;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::dirsync::conflict_fact;
+use crate::error::WikiError;
+use crate::helpers::{FileLock, LOCK_TIMEOUT, lock_path_for};
+use crate::storage::{Backend, FsStorage, is_conflict_copy, recover_json};
+use crate::validate::{self, Field, ValidationError};
+use crate::wiki::{Information, Wiki};
+
+/// A problem found by one of the `Wiki::check_*` functions
+#[derive(Debug, Clone, PartialEq)]
+pub enum Finding {
+    /// A fact file, or other file in the wiki directory, that failed to load
+    Corrupt { path: PathBuf, error: String },
+    /// A fact file whose name doesn't match the id stored in it
+    Misnamed { path: PathBuf, id: Uuid },
+    /// The same id stored in more than one file
+    DuplicateId { id: Uuid, paths: Vec<PathBuf> },
+    /// A temp file left behind by an interrupted write
+    StrayTempFile { path: PathBuf },
+    /// A copy of a fact file made by Syncthing or Dropbox when it saw two
+    /// conflicting edits
+    ConflictCopy { path: PathBuf, id: Uuid },
+    /// A fact named after its data whose data has since changed, with the
+    /// name its first line gives it now; see [`Information::stale_name`]
+    StaleName { id: Uuid, name: String, derived: String },
+    /// A name or tag that needs trimming or wouldn't be accepted today, with
+    /// what normalizing it gives or why that still wouldn't do
+    InvalidValue {
+        id: Uuid,
+        field: Field,
+        value: String,
+        normalized: Result<String, ValidationError>,
+    },
+}
+
+impl Finding {
+    /// Heading the finding is listed under in a report
+    pub fn category(&self) -> &'static str {
+        match self {
+            Finding::Corrupt { .. } => "Unreadable files",
+            Finding::Misnamed { .. } => "Misnamed fact files",
+            Finding::DuplicateId { .. } => "Duplicate ids",
+            Finding::StrayTempFile { .. } => "Leftover temp files",
+            Finding::ConflictCopy { .. } => "Sync conflict copies",
+            Finding::StaleName { .. } => "Outdated names",
+            Finding::InvalidValue { .. } => "Malformed names and tags",
+        }
+    }
+}
+
+/// What [`Wiki::fix`] did about a finding
+#[derive(Debug)]
+pub enum Repair {
+    /// Salvaged from the last complete JSON document in the file
+    Recovered { path: PathBuf, id: Uuid },
+    /// Nothing usable could be read, or a newer copy exists; moved into the
+    /// `corrupt/` folder
+    Quarantined { path: PathBuf, to: PathBuf },
+    /// Renamed to match the id stored in it
+    Renamed { from: PathBuf, to: PathBuf },
+    /// Deleted outright
+    Removed { path: PathBuf },
+    /// Folded into a new fact tagged `conflict` holding both versions, then
+    /// deleted
+    Merged { path: PathBuf, id: Uuid },
+    /// Renamed after the first line of its data
+    Retitled { id: Uuid, from: String, to: String },
+    /// A name or tag replaced with its normalized form
+    Normalized { id: Uuid, field: Field, from: String, to: String },
+}
+
+/// A parsed fact file, for checks that look at the directory rather than
+/// what was loaded
+struct ScannedFile {
+    path: PathBuf,
+    info: Information,
+}
+
+impl Wiki {
+    /// Run every check, in report order
+    pub fn diagnose(&self) -> std::io::Result<Vec<Finding>> {
+        let mut findings = self.check_corrupt();
+        findings.extend(self.check_filenames()?);
+        findings.extend(self.check_duplicates()?);
+        findings.extend(self.check_stray_files()?);
+        findings.extend(self.check_conflict_copies());
+        findings.extend(self.check_names()?);
+        findings.extend(self.check_values());
+        Ok(findings)
+    }
+
+    /// Files that failed to load when the wiki was opened
+    pub fn check_corrupt(&self) -> Vec<Finding> {
+        self.warnings
+            .iter()
+            .map(|w| Finding::Corrupt {
+                path: w.path.clone(),
+                error: w.error.clone(),
+            })
+            .collect()
+    }
+
+    /// Fact files not named `<id>.json` after the id inside them
+    pub fn check_filenames(&self) -> std::io::Result<Vec<Finding>> {
+        Ok(self
+            .scan_fact_files()?
+            .into_iter()
+            .filter(|f| f.path.file_stem().and_then(|s| s.to_str()) != Some(&f.info.id.to_string()))
+            .map(|f| Finding::Misnamed {
+                path: f.path,
+                id: f.info.id,
+            })
+            .collect())
+    }
+
+    /// Ids stored in more than one fact file
+    pub fn check_duplicates(&self) -> std::io::Result<Vec<Finding>> {
+        let mut by_id: BTreeMap<Uuid, Vec<PathBuf>> = BTreeMap::new();
+        for file in self.scan_fact_files()? {
+            by_id.entry(file.info.id).or_default().push(file.path);
+        }
+        Ok(by_id
+            .into_iter()
+            .filter(|(_, paths)| paths.len() > 1)
+            .map(|(id, paths)| Finding::DuplicateId { id, paths })
+            .collect())
+    }
+
+    /// `*.tmp` files left next to facts by atomic writes that never finished
+    pub fn check_stray_files(&self) -> std::io::Result<Vec<Finding>> {
+        if Backend::detect(&self.path) != Backend::Files || !self.path.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut paths: Vec<PathBuf> = std::fs::read_dir(&self.path)?
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file() && path.extension().and_then(|e| e.to_str()) == Some("tmp"))
+            .collect();
+        paths.sort();
+        Ok(paths.into_iter().map(|path| Finding::StrayTempFile { path }).collect())
+    }
+
+    /// Conflict copies found next to fact files when the wiki was opened
+    pub fn check_conflict_copies(&self) -> Vec<Finding> {
+        self.conflict_copies
+            .iter()
+            .map(|(path, info)| Finding::ConflictCopy {
+                path: path.clone(),
+                id: info.id,
+            })
+            .collect()
+    }
+
+    /// Facts still named after what their data used to say
+    pub fn check_names(&self) -> std::io::Result<Vec<Finding>> {
+        self.hydrate_all()?;
+        Ok(self
+            .info
+            .iter()
+            .filter_map(|locked| {
+                let info = locked.read();
+                let derived = info.stale_name()?;
+                Some(Finding::StaleName { id: info.id, name: info.name.clone(), derived })
+            })
+            .collect())
+    }
+
+    /// Names and tags that [`validate`] would change or refuse, given names
+    /// only since derived ones follow the data
+    pub fn check_values(&self) -> Vec<Finding> {
+        let max = self.max_tag_len();
+        let mut findings = Vec::new();
+        for locked in &self.info {
+            let info = locked.read();
+            let mut values: Vec<(Field, &String)> = info.tags.iter().map(|t| (Field::Tag, t)).collect();
+            if !info.name_is_derived {
+                values.insert(0, (Field::Name, &info.name));
+            }
+            for (field, value) in values {
+                let checked = |v: &str| match field {
+                    Field::Name => validate::name(v),
+                    Field::Tag => validate::tag(v, max),
+                };
+                if checked(value).ok().as_ref() != Some(value) {
+                    let normalized = checked(&validate::normalize(field, value));
+                    findings.push(Finding::InvalidValue { id: info.id, field, value: value.clone(), normalized });
+                }
+            }
+        }
+        findings
+    }
+
+    /// Rename facts whose derived names have gone stale, as
+    /// `refresh_names` in the config asks for whenever the wiki is opened.
+    /// Failing just leaves them for next time.
+    pub(crate) fn refresh_stale_names(&mut self) {
+        if let Ok(findings) = self.check_names()
+            && !findings.is_empty()
+        {
+            self.fix(&findings).ok();
+        }
+    }
+
+    /// Repair what can be repaired safely, returning what was done.
+    ///
+    /// Unreadable fact files are salvaged if they hold a complete fact and
+    /// quarantined otherwise; misnamed files are renamed unless that would
+    /// overwrite another file; of several copies of one id the most recently
+    /// updated is kept and the rest quarantined; temp files are deleted;
+    /// conflict copies are merged into a `conflict` fact unless they match
+    /// the fact, and deleted; stale derived names are taken from the data
+    /// again; names and tags are normalized, unless that leaves them invalid,
+    /// as for a tag with `]` in it or one too long.
+    pub fn fix(&mut self, findings: &[Finding]) -> Result<Vec<Repair>, WikiError> {
+        self.check_writable()?;
+        self.refuse_dry_run("repair the wiki")?;
+        let _lock = self.lock_exclusive()?;
+        let mut repairs = Vec::new();
+
+        for finding in findings {
+            match finding {
+                Finding::Corrupt { path, .. } => {
+                    if let Some(repair) = self.fix_corrupt(path)? {
+                        self.warnings.retain(|w| w.path != *path);
+                        repairs.push(repair);
+                    }
+                }
+                Finding::Misnamed { path, id } => {
+                    let to = self.path.join(format!("{}.json", id));
+                    // Copies of duplicated ids are sorted out by fix_duplicate
+                    let duplicated = findings
+                        .iter()
+                        .any(|f| matches!(f, Finding::DuplicateId { id: d, .. } if d == id));
+                    if !duplicated && path.exists() && !to.exists() {
+                        std::fs::rename(path, &to)?;
+                        repairs.push(Repair::Renamed {
+                            from: path.clone(),
+                            to,
+                        });
+                    }
+                }
+                Finding::DuplicateId { id, paths } => repairs.extend(self.fix_duplicate(*id, paths)?),
+                Finding::StrayTempFile { path } => {
+                    let fact_path = path.with_extension("");
+                    let _file_lock = FileLock::exclusive(&lock_path_for(&fact_path), LOCK_TIMEOUT)?;
+                    match std::fs::remove_file(path) {
+                        Ok(()) => repairs.push(Repair::Removed { path: path.clone() }),
+                        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+                Finding::ConflictCopy { path, .. } => {
+                    if let Some(repair) = self.fix_conflict_copy(path)? {
+                        self.conflict_copies.retain(|(p, _)| p != path);
+                        repairs.push(repair);
+                    }
+                }
+                Finding::StaleName { id, name, derived } => {
+                    // Unless it was changed or deleted since it was checked
+                    if self.get(*id).is_ok_and(|fact| fact.stale_name().as_ref() == Some(derived)) {
+                        self.update(*id, |info| info.name = derived.clone())?;
+                        repairs.push(Repair::Retitled { id: *id, from: name.clone(), to: derived.clone() });
+                    }
+                }
+                Finding::InvalidValue { id, field, value, normalized: Ok(to) } => {
+                    let Ok(fact) = self.get(*id) else { continue };
+                    match field {
+                        Field::Name if fact.name == *value => self.update(*id, |info| info.name = to.clone())?,
+                        Field::Tag if fact.tags.contains(value) => self.update(*id, |info| {
+                            info.tags.retain(|t| t != value);
+                            if !to.is_empty() && !info.tags.contains(to) {
+                                info.tags.push(to.clone());
+                            }
+                        })?,
+                        _ => continue,
+                    };
+                    repairs.push(Repair::Normalized { id: *id, field: *field, from: value.clone(), to: to.clone() });
+                }
+                Finding::InvalidValue { normalized: Err(_), .. } => {}
+            }
+        }
+
+        Ok(repairs)
+    }
+
+    /// Fold a conflict copy back into the wiki: restore it if its fact is
+    /// gone, drop it if it matches the fact, and otherwise keep both versions
+    /// in a new `conflict` fact
+    fn fix_conflict_copy(&mut self, path: &Path) -> Result<Option<Repair>, WikiError> {
+        let Some((_, copy)) = self.conflict_copies.iter().find(|(p, _)| p == path).cloned() else {
+            return Ok(None);
+        };
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let repair = if !self.info.iter().any(|l| l.read().id == copy.id) {
+            self.put(copy.clone())?;
+            Repair::Recovered {
+                path: path.to_path_buf(),
+                id: copy.id,
+            }
+        } else {
+            self.hydrate(copy.id)?;
+            let current = self
+                .info
+                .iter()
+                .find(|l| l.read().id == copy.id)
+                .map(|l| (*l.read()).clone())
+                .ok_or(WikiError::NotFound(copy.id))?;
+            if current.data == copy.data && current.tags == copy.tags {
+                Repair::Removed {
+                    path: path.to_path_buf(),
+                }
+            } else {
+                let label = path.file_name().unwrap_or_default().to_string_lossy();
+                let id = self.insert(conflict_fact(&current, "current", &copy, &label))?;
+                Repair::Merged {
+                    path: path.to_path_buf(),
+                    id,
+                }
+            }
+        };
+        std::fs::remove_file(path)?;
+        Ok(Some(repair))
+    }
+
+    /// Salvage or quarantine one unreadable fact file; `None` for anything
+    /// that isn't a fact file
+    fn fix_corrupt(&mut self, path: &Path) -> Result<Option<Repair>, WikiError> {
+        if !path.is_file() || path.extension().and_then(|e| e.to_str()) != Some("json") {
+            return Ok(None);
+        }
+
+        let recovered = std::fs::read(path).ok().and_then(|bytes| recover_json(&bytes));
+        match recovered {
+            Some(info) if !self.info.iter().any(|l| l.read().id == info.id) => {
+                let id = info.id;
+                self.storage.write(&info)?;
+                if path != info.path(self) {
+                    std::fs::remove_file(path)?;
+                }
+                self.update_index(|index| index.insert(&info));
+                self.push_sorted(info);
+                Ok(Some(Repair::Recovered {
+                    path: path.to_path_buf(),
+                    id,
+                }))
+            }
+            _ => Ok(Some(self.quarantine(path)?)),
+        }
+    }
+
+    /// Keep the most recently updated copy of `id` at `<id>.json` and
+    /// quarantine the others
+    fn fix_duplicate(&mut self, id: Uuid, paths: &[PathBuf]) -> Result<Vec<Repair>, WikiError> {
+        let canonical = self.path.join(format!("{}.json", id));
+        let storage = FsStorage::open(&self.path)?;
+        let mut copies: Vec<(PathBuf, Information)> = paths
+            .iter()
+            .filter_map(|p| Some((p.clone(), storage.read_file(p).ok()?)))
+            .collect();
+        // Newest last; on a tie prefer the correctly named file
+        copies.sort_by(|(pa, a), (pb, b)| a.updated.cmp(&b.updated).then((*pa == canonical).cmp(&(*pb == canonical))));
+        let Some((kept_path, keep)) = copies.pop() else {
+            return Ok(Vec::new());
+        };
+
+        let mut repairs = Vec::new();
+        for (path, _) in &copies {
+            repairs.push(self.quarantine(path)?);
+        }
+        if kept_path != canonical {
+            self.storage.write(&keep)?;
+            std::fs::remove_file(&kept_path)?;
+            repairs.push(Repair::Renamed {
+                from: kept_path,
+                to: canonical,
+            });
+        }
+
+        // Loading read every copy; keep only the survivor in memory
+        self.forget(id);
+        self.update_index(|index| index.insert(&keep));
+        self.push_sorted(keep);
+        Ok(repairs)
+    }
+
+    /// Move a file into the wiki's `corrupt/` folder
+    fn quarantine(&self, path: &Path) -> Result<Repair, WikiError> {
+        let corrupt_dir = self.path.join("corrupt");
+        create_dir_all(&corrupt_dir)?;
+        let to = corrupt_dir.join(path.file_name().unwrap_or_default());
+        std::fs::rename(path, &to)?;
+        Ok(Repair::Quarantined {
+            path: path.to_path_buf(),
+            to,
+        })
+    }
+
+    /// Every readable fact file in a files-backed wiki directory
+    fn scan_fact_files(&self) -> std::io::Result<Vec<ScannedFile>> {
+        if Backend::detect(&self.path) != Backend::Files || !self.path.is_dir() {
+            return Ok(Vec::new());
+        }
+        // Through the storage, so an encrypted wiki's files are opened with its key
+        let storage = FsStorage::open(&self.path)?;
+        let mut files: Vec<ScannedFile> = std::fs::read_dir(&self.path)?
+            .flatten()
+            .filter(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                !name.starts_with('.') && !is_conflict_copy(&name)
+            })
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().and_then(|e| e.to_str()) == Some("json"))
+            .filter_map(|path| Some(ScannedFile { info: storage.read_file(&path).ok()?, path }))
+            .collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(files)
+    }
+}
@@ -0,0 +1,228 @@
+//! Dry runs: a wiki whose storage only writes down what it was asked to do.
+//!
+//! [`Wiki::with_dry_run`] swaps the wiki's storage for a [`DryRun`] that
+//! reads through to the real one and records every write as a [`Planned`]
+//! step instead. The wiki itself changes in memory as usual, so a command
+//! sees the effect of its own earlier steps, but nothing reaches disk: not
+//! facts, snapshots or the trash, nor the search index, access log, hooks
+//! or git commits. What can't be planned this way, like a migration, is
+//! refused with [`WikiError::DryRun`].
+
+use chrono::{DateTime, Utc};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+use crate::error::WikiError;
+use crate::events::Subscribers;
+use crate::storage::{Loaded, MemoryStorage, Snapshot, Storage, Trashed, not_trashed};
+use crate::wiki::{Information, Wiki};
+
+/// What a dry run would have done to one fact
+#[derive(Debug, Clone, PartialEq)]
+pub struct Planned {
+    pub action: Action,
+    pub id: Uuid,
+    /// First line of the fact's name, as it would be after the step
+    pub name: String,
+}
+
+/// A step of a dry run, in terms of what storage would have been asked for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Create,
+    Update,
+    /// Remove for good
+    Delete,
+    /// Keep a snapshot under this label
+    Snapshot(String),
+    DeleteSnapshots,
+    Trash,
+    Untrash,
+    /// Remove from the trash for good
+    Purge,
+}
+
+impl Action {
+    /// The step as a verb, for plans and their summaries
+    pub fn verb(&self) -> &'static str {
+        match self {
+            Action::Create => "create",
+            Action::Update => "update",
+            Action::Delete => "delete",
+            Action::Snapshot(_) => "snapshot",
+            Action::DeleteSnapshots => "delete snapshots",
+            Action::Trash => "trash",
+            Action::Untrash => "restore",
+            Action::Purge => "purge",
+        }
+    }
+}
+
+impl fmt::Display for Planned {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.action {
+            Action::Snapshot(label) => write!(f, "snapshot {} {} as '{}'", self.id, self.name, label),
+            action => write!(f, "{} {} {}", action.verb(), self.id, self.name),
+        }
+    }
+}
+
+/// Storage that reads from `inner` and plans writes without making them
+pub struct DryRun {
+    inner: Box<dyn Storage>,
+    plan: Arc<Mutex<Vec<Planned>>>,
+}
+
+impl DryRun {
+    pub fn new(inner: Box<dyn Storage>) -> Self {
+        DryRun { inner, plan: Arc::default() }
+    }
+
+    /// The steps planned so far, in order
+    pub fn plan(&self) -> Vec<Planned> {
+        self.plan.lock().unwrap().clone()
+    }
+
+    fn push(&self, action: Action, id: Uuid, name: &str) {
+        let name = name.lines().next().unwrap_or_default().to_string();
+        self.plan.lock().unwrap().push(Planned { action, id, name });
+    }
+
+    /// The name of a stored fact, for steps that are only given its id
+    fn name_of(&self, id: Uuid) -> String {
+        self.inner.read(id).map(|info| info.name).unwrap_or_default()
+    }
+
+    fn trashed_fact(&self, id: Uuid) -> std::io::Result<Information> {
+        self.inner
+            .trashed()?
+            .into_iter()
+            .find(|t| t.fact.id == id)
+            .map(|t| t.fact)
+            .ok_or_else(|| not_trashed(id))
+    }
+}
+
+impl Storage for DryRun {
+    fn load_all(&self) -> std::io::Result<Loaded> {
+        self.inner.load_all()
+    }
+
+    fn load_lazy(&self) -> std::io::Result<Loaded> {
+        self.inner.load_lazy()
+    }
+
+    fn read(&self, id: Uuid) -> std::io::Result<Information> {
+        self.inner.read(id)
+    }
+
+    fn write(&self, info: &Information) -> std::io::Result<()> {
+        let action = if self.inner.exists(info.id) { Action::Update } else { Action::Create };
+        self.push(action, info.id, &info.name);
+        Ok(())
+    }
+
+    fn delete(&self, id: Uuid) -> std::io::Result<()> {
+        if self.inner.exists(id) {
+            self.push(Action::Delete, id, &self.name_of(id));
+        }
+        Ok(())
+    }
+
+    fn exists(&self, id: Uuid) -> bool {
+        self.inner.exists(id)
+    }
+
+    fn count(&self) -> std::io::Result<usize> {
+        self.inner.count()
+    }
+
+    fn search(&self, query: &str) -> Option<Vec<Uuid>> {
+        self.inner.search(query)
+    }
+
+    fn is_encrypted(&self) -> bool {
+        self.inner.is_encrypted()
+    }
+
+    fn snapshots(&self, id: Uuid) -> std::io::Result<Vec<Snapshot>> {
+        self.inner.snapshots(id)
+    }
+
+    fn write_snapshot(&self, snapshot: &Snapshot) -> std::io::Result<()> {
+        self.push(Action::Snapshot(snapshot.label.clone()), snapshot.fact.id, &snapshot.fact.name);
+        Ok(())
+    }
+
+    fn delete_snapshots(&self, id: Uuid) -> std::io::Result<()> {
+        if !self.inner.snapshots(id)?.is_empty() {
+            self.push(Action::DeleteSnapshots, id, &self.name_of(id));
+        }
+        Ok(())
+    }
+
+    fn trash(&self, id: Uuid, _deleted: DateTime<Utc>) -> std::io::Result<()> {
+        if self.inner.exists(id) {
+            self.push(Action::Trash, id, &self.name_of(id));
+        }
+        Ok(())
+    }
+
+    fn trashed(&self) -> std::io::Result<Vec<Trashed>> {
+        self.inner.trashed()
+    }
+
+    fn untrash(&self, id: Uuid) -> std::io::Result<Information> {
+        let fact = self.trashed_fact(id)?;
+        if self.inner.exists(id) {
+            return Err(crate::storage::already_exists(id));
+        }
+        self.push(Action::Untrash, id, &fact.name);
+        Ok(fact)
+    }
+
+    fn purge(&self, id: Uuid) -> std::io::Result<()> {
+        if let Ok(fact) = self.trashed_fact(id) {
+            self.push(Action::Purge, id, &fact.name);
+        }
+        Ok(())
+    }
+}
+
+impl Wiki {
+    /// Make every change to this wiki from now on a dry run: it still
+    /// happens in memory, but storage only records it in [`Wiki::plan`].
+    ///
+    /// Event subscribers registered so far are dropped, since the git
+    /// subscriber would commit the files that weren't changed.
+    pub fn with_dry_run(mut self) -> Self {
+        if self.dry_run.is_none() {
+            let storage = std::mem::replace(&mut self.storage, Box::new(MemoryStorage::new()));
+            let dry_run = DryRun::new(storage);
+            self.dry_run = Some(Arc::clone(&dry_run.plan));
+            self.storage = Box::new(dry_run);
+            self.hooks = false;
+            self.subscribers = Subscribers::default();
+        }
+        self
+    }
+
+    /// Whether this is a dry run, see [`Wiki::with_dry_run`]
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run.is_some()
+    }
+
+    /// What this dry run would have done so far, in order; empty if it isn't one
+    pub fn plan(&self) -> Vec<Planned> {
+        self.dry_run.as_ref().map(|plan| plan.lock().unwrap().clone()).unwrap_or_default()
+    }
+
+    /// Fail with [`WikiError::DryRun`] for operations that can't be planned
+    pub(crate) fn refuse_dry_run(&self, what: &str) -> Result<(), WikiError> {
+        match self.dry_run {
+            Some(_) => Err(WikiError::DryRun(what.to_string())),
+            None => Ok(()),
+        }
+    }
+}
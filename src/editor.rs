@@ -0,0 +1,302 @@
+use serde::Serialize;
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+use tempfile::NamedTempFile;
+
+use crate::helpers::write_atomic;
+use crate::wiki::Information;
+
+/// A fact as read back from the editor
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Edited {
+    /// New title, empty if none was given
+    pub title: String,
+    /// New tags, if the frontmatter contained a tag list
+    pub tags: Option<Vec<String>>,
+    /// New source, if there was frontmatter: `Some(None)` when it had no
+    /// `source` line, so deleting the line clears it
+    pub source: Option<Option<String>>,
+    pub body: String,
+}
+
+#[derive(Serialize)]
+struct Front<'a> {
+    title: &'a str,
+    tags: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<&'a str>,
+}
+
+/// Render a fact with YAML frontmatter:
+/// ---
+/// title: ...
+/// tags: [..]
+/// source: ... (if it has one)
+/// ---
+/// CONTENT
+pub fn to_frontmatter(title: &str, tags: &[String], source: Option<&str>, body: &str) -> String {
+    let fm = serde_yaml::to_string(&Front { title, tags, source }).unwrap_or_default();
+    format!("---\n{}---\n\n{}", fm, body)
+}
+
+/// Parse editor contents written by [`to_frontmatter`], falling back to
+/// "first line title, optional '---' separator" when there is no frontmatter
+pub fn parse_frontmatter(edited: &str) -> Edited {
+    let mut out = Edited::default();
+    if let Some((fm_block, body)) = split_frontmatter(edited) {
+        // parse YAML
+        if let Ok(fm_val) = serde_yaml::from_str::<serde_yaml::Value>(fm_block) {
+            if let Some(t) = fm_val.get("title")
+                && let Some(s) = t.as_str()
+            {
+                out.title = s.to_string();
+            }
+            if let Some(tg) = fm_val.get("tags")
+                && let Some(arr) = tg.as_sequence()
+            {
+                let parsed: Vec<String> = arr
+                    .iter()
+                    .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                    .collect();
+                out.tags = Some(parsed);
+            }
+            let source = fm_val.get("source").and_then(|s| s.as_str()).map(str::trim);
+            out.source = Some(source.filter(|s| !s.is_empty()).map(str::to_string));
+        }
+        out.body = body.to_string();
+    } else if is_delimiter(edited.lines().next().unwrap_or_default()) {
+        // no closing delimiter; treat whole as content
+        out.body = edited.to_string();
+    } else {
+        // fallback: first line title, optional '---' separator
+        let mut lines = edited.lines();
+        out.title = lines.next().unwrap_or("").to_string();
+        let second = lines.next();
+        if second == Some("---") {
+            out.body = lines.collect::<Vec<_>>().join("\n");
+        } else {
+            let mut v = Vec::new();
+            if let Some(s) = second {
+                v.push(s);
+            }
+            v.extend(lines);
+            out.body = v.join("\n");
+        }
+    }
+    out
+}
+
+/// Split `edited` into the YAML between its opening `---` line and the next
+/// `---` line, and the body after the blank line [`to_frontmatter`] puts
+/// there. Either line ending will do, as editors on Windows may save CRLF.
+fn split_frontmatter(edited: &str) -> Option<(&str, &str)> {
+    let mut lines = edited.split_inclusive('\n');
+    let first = lines.next()?;
+    if !is_delimiter(first) {
+        return None;
+    }
+    let start = first.len();
+    let mut at = start;
+    for line in lines {
+        if is_delimiter(line) {
+            let rest = &edited[at + line.len()..];
+            let body = rest.strip_prefix("\r\n").or_else(|| rest.strip_prefix('\n')).unwrap_or(rest);
+            return Some((&edited[start..at], body));
+        }
+        at += line.len();
+    }
+    None
+}
+
+/// Whether `line` is a `---` frontmatter delimiter, with or without its line ending
+fn is_delimiter(line: &str) -> bool {
+    line.trim_end_matches(['\r', '\n']) == "---"
+}
+
+/// Write a fact in frontmatter form to a fresh temp file
+pub fn write_temp(title: &str, tags: &[String], source: Option<&str>, body: &str) -> std::io::Result<NamedTempFile> {
+    let mut tmp = NamedTempFile::new()?;
+    tmp.write_all(to_frontmatter(title, tags, source, body).as_bytes())?;
+    Ok(tmp)
+}
+
+/// How an edit in the external editor ended
+#[derive(Debug, PartialEq)]
+pub enum EditOutcome {
+    /// Changed and saved, as read back
+    Saved(Edited),
+    /// Saved just as it was written out, or not saved at all
+    Unchanged,
+    /// The editor exited with an error, as `:cq` makes vim do, or left the
+    /// file empty
+    Cancelled,
+}
+
+/// Why an edit in the external editor came to nothing
+#[derive(Debug)]
+pub enum EditError {
+    /// The temp file couldn't be created
+    TempFile(std::io::Error),
+    /// The fact couldn't be written to the temp file
+    Write(std::io::Error),
+    /// The editor couldn't be started
+    Launch(std::io::Error),
+    /// What the editor saved couldn't be read back
+    Read(std::io::Error),
+    /// What the editor saved has frontmatter that isn't valid YAML
+    Frontmatter(serde_yaml::Error),
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EditError::TempFile(e) => write!(f, "couldn't create a temp file for the editor: {}", e),
+            EditError::Write(e) => write!(f, "couldn't write the fact out for the editor: {}", e),
+            EditError::Launch(e) => write!(f, "couldn't launch the editor: {}", e),
+            EditError::Read(e) => write!(f, "couldn't read back what the editor saved: {}", e),
+            EditError::Frontmatter(e) => write!(f, "the frontmatter isn't valid YAML: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for EditError {}
+
+/// Write `info` out in frontmatter form, open it with `run`, which hands
+/// it to the editor and waits, and read back how the edit went. The temp
+/// file is removed either way.
+pub fn open_in_editor(
+    info: &Information,
+    run: impl FnOnce(&Path) -> std::io::Result<ExitStatus>,
+) -> Result<EditOutcome, EditError> {
+    let written = to_frontmatter(&info.name, &info.tags, info.source.as_deref(), &info.data);
+    let mut tmp = NamedTempFile::new().map_err(EditError::TempFile)?;
+    tmp.write_all(written.as_bytes()).map_err(EditError::Write)?;
+    let status = run(tmp.path()).map_err(EditError::Launch)?;
+    let saved = std::fs::read_to_string(tmp.path()).map_err(EditError::Read)?;
+    classify(&written, &saved, status.success())
+}
+
+/// How an edit ended, given the text `written` out for the editor, the text
+/// it `saved` and whether it exited successfully
+pub fn classify(written: &str, saved: &str, exited_ok: bool) -> Result<EditOutcome, EditError> {
+    if !exited_ok || saved.trim().is_empty() {
+        return Ok(EditOutcome::Cancelled);
+    }
+    // Editors on Windows may have saved it with CRLF line endings
+    if saved == written || saved.replace("\r\n", "\n") == written {
+        return Ok(EditOutcome::Unchanged);
+    }
+    if let Some((front, _)) = split_frontmatter(saved) {
+        serde_yaml::from_str::<serde_yaml::Value>(front).map_err(EditError::Frontmatter)?;
+    }
+    Ok(EditOutcome::Saved(parse_frontmatter(saved)))
+}
+
+/// Render a fact in frontmatter form to `<dir>/<id>.md`, replacing any
+/// earlier rendering, so the same fact always opens at the same path
+pub fn materialize(dir: &Path, info: &Information) -> std::io::Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{}.md", info.id));
+    write_atomic(&path, to_frontmatter(&info.name, &info.tags, info.source.as_deref(), &info.data).as_bytes())?;
+    Ok(path)
+}
+
+/// What opens facts when neither `$EDITOR` nor the config's `editor` is set
+#[cfg(windows)]
+const DEFAULT_EDITOR: &str = "notepad";
+#[cfg(not(windows))]
+const DEFAULT_EDITOR: &str = "vi";
+
+/// `$EDITOR` (`%EDITOR%` on Windows) if it's set to anything, then the
+/// global config's `editor`, otherwise `notepad` on Windows and `vi` elsewhere
+pub fn editor() -> String {
+    std::env::var("EDITOR")
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .or_else(|| crate::config::Config::load_global().ok()?.editor)
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_EDITOR.to_string())
+}
+
+/// Split a command line like `code --wait` into its words: on whitespace,
+/// except inside single or double quotes, which are removed
+pub fn split_command(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    for c in line.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => word.get_or_insert_default().push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                word.get_or_insert_default();
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_default().push(c),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+/// The command that opens `path` in `editor`, a program or a command line
+/// with arguments. A program path containing spaces is run as it is;
+/// otherwise, on Windows, command lines are left to `cmd /C` so they're
+/// understood as they would be typed.
+pub fn editor_command(editor: &str, path: &Path) -> Command {
+    let editor = editor.trim();
+    if !editor.contains(char::is_whitespace) || Path::new(editor).is_file() {
+        let mut command = Command::new(editor);
+        command.arg(path);
+        return command;
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        let mut command = Command::new("cmd");
+        command.arg("/C").raw_arg(editor).arg(path);
+        command
+    }
+    #[cfg(not(windows))]
+    {
+        let mut words = split_command(editor).into_iter();
+        let mut command = Command::new(words.next().unwrap_or_default());
+        command.args(words).arg(path);
+        command
+    }
+}
+
+/// Open `path` in [`editor`] and wait for it to exit
+pub fn launch(path: &Path) -> std::io::Result<std::process::ExitStatus> {
+    editor_command(&editor(), path).status()
+}
+
+/// Open `path` with the platform's default application without waiting for it
+pub fn open_external(path: &Path) -> std::io::Result<()> {
+    opener(path).spawn().map(drop)
+}
+
+#[cfg(target_os = "macos")]
+fn opener(path: &Path) -> Command {
+    let mut command = Command::new("open");
+    command.arg(path);
+    command
+}
+
+#[cfg(windows)]
+fn opener(path: &Path) -> Command {
+    // `start` takes its first quoted argument as a window title, hence the empty one
+    let mut command = Command::new("cmd");
+    command.args(["/C", "start", ""]).arg(path);
+    command
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn opener(path: &Path) -> Command {
+    let mut command = Command::new("xdg-open");
+    command.arg(path);
+    command
+}
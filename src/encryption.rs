@@ -0,0 +1,123 @@
+//! Encrypted wikis: each fact file sealed with a key derived from a
+//! passphrase, and the wiki marked with [`ENCRYPTION_FILE`].
+//!
+//! The ciphers need the `encryption` feature. Without it, encrypted wikis
+//! are still recognised, so they're never read or written as plaintext, but
+//! unlocking one fails.
+
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex, OnceLock, PoisonError};
+
+#[cfg(feature = "encryption")]
+pub use crate::cipher::{Cipher, Setup, unlock_with};
+#[cfg(not(feature = "encryption"))]
+pub use unsupported::{Cipher, Setup, unlock_with};
+
+/// Name of the file marking a wiki as encrypted, holding its key derivation
+/// parameters
+pub const ENCRYPTION_FILE: &str = ".encryption.json";
+
+/// Environment variable read for the passphrase before prompting
+pub const PASSPHRASE_VAR: &str = "TWK_PASSPHRASE";
+
+/// Asks the user for the passphrase of the wiki at the given path
+pub type Prompt = fn(&Path) -> std::io::Result<String>;
+
+static PROMPT: OnceLock<Prompt> = OnceLock::new();
+
+/// Keys already unlocked by this process, by wiki directory
+static UNLOCKED: LazyLock<Mutex<HashMap<PathBuf, Arc<Cipher>>>> = LazyLock::new(Mutex::default);
+
+/// Whether the wiki directory at `path` is encrypted
+pub fn is_encrypted(path: &Path) -> bool {
+    path.join(ENCRYPTION_FILE).is_file()
+}
+
+/// Set how [`unlock`] asks for a passphrase when `TWK_PASSPHRASE` isn't set.
+/// Without one, unlocking fails instead.
+pub fn set_prompt(prompt: Prompt) {
+    PROMPT.set(prompt).ok();
+}
+
+/// The key of the encrypted wiki at `path`, asking for its passphrase the
+/// first time this process needs it
+pub fn unlock(path: &Path) -> std::io::Result<Arc<Cipher>> {
+    let mut unlocked = UNLOCKED.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(cipher) = unlocked.get(path) {
+        return Ok(cipher.clone());
+    }
+
+    let cipher = Arc::new(unlock_with(path, &passphrase(path)?)?);
+    unlocked.insert(path.to_path_buf(), cipher.clone());
+    Ok(cipher)
+}
+
+/// `TWK_PASSPHRASE`, or the answer to the prompt set with [`set_prompt`]
+pub fn passphrase(path: &Path) -> std::io::Result<String> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_VAR) {
+        return Ok(passphrase);
+    }
+    match PROMPT.get() {
+        Some(prompt) => prompt(path),
+        None => Err(Error::new(
+            ErrorKind::PermissionDenied,
+            format!("{} is encrypted; set {}", path.display(), PASSPHRASE_VAR),
+        )),
+    }
+}
+
+/// Forget the key cached for `path`, after the wiki is decrypted or removed
+pub(crate) fn forget(path: &Path) {
+    UNLOCKED.lock().unwrap_or_else(PoisonError::into_inner).remove(path);
+}
+
+/// Stand-ins for the ciphers when they aren't built: there is never a key,
+/// so nothing can be sealed or opened
+#[cfg(not(feature = "encryption"))]
+mod unsupported {
+    use std::io::{Error, ErrorKind};
+    use std::path::Path;
+    use std::sync::Arc;
+
+    /// The key of an unlocked wiki, which can't exist in this build
+    pub enum Cipher {}
+
+    impl Cipher {
+        pub fn encrypt(&self, _plaintext: &[u8]) -> std::io::Result<Vec<u8>> {
+            match *self {}
+        }
+
+        pub fn decrypt(&self, _bytes: &[u8]) -> Option<std::io::Result<Vec<u8>>> {
+            match *self {}
+        }
+    }
+
+    fn unsupported(path: &Path) -> Error {
+        Error::new(
+            ErrorKind::Unsupported,
+            format!("{} is encrypted, and twk was built without the encryption feature", path.display()),
+        )
+    }
+
+    /// Always fails: there are no ciphers to unlock the wiki at `path` with
+    pub fn unlock_with(path: &Path, _passphrase: &str) -> std::io::Result<Cipher> {
+        Err(unsupported(path))
+    }
+
+    /// Key settings for a wiki about to be encrypted, which can't be made
+    pub struct Setup {
+        pub cipher: Arc<Cipher>,
+    }
+
+    impl Setup {
+        pub fn new(_passphrase: &str) -> std::io::Result<Self> {
+            Err(Error::new(ErrorKind::Unsupported, "twk was built without the encryption feature"))
+        }
+
+        pub fn write_marker(&self, _path: &Path) -> std::io::Result<()> {
+            match *self.cipher {}
+        }
+    }
+}
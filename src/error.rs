@@ -0,0 +1,175 @@
+use chrono::{DateTime, Utc};
+use std::fmt;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+use crate::size;
+use crate::validate::ValidationError;
+
+/// Errors produced by wiki operations
+#[derive(Debug)]
+pub enum WikiError {
+    /// No fact with the given id exists in the wiki
+    NotFound(Uuid),
+    /// The fact was modified since the caller last read it
+    Conflict {
+        id: Uuid,
+        expected: Option<DateTime<Utc>>,
+        found: Option<DateTime<Utc>>,
+    },
+    /// A batch commit failed part-way; `committed` holds the ids that were written
+    PartialCommit {
+        committed: Vec<Uuid>,
+        source: Box<WikiError>,
+    },
+    /// No wiki context has been selected with `switch()`
+    NoContext,
+    /// No wiki directory with the given name exists
+    UnknownWiki(String),
+    /// A wiki with the given name already exists
+    WikiExists(String),
+    /// The name can't be used as a wiki directory name
+    InvalidWikiName(String),
+    /// The wiki is encrypted and the operation would store or output its facts in plaintext
+    Encrypted(String),
+    /// The wiki isn't encrypted
+    NotEncrypted(String),
+    /// The wiki is read-only, by config, flag or file permissions
+    ReadOnly(String),
+    /// The wiki is a dry run, and the named operation can't be planned
+    DryRun(String),
+    /// The fact already has a snapshot with this label
+    SnapshotExists { id: Uuid, label: String },
+    /// The fact has no snapshot with this label
+    NoSnapshot { id: Uuid, label: String },
+    /// No fact with the given id is in the trash
+    NotTrashed(Uuid),
+    /// The pre-commit hook refused the fact, for the reason given
+    HookRejected(String),
+    /// The wiki's `require_tags`, set in `config` unless that can't be
+    /// found, refused a fact without tags
+    TagsRequired { wiki: String, config: Option<PathBuf> },
+    /// A search pattern isn't a valid regular expression
+    InvalidPattern(regex::Error),
+    /// A fact's name or tag can't be stored as given
+    Invalid(ValidationError),
+    /// A fact's data is `len` bytes, over the wiki's `max_fact_bytes`
+    TooLarge { len: usize, max: usize },
+    #[cfg(feature = "git")]
+    Git(git2::Error),
+    Io(std::io::Error),
+}
+
+impl WikiError {
+    /// A short snake_case name for the kind of error, for machine-readable output
+    pub fn kind(&self) -> &'static str {
+        match self {
+            WikiError::NotFound(_) => "not_found",
+            WikiError::Conflict { .. } => "conflict",
+            WikiError::PartialCommit { .. } => "partial_commit",
+            WikiError::NoContext => "no_context",
+            WikiError::UnknownWiki(_) => "unknown_wiki",
+            WikiError::WikiExists(_) => "wiki_exists",
+            WikiError::InvalidWikiName(_) => "invalid_wiki_name",
+            WikiError::Encrypted(_) => "encrypted",
+            WikiError::NotEncrypted(_) => "not_encrypted",
+            WikiError::ReadOnly(_) => "read_only",
+            WikiError::DryRun(_) => "dry_run",
+            WikiError::SnapshotExists { .. } => "snapshot_exists",
+            WikiError::NoSnapshot { .. } => "no_snapshot",
+            WikiError::NotTrashed(_) => "not_trashed",
+            WikiError::HookRejected(_) => "hook_rejected",
+            WikiError::TagsRequired { .. } => "tags_required",
+            WikiError::InvalidPattern(_) => "invalid_pattern",
+            WikiError::Invalid(_) => "invalid",
+            WikiError::TooLarge { .. } => "too_large",
+            #[cfg(feature = "git")]
+            WikiError::Git(_) => "git",
+            WikiError::Io(_) => "io",
+        }
+    }
+}
+
+impl fmt::Display for WikiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WikiError::NotFound(id) => write!(f, "No fact with id {}", id),
+            WikiError::Conflict { id, .. } => {
+                write!(f, "Fact {} was modified since it was read", id)
+            }
+            WikiError::PartialCommit { committed, source } => {
+                write!(f, "Committed {} facts before failing: {}", committed.len(), source)
+            }
+            WikiError::NoContext => write!(f, "No wiki context selected. Use switch() first."),
+            WikiError::UnknownWiki(name) => write!(f, "No wiki named '{}'", name),
+            WikiError::WikiExists(name) => write!(f, "A wiki named '{}' already exists", name),
+            WikiError::InvalidWikiName(name) => write!(f, "'{}' is not a valid wiki name", name),
+            WikiError::Encrypted(name) => write!(f, "Wiki '{}' is encrypted", name),
+            WikiError::NotEncrypted(name) => write!(f, "Wiki '{}' is not encrypted", name),
+            WikiError::ReadOnly(name) => write!(f, "Wiki '{}' is read-only", name),
+            WikiError::DryRun(what) => write!(f, "Can't {} in a dry run", what),
+            WikiError::SnapshotExists { id, label } => {
+                write!(f, "Fact {} already has a snapshot labelled '{}'", id, label)
+            }
+            WikiError::NoSnapshot { id, label } => write!(f, "Fact {} has no snapshot labelled '{}'", id, label),
+            WikiError::NotTrashed(id) => write!(f, "No fact with id {} in the trash", id),
+            WikiError::HookRejected(reason) => write!(f, "Refused by the pre-commit hook: {}", reason),
+            WikiError::TagsRequired { wiki, config: Some(config) } => {
+                write!(f, "Wiki '{}' needs every fact tagged (require_tags in {})", wiki, config.display())
+            }
+            WikiError::TagsRequired { wiki, config: None } => {
+                write!(f, "Wiki '{}' needs every fact tagged (require_tags in its config)", wiki)
+            }
+            WikiError::InvalidPattern(e) => write!(f, "Invalid pattern: {}", e),
+            WikiError::Invalid(e) => write!(f, "Invalid {}", e),
+            WikiError::TooLarge { len, max } => write!(
+                f,
+                "Fact data is {}, over the limit of {} (max_fact_bytes); keep files this big outside the wiki and link them with --source",
+                size::format_size(*len),
+                size::format_size(*max)
+            ),
+            #[cfg(feature = "git")]
+            WikiError::Git(e) => write!(f, "git: {}", e.message()),
+            WikiError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for WikiError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WikiError::PartialCommit { source, .. } => Some(source.as_ref()),
+            #[cfg(feature = "git")]
+            WikiError::Git(e) => Some(e),
+            WikiError::InvalidPattern(e) => Some(e),
+            WikiError::Invalid(e) => Some(e),
+            WikiError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for WikiError {
+    fn from(e: std::io::Error) -> Self {
+        WikiError::Io(e)
+    }
+}
+
+impl From<regex::Error> for WikiError {
+    fn from(e: regex::Error) -> Self {
+        WikiError::InvalidPattern(e)
+    }
+}
+
+impl From<ValidationError> for WikiError {
+    fn from(e: ValidationError) -> Self {
+        WikiError::Invalid(e)
+    }
+}
+
+#[cfg(feature = "git")]
+impl From<git2::Error> for WikiError {
+    fn from(e: git2::Error) -> Self {
+        WikiError::Git(e)
+    }
+}
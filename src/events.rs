@@ -0,0 +1,57 @@
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::sync::mpsc;
+use uuid::Uuid;
+
+use crate::wiki::Information;
+
+/// A change to a wiki, emitted after it has been persisted
+#[derive(Debug, Clone)]
+pub enum WikiEvent {
+    Created(Information),
+    Updated {
+        before: Information,
+        after: Information,
+    },
+    /// The fact as it was before it was deleted
+    Deleted(Information),
+    Retagged {
+        id: Uuid,
+        name: String,
+        before: Vec<String>,
+        after: Vec<String>,
+    },
+}
+
+pub type Callback = Box<dyn Fn(&WikiEvent) + Send + Sync>;
+
+/// Registered event callbacks for a wiki
+#[derive(Default)]
+pub struct Subscribers {
+    callbacks: Vec<Callback>,
+}
+
+impl Subscribers {
+    pub fn push(&mut self, callback: Callback) {
+        self.callbacks.push(callback);
+    }
+
+    /// Invoke every callback with `event`. Callers must not hold any fact locks
+    /// here; a panicking callback is reported and skipped.
+    pub fn emit(&self, event: WikiEvent) {
+        for callback in &self.callbacks {
+            if catch_unwind(AssertUnwindSafe(|| callback(&event))).is_err() {
+                tracing::error!(?event, "event subscriber panicked");
+            }
+        }
+    }
+
+    /// Build a callback that forwards events into a channel, returning its receiver
+    pub fn channel() -> (Callback, mpsc::Receiver<WikiEvent>) {
+        let (tx, rx) = mpsc::channel();
+        let callback: Callback = Box::new(move |event: &WikiEvent| {
+            // A dropped receiver just means nobody is listening any more
+            tx.send(event.clone()).ok();
+        });
+        (callback, rx)
+    }
+}
@@ -0,0 +1,112 @@
+//! Writing a wiki's facts out for backups and other tools, with `wk export`.
+//!
+//! Exports can be limited to the facts changed since a time, and each one
+//! is noted in a manifest in the wiki directory under where it was written
+//! to, so the next can ask for only what changed since. Files whose
+//! contents wouldn't change are left alone, keeping their modification
+//! times, so tools like rsync only see the facts that did.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::editor::to_frontmatter;
+use crate::error::WikiError;
+use crate::helpers::write_atomic;
+use crate::progress::Progress;
+use crate::wiki::{Information, Wiki};
+
+/// Name of the export manifest inside a wiki directory
+pub const EXPORT_MANIFEST: &str = ".exports.json";
+
+/// What `--since` takes to mean the last export to the same place
+pub const SINCE_LAST: &str = "last";
+
+#[derive(Serialize, Deserialize, Default)]
+struct Manifest {
+    /// When each export started, by [`destination`]
+    exports: BTreeMap<String, DateTime<Utc>>,
+}
+
+/// How the manifest names an export of `format` to `out`, or to stdout if
+/// `None`; paths are made absolute so any working directory finds them
+pub fn destination(format: &str, out: Option<&Path>) -> String {
+    match out {
+        Some(out) => {
+            let out = std::path::absolute(out).unwrap_or_else(|_| out.to_path_buf());
+            format!("{}:{}", format, out.display())
+        }
+        None => format!("{}:-", format),
+    }
+}
+
+impl Wiki {
+    fn export_manifest_path(&self) -> PathBuf {
+        self.path.join(EXPORT_MANIFEST)
+    }
+
+    fn export_manifest(&self) -> Manifest {
+        std::fs::read(self.export_manifest_path())
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+
+    /// When the last export to `destination` started, if there was one
+    pub fn last_export(&self, destination: &str) -> Option<DateTime<Utc>> {
+        self.export_manifest().exports.get(destination).copied()
+    }
+
+    /// Note an export to `destination` that started `at`. Nothing is noted in
+    /// a read-only wiki or a dry run.
+    pub fn record_export(&self, destination: &str, at: DateTime<Utc>) -> Result<(), WikiError> {
+        if self.readonly || self.is_dry_run() {
+            return Ok(());
+        }
+        let mut manifest = self.export_manifest();
+        manifest.exports.insert(destination.to_string(), at);
+        let json = serde_json::to_vec_pretty(&manifest).map_err(std::io::Error::other)?;
+        Ok(write_atomic(&self.export_manifest_path(), &json)?)
+    }
+}
+
+/// Write `contents` to `path` unless it already holds exactly that; whether
+/// it was written
+pub fn write_if_changed(path: &Path, contents: &[u8]) -> std::io::Result<bool> {
+    if std::fs::read(path).is_ok_and(|old| old == contents) {
+        return Ok(false);
+    }
+    write_atomic(path, contents)?;
+    Ok(true)
+}
+
+/// Facts as a JSON array, every field included
+pub fn to_json(facts: &[Information]) -> serde_json::Result<String> {
+    let exported: Vec<_> = facts.iter().map(Information::exported).collect();
+    serde_json::to_string_pretty(&exported)
+}
+
+/// Write each of `facts` in frontmatter form to `<dir>/<id>.md`, leaving
+/// those already up to date alone and reporting each to `progress`; the
+/// paths written to
+pub fn to_markdown(dir: &Path, facts: &[Information], progress: &mut dyn Progress) -> std::io::Result<Vec<PathBuf>> {
+    let result = write_markdown(dir, facts, progress);
+    progress.finish();
+    result
+}
+
+fn write_markdown(dir: &Path, facts: &[Information], progress: &mut dyn Progress) -> std::io::Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(dir)?;
+    let mut written = Vec::new();
+    progress.start(facts.len());
+    for info in facts {
+        progress.tick(info.name.lines().next().unwrap_or_default());
+        let path = dir.join(format!("{}.md", info.id));
+        let text = to_frontmatter(&info.name, &info.tags, info.source.as_deref(), &info.data);
+        if write_if_changed(&path, text.as_bytes())? {
+            written.push(path);
+        }
+    }
+    Ok(written)
+}
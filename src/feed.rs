@@ -0,0 +1,88 @@
+//! An Atom feed of the newest facts, written beside the wiki's book as
+//! [`FEED_FILE`] so readers can subscribe to new entries.
+//!
+//! Feed readers need absolute links, so the feed is only written once the
+//! book knows where it's published: [`crate::BookOptions::base_url`], or
+//! the config's `book_url`. Each entry links to the fact's page there and
+//! is identified by the fact's id, so it keeps its identity across renames.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::fmt::Write;
+
+use crate::wiki::{Information, cut_at_word, first_paragraph};
+
+/// The feed's file name in the book's output folder
+pub const FEED_FILE: &str = "atom.xml";
+
+/// How many facts the feed lists unless told otherwise
+pub const DEFAULT_FEED_LIMIT: usize = 20;
+
+/// Longest an entry's summary may be, in characters
+pub const SUMMARY_LEN: usize = 500;
+
+/// The `limit` most recently created of `facts`, newest first. Facts from
+/// before creation times were recorded are left out.
+pub fn newest(facts: &[Information], limit: usize) -> Vec<&Information> {
+    let mut dated: Vec<&Information> = facts.iter().filter(|info| info.created.is_some()).collect();
+    dated.sort_by_key(|info| std::cmp::Reverse((info.created, info.id)));
+    dated.truncate(limit);
+    dated
+}
+
+/// An Atom feed titled `title` of `facts`, whose pages are under
+/// `base_url`; `now` stands as its update time if there are no entries
+pub fn atom(title: &str, base_url: &str, facts: &[&Information], now: DateTime<Utc>) -> String {
+    let base = base_url.trim_end_matches('/');
+    let updated = facts.iter().filter_map(|info| info.updated.or(info.created)).max().unwrap_or(now);
+
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    writeln!(xml, "  <title>{}</title>", escape(title)).ok();
+    writeln!(xml, "  <id>{}/</id>", escape(base)).ok();
+    writeln!(xml, "  <link rel=\"alternate\" href=\"{}/\"/>", escape(base)).ok();
+    writeln!(xml, "  <link rel=\"self\" href=\"{}/{}\"/>", escape(base), FEED_FILE).ok();
+    writeln!(xml, "  <updated>{}</updated>", date(updated)).ok();
+    writeln!(xml, "  <author><name>{}</name></author>", escape(title)).ok();
+    xml.push_str("  <generator>twk</generator>\n");
+    for info in facts {
+        let created = info.created.unwrap_or(now);
+        xml.push_str("  <entry>\n");
+        writeln!(xml, "    <title>{}</title>", escape(info.name.lines().next().unwrap_or_default())).ok();
+        writeln!(xml, "    <link rel=\"alternate\" href=\"{}/{}.html\"/>", escape(base), info.id).ok();
+        writeln!(xml, "    <id>urn:uuid:{}</id>", info.id).ok();
+        writeln!(xml, "    <published>{}</published>", date(created)).ok();
+        writeln!(xml, "    <updated>{}</updated>", date(info.updated.unwrap_or(created))).ok();
+        let summary = cut_at_word(&first_paragraph(&info.data), SUMMARY_LEN);
+        if !summary.is_empty() {
+            writeln!(xml, "    <summary type=\"text\">{}</summary>", escape(&summary)).ok();
+        }
+        xml.push_str("  </entry>\n");
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
+
+/// RFC 3339 in UTC to the second, as Atom dates are written
+fn date(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// `text` safe inside XML text or a quoted attribute. Control characters
+/// XML can't hold at all are dropped.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,180 @@
+//! Throwaway wikis full of generated facts, for tests of twk and of crates
+//! embedding it. Built with the `test-util` feature.
+//!
+//! Each [`Fixture`] lives in its own temporary directory, removed when it's
+//! dropped, and never reads or writes the user's own wikis.
+
+use chrono::{TimeDelta, Utc};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+use uuid::Uuid;
+
+use crate::storage::FsStorage;
+use crate::wiki::{Information, Wiki};
+
+/// What generated facts are written in, so recall has something to match
+const WORDS: [&str; 12] = [
+    "alpha", "bravo", "charlie", "delta", "echo", "foxtrot", "golf", "hotel", "india", "juliet", "kilo", "lima",
+];
+
+/// Builds a [`Fixture`]: a wiki of generated facts in a temporary data
+/// directory
+#[derive(Debug, Clone)]
+pub struct FixtureWiki {
+    name: String,
+    facts: usize,
+    tags: usize,
+    size: usize,
+    corrupted: usize,
+}
+
+impl Default for FixtureWiki {
+    fn default() -> Self {
+        FixtureWiki { name: "fixture".to_string(), facts: 10, tags: 3, size: 64, corrupted: 0 }
+    }
+}
+
+impl FixtureWiki {
+    /// Ten small facts over three tags, in a wiki named `fixture`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Name the wiki `name`
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Write `n` facts
+    pub fn facts(mut self, n: usize) -> Self {
+        self.facts = n;
+        self
+    }
+
+    /// Tag the facts `tag0` to `tag{n-1}` in turn; with none they're untagged
+    pub fn tags(mut self, n: usize) -> Self {
+        self.tags = n;
+        self
+    }
+
+    /// Make each fact's data at least `bytes` long
+    pub fn size(mut self, bytes: usize) -> Self {
+        self.size = bytes;
+        self
+    }
+
+    /// Also write `n` fact files that aren't valid JSON, for load warnings
+    pub fn corrupted(mut self, n: usize) -> Self {
+        self.corrupted = n;
+        self
+    }
+
+    /// Write the wiki. Fact `i` is named `Fact i`, starts with that line and
+    /// was created `i` minutes after the one before it, the last a minute ago.
+    pub fn build(self) -> std::io::Result<Fixture> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("data").join(&self.name);
+        std::fs::create_dir_all(&path)?;
+
+        let now = Utc::now();
+        let mut facts = Vec::with_capacity(self.facts);
+        for i in 0..self.facts {
+            // Lines of eight words, in an order that differs from fact to fact
+            let mut data = format!("Fact {}", i);
+            let mut k = 0;
+            while data.len() < self.size {
+                data.push(if k % 8 == 0 { '\n' } else { ' ' });
+                data.push_str(WORDS[(i + k * 7) % WORDS.len()]);
+                k += 1;
+            }
+            let created = now - TimeDelta::minutes((self.facts - i) as i64);
+            let info = Information {
+                id: Uuid::new_v4(),
+                tags: (self.tags > 0).then(|| format!("tag{}", i % self.tags)).into_iter().collect(),
+                name: format!("Fact {}", i),
+                data,
+                created: Some(created),
+                updated: None,
+                source: None,
+                name_is_derived: false,
+                extra: Default::default(),
+            };
+            let json = crate::canonical::to_vec(&info)?;
+            std::fs::write(path.join(format!("{}.json", info.id)), json)?;
+            facts.push(info);
+        }
+
+        let mut corrupted = Vec::with_capacity(self.corrupted);
+        for i in 0..self.corrupted {
+            let file = path.join(format!("{}.json", Uuid::new_v4()));
+            std::fs::write(&file, format!("{{\"name\": \"Broken {}\"", i))?;
+            corrupted.push(file);
+        }
+
+        Ok(Fixture { dir, name: self.name, facts, corrupted })
+    }
+}
+
+/// A wiki written by [`FixtureWiki`], deleted along with everything in its
+/// data directory when dropped
+pub struct Fixture {
+    dir: TempDir,
+    name: String,
+    facts: Vec<Information>,
+    corrupted: Vec<PathBuf>,
+}
+
+impl Fixture {
+    /// Root of global wikis holding this one, to pass as `TWK_DATA_DIR` or
+    /// to [`crate::set_data_dir`]
+    pub fn data_dir(&self) -> PathBuf {
+        self.dir.path().join("data")
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The wiki's own directory
+    pub fn path(&self) -> PathBuf {
+        self.data_dir().join(&self.name)
+    }
+
+    /// A directory inside the fixture for anything else a test needs
+    pub fn scratch(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// The facts written, in the order they were generated
+    pub fn facts(&self) -> &[Information] {
+        &self.facts
+    }
+
+    /// Files written with [`FixtureWiki::corrupted`]
+    pub fn corrupted(&self) -> &[PathBuf] {
+        &self.corrupted
+    }
+
+    /// Open the wiki without selecting it as the current one or touching any
+    /// other global setting
+    pub fn open(&self) -> std::io::Result<Wiki> {
+        let path = self.path();
+        Wiki::with_storage(self.name.clone(), path.clone(), Box::new(FsStorage::new(&path)))
+    }
+
+    /// Environment for running `wk -g` against this wiki alone: its data
+    /// directory and name, and a home and config directory inside the fixture
+    /// so the user's own config and wikis are never read or written
+    pub fn env(&self) -> Vec<(&'static str, OsString)> {
+        let home = self.dir.path().join("home");
+        vec![
+            ("TWK_DATA_DIR", self.data_dir().into()),
+            ("TWK_WIKI", self.name.clone().into()),
+            ("HOME", home.clone().into()),
+            ("XDG_CONFIG_HOME", home.join(".config").into()),
+            ("XDG_DATA_HOME", home.join(".local").join("share").into()),
+        ]
+    }
+}
@@ -0,0 +1,58 @@
+//! Text with its diacritics folded away, for `match.fold_diacritics` and
+//! `match.fold_patterns`.
+//!
+//! Folding decomposes text to NFKD and drops the combining marks left, so
+//! `café` and `cafe\u{301}` both become `cafe` and the ligature `ﬁ` becomes
+//! `fi`. Only what is handed to a matcher is folded; what is shown stays as
+//! written, so positions found in folded text are mapped back with a
+//! [`FoldMap`].
+
+use std::borrow::Cow;
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// `text` without its diacritics, borrowed if it has nothing to fold
+pub fn fold(text: &str) -> Cow<'_, str> {
+    if text.is_ascii() {
+        return Cow::Borrowed(text);
+    }
+    let folded: String = text.nfkd().filter(|c| !is_combining_mark(*c)).collect();
+    match folded == text {
+        true => Cow::Borrowed(text),
+        false => Cow::Owned(folded),
+    }
+}
+
+/// Where each grapheme of a folded text came from in the original.
+///
+/// Positions are counted in graphemes, as the fuzzy matcher counts them. A
+/// grapheme may fold to several, like `ﬁ` to `f` and `i`, each mapping back
+/// to it, or to none, like a combining mark with nothing to combine with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FoldMap {
+    origins: Vec<u32>,
+}
+
+impl FoldMap {
+    /// `text` folded, along with the map back to it
+    pub fn new(text: &str) -> (String, FoldMap) {
+        let mut folded = String::with_capacity(text.len());
+        let mut origins = Vec::new();
+        for (i, grapheme) in text.graphemes(true).enumerate() {
+            let part = fold(grapheme);
+            origins.extend(std::iter::repeat_n(i as u32, part.graphemes(true).count()));
+            folded.push_str(&part);
+        }
+        (folded, FoldMap { origins })
+    }
+
+    /// `indices` into the folded text as positions in the original, in
+    /// order and without repeats; any past its end are dropped
+    pub fn original(&self, indices: &[u32]) -> Vec<u32> {
+        let mut mapped: Vec<u32> = indices.iter().filter_map(|&i| self.origins.get(i as usize).copied()).collect();
+        mapped.sort_unstable();
+        mapped.dedup();
+        mapped
+    }
+}
@@ -0,0 +1,402 @@
+use git2::{
+    AnnotatedCommit, BranchType, Cred, CredentialType, ErrorCode, FetchOptions, Index, IndexEntry, IndexTime,
+    Oid, PushOptions, RemoteCallbacks, Repository, Signature, StatusOptions,
+};
+use std::cell::Cell;
+use std::path::{Component, Path, PathBuf};
+use uuid::Uuid;
+
+use crate::encryption::{self, Cipher};
+use crate::error::WikiError;
+use crate::events::WikiEvent;
+use crate::storage::Backend;
+use crate::wiki::{Information, Wiki};
+
+/// What [`Wiki::sync`] did
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    /// Whether uncommitted wiki changes were committed first
+    pub committed: bool,
+    /// Commits brought in from the remote
+    pub pulled: usize,
+    /// Commits sent to the remote
+    pub pushed: usize,
+    /// Facts changed on both sides; when there are any, the rebase was
+    /// abandoned and nothing was pushed
+    pub conflicts: Vec<SyncConflict>,
+}
+
+/// A fact file both this copy and the remote changed
+#[derive(Debug)]
+pub struct SyncConflict {
+    pub path: PathBuf,
+    /// This copy's version; `None` if it deleted the fact or it can't be read
+    pub local: Option<Information>,
+    /// The remote's version; `None` if it deleted the fact or it can't be read
+    pub remote: Option<Information>,
+}
+
+impl Wiki {
+    /// Commit every change to the wiki's facts to the git repository it
+    /// lives in, creating one in the wiki directory if there is none
+    pub(crate) fn commit_to_git(&mut self) {
+        let path = self.path.clone();
+        self.subscribe(move |event| {
+            let (id, action, name) = match event {
+                WikiEvent::Created(info) => (info.id, "commit", &info.name),
+                WikiEvent::Updated { after, .. } => (after.id, "update", &after.name),
+                WikiEvent::Retagged { id, name, .. } => (*id, "retag", name),
+                WikiEvent::Deleted(info) => (info.id, "delete", &info.name),
+            };
+            // Commit messages aren't encrypted
+            let message = if encryption::is_encrypted(&path) {
+                format!("{}: {}", action, id)
+            } else {
+                format!("{}: {}", action, name)
+            };
+
+            let committed = open_or_init(&path)
+                .and_then(|repo| commit_files(&repo, &fact_files(&path, id), &message));
+            if let Err(e) = committed {
+                tracing::error!(path = %path.display(), "couldn't commit to git: {}", e.message());
+            }
+        });
+    }
+
+    /// Whether the wiki has changes that aren't committed or aren't pushed
+    /// to its upstream branch yet
+    pub fn unsynced(&self) -> bool {
+        let Ok(repo) = Repository::discover(&self.path) else {
+            return false;
+        };
+        !wiki_changes(&repo, &self.path).unwrap_or_default().is_empty()
+            || ahead_behind(&repo).is_ok_and(|(ahead, _)| ahead > 0)
+    }
+
+    /// Commit outstanding changes, pull with rebase from the upstream branch
+    /// and push.
+    ///
+    /// If the rebase runs into conflicts it is abandoned, leaving the
+    /// repository as it was, and the conflicting facts are reported.
+    pub fn sync(&self) -> Result<SyncReport, WikiError> {
+        self.check_writable()?;
+        self.refuse_dry_run("sync with git")?;
+        let repo = Repository::discover(&self.path)?;
+        let mut report = SyncReport::default();
+
+        let changes = wiki_changes(&repo, &self.path)?;
+        if !changes.is_empty() {
+            report.committed = commit_files(&repo, &changes, "sync: local changes")?.is_some();
+        }
+
+        let head = repo.head()?;
+        let branch = head
+            .shorthand()
+            .filter(|_| head.is_branch())
+            .ok_or_else(|| git2::Error::from_str("HEAD is not on a branch"))?
+            .to_string();
+        let remote_name = repo
+            .branch_upstream_remote(&format!("refs/heads/{}", branch))
+            .ok()
+            .and_then(|buf| buf.as_str().map(str::to_string))
+            .unwrap_or_else(|| "origin".to_string());
+        let mut remote = repo.find_remote(&remote_name)?;
+
+        let mut fetch = FetchOptions::new();
+        fetch.remote_callbacks(callbacks());
+        remote.fetch(&[&branch], Some(&mut fetch), None)?;
+
+        // A remote without the branch yet just needs it pushed
+        let upstream_ref = format!("refs/remotes/{}/{}", remote_name, branch);
+        if let Ok(upstream) = repo.find_reference(&upstream_ref) {
+            let upstream = repo.reference_to_annotated_commit(&upstream)?;
+            let (_, behind) = ahead_behind_of(&repo, &upstream)?;
+            let (analysis, _) = repo.merge_analysis(&[&upstream])?;
+
+            if analysis.is_fast_forward() {
+                repo.head()?.set_target(upstream.id(), "wk sync: fast-forward")?;
+                repo.checkout_head(Some(git2::build::CheckoutBuilder::new().safe()))?;
+                report.pulled = behind;
+            } else if !analysis.is_up_to_date() {
+                report.conflicts = rebase(&repo, &upstream, self.cipher().as_deref())?;
+                if !report.conflicts.is_empty() {
+                    return Ok(report);
+                }
+                report.pulled = behind;
+            }
+        }
+
+        let local = repo.head()?.peel_to_commit()?.id();
+        let ahead = match repo.refname_to_id(&upstream_ref) {
+            Ok(upstream) => repo.graph_ahead_behind(local, upstream)?.0,
+            Err(_) => {
+                let mut walk = repo.revwalk()?;
+                walk.push(local)?;
+                walk.count()
+            }
+        };
+        if ahead > 0 {
+            let rejected = std::cell::RefCell::new(None);
+            let mut cbs = callbacks();
+            cbs.push_update_reference(|refname, status| {
+                if let Some(status) = status {
+                    *rejected.borrow_mut() = Some(format!("{} was rejected: {}", refname, status));
+                }
+                Ok(())
+            });
+            let mut push = PushOptions::new();
+            push.remote_callbacks(cbs);
+            remote.push(&[format!("refs/heads/{0}:refs/heads/{0}", branch)], Some(&mut push))?;
+            if let Some(message) = rejected.borrow_mut().take() {
+                return Err(git2::Error::from_str(&message).into());
+            }
+            report.pushed = ahead;
+
+            let mut local_branch = repo.find_branch(&branch, BranchType::Local)?;
+            if local_branch.upstream().is_err() {
+                local_branch.set_upstream(Some(&format!("{}/{}", remote_name, branch)))?;
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Key of the wiki if it is encrypted and unlocked, to read conflicting versions
+    fn cipher(&self) -> Option<std::sync::Arc<Cipher>> {
+        self.is_encrypted().then(|| encryption::unlock(&self.path).ok()).flatten()
+    }
+}
+
+/// The repository the wiki at `path` lives in, initialised in the wiki
+/// directory if there is none
+fn open_or_init(path: &Path) -> Result<Repository, git2::Error> {
+    match Repository::discover(path) {
+        Err(e) if e.code() == ErrorCode::NotFound => Repository::init(path),
+        result => result,
+    }
+}
+
+/// The files that hold the fact `id`
+fn fact_files(wiki_path: &Path, id: Uuid) -> Vec<PathBuf> {
+    match Backend::detect(wiki_path) {
+        Backend::Files => vec![wiki_path.join(format!("{}.json", id))],
+        #[cfg(feature = "sqlite")]
+        Backend::Sqlite => vec![wiki_path.join(crate::sqlite::DB_FILE)],
+    }
+}
+
+/// Uncommitted files in the wiki directory, skipping hidden bookkeeping
+/// files such as locks and caches
+fn wiki_changes(repo: &Repository, wiki_path: &Path) -> Result<Vec<PathBuf>, git2::Error> {
+    let workdir = workdir(repo)?;
+    let wiki_rel = relative(repo, wiki_path)?;
+
+    let mut options = StatusOptions::new();
+    options
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .pathspec(index_path(&wiki_rel));
+    let mut changes = Vec::new();
+    for entry in repo.statuses(Some(&mut options))?.iter() {
+        let Some(path) = entry.path().map(PathBuf::from) else {
+            continue;
+        };
+        let Ok(inner) = path.strip_prefix(&wiki_rel) else {
+            continue;
+        };
+        let hidden = inner
+            .components()
+            .any(|c| c.as_os_str().to_string_lossy().starts_with('.'));
+        if !hidden {
+            changes.push(workdir.join(path));
+        }
+    }
+    Ok(changes)
+}
+
+/// Commit the current contents of `files` on top of HEAD, removing those
+/// that no longer exist. Anything else staged in the repository is left
+/// staged rather than swept into the commit. `None` if nothing changed.
+fn commit_files(repo: &Repository, files: &[PathBuf], message: &str) -> Result<Option<Oid>, git2::Error> {
+    let head = repo.head().ok().and_then(|h| h.peel_to_commit().ok());
+    let mut tree_index = Index::new()?;
+    if let Some(commit) = &head {
+        tree_index.read_tree(&commit.tree()?)?;
+    }
+    let mut repo_index = repo.index()?;
+
+    for file in files {
+        let rel = relative(repo, file)?;
+        match std::fs::read(file) {
+            Ok(bytes) => {
+                tree_index.add(&blob_entry(repo.blob(&bytes)?, &rel, bytes.len()))?;
+                repo_index.add_path(&rel)?;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                // Deleting something never committed is fine
+                tree_index.remove_path(&rel).ok();
+                repo_index.remove_path(&rel).ok();
+            }
+            Err(e) => return Err(git2::Error::from_str(&e.to_string())),
+        }
+    }
+
+    let tree = repo.find_tree(tree_index.write_tree_to(repo)?)?;
+    if head.as_ref().is_some_and(|c| c.tree_id() == tree.id()) {
+        return Ok(None);
+    }
+    let signature = repo
+        .signature()
+        .or_else(|_| Signature::now("wk", "wk@localhost"))?;
+    let parents: Vec<&git2::Commit> = head.iter().collect();
+    let id = repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &parents)?;
+    repo_index.write()?;
+    Ok(Some(id))
+}
+
+/// Replay local commits onto `upstream`, giving up at the first conflict
+fn rebase(
+    repo: &Repository,
+    upstream: &AnnotatedCommit<'_>,
+    cipher: Option<&Cipher>,
+) -> Result<Vec<SyncConflict>, git2::Error> {
+    let signature = repo
+        .signature()
+        .or_else(|_| Signature::now("wk", "wk@localhost"))?;
+    let mut rebase = repo.rebase(None, Some(upstream), None, None)?;
+    let workdir = workdir(repo)?;
+
+    while let Some(operation) = rebase.next() {
+        operation?;
+        let index = repo.index()?;
+        if index.has_conflicts() {
+            let mut conflicts = Vec::new();
+            for conflict in index.conflicts()? {
+                let conflict = conflict?;
+                let Some(path) = conflict
+                    .our
+                    .as_ref()
+                    .or(conflict.their.as_ref())
+                    .map(|e| String::from_utf8_lossy(&e.path).into_owned())
+                else {
+                    continue;
+                };
+                let version = |entry: Option<&IndexEntry>| {
+                    let blob = repo.find_blob(entry?.id).ok()?;
+                    parse_fact(blob.content(), cipher)
+                };
+                // While rebasing, "ours" is the upstream being rebased onto
+                conflicts.push(SyncConflict {
+                    path: workdir.join(path),
+                    local: version(conflict.their.as_ref()),
+                    remote: version(conflict.our.as_ref()),
+                });
+            }
+            rebase.abort()?;
+            return Ok(conflicts);
+        }
+
+        match rebase.commit(None, &signature, None) {
+            // The remote already has this change
+            Err(e) if e.code() == ErrorCode::Applied => {}
+            result => {
+                result?;
+            }
+        }
+    }
+    rebase.finish(Some(&signature))?;
+    Ok(Vec::new())
+}
+
+fn parse_fact(bytes: &[u8], cipher: Option<&Cipher>) -> Option<Information> {
+    match cipher.and_then(|c| c.decrypt(bytes)) {
+        Some(decrypted) => serde_json::from_slice(&decrypted.ok()?).ok(),
+        None => serde_json::from_slice(bytes).ok(),
+    }
+}
+
+/// Commits HEAD is ahead of and behind its upstream branch
+fn ahead_behind(repo: &Repository) -> Result<(usize, usize), git2::Error> {
+    let head = repo.head()?;
+    let branch = repo.find_branch(head.shorthand().unwrap_or_default(), BranchType::Local)?;
+    let upstream = branch.upstream()?;
+    let upstream = repo.reference_to_annotated_commit(upstream.get())?;
+    ahead_behind_of(repo, &upstream)
+}
+
+fn ahead_behind_of(repo: &Repository, upstream: &AnnotatedCommit<'_>) -> Result<(usize, usize), git2::Error> {
+    let local = repo.head()?.peel_to_commit()?.id();
+    repo.graph_ahead_behind(local, upstream.id())
+}
+
+/// Credentials from the ssh agent or git's credential helpers, tried a
+/// few times at most so a bad key can't loop forever
+fn callbacks<'a>() -> RemoteCallbacks<'a> {
+    let mut callbacks = RemoteCallbacks::new();
+    let config = git2::Config::open_default().ok();
+    let attempts = Cell::new(0);
+    callbacks.credentials(move |url, username, allowed| {
+        attempts.set(attempts.get() + 1);
+        if attempts.get() > 3 {
+            return Err(git2::Error::from_str("authentication failed"));
+        }
+        if allowed.contains(CredentialType::SSH_KEY) {
+            return Cred::ssh_key_from_agent(username.unwrap_or("git"));
+        }
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT)
+            && let Some(config) = &config
+        {
+            return Cred::credential_helper(config, url, username);
+        }
+        Cred::default()
+    });
+    callbacks
+}
+
+fn workdir(repo: &Repository) -> Result<PathBuf, git2::Error> {
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| git2::Error::from_str("the wiki's repository is bare"))?;
+    Ok(workdir.canonicalize().unwrap_or_else(|_| workdir.to_path_buf()))
+}
+
+/// `path` relative to the repository's working directory. The file itself
+/// may not exist any more, so only its directory is canonicalised.
+fn relative(repo: &Repository, path: &Path) -> Result<PathBuf, git2::Error> {
+    let absolute = match (path.parent().and_then(|p| p.canonicalize().ok()), path.file_name()) {
+        (Some(dir), Some(name)) => dir.join(name),
+        _ => path.to_path_buf(),
+    };
+    absolute
+        .strip_prefix(workdir(repo)?)
+        .map(Path::to_path_buf)
+        .map_err(|_| git2::Error::from_str(&format!("{} is outside the repository", path.display())))
+}
+
+/// A repository path as git spells it, with `/` separators
+fn index_path(rel: &Path) -> String {
+    rel.components()
+        .filter_map(|c| match c {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn blob_entry(id: Oid, rel: &Path, len: usize) -> IndexEntry {
+    IndexEntry {
+        ctime: IndexTime::new(0, 0),
+        mtime: IndexTime::new(0, 0),
+        dev: 0,
+        ino: 0,
+        mode: 0o100644,
+        uid: 0,
+        gid: 0,
+        file_size: len as u32,
+        id,
+        flags: 0,
+        flags_extended: 0,
+        path: index_path(rel).into_bytes(),
+    }
+}
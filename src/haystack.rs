@@ -0,0 +1,163 @@
+//! Fact names and data converted for the fuzzy matcher, kept between recalls.
+//!
+//! nucleo matches over UTF-32, so scoring a fact means converting its text
+//! first, and doing that for every candidate dominates a recall over text
+//! that isn't plain ASCII. A [`HaystackCache`] keeps the converted text of
+//! each fact it has seen. Entries remember the `updated` time and text
+//! lengths they were made from and are skipped once the fact no longer
+//! matches them; the wiki also drops them whenever it changes a fact.
+//!
+//! Names are always kept, but data only while the cache holds less than its
+//! capacity, [`HAYSTACK_CACHE_BYTES`] by default. Past that, data is converted
+//! on the fly again for every recall.
+
+use chrono::{DateTime, Utc};
+use nucleo_matcher::Utf32String;
+use std::collections::HashMap;
+use std::sync::{PoisonError, RwLock, RwLockReadGuard};
+use uuid::Uuid;
+
+use crate::matching::MatchConfig;
+use crate::wiki::Information;
+
+/// Default cap on the converted data a wiki keeps cached
+pub const HAYSTACK_CACHE_BYTES: usize = 64 * 1024 * 1024;
+
+/// A fact's name, and maybe its data, converted for the matcher
+pub struct Haystacks {
+    updated: Option<DateTime<Utc>>,
+    lens: (usize, usize),
+    pub name: Utf32String,
+    /// `None` if it wasn't asked for, or there was no room for it
+    pub data: Option<Utf32String>,
+}
+
+impl Haystacks {
+    /// Convert the name of `info`, and its data too if `data`
+    pub fn new(info: &Information, data: bool) -> Self {
+        Self::new_with(info, data, &MatchConfig::default())
+    }
+
+    /// Like [`Haystacks::new`], with the text as `config` hands it to the matcher
+    pub fn new_with(info: &Information, data: bool, config: &MatchConfig) -> Self {
+        Haystacks {
+            updated: info.updated,
+            lens: (info.name.len(), info.data.len()),
+            name: Utf32String::from(config.haystack(&info.name).as_ref()),
+            data: data.then(|| Utf32String::from(config.haystack(&info.data).as_ref())),
+        }
+    }
+
+    /// Whether these were converted from `info` as it is now
+    pub fn is_fresh(&self, info: &Information) -> bool {
+        self.updated == info.updated && self.lens == (info.name.len(), info.data.len())
+    }
+
+    fn data_size(&self) -> usize {
+        self.data.as_ref().map_or(0, size)
+    }
+}
+
+/// Bytes held by a converted string
+fn size(text: &Utf32String) -> usize {
+    match text {
+        Utf32String::Ascii(text) => text.len(),
+        Utf32String::Unicode(chars) => chars.len() * size_of::<char>(),
+    }
+}
+
+/// Bytes `text` would take up once converted
+pub fn converted_size(text: &str) -> usize {
+    match text.is_ascii() {
+        true => text.len(),
+        false => text.chars().count() * size_of::<char>(),
+    }
+}
+
+#[derive(Default)]
+struct Entries {
+    by_id: HashMap<Uuid, Haystacks>,
+    /// Total size of the cached data
+    data_bytes: usize,
+}
+
+impl Entries {
+    fn remove(&mut self, id: Uuid) {
+        if let Some(old) = self.by_id.remove(&id) {
+            self.data_bytes -= old.data_size();
+        }
+    }
+}
+
+/// Converted haystacks of a wiki's facts by id, see the [module docs](self)
+pub struct HaystackCache {
+    entries: RwLock<Entries>,
+    capacity: usize,
+}
+
+impl Default for HaystackCache {
+    fn default() -> Self {
+        HaystackCache::with_capacity(HAYSTACK_CACHE_BYTES)
+    }
+}
+
+impl HaystackCache {
+    /// A cache keeping at most `capacity` bytes of converted data
+    pub fn with_capacity(capacity: usize) -> Self {
+        HaystackCache {
+            entries: RwLock::default(),
+            capacity,
+        }
+    }
+
+    /// Look entries up without blocking other readers. Hold the view only
+    /// while scoring; [`HaystackCache::insert`] waits for it to be dropped.
+    pub fn read(&self) -> CacheView<'_> {
+        CacheView {
+            entries: self.entries.read().unwrap_or_else(PoisonError::into_inner),
+            capacity: self.capacity,
+        }
+    }
+
+    /// Cache freshly converted haystacks, replacing older ones for the same
+    /// facts. Data that doesn't fit in the capacity is left out.
+    pub fn insert(&self, haystacks: impl IntoIterator<Item = (Uuid, Haystacks)>) {
+        let mut entries = self.entries.write().unwrap_or_else(PoisonError::into_inner);
+        for (id, mut haystacks) in haystacks {
+            entries.remove(id);
+            if entries.data_bytes + haystacks.data_size() > self.capacity {
+                haystacks.data = None;
+            }
+            entries.data_bytes += haystacks.data_size();
+            entries.by_id.insert(id, haystacks);
+        }
+    }
+
+    /// Forget the haystacks of a fact that changed or is gone
+    pub fn invalidate(&self, id: Uuid) {
+        self.entries.write().unwrap_or_else(PoisonError::into_inner).remove(id);
+    }
+
+    /// Total size of the cached data
+    pub fn data_bytes(&self) -> usize {
+        self.read().entries.data_bytes
+    }
+}
+
+/// Read access to a [`HaystackCache`], from [`HaystackCache::read`]
+pub struct CacheView<'a> {
+    entries: RwLockReadGuard<'a, Entries>,
+    capacity: usize,
+}
+
+impl CacheView<'_> {
+    /// The cached haystacks of `info`, unless they are stale
+    pub fn get(&self, info: &Information) -> Option<&Haystacks> {
+        self.entries.by_id.get(&info.id).filter(|haystacks| haystacks.is_fresh(info))
+    }
+
+    /// Whether `bytes` more of data would still fit
+    pub fn has_room(&self, bytes: usize) -> bool {
+        self.entries.data_bytes + bytes <= self.capacity
+    }
+}
@@ -1,106 +1,175 @@
 use std::{
-    cell::UnsafeCell,
-    fs::{File, OpenOptions},
-    io::Write,
+    fs::{File, OpenOptions, TryLockError},
+    io::{BufReader, Write},
     ops::{Deref, DerefMut},
-    path::PathBuf,
-    ptr::NonNull,
-    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    time::{Duration, Instant},
 };
 
 use serde::{Deserialize, Serialize};
 
+use crate::canonical;
+
+/// A value behind a reader/writer lock, optionally mirrored to a JSON file.
+///
+/// Writes are persisted explicitly with [`WritableKey::save`] or
+/// [`Locked::commit`]; a write key dropped without saving makes a best-effort
+/// save and records any failure for [`Locked::last_error`].
+///
+/// Built on `std` locks only, so it is `Send` and `Sync` whenever `T` is.
 #[derive(Debug)]
 pub struct Locked<T> {
-    readers: AtomicUsize,
-    writer: AtomicBool,
-    in_memory: UnsafeCell<T>,
-    file: UnsafeCell<File>,
+    data: RwLock<T>,
+    /// Backing file rewritten on save; `None` for purely in-memory locks
+    path: Option<PathBuf>,
+    /// Most recent failure to persist
+    last_error: Mutex<Option<std::io::Error>>,
 }
 
-pub struct Key<'a, T: Serialize> {
-    lock: &'a Locked<T>,
-    data: NonNull<T>,
+pub struct Key<'a, T> {
+    guard: RwLockReadGuard<'a, T>,
 }
 
 pub struct WritableKey<'a, T: Serialize> {
     lock: &'a Locked<T>,
+    guard: RwLockWriteGuard<'a, T>,
+    saved: bool,
 }
 
 impl<T: Serialize> Locked<T> {
     pub fn new(path: impl Into<PathBuf>, data: T) -> std::io::Result<Self> {
         let path_buf = path.into();
-        let file = OpenOptions::new()
-            .create(true)
-            .truncate(true)
-            .write(true)
-            .read(true)
-            .open(&path_buf)?;
-        
+
         // Write initial data
-        if let Ok(json) = serde_json::to_string_pretty(&data) {
-            use std::io::Write;
-            (&file).write_all(json.as_bytes())?;
-        }
-        
+        write_atomic(&path_buf, &canonical::to_vec(&data)?)?;
+
         Ok(Self {
-            readers: AtomicUsize::new(0),
-            writer: AtomicBool::new(false),
-            in_memory: UnsafeCell::new(data),
-            file: UnsafeCell::new(file),
+            data: RwLock::new(data),
+            path: Some(path_buf),
+            last_error: Mutex::new(None),
         })
     }
 
+    /// Wrap `data` in a lock without a backing file; persisting is left to the caller
+    pub fn in_memory(data: T) -> Self {
+        Self {
+            data: RwLock::new(data),
+            path: None,
+            last_error: Mutex::new(None),
+        }
+    }
+
     pub fn load(path: impl Into<PathBuf>) -> std::io::Result<Self>
     where
         T: for<'de> Deserialize<'de>,
     {
-        let file = OpenOptions::new()
-            .write(true)
-            .read(true)
-            .open(path.into())?;
+        let path_buf = path.into();
+        let file = File::open(&path_buf)?;
 
-        let data: T = serde_json::from_reader(&file)
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        let data: T = serde_json::from_reader(BufReader::new(file))
+            .map_err(std::io::Error::other)?;
 
         Ok(Self {
-            readers: AtomicUsize::new(0),
-            writer: AtomicBool::new(false),
-            in_memory: UnsafeCell::new(data),
-            file: UnsafeCell::new(file),
+            data: RwLock::new(data),
+            path: Some(path_buf),
+            last_error: Mutex::new(None),
         })
     }
 
-    pub fn read<'a>(&'a self) -> Key<'a, T> {
-        while self.writer.load(Ordering::SeqCst) {
-            std::hint::spin_loop();
-        }
+    /// The most recent failure to persist, if the last attempt failed
+    pub fn last_error(&self) -> Option<std::io::Error> {
+        self.last_error
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+            .map(|e| std::io::Error::new(e.kind(), e.to_string()))
+    }
 
-        self.readers.fetch_add(1, Ordering::SeqCst);
+    /// Block until no writer holds the lock
+    pub fn read(&self) -> Key<'_, T> {
         Key {
-            lock: &self,
-            data: NonNull::new(self.in_memory.get()).unwrap(),
+            guard: self.data.read().unwrap_or_else(PoisonError::into_inner),
         }
     }
 
-    pub fn write<'a>(&'a self) -> WritableKey<'a, T> {
-        while self.writer.load(Ordering::SeqCst) || self.readers.load(Ordering::SeqCst) > 0 {
-            std::hint::spin_loop();
+    /// Block until no reader or writer holds the lock
+    pub fn write(&self) -> WritableKey<'_, T> {
+        WritableKey {
+            lock: self,
+            guard: self.data.write().unwrap_or_else(PoisonError::into_inner),
+            saved: false,
         }
+    }
 
-        self.writer.fetch_or(true, Ordering::SeqCst);
-        WritableKey { lock: &self }
+    /// Read without blocking; `None` if a writer holds the lock
+    pub fn try_read(&self) -> Option<Key<'_, T>> {
+        match self.data.try_read() {
+            Ok(guard) => Some(Key { guard }),
+            Err(std::sync::TryLockError::Poisoned(p)) => Some(Key {
+                guard: p.into_inner(),
+            }),
+            Err(std::sync::TryLockError::WouldBlock) => None,
+        }
+    }
+
+    /// Write without blocking; `None` if anyone else holds the lock
+    pub fn try_write(&self) -> Option<WritableKey<'_, T>> {
+        let guard = match self.data.try_write() {
+            Ok(guard) => guard,
+            Err(std::sync::TryLockError::Poisoned(p)) => p.into_inner(),
+            Err(std::sync::TryLockError::WouldBlock) => return None,
+        };
+        Some(WritableKey {
+            lock: self,
+            guard,
+            saved: false,
+        })
+    }
+
+    /// Persist the current value to the backing file, if there is one
+    pub fn commit(&self) -> std::io::Result<()> {
+        let key = self.read();
+        self.persist(&key)
+    }
+
+    fn persist(&self, data: &T) -> std::io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let result = canonical::to_vec(data).and_then(|json| {
+            let _lock = FileLock::exclusive(&lock_path_for(path), LOCK_TIMEOUT)?;
+            write_atomic(path, &json)
+        });
+
+        let mut last_error = self.last_error.lock().unwrap_or_else(PoisonError::into_inner);
+        match result {
+            Ok(()) => {
+                *last_error = None;
+                Ok(())
+            }
+            Err(e) => {
+                *last_error = Some(std::io::Error::new(e.kind(), e.to_string()));
+                Err(e)
+            }
+        }
     }
 }
 
-impl<'a, T> Deref for Key<'a, T>
-where
-    T: Serialize,
-{
+impl<'a, T: Serialize> WritableKey<'a, T> {
+    /// Persist the modified value and release the lock
+    pub fn save(mut self) -> std::io::Result<()> {
+        self.saved = true;
+        self.lock.persist(&self.guard)
+    }
+}
+
+impl<'a, T> Deref for Key<'a, T> {
     type Target = T;
 
-    fn deref(&self) -> &'a Self::Target {
-        unsafe { self.data.as_ref() }
+    fn deref(&self) -> &Self::Target {
+        &self.guard
     }
 }
 
@@ -110,8 +179,8 @@ where
 {
     type Target = T;
 
-    fn deref(&self) -> &'a Self::Target {
-        unsafe { &*self.lock.in_memory.get() }
+    fn deref(&self) -> &Self::Target {
+        &self.guard
     }
 }
 
@@ -120,27 +189,118 @@ where
     T: Serialize,
 {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        unsafe { &mut *self.lock.in_memory.get() }
+        &mut self.guard
     }
 }
 
-impl<'a, T> Drop for Key<'a, T>
+impl<'a, T> Drop for WritableKey<'a, T>
 where
     T: Serialize,
 {
     fn drop(&mut self) {
-        self.lock.readers.fetch_sub(1, Ordering::SeqCst);
+        // Best effort; a failure is recorded on the lock for later inspection
+        if !self.saved
+            && let Err(e) = self.lock.persist(&self.guard)
+        {
+            tracing::error!(path = ?self.lock.path, "couldn't save on drop: {}", e);
+        }
     }
 }
 
-impl<'a, T> Drop for WritableKey<'a, T>
-where
-    T: Serialize,
-{
-    fn drop(&mut self) {
-        if let Ok(s) = serde_json::to_string_pretty(unsafe { &*self.lock.in_memory.get() }) {
-            let _ = unsafe { (*self.lock.file.get()).write_all(s.as_bytes()) };
+/// Replace the file at `path` with `contents` so that it is always either the
+/// old or the new complete version, even across a crash: write `<name>.tmp`
+/// next to it, fsync, rename over the original, then fsync the directory.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut tmp_name = path
+        .file_name()
+        .ok_or_else(|| std::io::Error::other(format!("not a file path: {}", path.display())))?
+        .to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let written = File::create(&tmp_path).and_then(|mut file| {
+        file.write_all(contents)?;
+        file.sync_all()
+    });
+    if let Err(e) = written.and_then(|_| std::fs::rename(&tmp_path, path)) {
+        std::fs::remove_file(&tmp_path).ok();
+        return Err(e);
+    }
+
+    // Persist the rename itself; directories can't be opened for syncing on Windows
+    #[cfg(unix)]
+    {
+        let dir = match path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new("."),
+        };
+        File::open(dir)?.sync_all()?;
+    }
+
+    Ok(())
+}
+
+/// How long to wait for another process to release a lock before giving up
+pub const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Lock file guarding writes to `path`: `.locks/<file name>.lock` next to it.
+///
+/// The lock can't live on the file itself because atomic writes replace it.
+pub fn lock_path_for(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".lock");
+    path.with_file_name(".locks").join(name)
+}
+
+/// An advisory inter-process lock on a file, released when dropped
+#[derive(Debug)]
+pub struct FileLock {
+    _file: File,
+}
+
+impl FileLock {
+    /// Take an exclusive lock, waiting up to `timeout` for other holders
+    pub fn exclusive(path: &Path, timeout: Duration) -> std::io::Result<Self> {
+        Self::acquire(path, timeout, File::try_lock)
+    }
+
+    /// Take a shared lock, waiting up to `timeout` for an exclusive holder
+    pub fn shared(path: &Path, timeout: Duration) -> std::io::Result<Self> {
+        Self::acquire(path, timeout, File::try_lock_shared)
+    }
+
+    fn acquire(
+        path: &Path,
+        timeout: Duration,
+        try_lock: fn(&File) -> Result<(), TryLockError>,
+    ) -> std::io::Result<Self> {
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(path)?;
+
+        let start = Instant::now();
+        loop {
+            match try_lock(&file) {
+                Ok(()) => return Ok(FileLock { _file: file }),
+                Err(TryLockError::WouldBlock) if start.elapsed() < timeout => {
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                Err(TryLockError::WouldBlock) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        format!("another wk process is writing {}", path.display()),
+                    ));
+                }
+                Err(TryLockError::Error(e)) => return Err(e),
+            }
         }
-        self.lock.writer.store(false, Ordering::SeqCst);
     }
 }
@@ -0,0 +1,113 @@
+//! Scripts run before and after each commit, set under `[hooks]` in the
+//! global `config.toml`.
+//!
+//! A wiki's own config travels with it when it's pulled, synced or shared,
+//! so hooks named there are never run: anyone who could write to the wiki
+//! could run anything on the machines using it. A wiki config with `[hooks]`
+//! is reported as a load warning instead.
+
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::config::global_config_path;
+use crate::error::WikiError;
+use crate::wiki::{Information, Wiki};
+use crate::wikis::expand_path;
+
+/// Seconds a hook may run when `hooks.timeout` isn't set
+pub const DEFAULT_HOOK_TIMEOUT: u64 = 10;
+/// How often a running hook is checked on
+const POLL: Duration = Duration::from_millis(10);
+
+impl Wiki {
+    /// Run the pre-commit hook on a fact about to be stored, refusing the
+    /// commit with [`WikiError::HookRejected`] if it exits non-zero, can't be
+    /// run, or runs out of time
+    pub(crate) fn pre_commit(&self, info: &Information) -> Result<(), WikiError> {
+        match self.hook(&self.config.hooks.pre_commit) {
+            Some(hook) => self.run_hook(&hook, info).map_err(WikiError::HookRejected),
+            None => Ok(()),
+        }
+    }
+
+    /// Run the post-commit hook on a fact just stored. The commit stands
+    /// whatever happens, so failing only warns.
+    pub(crate) fn post_commit(&self, info: &Information) {
+        if let Some(hook) = self.hook(&self.config.hooks.post_commit)
+            && let Err(e) = self.run_hook(&hook, info)
+        {
+            tracing::warn!(wiki = %self.name, "post-commit hook failed: {}", e);
+        }
+    }
+
+    /// Where a configured hook lives, relative to the global config's
+    /// directory rather than anywhere inside the wiki; `None` if it isn't
+    /// set or hooks are off
+    fn hook(&self, path: &Option<PathBuf>) -> Option<PathBuf> {
+        let path = path.as_ref().filter(|_| self.hooks)?;
+        let config = global_config_path()?;
+        let base = config.parent().unwrap_or(&self.path);
+        Some(expand_path(path, dirs::home_dir().as_deref(), base))
+    }
+
+    fn run_hook(&self, hook: &Path, info: &Information) -> Result<(), String> {
+        let timeout = Duration::from_secs(self.config.hooks.timeout.unwrap_or(DEFAULT_HOOK_TIMEOUT));
+        run(hook, &self.path, &self.name, info, timeout)
+    }
+}
+
+/// Run `hook` in `dir` with `info` as JSON on stdin, returning why it failed:
+/// what it wrote to stderr, or its exit status if that was nothing
+fn run(hook: &Path, dir: &Path, wiki: &str, info: &Information, timeout: Duration) -> Result<(), String> {
+    let json = serde_json::to_vec(info).map_err(|e| e.to_string())?;
+    let mut child = Command::new(hook)
+        .current_dir(dir)
+        .env("TWK_WIKI", wiki)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("couldn't run {}: {}", hook.display(), e))?;
+
+    // Feed stdin and drain stderr on their own threads, so a hook that
+    // ignores one or fills the other can't hold up the wait
+    let stdin = child.stdin.take();
+    thread::spawn(move || {
+        if let Some(mut stdin) = stdin {
+            stdin.write_all(&json).ok();
+        }
+    });
+    let stderr = child.stderr.take();
+    let drain = thread::spawn(move || {
+        let mut text = String::new();
+        if let Some(mut stderr) = stderr {
+            stderr.read_to_string(&mut text).ok();
+        }
+        text
+    });
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            child.kill().ok();
+            child.wait().ok();
+            return Err(format!("{} timed out after {}s", hook.display(), timeout.as_secs()));
+        }
+        thread::sleep(POLL);
+    };
+    if status.success() {
+        return Ok(());
+    }
+
+    let stderr = drain.join().unwrap_or_default();
+    match stderr.trim() {
+        "" => Err(format!("{} exited with {}", hook.display(), status)),
+        reason => Err(reason.to_string()),
+    }
+}
@@ -0,0 +1,250 @@
+//! Bringing in notes kept in TiddlyWiki, for `wk import --format tiddlers`.
+//!
+//! Tiddlers are read from a JSON export (an array of objects of string
+//! fields) or from the tiddler store of a single-file wiki saved as HTML.
+//! Each becomes a fact named by its `title`, with its `text` as data, its
+//! `tags` as tags and its `created` and `modified` times. System tiddlers,
+//! whose titles start with `$:/`, are left out.
+//!
+//! Importing the same tiddlers again changes nothing: a tiddler with a
+//! `twk-id` field updates the fact with that id, and otherwise the fact
+//! named like it, if there is one.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_json::{Map, Value};
+use std::io::{Error, ErrorKind};
+use uuid::Uuid;
+
+use crate::error::WikiError;
+use crate::progress::Progress;
+use crate::wiki::{Information, Wiki};
+
+/// Titles of system tiddlers start with this
+pub const SYSTEM_PREFIX: &str = "$:/";
+/// Field of a tiddler holding the id of the fact it came from
+pub const ID_FIELD: &str = "twk-id";
+
+/// One tiddler as it will be imported
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tiddler {
+    pub title: String,
+    pub text: String,
+    pub tags: Vec<String>,
+    pub created: Option<DateTime<Utc>>,
+    pub modified: Option<DateTime<Utc>>,
+    /// From the [`ID_FIELD`], if it holds a valid id
+    pub twk_id: Option<Uuid>,
+}
+
+/// What [`Wiki::import_tiddlers`] did with each tiddler
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ImportReport {
+    pub created: usize,
+    pub updated: usize,
+    /// Already in the wiki just as they are
+    pub unchanged: usize,
+    /// System tiddlers left out
+    pub system: usize,
+    /// Tiddlers without a title, left out
+    pub untitled: usize,
+}
+
+/// Read the tiddlers in `input`, either a JSON array of them or the HTML of
+/// a single-file wiki. Tiddlers without a title are kept, with an empty one.
+pub fn parse_tiddlers(input: &str) -> std::io::Result<Vec<Tiddler>> {
+    let trimmed = input.trim_start();
+    if trimmed.starts_with('[') {
+        return parse_json(input);
+    }
+    if !trimmed.starts_with('<') {
+        return Err(invalid("expected a JSON array of tiddlers or a TiddlyWiki HTML file"));
+    }
+    let stores = html_stores(input);
+    if stores.is_empty() {
+        return Err(invalid(
+            "no tiddler store found in the HTML; wikis saved by TiddlyWiki before 5.2 aren't supported, export their tiddlers as JSON instead",
+        ));
+    }
+    let mut tiddlers = Vec::new();
+    for store in stores {
+        tiddlers.extend(parse_json(store)?);
+    }
+    Ok(tiddlers)
+}
+
+fn parse_json(json: &str) -> std::io::Result<Vec<Tiddler>> {
+    let fields: Vec<Map<String, Value>> =
+        serde_json::from_str(json).map_err(|e| invalid(&format!("not a JSON array of tiddlers: {}", e)))?;
+    Ok(fields.iter().map(tiddler).collect())
+}
+
+fn tiddler(fields: &Map<String, Value>) -> Tiddler {
+    let text = |name: &str| fields.get(name).and_then(Value::as_str).unwrap_or_default();
+    // TiddlyWiki writes tags as one string, but some tools write a list
+    let tags = match fields.get("tags") {
+        Some(Value::Array(tags)) => tags.iter().filter_map(Value::as_str).map(str::to_string).collect(),
+        _ => split_tags(text("tags")),
+    };
+    Tiddler {
+        title: text("title").to_string(),
+        text: text("text").to_string(),
+        tags,
+        created: parse_timestamp(text("created")),
+        modified: parse_timestamp(text("modified")),
+        twk_id: text(ID_FIELD).parse().ok(),
+    }
+}
+
+/// The JSON in each `<script class="tiddlywiki-tiddler-store">` of a wiki
+/// saved as HTML, where TiddlyWiki 5.2 and later keep their tiddlers
+fn html_stores(html: &str) -> Vec<&str> {
+    let mut stores = Vec::new();
+    let mut rest = html;
+    while let Some(at) = rest.find("tiddlywiki-tiddler-store") {
+        rest = &rest[at..];
+        let Some(open) = rest.find('>') else {
+            break;
+        };
+        rest = &rest[open + 1..];
+        let Some(close) = rest.find("</script>") else {
+            break;
+        };
+        stores.push(&rest[..close]);
+        rest = &rest[close..];
+    }
+    stores
+}
+
+/// Split a tiddler's `tags` field the way TiddlyWiki does: on whitespace,
+/// except within `[[double brackets]]` closed before whitespace or the end.
+/// A non-breaking space doesn't separate tags. Brackets that aren't closed
+/// that way are part of a tag like any other character. Repeated and empty
+/// tags are dropped.
+pub fn split_tags(field: &str) -> Vec<String> {
+    let is_space = |c: char| c.is_whitespace() && c != '\u{a0}';
+    let mut tags: Vec<String> = Vec::new();
+    let mut rest = field;
+    loop {
+        rest = rest.trim_start_matches(is_space);
+        if rest.is_empty() {
+            break;
+        }
+        let bracketed = rest.strip_prefix("[[").and_then(|inner| {
+            inner
+                .match_indices("]]")
+                .map(|(end, _)| end)
+                .find(|&end| inner[end + 2..].chars().next().is_none_or(is_space))
+                .map(|end| (&inner[..end], &inner[end + 2..]))
+        });
+        let (tag, after) = bracketed.unwrap_or_else(|| {
+            let end = rest.find(is_space).unwrap_or(rest.len());
+            (&rest[..end], &rest[end..])
+        });
+        let tag = tag.trim();
+        if !tag.is_empty() && !tags.iter().any(|t| t == tag) {
+            tags.push(tag.to_string());
+        }
+        rest = after;
+    }
+    tags
+}
+
+/// A TiddlyWiki timestamp like `20240131154500123`, in UTC to the
+/// millisecond. Shorter ones from older versions are read as far as they
+/// go, down to a date alone.
+pub fn parse_timestamp(stamp: &str) -> Option<DateTime<Utc>> {
+    let stamp = stamp.trim();
+    if stamp.len() < 8 || stamp.len() > 17 || !stamp.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let padded = format!("{:0<17}", stamp);
+    let part = |range: std::ops::Range<usize>| padded[range].parse::<u32>().ok();
+    NaiveDate::from_ymd_opt(part(0..4)? as i32, part(4..6)?, part(6..8)?)?
+        .and_hms_milli_opt(part(8..10)?, part(10..12)?, part(12..14)?, part(14..17)?)
+        .map(|time| time.and_utc())
+}
+
+fn invalid(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+impl Wiki {
+    /// Add `tiddlers` as facts, or update the facts they were imported as
+    /// before; see the [module docs](self). Tags are taken as they are,
+    /// without the config's aliases or default tags, and hooks aren't run.
+    /// Each tiddler taken is reported to `progress`.
+    pub fn import_tiddlers(&mut self, tiddlers: Vec<Tiddler>, progress: &mut dyn Progress) -> Result<ImportReport, WikiError> {
+        let result = self.import_each(tiddlers, progress);
+        progress.finish();
+        result
+    }
+
+    fn import_each(&mut self, tiddlers: Vec<Tiddler>, progress: &mut dyn Progress) -> Result<ImportReport, WikiError> {
+        self.check_writable()?;
+        let _lock = self.lock_exclusive()?;
+        self.hydrate_all()?;
+
+        let mut report = ImportReport::default();
+        progress.start(tiddlers.len());
+        for tiddler in tiddlers {
+            progress.tick(&tiddler.title);
+            if tiddler.title.starts_with(SYSTEM_PREFIX) {
+                report.system += 1;
+                continue;
+            }
+            if tiddler.title.trim().is_empty() {
+                report.untitled += 1;
+                continue;
+            }
+
+            let find = |matches: &dyn Fn(&Information) -> bool| {
+                self.info.iter().map(|locked| locked.read()).find(|info| matches(info)).map(|info| (*info).clone())
+            };
+            let existing = tiddler
+                .twk_id
+                .and_then(|id| find(&|info| info.id == id))
+                .or_else(|| find(&|info| info.name == tiddler.title));
+            let created = tiddler.created.or(tiddler.modified);
+            let fact = match &existing {
+                Some(before) => Information {
+                    id: before.id,
+                    tags: tiddler.tags,
+                    name: tiddler.title,
+                    data: tiddler.text,
+                    created: created.or(before.created),
+                    updated: tiddler.modified.or(before.updated),
+                    source: before.source.clone(),
+                    name_is_derived: false,
+                    extra: before.extra.clone(),
+                },
+                None => {
+                    let created = created.unwrap_or_else(Utc::now);
+                    Information {
+                        id: tiddler.twk_id.unwrap_or_else(Uuid::new_v4),
+                        tags: tiddler.tags,
+                        name: tiddler.title,
+                        data: tiddler.text,
+                        created: Some(created),
+                        updated: Some(tiddler.modified.unwrap_or(created)),
+                        source: None,
+                        name_is_derived: false,
+                        extra: Default::default(),
+                    }
+                }
+            };
+
+            match existing {
+                Some(before) if before == fact => report.unchanged += 1,
+                Some(_) => {
+                    self.put(fact)?;
+                    report.updated += 1;
+                }
+                None => {
+                    self.put(fact)?;
+                    report.created += 1;
+                }
+            }
+        }
+        Ok(report)
+    }
+}
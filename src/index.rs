@@ -0,0 +1,168 @@
+use chrono::{DateTime, Utc};
+use nucleo_matcher::chars;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use uuid::Uuid;
+
+use crate::helpers::write_atomic;
+use crate::wiki::Information;
+
+/// Name of the search index file inside a wiki directory
+pub const SEARCH_INDEX_FILE: &str = ".index.json";
+
+/// Queries shorter than this skip the index; nearly every fact would match anyway
+pub const MIN_INDEXED_QUERY: usize = 3;
+
+/// Fold a character the way the fuzzy matcher compares it
+fn fold(c: char) -> char {
+    chars::to_lower_case(chars::normalize(c))
+}
+
+/// Character-level inverted index used to narrow down fuzzy recall.
+///
+/// A fuzzy match needs every character of the query somewhere in the fact's
+/// name or data, so the facts containing all of them are a superset of the
+/// matches and scoring can skip the rest. Only each fact's character set is
+/// persisted; postings are rebuilt in memory on load.
+#[derive(Default)]
+pub struct SearchIndex {
+    entries: HashMap<Uuid, Entry>,
+    postings: HashMap<char, HashSet<Uuid>>,
+    dirty: bool,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct IndexFile {
+    entries: HashMap<Uuid, Entry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    /// `updated` of the fact when it was indexed
+    updated: Option<DateTime<Utc>>,
+    /// Every folded character of the fact's name and data
+    chars: String,
+}
+
+impl SearchIndex {
+    /// Read the index at `path`; a missing or unreadable file gives an empty index
+    pub fn load(path: &Path) -> Self {
+        let file: IndexFile = std::fs::read(path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+
+        let mut index = SearchIndex::default();
+        for (id, entry) in file.entries {
+            for c in entry.chars.chars() {
+                index.postings.entry(c).or_default().insert(id);
+            }
+            index.entries.insert(id, entry);
+        }
+        index
+    }
+
+    /// Write the index to `path` if it changed since it was loaded or saved
+    pub fn save(&mut self, path: &Path) -> std::io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        #[derive(Serialize)]
+        struct IndexFileRef<'a> {
+            entries: &'a HashMap<Uuid, Entry>,
+        }
+        let json = serde_json::to_vec(&IndexFileRef {
+            entries: &self.entries,
+        })
+        .map_err(std::io::Error::other)?;
+        write_atomic(path, &json)?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Whether `info` is indexed as of its current `updated` timestamp
+    pub fn is_fresh(&self, info: &Information) -> bool {
+        self.entries
+            .get(&info.id)
+            .is_some_and(|entry| entry.updated == info.updated)
+    }
+
+    /// Index `info`, replacing any previous entry for it
+    pub fn insert(&mut self, info: &Information) {
+        self.remove(info.id);
+
+        let mut folded: Vec<char> = info.name.chars().chain(info.data.chars()).map(fold).collect();
+        folded.sort_unstable();
+        folded.dedup();
+        for &c in &folded {
+            self.postings.entry(c).or_default().insert(info.id);
+        }
+
+        self.entries.insert(
+            info.id,
+            Entry {
+                updated: info.updated,
+                chars: folded.into_iter().collect(),
+            },
+        );
+        self.dirty = true;
+    }
+
+    pub fn remove(&mut self, id: Uuid) {
+        let Some(entry) = self.entries.remove(&id) else {
+            return;
+        };
+        for c in entry.chars.chars() {
+            if let Some(ids) = self.postings.get_mut(&c) {
+                ids.remove(&id);
+                if ids.is_empty() {
+                    self.postings.remove(&c);
+                }
+            }
+        }
+        self.dirty = true;
+    }
+
+    /// Drop every entry whose id doesn't satisfy `keep`
+    pub fn retain(&mut self, keep: impl Fn(&Uuid) -> bool) {
+        let gone: Vec<Uuid> = self.entries.keys().filter(|id| !keep(id)).copied().collect();
+        for id in gone {
+            self.remove(id);
+        }
+    }
+
+    /// Ids of indexed facts containing every character of `query`
+    pub fn candidates(&self, query: &str) -> HashSet<Uuid> {
+        let mut needed: Vec<char> = query.chars().map(fold).collect();
+        needed.sort_unstable();
+        needed.dedup();
+
+        let mut lists: Vec<&HashSet<Uuid>> = Vec::with_capacity(needed.len());
+        for c in needed {
+            match self.postings.get(&c) {
+                Some(ids) => lists.push(ids),
+                None => return HashSet::new(),
+            }
+        }
+        lists.sort_by_key(|ids| ids.len());
+
+        let Some((first, rest)) = lists.split_first() else {
+            return self.entries.keys().copied().collect();
+        };
+        first
+            .iter()
+            .filter(|id| rest.iter().all(|ids| ids.contains(id)))
+            .copied()
+            .collect()
+    }
+}
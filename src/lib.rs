@@ -1,14 +1,98 @@
+pub mod access;
+#[cfg(feature = "cli")]
+pub mod app;
+pub mod batch;
+pub mod canonical;
+#[cfg(feature = "cli")]
+pub mod chart;
+#[cfg(feature = "encryption")]
+mod cipher;
+#[cfg(feature = "cli")]
+pub mod clipboard;
+pub mod commands;
+pub mod config;
+pub mod diff;
+pub mod dirsync;
+pub mod doctor;
+pub mod dryrun;
+#[cfg(feature = "cli")]
+pub mod editor;
+pub mod encryption;
+pub mod error;
+pub mod events;
+#[cfg(feature = "cli")]
+pub mod export;
+pub mod feed;
+pub mod fold;
+#[cfg(feature = "test-util")]
+pub mod fixture;
+#[cfg(feature = "git")]
+pub mod git;
+pub mod haystack;
 pub mod helpers;
+pub mod hooks;
+pub mod import;
+pub mod index;
+pub mod matching;
+pub mod mcp;
+pub mod pdf;
+pub mod preview;
+pub mod progress;
+pub mod replace;
+pub mod secrets;
+pub mod size;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod snapshot;
+pub mod snippets;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod stats;
+pub mod storage;
+pub mod suggestions;
+#[cfg(feature = "cli")]
+pub mod table;
+pub mod tags;
+pub mod trash;
+pub mod usage;
+pub mod validate;
 pub mod wiki;
+pub mod wikis;
+pub mod window;
 
-pub use wiki::{Information, Wiki};
+pub use doctor::{Finding, Repair};
+pub use dryrun::Planned;
+pub use error::WikiError;
+pub use events::WikiEvent;
+pub use import::{ImportReport, Tiddler};
+pub use progress::{NoProgress, Progress};
+pub use replace::{ChangedLines, ReplaceOptions, ReplaceReport};
+pub use secrets::{Scanner, SecretHit, SecretMatch, SecretPolicy};
+pub use snippets::expand_snippets;
+pub use stats::Stats;
+pub use storage::{Snapshot, Trashed};
+pub use suggestions::Suggestions;
+pub use tags::TagNode;
+pub use usage::Usage;
+pub use validate::ValidationError;
+pub use wiki::{
+    BookOptions, BookSearch, Field, Fields, GrepHit, GrepOptions, Information, RecallHit, RecallOptions, ScoredFact, Wiki,
+    derive_title,
+};
+pub use window::TimeWindow;
 
 use std::cell::RefCell;
 use std::path::PathBuf;
 
 thread_local! {
-    static CURRENT_WIKI: RefCell<Option<Wiki>> = RefCell::new(None);
-    static USE_GLOBAL: RefCell<bool> = RefCell::new(false);
+    static CURRENT_WIKI: RefCell<Option<Wiki>> = const { RefCell::new(None) };
+    static USE_GLOBAL: RefCell<bool> = const { RefCell::new(false) };
+    static DATA_DIR: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+    static READONLY: RefCell<bool> = const { RefCell::new(false) };
+    static NO_HOOKS: RefCell<bool> = const { RefCell::new(false) };
+    static NO_DEFAULT_TAGS: RefCell<bool> = const { RefCell::new(false) };
+    static DRY_RUN: RefCell<bool> = const { RefCell::new(false) };
+    static CASE: RefCell<Option<matching::Case>> = const { RefCell::new(None) };
 }
 
 /// Set whether to use the global wiki directory
@@ -23,10 +107,84 @@ pub fn is_using_global() -> bool {
     USE_GLOBAL.with(|g| *g.borrow())
 }
 
+/// Override the root of global wikis, taking precedence over `TWK_DATA_DIR`
+/// and the config
+pub fn set_data_dir(dir: Option<PathBuf>) {
+    DATA_DIR.with(|d| {
+        *d.borrow_mut() = dir;
+    });
+}
+
+/// The root of global wikis set with [`set_data_dir`], if any
+pub fn data_dir_override() -> Option<PathBuf> {
+    DATA_DIR.with(|d| d.borrow().clone())
+}
+
+/// Open every wiki read-only, whatever its config says
+pub fn set_readonly(readonly: bool) {
+    READONLY.with(|r| {
+        *r.borrow_mut() = readonly;
+    });
+}
+
+/// Whether [`set_readonly`] asked for wikis to be opened read-only
+pub fn readonly_override() -> bool {
+    READONLY.with(|r| *r.borrow())
+}
+
+/// Skip the commit hooks of every wiki opened from now on
+pub fn set_no_hooks(no_hooks: bool) {
+    NO_HOOKS.with(|h| {
+        *h.borrow_mut() = no_hooks;
+    });
+}
+
+/// Whether [`set_no_hooks`] asked for commit hooks to be skipped
+pub fn no_hooks_override() -> bool {
+    NO_HOOKS.with(|h| *h.borrow())
+}
+
+/// Commit facts to every wiki opened from now on without its `default_tags`
+pub fn set_no_default_tags(no_default_tags: bool) {
+    NO_DEFAULT_TAGS.with(|t| {
+        *t.borrow_mut() = no_default_tags;
+    });
+}
+
+/// Whether [`set_no_default_tags`] asked for default tags to be left off
+pub fn no_default_tags_override() -> bool {
+    NO_DEFAULT_TAGS.with(|t| *t.borrow())
+}
+
+/// Open every wiki from now on as a dry run, see [`Wiki::with_dry_run`]
+pub fn set_dry_run(dry_run: bool) {
+    DRY_RUN.with(|d| {
+        *d.borrow_mut() = dry_run;
+    });
+}
+
+/// Whether [`set_dry_run`] asked for wikis to be opened as dry runs
+pub fn dry_run_override() -> bool {
+    DRY_RUN.with(|d| *d.borrow())
+}
+
+/// Match case in queries this way in every wiki opened from now on, whatever
+/// its config says; `None` leaves it to the config
+pub fn set_case(case: Option<matching::Case>) {
+    CASE.with(|c| {
+        *c.borrow_mut() = case;
+    });
+}
+
+/// How [`set_case`] asked for case to be matched, if it did
+pub fn case_override() -> Option<matching::Case> {
+    CASE.with(|c| *c.borrow())
+}
+
 /// Switch to a different wiki context (creates if it doesn't exist)
 pub fn switch(wiki_name: String) -> Result<(), String> {
     let use_global = is_using_global();
-    let wiki = Wiki::load_or_create(wiki_name, use_global);
+    let wiki = Wiki::load_or_create(wiki_name, use_global).map_err(|e| e.to_string())?;
     CURRENT_WIKI.with(|w| {
         *w.borrow_mut() = Some(wiki);
     });
@@ -45,6 +203,89 @@ pub fn commit(fact: String, tags: Vec<String>) -> Result<uuid::Uuid, String> {
     })
 }
 
+/// Commit a fact to the current wiki under a name other than its data,
+/// noting where it came from
+pub fn commit_named(
+    name: String,
+    data: String,
+    tags: Vec<String>,
+    source: Option<String>,
+) -> Result<uuid::Uuid, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let mut wiki_ref = w.borrow_mut();
+        if let Some(wiki) = wiki_ref.as_mut() {
+            wiki.commit_named(name, data, tags, source)
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Read one fact of the current wiki in full
+pub fn get(id: uuid::Uuid) -> Result<Information, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            wiki.get(id)
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Change one fact of the current wiki, failing with [`WikiError::Conflict`]
+/// if it was modified since `expected_updated` was read
+pub fn update_if(
+    id: uuid::Uuid,
+    expected_updated: Option<chrono::DateTime<chrono::Utc>>,
+    f: impl FnOnce(&mut Information),
+) -> Result<Information, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let mut wiki_ref = w.borrow_mut();
+        if let Some(wiki) = wiki_ref.as_mut() {
+            wiki.update_if(id, expected_updated, f)
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Read one fact of the current wiki back from storage after it was edited by hand
+pub fn reload(id: uuid::Uuid) -> Result<Information, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let mut wiki_ref = w.borrow_mut();
+        if let Some(wiki) = wiki_ref.as_mut() {
+            wiki.reload(id)
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Every fact of the current wiki, read in full, in the configured order
+pub fn all() -> Result<Vec<Information>, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            Ok(wiki.all())
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Commit many facts to the current wiki in one pass
+pub fn commit_many(facts: Vec<(String, Vec<String>)>) -> Result<Vec<uuid::Uuid>, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let mut wiki_ref = w.borrow_mut();
+        if let Some(wiki) = wiki_ref.as_mut() {
+            wiki.commit_many(facts)
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
 /// Recall facts related to a query
 pub fn recall(query: &str, tag_filter: Option<&str>) -> Result<Vec<Information>, String> {
     CURRENT_WIKI.with(|w| {
@@ -57,6 +298,95 @@ pub fn recall(query: &str, tag_filter: Option<&str>) -> Result<Vec<Information>,
     })
 }
 
+/// Recall the `limit` best facts related to a query, cloning only those
+pub fn recall_top(query: &str, tag_filter: Option<&str>, limit: usize) -> Result<Vec<Information>, String> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            Ok(wiki
+                .recall_refs(query, tag_filter, Fields::ALL, TimeWindow::ANY, Some(limit))
+                .into_iter()
+                .map(|hit| (*hit).clone())
+                .collect())
+        } else {
+            Err("No wiki context selected. Use switch() first.".to_string())
+        }
+    })
+}
+
+/// Recall from the current wiki with each hit's score and the field it
+/// matched; see [`Wiki::recall_detailed`]
+pub fn recall_detailed(query: &str, limit: Option<usize>, opts: RecallOptions<'_>) -> Result<Vec<ScoredFact>, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            Ok(wiki.recall_detailed(query, limit, opts).into_iter().map(ScoredFact::from).collect())
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Recall the best `limit` facts (all of them if `None`) whose `fields` are
+/// related to a query, among those changed within `window`
+pub fn recall_within(
+    query: &str,
+    tag_filter: Option<&str>,
+    fields: Fields,
+    window: TimeWindow,
+    limit: Option<usize>,
+) -> Result<Vec<Information>, String> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            Ok(wiki
+                .recall_refs(query, tag_filter, fields, window, limit)
+                .into_iter()
+                .map(|hit| (*hit).clone())
+                .collect())
+        } else {
+            Err("No wiki context selected. Use switch() first.".to_string())
+        }
+    })
+}
+
+/// Recall facts with every word of a query in their `fields`
+pub fn recall_exact(query: &str, tag_filter: Option<&str>, fields: Fields) -> Result<Vec<Information>, String> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            Ok(wiki.recall_exact(query, tag_filter, fields))
+        } else {
+            Err("No wiki context selected. Use switch() first.".to_string())
+        }
+    })
+}
+
+/// Lines of the current wiki's facts matching a pattern
+pub fn grep(pattern: &str, opts: GrepOptions) -> Result<Vec<GrepHit>, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            wiki.grep(pattern, opts)
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Replace matches of a pattern across the current wiki's facts, or report
+/// what would change
+pub fn replace(re: &regex::Regex, replacement: &str, opts: &ReplaceOptions) -> Result<Vec<ReplaceReport>, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let mut wiki_ref = w.borrow_mut();
+        if let Some(wiki) = wiki_ref.as_mut() {
+            wiki.replace(re, replacement, opts)
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
 /// Recall all facts with a specific tag
 pub fn recall_by_tag(tag: &str) -> Result<Vec<Information>, String> {
     CURRENT_WIKI.with(|w| {
@@ -69,14 +399,703 @@ pub fn recall_by_tag(tag: &str) -> Result<Vec<Information>, String> {
     })
 }
 
-/// Build static site generator using mdbook
-pub fn book() -> Result<PathBuf, String> {
+/// Recall the facts with a specific tag changed within `window`
+pub fn recall_by_tag_within(tag: &str, window: TimeWindow) -> Result<Vec<Information>, String> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            Ok(wiki.recall_by_tag_refs(tag, window).into_iter().map(|key| (*key).clone()).collect())
+        } else {
+            Err("No wiki context selected. Use switch() first.".to_string())
+        }
+    })
+}
+
+/// The `n` facts of the current wiki changed most recently, newest first
+pub fn recent(n: usize) -> Result<Vec<Information>, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            Ok(wiki.recent(n))
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// The `n` facts of the current wiki recalled or opened most recently, with
+/// when, newest first
+pub fn recently_accessed(n: usize) -> Result<Vec<(chrono::DateTime<chrono::Utc>, Information)>, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            Ok(wiki.recently_accessed(n))
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Note in the current wiki's access log that facts were just recalled
+pub fn record_access(ids: &[uuid::Uuid]) -> Result<(), WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            wiki.record_access(ids);
+            Ok(())
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// When the last export of the current wiki to `destination` started; see
+/// [`export::destination`]
+#[cfg(feature = "cli")]
+pub fn last_export(destination: &str) -> Result<Option<chrono::DateTime<chrono::Utc>>, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            Ok(wiki.last_export(destination))
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Every fact of the current wiki changed since `since`, or all of them, to
+/// export. Encrypted wikis are only written out if `allow_plaintext_output` is set.
+#[cfg(feature = "cli")]
+pub fn exportable(since: Option<chrono::DateTime<chrono::Utc>>, allow_plaintext_output: bool) -> Result<Vec<Information>, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            if wiki.is_encrypted() && !allow_plaintext_output {
+                return Err(WikiError::Encrypted(wiki.name.clone()));
+            }
+            let window = TimeWindow { since, until: None };
+            Ok(wiki.all().into_iter().filter(|info| window.contains(info)).collect())
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Note in the current wiki's export manifest an export to `destination`
+/// that started `at`
+#[cfg(feature = "cli")]
+pub fn record_export(destination: &str, at: chrono::DateTime<chrono::Utc>) -> Result<(), WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            wiki.record_export(destination, at)
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// How much each fact of the current wiki has been used, or `None` if
+/// usage isn't tracked
+pub fn usage() -> Result<Option<std::collections::HashMap<uuid::Uuid, Usage>>, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            Ok(wiki.usage())
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Count a use of facts of the current wiki, if usage is tracked
+pub fn record_use(ids: &[uuid::Uuid]) -> Result<(), WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            wiki.record_use(ids);
+            Ok(())
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Forget every use of the current wiki's facts; returns whether there were any
+pub fn reset_usage() -> Result<bool, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            wiki.reset_usage()
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// How many facts of the current wiki, or only those with `tag`, were
+/// created in each day, week or month, oldest first
+pub fn activity(bucket: stats::Bucket, tag: Option<&str>) -> Result<Vec<(chrono::NaiveDate, usize)>, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            Ok(match tag {
+                Some(tag) => wiki.tag_activity(tag, bucket),
+                None => wiki.activity(bucket),
+            })
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Number of facts in the current wiki saved before timestamps were recorded
+pub fn undated() -> Result<usize, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            Ok(wiki.undated())
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Bytes of data past which a fact of the current wiki counts as large
+pub fn large_fact_bytes() -> Result<usize, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            Ok(wiki.large_fact_bytes())
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Facts of the current wiki with more than `threshold` bytes of data, the
+/// largest first
+pub fn large_facts(threshold: usize) -> Result<Vec<Information>, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            Ok(wiki.large_facts(threshold))
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Facts of the current wiki similar enough to `text` to be a duplicate of
+/// it, per its `duplicate_threshold` setting, most similar first
+pub fn find_similar(text: &str) -> Result<Vec<(Information, f32)>, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            let threshold = wiki.config.duplicate_threshold.unwrap_or(config::DEFAULT_DUPLICATE_THRESHOLD);
+            Ok(wiki.find_similar(text, threshold))
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Add `text` to the end of a fact of the current wiki, with any new `tags`
+pub fn append(id: uuid::Uuid, text: &str, tags: &[String]) -> Result<Information, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let mut wiki_ref = w.borrow_mut();
+        if let Some(wiki) = wiki_ref.as_mut() {
+            wiki.append(id, text, tags)
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Keep a labelled copy of a fact of the current wiki
+pub fn snapshot(id: uuid::Uuid, label: Option<&str>) -> Result<Snapshot, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let mut wiki_ref = w.borrow_mut();
+        if let Some(wiki) = wiki_ref.as_mut() {
+            wiki.snapshot(id, label)
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Every snapshot of a fact of the current wiki, oldest first
+pub fn snapshots(id: uuid::Uuid) -> Result<Vec<Snapshot>, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            wiki.snapshots(id)
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Revert a fact of the current wiki to one of its snapshots
+pub fn restore(id: uuid::Uuid, label: &str) -> Result<Information, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let mut wiki_ref = w.borrow_mut();
+        if let Some(wiki) = wiki_ref.as_mut() {
+            wiki.restore(id, label)
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Delete a fact of the current wiki, moving it to the trash unless `hard`
+pub fn delete(id: uuid::Uuid, hard: bool) -> Result<Information, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let mut wiki_ref = w.borrow_mut();
+        if let Some(wiki) = wiki_ref.as_mut() {
+            wiki.delete(id, hard)
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Every fact in the current wiki's trash, oldest deletion first
+pub fn trashed() -> Result<Vec<Trashed>, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            wiki.trashed()
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Bring a fact of the current wiki back from the trash
+pub fn untrash(id: uuid::Uuid) -> Result<Information, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let mut wiki_ref = w.borrow_mut();
+        if let Some(wiki) = wiki_ref.as_mut() {
+            wiki.untrash(id)
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Purge facts deleted before `before`, or all of them, from the current
+/// wiki's trash, returning how many
+pub fn empty_trash(before: Option<chrono::DateTime<chrono::Utc>>) -> Result<usize, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let mut wiki_ref = w.borrow_mut();
+        if let Some(wiki) = wiki_ref.as_mut() {
+            wiki.empty_trash(before)
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Every tag of the current wiki arranged by its `/`-separated segments
+pub fn tag_tree() -> Result<Vec<TagNode>, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            Ok(wiki.tag_tree())
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Existing tags of the current wiki that fit `text`, best first
+pub fn suggest_tags(text: &str, limit: usize) -> Result<Vec<(String, f32)>, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            Ok(wiki.suggest_tags(text, limit))
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// How many facts and tags the current wiki has and how many were added lately
+pub fn stats() -> Result<Stats, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            Ok(wiki.stats())
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// What to suggest for a `query` over `fields` that recalled nothing from
+/// the current wiki, probing other wikis like `wk wikis` would
+pub fn suggestions(query: &str, fields: Fields) -> Result<Suggestions, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            Ok(wiki.suggestions(query, fields, is_using_global()))
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// How the current wiki fuzzy matches queries
+pub fn match_config() -> Result<matching::MatchConfig, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            Ok(wiki.config.matching)
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Every tag of the current wiki with the number of facts carrying it
+pub fn tags() -> Result<std::collections::BTreeMap<String, usize>, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            Ok(wiki.tags())
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// The current wiki's tag aliases, each mapped to the tag it stands for
+pub fn tag_aliases() -> Result<std::collections::BTreeMap<String, String>, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            Ok(wiki.config.aliases.clone())
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// The current wiki's snippets, by trigger
+pub fn snippets() -> Result<std::collections::BTreeMap<String, String>, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            Ok(wiki.config.snippets.clone())
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// The current wiki's secret patterns, and what to do about a match
+pub fn secret_scanner() -> Result<(Scanner, SecretPolicy), WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            Ok((wiki.secret_scanner()?, wiki.config.secrets.policy))
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Every secret in the current wiki's facts, by fact and line
+pub fn scan_secrets() -> Result<Vec<SecretHit>, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            wiki.scan_secrets()
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Rewrite aliased tags in the current wiki to their canonical names,
+/// returning each changed fact before and after
+pub fn normalize_tags() -> Result<Vec<(Information, Information)>, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let mut wiki_ref = w.borrow_mut();
+        if let Some(wiki) = wiki_ref.as_mut() {
+            wiki.normalize_tags()
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Add and remove tags on every fact of the current wiki `selection` picks
+/// out, see [`Wiki::bulk_retag`]
+pub fn bulk_retag(
+    selection: &tags::Selection,
+    add: &[String],
+    remove: &[String],
+) -> Result<Vec<(Information, Information)>, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let mut wiki_ref = w.borrow_mut();
+        if let Some(wiki) = wiki_ref.as_mut() {
+            wiki.bulk_retag(selection, add, remove)
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Import `tiddlers` into the current wiki, see [`import`]
+pub fn import_tiddlers(tiddlers: Vec<Tiddler>, progress: &mut dyn Progress) -> Result<ImportReport, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let mut wiki_ref = w.borrow_mut();
+        if let Some(wiki) = wiki_ref.as_mut() {
+            wiki.import_tiddlers(tiddlers, progress)
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// The plain JSON file each fact of the current wiki is stored in, `None`
+/// where there isn't one
+pub fn fact_files(facts: &[Information]) -> Result<Vec<Option<PathBuf>>, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            Ok(facts.iter().map(|info| wiki.fact_file(info.id)).collect())
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// The plain JSON file a fact of the current wiki is stored in, whether or
+/// not it loaded; `None` if facts aren't stored that way
+pub fn fact_file(id: uuid::Uuid) -> Result<Option<PathBuf>, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            Ok(wiki.fact_file(id))
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Convert the current wiki to another storage backend
+pub fn migrate(to: storage::Backend) -> Result<usize, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let mut wiki_ref = w.borrow_mut();
+        if let Some(wiki) = wiki_ref.as_mut() {
+            wiki.migrate(to)
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Rebuild the current wiki's search index from scratch, reporting to `progress`
+pub fn reindex(progress: &mut dyn Progress) -> Result<usize, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            wiki.rebuild_index_with(progress)
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Rewrite the current wiki's files in the canonical format, returning how
+/// many weren't
+pub fn reformat() -> Result<usize, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            wiki.reformat()
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Stored facts of the current wiki that failed to load
+pub fn load_warnings() -> Vec<storage::LoadWarning> {
+    CURRENT_WIKI.with(|w| {
+        w.borrow()
+            .as_ref()
+            .map(|wiki| wiki.warnings.clone())
+            .unwrap_or_default()
+    })
+}
+
+/// What the current wiki, opened as a dry run, would have done so far
+pub fn plan() -> Vec<Planned> {
+    CURRENT_WIKI.with(|w| w.borrow().as_ref().map(Wiki::plan).unwrap_or_default())
+}
+
+/// Conflict copies found next to the current wiki's fact files
+pub fn conflict_copies() -> Vec<PathBuf> {
+    CURRENT_WIKI.with(|w| {
+        w.borrow()
+            .as_ref()
+            .map(|wiki| wiki.conflict_copies.iter().map(|(path, _)| path.clone()).collect())
+            .unwrap_or_default()
+    })
+}
+
+/// Where the current wiki lives and what state it is in
+pub fn status() -> Result<wikis::Status, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            Ok(wikis::Status {
+                name: wiki.name.clone(),
+                resolved: Wiki::get_wiki_path(&wiki.name, is_using_global()),
+                facts: wiki.info.len(),
+                modified: wiki.last_modified(),
+                warnings: wiki.warnings.clone(),
+                readonly: wiki.readonly,
+            })
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Commit, pull and push the current wiki's git repository
+#[cfg(feature = "git")]
+pub fn sync() -> Result<git::SyncReport, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            wiki.sync()
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Reconcile the current wiki with another copy of it in `other`
+pub fn sync_dir(other: &std::path::Path, dry_run: bool) -> Result<dirsync::DirSyncPlan, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let mut wiki_ref = w.borrow_mut();
+        if let Some(wiki) = wiki_ref.as_mut() {
+            wiki.sync_dir(other, dry_run)
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Run the commands read from `input` against the current wiki, writing a
+/// line of JSON for each to `output`; see [`batch`]
+pub fn batch(
+    input: impl std::io::BufRead,
+    output: impl std::io::Write,
+    options: batch::BatchOptions,
+) -> Result<batch::BatchReport, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let mut wiki_ref = w.borrow_mut();
+        if let Some(wiki) = wiki_ref.as_mut() {
+            batch::run(wiki, input, output, options)
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Serve the current wiki as an MCP tool server on `input` and `output`
+/// until `input` ends
+pub fn mcp(input: impl std::io::BufRead, output: impl std::io::Write) -> Result<(), WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let mut wiki_ref = w.borrow_mut();
+        if let Some(wiki) = wiki_ref.as_mut() {
+            Ok(mcp::serve(wiki, input, output)?)
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Check the current wiki for integrity problems
+pub fn diagnose() -> Result<Vec<Finding>, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            Ok(wiki.diagnose()?)
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Repair what can be repaired of the given findings in the current wiki
+pub fn doctor(findings: &[Finding]) -> Result<Vec<Repair>, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let mut wiki_ref = w.borrow_mut();
+        if let Some(wiki) = wiki_ref.as_mut() {
+            wiki.fix(findings)
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Render the book page of one fact of the current wiki and return its HTML
+/// file. Pages of encrypted wikis are only written out if `allow_plaintext_output` is set.
+pub fn book_page(id: uuid::Uuid, allow_plaintext_output: bool) -> Result<PathBuf, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            if wiki.is_encrypted() && !allow_plaintext_output {
+                return Err(WikiError::Encrypted(wiki.name.clone()));
+            }
+            wiki.generate_page(id)
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Make a PDF of the current wiki's book with `backend`, or join its pages
+/// for pandoc without one; see [`Wiki::generate_pdf`]. Encrypted wikis are
+/// only written out if `allow_plaintext_output` is set.
+pub fn book_pdf(
+    allow_plaintext_output: bool,
+    options: &BookOptions,
+    backend: Option<pdf::PdfBackend>,
+    progress: &mut dyn Progress,
+) -> Result<pdf::PdfOutput, WikiError> {
+    CURRENT_WIKI.with(|w| {
+        let wiki_ref = w.borrow();
+        if let Some(wiki) = wiki_ref.as_ref() {
+            if wiki.is_encrypted() && !allow_plaintext_output {
+                return Err(WikiError::Encrypted(wiki.name.clone()));
+            }
+            Ok(wiki.generate_pdf(options, backend, progress)?)
+        } else {
+            Err(WikiError::NoContext)
+        }
+    })
+}
+
+/// Build static site generator using mdbook with `options`, reporting each page
+/// to `progress`. Encrypted wikis are only written out if `allow_plaintext_output` is set.
+pub fn book(allow_plaintext_output: bool, options: &BookOptions, progress: &mut dyn Progress) -> Result<PathBuf, WikiError> {
     CURRENT_WIKI.with(|w| {
         let wiki_ref = w.borrow();
         if let Some(wiki) = wiki_ref.as_ref() {
-            wiki.generate_book().map_err(|e| e.to_string())
+            if wiki.is_encrypted() && !allow_plaintext_output {
+                return Err(WikiError::Encrypted(wiki.name.clone()));
+            }
+            Ok(wiki.generate_book_with(options, progress)?)
         } else {
-            Err("No wiki context selected. Use switch() first".to_string())
+            Err(WikiError::NoContext)
         }
     })
 }
@@ -0,0 +1,40 @@
+//! Debug logging to a file with `--log-file`, for following what `wk` and
+//! especially the TUI did. Without the flag no subscriber is installed and
+//! every event is dropped.
+//!
+//! What's written is chosen by `TWK_LOG`, an `EnvFilter` directive such as
+//! `trace` or `twk::wiki=debug,wk=warn`; it defaults to debug events from
+//! the library, `twk`, and the binary, `wk`.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::sync::Mutex;
+use tracing_subscriber::EnvFilter;
+
+/// Environment variable holding the log filter
+pub const FILTER_VAR: &str = "TWK_LOG";
+
+/// Append every event passing the filter to `path` from here on
+pub fn init(path: &Path) -> io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let filter = EnvFilter::try_from_env(FILTER_VAR).unwrap_or_else(|_| EnvFilter::new("twk=debug,wk=debug"));
+    tracing_subscriber::fmt()
+        .with_writer(Mutex::new(file))
+        .with_ansi(false)
+        .with_env_filter(filter)
+        .try_init()
+        .map_err(io::Error::other)
+}
+
+/// The last `n` lines of the log at `path`
+pub fn tail(path: &Path, n: usize) -> io::Result<Vec<String>> {
+    let mut lines = std::collections::VecDeque::with_capacity(n + 1);
+    for line in BufReader::new(File::open(path)?).lines() {
+        lines.push_back(line?);
+        if lines.len() > n {
+            lines.pop_front();
+        }
+    }
+    Ok(lines.into())
+}
@@ -1,17 +1,89 @@
-use clap::{Parser, Subcommand};
+use chrono::{DateTime, Utc};
+use clap::{CommandFactory, Parser, Subcommand};
 use colored::*;
 use std::env;
-use twk::{commit, recall, recall_by_tag, switch, book, set_use_global};
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use twk::{append, commit_named, derive_title, delete, empty_trash, restore, trashed, untrash, snapshot, snapshots, find_similar, get, grep, suggest_tags, tags, tag_aliases, normalize_tags, bulk_retag, snippets, expand_snippets, import_tiddlers, stats, tag_tree, recall_within, recall_detailed, exportable, last_export, record_export, large_fact_bytes, large_facts, recall_by_tag, recall_by_tag_within, recall_exact, scan_secrets, secret_scanner, undated, activity, recent, recently_accessed, record_access, record_use, reset_usage, usage, replace, switch, book, book_pdf, migrate, diagnose, doctor, reindex, reformat, load_warnings, conflict_copies, fact_files, status, sync_dir, set_use_global, set_data_dir, set_readonly, set_no_hooks, set_no_default_tags, set_dry_run, set_case, match_config, plan, BookOptions, BookSearch, Planned, Fields, Finding, GrepOptions, RecallOptions, ReplaceOptions, Repair, SecretPolicy, TagNode, TimeWindow, Wiki, WikiError};
+use twk::batch::BatchOptions;
+use twk::{chart, table};
+use twk::config::Config;
+use twk::pdf::PdfOutput;
+use twk::dirsync::DirSyncAction;
+use twk::matching::Case;
+use twk::preview::{LineMatcher, preview_line};
+use twk::usage::{RECALL_USES, sort_by_frecency};
+use twk::size::format_size;
+use twk::stats::Bucket;
+use twk::storage::Backend;
+use twk::{clipboard, encryption, wikis};
+use output::{ColorMode, Level, detail, eprintln_colored, say, warning};
+use unicode_width::UnicodeWidthStr;
 
+mod bar;
+mod logging;
+mod output;
+mod picker;
 mod tui;
+mod wizard;
 
 #[derive(Parser)]
 #[command(name = "wk")]
 #[command(about = "Home grown knowledge management tool", long_about = None)]
+#[command(after_help = "Exit status: 0 on success, 1 on failure, 2 for a usage error, 3 if something asked for \
+                       doesn't exist and 4 on a conflict, e.g. a fact changed meanwhile or a wiki is locked")]
 struct Cli {
     /// Use global wiki directory instead of local .wiki/ folder
     #[arg(short = 'g', long = "global", global = true)]
     global: bool,
+
+    /// Root directory for global wikis, overriding TWK_DATA_DIR and the config
+    #[arg(long = "data-dir", global = true)]
+    data_dir: Option<PathBuf>,
+
+    /// Also print where the wiki was found, entries that failed to load and
+    /// how long loading and recalling took, on stderr
+    #[arg(short = 'v', long = "verbose", global = true, conflicts_with = "quiet")]
+    verbose: bool,
+
+    /// Print only results and errors: no confirmations, notes or warnings,
+    /// and recalled facts as bare data
+    #[arg(short = 'q', long = "quiet", global = true)]
+    quiet: bool,
+
+    /// Refuse every change to the wiki
+    #[arg(long = "readonly", global = true)]
+    readonly: bool,
+
+    /// Don't run the wiki's pre- and post-commit hooks
+    #[arg(long = "no-hooks", global = true)]
+    no_hooks: bool,
+
+    /// Don't look for secrets like API keys in facts being committed
+    #[arg(long = "no-scan", global = true)]
+    no_scan: bool,
+
+    /// Print what the command would change without writing anything
+    #[arg(long = "dry-run", global = true)]
+    dry_run: bool,
+
+    /// Match case in queries this way instead of as the config's `[match]`
+    /// table says; `smart` only when the query has a capital letter
+    #[arg(long = "case", value_enum, global = true)]
+    case: Option<CaseArg>,
+
+    /// When to colour output; `auto` also honours NO_COLOR
+    #[arg(long = "color", value_enum, default_value_t = ColorMode::Auto, global = true)]
+    color: ColorMode,
+
+    /// Append debug logs to this file, filtered by TWK_LOG (e.g. `twk=trace`)
+    #[arg(long = "log-file", global = true, value_name = "PATH")]
+    log_file: Option<PathBuf>,
+
+    /// With no command and no wikis yet, show the usage rather than asking
+    /// how to set up the first wiki
+    #[arg(long = "no-wizard")]
+    no_wizard: bool,
     
     #[command(subcommand)]
     command: Option<Commands>,
@@ -22,10 +94,52 @@ enum Commands {
     /// Commit a fact to memory
     #[command(name = "c", alias = "commit")]
     Commit {
-        /// The fact to commit
-        fact: String,
-        /// Optional tag for the fact
-        tag: Option<String>,
+        /// The fact to commit, or - to read it from stdin; with --clip, a tag
+        /// like the rest
+        #[arg(required_unless_present = "clip")]
+        fact: Option<String>,
+        /// Optional tags for the fact
+        tags: Vec<String>,
+        /// Commit the clipboard's text, its first line becoming the name
+        #[arg(long = "clip")]
+        clip: bool,
+        /// Name the fact this rather than after its text
+        #[arg(long = "name")]
+        name: Option<String>,
+        /// Name the fact after its first Markdown heading or first wordy
+        /// line, rather than its first line; --name wins over this
+        #[arg(long = "auto-title")]
+        auto_title: bool,
+        /// Review and edit the fact in $EDITOR before saving it
+        #[arg(short = 'e', long = "edit")]
+        edit: bool,
+        /// Suggest existing tags that fit the fact and offer to add them
+        #[arg(long = "suggest")]
+        suggest: bool,
+        /// Don't look for a similar fact before committing
+        #[arg(long = "no-dup-check")]
+        no_dup_check: bool,
+        /// Where the fact came from, such as a URL
+        #[arg(long = "source")]
+        source: Option<String>,
+        /// Commit the text as typed, without expanding snippets
+        #[arg(long = "no-expand")]
+        no_expand: bool,
+        /// Leave off the tags the wiki's `default_tags` adds to every fact
+        #[arg(long = "no-default-tags")]
+        no_default_tags: bool,
+    },
+
+    /// Append everything copied to the clipboard to a fact for today's
+    /// date, each under the time it was copied, until Ctrl-C
+    #[command(name = "watch-clipboard")]
+    WatchClipboard {
+        /// Tag the capture fact with this; repeat for more
+        #[arg(short = 't', long = "tag")]
+        tags: Vec<String>,
+        /// Skip copies larger than this many bytes, with a warning
+        #[arg(long = "max-bytes", default_value_t = CAPTURE_MAX_BYTES)]
+        max_bytes: usize,
     },
     
     /// Recall facts related to a query
@@ -36,13 +150,363 @@ enum Commands {
         /// Show fact IDs in the output
         #[arg(long = "id")]
         show_id: bool,
+        /// Match facts with every word of the query somewhere in the `--in`
+        /// fields, instead of fuzzy matching
+        #[arg(short = 'e', long = "exact")]
+        exact: bool,
+        /// Show at most this many facts
+        #[arg(short = 'n', long = "limit")]
+        limit: Option<usize>,
+        /// Match the query against these parts of each fact; repeat or
+        /// separate with commas to match any of them
+        #[arg(long = "in", value_enum, value_delimiter = ',', default_value = "all")]
+        search_in: Vec<RecallIn>,
+        /// Only facts changed since then: an age like 3d or 2w, `yesterday`,
+        /// or a date like 2024-01-31. Without a query, lists them newest first.
+        #[arg(long = "since", value_parser = parse_since)]
+        since: Option<DateTime<Utc>>,
+        /// Only facts changed before then, taking in the whole of a date
+        #[arg(long = "until", value_parser = parse_until)]
+        until: Option<DateTime<Utc>>,
+        /// How to print the results
+        #[arg(long = "format", value_enum, default_value_t = RecallFormat::Text)]
+        format: RecallFormat,
+        /// With vimgrep or paths, point at a readable Markdown rendering of
+        /// each fact in a temp folder instead of its JSON file
+        #[arg(long = "materialize")]
+        materialize: bool,
+        /// Narrow the facts down interactively, starting from the query, and
+        /// print only the chosen one's data; Esc exits with status 130
+        #[arg(long = "pick")]
+        pick: bool,
+        /// Like --pick, but print the chosen fact's id
+        #[arg(long = "pick-id", conflicts_with = "pick")]
+        pick_id: bool,
+        /// Order the results by this instead of how well they match
+        #[arg(long = "sort", value_enum)]
+        sort: Option<SortBy>,
+        /// Print everything directly instead of through $PAGER when it
+        /// doesn't fit on the screen
+        #[arg(long = "no-pager")]
+        no_pager: bool,
+        /// With --format json, print each fact as `info` along with its
+        /// `score`, its `raw_score` before tag_weights, the `matched_field`
+        /// and the `indices` of the characters there that matched, best first
+        #[arg(long = "detailed", conflicts_with = "sort")]
+        detailed: bool,
+        /// With --detailed, search every wiki rather than this one; a fact
+        /// several hold is listed once, its `wiki` the one where it matched
+        /// best and `also_in` the others
+        #[arg(long = "all", requires = "detailed")]
+        all: bool,
+        /// Fuzzy match the data of facts over large_fact_bytes too, rather
+        /// than only their names, tags and sources; --detailed marks hits
+        /// that skipped it with `large`
+        #[arg(long = "include-large")]
+        include_large: bool,
+    },
+
+    /// List every fact, in the wiki's order unless sorted otherwise
+    #[command(name = "ls")]
+    Ls {
+        /// Order the facts by this instead
+        #[arg(long = "sort", value_enum)]
+        sort: Option<SortBy>,
+        /// Show at most this many facts
+        #[arg(short = 'n', long = "limit")]
+        limit: Option<usize>,
+        /// Show fact IDs in the output
+        #[arg(long = "id")]
+        show_id: bool,
+        /// How to print the results
+        #[arg(long = "format", value_enum, default_value_t = RecallFormat::Text)]
+        format: RecallFormat,
+        /// With vimgrep or paths, point at a readable Markdown rendering of
+        /// each fact in a temp folder instead of its JSON file
+        #[arg(long = "materialize")]
+        materialize: bool,
+        /// Print everything directly instead of through $PAGER when it
+        /// doesn't fit on the screen
+        #[arg(long = "no-pager")]
+        no_pager: bool,
+        /// Only facts with more than this many bytes of data, largest first;
+        /// the wiki's large_fact_bytes if no size is given
+        #[arg(long = "large", value_name = "BYTES", num_args = 0..=1)]
+        large: Option<Option<usize>>,
+    },
+
+    /// List the facts changed most recently, newest first
+    #[command(name = "recent")]
+    Recent {
+        /// How many facts to list
+        #[arg(default_value_t = twk::access::DEFAULT_RECENT)]
+        n: usize,
+        /// List the facts recalled or opened most recently instead
+        #[arg(long = "accessed")]
+        accessed: bool,
+        /// Show fact IDs in the output
+        #[arg(long = "id")]
+        show_id: bool,
+        /// How to print the results
+        #[arg(long = "format", value_enum, default_value_t = RecallFormat::Text)]
+        format: RecallFormat,
+        /// With vimgrep or paths, point at a readable Markdown rendering of
+        /// each fact in a temp folder instead of its JSON file
+        #[arg(long = "materialize")]
+        materialize: bool,
+        /// Print everything directly instead of through $PAGER when it
+        /// doesn't fit on the screen
+        #[arg(long = "no-pager")]
+        no_pager: bool,
     },
     
+    /// Print every line of every fact matching a pattern, like grep.
+    /// Exits 0 if anything matched, 1 if nothing did and 2 on error.
+    #[command(name = "grep")]
+    Grep {
+        /// Regular expression to look for
+        pattern: String,
+        /// Treat the pattern as a fixed string
+        #[arg(short = 'F', long = "fixed-strings")]
+        fixed: bool,
+        /// Ignore case
+        #[arg(short = 'i', long = "ignore-case")]
+        ignore_case: bool,
+        /// Print only the number of matching lines per fact
+        #[arg(short = 'c', long = "count", conflicts_with = "names_only")]
+        count: bool,
+        /// Print only the names of facts with a match
+        #[arg(short = 'l', long = "files-with-matches")]
+        names_only: bool,
+    },
+
+    /// List lines of facts that look like they hold a secret, such as an API
+    /// key or password, as `id:line: pattern`. Exits 0 if none do and 1 if
+    /// any do.
+    #[command(name = "scan")]
+    Scan,
+
+    /// Replace every match of a pattern in facts' data. Shows what would
+    /// change unless --write is given, and snapshots each fact it changes.
+    #[command(name = "replace")]
+    Replace {
+        /// Regular expression to look for
+        pattern: String,
+        /// What to put in its place; `$1` or `${name}` insert capture groups
+        replacement: String,
+        /// Treat the pattern as a fixed string
+        #[arg(short = 'F', long = "fixed-strings")]
+        fixed: bool,
+        /// Ignore case
+        #[arg(short = 'i', long = "ignore-case")]
+        ignore_case: bool,
+        /// Replace in facts' names too
+        #[arg(long = "names")]
+        names: bool,
+        /// Make the changes, asking about each fact
+        #[arg(long = "write")]
+        write: bool,
+        /// With --write, change every fact without asking
+        #[arg(long = "all", requires = "write")]
+        all: bool,
+    },
+
+    /// Print one fact by id
+    #[command(name = "show")]
+    Show {
+        /// Id of the fact, as printed by `wk r --id`
+        id: uuid::Uuid,
+        /// Copy the fact's data to the clipboard instead of printing it
+        #[arg(long = "copy")]
+        copy: bool,
+        /// Print the fact as JSON, every field included
+        #[arg(long = "json", conflicts_with = "copy")]
+        json: bool,
+    },
+
+    /// Edit a fact in $EDITOR, or open its JSON file or book page
+    #[command(name = "open")]
+    Open {
+        /// Id of the fact, as printed by `wk r --id`
+        id: uuid::Uuid,
+        /// Edit the fact's JSON file itself rather than a copy with frontmatter
+        #[arg(long = "raw", conflicts_with = "book")]
+        raw: bool,
+        /// Render just this fact's book page and open it with the default app
+        #[arg(long = "book")]
+        book: bool,
+        /// With --book, render a page of an encrypted wiki in plaintext
+        #[arg(long = "allow-plaintext-output", requires = "book")]
+        allow_plaintext_output: bool,
+    },
+
+    /// Keep a labelled copy of a fact to return to with `wk restore`
+    #[command(name = "snapshot")]
+    Snapshot {
+        /// Id of the fact
+        id: uuid::Uuid,
+        /// Name for the snapshot, unique for the fact; defaults to the current time
+        label: Option<String>,
+    },
+
+    /// List the snapshots of a fact
+    #[command(name = "snapshots")]
+    Snapshots {
+        /// Id of the fact
+        id: uuid::Uuid,
+    },
+
+    /// Revert a fact to a snapshot, first snapshotting it as `pre-restore`
+    #[command(name = "restore")]
+    Restore {
+        /// Id of the fact
+        id: uuid::Uuid,
+        /// Label of the snapshot to revert to
+        #[arg(long = "snapshot")]
+        snapshot: String,
+    },
+
+    /// Show how a fact's data differs from its latest snapshot, the fact with
+    /// the same id in another wiki, or a file
+    #[command(
+        name = "diff",
+        after_help = "Exits 0 if they're the same, 1 if they differ and 2 or more if they couldn't be compared."
+    )]
+    Diff {
+        /// Id of the fact
+        id: uuid::Uuid,
+        /// Compare with this snapshot instead of the latest
+        #[arg(long = "snapshot", conflicts_with_all = ["wiki", "file"])]
+        snapshot: Option<String>,
+        /// Compare with the fact in this wiki
+        #[arg(long = "wiki", conflicts_with = "file")]
+        wiki: Option<String>,
+        /// Compare with this file
+        #[arg(long = "file")]
+        file: Option<PathBuf>,
+        /// Only count the lines added and removed
+        #[arg(long = "stat")]
+        stat: bool,
+    },
+
+    /// Delete a fact, moving it to the trash to bring back with `wk trash restore`
+    #[command(name = "delete", alias = "rm")]
+    Delete {
+        /// Id of the fact
+        id: uuid::Uuid,
+        /// Remove the fact and its snapshots for good instead
+        #[arg(long = "hard")]
+        hard: bool,
+    },
+
+    /// List, restore and purge deleted facts
+    #[command(name = "trash", subcommand)]
+    Trash(TrashCommand),
+
+    /// Manage the counts of how often each fact is used, kept for
+    /// `--sort frecency` when `track_usage` is on
+    #[command(name = "usage", subcommand)]
+    Usage(UsageCommand),
+
+    /// Add tags to and take them off every fact matching a query or tag
+    #[command(
+        name = "tag",
+        group = clap::ArgGroup::new("selection").args(["query", "tag"]).multiple(true).required(true)
+    )]
+    Tag {
+        /// Tags to add, written `+tag` or just `tag`
+        #[arg(value_name = "+TAG")]
+        tags: Vec<String>,
+        /// Pick the facts matching this query, as `wk r` finds them
+        #[arg(long = "query")]
+        query: Option<String>,
+        /// Pick only facts with this tag or one nested under it
+        #[arg(long = "tag")]
+        tag: Option<String>,
+        /// Tag to add, as many times as needed
+        #[arg(long = "add", value_name = "TAG")]
+        add: Vec<String>,
+        /// Tag to take off, as many times as needed
+        #[arg(long = "remove", value_name = "TAG")]
+        remove: Vec<String>,
+        /// Change at most this many facts, the best matches for a query
+        #[arg(short = 'n', long = "limit")]
+        limit: Option<usize>,
+    },
+
+    /// List every tag in use with how many facts carry it, aliases under
+    /// the tag they stand for
+    #[command(name = "tags")]
+    Tags {
+        /// Arrange nested tags like `lang/rust` under their parents, with
+        /// counts covering everything beneath
+        #[arg(long = "tree")]
+        tree: bool,
+        #[command(subcommand)]
+        action: Option<TagsCommand>,
+    },
+
+    /// Keep serving copied text on the clipboard; run by `wk show --copy`
+    #[command(name = twk::clipboard::HOLD_COMMAND, hide = true)]
+    HoldClipboard,
+
     /// Build static site generator
     #[command(name = "book")]
-    Book,
+    Book {
+        /// Build even if the wiki is encrypted, leaving its facts readable in the output
+        #[arg(long = "allow-plaintext-output")]
+        allow_plaintext_output: bool,
+        /// Leave out the footer of tags, dates, source and id on each page,
+        /// and the pages per tag it links to
+        #[arg(long = "no-metadata")]
+        no_metadata: bool,
+        /// Leave out the page listing every fact A to Z
+        #[arg(long = "no-index")]
+        no_index: bool,
+        /// Leave out the page of statistics
+        #[arg(long = "no-stats")]
+        no_stats: bool,
+        /// Leave facts with this tag out of the book's search
+        #[arg(long = "nosearch-tag", value_name = "TAG", default_value = twk::wiki::DEFAULT_NOSEARCH_TAG)]
+        nosearch_tag: String,
+        /// Make a PDF with mdbook-pdf or mdbook-typst, whichever is on PATH
+        /// first; without either, join the pages into one Markdown file and
+        /// print the pandoc command that makes a PDF of it
+        #[arg(long = "pdf")]
+        pdf: bool,
+        /// Leave out the Atom feed of the newest facts
+        #[arg(long = "no-feed")]
+        no_feed: bool,
+        /// How many of the newest facts the Atom feed lists
+        #[arg(long = "feed-limit", value_name = "N", default_value_t = twk::feed::DEFAULT_FEED_LIMIT)]
+        feed_limit: usize,
+        /// Where the book is published, for the feed's links, instead of the
+        /// config's book_url; no feed is written without either
+        #[arg(long = "base-url", value_name = "URL")]
+        base_url: Option<String>,
+    },
     
-    /// Switch wiki context (creates if not exists)
+    /// Write the wiki's facts out, all of them or those changed lately
+    #[command(name = "export")]
+    Export {
+        /// What to write
+        #[arg(long = "format", value_enum, default_value_t = ExportFormat::Json)]
+        format: ExportFormat,
+        /// The file to write JSON to, stdout if not given, or the directory
+        /// to write Markdown to, which is required
+        #[arg(short = 'o', long = "out")]
+        out: Option<PathBuf>,
+        /// Only facts changed since then: an age like 3d or 2w, a date or
+        /// time, or `last` for since the last export of this format to the
+        /// same place
+        #[arg(long = "since")]
+        since: Option<String>,
+        /// Export even if the wiki is encrypted, leaving its facts readable in the output
+        #[arg(long = "allow-plaintext-output")]
+        allow_plaintext_output: bool,
+    },
+
+    /// Switch wiki context, asking before creating one that doesn't exist
     #[command(name = "switch")]
     Switch {
         /// Name of the wiki to switch to
@@ -50,166 +514,1703 @@ enum Commands {
         /// Initialize a local .wiki/ folder in the current directory
         #[arg(short = 'l', long = "local")]
         local: bool,
+        /// Create the wiki if it doesn't exist without asking
+        #[arg(long)]
+        create: bool,
+        /// Use the wiki whenever wk runs in this directory or under it, by
+        /// writing a .twk file here
+        #[arg(long)]
+        pin: bool,
     },
 
     /// Launch the TUI
     #[command(name = "tui")]
     Tui,
+
+    /// Add notes from another tool as facts. Notes imported before are
+    /// updated rather than added again, so it can be run more than once.
+    #[command(name = "import")]
+    Import {
+        /// File to import
+        file: PathBuf,
+        /// What the file holds
+        #[arg(long = "format", value_enum)]
+        format: ImportFormat,
+    },
+
+    /// Convert the current wiki to another storage backend
+    #[command(name = "migrate")]
+    Migrate {
+        /// Backend to convert to
+        #[arg(long = "to", value_enum)]
+        to: BackendArg,
+    },
+
+    /// Check the wiki for unreadable, misnamed and duplicate fact files
+    #[command(name = "doctor")]
+    Doctor {
+        /// Repair what can be repaired safely
+        #[arg(long = "fix")]
+        fix: bool,
+    },
+
+    /// Manage wikis themselves
+    #[command(name = "wiki", subcommand)]
+    Wiki(WikiCommand),
+
+    /// Rebuild the search index used by recall
+    #[command(name = "reindex")]
+    Reindex,
+
+    /// Rewrite the wiki's files with keys in a fixed order, so they diff
+    /// cleanly: id, name, tags, created, updated, then the rest alphabetically
+    #[command(name = "fmt")]
+    Fmt,
+
+    /// Show which wiki commands use, where it lives and why
+    #[command(name = "status")]
+    Status,
+
+    /// List the commands defined in the `[commands]` table of the global
+    /// config, like `todo = "c {args} todo"`
+    #[command(name = "aliases")]
+    Aliases,
+
+    /// Count the wiki's facts and tags and how many were added lately
+    #[command(name = "stats")]
+    Stats {
+        /// Chart how many facts were added over time instead
+        #[arg(long = "history")]
+        history: bool,
+        /// With --history, how long each bar of the chart covers
+        #[arg(long = "bucket", value_enum, default_value_t = StatsBucket::Week, requires = "history")]
+        bucket: StatsBucket,
+        /// With --history, only count facts with this tag or one nested under it
+        #[arg(long = "by-tag", requires = "history")]
+        by_tag: Option<String>,
+        /// With --history, print each bucket's start and count as JSON
+        #[arg(long = "json", requires = "history")]
+        json: bool,
+    },
+
+    /// Commit, pull and push the wiki's git repository (requires the `git`
+    /// feature), or reconcile it with another copy of the wiki with --dir
+    #[command(name = "sync")]
+    Sync {
+        /// Another copy of the wiki, e.g. kept by Syncthing, to exchange
+        /// changes with; --dry-run prints what would change in both
+        #[arg(long = "dir")]
+        dir: Option<PathBuf>,
+    },
+
+    /// Run commands read from stdin, one per line, against the wiki loaded
+    /// once, printing a line of JSON for each
+    #[command(name = "batch", after_help = "Commands, or JSON objects naming one in \"cmd\":\n  \
+                                            c {\"text\": \"...\", \"tags\": [...]}\n  \
+                                            tag <id> +add -remove\n  \
+                                            d <id> [--hard]\n  \
+                                            r <query>\n  \
+                                            get <id>")]
+    Batch {
+        /// Stop at the first command that fails
+        #[arg(long = "fail-fast")]
+        fail_fast: bool,
+    },
+
+    /// Act as an MCP server on stdin/stdout so AI assistants can use the wiki as memory
+    #[command(name = "mcp")]
+    Mcp,
+
+    /// Serve the wiki as a JSON API with a search page (requires the `server` feature)
+    #[command(name = "serve")]
+    Serve {
+        /// Port to listen on
+        #[arg(short = 'p', long = "port", default_value_t = 7171)]
+        port: u16,
+        /// Address to listen on; anything but localhost exposes the wiki to the network
+        #[arg(long = "bind", default_value = "127.0.0.1")]
+        bind: std::net::IpAddr,
+        /// Require this token in an `Authorization: Bearer` header
+        #[arg(long = "token")]
+        token: Option<String>,
+        /// Also answer requests sent to this host name, e.g. one the machine
+        /// is known by on the network; may be repeated
+        #[arg(long = "allow-host", value_name = "NAME")]
+        allow_host: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum WikiCommand {
+    /// List wikis with their locations and fact counts
+    #[command(name = "list", alias = "ls")]
+    List,
+
+    /// Rename a wiki
+    #[command(name = "rename", alias = "mv")]
+    Rename {
+        /// Current name
+        old: String,
+        /// New name
+        new: String,
+    },
+
+    /// Delete a wiki and all of its facts
+    #[command(name = "rm", alias = "remove")]
+    Rm {
+        /// Name of the wiki to delete
+        name: String,
+    },
+
+    /// Encrypt a wiki's facts with a passphrase
+    #[command(name = "encrypt")]
+    Encrypt {
+        /// Name of the wiki to encrypt
+        name: String,
+    },
+
+    /// Turn an encrypted wiki back into plaintext
+    #[command(name = "decrypt")]
+    Decrypt {
+        /// Name of the wiki to decrypt
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum TagsCommand {
+    /// Rewrite tags that are aliases in the wiki config, like `js` for
+    /// `javascript`, to the tag they stand for on every fact
+    #[command(name = "normalize")]
+    Normalize,
+}
+
+#[derive(Subcommand)]
+enum UsageCommand {
+    /// Forget how often and how lately every fact was used
+    #[command(name = "reset")]
+    Reset,
+}
+
+#[derive(Subcommand)]
+enum TrashCommand {
+    /// List deleted facts, oldest deletion first
+    #[command(name = "list", alias = "ls")]
+    List,
+
+    /// Bring a deleted fact back
+    #[command(name = "restore")]
+    Restore {
+        /// Id of the fact, as listed by `wk trash list`
+        id: uuid::Uuid,
+    },
+
+    /// Remove deleted facts for good
+    #[command(name = "empty")]
+    Empty {
+        /// Only those deleted longer ago than this, an age like 30d
+        #[arg(long = "older-than", value_parser = parse_since)]
+        older_than: Option<DateTime<Utc>>,
+    },
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum BackendArg {
+    /// One JSON file per fact
+    Files,
+    /// A single SQLite database (requires the `sqlite` feature)
+    Sqlite,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum ImportFormat {
+    /// TiddlyWiki tiddlers: a JSON export, or a single-file wiki's HTML
+    Tiddlers,
+}
+
+#[derive(Clone, Copy, PartialEq, clap::ValueEnum)]
+enum RecallFormat {
+    /// Facts with their tags
+    Text,
+    /// `<path>:1:1: <name> — <preview>` lines for an editor's quickfix list
+    Vimgrep,
+    /// Only absolute file paths, one per line
+    Paths,
+    /// Aligned name, preview, tag and age columns under a header
+    Table,
+    /// A JSON array of the facts, every field included
+    Json,
+}
+
+/// What `wk export` writes
+#[derive(Clone, Copy, PartialEq, clap::ValueEnum)]
+enum ExportFormat {
+    /// A JSON array of the facts, every field included
+    Json,
+    /// A Markdown file with frontmatter per fact, named by its id
+    Md,
+}
+
+/// What `wk ls` and `wk r` can order facts by
+#[derive(Clone, Copy, PartialEq, clap::ValueEnum)]
+enum SortBy {
+    /// Most recently changed first
+    Modified,
+    /// Most used first, counting recent uses for more; needs `track_usage`
+    /// in the config
+    Frecency,
+}
+
+/// How long each bar of `wk stats --history` covers
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum StatsBucket {
+    Day,
+    /// Monday to Sunday
+    Week,
+    Month,
+}
+
+impl StatsBucket {
+    fn bucket(self) -> Bucket {
+        match self {
+            StatsBucket::Day => Bucket::Day,
+            StatsBucket::Week => Bucket::Week,
+            StatsBucket::Month => Bucket::Month,
+        }
+    }
+}
+
+/// Which part of each fact `wk r` matches the query against
+#[derive(Clone, Copy, PartialEq, clap::ValueEnum)]
+enum RecallIn {
+    Name,
+    Data,
+    /// Each tag in full, so `lang` finds facts tagged lang/rust
+    Tags,
+    /// Where the fact came from, as given with `wk c --source`
+    Source,
+    /// Name, data and tags
+    All,
+}
+
+impl RecallIn {
+    fn fields(self) -> Fields {
+        match self {
+            RecallIn::Name => Fields::NAME,
+            RecallIn::Data => Fields::DATA,
+            RecallIn::Tags => Fields::TAGS,
+            RecallIn::Source => Fields::SOURCE,
+            RecallIn::All => Fields::ALL,
+        }
+    }
+}
+
+/// How `--case` matches case in queries
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum CaseArg {
+    Smart,
+    Sensitive,
+    Insensitive,
+}
+
+impl CaseArg {
+    fn case(self) -> Case {
+        match self {
+            CaseArg::Smart => Case::Smart,
+            CaseArg::Sensitive => Case::Sensitive,
+            CaseArg::Insensitive => Case::Insensitive,
+        }
+    }
+}
+
+/// The one place wiki errors are given exit statuses; see [`output::EXIT_FAILURE`]
+/// and the statuses after it
+impl output::Failure for WikiError {
+    fn exit_code(&self) -> i32 {
+        match self {
+            WikiError::NotFound(_) | WikiError::UnknownWiki(_) | WikiError::NoSnapshot { .. } | WikiError::NotTrashed(_) => {
+                output::EXIT_NOT_FOUND
+            }
+            WikiError::Conflict { .. } | WikiError::WikiExists(_) | WikiError::SnapshotExists { .. } => output::EXIT_CONFLICT,
+            // A lock another process held for too long
+            WikiError::Io(e) if e.kind() == std::io::ErrorKind::TimedOut => output::EXIT_CONFLICT,
+            WikiError::InvalidWikiName(_) | WikiError::InvalidPattern(_) | WikiError::DryRun(_) => output::EXIT_USAGE,
+            WikiError::TagsRequired { .. } | WikiError::Invalid(_) | WikiError::TooLarge { .. } => output::EXIT_USAGE,
+            WikiError::PartialCommit { source, .. } => source.exit_code(),
+            _ => output::EXIT_FAILURE,
+        }
+    }
 }
 
 fn main() {
-    let cli = Cli::parse();
+    let cli = Cli::parse_from(expand_custom_command(env::args_os().collect()));
+    output::init(cli.color);
+    output::set_level(match (cli.quiet, cli.verbose) {
+        (true, _) => Level::Quiet,
+        (_, true) => Level::Verbose,
+        _ => Level::Normal,
+    });
+    if let Some(path) = &cli.log_file
+        && let Err(e) = logging::init(path)
+    {
+        output::fail(format!("couldn't open the log file {}: {}", path.display(), e))
+    }
+    tracing::debug!(args = ?env::args().skip(1).collect::<Vec<_>>(), "wk started");
 
     // Set whether to use global directory
     set_use_global(cli.global);
+    set_data_dir(cli.data_dir);
+    set_readonly(cli.readonly);
+    set_no_hooks(cli.no_hooks);
+    set_no_default_tags(matches!(cli.command, Some(Commands::Commit { no_default_tags: true, .. })));
+    set_dry_run(cli.dry_run);
+    set_case(cli.case.map(CaseArg::case));
+    if cli.dry_run
+        && let Some(command) = cli.command.as_ref().and_then(Commands::unplannable)
+    {
+        output::fail_with(output::EXIT_USAGE, format!("`wk {}` can't be dry-run", command))
+    }
+    encryption::set_prompt(|path| {
+        rpassword::prompt_password(format!("Passphrase for {}: ", path.display())).map_err(|e| {
+            std::io::Error::new(
+                e.kind(),
+                format!("couldn't ask for the passphrase ({}); set {}", e, encryption::PASSPHRASE_VAR),
+            )
+        })
+    });
+
+    if let Some(Commands::HoldClipboard) = cli.command {
+        let mut text = String::new();
+        if std::io::Read::read_to_string(&mut std::io::stdin(), &mut text).is_err() || clipboard::hold(&text).is_err() {
+            std::process::exit(output::EXIT_FAILURE);
+        }
+        return;
+    }
+
+    // A first run in a terminal sets up a wiki rather than making `default`
+    if cli.command.is_none()
+        && !cli.no_wizard
+        && std::io::stdin().is_terminal()
+        && wikis::discover(cli.global).is_ok_and(|found| found.is_empty())
+        && wizard::run(cli.global)
+    {
+        return;
+    }
 
     // Get or set default wiki context
-    let current_wiki = env::var("TWK_WIKI").unwrap_or_else(|_| "default".to_string());
+    let resolved_name = wikis::active_name();
+    let current_wiki = resolved_name.name.clone();
     
-    // Initialize wiki context if no switch command
-    if !matches!(cli.command, Some(Commands::Switch { .. })) {
+    // Initialize wiki context unless the command picks or manages wikis itself
+    if !matches!(cli.command, Some(Commands::Switch { .. } | Commands::Wiki(_) | Commands::Aliases)) {
+        let started = std::time::Instant::now();
         if let Err(e) = switch(current_wiki.clone()) {
-            eprintln!("{} {}", "Error:".red().bold(), e);
-            std::process::exit(1);
+            output::fail(e)
+        }
+        if let Ok(status) = status() {
+            detail!(
+                "{} ({}) · {} · {}",
+                status.name,
+                resolved_name.source,
+                status.resolved.source.location(),
+                status.resolved.path.display()
+            );
+            detail!(
+                "loaded {} facts from {} entries in {:.1?}",
+                status.facts,
+                status.facts + status.warnings.len(),
+                started.elapsed()
+            );
         }
     }
 
+    let warnings = load_warnings();
+    for warning in &warnings {
+        detail!("couldn't load {}: {}", warning.path.display(), warning.error);
+    }
+    if !warnings.is_empty() && !matches!(cli.command, Some(Commands::Doctor { .. } | Commands::Status | Commands::Tui)) {
+        warning!("{} entries could not be loaded; run `wk doctor`", warnings.len());
+    }
+    let copies = conflict_copies();
+    if !copies.is_empty() && !matches!(cli.command, Some(Commands::Doctor { .. } | Commands::Status | Commands::Tui)) {
+        warning!("{} sync conflict copies found; run `wk doctor --fix` to merge them", copies.len());
+    }
+
+    // Sync prints its own plan
+    let print_plan = cli.dry_run && !matches!(cli.command, Some(Commands::Sync { .. }));
     match cli.command {
-        Some(Commands::Commit { fact, tag }) => {
-            let tags = tag.map(|t| vec![t]).unwrap_or_default();
-            
-            match commit(fact.clone(), tags.clone()) {
-                Ok(_) => {
+        Some(Commands::Commit { fact, tags, clip, name, auto_title, edit, suggest, no_dup_check, source, no_expand, .. }) => {
+            let explicit = name;
+            let expand = |text: String| -> String {
+                let snippets = if no_expand { Default::default() } else { snippets().unwrap_or_default() };
+                let (text, fired) = expand_snippets(&text, &snippets);
+                if !fired.is_empty() {
+                    detail!("expanded {}", fired.join(", "));
+                }
+                text
+            };
+            let (name, data, tags) = if clip {
+                match clipboard::read() {
+                    Ok(text) if !text.trim().is_empty() => {
+                        let text = expand(text.trim_end().to_string());
+                        let name = text.lines().find(|l| !l.trim().is_empty()).unwrap_or_default().trim().to_string();
+                        (name, text, fact.into_iter().chain(tags).collect())
+                    }
+                    Ok(_) => output::fail("the clipboard is empty"),
+                    Err(e) => output::fail(e),
+                }
+            } else if fact.as_deref() == Some("-") {
+                let mut text = String::new();
+                if let Err(e) = std::io::Read::read_to_string(&mut std::io::stdin(), &mut text) {
+                    output::fail(format!("couldn't read stdin: {}", e))
+                }
+                let text = expand(text.trim_end().to_string());
+                (text.clone(), text, tags)
+            } else {
+                let fact = expand(fact.unwrap_or_default());
+                (fact.clone(), fact, tags)
+            };
+            let name = match explicit {
+                Some(name) => name,
+                None if auto_title => derive_title(&data),
+                None => name,
+            };
+            let (name, data, tags, source) =
+                if edit { edit_before_commit(name, data, tags, source) } else { (name, data, tags, source) };
+            let tags = if suggest { pick_suggested_tags(&data, tags) } else { tags };
+            let (name, data) = if cli.no_scan { (name, data) } else { check_secrets(name, data) };
+
+            if !no_dup_check
+                && let Some((similar, similarity)) = find_similar(&data).ok().and_then(|s| s.into_iter().next())
+            {
+                warning!(
+                    "similar fact exists: {} ({}, {:.0}% similar)",
+                    similar.name.lines().next().unwrap_or_default(),
+                    similar.id.to_string().bright_black(),
+                    similarity * 100.0
+                );
+                // Scripts get the fact committed; only people are asked
+                if std::io::stdin().is_terminal() {
+                    let answer = output::ask(format_args!("[c]ommit anyway, [a]ppend to it, or a[b]ort?"));
+                    match answer.to_lowercase().as_str() {
+                        "c" | "commit" => {}
+                        "a" | "append" => {
+                            match append(similar.id, &data, &tags) {
+                                Ok(info) => say!("{} appended to {}", "✓".green().bold(), info.id.to_string().bright_black()),
+                                Err(e) => output::fail(e),
+                            }
+                            return;
+                        }
+                        _ => {
+                            say!("{}", "Aborted.".yellow());
+                            std::process::exit(output::EXIT_FAILURE);
+                        }
+                    }
+                }
+            }
+
+            let bytes = data.len();
+            match commit_named(name, data, tags.clone(), source) {
+                Ok(id) => {
+                    if let Ok(large) = large_fact_bytes()
+                        && bytes > large
+                    {
+                        warning!(
+                            "this fact is {}, over {}; recall will only match its name and tags unless given --include-large",
+                            format_size(bytes),
+                            format_size(large)
+                        );
+                    }
+                    // With the wiki's default tags, if any
+                    let tags = get(id).map(|fact| fact.tags).unwrap_or(tags);
                     if !tags.is_empty() {
-                        println!("{} {}", "✓".green().bold(), 
+                        say!("{} {}", "✓".green().bold(), 
                             tags.iter()
                                 .map(|t| format!("[{}]", t.yellow()))
                                 .collect::<Vec<_>>()
                                 .join(" "));
                     } else {
-                        println!("{}", "✓".green().bold());
+                        say!("{}", "✓".green().bold());
                     }
                 }
-                Err(e) => {
-                    eprintln!("{} {}", "Error:".red().bold(), e);
-                    std::process::exit(1);
-                }
+                Err(e) => output::fail(e),
             }
         }
-        
-        Some(Commands::Recall { query, show_id }) => {
-            match query {
-                Some(q) => {
-                    // Check if it's a tag query (no spaces, looks like a tag)
-                    let results = if q.starts_with('[') && q.ends_with(']') {
-                        // Tag query: [tag]
-                        let tag = q.trim_matches(|c| c == '[' || c == ']');
-                        recall_by_tag(tag)
-                    } else {
-                        // Regular text query
-                        recall(&q, None)
-                    };
-                    
-                    match results {
-                        Ok(facts) => {
-                            if facts.is_empty() {
-                                println!("{}", "No matching facts found.".yellow());
-                            } else {
-                                for fact in facts.iter() {
-                                    // Simple, clean output
-                                    print!("{}", fact.data.white());
-                                    
-                                    if !fact.tags.is_empty() {
-                                        print!(" {}", 
-                                            fact.tags.iter()
-                                                .map(|t| format!("[{}]", t.bright_black()))
-                                                .collect::<Vec<_>>()
-                                                .join(" "));
-                                    }
-                                    
-                                    if show_id {
-                                        print!(" {}", format!("({})", fact.id.to_string().bright_black()));
-                                    }
-                                    
-                                    println!();
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!("{} {}", "Error:".red().bold(), e);
-                            std::process::exit(1);
+
+        Some(Commands::Scan) => {
+            let hits = scan_secrets().unwrap_or_else(|e| output::fail(e));
+            for hit in &hits {
+                println!("{}:{}: {}", hit.id, hit.line, hit.pattern);
+            }
+            if hits.is_empty() {
+                say!("{}", "No secrets found.".green());
+                return;
+            }
+            let mut facts: Vec<_> = hits.iter().map(|hit| hit.id).collect();
+            facts.dedup();
+            say!("{}", format!("{} possible secrets in {} facts", hits.len(), facts.len()).yellow());
+            std::process::exit(output::EXIT_FAILURE);
+        }
+
+        Some(Commands::Replace { pattern, replacement, fixed, ignore_case, names, write, all }) => {
+            let pattern = if fixed { regex::escape(&pattern) } else { pattern };
+            let re = regex::RegexBuilder::new(&pattern)
+                .case_insensitive(ignore_case)
+                .build()
+                .unwrap_or_else(|e| output::fail(WikiError::InvalidPattern(e)));
+            let mut opts = ReplaceOptions { names, ..Default::default() };
+            let planned = replace(&re, &replacement, &opts).unwrap_or_else(|e| output::fail(e));
+            if planned.is_empty() {
+                say!("{}", "No matching facts found.".yellow());
+                return;
+            }
+            if !write {
+                for report in &planned {
+                    print_replace_report(report);
+                }
+                say!("{}", format!("{} facts would change; rerun with --write to apply", planned.len()).bright_black());
+                return;
+            }
+
+            // Scripts change every fact; only people are asked about each
+            let ask = !all && std::io::stdin().is_terminal();
+            let mut chosen = Vec::new();
+            for (i, report) in planned.iter().enumerate() {
+                if !ask {
+                    chosen.push(report.id);
+                    continue;
+                }
+                print_replace_report(report);
+                let answer = output::ask(format_args!("Change this fact? [y]es, [n]o, [a]ll the rest, [q]uit"));
+                match answer.to_lowercase().as_str() {
+                    "y" | "yes" => chosen.push(report.id),
+                    "a" | "all" => {
+                        chosen.extend(planned[i..].iter().map(|r| r.id));
+                        break;
+                    }
+                    "q" | "quit" => break,
+                    _ => {}
+                }
+            }
+            if chosen.is_empty() {
+                say!("{}", "Nothing changed.".yellow());
+                return;
+            }
+
+            opts.write = true;
+            opts.only = Some(chosen);
+            match replace(&re, &replacement, &opts) {
+                Ok(reports) => {
+                    for report in &reports {
+                        if !ask {
+                            print_replace_report(report);
                         }
+                        let label = report.snapshot.clone().unwrap_or_default();
+                        say!(
+                            "{} {} {}",
+                            "✓".green().bold(),
+                            report.new_name.as_ref().unwrap_or(&report.name).lines().next().unwrap_or_default(),
+                            format!("undo with `wk restore {} --snapshot {}`", report.id, label).bright_black()
+                        );
                     }
+                    say!("{} replaced in {} facts", "✓".green().bold(), reports.len());
                 }
-                None => {
-                    // No query provided - could show all or enter TUI mode
-                    println!("{}", "Usage: wk r <query> or wk r [tag]".yellow());
-                    println!("  {} Search for facts containing query", "wk r \"rust tips\"".bright_black());
-                    println!("  {} Recall all facts with tag", "wk r [programming]".bright_black());
+                Err(e) => output::fail(e),
+            }
+        }
+
+        Some(Commands::WatchClipboard { tags, max_bytes }) => watch_clipboard(&tags, max_bytes),
+
+        Some(Commands::Grep { pattern, fixed, ignore_case, count, names_only }) => {
+            let hits = match grep(&pattern, GrepOptions { fixed, ignore_case }) {
+                Ok(hits) => hits,
+                Err(e) => {
+                    eprintln_colored!("{} {}", "Error:".red().bold(), e);
+                    std::process::exit(2);
+                }
+            };
+            let name = |hit: &twk::GrepHit| hit.name.lines().next().unwrap_or_default().to_string();
+
+            // Hits come grouped by fact, so runs of one id are one fact
+            let mut facts: Vec<(String, usize)> = Vec::new();
+            let mut last = None;
+            for hit in &hits {
+                if last != Some(hit.id) {
+                    facts.push((name(hit), 0));
+                    last = Some(hit.id);
+                }
+                if let Some((_, n)) = facts.last_mut() {
+                    *n += 1;
+                }
+            }
+
+            if count {
+                for (name, n) in &facts {
+                    println!("{}:{}", name, n);
                 }
+            } else if names_only {
+                for (name, _) in &facts {
+                    println!("{}", name);
+                }
+            } else {
+                for hit in &hits {
+                    println!("{}:{}: {}", name(hit), hit.line, hit.text);
+                }
+            }
+            if hits.is_empty() {
+                std::process::exit(1);
             }
         }
-        
-        Some(Commands::Book) => {
-            match book() {
-                Ok(output_path) => {
-                    println!("{}", "✓ Static site generated".green().bold());
-                    println!("  {} {}", "Output:".cyan(), output_path.display().to_string().white());
-                    println!();
-                    println!("{}", "To view the book:".bright_black());
-                    println!("  {}", format!("mdbook serve {}", output_path.parent().unwrap().display()).yellow());
+
+        Some(Commands::Show { id, copy, json }) => match get(id) {
+            Ok(fact) if copy => match clipboard::write(&fact.data) {
+                Ok(()) => {
+                    record_use(&[id]).ok();
+                    say!("{} {}", "✓ Copied".green().bold(), fact.name.lines().next().unwrap_or_default());
                 }
-                Err(e) => {
-                    eprintln!("{} {}", "Error:".red().bold(), e);
-                    std::process::exit(1);
+                Err(e) => output::fail(e),
+            },
+            Ok(fact) if json => {
+                record_use(&[id]).ok();
+                print_json(&fact.exported());
+            }
+            Ok(fact) => {
+                record_use(&[id]).ok();
+                if fact.name != fact.data {
+                    println!("{}", fact.name.white().bold());
+                }
+                println!("{}", fact.data.white());
+                if !fact.tags.is_empty() {
+                    println!(
+                        "{}",
+                        fact.tags.iter().map(|t| format!("[{}]", t.bright_black())).collect::<Vec<_>>().join(" ")
+                    );
+                }
+                if let Some(source) = &fact.source {
+                    println!("{} {}", "Source:".cyan(), source.white());
                 }
             }
+            Err(e) => output::fail(e),
+        },
+
+        Some(Commands::HoldClipboard) => {}
+
+        Some(Commands::Open { id, raw, book, allow_plaintext_output }) => {
+            // Refuse before the editor opens rather than lose the edits afterwards
+            if !book
+                && let Ok(status) = status()
+                && status.readonly
+            {
+                output::fail(WikiError::ReadOnly(status.name))
+            }
+            if book {
+                open_book_page(id, allow_plaintext_output)
+            } else if raw {
+                open_raw(id)
+            } else {
+                open_in_editor(id)
+            }
         }
-        
-        Some(Commands::Switch { wikiname, local }) => {
-            if local {
-                // Create local .wiki/ folder
-                if let Err(e) = std::fs::create_dir_all(".wiki") {
-                    eprintln!("{} Failed to create .wiki/ folder: {}", "Error:".red().bold(), e);
-                    std::process::exit(1);
+
+        Some(Commands::Snapshot { id, label }) => match snapshot(id, label.as_deref()) {
+            Ok(snapshot) => say!("{} snapshot {}", "✓".green().bold(), snapshot.label.yellow()),
+            Err(e) => output::fail(e),
+        },
+
+        Some(Commands::Snapshots { id }) => match snapshots(id) {
+            Ok(snapshots) if snapshots.is_empty() => say!("{}", "No snapshots of this fact.".yellow()),
+            Ok(snapshots) => {
+                for snapshot in snapshots {
+                    println!(
+                        "{} {} {}",
+                        snapshot.taken.format("%Y-%m-%d %H:%M").to_string().bright_black(),
+                        snapshot.label.yellow(),
+                        snapshot.fact.name.lines().next().unwrap_or_default().white()
+                    );
                 }
-                println!("{}", "✓ Created local .wiki/ folder".green().bold());
-                println!("  {} {}", "Path:".cyan(), ".wiki/".white());
-                println!();
             }
-            
-            match switch(wikiname.clone()) {
+            Err(e) => output::fail(e),
+        },
+
+        Some(Commands::Restore { id, snapshot }) => match restore(id, &snapshot) {
+            Ok(fact) => {
+                say!("{} restored {} from {}", "✓".green().bold(), fact.name.lines().next().unwrap_or_default(), snapshot.yellow());
+                say!("  {}", "The previous content was snapshotted first; see `wk snapshots`".bright_black());
+            }
+            Err(e) => output::fail(e),
+        },
+
+        Some(Commands::Diff { id, snapshot, wiki, file, stat }) => diff_fact(id, snapshot, wiki, file, stat),
+
+        Some(Commands::Delete { id, hard }) => {
+            let fact = get(id).unwrap_or_else(|e| output::fail(e));
+            let name = fact.name.lines().next().unwrap_or_default();
+            if hard {
+                confirm_purge(&format!("Delete '{}' and its snapshots for good?", name));
+            }
+            match delete(id, hard) {
+                Ok(_) if hard => say!("{} deleted {}", "✓".green().bold(), name),
                 Ok(_) => {
-                    println!("{}", "✓ Switched wiki context".green().bold());
-                    println!("  {} {}", "Wiki:".cyan(), wikiname.white());
-                    if !local {
-                        println!();
-                        println!("{}", "To persist this change, set the environment variable:".bright_black());
-                        println!("  {}", format!("export TWK_WIKI={}", wikiname).yellow());
-                    }
+                    say!("{} moved {} to the trash", "✓".green().bold(), name);
+                    say!("  {}", format!("Bring it back with `wk trash restore {}`", id).bright_black());
                 }
-                Err(e) => {
-                    eprintln!("{} {}", "Error:".red().bold(), e);
-                    std::process::exit(1);
+                Err(e) => output::fail(e),
+            }
+        }
+
+        Some(Commands::Trash(TrashCommand::List)) => match trashed() {
+            Ok(trashed) if trashed.is_empty() => say!("{}", "The trash is empty.".yellow()),
+            Ok(trashed) => {
+                let mut table = table::Table::new(&["NAME", "DELETED", "ID"]).flex(0);
+                for t in trashed {
+                    table.row(vec![
+                        table::Cell::new(t.fact.name.as_str()).color(Color::White),
+                        table::Cell::new(table::age(t.deleted)).color(Color::Cyan),
+                        table::Cell::new(t.fact.id.to_string()).color(Color::BrightBlack),
+                    ]);
                 }
+                table.print();
+            }
+            Err(e) => output::fail(e),
+        },
+
+        Some(Commands::Trash(TrashCommand::Restore { id })) => match untrash(id) {
+            Ok(fact) => say!("{} restored {}", "✓".green().bold(), fact.name.lines().next().unwrap_or_default()),
+            Err(e) => output::fail(e),
+        },
+
+        Some(Commands::Trash(TrashCommand::Empty { older_than })) => {
+            confirm_purge(match older_than {
+                Some(_) => "Purge the older facts in the trash for good?",
+                None => "Purge every fact in the trash for good?",
+            });
+            match empty_trash(older_than) {
+                Ok(0) => say!("{}", "Nothing to purge.".yellow()),
+                Ok(purged) => say!("{} purged {} facts from the trash", "✓".green().bold(), purged),
+                Err(e) => output::fail(e),
             }
         }
 
-        Some(Commands::Tui) => {
-            if let Err(e) = tui::run(current_wiki, cli.global) {
-                eprintln!("{} {}", "Error:".red().bold(), e);
-                std::process::exit(1);
+        Some(Commands::Tag { tags, query, tag, add, remove, limit }) => {
+            let add: Vec<String> = tags.iter().map(|t| t.strip_prefix('+').unwrap_or(t).to_string()).chain(add).collect();
+            if add.is_empty() && remove.is_empty() {
+                output::fail_with(output::EXIT_USAGE, "give tags to add, as +tag or --add, or to remove with --remove");
+            }
+            let selection = twk::tags::Selection { query, tag, limit };
+            match bulk_retag(&selection, &add, &remove) {
+                Ok(picked) if picked.is_empty() => say!("{}", "No facts matched, so none were tagged.".yellow()),
+                Ok(picked) => {
+                    let mut changed = 0;
+                    for (before, after) in &picked {
+                        let name = after.name.lines().next().unwrap_or_default();
+                        if before.tags == after.tags {
+                            println!("{} {}", name.white(), "(unchanged)".bright_black());
+                            continue;
+                        }
+                        changed += 1;
+                        let before = if before.tags.is_empty() { "untagged".to_string() } else { before.tags.join(" ") };
+                        println!(
+                            "{} {} {} {}",
+                            name.white(),
+                            before.bright_black(),
+                            "→".bright_black(),
+                            after.tags.join(" ").cyan()
+                        );
+                    }
+                    say!("{} retagged {} of {} matching facts", "✓".green().bold(), changed, picked.len());
+                }
+                Err(e) => output::fail(e),
             }
         }
+
+        Some(Commands::Tags { action: Some(TagsCommand::Normalize), .. }) => match normalize_tags() {
+            Ok(changed) if changed.is_empty() => say!("{}", "Every tag is already canonical.".yellow()),
+            Ok(changed) => {
+                for (before, after) in &changed {
+                    println!(
+                        "{} {} {} {}",
+                        after.name.lines().next().unwrap_or_default().white(),
+                        before.tags.join(" ").bright_black(),
+                        "→".bright_black(),
+                        after.tags.join(" ").cyan()
+                    );
+                }
+                say!("{} retagged {} facts", "✓".green().bold(), changed.len());
+            }
+            Err(e) => output::fail(e),
+        },
+
+        Some(Commands::Tags { tree: false, .. }) => match tags() {
+            Ok(tags) if tags.is_empty() => say!("{}", "No tags yet.".yellow()),
+            Ok(tags) => {
+                // Aliases still carried by some facts, under the tag they stand for
+                let aliases = tag_aliases().unwrap_or_default();
+                let mut canonical: std::collections::BTreeMap<String, (usize, Vec<(String, usize)>)> = Default::default();
+                for (tag, count) in tags {
+                    let target = twk::tags::canonical(&aliases, &tag);
+                    let entry = canonical.entry(target.clone()).or_default();
+                    if target == tag {
+                        entry.0 = count;
+                    } else {
+                        entry.1.push((tag, count));
+                    }
+                }
+
+                let mut table = table::Table::new(&["TAG", "FACTS"]).flex(0);
+                for (tag, (count, aliased)) in canonical {
+                    table.row(vec![table::Cell::new(tag).color(Color::White), table::Cell::new(count.to_string()).color(Color::BrightBlack)]);
+                    for (alias, count) in aliased {
+                        table.row(vec![
+                            table::Cell::new(format!("  {} (alias)", alias)).color(Color::BrightBlack),
+                            table::Cell::new(count.to_string()).color(Color::BrightBlack),
+                        ]);
+                    }
+                }
+                table.print();
+            }
+            Err(e) => output::fail(e),
+        },
+
+        Some(Commands::Tags { tree: true, .. }) => match tag_tree() {
+            Ok(tree) if tree.is_empty() => say!("{}", "No tags yet.".yellow()),
+            Ok(tree) => {
+                for (depth, node) in TagNode::walk(&tree) {
+                    println!("{}{} {}", "  ".repeat(depth), node.name.white(), node.total.to_string().bright_black());
+                }
+            }
+            Err(e) => output::fail(e),
+        },
         
-        None => {
-            // No command - could enter TUI mode in the future
-            println!("{}", "TiddlyWiki Knowledge Manager".bright_cyan().bold());
-            println!();
-            println!("{}", "Usage:".white().bold());
-            println!("  {} {}      Commit a fact to memory", "wk c".yellow(), "<fact> [tag]".bright_black());
-            println!("  {} {}      Recall facts", "wk r".yellow(), "<query>".bright_black());
-            println!("  {} {}         Recall facts by tag", "wk r".yellow(), "[tag]".bright_black());
+        Some(Commands::Recall { query, pick, pick_id, since, until, .. }) if pick || pick_id => {
+            let window = TimeWindow { since, until };
+            // A [tag] query picks among that tag's facts, anything else is typed into the picker
+            let (candidates, input) = match query {
+                Some(q) if q.starts_with('[') && q.ends_with(']') => {
+                    (recall_by_tag(q.trim_matches(|c| c == '[' || c == ']')), String::new())
+                }
+                q => (twk::all().map_err(|e| e.to_string()), q.unwrap_or_default()),
+            };
+            let mut candidates = candidates.unwrap_or_else(|e| output::fail(e));
+            candidates.retain(|info| window.contains(info));
+            if !std::io::stderr().is_terminal() {
+                output::fail("--pick needs a terminal on stderr to draw on");
+            }
+            let picked = picker::pick(&candidates, &input, &match_config().unwrap_or_default());
+            if let Ok(Some(i)) = picked {
+                record_access(&[candidates[i].id]).ok();
+                record_use(&[candidates[i].id]).ok();
+            }
+            match picked {
+                Ok(Some(i)) if pick_id => println!("{}", candidates[i].id),
+                Ok(Some(i)) => println!("{}", candidates[i].data),
+                Ok(None) => std::process::exit(130),
+                Err(e) => output::fail(e),
+            }
+        }
+
+        Some(Commands::Recall { query, show_id, exact, limit, search_in, since, until, format, materialize, sort, no_pager, detailed, all, include_large, .. }) => {
+            let window = TimeWindow { since, until };
+            if window.since.is_some()
+                && let Ok(undated) = undated()
+                && undated > 0
+            {
+                detail!("Note: {} facts have no timestamps and count as older than --since", undated);
+            }
+
+            let fields = search_in.iter().fold(Fields::NONE, |fields, part| fields | part.fields());
+            if detailed {
+                if format != RecallFormat::Json {
+                    output::fail_with(output::EXIT_USAGE, "--detailed needs --format json");
+                }
+                let tag_query = |q: &String| q.starts_with('[') && q.ends_with(']');
+                let Some(query) = query.filter(|q| !exact && !tag_query(q)) else {
+                    output::fail_with(output::EXIT_USAGE, "--detailed needs a query to fuzzy match");
+                };
+                let opts = RecallOptions { tag: None, fields, window, indices: true, include_large };
+                match all {
+                    true => match wikis::recall_everywhere(&query, limit, opts, cli.global) {
+                        Ok(hits) => print_json(&hits),
+                        Err(e) => output::fail(e),
+                    },
+                    false => match recall_detailed(&query, limit, opts) {
+                        Ok(hits) => {
+                            let ids: Vec<_> = hits.iter().map(|hit| hit.info.id).collect();
+                            record_access(&ids).ok();
+                            record_use(&ids[..ids.len().min(RECALL_USES)]).ok();
+                            print_json(&hits);
+                        }
+                        Err(e) => output::fail(e),
+                    },
+                }
+                return;
+            }
+            let started = std::time::Instant::now();
+            let searching = query.is_some();
+            // Table previews show the line that matched rather than the first
+            let match_config = match_config().unwrap_or_default();
+            let matcher = query
+                .as_deref()
+                .filter(|q| !(q.starts_with('[') && q.ends_with(']')) && fields.data)
+                .map(|q| LineMatcher::fuzzy_with(q, &match_config));
+            // Suggestions are for people reading, not for scripts
+            let suggest_for = query
+                .clone()
+                .filter(|q| !(q.starts_with('[') && q.ends_with(']')))
+                .filter(|_| matches!(format, RecallFormat::Text | RecallFormat::Table) && output::level() != Level::Quiet);
+            let results = match query {
+                // Tag query: [tag]
+                Some(q) if q.starts_with('[') && q.ends_with(']') => {
+                    recall_by_tag_within(q.trim_matches(|c| c == '[' || c == ']'), window)
+                }
+                Some(q) if exact => recall_exact(&q, None, fields).map(|facts| within(facts, window)),
+                // Only the best results need to be copied out of the wiki
+                Some(q) if include_large => {
+                    let opts = RecallOptions { fields, window, include_large, ..Default::default() };
+                    recall_detailed(&q, limit, opts)
+                        .map(|hits| hits.into_iter().map(|hit| hit.info).collect())
+                        .map_err(|e| e.to_string())
+                }
+                Some(q) => recall_within(&q, None, fields, window, limit),
+                None if window.is_bounded() => twk::all().map_err(|e| e.to_string()).map(|facts| {
+                    let mut facts = within(facts, window);
+                    facts.sort_by_key(|info| std::cmp::Reverse(info.updated.or(info.created)));
+                    facts
+                }),
+                None => {
+                    eprintln_colored!("{}", "Usage: wk r <query> or wk r [tag]".yellow());
+                    eprintln_colored!("  {} Search for facts containing query", "wk r \"rust tips\"".bright_black());
+                    eprintln_colored!("  {} Recall all facts with tag", "wk r [programming]".bright_black());
+                    eprintln_colored!("  {} Recall all facts with tags under lang/", "wk r [lang]".bright_black());
+                    eprintln_colored!("  {} List facts changed in the last week", "wk r --since 1w".bright_black());
+                    std::process::exit(output::EXIT_USAGE);
+                }
+            };
+            let results = results.map(|mut facts| {
+                facts.truncate(limit.unwrap_or(usize::MAX));
+                facts
+            });
+            if let Ok(facts) = &results {
+                let searched = status().map(|status| status.facts).unwrap_or_default();
+                detail!("recalled {} of {} facts in {:.1?}", facts.len(), searched, started.elapsed());
+            }
+
+            match results {
+                Ok(mut facts) => {
+                    record_access(&facts.iter().map(|info| info.id).collect::<Vec<_>>()).ok();
+                    // Only the best matches count as used, not a listing by date
+                    if searching {
+                        record_use(&facts.iter().take(RECALL_USES).map(|info| info.id).collect::<Vec<_>>()).ok();
+                    }
+                    if let Some(sort) = sort {
+                        sort_facts(&mut facts, sort);
+                    }
+                    print_facts(&current_wiki, &facts, format, show_id, materialize, !no_pager, matcher.as_ref());
+                    if facts.is_empty()
+                        && let Some(query) = suggest_for
+                    {
+                        print_suggestions(&query, fields);
+                    }
+                }
+                Err(e) => output::fail(e),
+            }
+        }
+
+        Some(Commands::Ls { sort, limit, show_id, format, materialize, no_pager, large }) => {
+            let facts = match large {
+                Some(threshold) => {
+                    let threshold = threshold.map_or_else(large_fact_bytes, Ok).unwrap_or_else(|e| output::fail(e));
+                    let facts = large_facts(threshold);
+                    if let Ok(facts) = &facts {
+                        detail!("{} facts over {}", facts.len(), format_size(threshold));
+                    }
+                    facts
+                }
+                None => twk::all(),
+            };
+            match facts {
+                Ok(mut facts) => {
+                    if let Some(sort) = sort {
+                        sort_facts(&mut facts, sort);
+                    }
+                    facts.truncate(limit.unwrap_or(usize::MAX));
+                    print_facts(&current_wiki, &facts, format, show_id, materialize, !no_pager, None);
+                }
+                Err(e) => output::fail(e),
+            }
+        }
+
+        Some(Commands::Usage(UsageCommand::Reset)) => match reset_usage() {
+            Ok(true) => say!("{}", "✓ Usage counts cleared".green().bold()),
+            Ok(false) => say!("No usage counted yet"),
+            Err(e) => output::fail(e),
+        },
+
+        Some(Commands::Recent { n, accessed, show_id, format, materialize, no_pager }) => {
+            let facts = if accessed {
+                recently_accessed(n).map(|looks| looks.into_iter().map(|(_, info)| info).collect())
+            } else {
+                recent(n)
+            };
+            match facts {
+                Ok(facts) => print_facts(&current_wiki, &facts, format, show_id, materialize, !no_pager, None),
+                Err(e) => output::fail(e),
+            }
+        }
+        
+        Some(Commands::Export { format, out, since, allow_plaintext_output }) => {
+            if format == ExportFormat::Md && out.is_none() {
+                output::fail_with(output::EXIT_USAGE, "--format md needs --out <DIR>");
+            }
+            let name = match format {
+                ExportFormat::Json => "json",
+                ExportFormat::Md => "md",
+            };
+            let destination = twk::export::destination(name, out.as_deref());
+            let since = match since.as_deref() {
+                Some(twk::export::SINCE_LAST) => last_export(&destination).unwrap_or_else(|e| output::fail(e)),
+                Some(when) => Some(parse_since(when).unwrap_or_else(|e| output::fail_with(output::EXIT_USAGE, e))),
+                None => None,
+            };
+            // Anything changed while exporting is taken in again next time
+            let started = Utc::now();
+            let facts = exportable(since, allow_plaintext_output).unwrap_or_else(|e| fail_output(e));
+            let json = || twk::export::to_json(&facts).unwrap_or_else(|e| output::fail(e.to_string()));
+            let written = match (format, &out) {
+                (ExportFormat::Md, Some(dir)) => twk::export::to_markdown(dir, &facts, bar::progress("Exporting").as_mut()).map(|paths| paths.len()),
+                (_, Some(path)) => twk::export::write_if_changed(path, format!("{}\n", json()).as_bytes()).map(usize::from),
+                (_, None) => {
+                    println!("{}", json());
+                    Ok(facts.len())
+                }
+            };
+            let written = written.unwrap_or_else(|e| output::fail(e));
+            if let Err(e) = record_export(&destination, started) {
+                warning!("couldn't note the export for --since last: {}", e);
+            }
+            match format {
+                ExportFormat::Md => say!("{} exported {} facts, {} of them changed", "✓".green().bold(), facts.len(), written),
+                ExportFormat::Json => say!("{} exported {} facts", "✓".green().bold(), facts.len()),
+            }
+        }
+
+        Some(Commands::Book { allow_plaintext_output, no_metadata, no_index, no_stats, nosearch_tag, pdf, no_feed, feed_limit, base_url }) => {
+            let search = BookSearch { exclude_tag: nosearch_tag, ..Default::default() };
+            let options = BookOptions {
+                metadata_footer: !no_metadata,
+                search,
+                index_page: !no_index,
+                stats_page: !no_stats,
+                feed: !no_feed,
+                feed_limit,
+                base_url,
+            };
+            if pdf {
+                let backend = twk::pdf::detect(env::var_os("PATH").as_deref());
+                match book_pdf(allow_plaintext_output, &options, backend, bar::progress("Writing pages").as_mut()) {
+                    Ok(PdfOutput::Built { backend, path }) => {
+                        say!("{}", format!("✓ PDF rendered with {}", backend.program()).green().bold());
+                        say!("  {} {}", "Output:".cyan(), path.display().to_string().white());
+                    }
+                    Ok(PdfOutput::Markdown(path)) => {
+                        say!("{}", "✓ Book joined into one Markdown file".green().bold());
+                        say!("  {} {}", "Output:".cyan(), path.display().to_string().white());
+                        say!();
+                        say!("{}", "Neither mdbook-pdf nor mdbook-typst is on PATH; to make a PDF:".bright_black());
+                        say!("  {}", twk::pdf::pandoc_command(&path).yellow());
+                    }
+                    Err(e) => fail_output(e),
+                }
+                return;
+            }
+            match book(allow_plaintext_output, &options, bar::progress("Writing pages").as_mut()) {
+                Ok(output_path) => {
+                    say!("{}", "✓ Static site generated".green().bold());
+                    say!("  {} {}", "Output:".cyan(), output_path.display().to_string().white());
+                    match output_path.join(twk::feed::FEED_FILE) {
+                        feed if feed.exists() => say!("  {} {}", "Feed:".cyan(), feed.display().to_string().white()),
+                        _ if !no_feed => detail!("no Atom feed: set book_url in the config or pass --base-url"),
+                        _ => {}
+                    }
+                    say!();
+                    say!("{}", "To view the book:".bright_black());
+                    say!("  {}", format!("mdbook serve {}", output_path.parent().unwrap().display()).yellow());
+                }
+                Err(e) => fail_output(e),
+            }
+        }
+        
+        Some(Commands::Switch { mut wikiname, local, create, pin }) => {
+            if local {
+                // Create local .wiki/ folder
+                if let Err(e) = std::fs::create_dir_all(".wiki") {
+                    output::fail(format!("Failed to create .wiki folder: {}", e))
+                }
+                let root = std::path::Path::new(".wiki");
+                say!("{}", "✓ Created local .wiki folder".green().bold());
+                say!("  {} {}", "Path:".cyan(), root.canonicalize().as_deref().unwrap_or(root).display().to_string().white());
+                say!();
+            }
+
+            if !local && !create && !wikis::exists(&wikiname, cli.global) {
+                wikiname = confirm_new_wiki(wikiname, cli.global);
+            }
+
+            match switch(wikiname.clone()) {
+                Ok(_) => {
+                    say!("{}", "✓ Switched wiki context".green().bold());
+                    say!("  {} {}", "Wiki:".cyan(), wikiname.white());
+                    if pin {
+                        let marker = wikis::Marker { wiki: wikiname.clone(), global: cli.global };
+                        let path = std::path::Path::new(wikis::MARKER_FILE);
+                        if let Err(e) = marker.write(path) {
+                            output::fail(format!("couldn't write {}: {}", wikis::MARKER_FILE, e))
+                        }
+                        say!("  {} {}", "Pinned:".cyan(), path.canonicalize().as_deref().unwrap_or(path).display().to_string().white());
+                    } else if let Some((path, marker)) = wikis::project_marker().filter(|(_, m)| m.wiki != wikiname) {
+                        say!();
+                        warning!("{} pins '{}' here, over TWK_WIKI; use --pin to change it", path.display(), marker.wiki);
+                    } else if !local {
+                        say!();
+                        say!("{}", "To persist this change, set the environment variable:".bright_black());
+                        say!("  {}", format!("export TWK_WIKI={}", wikiname).yellow());
+                    }
+                }
+                Err(e) => output::fail(e),
+            }
+        }
+
+        Some(Commands::Import { file, format: ImportFormat::Tiddlers }) => {
+            let tiddlers = std::fs::read_to_string(&file)
+                .and_then(|text| twk::import::parse_tiddlers(&text))
+                .unwrap_or_else(|e| output::fail(format!("{}: {}", file.display(), e)));
+            match import_tiddlers(tiddlers, bar::progress("Importing").as_mut()) {
+                Ok(report) => {
+                    say!("{}", "✓ Imported tiddlers".green().bold());
+                    say!("  {} {}", "New:".cyan(), report.created.to_string().white());
+                    say!("  {} {}", "Updated:".cyan(), report.updated.to_string().white());
+                    say!("  {} {}", "Unchanged:".cyan(), report.unchanged.to_string().white());
+                    if report.system > 0 {
+                        say!("  {} {} system tiddlers", "Skipped:".cyan(), report.system.to_string().white());
+                    }
+                    if report.untitled > 0 {
+                        warning!("Skipped {} tiddlers without a title", report.untitled);
+                    }
+                }
+                Err(e) => output::fail(e),
+            }
+        }
+
+        Some(Commands::Migrate { to }) => {
+            let backend = match to {
+                BackendArg::Files => Backend::Files,
+                #[cfg(feature = "sqlite")]
+                BackendArg::Sqlite => Backend::Sqlite,
+                #[cfg(not(feature = "sqlite"))]
+                BackendArg::Sqlite => output::fail("wk was built without the sqlite feature"),
+            };
+
+            match migrate(backend) {
+                Ok(n) => {
+                    say!("{}", "✓ Migrated wiki".green().bold());
+                    say!("  {} {}", "Backend:".cyan(), backend.to_string().white());
+                    say!("  {} {}", "Facts:".cyan(), n.to_string().white());
+                }
+                Err(e) => output::fail(e),
+            }
+        }
+
+        Some(Commands::Doctor { fix }) => {
+            let findings = match diagnose() {
+                Ok(findings) => findings,
+                Err(e) => output::fail(e),
+            };
+            if findings.is_empty() {
+                say!("{}", "✓ No problems found".green().bold());
+                return;
+            }
+
+            let mut category = "";
+            for finding in &findings {
+                if finding.category() != category {
+                    category = finding.category();
+                    println!("{}", category.yellow().bold());
+                }
+                match finding {
+                    Finding::Corrupt { path, error } => {
+                        println!("  {} {}", path.display().to_string().white(), error.bright_black())
+                    }
+                    Finding::Misnamed { path, id } => println!(
+                        "  {} {}",
+                        path.display().to_string().white(),
+                        format!("holds {}", id).bright_black()
+                    ),
+                    Finding::DuplicateId { id, paths } => {
+                        println!("  {}", id.to_string().white());
+                        for path in paths {
+                            println!("    {}", path.display().to_string().bright_black());
+                        }
+                    }
+                    Finding::StrayTempFile { path } => println!("  {}", path.display().to_string().white()),
+                    Finding::ConflictCopy { path, id } => println!(
+                        "  {} {}",
+                        path.display().to_string().white(),
+                        format!("copy of {}", id).bright_black()
+                    ),
+                    Finding::StaleName { id, name, derived } => println!(
+                        "  {} {} {} {}",
+                        id.to_string().bright_black(),
+                        name.lines().next().unwrap_or_default().white(),
+                        "->".bright_black(),
+                        derived.white()
+                    ),
+                    Finding::InvalidValue { id, field, value, normalized } => println!(
+                        "  {} {} {:?} {}",
+                        id.to_string().bright_black(),
+                        field,
+                        value,
+                        match normalized {
+                            Ok(to) => format!("-> {:?}", to).bright_black(),
+                            Err(e) => format!("can't be fixed for you: {}", e.problem).yellow(),
+                        }
+                    ),
+                }
+            }
+            say!();
+
+            if !fix {
+                say!("{}", "Run 'wk doctor --fix' to repair what can be repaired".bright_black());
+                return;
+            }
+
+            match doctor(&findings) {
+                Ok(repairs) => {
+                    for repair in repairs {
+                        match repair {
+                            Repair::Recovered { path, id } => println!(
+                                "{} {} {}",
+                                "✓ Recovered".green().bold(),
+                                path.display(),
+                                format!("({})", id).bright_black()
+                            ),
+                            Repair::Quarantined { path, to } => println!(
+                                "{} {} {} {}",
+                                "✗ Moved".yellow().bold(),
+                                path.display(),
+                                "->".bright_black(),
+                                to.display()
+                            ),
+                            Repair::Renamed { from, to } => println!(
+                                "{} {} {} {}",
+                                "✓ Renamed".green().bold(),
+                                from.display(),
+                                "->".bright_black(),
+                                to.display()
+                            ),
+                            Repair::Removed { path } => {
+                                println!("{} {}", "✓ Removed".green().bold(), path.display())
+                            }
+                            Repair::Merged { path, id } => println!(
+                                "{} {} {} {}",
+                                "✓ Merged".green().bold(),
+                                path.display(),
+                                "->".bright_black(),
+                                format!("conflict fact {}", id).bright_black()
+                            ),
+                            Repair::Retitled { id, to, .. } => println!(
+                                "{} {} {} {}",
+                                "✓ Renamed".green().bold(),
+                                id.to_string().bright_black(),
+                                "->".bright_black(),
+                                to
+                            ),
+                            Repair::Normalized { id, field, to, .. } => println!(
+                                "{} {} {} {:?}",
+                                format!("✓ Cleaned up {}", field).green().bold(),
+                                id.to_string().bright_black(),
+                                "->".bright_black(),
+                                to
+                            ),
+                        }
+                    }
+                }
+                Err(e) => output::fail(e),
+            }
+        }
+
+        Some(Commands::Wiki(WikiCommand::List)) => match wikis::discover(cli.global) {
+            Ok(listings) if listings.is_empty() => say!("{}", "No wikis yet.".yellow()),
+            Ok(listings) => {
+                // The same name can exist in both roots; only one of them is active
+                let active_location = match wikis::local_root() {
+                    Some(_) if !cli.global => wikis::Location::Local,
+                    _ => wikis::Location::Global,
+                };
+                let mut table = table::Table::new(&["", "NAME", "LOCATION", "FACTS", "PATH"]).flex(4);
+                for listing in listings {
+                    let active = listing.name == current_wiki && listing.location == active_location;
+                    let marker = if active { "*" } else { " " };
+                    let facts = listing
+                        .facts
+                        .map(|n| n.to_string())
+                        .unwrap_or_else(|| "unreadable".to_string());
+                    table.row(vec![
+                        table::Cell::new(marker).color(Color::Green).bold(),
+                        table::Cell::new(listing.name).color(Color::White).bold(),
+                        table::Cell::new(listing.location.to_string()).color(Color::Cyan),
+                        table::Cell::new(facts).color(Color::BrightBlack),
+                        table::Cell::new(listing.path.display().to_string()).color(Color::BrightBlack),
+                    ]);
+                }
+                table.print();
+            }
+            Err(e) => output::fail(e),
+        },
+
+        Some(Commands::Wiki(WikiCommand::Rename { old, new })) => match wikis::rename(&old, &new, cli.global) {
+            Ok(path) => {
+                say!("{}", "✓ Renamed wiki".green().bold());
+                say!("  {} {}", "Path:".cyan(), path.display().to_string().white());
+                if old == current_wiki {
+                    say!();
+                    say!("{}", "This was the active wiki; update the environment variable:".bright_black());
+                    say!("  {}", format!("export TWK_WIKI={}", new).yellow());
+                }
+            }
+            Err(e) => output::fail(e),
+        },
+
+        Some(Commands::Wiki(WikiCommand::Rm { name })) => {
+            if output::ask(format_args!("Type '{}' to delete it and all of its facts:", name)) != name {
+                say!("{}", "Aborted.".yellow());
+                std::process::exit(output::EXIT_FAILURE);
+            }
+
+            match wikis::remove(&name, cli.global) {
+                Ok(path) => {
+                    say!("{}", "✓ Deleted wiki".green().bold());
+                    say!("  {} {}", "Path:".cyan(), path.display().to_string().white());
+                }
+                Err(e) => output::fail(e),
+            }
+        }
+
+        Some(Commands::Wiki(WikiCommand::Encrypt { name })) => {
+            let passphrase = match env::var(encryption::PASSPHRASE_VAR) {
+                Ok(passphrase) => passphrase,
+                Err(_) => {
+                    let first = rpassword::prompt_password("New passphrase: ").unwrap_or_default();
+                    let second = rpassword::prompt_password("Repeat passphrase: ").unwrap_or_default();
+                    if first != second {
+                        output::fail("Passphrases don't match")
+                    }
+                    first
+                }
+            };
+            if passphrase.is_empty() {
+                output::fail("The passphrase can't be empty")
+            }
+
+            match wikis::encrypt(&name, &passphrase, cli.global) {
+                Ok(n) => {
+                    say!("{}", "✓ Encrypted wiki".green().bold());
+                    say!("  {} {}", "Wiki:".cyan(), name.white());
+                    say!("  {} {}", "Facts:".cyan(), n.to_string().white());
+                    say!();
+                    say!("{}", "There is no way to recover the facts without the passphrase.".bright_black());
+                }
+                Err(e) => output::fail(e),
+            }
+        }
+
+        Some(Commands::Wiki(WikiCommand::Decrypt { name })) => {
+            let path = Wiki::get_wiki_path(&name, cli.global).path;
+            let decrypted = encryption::passphrase(&path)
+                .map_err(WikiError::from)
+                .and_then(|passphrase| wikis::decrypt(&name, &passphrase, cli.global));
+            match decrypted {
+                Ok(n) => {
+                    say!("{}", "✓ Decrypted wiki".green().bold());
+                    say!("  {} {}", "Wiki:".cyan(), name.white());
+                    say!("  {} {}", "Facts:".cyan(), n.to_string().white());
+                }
+                Err(e) => output::fail(e),
+            }
+        }
+
+        Some(Commands::Reindex) => {
+            match reindex(bar::progress("Indexing").as_mut()) {
+                Ok(n) => {
+                    say!("{}", "✓ Rebuilt search index".green().bold());
+                    say!("  {} {}", "Facts:".cyan(), n.to_string().white());
+                }
+                Err(e) => output::fail(e),
+            }
+        }
+
+        Some(Commands::Fmt) => match reformat() {
+            Ok(0) => say!("{}", "✓ Every file is already formatted".green().bold()),
+            Ok(n) => say!("{} {} {}", "✓ Formatted".green().bold(), n.to_string().white(), if n == 1 { "file" } else { "files" }),
+            Err(e) => output::fail(e),
+        },
+
+        Some(Commands::Stats { history: false, .. }) => {
+            let stats = stats().unwrap_or_else(|e| output::fail(e));
+            println!("{} {}", "Facts:".cyan(), stats.facts.to_string().white());
+            println!("{} {}", "Tags:".cyan(), stats.tags.len().to_string().white());
+            println!(
+                "{} {} in the last week, {} in the last 30 days",
+                "Added:".cyan(),
+                stats.added_week.to_string().white(),
+                stats.added_month.to_string().white()
+            );
+        }
+
+        Some(Commands::Stats { bucket, by_tag, json, .. }) => {
+            let buckets = activity(bucket.bucket(), by_tag.as_deref()).unwrap_or_else(|e| output::fail(e));
+            if json {
+                let rows: Vec<_> = buckets
+                    .iter()
+                    .map(|(start, count)| serde_json::json!({ "start": start, "count": count }))
+                    .collect();
+                println!("{}", serde_json::Value::Array(rows));
+                return;
+            }
+            if buckets.is_empty() {
+                say!("{}", "No facts to chart yet.".yellow());
+                return;
+            }
+
+            let per = match bucket {
+                StatsBucket::Day => "day",
+                StatsBucket::Week => "week",
+                StatsBucket::Month => "month",
+            };
+            let of = by_tag.map(|tag| format!(" tagged {}", tag)).unwrap_or_default();
+            println!("{}", format!("Facts{} added per {}", of, per).cyan());
+            let counts: Vec<usize> = buckets.iter().map(|&(_, n)| n).collect();
+            println!("{}  {}", chart::sparkline(&counts), format!("{} in all", counts.iter().sum::<usize>()).bright_black());
+            println!();
+
+            let width = std::io::stdout()
+                .is_terminal()
+                .then(|| crossterm::terminal::size().ok())
+                .flatten()
+                .map_or(80, |(columns, _)| columns as usize);
+            let rows: Vec<(String, usize)> = buckets
+                .iter()
+                .map(|(start, n)| (start.format("%Y-%m-%d").to_string(), *n))
+                .collect();
+            for line in chart::bar_chart(&rows, width) {
+                println!("{}", line);
+            }
+        }
+
+        Some(Commands::Aliases) => {
+            let commands = Config::load_global().unwrap_or_else(|e| output::fail(e)).commands;
+            if commands.is_empty() {
+                say!("{}", "No custom commands; add them to [commands] in the global config".yellow());
+                return;
+            }
+            let mut table = table::Table::new(&["COMMAND", "RUNS"]).flex(1);
+            for (name, template) in commands {
+                table.row(vec![table::Cell::new(name).color(Color::White), table::Cell::new(template).color(Color::BrightBlack)]);
+            }
+            table.print();
+        }
+
+        Some(Commands::Status) => match status() {
+            Ok(status) => {
+                println!(
+                    "{} {} {}",
+                    "Wiki:".cyan(),
+                    status.name.white().bold(),
+                    format!("({})", resolved_name.source).bright_black()
+                );
+                println!("{} {}", "Path:".cyan(), status.resolved.path.display().to_string().white());
+                println!(
+                    "{} {} {}",
+                    "Location:".cyan(),
+                    status.resolved.source.location().to_string().white(),
+                    format!("({})", status.resolved.source).bright_black()
+                );
+                println!("{} {}", "Facts:".cyan(), status.facts.to_string().white());
+                let modified = status
+                    .modified
+                    .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_else(|| "never".to_string());
+                println!("{} {}", "Modified:".cyan(), modified.white());
+                if status.readonly {
+                    println!("{} {}", "Read-only:".cyan(), "yes".yellow());
+                }
+                if status.warnings.is_empty() {
+                    println!("{} {}", "Warnings:".cyan(), "none".white());
+                } else {
+                    println!("{} {}", "Warnings:".cyan(), status.warnings.len().to_string().yellow());
+                    for warning in &status.warnings {
+                        println!(
+                            "  {} {}",
+                            warning.path.display().to_string().white(),
+                            warning.error.bright_black()
+                        );
+                    }
+                }
+            }
+            Err(e) => output::fail(e),
+        },
+
+        Some(Commands::Sync { dir: Some(dir) }) => match sync_dir(&dir, cli.dry_run) {
+            Ok(plan) => {
+                let first_line = |info: &twk::Information| info.data.lines().next().unwrap_or_default().to_string();
+                let peer = plan.peer.display().to_string();
+                for action in &plan.actions {
+                    let (label, info) = match action {
+                        DirSyncAction::Push(info) => ("→ copy to peer  ".green(), info),
+                        DirSyncAction::Pull(info) => ("← copy from peer".green(), info),
+                        DirSyncAction::DeleteLocal(info) => ("✗ delete here   ".yellow(), info),
+                        DirSyncAction::DeletePeer(info) => ("✗ delete in peer".yellow(), info),
+                        DirSyncAction::Conflict(c) => ("! conflict      ".red(), &c.local),
+                    };
+                    println!("  {} {} {}", label, info.id.to_string().bright_black(), first_line(info).white());
+                }
+                if !plan.actions.is_empty() {
+                    println!();
+                }
+
+                let count = |f: fn(&DirSyncAction) -> bool| plan.actions.iter().filter(|a| f(a)).count();
+                let conflicts = count(|a| matches!(a, DirSyncAction::Conflict(_)));
+                let summary = format!(
+                    "{} copied, {} deleted, {} conflicts",
+                    count(|a| matches!(a, DirSyncAction::Push(_) | DirSyncAction::Pull(_))),
+                    count(|a| matches!(a, DirSyncAction::DeleteLocal(_) | DirSyncAction::DeletePeer(_))),
+                    conflicts,
+                );
+                if cli.dry_run {
+                    println!("{} {}: {}", "Dry run against".cyan().bold(), peer, summary);
+                    say!("{}", "Nothing was written; run without --dry-run to apply".bright_black());
+                } else {
+                    say!("{} {}: {}", "✓ Synced with".green().bold(), peer, summary);
+                    if conflicts > 0 {
+                        say!(
+                            "{}",
+                            "The newer edit of each conflict was kept; both versions are in facts tagged 'conflict'"
+                                .bright_black()
+                        );
+                    }
+                }
+            }
+            Err(e) => output::fail(e),
+        },
+
+        #[cfg(feature = "git")]
+        Some(Commands::Sync { dir: None, .. }) => match twk::sync() {
+            Ok(report) if !report.conflicts.is_empty() => {
+                eprintln_colored!(
+                    "{} {} facts were changed both here and on the remote; nothing was pulled or pushed",
+                    "✗ Conflicts:".red().bold(),
+                    report.conflicts.len()
+                );
+                let describe = |fact: &Option<twk::Information>| match fact {
+                    Some(fact) => fact.data.white().to_string(),
+                    None => "deleted or unreadable".bright_black().to_string(),
+                };
+                for conflict in &report.conflicts {
+                    eprintln!();
+                    eprintln_colored!("  {}", conflict.path.display().to_string().yellow());
+                    eprintln_colored!("    {} {}", "local: ".cyan(), describe(&conflict.local));
+                    eprintln_colored!("    {} {}", "remote:".cyan(), describe(&conflict.remote));
+                }
+                eprintln!();
+                eprintln_colored!("{}", "Resolve them with git, then run 'wk sync' again".bright_black());
+                std::process::exit(output::EXIT_CONFLICT);
+            }
+            Ok(report) => {
+                say!("{}", "✓ Synced".green().bold());
+                if report.committed {
+                    say!("  {} {}", "Committed:".cyan(), "local changes".white());
+                }
+                say!("  {} {} commits", "Pulled:".cyan(), report.pulled.to_string().white());
+                say!("  {} {} commits", "Pushed:".cyan(), report.pushed.to_string().white());
+            }
+            Err(e) => output::fail(e),
+        },
+
+        #[cfg(not(feature = "git"))]
+        Some(Commands::Sync { dir: None, .. }) => {
+            eprintln_colored!(
+                "{} wk was built without the git feature; use --dir to sync with another copy",
+                "Error:".red().bold()
+            );
+            std::process::exit(output::EXIT_FAILURE);
+        }
+
+        Some(Commands::Batch { fail_fast }) => {
+            let options = BatchOptions { fail_fast, scan: !cli.no_scan };
+            match twk::batch(std::io::stdin().lock(), std::io::stdout().lock(), options) {
+                Ok(report) if report.failed > 0 => {
+                    say!("{}", format!("{} of {} commands failed", report.failed, report.ran).yellow());
+                    std::process::exit(output::EXIT_FAILURE);
+                }
+                Ok(_) => {}
+                Err(e) => output::fail(e),
+            }
+        }
+
+        Some(Commands::Mcp) => {
+            if let Err(e) = twk::mcp(std::io::stdin().lock(), std::io::stdout().lock()) {
+                output::fail(e)
+            }
+        }
+
+        #[cfg(feature = "server")]
+        Some(Commands::Serve { port, bind, token, allow_host }) => {
+            use std::sync::{Arc, RwLock};
+            use twk::server::{ServeOptions, Server};
+
+            // With --readonly or a read-only wiki, mutations are refused up front
+            let result = Wiki::load_or_create(current_wiki.clone(), cli.global)
+                .map_err(|e| e.to_string())
+                .and_then(|wiki| {
+                    let options = ServeOptions {
+                        addr: std::net::SocketAddr::new(bind, port),
+                        readonly: wiki.readonly,
+                        token,
+                        allowed_hosts: allow_host,
+                    };
+                    Ok((wiki, Server::bind(options).map_err(|e| e.to_string())?))
+                });
+            match result {
+                Ok((wiki, server)) => {
+                    let readonly = wiki.readonly;
+                    say!(
+                        "{} {} on {}{}",
+                        "✓ Serving".green().bold(),
+                        current_wiki.white(),
+                        format!("http://{}", server.addr()).cyan(),
+                        if readonly { " (read-only)" } else { "" }.bright_black()
+                    );
+                    server.run(Arc::new(RwLock::new(wiki)));
+                }
+                Err(e) => output::fail(e),
+            }
+        }
+
+        #[cfg(not(feature = "server"))]
+        Some(Commands::Serve { .. }) => output::fail("wk was built without the server feature"),
+
+        Some(Commands::Tui) => {
+            if let Err(e) = tui::run(current_wiki, cli.global, cli.log_file, !cli.no_scan) {
+                output::fail(e)
+            }
+        }
+        
+        None => {
+            // No command - could enter TUI mode in the future
+            println!("{}", "TiddlyWiki Knowledge Manager".bright_cyan().bold());
+            println!();
+            println!("{}", "Usage:".white().bold());
+            println!("  {} {}      Commit a fact to memory", "wk c".yellow(), "<fact> [tag]".bright_black());
+            println!("  {} {}      Recall facts", "wk r".yellow(), "<query>".bright_black());
+            println!("  {} {}         Recall facts by tag", "wk r".yellow(), "[tag]".bright_black());
             println!("  {} {}  Switch wiki context", "wk switch".yellow(), "<name>".bright_black());
             println!("  {} {}         Build static site", "wk book".yellow(), "          ".bright_black());
             println!();
@@ -218,4 +2219,710 @@ fn main() {
             println!("{}", "Run 'wk --help' for more information".bright_black());
         }
     }
+
+    if print_plan {
+        print_dry_run(&plan());
+    }
+}
+
+impl Commands {
+    /// The name of the command if it can't be run with --dry-run, because it
+    /// writes outside the wiki's storage or runs until stopped
+    fn unplannable(&self) -> Option<&'static str> {
+        match self {
+            Commands::WatchClipboard { .. } => Some("watch-clipboard"),
+            Commands::Open { raw: true, .. } => Some("open --raw"),
+            Commands::Book { .. } => Some("book"),
+            Commands::Switch { .. } => Some("switch"),
+            Commands::Tui => Some("tui"),
+            Commands::Migrate { .. } => Some("migrate"),
+            Commands::Doctor { fix: true } => Some("doctor --fix"),
+            Commands::Wiki(WikiCommand::List) => None,
+            Commands::Wiki(_) => Some("wiki"),
+            Commands::Mcp => Some("mcp"),
+            Commands::Serve { .. } => Some("serve"),
+            Commands::Usage(UsageCommand::Reset) => Some("usage reset"),
+            _ => None,
+        }
+    }
+}
+
+/// Order `facts` for `--sort`, keeping the order they came in among equals
+fn sort_facts(facts: &mut [twk::Information], sort: SortBy) {
+    match sort {
+        SortBy::Modified => facts.sort_by_key(|info| std::cmp::Reverse(info.updated.or(info.created))),
+        SortBy::Frecency => match usage() {
+            Ok(Some(usage)) => sort_by_frecency(facts, &usage, Utc::now()),
+            Ok(None) => warning!("Usage isn't tracked in this wiki, so facts keep their order; set track_usage = true in its config"),
+            Err(e) => output::fail(e),
+        },
+    }
+}
+
+/// Print the steps a dry run planned, one per fact, and how many of each
+fn print_dry_run(plan: &[Planned]) {
+    if plan.is_empty() {
+        say!("{}", "Dry run: nothing would change".cyan().bold());
+        return;
+    }
+    say!("{}", "Dry run: nothing was written. It would:".cyan().bold());
+    let width = plan.iter().map(|step| step.action.verb().len()).max().unwrap_or_default();
+    let mut counts: Vec<(&str, usize)> = Vec::new();
+    for step in plan {
+        let verb = step.action.verb();
+        let label = match &step.action {
+            twk::dryrun::Action::Snapshot(label) => format!(" as '{}'", label),
+            _ => String::new(),
+        };
+        println!(
+            "  {} {} {}{}",
+            format!("{:<width$}", verb).yellow(),
+            step.id.to_string().bright_black(),
+            step.name.white(),
+            label.bright_black()
+        );
+        match counts.iter_mut().find(|(v, _)| *v == verb) {
+            Some((_, n)) => *n += 1,
+            None => counts.push((verb, 1)),
+        }
+    }
+    let counts: Vec<String> = counts.iter().map(|(verb, n)| format!("{} {}", verb, n)).collect();
+    println!("{}", counts.join(", "));
+}
+
+fn parse_since(text: &str) -> Result<DateTime<Utc>, String> {
+    twk::window::since(text, Utc::now())
+}
+
+fn parse_until(text: &str) -> Result<DateTime<Utc>, String> {
+    twk::window::until(text, Utc::now())
+}
+
+/// `facts` less those changed outside `window`
+fn within(mut facts: Vec<twk::Information>, window: TimeWindow) -> Vec<twk::Information> {
+    facts.retain(|info| window.contains(info));
+    facts
+}
+
+/// Copies larger than this aren't captured by `wk watch-clipboard` unless
+/// `--max-bytes` says otherwise
+const CAPTURE_MAX_BYTES: usize = 64 * 1024;
+/// How often `wk watch-clipboard` looks at the clipboard
+const CAPTURE_POLL: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Append each new clipboard text to today's capture fact, creating it with
+/// `tags` on the first copy, until interrupted
+fn watch_clipboard(tags: &[String], max_bytes: usize) {
+    let name = format!("Clipboard {}", chrono::Local::now().format("%Y-%m-%d"));
+    // Pick up where an earlier session today left off
+    let mut capture = recall_exact(&name, None, Fields::NAME)
+        .ok()
+        .and_then(|facts| facts.into_iter().find(|info| info.name == name))
+        .map(|info| info.id);
+
+    // Ctrl-C only raises the flag, so a capture in progress is always finished
+    let stop = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    for signal in [signal_hook::consts::SIGINT, signal_hook::consts::SIGTERM] {
+        if let Err(e) = signal_hook::flag::register(signal, std::sync::Arc::clone(&stop)) {
+            output::fail(e);
+        }
+    }
+
+    let mut reader = clipboard::Reader::open().unwrap_or_else(|e| output::fail(e));
+    // What's on the clipboard already was copied before we started
+    let mut last = reader.text().unwrap_or_else(|e| output::fail(e));
+    say!("{}", format!("Capturing copies into '{}'; Ctrl-C to stop", name).bright_black());
+
+    let mut captured = 0;
+    loop {
+        let stopping = stop.load(std::sync::atomic::Ordering::Relaxed);
+        if stopping {
+            // Off the line the terminal echoed ^C on
+            say!();
+        }
+        // Images and other non-text copies, and brief errors, just wait for the next look
+        if let Ok(Some(text)) = reader.text()
+            && last.as_ref() != Some(&text)
+        {
+            let snippet = text.trim_end();
+            if snippet.len() > max_bytes {
+                warning!(
+                    "skipped a copy of {} bytes, over --max-bytes {}",
+                    snippet.len(),
+                    max_bytes
+                );
+            } else if !snippet.trim().is_empty() {
+                let entry = format!("--- {} ---\n{}", chrono::Local::now().format("%H:%M:%S"), snippet);
+                let saved = match capture {
+                    Some(id) => append(id, &entry, tags).map(|info| info.id),
+                    None => commit_named(name.clone(), entry, tags.to_vec(), None),
+                };
+                match saved {
+                    Ok(id) => {
+                        capture = Some(id);
+                        captured += 1;
+                        say!("{} {}", "✓".green().bold(), snippet.lines().next().unwrap_or_default());
+                    }
+                    Err(e) => eprintln_colored!("{} couldn't capture a copy: {}", "Error:".red().bold(), e),
+                }
+            }
+            last = Some(text);
+        }
+        if stopping {
+            break;
+        }
+        std::thread::sleep(CAPTURE_POLL);
+    }
+
+    match captured {
+        0 => say!("{}", "Nothing captured.".yellow()),
+        n => say!("{} captured {} snippets into '{}'", "✓".green().bold(), n, name),
+    }
+}
+
+/// Print how fact `id` differs from what it's compared with, as a unified
+/// diff or a count of lines, and exit as `diff` does: 0 if they're the same,
+/// 1 if they differ and 2 or more if they couldn't be compared
+fn diff_fact(id: uuid::Uuid, snapshot: Option<String>, wiki: Option<String>, file: Option<PathBuf>, stat: bool) {
+    fn fail(e: impl output::Failure) -> ! {
+        output::fail_with(e.exit_code().max(output::EXIT_USAGE), e)
+    }
+
+    let fact = get(id).unwrap_or_else(|e| fail(e));
+    let (old, old_label) = match (wiki, file) {
+        (Some(name), _) => {
+            let other = wikis::open_existing(&name, twk::is_using_global()).and_then(|other| other.get(id));
+            (other.unwrap_or_else(|e| fail(e)).data, format!("{}/{}", name, id))
+        }
+        (_, Some(path)) => {
+            let text = std::fs::read_to_string(&path).unwrap_or_else(|e| fail(format!("{}: {}", path.display(), e)));
+            (text, path.display().to_string())
+        }
+        (None, None) => {
+            let snapshots = snapshots(id).unwrap_or_else(|e| fail(e));
+            let found = match &snapshot {
+                Some(label) => snapshots.into_iter().find(|s| &s.label == label),
+                None => snapshots.into_iter().last(),
+            };
+            let Some(found) = found else {
+                match snapshot {
+                    Some(label) => fail(WikiError::NoSnapshot { id, label }),
+                    None => output::fail_with(output::EXIT_NOT_FOUND, "the fact has no snapshots; take one with `wk snapshot`"),
+                }
+            };
+            (found.fact.data, format!("{} ({})", id, found.label))
+        }
+    };
+    let new_label = match status() {
+        Ok(status) => format!("{}/{}", status.name, id),
+        Err(_) => id.to_string(),
+    };
+
+    let lines = twk::diff::diff_lines(&old, &fact.data);
+    let hunks = twk::diff::hunks(&lines);
+    if hunks.is_empty() {
+        return;
+    }
+    if stat {
+        let twk::diff::DiffStat { added, removed } = twk::diff::stat(&lines);
+        println!(
+            "{} {}, {}",
+            fact.name.lines().next().unwrap_or_default().white().bold(),
+            format!("{} {}(+)", added, if added == 1 { "insertion" } else { "insertions" }).green(),
+            format!("{} {}(-)", removed, if removed == 1 { "deletion" } else { "deletions" }).red()
+        );
+    } else {
+        println!("{}", format!("--- {}", old_label).bold());
+        println!("{}", format!("+++ {}", new_label).bold());
+        for hunk in hunks {
+            println!("{}", format!("@@ -{},{} +{},{} @@", hunk.old_start, hunk.old_len, hunk.new_start, hunk.new_len).cyan());
+            for line in hunk.lines {
+                match line {
+                    twk::diff::Line::Same(text) => println!(" {}", text),
+                    twk::diff::Line::Removed(text) => println!("{}", format!("-{}", text).red()),
+                    twk::diff::Line::Added(text) => println!("{}", format!("+{}", text).green()),
+                }
+            }
+        }
+    }
+    std::process::exit(1);
+}
+
+/// Show what `wk replace` changes in one fact, as numbered lines before and after
+/// The command line with any custom command from the global config's
+/// `[commands]` expanded, or as it was if there are none or it isn't UTF-8.
+/// A custom command named like a built-in one is refused here.
+fn expand_custom_command(args: Vec<std::ffi::OsString>) -> Vec<std::ffi::OsString> {
+    let Some(argv) = args.iter().map(|arg| arg.to_str().map(str::to_string)).collect::<Option<Vec<_>>>() else {
+        return args;
+    };
+    // A broken config is reported once a wiki loads it
+    let commands = Config::load_global().map(|config| config.commands).unwrap_or_default();
+    if commands.is_empty() {
+        return args;
+    }
+
+    let cli = Cli::command();
+    let builtins: Vec<String> = cli
+        .get_subcommands()
+        .flat_map(|command| std::iter::once(command.get_name()).chain(command.get_all_aliases()))
+        .chain(["help"])
+        .map(str::to_string)
+        .collect();
+    if let Err(e) = twk::commands::check_builtins(&commands, &builtins) {
+        output::fail_with(output::EXIT_USAGE, format!("invalid config: {}", e))
+    }
+    let takes_value = |option: &str| {
+        cli.get_arguments().any(|arg| {
+            let named = match option.strip_prefix("--") {
+                Some(long) => arg.get_long() == Some(long),
+                None => option.strip_prefix('-').is_some_and(|short| arg.get_short().is_some_and(|s| short == s.to_string())),
+            };
+            named && arg.get_action().takes_values()
+        })
+    };
+    match twk::commands::rewrite(argv, &commands, takes_value) {
+        Ok(argv) => argv.into_iter().map(Into::into).collect(),
+        Err(e) => output::fail_with(output::EXIT_USAGE, e),
+    }
+}
+
+fn print_replace_report(report: &twk::ReplaceReport) {
+    println!(
+        "{} {}",
+        report.name.lines().next().unwrap_or_default().white().bold(),
+        report.id.to_string().bright_black()
+    );
+    if let Some(new_name) = &report.new_name {
+        // Names made from a fact's text can run to many lines; show those that changed
+        for (before, after) in report.name.lines().zip(new_name.lines()).filter(|(b, a)| b != a) {
+            println!("  {} {} {} {}", "name:".cyan(), before.red(), "→".bright_black(), after.green());
+        }
+    }
+    for change in &report.lines {
+        for (i, line) in change.before.lines().enumerate() {
+            println!("  {} {}", format!("{:>4}-", change.line + i).bright_black(), line.red());
+        }
+        for (i, line) in change.after.lines().enumerate() {
+            println!("  {} {}", format!("{:>4}+", change.line + i).bright_black(), line.green());
+        }
+    }
+}
+
+/// After a recall of `query` found nothing, say which tags, facts and other
+/// wikis might have been meant, on stderr
+fn print_suggestions(query: &str, fields: Fields) {
+    let Ok(suggestions) = twk::suggestions(query, fields) else {
+        return;
+    };
+    if suggestions.is_empty() {
+        return;
+    }
+    say!("{}", "Suggestions:".bright_black());
+    if !suggestions.tags.is_empty() {
+        let tags: Vec<_> = suggestions.tags.iter().map(|tag| format!("wk r [{}]", tag).yellow().to_string()).collect();
+        say!("  {} {}", "tags like it:".cyan(), tags.join(", "));
+    }
+    for fact in &suggestions.facts {
+        say!(
+            "  {} {} {}",
+            "almost matched:".cyan(),
+            fact.name.lines().next().unwrap_or_default().white(),
+            fact.id.to_string().bright_black()
+        );
+    }
+    for wiki in &suggestions.wikis {
+        say!(
+            "  {} {} {}",
+            format!("{} facts named like it in {} wiki", wiki.facts, wiki.location).cyan(),
+            wiki.name.white().bold(),
+            format!("(wk switch {})", wiki.name).bright_black()
+        );
+    }
+}
+
+/// Print facts listed by `wk r` or `wk recent` in the chosen `format`
+fn print_facts(
+    wiki: &str,
+    facts: &[twk::Information],
+    format: RecallFormat,
+    show_id: bool,
+    materialize: bool,
+    pager: bool,
+    matcher: Option<&LineMatcher>,
+) {
+    match format {
+        RecallFormat::Table => print_fact_table(facts, show_id, matcher),
+        RecallFormat::Vimgrep | RecallFormat::Paths => print_fact_paths(wiki, facts, format, materialize),
+        RecallFormat::Text if facts.is_empty() => say!("{}", "No matching facts found.".yellow()),
+        // Bare data for scripts: no tags, ids, wrapping or pager
+        RecallFormat::Text if output::level() == Level::Quiet => {
+            for fact in facts {
+                println!("{}", fact.data);
+            }
+        }
+        RecallFormat::Text => print_fact_text(facts, show_id, pager),
+        RecallFormat::Json => print_json(&facts.iter().map(twk::Information::exported).collect::<Vec<_>>()),
+    }
+}
+
+/// Print `value` to stdout as JSON, for scripts
+fn print_json<T: serde::Serialize + ?Sized>(value: &T) {
+    match serde_json::to_string(value) {
+        Ok(json) => println!("{}", json),
+        Err(e) => output::fail(e.to_string()),
+    }
+}
+
+/// Print recall results as each fact's data followed by its tags, and id if
+/// asked for. On a terminal long lines are wrapped at word boundaries, with
+/// later lines of a fact indented under its first.
+fn print_fact_text(facts: &[twk::Information], show_id: bool, pager: bool) {
+    const HANG: usize = 2;
+    let width = std::io::stdout()
+        .is_terminal()
+        .then(|| crossterm::terminal::size().ok())
+        .flatten()
+        .map(|(columns, _)| columns as usize)
+        .filter(|&columns| columns > 0);
+
+    let mut out = String::new();
+    for fact in facts {
+        let mut lines: Vec<Vec<table::Word>> = fact
+            .data
+            .lines()
+            .map(|line| match width {
+                // Lines that fit keep their spacing, e.g. indented code
+                Some(width) if line.width() + HANG > width => {
+                    line.split_whitespace().map(|word| table::Word::new(word, Color::White)).collect()
+                }
+                _ => vec![table::Word::new(line, Color::White)],
+            })
+            .collect();
+        if lines.is_empty() {
+            lines.push(Vec::new());
+        }
+        if let Some(last) = lines.last_mut() {
+            last.extend(fact.tags.iter().map(|t| table::Word::bracketed(t.as_str(), Color::BrightBlack, "[", "]")));
+            if show_id {
+                last.push(table::Word::bracketed(fact.id.to_string(), Color::BrightBlack, "(", ")"));
+            }
+        }
+        let (width, hang) = width.map_or((usize::MAX, 0), |width| (width, HANG));
+        for line in table::wrap(lines, width, hang) {
+            out.push_str(&line);
+            out.push('\n');
+        }
+    }
+    output::page(&out, pager);
+}
+
+/// Print recall results as a table of names, previews, tags and ages
+fn print_fact_table(facts: &[twk::Information], show_id: bool, matcher: Option<&LineMatcher>) {
+    if facts.is_empty() {
+        say!("{}", "No matching facts found.".yellow());
+        return;
+    }
+    let mut headers = vec!["NAME", "PREVIEW", "TAGS", "AGE"];
+    if show_id {
+        headers.push("ID");
+    }
+    let mut table = table::Table::new(&headers).flex(1);
+    for fact in facts {
+        let tags = fact.tags.iter().map(|t| format!("[{}]", t)).collect::<Vec<_>>().join(" ");
+        let age = fact.updated.or(fact.created).map(table::age).unwrap_or_default();
+        // A fact named after its only line would say the same thing twice
+        let preview = Some(preview_line(&fact.data, matcher)).filter(|preview| preview != fact.name.trim());
+        let mut row = vec![
+            table::Cell::new(fact.name.as_str()).color(Color::White),
+            table::Cell::new(preview.unwrap_or_default()).color(Color::BrightBlack),
+            table::Cell::new(tags).color(Color::BrightBlack),
+            table::Cell::new(age).color(Color::Cyan),
+        ];
+        if show_id {
+            row.push(table::Cell::new(fact.id.to_string()).color(Color::BrightBlack));
+        }
+        table.row(row);
+    }
+    table.print();
+}
+
+/// Print recall results as file paths for editors, either of the fact files
+/// themselves or of Markdown renderings of them
+fn print_fact_paths(wiki: &str, facts: &[twk::Information], format: RecallFormat, materialize: bool) {
+    let files = match fact_files(facts) {
+        Ok(files) => files,
+        Err(e) => output::fail(e),
+    };
+    if !materialize && files.iter().any(Option::is_none) {
+        eprintln_colored!(
+            "{} this wiki's facts aren't stored as plain JSON files; pass --materialize to render them",
+            "Error:".red().bold()
+        );
+        std::process::exit(output::EXIT_FAILURE);
+    }
+
+    let dir = std::env::temp_dir().join(format!("twk-{}", wiki));
+    for (fact, file) in facts.iter().zip(files) {
+        let path = match file.filter(|_| !materialize) {
+            Some(path) => path,
+            None => match twk::editor::materialize(&dir, fact) {
+                Ok(path) => path,
+                Err(e) => output::fail(e),
+            },
+        };
+        match format {
+            RecallFormat::Vimgrep => {
+                let name = fact.name.lines().next().unwrap_or_default();
+                let preview: String = fact
+                    .data
+                    .strip_prefix(name)
+                    .unwrap_or(&fact.data)
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+                    .chars()
+                    .take(80)
+                    .collect();
+                let preview = preview.trim_end();
+                if preview.is_empty() {
+                    println!("{}:1:1: {}", path.display(), name);
+                } else {
+                    println!("{}:1:1: {} — {}", path.display(), name, preview);
+                }
+            }
+            RecallFormat::Paths | RecallFormat::Text | RecallFormat::Table | RecallFormat::Json => {
+                println!("{}", path.display())
+            }
+        }
+    }
+}
+
+/// Edit an existing fact in $EDITOR with its title and tags as frontmatter,
+/// refusing to save if it changed in the meantime
+fn open_in_editor(id: uuid::Uuid) {
+    let fact = get(id).unwrap_or_else(|e| output::fail(e));
+    let tmp = twk::editor::write_temp(&fact.name, &fact.tags, fact.source.as_deref(), &fact.data).unwrap_or_else(|e| output::fail(e));
+    match twk::editor::launch(tmp.path()) {
+        Ok(status) if status.success() => {}
+        Ok(status) => output::fail(format!("editor exited with {}; nothing saved", status)),
+        Err(e) => output::fail(e),
+    }
+    let edited = std::fs::read_to_string(tmp.path()).unwrap_or_else(|e| output::fail(e));
+    let edited = twk::editor::parse_frontmatter(&edited);
+    let body = edited.body.trim_end().to_string();
+    if body == fact.data
+        && edited.title.trim() == fact.name
+        && edited.tags.as_ref().is_none_or(|t| *t == fact.tags)
+        && edited.source.as_ref().is_none_or(|s| *s == fact.source)
+    {
+        say!("{}", "No changes.".bright_black());
+        return;
+    }
+
+    let saved = twk::update_if(id, fact.updated, |info| {
+        if !edited.title.trim().is_empty() && edited.title.trim() != info.name.trim() {
+            info.name = edited.title.trim().to_string();
+            info.name_is_derived = false;
+        }
+        if let Some(tags) = edited.tags {
+            info.tags = tags;
+        }
+        if let Some(source) = edited.source {
+            info.source = source;
+        }
+        info.data = body;
+    });
+    match saved {
+        Ok(fact) => say!("{} {}", "✓ Saved".green().bold(), fact.name.lines().next().unwrap_or_default()),
+        Err(WikiError::Conflict { .. }) => output::fail("the fact was modified while the editor was open; nothing saved"),
+        Err(e) => output::fail(e),
+    }
+}
+
+/// Let the user edit a fact's JSON file directly, then read it back so a
+/// broken edit is reported straight away
+fn open_raw(id: uuid::Uuid) {
+    // The fact may not have loaded at all; that is what --raw is there to fix
+    let Some(path) = twk::fact_file(id).unwrap_or_else(|e| output::fail(e)) else {
+        output::fail("this wiki's facts aren't stored as plain JSON files; use `wk open` without --raw");
+    };
+    if !path.exists() {
+        output::fail(WikiError::NotFound(id));
+    }
+
+    warning!("editing the file directly skips every check; changing the id or leaving invalid JSON can corrupt the wiki");
+    if let Err(e) = twk::editor::launch(&path) {
+        output::fail(e);
+    }
+    match twk::reload(id) {
+        Ok(fact) => say!("{} {}", "✓ Reloaded".green().bold(), fact.name.lines().next().unwrap_or_default()),
+        Err(e) => {
+            eprintln_colored!("{} {} no longer loads: {}", "Error:".red().bold(), path.display(), e);
+            eprintln_colored!("  {}", format!("Fix it with `wk open {} --raw`, or run `wk doctor`", id).bright_black());
+            std::process::exit(output::EXIT_FAILURE);
+        }
+    }
+}
+
+/// Fail for an error writing a wiki out, telling how to write out an
+/// encrypted one anyway
+fn fail_output(e: WikiError) -> ! {
+    match e {
+        WikiError::Encrypted(_) => {
+            output::fail_with(output::Failure::exit_code(&e), format!("{}; pass --allow-plaintext-output to write it out unencrypted", e))
+        }
+        e => output::fail(e),
+    }
+}
+
+/// Render a fact's book page and open it with the platform's default app
+fn open_book_page(id: uuid::Uuid, allow_plaintext_output: bool) {
+    let page = match twk::book_page(id, allow_plaintext_output) {
+        Ok(page) => page,
+        Err(e) => fail_output(e),
+    };
+    println!("{} {}", "Page:".cyan(), page.display().to_string().white());
+    if let Err(e) = twk::editor::open_external(&page) {
+        output::fail(format!("couldn't open the page: {}", e))
+    }
+}
+
+/// Open a fact about to be committed in $EDITOR and return what was saved;
+/// an emptied fact aborts the commit
+fn edit_before_commit(
+    name: String,
+    data: String,
+    tags: Vec<String>,
+    source: Option<String>,
+) -> (String, String, Vec<String>, Option<String>) {
+    let tmp = twk::editor::write_temp(&name, &tags, source.as_deref(), &data).unwrap_or_else(|e| output::fail(e));
+    match twk::editor::launch(tmp.path()) {
+        Ok(status) if status.success() => {}
+        Ok(status) => output::fail(format!("editor exited with {}; nothing committed", status)),
+        Err(e) => output::fail(e),
+    }
+    let edited = std::fs::read_to_string(tmp.path()).unwrap_or_else(|e| output::fail(e));
+    let edited = twk::editor::parse_frontmatter(&edited);
+
+    let body = edited.body.trim_end().to_string();
+    if body.trim().is_empty() {
+        output::fail("the fact is empty; nothing committed");
+    }
+    let name = match edited.title.trim() {
+        "" => body.lines().next().unwrap_or_default().trim().to_string(),
+        title => title.to_string(),
+    };
+    (name, body, edited.tags.unwrap_or(tags), edited.source.unwrap_or(source))
+}
+
+/// Look for secrets in a fact about to be committed and deal with them as
+/// the wiki's `[secrets]` policy says, returning the name and data to commit.
+/// Only where and what kind of secret is printed, never the secret.
+fn check_secrets(name: String, data: String) -> (String, String) {
+    let (scanner, policy) = secret_scanner().unwrap_or_else(|e| output::fail(e));
+    let in_data = scanner.scan(&data);
+    let in_name = scanner.scan(&name);
+    if in_data.is_empty() && in_name.is_empty() {
+        return (name, data);
+    }
+
+    for m in &in_data {
+        warning!("line {} looks like it holds a secret ({})", m.line, m.pattern);
+    }
+    // The name is usually the first line of the data, already reported
+    let in_name = if data.contains(name.as_str()) { Vec::new() } else { in_name };
+    for m in &in_name {
+        warning!("the name looks like it holds a secret ({})", m.pattern);
+    }
+
+    let refuse = |why: &str| -> ! {
+        output::fail(format!("{}; commit with --no-scan if the secret is meant to be there", why))
+    };
+    match policy {
+        SecretPolicy::Redact => {
+            say!("{}", format!("redacted {} secrets", in_data.len() + in_name.len()).yellow());
+            (scanner.redact(&name).0, scanner.redact(&data).0)
+        }
+        SecretPolicy::Block => refuse("the wiki doesn't allow committing secrets"),
+        SecretPolicy::Warn if std::io::stdin().is_terminal() => {
+            if !output::confirm(format_args!("Commit it anyway?"), false) {
+                say!("{}", "Aborted.".yellow());
+                std::process::exit(output::EXIT_FAILURE);
+            }
+            (name, data)
+        }
+        SecretPolicy::Warn => refuse("not committing a secret without asking"),
+    }
+}
+
+/// Print existing tags that fit `data` and, when run interactively, add the
+/// ones picked by number to `tags`
+fn pick_suggested_tags(data: &str, mut tags: Vec<String>) -> Vec<String> {
+    let suggestions: Vec<(String, f32)> = suggest_tags(data, 5 + tags.len())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(tag, _)| !tags.contains(tag))
+        .take(5)
+        .collect();
+    if suggestions.is_empty() {
+        say!("{}", "No tag suggestions.".bright_black());
+        return tags;
+    }
+
+    say!("{}", "Suggested tags:".cyan());
+    for (i, (tag, score)) in suggestions.iter().enumerate() {
+        say!("  {} {} {}", format!("{}.", i + 1).bright_black(), tag.yellow(), format!("({:.2})", score).bright_black());
+    }
+    if !std::io::stdin().is_terminal() {
+        return tags;
+    }
+    let answer = output::ask(format_args!("Add which? (e.g. 1 3, Enter for none):"));
+    for pick in answer.split(|c: char| c.is_whitespace() || c == ',') {
+        if let Some((tag, _)) = pick.parse::<usize>().ok().and_then(|n| suggestions.get(n.wrapping_sub(1))) {
+            tags.push(tag.clone());
+        }
+    }
+    tags
+}
+
+/// Ask before removing something for good, exiting unless the answer is
+/// yes; scripts without a terminal aren't asked
+fn confirm_purge(question: &str) {
+    if !std::io::stdin().is_terminal() {
+        return;
+    }
+    if !output::confirm(format_args!("{}", question), false) {
+        say!("{}", "Aborted.".yellow());
+        std::process::exit(output::EXIT_FAILURE);
+    }
+}
+
+/// Ask before `wk switch` creates the wiki `name`, offering an existing wiki
+/// with a similar name instead. Returns the wiki to switch to; exits if the
+/// user declines, or can't be asked because stdin isn't a terminal.
+fn confirm_new_wiki(name: String, use_global: bool) -> String {
+    let suggestion = wikis::near_misses(&name, use_global).ok().and_then(|n| n.into_iter().next());
+    if !std::io::stdin().is_terminal() {
+        let e = match suggestion {
+            Some(existing) => format!("no wiki named '{}' (did you mean '{}'?); pass --create to create it", name, existing),
+            None => format!("no wiki named '{}'; pass --create to create it", name),
+        };
+        output::fail_with(output::EXIT_NOT_FOUND, e)
+    }
+
+    let answer = match &suggestion {
+        Some(existing) => {
+            output::ask(format_args!("No wiki named '{}'. Did you mean '{}'? [Y/n/create]", name.yellow(), existing.green()))
+        }
+        None => output::ask(format_args!("No wiki named '{}'. Create it? [y/N]", name.yellow())),
+    };
+    match (answer.to_lowercase().as_str(), suggestion) {
+        ("" | "y" | "yes", Some(existing)) => existing,
+        ("c" | "create", Some(_)) | ("y" | "yes", None) => name,
+        _ => {
+            say!("{}", "Aborted.".yellow());
+            std::process::exit(output::EXIT_FAILURE);
+        }
+    }
 }
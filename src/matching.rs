@@ -0,0 +1,122 @@
+//! How queries are fuzzy matched, set by the `[match]` table of the config:
+//!
+//! ```toml
+//! [match]
+//! case = "smart"
+//! prefer_word_boundaries = false
+//! fold_diacritics = true
+//! fold_patterns = true
+//! ```
+//!
+//! Case is ignored by default. `smart` respects it only in queries with a
+//! capital letter, so `HashMap` finds code using the type and not prose
+//! about hashmaps, and `sensitive` always respects it. Matches right after
+//! a space or the start of a word score higher than ones inside a word,
+//! which suits prose. Without `prefer_word_boundaries`, that bonus goes to
+//! matches after a `/` or `:` instead, which suits paths and code.
+//!
+//! With `fold_diacritics`, queries and the text they're matched against are
+//! both [folded](crate::fold), so `cafe` finds `café` and `résumé` finds
+//! `resume`. `fold_patterns` does the same for exact recall, `wk grep` and
+//! the TUI's regex filter, which then also ignore case.
+
+use nucleo_matcher::Matcher;
+use serde::Deserialize;
+use std::borrow::Cow;
+
+use crate::config::Config;
+use crate::fold::fold;
+
+/// Whether queries match regardless of case
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Case {
+    #[default]
+    Insensitive,
+    Sensitive,
+    /// Sensitive to case only in queries with an uppercase letter
+    Smart,
+}
+
+/// The `[match]` table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct MatchConfig {
+    pub case: Case,
+    /// Favour matches at the start of words over those after path separators
+    pub prefer_word_boundaries: bool,
+    /// Fold away diacritics before fuzzy matching; recall then scores every
+    /// fact rather than those the search index picks out
+    pub fold_diacritics: bool,
+    /// Ignore case and fold away diacritics in exact and regex searches
+    pub fold_patterns: bool,
+}
+
+impl Default for MatchConfig {
+    fn default() -> Self {
+        MatchConfig { case: Case::Insensitive, prefer_word_boundaries: true, fold_diacritics: false, fold_patterns: false }
+    }
+}
+
+impl MatchConfig {
+    /// Whether `query` matches regardless of case
+    pub fn ignores_case(&self, query: &str) -> bool {
+        match self.case {
+            Case::Insensitive => true,
+            Case::Sensitive => false,
+            Case::Smart => !query.chars().any(char::is_uppercase),
+        }
+    }
+
+    /// The matcher's settings for `query`
+    pub fn nucleo_config(&self, query: &str) -> nucleo_matcher::Config {
+        let mut config = nucleo_matcher::Config::DEFAULT;
+        config.ignore_case = self.ignores_case(query);
+        if !self.prefer_word_boundaries {
+            config.set_match_paths();
+        }
+        config
+    }
+
+    /// `query` as the matcher expects it: folded to lowercase when case is
+    /// ignored, since it only folds the haystack, and without diacritics
+    /// when they're folded
+    pub fn needle(&self, query: &str) -> String {
+        let query = self.haystack(query);
+        match self.ignores_case(&query) {
+            true => query.to_lowercase(),
+            false => query.into_owned(),
+        }
+    }
+
+    /// `text` as it's handed to the fuzzy matcher
+    pub fn haystack<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        match self.fold_diacritics {
+            true => fold(text),
+            false => Cow::Borrowed(text),
+        }
+    }
+
+    /// `text` as exact and regex searches see it
+    pub fn pattern_text<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        match self.fold_patterns {
+            true => fold(text),
+            false => Cow::Borrowed(text),
+        }
+    }
+
+    /// A regex for `pattern` as [`MatchConfig::fold_patterns`] asks, to be
+    /// matched against [`MatchConfig::pattern_text`]; `ignore_case` is
+    /// overridden to true when folding
+    pub fn regex(&self, pattern: &str, ignore_case: bool) -> Result<regex::Regex, regex::Error> {
+        regex::RegexBuilder::new(&self.pattern_text(pattern))
+            .case_insensitive(ignore_case || self.fold_patterns)
+            .build()
+    }
+}
+
+/// A matcher for `query` set up as the wiki's `config` asks; give it
+/// [`MatchConfig::needle`] of the query
+pub fn make_matcher(config: &Config, query: &str) -> Matcher {
+    Matcher::new(config.matching.nucleo_config(query))
+}
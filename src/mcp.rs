@@ -0,0 +1,271 @@
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::io::{BufRead, Write};
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use uuid::Uuid;
+
+use crate::error::WikiError;
+use crate::wiki::{Fields, Wiki};
+use crate::window::TimeWindow;
+
+/// Protocol revisions this server can speak, newest first
+const PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+
+/// Results returned by `recall` when no limit is given
+const DEFAULT_LIMIT: usize = 20;
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+/// Raised by the wiki itself; `data.kind` says which [`WikiError`] it was
+const WIKI_ERROR: i64 = -32000;
+
+/// A JSON-RPC error on its way to the client
+struct RpcError {
+    code: i64,
+    message: String,
+    data: Option<Value>,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        let mut error = json!({ "code": self.code, "message": self.message });
+        if let Some(data) = &self.data {
+            error["data"] = data.clone();
+        }
+        error
+    }
+}
+
+impl From<WikiError> for RpcError {
+    fn from(e: WikiError) -> Self {
+        // Whatever identifies the fact, wiki or snapshot involved, beside the kind
+        let mut data = match &e {
+            WikiError::NotFound(id) | WikiError::NotTrashed(id) => json!({ "id": id }),
+            WikiError::Conflict { id, expected, found } => json!({ "id": id, "expected": expected, "found": found }),
+            WikiError::PartialCommit { committed, .. } => json!({ "committed": committed }),
+            WikiError::UnknownWiki(name)
+            | WikiError::WikiExists(name)
+            | WikiError::InvalidWikiName(name)
+            | WikiError::Encrypted(name)
+            | WikiError::NotEncrypted(name)
+            | WikiError::ReadOnly(name) => json!({ "name": name }),
+            WikiError::DryRun(what) => json!({ "operation": what }),
+            WikiError::SnapshotExists { id, label } | WikiError::NoSnapshot { id, label } => {
+                json!({ "id": id, "label": label })
+            }
+            WikiError::HookRejected(reason) => json!({ "reason": reason }),
+            WikiError::TagsRequired { wiki, config } => json!({ "wiki": wiki, "config": config }),
+            WikiError::Invalid(e) => json!({ "field": e.field.to_string(), "value": e.value, "problem": e.problem.to_string() }),
+            WikiError::TooLarge { len, max } => json!({ "len": len, "max": max }),
+            _ => json!({}),
+        };
+        data["kind"] = json!(e.kind());
+        RpcError {
+            code: WIKI_ERROR,
+            message: e.to_string(),
+            data: Some(data),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CommitArgs {
+    text: String,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RecallArgs {
+    #[serde(default)]
+    query: Option<String>,
+    #[serde(default)]
+    tag: Option<String>,
+    #[serde(default)]
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GetArgs {
+    id: Uuid,
+}
+
+/// Speak the Model Context Protocol over newline-delimited JSON-RPC until
+/// `input` ends, exposing the wiki as a small set of tools.
+///
+/// Requests are answered one at a time in the order they arrive. Malformed
+/// input gets an error response rather than ending the session; only a
+/// failure to write to `output` does.
+pub fn serve(wiki: &mut Wiki, input: impl BufRead, mut output: impl Write) -> std::io::Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match serde_json::from_str::<Value>(&line) {
+            Ok(Value::Array(batch)) if !batch.is_empty() => {
+                let replies: Vec<Value> = batch.into_iter().filter_map(|message| handle(wiki, message)).collect();
+                (!replies.is_empty()).then_some(Value::Array(replies))
+            }
+            Ok(message) => handle(wiki, message),
+            Err(e) => Some(response(Value::Null, Err(RpcError::new(PARSE_ERROR, e.to_string())))),
+        };
+        if let Some(reply) = reply {
+            serde_json::to_writer(&mut output, &reply)?;
+            output.write_all(b"\n")?;
+            output.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Answer one message; `None` for notifications, which get no response
+fn handle(wiki: &mut Wiki, message: Value) -> Option<Value> {
+    let id = message.get("id").cloned();
+    let method = message.get("method").and_then(Value::as_str);
+    let Some(method) = method.filter(|_| message.get("jsonrpc") == Some(&json!("2.0"))) else {
+        return Some(response(
+            id.unwrap_or(Value::Null),
+            Err(RpcError::new(INVALID_REQUEST, "expected a JSON-RPC 2.0 request")),
+        ));
+    };
+    let params = message.get("params").cloned().unwrap_or(Value::Null);
+
+    let result = catch_unwind(AssertUnwindSafe(|| dispatch(wiki, method, params)))
+        .unwrap_or_else(|_| Err(RpcError::new(INTERNAL_ERROR, "internal error")));
+    id.map(|id| response(id, result))
+}
+
+fn dispatch(wiki: &mut Wiki, method: &str, params: Value) -> Result<Value, RpcError> {
+    match method {
+        "initialize" => {
+            let requested = params.get("protocolVersion").and_then(Value::as_str);
+            let version = requested
+                .filter(|v| PROTOCOL_VERSIONS.contains(v))
+                .unwrap_or(PROTOCOL_VERSIONS[0]);
+            Ok(json!({
+                "protocolVersion": version,
+                "capabilities": { "tools": {} },
+                "serverInfo": { "name": "wk", "version": env!("CARGO_PKG_VERSION") },
+                "instructions": format!("Long-term memory backed by the '{}' wiki. Commit facts worth remembering and recall them later.", wiki.name),
+            }))
+        }
+        "ping" => Ok(json!({})),
+        "tools/list" => Ok(json!({ "tools": tools() })),
+        "tools/call" => {
+            let name = params
+                .get("name")
+                .and_then(Value::as_str)
+                .ok_or_else(|| RpcError::new(INVALID_PARAMS, "missing tool name"))?;
+            let args = params.get("arguments").cloned().unwrap_or_else(|| json!({}));
+            let result = call(wiki, name, args)?;
+            Ok(json!({
+                "content": [{ "type": "text", "text": serde_json::to_string_pretty(&result).unwrap_or_default() }],
+                "structuredContent": { "result": result },
+                "isError": false,
+            }))
+        }
+        _ if method.starts_with("notifications/") => Ok(Value::Null),
+        _ => Err(RpcError::new(METHOD_NOT_FOUND, format!("unknown method '{}'", method))),
+    }
+}
+
+fn call(wiki: &mut Wiki, tool: &str, args: Value) -> Result<Value, RpcError> {
+    match tool {
+        "commit_fact" => {
+            let args: CommitArgs = parse_args(args)?;
+            let id = wiki.commit(args.text, args.tags)?;
+            Ok(json!(wiki.get(id)?))
+        }
+        "recall" => {
+            let args: RecallArgs = parse_args(args)?;
+            let limit = args.limit.unwrap_or(DEFAULT_LIMIT);
+            let facts = match (args.query.filter(|q| !q.is_empty()), &args.tag) {
+                (Some(query), tag) => wiki
+                    .recall_refs(&query, tag.as_deref(), Fields::ALL, TimeWindow::ANY, Some(limit))
+                    .into_iter()
+                    .map(|hit| (*hit).clone())
+                    .collect(),
+                (None, Some(tag)) => wiki.recall_by_tag(tag).into_iter().take(limit).collect(),
+                (None, None) => wiki.all().into_iter().take(limit).collect::<Vec<_>>(),
+            };
+            Ok(json!(facts))
+        }
+        "list_tags" => Ok(json!(wiki.tags())),
+        "get_fact" => {
+            let args: GetArgs = parse_args(args)?;
+            Ok(json!(wiki.get(args.id)?))
+        }
+        _ => Err(RpcError::new(INVALID_PARAMS, format!("unknown tool '{}'", tool))),
+    }
+}
+
+fn parse_args<T: for<'de> Deserialize<'de>>(args: Value) -> Result<T, RpcError> {
+    serde_json::from_value(args).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error.to_json() }),
+    }
+}
+
+/// Descriptions and argument schemas of every tool
+fn tools() -> Value {
+    json!([
+        {
+            "name": "commit_fact",
+            "description": "Remember a fact. Returns the stored fact with its id.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "text": { "type": "string", "description": "The fact to remember" },
+                    "tags": { "type": "array", "items": { "type": "string" }, "description": "Tags to file it under" }
+                },
+                "required": ["text"]
+            }
+        },
+        {
+            "name": "recall",
+            "description": "Fuzzy-search remembered facts, optionally only those with a tag. Without a query, lists facts.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string" },
+                    "tag": { "type": "string" },
+                    "limit": { "type": "integer", "minimum": 0, "default": DEFAULT_LIMIT }
+                }
+            }
+        },
+        {
+            "name": "list_tags",
+            "description": "Every tag in use, with how many facts carry it.",
+            "inputSchema": { "type": "object", "properties": {} }
+        },
+        {
+            "name": "get_fact",
+            "description": "Read one fact in full by id.",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "id": { "type": "string", "format": "uuid" } },
+                "required": ["id"]
+            }
+        }
+    ])
+}
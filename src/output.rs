@@ -0,0 +1,209 @@
+//! Whether `wk` writes colour, decided once from `--color`, `NO_COLOR` and
+//! whether stdout and stderr are terminals, how much it says besides its
+//! results, from `--quiet` and `--verbose`, and how it exits when it fails.
+//!
+//! Only results go to stdout; everything said to the person running `wk`
+//! goes to stderr.
+
+use colored::Colorize;
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+/// When to colour output
+#[derive(Clone, Copy, Default, PartialEq, clap::ValueEnum)]
+pub enum ColorMode {
+    /// When writing to a terminal and `NO_COLOR` isn't set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+static STDOUT_COLOR: AtomicBool = AtomicBool::new(false);
+static STDERR_COLOR: AtomicBool = AtomicBool::new(false);
+
+/// Apply `mode` to everything printed from here on
+pub fn init(mode: ColorMode) {
+    let enabled = |tty: bool| match mode {
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+        // Any non-empty value counts, see https://no-color.org
+        ColorMode::Auto => tty && std::env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()),
+    };
+    let stdout = enabled(std::io::stdout().is_terminal());
+    STDOUT_COLOR.store(stdout, Ordering::Relaxed);
+    STDERR_COLOR.store(enabled(std::io::stderr().is_terminal()), Ordering::Relaxed);
+    colored::control::set_override(stdout);
+}
+
+/// Format something for stderr, coloured only if stderr should be; colours
+/// otherwise follow stdout
+pub fn on_stderr(format: impl FnOnce() -> String) -> String {
+    colored::control::set_override(STDERR_COLOR.load(Ordering::Relaxed));
+    let text = format();
+    colored::control::set_override(STDOUT_COLOR.load(Ordering::Relaxed));
+    text
+}
+
+/// `eprintln!` with colours decided by [`on_stderr`]
+macro_rules! eprintln_colored {
+    ($($arg:tt)*) => {
+        eprintln!("{}", $crate::output::on_stderr(|| format!($($arg)*)))
+    };
+}
+pub(crate) use eprintln_colored;
+
+/// How much to print besides results and errors
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+pub enum Level {
+    /// Results only, as bare as they come: no confirmations, notes or warnings
+    Quiet,
+    Normal,
+    /// Also where the wiki was found, what went wrong loading it and how long
+    /// things took, on stderr
+    Verbose,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Normal as u8);
+
+/// Print at `level` from here on
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn level() -> Level {
+    match LEVEL.load(Ordering::Relaxed) {
+        0 => Level::Quiet,
+        1 => Level::Normal,
+        _ => Level::Verbose,
+    }
+}
+
+/// A line on stderr unless `--quiet`, for confirmations, notes and hints
+/// around results
+macro_rules! say {
+    () => {
+        if $crate::output::level() >= $crate::output::Level::Normal {
+            eprintln!()
+        }
+    };
+    ($($arg:tt)*) => {
+        if $crate::output::level() >= $crate::output::Level::Normal {
+            $crate::output::eprintln_colored!($($arg)*)
+        }
+    };
+}
+pub(crate) use say;
+
+/// A warning on stderr, unless `--quiet`
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        if $crate::output::level() >= $crate::output::Level::Normal {
+            $crate::output::eprintln_colored!("{} {}", "Warning:".yellow().bold(), format!($($arg)*))
+        }
+    };
+}
+pub(crate) use warning;
+
+/// A dimmed line on stderr with `--verbose` only, for working out what `wk` did
+macro_rules! detail {
+    ($($arg:tt)*) => {
+        if $crate::output::level() >= $crate::output::Level::Verbose {
+            $crate::output::eprintln_colored!("{}", format!($($arg)*).dimmed())
+        }
+    };
+}
+pub(crate) use detail;
+
+/// Ask `question` on stderr and read the answer from stdin, trimmed; empty if
+/// nothing could be read
+pub fn ask(question: std::fmt::Arguments) -> String {
+    eprint!("{} ", on_stderr(|| question.to_string()));
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).ok();
+    answer.trim().to_string()
+}
+
+/// Ask a yes-or-no `question`, taking Enter as `default`; anything but a
+/// yes is a no
+pub fn confirm(question: std::fmt::Arguments, default: bool) -> bool {
+    let choices = if default { "[Y/n]" } else { "[y/N]" };
+    match ask(format_args!("{} {}", question, choices)).to_lowercase().as_str() {
+        "" => default,
+        answer => matches!(answer, "y" | "yes"),
+    }
+}
+
+/// Ask `question`, showing `default` and taking Enter as it; with no
+/// default, Enter answers with nothing
+pub fn prompt(question: std::fmt::Arguments, default: Option<&str>) -> String {
+    let answer = match default.filter(|default| !default.is_empty()) {
+        Some(default) => ask(format_args!("{} [{}]", question, default.bright_black())),
+        None => ask(question),
+    };
+    match (answer.is_empty(), default) {
+        (true, Some(default)) => default.to_string(),
+        _ => answer,
+    }
+}
+
+/// Something went wrong that has no more particular status
+pub const EXIT_FAILURE: i32 = 1;
+/// The command line asked for something that can't be done, as clap exits with
+pub const EXIT_USAGE: i32 = 2;
+/// The fact, snapshot or wiki asked for doesn't exist
+pub const EXIT_NOT_FOUND: i32 = 3;
+/// Something else got there first: the fact changed, the name is taken or
+/// another `wk` holds the lock
+pub const EXIT_CONFLICT: i32 = 4;
+
+/// An error `wk` can stop on, and the status to exit with for it
+pub trait Failure: std::fmt::Display {
+    fn exit_code(&self) -> i32 {
+        EXIT_FAILURE
+    }
+}
+
+impl Failure for String {}
+impl Failure for &str {}
+impl Failure for std::io::Error {}
+impl Failure for Box<dyn std::error::Error> {}
+
+/// Print `e` as an error and exit with its [`Failure::exit_code`]
+pub fn fail(e: impl Failure) -> ! {
+    fail_with(e.exit_code(), e)
+}
+
+/// Print `e` as an error and exit with status `code`
+pub fn fail_with(code: i32, e: impl std::fmt::Display) -> ! {
+    eprintln_colored!("{} {}", "Error:".red().bold(), e);
+    std::process::exit(code)
+}
+
+/// Print `text` to stdout, through `$PAGER` (`less -R` by default) when
+/// stdout is a terminal the text doesn't fit on and `pager` is set. Falls back
+/// to printing directly if the pager can't be started.
+pub fn page(text: &str, pager: bool) {
+    let tall = pager
+        && std::io::stdout().is_terminal()
+        && crossterm::terminal::size().is_ok_and(|(_, rows)| text.lines().count() >= rows as usize);
+    if tall && let Some(mut child) = spawn_pager() {
+        if let Some(mut stdin) = child.stdin.take() {
+            // The pager may be quit before reading everything
+            stdin.write_all(text.as_bytes()).ok();
+        }
+        child.wait().ok();
+        return;
+    }
+    print!("{}", text);
+}
+
+fn spawn_pager() -> Option<std::process::Child> {
+    let pager = std::env::var("PAGER").unwrap_or_else(|_| "less -R".to_string());
+    let mut words = twk::editor::split_command(&pager).into_iter();
+    std::process::Command::new(words.next()?)
+        .args(words)
+        .stdin(std::process::Stdio::piped())
+        .spawn()
+        .ok()
+}
@@ -0,0 +1,102 @@
+//! PDFs of the wiki's book, for `wk book --pdf`.
+//!
+//! mdbook has no PDF output of its own, so an installed renderer does the
+//! work: `mdbook-pdf` or else `mdbook-typst`, added to the book's
+//! `book.toml` alongside the HTML output. Without either, the book's pages
+//! are joined into one Markdown file for pandoc, each starting a new page.
+
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+/// The file the book's pages are joined into when no renderer is installed
+pub const MARKDOWN_FILE: &str = "wiki.md";
+
+/// Starts a page in LaTeX, which pandoc passes through when making a PDF
+pub const PAGE_BREAK: &str = "\\newpage";
+
+/// An mdbook renderer that makes PDFs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PdfBackend {
+    /// `mdbook-pdf`, printing the HTML book with a headless Chrome
+    Pdf,
+    /// `mdbook-typst`, typesetting the Markdown with Typst
+    Typst,
+}
+
+impl PdfBackend {
+    /// In the order they're looked for
+    pub const ALL: [PdfBackend; 2] = [PdfBackend::Pdf, PdfBackend::Typst];
+
+    /// The renderer's name, as its `[output.<name>]` table and output folder
+    pub fn name(self) -> &'static str {
+        match self {
+            PdfBackend::Pdf => "pdf",
+            PdfBackend::Typst => "typst",
+        }
+    }
+
+    /// The program mdbook runs for it
+    pub fn program(self) -> String {
+        format!("mdbook-{}", self.name())
+    }
+
+    /// What it adds to `book.toml`
+    fn config(self) -> &'static str {
+        match self {
+            PdfBackend::Pdf => "[output.pdf]\n",
+            PdfBackend::Typst => "[output.typst]\n\n[output.typst.output]\nformat = \"pdf\"\n",
+        }
+    }
+}
+
+/// The first renderer found on `path`, a list of directories like `$PATH`
+pub fn detect(path: Option<&OsStr>) -> Option<PdfBackend> {
+    let dirs: Vec<PathBuf> = path.map(|path| std::env::split_paths(path).collect()).unwrap_or_default();
+    PdfBackend::ALL.into_iter().find(|backend| {
+        let program = format!("{}{}", backend.program(), std::env::consts::EXE_SUFFIX);
+        dirs.iter().any(|dir| dir.join(&program).is_file())
+    })
+}
+
+/// `book_toml` with `backend` configured, keeping the HTML output it may
+/// render from
+pub fn with_backend(book_toml: &str, backend: PdfBackend) -> String {
+    let mut toml = book_toml.trim_end().to_string();
+    toml.push_str("\n\n");
+    toml.push_str(backend.config());
+    toml
+}
+
+/// `pages` joined into one document, each after the first on a new page
+pub fn concatenate(pages: &[String]) -> String {
+    let pages: Vec<&str> = pages.iter().map(|page| page.trim_end()).collect();
+    let mut joined = pages.join(&format!("\n\n{}\n\n", PAGE_BREAK));
+    joined.push('\n');
+    joined
+}
+
+/// The PDF a renderer wrote under `dir`, or `dir` itself if none turns up
+pub fn find_pdf(dir: &Path) -> PathBuf {
+    std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .find(|path| path.extension().is_some_and(|ext| ext == "pdf"))
+        .unwrap_or_else(|| dir.to_path_buf())
+}
+
+/// What [`crate::Wiki::generate_pdf`] made
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PdfOutput {
+    /// A PDF rendered by `backend`
+    Built { backend: PdfBackend, path: PathBuf },
+    /// The pages joined into one Markdown file, for [`pandoc_command`]
+    Markdown(PathBuf),
+}
+
+/// The pandoc command turning `markdown` into a PDF beside it
+pub fn pandoc_command(markdown: &Path) -> String {
+    let quote = |path: &Path| format!("\"{}\"", path.display());
+    format!("pandoc {} -o {}", quote(markdown), quote(&markdown.with_extension("pdf")))
+}
@@ -0,0 +1,181 @@
+//! A small fzf-like picker drawn inline on stderr, for `wk r --pick`, so
+//! stdout stays free for the chosen fact
+
+use colored::Colorize;
+use crossterm::{
+    cursor::{MoveToColumn, MoveUp},
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    queue,
+    style::Print,
+    terminal::{self, Clear, ClearType},
+};
+use nucleo_matcher::{Matcher, Utf32Str};
+use std::io::{self, Write};
+use std::panic::{self, PanicHookInfo};
+use std::sync::Arc;
+use twk::Information;
+use twk::matching::MatchConfig;
+use unicode_width::UnicodeWidthStr;
+
+use crate::output;
+use crate::table;
+
+/// Most candidates listed under the prompt at once
+const MAX_ROWS: usize = 10;
+const PROMPT: &str = "> ";
+
+/// Raw mode for as long as the picker is up, restored and the picker erased
+/// however it ends, panics included
+struct RawMode {
+    /// The panic hook from before, put back when raw mode ends
+    previous: Arc<PanicHook>,
+}
+
+type PanicHook = Box<dyn Fn(&PanicHookInfo<'_>) + Sync + Send + 'static>;
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        let previous: Arc<PanicHook> = Arc::new(panic::take_hook());
+        let chained = Arc::clone(&previous);
+        panic::set_hook(Box::new(move |info| {
+            // Before the message, so it isn't printed with raw line endings
+            terminal::disable_raw_mode().ok();
+            chained(info);
+        }));
+        if let Err(e) = terminal::enable_raw_mode() {
+            restore_hook(previous);
+            return Err(e);
+        }
+        Ok(RawMode { previous })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let mut stderr = io::stderr();
+        queue!(stderr, MoveToColumn(0), Clear(ClearType::FromCursorDown)).ok();
+        stderr.flush().ok();
+        terminal::disable_raw_mode().ok();
+        // The hook can't be changed while unwinding, and ours is harmless then
+        if !std::thread::panicking() {
+            restore_hook(Arc::clone(&self.previous));
+        }
+    }
+}
+
+/// Make `previous` the panic hook again, in place of the one chaining to it
+fn restore_hook(previous: Arc<PanicHook>) {
+    panic::set_hook(Box::new(move |info| previous(info)));
+}
+
+/// Let the user narrow `candidates` by typing, starting from `query`, and
+/// pick one, matching as `wk r` does. `None` if they gave up with Esc or Ctrl-C.
+pub fn pick(candidates: &[Information], query: &str, matching: &MatchConfig) -> io::Result<Option<usize>> {
+    let rows = candidates.len().clamp(1, MAX_ROWS);
+    let mut input = query.to_string();
+    let mut selected = 0;
+
+    let _raw = RawMode::enable()?;
+    let mut stderr = io::stderr();
+    // Make room below the prompt, scrolling if the cursor is near the bottom
+    queue!(stderr, Print("\r\n".repeat(rows)), MoveUp(rows as u16))?;
+
+    loop {
+        let matches = filter(matching, candidates, &input);
+        selected = selected.min(matches.len().saturating_sub(1));
+        draw(&mut stderr, candidates, &matches, selected, &input, rows)?;
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => return Ok(None),
+            KeyCode::Char('c') if ctrl => return Ok(None),
+            KeyCode::Enter => return Ok(matches.get(selected).copied()),
+            KeyCode::Up => selected = selected.saturating_sub(1),
+            KeyCode::Char('p' | 'k') if ctrl => selected = selected.saturating_sub(1),
+            KeyCode::Down => selected += 1,
+            KeyCode::Char('n' | 'j') if ctrl => selected += 1,
+            KeyCode::Backspace => {
+                input.pop();
+                selected = 0;
+            }
+            KeyCode::Char(c) if !ctrl => {
+                input.push(c);
+                selected = 0;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Indices of the candidates matching `input`, best first; all of them, in
+/// order, if it is empty
+fn filter(matching: &MatchConfig, candidates: &[Information], input: &str) -> Vec<usize> {
+    if input.is_empty() {
+        return (0..candidates.len()).collect();
+    }
+    // Set up afresh as the input changes, since smart case depends on it
+    let mut matcher = Matcher::new(matching.nucleo_config(input));
+    let mut needle_buf = Vec::new();
+    let mut haystack_buf = Vec::new();
+    let input = matching.needle(input);
+    let needle = Utf32Str::new(&input, &mut needle_buf);
+
+    let mut scored: Vec<(u16, usize)> = candidates
+        .iter()
+        .enumerate()
+        .filter_map(|(i, info)| {
+            let haystack = format!("{} {}", info.data, info.tags.join(" "));
+            matcher
+                .fuzzy_match(Utf32Str::new(&matching.haystack(&haystack), &mut haystack_buf), needle)
+                .map(|score| (score, i))
+        })
+        .collect();
+    scored.sort_by_key(|&(score, i)| (std::cmp::Reverse(score), i));
+    scored.into_iter().map(|(_, i)| i).collect()
+}
+
+fn draw(
+    out: &mut impl Write,
+    candidates: &[Information],
+    matches: &[usize],
+    selected: usize,
+    input: &str,
+    rows: usize,
+) -> io::Result<()> {
+    let width = terminal::size().map(|(w, _)| w as usize).unwrap_or(80).max(10) - 1;
+    // Scroll the list so the selection stays visible
+    let first = (selected + 1).saturating_sub(rows);
+
+    queue!(out, MoveToColumn(0), Clear(ClearType::FromCursorDown))?;
+    let count = format!("  {}/{}", matches.len(), candidates.len());
+    let prompt = table::truncate(&format!("{}{}", PROMPT, input), width.saturating_sub(count.width()));
+    queue!(out, Print(output::on_stderr(|| format!("{}{}", prompt, count.bright_black()))))?;
+
+    for (row, &i) in matches.iter().enumerate().skip(first).take(rows) {
+        let info = &candidates[i];
+        let name = info.name.lines().next().unwrap_or_default();
+        let tags = info.tags.iter().map(|t| format!("[{}]", t)).collect::<Vec<_>>().join(" ");
+        let line = table::truncate(&format!("  {} {}", name, tags), width);
+        let line = output::on_stderr(|| {
+            if row == selected {
+                format!("{}{}", ">".green().bold(), &line[1..]).bold().to_string()
+            } else {
+                line.normal().to_string()
+            }
+        });
+        queue!(out, Print("\r\n"), Print(line))?;
+    }
+
+    let shown = matches.len().saturating_sub(first).min(rows);
+    if shown > 0 {
+        queue!(out, MoveUp(shown as u16))?;
+    }
+    queue!(out, MoveToColumn(prompt.width() as u16))?;
+    out.flush()
+}
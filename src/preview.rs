@@ -0,0 +1,99 @@
+//! The line of a fact shown beside its name in the TUI list and in `wk r
+//! --format table`.
+//!
+//! Facts often open with a heading or a blank line, so the first line of
+//! their data says little. [`preview_line`] looks past those for the first
+//! line of prose, or, while the list is filtered, for the line that matched.
+
+use nucleo_matcher::{Matcher, Utf32Str};
+use regex::Regex;
+use std::cell::RefCell;
+
+use crate::matching::MatchConfig;
+
+/// What a list is filtered by, for finding the line of a fact that matched
+pub enum LineMatcher {
+    Regex(Regex),
+    /// A fuzzy query, folded to lowercase if case is ignored, and the
+    /// config's matching to fold each line by
+    Fuzzy { query: String, matcher: RefCell<Matcher>, config: MatchConfig },
+}
+
+impl LineMatcher {
+    pub fn regex(re: Regex) -> Self {
+        LineMatcher::Regex(re)
+    }
+
+    pub fn fuzzy(query: &str) -> Self {
+        Self::fuzzy_with(query, &MatchConfig::default())
+    }
+
+    /// A fuzzy query matched as a wiki's `[match]` config asks
+    pub fn fuzzy_with(query: &str, config: &MatchConfig) -> Self {
+        LineMatcher::Fuzzy {
+            query: config.needle(query),
+            matcher: RefCell::new(Matcher::new(config.nucleo_config(query))),
+            config: *config,
+        }
+    }
+
+    /// How well `line` matches, higher being better; `None` if it doesn't
+    fn score(&self, line: &str) -> Option<u16> {
+        match self {
+            LineMatcher::Regex(re) => re.is_match(line).then_some(1),
+            LineMatcher::Fuzzy { query, matcher, config } => {
+                let (mut needle_buf, mut haystack_buf) = (Vec::new(), Vec::new());
+                let line = config.haystack(line);
+                matcher
+                    .borrow_mut()
+                    .fuzzy_match(Utf32Str::new(&line, &mut haystack_buf), Utf32Str::new(query, &mut needle_buf))
+            }
+        }
+    }
+}
+
+/// The line of `data` to show as its preview, trimmed.
+///
+/// With a `filter`, that is the line matching it best, the first of those
+/// for a regex. Otherwise, or if no line matches, it is the first line with
+/// letters in it that isn't a heading or code fence; failing that the first
+/// heading without its `#` markers, then the first line with anything in it.
+pub fn preview_line(data: &str, filter: Option<&LineMatcher>) -> String {
+    let lines = || data.lines().map(str::trim).filter(|line| !line.is_empty());
+
+    if let Some(filter) = filter {
+        let mut best: Option<(u16, &str)> = None;
+        for line in lines() {
+            if let Some(score) = filter.score(line)
+                && best.is_none_or(|(top, _)| score > top)
+            {
+                best = Some((score, line));
+            }
+        }
+        if let Some((_, line)) = best {
+            return strip_heading(line).to_string();
+        }
+    }
+
+    let is_fence = |line: &str| line.starts_with("```") || line.starts_with("~~~");
+    lines()
+        .find(|&line| !is_heading(line) && !is_fence(line) && line.chars().any(char::is_alphabetic))
+        .or_else(|| lines().filter(|&line| is_heading(line)).map(strip_heading).find(|heading| !heading.is_empty()))
+        .or_else(|| lines().find(|&line| !strip_heading(line).is_empty()))
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Whether `line` is a Markdown heading like `## Notes`; a bare `#tag` isn't
+fn is_heading(line: &str) -> bool {
+    let rest = line.trim_start_matches('#');
+    line.len() - rest.len() <= 6 && rest.len() < line.len() && (rest.is_empty() || rest.starts_with(' '))
+}
+
+/// `line` without the markers of a heading, if it is one
+fn strip_heading(line: &str) -> &str {
+    match is_heading(line) {
+        true => line.trim_start_matches('#').trim(),
+        false => line,
+    }
+}
@@ -0,0 +1,20 @@
+/// Receives the progress of an operation over many facts, such as building
+/// the book or rebuilding the search index.
+///
+/// `start` is called once with the number of steps, `tick` after each step
+/// with a short description of it, and `finish` once the operation is over,
+/// whether or not it succeeded.
+pub trait Progress {
+    fn start(&mut self, total: usize);
+    fn tick(&mut self, msg: &str);
+    fn finish(&mut self);
+}
+
+/// Reports nothing, for quiet or non-interactive use
+pub struct NoProgress;
+
+impl Progress for NoProgress {
+    fn start(&mut self, _total: usize) {}
+    fn tick(&mut self, _msg: &str) {}
+    fn finish(&mut self) {}
+}
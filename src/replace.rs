@@ -0,0 +1,161 @@
+use regex::Regex;
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::error::WikiError;
+use crate::snapshot::unused_label;
+use crate::wiki::Wiki;
+
+/// Label given to the snapshot [`Wiki::replace`] takes of each fact it changes
+pub const PRE_REPLACE_LABEL: &str = "pre-replace";
+
+/// What [`Wiki::replace`] looks at and whether it writes
+#[derive(Debug, Clone, Default)]
+pub struct ReplaceOptions {
+    /// Replace in names as well as data
+    pub names: bool,
+    /// Save the changes; otherwise only report what they would be
+    pub write: bool,
+    /// Only change the facts with these ids, e.g. those confirmed from a dry run
+    pub only: Option<Vec<Uuid>>,
+}
+
+/// How [`Wiki::replace`] changed, or would change, one fact
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplaceReport {
+    pub id: Uuid,
+    /// The fact's name before replacing
+    pub name: String,
+    /// The name after replacing, if it changed
+    pub new_name: Option<String>,
+    /// Each run of lines of the data that changed, in order
+    pub lines: Vec<ChangedLines>,
+    /// Label of the snapshot of the fact as it was, to restore it from; `None`
+    /// if nothing was written
+    pub snapshot: Option<String>,
+}
+
+/// Lines of a fact's data touched by the same matches, before and after
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangedLines {
+    /// 1-based number of the first line
+    pub line: usize,
+    pub before: String,
+    pub after: String,
+}
+
+impl Wiki {
+    /// Replace every match of `re` in facts' data, and names if asked, with
+    /// `replacement`, in which `$1` or `${name}` stand for capture groups.
+    ///
+    /// Returns a report for each fact that changes, in the wiki's order. With
+    /// `opts.write` each of them is first snapshotted as [`PRE_REPLACE_LABEL`]
+    /// so it can be undone with [`Wiki::restore`], then updated; otherwise
+    /// nothing is touched.
+    pub fn replace(&mut self, re: &Regex, replacement: &str, opts: &ReplaceOptions) -> Result<Vec<ReplaceReport>, WikiError> {
+        if opts.write {
+            self.check_writable()?;
+        }
+        // Held from the plan to the last update, so nothing written meanwhile
+        // is lost; a dry run only plans its updates
+        let _lock = match opts.write && !self.is_dry_run() {
+            true => Some(self.lock_exclusive()?),
+            false => None,
+        };
+        self.hydrate_all()?;
+
+        let mut planned = Vec::new();
+        for locked in &self.info {
+            let info = locked.read();
+            if opts.only.as_ref().is_some_and(|only| !only.contains(&info.id)) {
+                continue;
+            }
+            let (data, lines) = replace_lines(re, replacement, &info.data);
+            let new_name = opts
+                .names
+                .then(|| replace_lines(re, replacement, &info.name).0)
+                .filter(|name| *name != info.name);
+            if !lines.is_empty() || new_name.is_some() {
+                let report = ReplaceReport { id: info.id, name: info.name.clone(), new_name, lines, snapshot: None };
+                planned.push((report, data));
+            }
+        }
+        if !opts.write {
+            return Ok(planned.into_iter().map(|(report, _)| report).collect());
+        }
+
+        let mut reports = Vec::with_capacity(planned.len());
+        for (mut report, data) in planned {
+            let label = unused_label(&self.snapshots(report.id)?, PRE_REPLACE_LABEL);
+            self.snapshot(report.id, Some(&label))?;
+            let new_name = report.new_name.clone();
+            self.update(report.id, |info| {
+                info.data = data;
+                if let Some(name) = new_name {
+                    info.name = name;
+                }
+            })?;
+            report.snapshot = Some(label);
+            reports.push(report);
+        }
+        Ok(reports)
+    }
+}
+
+/// `text` with every match of `re` replaced, and the runs of its lines that
+/// changed. Matches on the same line share a run, as do all the lines a
+/// pattern spans.
+fn replace_lines(re: &Regex, replacement: &str, text: &str) -> (String, Vec<ChangedLines>) {
+    // Each match's byte range with what replaces it
+    let mut matches = Vec::new();
+    for caps in re.captures_iter(text) {
+        let whole = caps.get(0).expect("group 0 is the whole match");
+        let mut with = String::new();
+        caps.expand(replacement, &mut with);
+        matches.push((whole.start(), whole.end(), with));
+    }
+    if matches.is_empty() {
+        return (text.to_string(), Vec::new());
+    }
+
+    // Splice `matches[range]` into `text[from..to]`
+    let splice = |from: usize, to: usize, range: &[(usize, usize, String)]| {
+        let mut out = String::new();
+        let mut at = from;
+        for (start, end, with) in range {
+            out.push_str(&text[at..*start]);
+            out.push_str(with);
+            at = *end;
+        }
+        out.push_str(&text[at..to]);
+        out
+    };
+
+    let mut changes = Vec::new();
+    let mut first = 0;
+    while first < matches.len() {
+        let start = text[..matches[first].0].rfind('\n').map_or(0, |i| i + 1);
+        let mut end = line_end(text, matches[first].1);
+        let mut last = first + 1;
+        while last < matches.len() && matches[last].0 <= end {
+            end = end.max(line_end(text, matches[last].1));
+            last += 1;
+        }
+        let before = &text[start..end];
+        let after = splice(start, end, &matches[first..last]);
+        if before != after {
+            changes.push(ChangedLines {
+                line: text[..start].matches('\n').count() + 1,
+                before: before.to_string(),
+                after,
+            });
+        }
+        first = last;
+    }
+    (splice(0, text.len(), &matches), changes)
+}
+
+/// Where the line holding byte `at` of `text` ends, before its newline
+fn line_end(text: &str, at: usize) -> usize {
+    text[at..].find('\n').map_or(text.len(), |i| at + i)
+}
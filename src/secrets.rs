@@ -0,0 +1,196 @@
+//! Spotting secrets like API keys and passwords in fact text before it is
+//! committed, with built-in patterns and more from the `[secrets]` table of
+//! the config.
+//!
+//! A pattern with a group named `secret` marks only that part as the secret,
+//! so `password=hunter2` is redacted to `password=［redacted］` rather than
+//! losing the key too. Without one the whole match is the secret. Values that
+//! are plainly placeholders, like `$PASSWORD`, `<token>` or `****`, are never
+//! reported.
+
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use uuid::Uuid;
+
+use crate::error::WikiError;
+use crate::wiki::Wiki;
+
+/// What replaces each secret when redacting
+pub const REDACTED: &str = "［redacted］";
+
+/// Patterns every scanner starts with, by name. A pattern of the same name
+/// in the config replaces one of these.
+pub const BUILTIN_PATTERNS: &[(&str, &str)] = &[
+    ("aws_access_key_id", r"\b(?P<secret>(?:AKIA|ASIA|ABIA|ACCA|AGPA|AIDA|AIPA|ANPA|ANVA|AROA|APKA)[0-9A-Z]{16})\b"),
+    (
+        "aws_secret_access_key",
+        r#"(?i)\baws_?secret_?access_?key["']?\s*[=:]\s*["']?(?P<secret>[A-Za-z0-9/+]{40})(?:[^A-Za-z0-9/+=]|$)"#,
+    ),
+    ("private_key", r"-----BEGIN (?:[A-Z0-9]+ )*PRIVATE KEY(?: BLOCK)?-----"),
+    ("github_token", r"\b(?P<secret>(?:gh[pousr]_[A-Za-z0-9]{36,255}|github_pat_[A-Za-z0-9_]{22,255}))\b"),
+    ("slack_token", r"\b(?P<secret>xox[abposr]-[A-Za-z0-9-]{10,})"),
+    // `password = x` anywhere, but `password: x` only where a key would be,
+    // so prose like "reset your password: it's easy" passes
+    ("password", r#"(?i)\b(?:password|passwd|pwd)["']?\s*=\s*["']?(?P<secret>[^\s"'&;,]+)"#),
+    (
+        "password_field",
+        r#"(?im)(?:^[\s-]*|[{,]\s*)["']?(?:password|passwd|pwd)["']?\s*:\s*["']?(?P<secret>[^\s"',}]+)"#,
+    ),
+];
+
+/// What to do with a fact that looks like it holds a secret
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SecretPolicy {
+    /// Say what was found and ask before committing; refused when there is
+    /// no one to ask
+    #[default]
+    Warn,
+    /// Refuse to commit it
+    Block,
+    /// Commit it with each secret replaced by [`REDACTED`]
+    Redact,
+}
+
+/// A secret found in some text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecretMatch {
+    /// Name of the pattern that found it
+    pub pattern: String,
+    /// 1-based line it starts on
+    pub line: usize,
+    /// Byte range of the secret itself
+    pub range: Range<usize>,
+}
+
+/// A secret found in a fact by [`Wiki::scan_secrets`]
+#[derive(Debug, Clone)]
+pub struct SecretHit {
+    pub id: Uuid,
+    pub name: String,
+    /// 1-based line within the fact's data
+    pub line: usize,
+    pub pattern: String,
+}
+
+/// Compiled secret patterns, see the [module docs](self)
+#[derive(Debug, Clone)]
+pub struct Scanner {
+    patterns: Vec<(String, Regex)>,
+}
+
+impl Scanner {
+    /// The built-in patterns only
+    pub fn builtin() -> Self {
+        Scanner::new(&BTreeMap::new()).expect("built-in secret patterns compile")
+    }
+
+    /// The built-in patterns with `extra` added, by name
+    pub fn new(extra: &BTreeMap<String, String>) -> Result<Self, regex::Error> {
+        let mut patterns = Vec::new();
+        for (name, pattern) in BUILTIN_PATTERNS {
+            if !extra.contains_key(*name) {
+                patterns.push((name.to_string(), Regex::new(pattern)?));
+            }
+        }
+        for (name, pattern) in extra {
+            patterns.push((name.clone(), Regex::new(pattern)?));
+        }
+        Ok(Scanner { patterns })
+    }
+
+    /// Every secret in `text` in order. Where matches overlap only the first
+    /// to start is kept.
+    pub fn scan(&self, text: &str) -> Vec<SecretMatch> {
+        let mut found: Vec<(Range<usize>, &str)> = Vec::new();
+        for (name, re) in &self.patterns {
+            for caps in re.captures_iter(text) {
+                let Some(secret) = caps.name("secret").or_else(|| caps.get(0)) else {
+                    continue;
+                };
+                if !secret.is_empty() && !is_placeholder(secret.as_str()) {
+                    found.push((secret.range(), name));
+                }
+            }
+        }
+        found.sort_by_key(|(range, _)| (range.start, range.end));
+
+        let mut matches: Vec<SecretMatch> = Vec::new();
+        for (range, name) in found {
+            if matches.last().is_some_and(|last| range.start < last.range.end) {
+                continue;
+            }
+            matches.push(SecretMatch {
+                pattern: name.to_string(),
+                line: text[..range.start].matches('\n').count() + 1,
+                range,
+            });
+        }
+        matches
+    }
+
+    /// `text` with every secret replaced by [`REDACTED`], and what was replaced
+    pub fn redact(&self, text: &str) -> (String, Vec<SecretMatch>) {
+        let matches = self.scan(text);
+        let mut out = String::with_capacity(text.len());
+        let mut last = 0;
+        for m in &matches {
+            out.push_str(&text[last..m.range.start]);
+            out.push_str(REDACTED);
+            last = m.range.end;
+        }
+        out.push_str(&text[last..]);
+        (out, matches)
+    }
+}
+
+/// Whether a matched value only stands in for a secret: a variable like
+/// `$TOKEN` or `${TOKEN}`, a `<token>`, one character repeated like `****`,
+/// or an earlier redaction
+fn is_placeholder(value: &str) -> bool {
+    let value = value.trim_matches(|c| c == '"' || c == '\'');
+    let mut chars = value.chars();
+    let Some(first) = chars.next() else {
+        return true;
+    };
+    value.starts_with(REDACTED)
+        || matches!(first, '$' | '<' | '{' | '%')
+        || (value.chars().count() > 1 && chars.all(|c| c == first))
+}
+
+/// Check the `patterns` of a `[secrets]` table compile
+pub(crate) fn check_patterns(patterns: &BTreeMap<String, String>) -> Result<(), String> {
+    for (name, pattern) in patterns {
+        Regex::new(pattern).map_err(|e| format!("secret pattern '{}': {}", name, e))?;
+    }
+    Ok(())
+}
+
+impl Wiki {
+    /// The built-in secret patterns and those from the config
+    pub fn secret_scanner(&self) -> Result<Scanner, WikiError> {
+        Ok(Scanner::new(&self.config.secrets.patterns)?)
+    }
+
+    /// Every secret in the data of the wiki's facts, by fact and line
+    pub fn scan_secrets(&self) -> Result<Vec<SecretHit>, WikiError> {
+        let scanner = self.secret_scanner()?;
+        self.hydrate_all()?;
+
+        let mut hits = Vec::new();
+        for locked in &self.info {
+            let info = locked.read();
+            for m in scanner.scan(&info.data) {
+                hits.push(SecretHit {
+                    id: info.id,
+                    name: info.name.clone(),
+                    line: m.line,
+                    pattern: m.pattern,
+                });
+            }
+        }
+        Ok(hits)
+    }
+}
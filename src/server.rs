@@ -0,0 +1,353 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::{Error, Read};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::sync::{Arc, PoisonError, RwLock};
+use std::thread;
+use tiny_http::{Header, Method, Request, Response};
+use uuid::Uuid;
+
+use crate::error::WikiError;
+use crate::wiki::{Fields, Information, Wiki};
+use crate::window::TimeWindow;
+
+/// Port `wk serve` listens on unless told otherwise
+pub const DEFAULT_PORT: u16 = 7171;
+
+/// Largest request body accepted, in bytes
+const MAX_BODY: u64 = 1 << 20;
+
+/// The search page served at `/`
+const INDEX_HTML: &str = include_str!("server.html");
+
+/// How `wk serve` listens and what it allows
+#[derive(Debug, Clone)]
+pub struct ServeOptions {
+    pub addr: SocketAddr,
+    /// Reject every request that would change the wiki
+    pub readonly: bool,
+    /// Require `Authorization: Bearer <token>` on every API request
+    pub token: Option<String>,
+    /// Names besides localhost and the address listened on that requests
+    /// may be sent to, as given in their `Host` header
+    pub allowed_hosts: Vec<String>,
+}
+
+impl Default for ServeOptions {
+    fn default() -> Self {
+        ServeOptions {
+            addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), DEFAULT_PORT),
+            readonly: false,
+            token: None,
+            allowed_hosts: Vec::new(),
+        }
+    }
+}
+
+/// Body of `POST /facts`
+#[derive(Deserialize)]
+struct NewFact {
+    data: String,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// Body of `PATCH /facts/:id`; fields left out are kept
+#[derive(Deserialize)]
+struct FactPatch {
+    name: Option<String>,
+    data: Option<String>,
+    tags: Option<Vec<String>>,
+    /// The fact's `updated` time as the client last saw it; the patch is
+    /// refused if it has changed since
+    updated: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+/// A response before it is handed to tiny_http
+struct Reply {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Reply {
+    fn json(status: u16, value: &impl Serialize) -> Self {
+        Reply {
+            status,
+            content_type: "application/json",
+            body: serde_json::to_vec(value).unwrap_or_default(),
+        }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        Reply::json(status, &ErrorBody { error: message.into() })
+    }
+}
+
+impl From<WikiError> for Reply {
+    fn from(e: WikiError) -> Self {
+        let status = match e {
+            WikiError::NotFound(_) | WikiError::NoSnapshot { .. } | WikiError::NotTrashed(_) => 404,
+            WikiError::Conflict { .. } | WikiError::SnapshotExists { .. } => 409,
+            WikiError::ReadOnly(_) | WikiError::DryRun(_) => 403,
+            WikiError::HookRejected(_) | WikiError::TagsRequired { .. } | WikiError::Invalid(_) => 422,
+            WikiError::TooLarge { .. } => 413,
+            _ => 500,
+        };
+        Reply::error(status, e.to_string())
+    }
+}
+
+/// A bound HTTP server exposing a wiki as JSON
+pub struct Server {
+    http: tiny_http::Server,
+    options: ServeOptions,
+}
+
+impl Server {
+    /// Start listening on `options.addr`
+    pub fn bind(options: ServeOptions) -> std::io::Result<Self> {
+        let http = tiny_http::Server::http(options.addr).map_err(Error::other)?;
+        Ok(Server { http, options })
+    }
+
+    /// Where the server is listening, with the port filled in if 0 was asked for
+    pub fn addr(&self) -> SocketAddr {
+        self.http.server_addr().to_ip().unwrap_or(self.options.addr)
+    }
+
+    /// Answer requests on a few worker threads until the process exits.
+    ///
+    /// Reads share the wiki; writes take it exclusively, so every mutation
+    /// still goes through [`Wiki`] and fires its events.
+    pub fn run(&self, wiki: Arc<RwLock<Wiki>>) {
+        let workers = thread::available_parallelism().map(|n| n.get()).unwrap_or(1).min(4);
+        thread::scope(|s| {
+            for _ in 0..workers {
+                s.spawn(|| {
+                    for mut request in self.http.incoming_requests() {
+                        let reply = catch_unwind(AssertUnwindSafe(|| self.handle(&wiki, &mut request)))
+                            .unwrap_or_else(|_| Reply::error(500, "internal error"));
+                        respond(request, reply);
+                    }
+                });
+            }
+        });
+    }
+
+    fn handle(&self, wiki: &RwLock<Wiki>, request: &mut Request) -> Reply {
+        let url = request.url().to_string();
+        let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let method = request.method().clone();
+
+        // A page on another site can point its own name at this address and
+        // then read from it as same-origin, so the name it was sent to matters
+        if !self.allowed_host(request) {
+            return Reply::error(403, "unexpected Host header");
+        }
+
+        if segments.is_empty() {
+            return match method {
+                Method::Get => Reply {
+                    status: 200,
+                    content_type: "text/html; charset=utf-8",
+                    body: INDEX_HTML.as_bytes().to_vec(),
+                },
+                _ => Reply::error(405, "method not allowed"),
+            };
+        }
+
+        if !self.authorized(request) {
+            return Reply::error(401, "missing or wrong token");
+        }
+        if self.options.readonly && method != Method::Get {
+            return Reply::error(403, "the server is read-only");
+        }
+
+        let read = || wiki.read().unwrap_or_else(PoisonError::into_inner);
+        let write = || wiki.write().unwrap_or_else(PoisonError::into_inner);
+
+        match (&method, segments.as_slice()) {
+            (Method::Get, ["facts"]) => {
+                let param = |key: &str| query_param(query, key).filter(|v| !v.is_empty());
+                let limit = param("limit").and_then(|l| l.parse().ok());
+                let tag = param("tag");
+                let wiki = read();
+                let facts: Vec<Information> = match (param("query"), &tag) {
+                    (Some(q), _) => wiki
+                        .recall_refs(&q, tag.as_deref(), Fields::ALL, TimeWindow::ANY, limit)
+                        .into_iter()
+                        .map(|hit| (*hit).clone())
+                        .collect(),
+                    (None, Some(tag)) => wiki.recall_by_tag(tag),
+                    (None, None) => wiki.all(),
+                };
+                let facts: Vec<Information> = facts.into_iter().take(limit.unwrap_or(usize::MAX)).collect();
+                Reply::json(200, &facts)
+            }
+            (Method::Post, ["facts"]) => match body::<NewFact>(request) {
+                Ok(new) => {
+                    let mut wiki = write();
+                    match wiki.commit(new.data, new.tags).and_then(|id| wiki.get(id)) {
+                        Ok(info) => Reply::json(201, &info),
+                        Err(e) => e.into(),
+                    }
+                }
+                Err(reply) => reply,
+            },
+            (Method::Get, ["tags"]) => Reply::json(200, &read().tags()),
+            (_, ["facts", id]) => {
+                let Ok(id) = id.parse::<Uuid>() else {
+                    return Reply::error(400, format!("'{}' is not a fact id", id));
+                };
+                let result = match method {
+                    Method::Get => read().get(id),
+                    Method::Delete => write().delete(id, false),
+                    Method::Patch => match body::<FactPatch>(request) {
+                        Ok(patch) => {
+                            let apply = |info: &mut Information| {
+                                if let Some(name) = patch.name {
+                                    info.name = name;
+                                    info.name_is_derived = false;
+                                }
+                                if let Some(data) = patch.data {
+                                    info.data = data;
+                                }
+                                if let Some(tags) = patch.tags {
+                                    info.tags = tags;
+                                }
+                            };
+                            match patch.updated {
+                                Some(updated) => write().update_if(id, Some(updated), apply),
+                                None => write().update(id, apply),
+                            }
+                        }
+                        Err(reply) => return reply,
+                    },
+                    _ => return Reply::error(405, "method not allowed"),
+                };
+                match result {
+                    Ok(info) => Reply::json(200, &info),
+                    Err(e) => e.into(),
+                }
+            }
+            (_, ["facts"] | ["tags"]) => Reply::error(405, "method not allowed"),
+            _ => Reply::error(404, "not found"),
+        }
+    }
+
+    /// Whether the request was sent to localhost, the address listened on,
+    /// or one of [`ServeOptions::allowed_hosts`]. Listening on every
+    /// interface, any IP address is taken as one of its own; only names can
+    /// be rebound to another address.
+    fn allowed_host(&self, request: &Request) -> bool {
+        let Some(host) = request.headers().iter().find(|h| h.field.equiv("Host")) else {
+            return false;
+        };
+        let host = host.value.as_str().to_ascii_lowercase();
+        let name = match host.rsplit_once(':') {
+            // A bracketed IPv6 address without a port has colons of its own
+            Some((name, port)) if !port.contains(']') => name,
+            _ => &host,
+        };
+        if name == "localhost" || self.options.allowed_hosts.iter().any(|allowed| allowed.eq_ignore_ascii_case(name)) {
+            return true;
+        }
+        match name.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+            Ok(ip) => ip.is_loopback() || ip == self.options.addr.ip() || self.options.addr.ip().is_unspecified(),
+            Err(_) => false,
+        }
+    }
+
+    /// Whether the request carries the token, if one is required
+    fn authorized(&self, request: &Request) -> bool {
+        let Some(token) = &self.options.token else {
+            return true;
+        };
+        let expected = format!("Bearer {}", token);
+        request
+            .headers()
+            .iter()
+            .filter(|h| h.field.equiv("Authorization"))
+            .any(|h| constant_time_eq(h.value.as_str().as_bytes(), expected.as_bytes()))
+    }
+}
+
+/// Parse a JSON request body, or the reply explaining why it couldn't be.
+/// Bodies must say they're JSON: browsers send other types cross-site
+/// without asking first.
+fn body<T: for<'de> Deserialize<'de>>(request: &mut Request) -> Result<T, Reply> {
+    let json = request
+        .headers()
+        .iter()
+        .filter(|h| h.field.equiv("Content-Type"))
+        .any(|h| h.value.as_str().split(';').next().unwrap_or_default().trim().eq_ignore_ascii_case("application/json"));
+    if !json {
+        return Err(Reply::error(415, "the body must be sent as application/json"));
+    }
+    let mut bytes = Vec::new();
+    request
+        .as_reader()
+        .take(MAX_BODY + 1)
+        .read_to_end(&mut bytes)
+        .map_err(|e| Reply::error(400, e.to_string()))?;
+    if bytes.len() as u64 > MAX_BODY {
+        return Err(Reply::error(413, "request body too large"));
+    }
+    serde_json::from_slice(&bytes).map_err(|e| Reply::error(400, e.to_string()))
+}
+
+fn respond(request: Request, reply: Reply) {
+    let mut response = Response::from_data(reply.body).with_status_code(reply.status);
+    if let Ok(header) = Header::from_bytes("Content-Type", reply.content_type) {
+        response.add_header(header);
+    }
+    // The client may have gone away; there's nobody left to tell
+    request.respond(response).ok();
+}
+
+/// The decoded value of `key` in a URL query string
+fn query_param(query: &str, key: &str) -> Option<String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('=').or(Some((pair, ""))))
+        .find(|(k, _)| percent_decode(k) == key)
+        .map(|(_, v)| percent_decode(v))
+}
+
+/// Decode `%XX` escapes and `+` as space, as browsers encode form values
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+                continue;
+            }
+            (b'+', _) => out.push(b' '),
+            (b, _) => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Compare without stopping at the first difference, so response times don't
+/// give away how much of a guessed token was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
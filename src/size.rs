@@ -0,0 +1,81 @@
+//! How big a fact's data may grow.
+//!
+//! Past the soft limit, [`DEFAULT_LARGE_FACT_BYTES`] unless the config's
+//! `large_fact_bytes` says otherwise, a fact still commits but `wk c` warns
+//! about it, and recall matches it by name and tags only unless asked to
+//! include large facts. Past the hard limit, [`DEFAULT_MAX_FACT_BYTES`] or
+//! `max_fact_bytes`, commits and changes that grow the data are refused.
+//!
+//! Facts already over either limit keep loading as they are: only writes
+//! are checked, and a change that doesn't grow the data goes through.
+
+use crate::config::Config;
+use crate::error::WikiError;
+use crate::wiki::{Information, Wiki};
+
+/// Bytes of data past which a fact counts as large, unless the config sets
+/// `large_fact_bytes`
+pub const DEFAULT_LARGE_FACT_BYTES: usize = 1024 * 1024;
+
+/// Most bytes of data a fact may be written with, unless the config sets
+/// `max_fact_bytes`
+pub const DEFAULT_MAX_FACT_BYTES: usize = 10 * 1024 * 1024;
+
+/// `bytes` for people: `512 B`, `1.5 KiB`, `12.0 MiB`
+pub fn format_size(bytes: usize) -> String {
+    const UNITS: [&str; 3] = ["KiB", "MiB", "GiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Check the soft limit isn't above the hard one, which would make it moot
+pub(crate) fn check_limits(config: &Config) -> Result<(), String> {
+    let large = config.large_fact_bytes.unwrap_or(DEFAULT_LARGE_FACT_BYTES);
+    let max = config.max_fact_bytes.unwrap_or(DEFAULT_MAX_FACT_BYTES);
+    match large <= max {
+        true => Ok(()),
+        false => Err(format!("large_fact_bytes ({}) is over max_fact_bytes ({})", large, max)),
+    }
+}
+
+impl Wiki {
+    /// Bytes of data past which a fact counts as large in this wiki
+    pub fn large_fact_bytes(&self) -> usize {
+        self.config.large_fact_bytes.unwrap_or(DEFAULT_LARGE_FACT_BYTES)
+    }
+
+    /// Most bytes of data a fact may be written with in this wiki
+    pub fn max_fact_bytes(&self) -> usize {
+        self.config.max_fact_bytes.unwrap_or(DEFAULT_MAX_FACT_BYTES)
+    }
+
+    /// Whether `info` is over the soft limit
+    pub fn is_large(&self, info: &Information) -> bool {
+        info.data.len() > self.large_fact_bytes()
+    }
+
+    /// Facts with more than `threshold` bytes of data, the largest first
+    pub fn large_facts(&self, threshold: usize) -> Vec<Information> {
+        let mut large: Vec<Information> = self.all().into_iter().filter(|info| info.data.len() > threshold).collect();
+        large.sort_by_key(|info| std::cmp::Reverse(info.data.len()));
+        large
+    }
+
+    /// Refuse `info` if its data is over the hard limit and bigger than it
+    /// was `before`
+    pub(crate) fn check_size(&self, info: &Information, before: Option<&Information>) -> Result<(), WikiError> {
+        let (len, max) = (info.data.len(), self.max_fact_bytes());
+        match len > max && before.is_none_or(|b| len > b.data.len()) {
+            true => Err(WikiError::TooLarge { len, max }),
+            false => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::error::WikiError;
+use crate::storage::Snapshot;
+use crate::wiki::{Information, Wiki};
+
+/// Label given to the snapshot [`Wiki::restore`] takes before reverting
+pub const PRE_RESTORE_LABEL: &str = "pre-restore";
+
+impl Wiki {
+    /// Keep a copy of a fact as it is now under `label`, or under the current
+    /// time if there is none. Labels are unique per fact.
+    pub fn snapshot(&mut self, id: Uuid, label: Option<&str>) -> Result<Snapshot, WikiError> {
+        self.check_writable()?;
+        let fact = self.get(id)?;
+        let taken = Utc::now();
+        let label = match label.map(str::trim).filter(|l| !l.is_empty()) {
+            Some(label) => label.to_string(),
+            None => taken.format("%Y-%m-%d %H:%M:%S").to_string(),
+        };
+        if self.storage.snapshots(id)?.iter().any(|s| s.label == label) {
+            return Err(WikiError::SnapshotExists { id, label });
+        }
+
+        let snapshot = Snapshot { label, taken, fact };
+        self.storage.write_snapshot(&snapshot)?;
+        Ok(snapshot)
+    }
+
+    /// Every snapshot of a fact, oldest first
+    pub fn snapshots(&self, id: Uuid) -> Result<Vec<Snapshot>, WikiError> {
+        if !self.info.iter().any(|l| l.read().id == id) {
+            return Err(WikiError::NotFound(id));
+        }
+        Ok(self.storage.snapshots(id)?)
+    }
+
+    /// Revert a fact's name, data and tags to those of its snapshot `label`,
+    /// first keeping what it holds now as a [`PRE_RESTORE_LABEL`] snapshot
+    pub fn restore(&mut self, id: Uuid, label: &str) -> Result<Information, WikiError> {
+        let snapshots = self.snapshots(id)?;
+        let Some(snapshot) = snapshots.iter().find(|s| s.label == label) else {
+            return Err(WikiError::NoSnapshot {
+                id,
+                label: label.to_string(),
+            });
+        };
+
+        let pre_restore = unused_label(&snapshots, PRE_RESTORE_LABEL);
+        self.snapshot(id, Some(&pre_restore))?;
+
+        let fact = snapshot.fact.clone();
+        self.update(id, |info| {
+            info.name = fact.name;
+            info.data = fact.data;
+            info.tags = fact.tags;
+        })
+    }
+}
+
+/// `base`, or `base-2`, `base-3` and so on if earlier snapshots have taken it
+pub(crate) fn unused_label(snapshots: &[Snapshot], base: &str) -> String {
+    std::iter::once(base.to_string())
+        .chain((2..).map(|n| format!("{}-{}", base, n)))
+        .find(|l| snapshots.iter().all(|s| &s.label != l))
+        .unwrap_or_default()
+}
@@ -0,0 +1,87 @@
+//! Abbreviations like `;;k8s` that expand to longer text as a fact is
+//! written, set in the `snippets` table of the config
+
+use std::collections::BTreeMap;
+
+/// Replace every word of `text` that is a snippet trigger with its
+/// expansion, returning the new text and the triggers that fired in order.
+///
+/// Only whole words separated by whitespace fire, so of `;;k` and `;;k8s`
+/// only the one typed does. Expansions are inserted as they are, never
+/// expanded again, and nothing inside a fenced code block is touched.
+pub fn expand_snippets(text: &str, snippets: &BTreeMap<String, String>) -> (String, Vec<String>) {
+    let mut out = String::with_capacity(text.len());
+    let mut fired = Vec::new();
+    if snippets.is_empty() {
+        return (text.to_string(), fired);
+    }
+
+    let mut fence = None;
+    for line in text.split_inclusive('\n') {
+        let fenced = fence.is_some();
+        if toggles_fence(line, &mut fence) || fenced {
+            out.push_str(line);
+            continue;
+        }
+        for piece in line.split_inclusive(char::is_whitespace) {
+            let word = piece.trim_end_matches(char::is_whitespace);
+            match snippets.get_key_value(word) {
+                Some((trigger, expansion)) => {
+                    out.push_str(expansion);
+                    out.push_str(&piece[word.len()..]);
+                    fired.push(trigger.clone());
+                }
+                None => out.push_str(piece),
+            }
+        }
+    }
+    (out, fired)
+}
+
+/// `text` with its last word expanded, and the trigger that fired, if that
+/// word is a trigger outside a fenced code block; for expanding as the
+/// trigger is typed
+pub fn expand_last<'a>(text: &str, snippets: &'a BTreeMap<String, String>) -> Option<(String, &'a str)> {
+    let start = text
+        .char_indices()
+        .rfind(|(_, c)| c.is_whitespace())
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    let (trigger, expansion) = snippets.get_key_value(&text[start..])?;
+
+    let mut fence = None;
+    for line in text[..start].split_inclusive('\n') {
+        toggles_fence(line, &mut fence);
+    }
+    fence.is_none().then(|| (format!("{}{}", &text[..start], expansion), trigger.as_str()))
+}
+
+/// The character a fenced code block was opened with and how many of it
+type Fence = (char, usize);
+
+/// Whether `line` opens or closes a fenced code block, updating `fence` to
+/// the block it is in. As in CommonMark, only a run of the opening character
+/// at least as long, with nothing after it, closes one.
+fn toggles_fence(line: &str, fence: &mut Option<Fence>) -> bool {
+    let line = line.trim_start();
+    let Some(marker) = line.chars().next().filter(|c| matches!(c, '`' | '~')) else {
+        return false;
+    };
+    let run = line.chars().take_while(|&c| c == marker).count();
+    if run < 3 {
+        return false;
+    }
+    match *fence {
+        Some((open, len)) if marker == open && run >= len && line[run..].trim().is_empty() => *fence = None,
+        Some(_) => return false,
+        None => *fence = Some((marker, run)),
+    }
+    true
+}
+
+/// Check a `snippets` table can fire: every trigger must be a single word
+pub(crate) fn check_snippets(snippets: &BTreeMap<String, String>) -> Result<(), String> {
+    match snippets.keys().find(|trigger| trigger.is_empty() || trigger.contains(char::is_whitespace)) {
+        Some(trigger) => Err(format!("snippet trigger '{}' must be a single word", trigger)),
+        None => Ok(()),
+    }
+}
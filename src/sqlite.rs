@@ -0,0 +1,412 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::{Connection, OptionalExtension, Transaction, TransactionBehavior, params};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use uuid::Uuid;
+
+use crate::storage::{LoadWarning, Loaded, Snapshot, Stale, Storage, Trashed, already_exists, not_trashed};
+use crate::wiki::Information;
+
+/// Name of the database file inside a wiki directory
+pub const DB_FILE: &str = "wiki.db";
+
+/// All facts in a single SQLite database, with an FTS5 table over name and data
+pub struct SqliteStorage {
+    conn: Mutex<Connection>,
+    path: PathBuf,
+}
+
+fn to_io(e: rusqlite::Error) -> std::io::Error {
+    std::io::Error::other(e)
+}
+
+fn format_time(t: Option<DateTime<Utc>>) -> Option<String> {
+    t.map(|t| t.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+}
+
+fn parse_time(s: Option<String>) -> Option<DateTime<Utc>> {
+    s.and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
+        .map(|t| t.with_timezone(&Utc))
+}
+
+/// Create or overwrite a fact and its full-text entry within `tx`
+fn write_fact(tx: &Transaction<'_>, info: &Information) -> std::io::Result<()> {
+    let tags = serde_json::to_string(&info.tags).map_err(std::io::Error::other)?;
+    let extra = serde_json::to_string(&info.extra).map_err(std::io::Error::other)?;
+    let id = info.id.to_string();
+    tx.execute(
+        "INSERT OR REPLACE INTO facts (id, name, data, tags, created, updated, source, name_is_derived, extra)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            id,
+            info.name,
+            info.data,
+            tags,
+            format_time(info.created),
+            format_time(info.updated),
+            info.source,
+            info.name_is_derived,
+            extra
+        ],
+    )
+    .map_err(to_io)?;
+    tx.execute("DELETE FROM facts_fts WHERE id = ?1", params![id])
+        .map_err(to_io)?;
+    tx.execute(
+        "INSERT INTO facts_fts (id, name, data) VALUES (?1, ?2, ?3)",
+        params![id, info.name, info.data],
+    )
+    .map_err(to_io)?;
+    Ok(())
+}
+
+impl SqliteStorage {
+    /// Open (or create) `wiki.db` inside the wiki directory `dir`
+    pub fn open(dir: &Path) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(DB_FILE);
+        let conn = Connection::open(&path).map_err(to_io)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS facts (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                data TEXT NOT NULL,
+                tags TEXT NOT NULL,
+                created TEXT,
+                updated TEXT,
+                source TEXT,
+                name_is_derived INTEGER NOT NULL DEFAULT 1,
+                extra TEXT NOT NULL DEFAULT '{}'
+            );
+            CREATE VIRTUAL TABLE IF NOT EXISTS facts_fts USING fts5(id UNINDEXED, name, data);
+            CREATE TABLE IF NOT EXISTS snapshots (
+                id TEXT NOT NULL,
+                label TEXT NOT NULL,
+                taken TEXT NOT NULL,
+                fact TEXT NOT NULL,
+                PRIMARY KEY (id, label)
+            );
+            CREATE TABLE IF NOT EXISTS trash (
+                id TEXT PRIMARY KEY,
+                deleted TEXT NOT NULL,
+                fact TEXT NOT NULL
+            );",
+        )
+        .map_err(to_io)?;
+        // Databases made before facts had a source lack the column
+        if conn.prepare("SELECT source FROM facts LIMIT 0").is_err() {
+            conn.execute_batch("ALTER TABLE facts ADD COLUMN source TEXT").map_err(to_io)?;
+        }
+        // and before names were told apart from data, the flag saying which
+        if conn.prepare("SELECT name_is_derived FROM facts LIMIT 0").is_err() {
+            conn.execute_batch("ALTER TABLE facts ADD COLUMN name_is_derived INTEGER NOT NULL DEFAULT 1")
+                .map_err(to_io)?;
+        }
+        // and before fields twk doesn't know were kept
+        if conn.prepare("SELECT extra FROM facts LIMIT 0").is_err() {
+            conn.execute_batch("ALTER TABLE facts ADD COLUMN extra TEXT NOT NULL DEFAULT '{}'").map_err(to_io)?;
+        }
+
+        Ok(SqliteStorage {
+            conn: Mutex::new(conn),
+            path,
+        })
+    }
+
+    /// Whether the wiki directory `dir` holds an SQLite database
+    pub fn detect(dir: &Path) -> bool {
+        dir.join(DB_FILE).is_file()
+    }
+}
+
+/// Turn free text into an FTS5 query matching every word, each quoted so
+/// user input can't inject FTS syntax
+fn fts_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .map(|w| format!("\"{}\"", w.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl Storage for SqliteStorage {
+    fn load_all(&self) -> std::io::Result<Loaded> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare("SELECT id, name, data, tags, created, updated, source, name_is_derived, extra FROM facts ORDER BY id")
+            .map_err(to_io)?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                    row.get::<_, Option<String>>(5)?,
+                    row.get::<_, Option<String>>(6)?,
+                    row.get::<_, bool>(7)?,
+                    row.get::<_, String>(8)?,
+                ))
+            })
+            .map_err(to_io)?;
+
+        let mut loaded = Loaded::default();
+        for row in rows {
+            let (id, name, data, tags, created, updated, source, name_is_derived, extra) = match row {
+                Ok(row) => row,
+                Err(e) => {
+                    loaded.warnings.push(LoadWarning {
+                        path: self.path.clone(),
+                        error: e.to_string(),
+                    });
+                    continue;
+                }
+            };
+            // Skip rows that don't decode, mirroring how unreadable files are skipped
+            let (Ok(fact_id), Ok(tags), Ok(extra)) =
+                (Uuid::parse_str(&id), serde_json::from_str(&tags), serde_json::from_str(&extra))
+            else {
+                loaded.warnings.push(LoadWarning {
+                    path: self.path.clone(),
+                    error: format!("row {} could not be decoded", id),
+                });
+                continue;
+            };
+            loaded.facts.push(Information {
+                id: fact_id,
+                tags,
+                name,
+                data,
+                created: parse_time(created),
+                updated: parse_time(updated),
+                source,
+                name_is_derived,
+                extra,
+            });
+        }
+        Ok(loaded)
+    }
+
+    fn read(&self, id: Uuid) -> std::io::Result<Information> {
+        let conn = self.conn.lock().unwrap();
+        let (name, data, tags, created, updated, source, name_is_derived, extra) = conn
+            .query_row(
+                "SELECT name, data, tags, created, updated, source, name_is_derived, extra FROM facts WHERE id = ?1",
+                params![id.to_string()],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                        row.get::<_, Option<String>>(4)?,
+                        row.get::<_, Option<String>>(5)?,
+                        row.get::<_, bool>(6)?,
+                        row.get::<_, String>(7)?,
+                    ))
+                },
+            )
+            .map_err(to_io)?;
+
+        Ok(Information {
+            id,
+            tags: serde_json::from_str(&tags).map_err(std::io::Error::other)?,
+            name,
+            data,
+            created: parse_time(created),
+            updated: parse_time(updated),
+            source,
+            name_is_derived,
+            extra: serde_json::from_str(&extra).map_err(std::io::Error::other)?,
+        })
+    }
+
+    fn write(&self, info: &Information) -> std::io::Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction().map_err(to_io)?;
+        write_fact(&tx, info)?;
+        tx.commit().map_err(to_io)
+    }
+
+    fn write_if(&self, info: &Information, expected: Option<DateTime<Utc>>) -> std::io::Result<Result<(), Stale>> {
+        let mut conn = self.conn.lock().unwrap();
+        // Taking the write lock up front keeps other processes out until the commit
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate).map_err(to_io)?;
+        let stored: Option<Option<String>> = tx
+            .query_row("SELECT updated FROM facts WHERE id = ?1", params![info.id.to_string()], |row| row.get(0))
+            .optional()
+            .map_err(to_io)?;
+        if let Some(updated) = stored.map(parse_time)
+            && updated != expected
+        {
+            return Ok(Err(Stale(updated)));
+        }
+        write_fact(&tx, info)?;
+        tx.commit().map_err(to_io).map(Ok)
+    }
+
+    fn delete(&self, id: Uuid) -> std::io::Result<()> {
+        let id = id.to_string();
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction().map_err(to_io)?;
+        tx.execute("DELETE FROM facts WHERE id = ?1", params![id])
+            .map_err(to_io)?;
+        tx.execute("DELETE FROM facts_fts WHERE id = ?1", params![id])
+            .map_err(to_io)?;
+        tx.commit().map_err(to_io)
+    }
+
+    fn exists(&self, id: Uuid) -> bool {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT 1 FROM facts WHERE id = ?1",
+            params![id.to_string()],
+            |_| Ok(()),
+        )
+        .optional()
+        .ok()
+        .flatten()
+        .is_some()
+    }
+
+    fn count(&self) -> std::io::Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row("SELECT COUNT(*) FROM facts", [], |row| row.get::<_, i64>(0))
+            .map(|n| n as usize)
+            .map_err(to_io)
+    }
+
+    fn snapshots(&self, id: Uuid) -> std::io::Result<Vec<Snapshot>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare("SELECT label, taken, fact FROM snapshots WHERE id = ?1 ORDER BY taken")
+            .map_err(to_io)?;
+        let rows = stmt
+            .query_map(params![id.to_string()], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?))
+            })
+            .map_err(to_io)?;
+
+        let mut snapshots = Vec::new();
+        for row in rows {
+            let (label, taken, fact) = row.map_err(to_io)?;
+            snapshots.push(Snapshot {
+                label,
+                taken: parse_time(Some(taken)).unwrap_or_default(),
+                fact: serde_json::from_str(&fact).map_err(std::io::Error::other)?,
+            });
+        }
+        Ok(snapshots)
+    }
+
+    fn write_snapshot(&self, snapshot: &Snapshot) -> std::io::Result<()> {
+        let fact = serde_json::to_string(&snapshot.fact).map_err(std::io::Error::other)?;
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO snapshots (id, label, taken, fact) VALUES (?1, ?2, ?3, ?4)",
+            params![snapshot.fact.id.to_string(), snapshot.label, format_time(Some(snapshot.taken)), fact],
+        )
+        .map_err(to_io)?;
+        Ok(())
+    }
+
+    fn delete_snapshots(&self, id: Uuid) -> std::io::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM snapshots WHERE id = ?1", params![id.to_string()])
+            .map_err(to_io)?;
+        Ok(())
+    }
+
+    fn trash(&self, id: Uuid, deleted: DateTime<Utc>) -> std::io::Result<()> {
+        let fact = match self.read(id) {
+            Ok(fact) => fact,
+            Err(_) if !self.exists(id) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let json = serde_json::to_string(&fact).map_err(std::io::Error::other)?;
+        let id = id.to_string();
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction().map_err(to_io)?;
+        tx.execute(
+            "INSERT OR REPLACE INTO trash (id, deleted, fact) VALUES (?1, ?2, ?3)",
+            params![id, format_time(Some(deleted)), json],
+        )
+        .map_err(to_io)?;
+        tx.execute("DELETE FROM facts WHERE id = ?1", params![id])
+            .map_err(to_io)?;
+        tx.execute("DELETE FROM facts_fts WHERE id = ?1", params![id])
+            .map_err(to_io)?;
+        tx.commit().map_err(to_io)
+    }
+
+    fn trashed(&self) -> std::io::Result<Vec<Trashed>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare("SELECT deleted, fact FROM trash ORDER BY deleted")
+            .map_err(to_io)?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+            .map_err(to_io)?;
+
+        let mut trashed = Vec::new();
+        for row in rows {
+            let (deleted, fact) = row.map_err(to_io)?;
+            trashed.push(Trashed {
+                deleted: parse_time(Some(deleted)).unwrap_or_default(),
+                fact: serde_json::from_str(&fact).map_err(std::io::Error::other)?,
+            });
+        }
+        Ok(trashed)
+    }
+
+    fn untrash(&self, id: Uuid) -> std::io::Result<Information> {
+        if self.exists(id) {
+            return Err(already_exists(id));
+        }
+        let fact = {
+            let conn = self.conn.lock().unwrap();
+            conn.query_row("SELECT fact FROM trash WHERE id = ?1", params![id.to_string()], |row| {
+                row.get::<_, String>(0)
+            })
+            .optional()
+            .map_err(to_io)?
+            .ok_or_else(|| not_trashed(id))?
+        };
+        let fact: Information = serde_json::from_str(&fact).map_err(std::io::Error::other)?;
+        // Written before it leaves the trash, so an interruption loses nothing
+        self.write(&fact)?;
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM trash WHERE id = ?1", params![id.to_string()])
+            .map_err(to_io)?;
+        Ok(fact)
+    }
+
+    fn purge(&self, id: Uuid) -> std::io::Result<()> {
+        {
+            let conn = self.conn.lock().unwrap();
+            conn.execute("DELETE FROM trash WHERE id = ?1", params![id.to_string()])
+                .map_err(to_io)?;
+        }
+        self.delete_snapshots(id)
+    }
+
+    fn search(&self, query: &str) -> Option<Vec<Uuid>> {
+        let query = fts_query(query);
+        if query.is_empty() {
+            return None;
+        }
+
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare("SELECT id FROM facts_fts WHERE facts_fts MATCH ?1 ORDER BY rank")
+            .ok()?;
+        let ids = stmt
+            .query_map(params![query], |row| row.get::<_, String>(0))
+            .ok()?
+            .flatten()
+            .filter_map(|id| Uuid::parse_str(&id).ok())
+            .collect();
+        Some(ids)
+    }
+}
@@ -0,0 +1,132 @@
+//! How big a wiki is and how it has grown over time, for `wk stats` and
+//! the book's statistics page
+
+use chrono::{DateTime, Datelike, Days, Local, Months, NaiveDate, Utc};
+use std::collections::BTreeMap;
+
+use crate::wiki::{Information, Wiki};
+
+/// A wiki's size at a glance, from [`Wiki::stats`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stats {
+    pub facts: usize,
+    /// Every tag in use, with the number of facts carrying it
+    pub tags: BTreeMap<String, usize>,
+    /// Facts created in the last 7 days, today included
+    pub added_week: usize,
+    /// Facts created in the last 30 days, today included
+    pub added_month: usize,
+}
+
+impl Wiki {
+    /// How many facts and tags the wiki has and how many facts were added
+    /// lately, all counted at the same moment
+    pub fn stats(&self) -> Stats {
+        let keys = self.read_all();
+        self.stats_of(&keys.iter().map(|key| &**key).collect::<Vec<_>>())
+    }
+
+    /// [`Wiki::stats`] of `facts`, e.g. a snapshot of the wiki's
+    pub(crate) fn stats_of(&self, facts: &[&Information]) -> Stats {
+        let dates: Vec<_> = facts.iter().filter_map(|info| self.created(info)).collect();
+        let days = buckets(&dates, Bucket::Day, Local::now().date_naive());
+        let added = |last: usize| days.iter().rev().take(last).map(|(_, n)| n).sum();
+        let mut tags = BTreeMap::new();
+        for tag in facts.iter().flat_map(|info| &info.tags) {
+            *tags.entry(tag.clone()).or_insert(0) += 1;
+        }
+        Stats { facts: facts.len(), tags, added_week: added(7), added_month: added(30) }
+    }
+
+    /// How many facts were created in each day, week or month, oldest first,
+    /// from the one holding the first fact up to today. See [`buckets`].
+    pub fn activity(&self, bucket: Bucket) -> Vec<(NaiveDate, usize)> {
+        self.activity_where(bucket, |_| true)
+    }
+
+    /// Like [`Wiki::activity`], but only counting facts with `tag` or one
+    /// nested under it
+    pub fn tag_activity(&self, tag: &str, bucket: Bucket) -> Vec<(NaiveDate, usize)> {
+        self.activity_where(bucket, |info| self.tagged(info, tag))
+    }
+
+    fn activity_where(&self, bucket: Bucket, keep: impl Fn(&Information) -> bool) -> Vec<(NaiveDate, usize)> {
+        let dates: Vec<NaiveDate> = self
+            .info
+            .iter()
+            .filter_map(|locked| {
+                let info = locked.read();
+                if !keep(&info) {
+                    return None;
+                }
+                self.created(&info)
+            })
+            .collect();
+        buckets(&dates, bucket, Local::now().date_naive())
+    }
+
+    /// The local date `info` was created on; facts from before timestamps
+    /// were recorded go by their file
+    fn created(&self, info: &Information) -> Option<NaiveDate> {
+        let created = info.created.or_else(|| {
+            let modified = std::fs::metadata(info.path(self)).and_then(|m| m.modified()).ok()?;
+            Some(DateTime::<Utc>::from(modified))
+        })?;
+        Some(created.with_timezone(&Local).date_naive())
+    }
+}
+
+/// The calendar spans [`Wiki::activity`] counts facts in, by the local date
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bucket {
+    Day,
+    /// Monday to Sunday
+    Week,
+    Month,
+}
+
+impl Bucket {
+    /// The first day of the span holding `date`
+    pub fn start(self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Bucket::Day => date,
+            Bucket::Week => date - Days::new(date.weekday().num_days_from_monday().into()),
+            Bucket::Month => date.with_day(1).unwrap_or(date),
+        }
+    }
+
+    /// The first day of the span after the one starting on `start`
+    fn next(self, start: NaiveDate) -> NaiveDate {
+        match self {
+            Bucket::Day => start + Days::new(1),
+            Bucket::Week => start + Days::new(7),
+            Bucket::Month => start + Months::new(1),
+        }
+    }
+}
+
+/// Count `dates` into back-to-back calendar spans, oldest first, each
+/// paired with its first day.
+///
+/// The spans run from the one holding the earliest date to the one holding
+/// `today`, and spans with nothing in them count zero, so there are no
+/// gaps. Dates after today count in today's span. Empty if there are no
+/// dates.
+pub fn buckets(dates: &[NaiveDate], bucket: Bucket, today: NaiveDate) -> Vec<(NaiveDate, usize)> {
+    let Some(&earliest) = dates.iter().min() else {
+        return Vec::new();
+    };
+    let last = bucket.start(today.max(earliest));
+    let mut spans = Vec::new();
+    let mut start = bucket.start(earliest);
+    while start <= last {
+        spans.push((start, 0));
+        start = bucket.next(start);
+    }
+    for &date in dates {
+        let start = bucket.start(date.min(today).max(earliest));
+        let i = spans.partition_point(|&(s, _)| s <= start) - 1;
+        spans[i].1 += 1;
+    }
+    spans
+}
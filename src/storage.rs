@@ -0,0 +1,843 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::SystemTime;
+use uuid::Uuid;
+
+use crate::canonical;
+use crate::encryption::{self, Cipher};
+use crate::helpers::{FileLock, LOCK_TIMEOUT, lock_path_for, write_atomic};
+use crate::preview::preview_line;
+use crate::wiki::Information;
+
+/// Where a wiki's facts are persisted.
+///
+/// `Wiki` keeps facts in memory and calls into its storage after every
+/// mutation, so recall and book generation never touch the backend directly.
+pub trait Storage: Send + Sync {
+    /// Load every stored fact. Entries that can't be read are skipped and
+    /// reported as warnings.
+    fn load_all(&self) -> std::io::Result<Loaded>;
+    /// Like [`Storage::load_all`], but facts listed in [`Loaded::partial`] may
+    /// be returned without their full `data`, to be read later with
+    /// [`Storage::read`]. Backends without a cheaper path load everything.
+    fn load_lazy(&self) -> std::io::Result<Loaded> {
+        self.load_all()
+    }
+    /// Read a single fact in full
+    fn read(&self, id: Uuid) -> std::io::Result<Information>;
+    /// Create or overwrite a fact
+    fn write(&self, info: &Information) -> std::io::Result<()>;
+    /// Overwrite a fact only if the stored copy was last updated at
+    /// `expected`, or can't be read. Backends shared between processes check
+    /// and write in one step, so no other save can land in between.
+    fn write_if(&self, info: &Information, expected: Option<DateTime<Utc>>) -> std::io::Result<Result<(), Stale>> {
+        match self.read(info.id) {
+            Ok(stored) if stored.updated != expected => Ok(Err(Stale(stored.updated))),
+            _ => self.write(info).map(Ok),
+        }
+    }
+    /// Remove a fact; removing a missing fact is not an error
+    fn delete(&self, id: Uuid) -> std::io::Result<()>;
+    fn exists(&self, id: Uuid) -> bool;
+    /// Number of stored facts
+    fn count(&self) -> std::io::Result<usize> {
+        Ok(self.load_all()?.facts.len())
+    }
+    /// Ids of facts containing every word of `query`, best match first, for
+    /// backends with a full-text index. `None` means the caller should scan.
+    fn search(&self, _query: &str) -> Option<Vec<Uuid>> {
+        None
+    }
+    /// Whether facts are encrypted at rest, in which case nothing derived
+    /// from them may be cached in plaintext
+    fn is_encrypted(&self) -> bool {
+        false
+    }
+    /// Snapshots taken of a fact, oldest first
+    fn snapshots(&self, id: Uuid) -> std::io::Result<Vec<Snapshot>>;
+    /// Store a snapshot, replacing one of the same fact with the same label
+    fn write_snapshot(&self, snapshot: &Snapshot) -> std::io::Result<()>;
+    /// Remove every snapshot of a fact
+    fn delete_snapshots(&self, id: Uuid) -> std::io::Result<()>;
+    /// Move a fact to the trash, noting that it was deleted at `deleted`; its
+    /// snapshots stay until it is purged. Trashing a missing fact is not an error.
+    fn trash(&self, id: Uuid, deleted: DateTime<Utc>) -> std::io::Result<()>;
+    /// Every fact in the trash, oldest deletion first
+    fn trashed(&self) -> std::io::Result<Vec<Trashed>>;
+    /// Move a fact out of the trash, returning it; fails if it isn't in the
+    /// trash or a fact with its id exists
+    fn untrash(&self, id: Uuid) -> std::io::Result<Information>;
+    /// Remove a fact from the trash for good, with its snapshots
+    fn purge(&self, id: Uuid) -> std::io::Result<()>;
+    /// Rewrite stored files that aren't in the [canonical](canonical) format,
+    /// returning how many were; backends that keep no JSON files have none
+    fn reformat(&self) -> std::io::Result<usize> {
+        Ok(0)
+    }
+}
+
+/// A copy of a fact kept under a label, apart from the fact itself
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Unique among the fact's snapshots
+    pub label: String,
+    pub taken: DateTime<Utc>,
+    pub fact: Information,
+}
+
+/// When the stored copy of a fact was updated instead, for a
+/// [`Storage::write_if`] that found it changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stale(pub Option<DateTime<Utc>>);
+
+/// A deleted fact, kept until it is restored or purged
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trashed {
+    pub deleted: DateTime<Utc>,
+    pub fact: Information,
+}
+
+/// The error for a fact that isn't in the trash
+pub(crate) fn not_trashed(id: Uuid) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::NotFound, format!("fact {} is not in the trash", id))
+}
+
+/// The error for restoring a fact over one with the same id
+pub(crate) fn already_exists(id: Uuid) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::AlreadyExists, format!("a fact with id {} already exists", id))
+}
+
+/// A stored fact, or other file in the wiki directory, that couldn't be loaded
+#[derive(Debug, Clone)]
+pub struct LoadWarning {
+    pub path: PathBuf,
+    pub error: String,
+}
+
+/// Everything a storage backend managed to read
+#[derive(Debug, Default)]
+pub struct Loaded {
+    pub facts: Vec<Information>,
+    pub warnings: Vec<LoadWarning>,
+    /// Facts whose `data` only holds its first line so far
+    pub partial: HashSet<Uuid>,
+    /// Copies of fact files left by a file synchroniser that saw two
+    /// conflicting edits, with what they hold; never loaded as facts
+    pub conflict_copies: Vec<(PathBuf, Information)>,
+}
+
+/// Whether a file name is one Syncthing or Dropbox gives the losing side of a
+/// conflicting edit, like `<id>.sync-conflict-20240101-120000-ABCDEFG.json`
+/// or `<id> (conflicted copy 2024-01-01).json`
+pub fn is_conflict_copy(file_name: &str) -> bool {
+    file_name.contains(".sync-conflict-") || file_name.contains("conflicted copy")
+}
+
+/// Salvage a fact from a damaged JSON file.
+///
+/// Files written before saves truncated hold several complete JSON documents
+/// back to back, newest last, possibly followed by a partial one; the last
+/// complete document is returned.
+pub fn recover_json(bytes: &[u8]) -> Option<Information> {
+    serde_json::Deserializer::from_slice(bytes)
+        .into_iter::<Information>()
+        .map_while(Result::ok)
+        .last()
+}
+
+/// The on-disk layouts a wiki directory can use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// One JSON file per fact
+    Files,
+    /// A single `wiki.db` SQLite database
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+}
+
+impl Backend {
+    /// Work out which backend an existing wiki directory uses
+    pub fn detect(path: &Path) -> Self {
+        #[cfg(feature = "sqlite")]
+        if crate::sqlite::SqliteStorage::detect(path) {
+            return Backend::Sqlite;
+        }
+        let _ = path;
+        Backend::Files
+    }
+
+    /// Open storage of this kind rooted at the wiki directory `path`,
+    /// unlocking it first if it is encrypted
+    pub fn open(self, path: &Path) -> std::io::Result<Box<dyn Storage>> {
+        match self {
+            Backend::Files => Ok(Box::new(FsStorage::open(path)?)),
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite => Ok(Box::new(crate::sqlite::SqliteStorage::open(path)?)),
+        }
+    }
+}
+
+impl std::fmt::Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Backend::Files => write!(f, "files"),
+            #[cfg(feature = "sqlite")]
+            Backend::Sqlite => write!(f, "sqlite"),
+        }
+    }
+}
+
+/// One pretty-printed `<id>.json` file per fact in a directory
+pub struct FsStorage {
+    path: PathBuf,
+    /// Key every fact file is sealed with, for encrypted wikis
+    cipher: Option<Arc<Cipher>>,
+    /// Read files that aren't sealed as they are, while the wiki is being
+    /// encrypted
+    plaintext: bool,
+}
+
+/// How the files of an [`FsStorage`] are opened before they're parsed
+#[derive(Clone, Copy)]
+struct Opener<'a> {
+    cipher: Option<&'a Cipher>,
+    plaintext: bool,
+}
+
+impl Opener<'_> {
+    /// The plaintext of a file holding `bytes`. With a key, a file that isn't
+    /// sealed with it is refused rather than trusted: anyone who can write
+    /// to the directory could have put it there.
+    fn open(self, bytes: Vec<u8>) -> std::io::Result<Vec<u8>> {
+        let Some(cipher) = self.cipher else {
+            return Ok(bytes);
+        };
+        match cipher.decrypt(&bytes) {
+            Some(decrypted) => decrypted,
+            None if self.plaintext => Ok(bytes),
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "not encrypted, though the wiki is; left unread",
+            )),
+        }
+    }
+}
+
+/// Name of the header cache [`FsStorage::load_lazy`] keeps in the wiki directory
+pub const HEADERS_FILE: &str = ".headers";
+
+/// Directory [`FsStorage`] keeps snapshots in, one subdirectory per fact
+pub const SNAPSHOTS_DIR: &str = ".snapshots";
+
+/// Directory [`FsStorage`] moves deleted fact files to, each next to an
+/// `<id>.deleted` file holding when it was deleted
+pub const TRASH_DIR: &str = ".trash";
+
+/// Cached headers of the fact files in a directory, keyed by file name
+#[derive(Serialize, Deserialize, Default)]
+struct Index {
+    entries: HashMap<String, IndexEntry>,
+}
+
+/// Everything but the body of one fact file, valid while the file's
+/// modification time and length are unchanged
+#[derive(Serialize, Deserialize, Clone)]
+struct IndexEntry {
+    modified: SystemTime,
+    len: u64,
+    id: Uuid,
+    name: String,
+    tags: Vec<String>,
+    preview: String,
+    created: Option<DateTime<Utc>>,
+    updated: Option<DateTime<Utc>>,
+    #[serde(default)]
+    source: Option<String>,
+    #[serde(default = "derived")]
+    name_is_derived: bool,
+}
+
+fn derived() -> bool {
+    true
+}
+
+impl IndexEntry {
+    fn new(info: &Information, meta: &std::fs::Metadata) -> Option<Self> {
+        Some(IndexEntry {
+            modified: meta.modified().ok()?,
+            len: meta.len(),
+            id: info.id,
+            name: info.name.clone(),
+            tags: info.tags.clone(),
+            preview: preview_line(&info.data, None),
+            created: info.created,
+            updated: info.updated,
+            source: info.source.clone(),
+            name_is_derived: info.name_is_derived,
+        })
+    }
+
+    fn is_fresh(&self, meta: &std::fs::Metadata) -> bool {
+        meta.modified().ok() == Some(self.modified) && meta.len() == self.len
+    }
+
+    fn to_partial(&self) -> Information {
+        Information {
+            id: self.id,
+            tags: self.tags.clone(),
+            name: self.name.clone(),
+            data: self.preview.clone(),
+            created: self.created,
+            updated: self.updated,
+            source: self.source.clone(),
+            name_is_derived: self.name_is_derived,
+            extra: Default::default(),
+        }
+    }
+}
+
+impl FsStorage {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FsStorage {
+            path: path.into(),
+            cipher: None,
+            plaintext: false,
+        }
+    }
+
+    /// Storage whose fact files are encrypted with `cipher`. Files that
+    /// aren't are refused.
+    pub fn encrypted(path: impl Into<PathBuf>, cipher: Arc<Cipher>) -> Self {
+        FsStorage {
+            path: path.into(),
+            cipher: Some(cipher),
+            plaintext: false,
+        }
+    }
+
+    /// Storage sealing fact files with `cipher` that still reads plaintext
+    /// ones, for encrypting and decrypting a wiki, which must be able to
+    /// finish an interrupted run
+    pub(crate) fn resealing(path: impl Into<PathBuf>, cipher: Arc<Cipher>) -> Self {
+        FsStorage {
+            plaintext: true,
+            ..FsStorage::encrypted(path, cipher)
+        }
+    }
+
+    /// Storage for the file-backed wiki at `path`, unlocking it first if it
+    /// is encrypted
+    pub fn open(path: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        match encryption::is_encrypted(&path) {
+            true => {
+                let cipher = encryption::unlock(&path)?;
+                Ok(FsStorage::encrypted(path, cipher))
+            }
+            false => Ok(FsStorage::new(path)),
+        }
+    }
+
+    fn opener(&self) -> Opener<'_> {
+        Opener {
+            cipher: self.cipher.as_deref(),
+            plaintext: self.plaintext,
+        }
+    }
+
+    /// The fact in the file at `path`, opened with this storage's key
+    pub(crate) fn read_file(&self, path: &Path) -> std::io::Result<Information> {
+        read_fact(path, self.opener()).map_err(std::io::Error::other)
+    }
+
+    fn fact_path(&self, id: Uuid) -> PathBuf {
+        self.path.join(format!("{}.json", id))
+    }
+
+    /// A fact as its file holds it, encrypted if the wiki is
+    fn encode(&self, info: &Information) -> std::io::Result<Vec<u8>> {
+        let json = canonical::to_vec(info)?;
+        match &self.cipher {
+            Some(cipher) => cipher.encrypt(&json),
+            None => Ok(json),
+        }
+    }
+
+    fn snapshot_dir(&self, id: Uuid) -> PathBuf {
+        self.path.join(SNAPSHOTS_DIR).join(id.to_string())
+    }
+
+    /// The label with everything but letters, digits, `-` and `_` escaped, so
+    /// any label makes a safe and distinct file name
+    fn snapshot_path(&self, id: Uuid, label: &str) -> PathBuf {
+        let mut name = String::new();
+        for byte in label.bytes() {
+            match byte {
+                b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' => name.push(byte as char),
+                _ => name.push_str(&format!("%{:02X}", byte)),
+            }
+        }
+        self.snapshot_dir(id).join(format!("{}.json", name))
+    }
+
+    fn trash_path(&self, id: Uuid) -> PathBuf {
+        self.path.join(TRASH_DIR).join(format!("{}.json", id))
+    }
+
+    fn deleted_path(&self, id: Uuid) -> PathBuf {
+        self.path.join(TRASH_DIR).join(format!("{}.deleted", id))
+    }
+
+    /// Rewrite every fact in the trash and its snapshots, read with `from`,
+    /// sealed if this storage is encrypted and in plaintext if it isn't; for
+    /// encrypting and decrypting a wiki
+    pub(crate) fn reseal_trash(&self, from: &FsStorage) -> std::io::Result<()> {
+        for trashed in from.trashed()? {
+            let json = canonical::to_vec(&trashed.fact)?;
+            let contents = match &self.cipher {
+                Some(cipher) => cipher.encrypt(&json)?,
+                None => json,
+            };
+            write_atomic(&self.trash_path(trashed.fact.id), &contents)?;
+            for snapshot in from.snapshots(trashed.fact.id)? {
+                self.write_snapshot(&snapshot)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Paths of every fact file in the directory, sorted by file name. Hidden
+    /// files such as the search index are not facts, and neither are
+    /// conflict copies or anything in hidden directories like the trash.
+    fn fact_files(&self) -> std::io::Result<Vec<PathBuf>> {
+        let (mut facts, _) = self.json_files()?;
+        facts.sort();
+        Ok(facts)
+    }
+
+    /// Fact files and conflict copies of them, unsorted
+    fn json_files(&self) -> std::io::Result<(Vec<PathBuf>, Vec<PathBuf>)> {
+        Ok(std::fs::read_dir(&self.path)?
+            .flatten()
+            .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().and_then(|s| s.to_str()) == Some("json"))
+            .partition(|path| !is_conflict_copy(&path.file_name().unwrap_or_default().to_string_lossy())))
+    }
+
+    /// Parse the conflict copies in the directory into `loaded`; unreadable
+    /// ones are reported as warnings
+    fn load_conflict_copies(&self, loaded: &mut Loaded) -> std::io::Result<()> {
+        let (_, mut copies) = self.json_files()?;
+        copies.sort();
+        for path in copies {
+            match read_fact(&path, self.opener()) {
+                Ok(info) => loaded.conflict_copies.push((path, info)),
+                Err(error) => loaded.warnings.push(LoadWarning { path, error }),
+            }
+        }
+        Ok(())
+    }
+
+    /// Rewrite the file at `path` in the canonical format if it isn't, as a
+    /// `T`, returning whether it was. Files that don't parse are left for
+    /// `wk doctor`; encrypted ones are compared by their plaintext.
+    fn reformat_file<T: Serialize + for<'de> Deserialize<'de>>(&self, path: &Path) -> std::io::Result<bool> {
+        let _lock = FileLock::exclusive(&lock_path_for(path), LOCK_TIMEOUT)?;
+        let bytes = std::fs::read(path)?;
+        let Ok(plaintext) = self.opener().open(bytes) else {
+            return Ok(false);
+        };
+        let Ok(value) = serde_json::from_slice::<T>(&plaintext) else {
+            return Ok(false);
+        };
+        let json = canonical::to_vec(&value)?;
+        if json == plaintext {
+            return Ok(false);
+        }
+        let contents = match &self.cipher {
+            Some(cipher) => cipher.encrypt(&json)?,
+            None => json,
+        };
+        write_atomic(path, &contents)?;
+        Ok(true)
+    }
+
+    fn load_index(&self) -> Index {
+        std::fs::read(self.path.join(HEADERS_FILE))
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Read and parse fact files on a bounded set of worker threads, keeping
+/// facts and warnings in the order of `paths`
+fn read_facts(paths: Vec<PathBuf>, opener: Opener) -> Loaded {
+    let mut loaded = Loaded::default();
+    if paths.is_empty() {
+        return loaded;
+    }
+
+    let workers = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let chunk_size = paths.len().div_ceil(workers);
+
+    let results: Vec<(&PathBuf, Result<Information, String>)> = thread::scope(|s| {
+        let handles: Vec<_> = paths
+            .chunks(chunk_size)
+            .map(|chunk| s.spawn(move || chunk.iter().map(|p| (p, read_fact(p, opener))).collect::<Vec<_>>()))
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap_or_default())
+            .collect()
+    });
+
+    for (path, result) in results {
+        match result {
+            Ok(info) => loaded.facts.push(info),
+            Err(error) => loaded.warnings.push(LoadWarning {
+                path: path.clone(),
+                error,
+            }),
+        }
+    }
+    loaded
+}
+
+fn read_fact(path: &Path, opener: Opener) -> Result<Information, String> {
+    let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+    let bytes = opener.open(bytes).map_err(|e| e.to_string())?;
+    serde_json::from_slice(&bytes).map_err(|e| e.to_string())
+}
+
+impl Storage for FsStorage {
+    fn load_all(&self) -> std::io::Result<Loaded> {
+        let mut loaded = read_facts(self.fact_files()?, self.opener());
+        self.load_conflict_copies(&mut loaded)?;
+        Ok(loaded)
+    }
+
+    /// Serve headers from the index for files that haven't changed since it
+    /// was written, parse the rest in full, and refresh the index if needed.
+    /// Encrypted wikis have no index and load everything.
+    fn load_lazy(&self) -> std::io::Result<Loaded> {
+        if self.cipher.is_some() {
+            return self.load_all();
+        }
+
+        let index = self.load_index();
+        let mut fresh = Index::default();
+        let mut loaded = Loaded::default();
+        let mut stale = Vec::new();
+
+        for path in self.fact_files()? {
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+            let cached = std::fs::metadata(&path)
+                .ok()
+                .and_then(|meta| index.entries.get(&file_name).filter(|e| e.is_fresh(&meta)));
+            match cached {
+                Some(entry) => {
+                    loaded.facts.push(entry.to_partial());
+                    loaded.partial.insert(entry.id);
+                    fresh.entries.insert(file_name, entry.clone());
+                }
+                None => stale.push(path),
+            }
+        }
+
+        let changed = !stale.is_empty() || fresh.entries.len() != index.entries.len();
+        let parsed = read_facts(stale, self.opener());
+        for info in &parsed.facts {
+            let path = self.fact_path(info.id);
+            if let Ok(meta) = std::fs::metadata(&path)
+                && let Some(entry) = IndexEntry::new(info, &meta)
+            {
+                fresh.entries.insert(format!("{}.json", info.id), entry);
+            }
+        }
+        loaded.facts.extend(parsed.facts);
+        loaded.warnings.extend(parsed.warnings);
+        // Same order as load_all: fact files are named by id
+        loaded.facts.sort_by_key(|info| info.id);
+        self.load_conflict_copies(&mut loaded)?;
+
+        // The index is only a cache; failing to write it just means a slower next start
+        if changed && let Ok(json) = serde_json::to_vec(&fresh) {
+            write_atomic(&self.path.join(HEADERS_FILE), &json).ok();
+        }
+
+        Ok(loaded)
+    }
+
+    fn read(&self, id: Uuid) -> std::io::Result<Information> {
+        read_fact(&self.fact_path(id), self.opener()).map_err(std::io::Error::other)
+    }
+
+    fn write(&self, info: &Information) -> std::io::Result<()> {
+        let contents = self.encode(info)?;
+        let path = self.fact_path(info.id);
+        let _lock = FileLock::exclusive(&lock_path_for(&path), LOCK_TIMEOUT)?;
+        write_atomic(&path, &contents)
+    }
+
+    fn write_if(&self, info: &Information, expected: Option<DateTime<Utc>>) -> std::io::Result<Result<(), Stale>> {
+        let contents = self.encode(info)?;
+        let path = self.fact_path(info.id);
+        let _lock = FileLock::exclusive(&lock_path_for(&path), LOCK_TIMEOUT)?;
+        match read_fact(&path, self.opener()) {
+            Ok(stored) if stored.updated != expected => Ok(Err(Stale(stored.updated))),
+            _ => write_atomic(&path, &contents).map(Ok),
+        }
+    }
+
+    fn delete(&self, id: Uuid) -> std::io::Result<()> {
+        let path = self.fact_path(id);
+        let _lock = FileLock::exclusive(&lock_path_for(&path), LOCK_TIMEOUT)?;
+        match std::fs::remove_file(&path) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            r => r,
+        }
+    }
+
+    fn exists(&self, id: Uuid) -> bool {
+        self.fact_path(id).exists()
+    }
+
+    fn count(&self) -> std::io::Result<usize> {
+        Ok(self.fact_files()?.len())
+    }
+
+    fn is_encrypted(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    fn snapshots(&self, id: Uuid) -> std::io::Result<Vec<Snapshot>> {
+        let entries = match std::fs::read_dir(self.snapshot_dir(id)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut snapshots = Vec::new();
+        for entry in entries {
+            let bytes = std::fs::read(entry?.path())?;
+            let bytes = self.opener().open(bytes)?;
+            snapshots.push(serde_json::from_slice::<Snapshot>(&bytes).map_err(std::io::Error::other)?);
+        }
+        snapshots.sort_by_key(|s| s.taken);
+        Ok(snapshots)
+    }
+
+    fn write_snapshot(&self, snapshot: &Snapshot) -> std::io::Result<()> {
+        let json = canonical::to_vec(snapshot)?;
+        let contents = match &self.cipher {
+            Some(cipher) => cipher.encrypt(&json)?,
+            None => json,
+        };
+        std::fs::create_dir_all(self.snapshot_dir(snapshot.fact.id))?;
+        write_atomic(&self.snapshot_path(snapshot.fact.id, &snapshot.label), &contents)
+    }
+
+    fn delete_snapshots(&self, id: Uuid) -> std::io::Result<()> {
+        match std::fs::remove_dir_all(self.snapshot_dir(id)) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            r => r,
+        }
+    }
+
+    /// Moves the file as it is, so an encrypted fact stays encrypted
+    fn trash(&self, id: Uuid, deleted: DateTime<Utc>) -> std::io::Result<()> {
+        let path = self.fact_path(id);
+        let _lock = FileLock::exclusive(&lock_path_for(&path), LOCK_TIMEOUT)?;
+        if !path.exists() {
+            return Ok(());
+        }
+        std::fs::create_dir_all(self.path.join(TRASH_DIR))?;
+        // Before the move, so nothing in the trash is without a time
+        write_atomic(&self.deleted_path(id), deleted.to_rfc3339().as_bytes())?;
+        std::fs::rename(&path, self.trash_path(id))
+    }
+
+    fn trashed(&self) -> std::io::Result<Vec<Trashed>> {
+        let entries = match std::fs::read_dir(self.path.join(TRASH_DIR)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut trashed = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|s| s.to_str()) != Some("json") {
+                continue;
+            }
+            let fact = read_fact(&path, self.opener()).map_err(std::io::Error::other)?;
+            // Without its time, a fact counts as deleted when it was moved
+            let deleted = std::fs::read_to_string(self.deleted_path(fact.id))
+                .ok()
+                .and_then(|text| DateTime::parse_from_rfc3339(text.trim()).ok())
+                .map(|t| t.with_timezone(&Utc))
+                .or_else(|| std::fs::metadata(&path).and_then(|m| m.modified()).ok().map(DateTime::from))
+                .unwrap_or_default();
+            trashed.push(Trashed { deleted, fact });
+        }
+        trashed.sort_by_key(|t| t.deleted);
+        Ok(trashed)
+    }
+
+    fn untrash(&self, id: Uuid) -> std::io::Result<Information> {
+        let path = self.fact_path(id);
+        let _lock = FileLock::exclusive(&lock_path_for(&path), LOCK_TIMEOUT)?;
+        let trashed = self.trash_path(id);
+        if !trashed.exists() {
+            return Err(not_trashed(id));
+        }
+        if path.exists() {
+            return Err(already_exists(id));
+        }
+        let fact = read_fact(&trashed, self.opener()).map_err(std::io::Error::other)?;
+        std::fs::rename(&trashed, &path)?;
+        std::fs::remove_file(self.deleted_path(id)).ok();
+        Ok(fact)
+    }
+
+    fn purge(&self, id: Uuid) -> std::io::Result<()> {
+        for path in [self.trash_path(id), self.deleted_path(id)] {
+            match std::fs::remove_file(path) {
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                r => r?,
+            }
+        }
+        self.delete_snapshots(id)
+    }
+
+    fn reformat(&self) -> std::io::Result<usize> {
+        let (mut facts, copies) = self.json_files()?;
+        facts.extend(copies);
+        facts.extend(entries(&self.path.join(TRASH_DIR))?.into_iter().filter(|path| is_json(path)));
+        let mut snapshots = Vec::new();
+        for dir in entries(&self.path.join(SNAPSHOTS_DIR))? {
+            snapshots.extend(entries(&dir)?.into_iter().filter(|path| is_json(path)));
+        }
+
+        let mut changed = 0;
+        for path in &facts {
+            changed += self.reformat_file::<Information>(path)? as usize;
+        }
+        for path in &snapshots {
+            changed += self.reformat_file::<Snapshot>(path)? as usize;
+        }
+        Ok(changed)
+    }
+}
+
+/// Everything in `dir`, or nothing if it doesn't exist
+fn entries(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    match std::fs::read_dir(dir) {
+        Ok(entries) => Ok(entries.flatten().map(|entry| entry.path()).collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension().and_then(|s| s.to_str()) == Some("json")
+}
+
+/// Keeps facts in memory only; useful for tests and scratch wikis
+#[derive(Default)]
+pub struct MemoryStorage {
+    facts: Mutex<HashMap<Uuid, Information>>,
+    snapshots: Mutex<HashMap<Uuid, Vec<Snapshot>>>,
+    trash: Mutex<HashMap<Uuid, Trashed>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn load_all(&self) -> std::io::Result<Loaded> {
+        let mut facts: Vec<Information> = self.facts.lock().unwrap().values().cloned().collect();
+        facts.sort_by_key(|info| info.id);
+        Ok(Loaded {
+            facts,
+            ..Loaded::default()
+        })
+    }
+
+    fn read(&self, id: Uuid) -> std::io::Result<Information> {
+        self.facts
+            .lock()
+            .unwrap()
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, format!("no fact {}", id)))
+    }
+
+    fn write(&self, info: &Information) -> std::io::Result<()> {
+        self.facts.lock().unwrap().insert(info.id, info.clone());
+        Ok(())
+    }
+
+    fn delete(&self, id: Uuid) -> std::io::Result<()> {
+        self.facts.lock().unwrap().remove(&id);
+        Ok(())
+    }
+
+    fn exists(&self, id: Uuid) -> bool {
+        self.facts.lock().unwrap().contains_key(&id)
+    }
+
+    fn snapshots(&self, id: Uuid) -> std::io::Result<Vec<Snapshot>> {
+        Ok(self.snapshots.lock().unwrap().get(&id).cloned().unwrap_or_default())
+    }
+
+    fn write_snapshot(&self, snapshot: &Snapshot) -> std::io::Result<()> {
+        let mut snapshots = self.snapshots.lock().unwrap();
+        let kept = snapshots.entry(snapshot.fact.id).or_default();
+        kept.retain(|s| s.label != snapshot.label);
+        kept.push(snapshot.clone());
+        kept.sort_by_key(|s| s.taken);
+        Ok(())
+    }
+
+    fn delete_snapshots(&self, id: Uuid) -> std::io::Result<()> {
+        self.snapshots.lock().unwrap().remove(&id);
+        Ok(())
+    }
+
+    fn trash(&self, id: Uuid, deleted: DateTime<Utc>) -> std::io::Result<()> {
+        if let Some(fact) = self.facts.lock().unwrap().remove(&id) {
+            self.trash.lock().unwrap().insert(id, Trashed { deleted, fact });
+        }
+        Ok(())
+    }
+
+    fn trashed(&self) -> std::io::Result<Vec<Trashed>> {
+        let mut trashed: Vec<Trashed> = self.trash.lock().unwrap().values().cloned().collect();
+        trashed.sort_by_key(|t| t.deleted);
+        Ok(trashed)
+    }
+
+    fn untrash(&self, id: Uuid) -> std::io::Result<Information> {
+        let mut facts = self.facts.lock().unwrap();
+        if facts.contains_key(&id) {
+            return Err(already_exists(id));
+        }
+        let trashed = self.trash.lock().unwrap().remove(&id).ok_or_else(|| not_trashed(id))?;
+        facts.insert(id, trashed.fact.clone());
+        Ok(trashed.fact)
+    }
+
+    fn purge(&self, id: Uuid) -> std::io::Result<()> {
+        self.trash.lock().unwrap().remove(&id);
+        self.delete_snapshots(id)
+    }
+}
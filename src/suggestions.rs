@@ -0,0 +1,161 @@
+//! What `wk r` offers in place of an empty result.
+//!
+//! A query that recalls nothing is usually a word away from one that does:
+//! a tag spelled differently, a fact matching most of the words, or the
+//! same facts kept in another wiki. [`Wiki::suggestions`] looks for each.
+//!
+//! Fuzzy recall has no score below which a match is dropped, so a fact
+//! either matches a query or doesn't. The facts that almost matched are
+//! those matching some words of a query of several, ranked by how many,
+//! or those a `--exact` search or a time window left out.
+
+use nucleo_matcher::{Matcher, Utf32Str};
+use std::collections::HashMap;
+use std::path::Path;
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::encryption;
+use crate::matching::{self, MatchConfig};
+use crate::storage::Backend;
+use crate::wiki::{Fields, Information, RecallOptions, Wiki};
+use crate::wikis::{self, Location};
+
+/// How many tags and facts are suggested at most
+pub const MAX_SUGGESTIONS: usize = 3;
+/// Tags shorter than this aren't looked for within the query's words, as
+/// nearly any word would contain them
+const MIN_CONTAINED_TAG: usize = 3;
+/// How many of the best matches of each word of a query are ranked
+const HITS_PER_WORD: usize = 100;
+
+/// Other places to look after a query recalled nothing
+#[derive(Debug, Default, Clone)]
+pub struct Suggestions {
+    /// Tags closest to the query or one of its words, best first
+    pub tags: Vec<String>,
+    /// Facts matching some of the words of the query, most words first
+    pub facts: Vec<Information>,
+    /// Other wikis with facts named like the query
+    pub wikis: Vec<WikiMatches>,
+}
+
+/// A wiki other than the current one holding facts named like a query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WikiMatches {
+    pub name: String,
+    pub location: Location,
+    /// How many of its facts' names match
+    pub facts: usize,
+}
+
+impl Suggestions {
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.facts.is_empty() && self.wikis.is_empty()
+    }
+}
+
+/// Scores queries against short haystacks as the wiki's `[match]` table
+/// asks, like recall does
+struct Scorer {
+    matching: MatchConfig,
+    matcher: Matcher,
+    needle_buf: Vec<char>,
+    haystack_buf: Vec<char>,
+}
+
+impl Scorer {
+    fn new(config: &Config) -> Self {
+        let matcher = matching::make_matcher(config, "");
+        Scorer { matching: config.matching, matcher, needle_buf: Vec::new(), haystack_buf: Vec::new() }
+    }
+
+    fn score(&mut self, haystack: &str, needle: &str) -> Option<u16> {
+        // Smart case depends on the needle, which changes from call to call
+        self.matcher.config = self.matching.nucleo_config(needle);
+        let needle = self.matching.needle(needle);
+        let haystack = self.matching.haystack(haystack);
+        self.matcher
+            .fuzzy_match(Utf32Str::new(&haystack, &mut self.haystack_buf), Utf32Str::new(&needle, &mut self.needle_buf))
+    }
+}
+
+impl Wiki {
+    /// Tags, facts and other wikis to suggest for a `query` over `fields`
+    /// that recalled nothing; see the [module docs](self). Other wikis are
+    /// found as by `wk wikis`, global ones only if `use_global`, and only
+    /// the names of their facts are matched. Encrypted wikis are skipped.
+    pub fn suggestions(&self, query: &str, fields: Fields, use_global: bool) -> Suggestions {
+        let words: Vec<&str> = query.split_whitespace().collect();
+        Suggestions {
+            tags: self.tags_like(query, &words),
+            facts: self.facts_like(&words, fields),
+            wikis: other_wikis(query, &self.path, &self.config, use_global),
+        }
+    }
+
+    fn tags_like(&self, query: &str, words: &[&str]) -> Vec<String> {
+        let mut scorer = Scorer::new(&self.config);
+        let mut scored: Vec<(u16, String)> = Vec::new();
+        for tag in self.tags().into_keys() {
+            let best = std::iter::once(query)
+                .chain(words.iter().copied())
+                .filter_map(|needle| {
+                    let within = scorer.score(&tag, needle);
+                    // A word may be a tag with more to it, like `rustlang`
+                    let around = (tag.chars().count() >= MIN_CONTAINED_TAG).then(|| scorer.score(needle, &tag)).flatten();
+                    within.max(around)
+                })
+                .max();
+            if let Some(score) = best {
+                scored.push((score, tag));
+            }
+        }
+        scored.sort_by(|(a, a_tag), (b, b_tag)| b.cmp(a).then_with(|| a_tag.cmp(b_tag)));
+        scored.into_iter().take(MAX_SUGGESTIONS).map(|(_, tag)| tag).collect()
+    }
+
+    fn facts_like(&self, words: &[&str], fields: Fields) -> Vec<Information> {
+        let opts = || RecallOptions { fields, ..Default::default() };
+        // Words matched and their summed scores, for each fact
+        let mut matched: HashMap<Uuid, (usize, u64, Information)> = HashMap::new();
+        for word in words {
+            for hit in self.recall_top_n(word, HITS_PER_WORD, opts()) {
+                let entry = matched.entry(hit.id).or_insert_with(|| (0, 0, (*hit).clone()));
+                entry.0 += 1;
+                entry.1 += u64::from(hit.score);
+            }
+        }
+        let mut ranked: Vec<_> = matched.into_values().collect();
+        ranked.sort_by(|(a_words, a_score, a), (b_words, b_score, b)| {
+            b_words.cmp(a_words).then(b_score.cmp(a_score)).then_with(|| a.name.cmp(&b.name))
+        });
+        ranked.into_iter().take(MAX_SUGGESTIONS).map(|(_, _, fact)| fact).collect()
+    }
+}
+
+/// Wikis other than the one at `current` with facts whose names match
+/// `query`, matched as `config` asks
+fn other_wikis(query: &str, current: &Path, config: &Config, use_global: bool) -> Vec<WikiMatches> {
+    let Ok(listings) = wikis::discover(use_global) else {
+        return Vec::new();
+    };
+    let current = current.canonicalize().unwrap_or_else(|_| current.to_path_buf());
+    let mut scorer = Scorer::new(config);
+    let mut found = Vec::new();
+    for listing in listings {
+        let path = listing.path.canonicalize().unwrap_or(listing.path);
+        if path == current || encryption::is_encrypted(&path) {
+            continue;
+        }
+        // Headers alone are enough for names
+        let Ok(loaded) = Backend::detect(&path).open(&path).and_then(|storage| storage.load_lazy()) else {
+            continue;
+        };
+        let facts = loaded.facts.iter().filter(|fact| scorer.score(&fact.name, query).is_some()).count();
+        if facts > 0 {
+            found.push(WikiMatches { name: listing.name, location: listing.location, facts });
+        }
+    }
+    found
+}
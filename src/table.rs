@@ -0,0 +1,266 @@
+//! Aligned columns for listings like `wk r --format table`, `wk tags` and
+//! `wk wiki list`, measured by display width so CJK and emoji line up
+
+use chrono::{DateTime, Utc};
+use colored::{Color, Colorize};
+use std::collections::VecDeque;
+use std::io::IsTerminal;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Spaces between columns
+const GAP: usize = 2;
+/// Narrowest a shrinking column gets, however small the terminal
+const MIN_FLEX: usize = 8;
+/// Marks text cut short to fit its column
+const ELLIPSIS: char = '…';
+/// Widest [`age`] short of a century
+pub const AGE_WIDTH: usize = 4;
+
+/// One cell of a table with how to colour it on a terminal
+pub struct Cell {
+    text: String,
+    color: Option<Color>,
+    bold: bool,
+}
+
+impl Cell {
+    /// A plain cell; only the first line of `text` is shown
+    pub fn new(text: impl Into<String>) -> Self {
+        let text = text.into();
+        let text = match text.split_once('\n') {
+            Some((first, _)) => first.to_string(),
+            None => text,
+        };
+        Cell { text: text.replace('\t', " "), color: None, bold: false }
+    }
+
+    pub fn color(mut self, color: Color) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    pub fn bold(mut self) -> Self {
+        self.bold = true;
+        self
+    }
+}
+
+/// Rows under a header, printed with every column padded to its widest cell.
+///
+/// On a terminal one column can be marked to shrink so rows fit its width;
+/// otherwise it is plain aligned text that is never cut. Colour follows `--color`.
+pub struct Table {
+    headers: Vec<&'static str>,
+    rows: Vec<Vec<Cell>>,
+    flex: Option<usize>,
+}
+
+impl Table {
+    pub fn new(headers: &[&'static str]) -> Self {
+        Table { headers: headers.to_vec(), rows: Vec::new(), flex: None }
+    }
+
+    /// Truncate column `column` as needed to fit the terminal
+    pub fn flex(mut self, column: usize) -> Self {
+        self.flex = Some(column);
+        self
+    }
+
+    pub fn row(&mut self, cells: Vec<Cell>) {
+        debug_assert_eq!(cells.len(), self.headers.len());
+        self.rows.push(cells);
+    }
+
+    pub fn print(&self) {
+        let tty = std::io::stdout().is_terminal();
+        let width = tty
+            .then(|| crossterm::terminal::size().ok())
+            .flatten()
+            .map(|(columns, _)| columns as usize);
+        for line in self.render(width) {
+            println!("{}", line);
+        }
+    }
+
+    /// The lines [`Table::print`] writes, laid out in `width` columns if given
+    pub fn render(&self, width: Option<usize>) -> Vec<String> {
+        let mut widths: Vec<usize> = self.headers.iter().map(|h| h.width()).collect();
+        for row in &self.rows {
+            for (w, cell) in widths.iter_mut().zip(row) {
+                *w = (*w).max(cell.text.width());
+            }
+        }
+        if let (Some(width), Some(flex)) = (width, self.flex) {
+            let rest: usize = widths
+                .iter()
+                .enumerate()
+                .filter(|&(i, _)| i != flex)
+                .map(|(_, w)| w + GAP)
+                .sum();
+            widths[flex] = widths[flex].min(width.saturating_sub(rest).max(MIN_FLEX));
+        }
+
+        let last = widths.len().saturating_sub(1);
+        let line = |cells: Vec<(String, Option<Color>, bool)>| -> String {
+            let mut out = String::new();
+            for (i, (text, fg, bold)) in cells.into_iter().enumerate() {
+                let text = truncate(&text, widths[i]);
+                let pad = if i == last { 0 } else { widths[i] - text.width() + GAP };
+                let mut styled = text.normal();
+                if let Some(fg) = fg {
+                    styled = styled.color(fg);
+                }
+                if bold {
+                    styled = styled.bold();
+                }
+                out.push_str(&styled.to_string());
+                out.extend(std::iter::repeat_n(' ', pad));
+            }
+            out
+        };
+
+        let mut lines = vec![line(
+            self.headers.iter().map(|h| (h.to_string(), Some(Color::BrightBlack), true)).collect(),
+        )];
+        for row in &self.rows {
+            lines.push(line(row.iter().map(|c| (c.text.clone(), c.color, c.bold)).collect()));
+        }
+        lines
+    }
+}
+
+/// `text` cut to at most `width` display columns, ending in `…` if anything
+/// was dropped. Wide characters are never split.
+pub fn truncate(text: &str, width: usize) -> String {
+    if text.width() <= width {
+        return text.to_string();
+    }
+    let budget = width.saturating_sub(ELLIPSIS.width().unwrap_or(1));
+    let mut out = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let w = c.width().unwrap_or(0);
+        if used + w > budget {
+            break;
+        }
+        used += w;
+        out.push(c);
+    }
+    if width > 0 {
+        out.push(ELLIPSIS);
+    }
+    out
+}
+
+/// How long ago `time` was, in the largest whole unit: `now`, `5m`, `3h`,
+/// `2d`, `4mo`, `1y`; never wider than [`AGE_WIDTH`]
+pub fn age(time: DateTime<Utc>) -> String {
+    age_at(time, Utc::now())
+}
+
+/// [`age`] as of `now`; times after it are `now` too
+pub fn age_at(time: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let secs = (now - time).num_seconds().max(0);
+    match secs {
+        s if s < 60 => "now".to_string(),
+        s if s < 60 * 60 => format!("{}m", s / 60),
+        s if s < 60 * 60 * 24 => format!("{}h", s / (60 * 60)),
+        s if s < 60 * 60 * 24 * 30 => format!("{}d", s / (60 * 60 * 24)),
+        s if s < 60 * 60 * 24 * 365 => format!("{}mo", s / (60 * 60 * 24 * 30)),
+        s => format!("{}y", s / (60 * 60 * 24 * 365)),
+    }
+}
+
+/// A word to lay out with [`wrap`], coloured but for the brackets around it
+pub struct Word {
+    text: String,
+    color: Color,
+    brackets: (&'static str, &'static str),
+}
+
+impl Word {
+    pub fn new(text: impl Into<String>, color: Color) -> Self {
+        Word { text: text.into(), color, brackets: ("", "") }
+    }
+
+    /// A word shown between `open` and `close`, like a `[tag]`
+    pub fn bracketed(text: impl Into<String>, color: Color, open: &'static str, close: &'static str) -> Self {
+        Word { text: text.into(), color, brackets: (open, close) }
+    }
+
+    fn width(&self) -> usize {
+        self.brackets.0.width() + self.text.width() + self.brackets.1.width()
+    }
+
+    fn styled(&self) -> String {
+        format!("{}{}{}", self.brackets.0, self.text.color(self.color), self.brackets.1)
+    }
+}
+
+/// Lay out `lines` of words in `width` display columns, breaking between
+/// words and indenting every line after the first by `indent` so they hang
+/// off it. Each entry of `lines` starts a new line; a word too long for a
+/// line of its own is split, unless it has brackets.
+pub fn wrap(lines: Vec<Vec<Word>>, width: usize, indent: usize) -> Vec<String> {
+    let indent = indent.min(width / 2);
+    let mut out = Vec::new();
+    for words in lines {
+        let started = out.len();
+        let margin = if out.is_empty() { 0 } else { indent };
+        let mut line = " ".repeat(margin);
+        let mut used = margin;
+        let mut empty = true;
+        let mut words: VecDeque<Word> = words.into();
+        while let Some(word) = words.pop_front() {
+            let gap = usize::from(!empty);
+            if used + gap + word.width() <= width {
+                if !empty {
+                    line.push(' ');
+                }
+                line.push_str(&word.styled());
+                used += gap + word.width();
+                empty = false;
+                continue;
+            }
+
+            if !empty {
+                words.push_front(word);
+            } else if word.brackets == ("", "") {
+                // Alone on a line and still too wide, so split it
+                let head = take_width(&word.text, width - used);
+                let tail = word.text[head.len()..].to_string();
+                line.push_str(&head.color(word.color).to_string());
+                if !tail.is_empty() {
+                    words.push_front(Word::new(tail, word.color));
+                }
+            } else {
+                line.push_str(&word.styled());
+            }
+            out.push(std::mem::take(&mut line));
+            line = " ".repeat(indent);
+            used = indent;
+            empty = true;
+        }
+        if !empty {
+            out.push(line);
+        } else if out.len() == started {
+            out.push(String::new());
+        }
+    }
+    out
+}
+
+/// The longest prefix of `text` at most `width` columns wide, at least one character
+fn take_width(text: &str, width: usize) -> String {
+    let mut out = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let w = c.width().unwrap_or(0);
+        if used + w > width && !out.is_empty() {
+            break;
+        }
+        used += w;
+        out.push(c);
+    }
+    out
+}
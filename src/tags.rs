@@ -0,0 +1,387 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use uuid::Uuid;
+
+use crate::error::WikiError;
+use crate::wiki::{Fields, Information, Wiki};
+use crate::window::TimeWindow;
+
+/// Separates the segments of a nested tag such as `lang/rust`
+pub const TAG_SEPARATOR: char = '/';
+
+/// Whether `tag` is `filter` itself or nested under it. Segments are compared
+/// whole, so `lang` matches `lang/rust` but not `language`.
+pub fn tag_matches(filter: &str, tag: &str) -> bool {
+    let filter = filter.trim_end_matches(TAG_SEPARATOR);
+    tag.strip_prefix(filter)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(TAG_SEPARATOR))
+}
+
+/// The canonical form of `tag` under `aliases`, which map an alias to the tag
+/// it stands for. Nested tags follow their parents, so with
+/// `js = "javascript"`, `js/react` becomes `javascript/react`.
+pub fn canonical(aliases: &BTreeMap<String, String>, tag: &str) -> String {
+    // The most specific alias wins
+    let ancestors: Vec<&str> = ancestors(tag).collect();
+    for prefix in ancestors.into_iter().rev() {
+        if let Some(target) = aliases.get(prefix) {
+            return format!("{}{}", target, &tag[prefix.len()..]);
+        }
+    }
+    tag.to_string()
+}
+
+/// Facts for [`Wiki::bulk_retag`]: those matching `query` as recall finds
+/// them, or every fact without one; only those with `tag` or a tag nested
+/// under it if given, and no more than `limit`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Selection {
+    pub query: Option<String>,
+    pub tag: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// A colour a tag is shown in, by name in the `tag_colors` config table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TagColor {
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    Gray,
+    LightRed,
+    LightGreen,
+    LightYellow,
+    LightBlue,
+    LightMagenta,
+    LightCyan,
+}
+
+/// Colours tags are given by [`tag_color`] when the config doesn't pick one,
+/// light enough for dark text on top and for each other on either a dark or
+/// a light terminal
+pub const TAG_PALETTE: [TagColor; 6] = [
+    TagColor::LightRed,
+    TagColor::LightGreen,
+    TagColor::LightYellow,
+    TagColor::LightBlue,
+    TagColor::LightMagenta,
+    TagColor::LightCyan,
+];
+
+/// The colour to show `tag` in: the one `overrides` gives it or the nearest
+/// tag it is nested under, else one from [`TAG_PALETTE`] picked by a hash of
+/// its name. The hash is FNV-1a, so a tag has the same colour in every
+/// session and on every machine.
+pub fn tag_color(tag: &str, overrides: &BTreeMap<String, TagColor>) -> TagColor {
+    let ancestors: Vec<&str> = ancestors(tag).collect();
+    if let Some(color) = ancestors.into_iter().rev().find_map(|prefix| overrides.get(prefix)) {
+        return *color;
+    }
+    let hash = tag.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    TAG_PALETTE[(hash % TAG_PALETTE.len() as u64) as usize]
+}
+
+/// Reject aliases that point at another alias, directly or through a parent
+/// tag; every cycle is such a chain
+pub(crate) fn check_aliases(aliases: &BTreeMap<String, String>) -> Result<(), String> {
+    for (alias, target) in aliases {
+        if aliases.contains_key(target) || canonical(aliases, target) != *target {
+            return Err(format!(
+                "tag alias '{}' points at '{}', which is itself an alias or nested under one; point it at the canonical tag instead",
+                alias, target
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// `tag` and every tag it is nested under, outermost first
+fn ancestors(tag: &str) -> impl Iterator<Item = &str> {
+    tag.match_indices(TAG_SEPARATOR)
+        .map(move |(i, _)| &tag[..i])
+        .chain(std::iter::once(tag))
+}
+
+/// One segment of the tag hierarchy
+#[derive(Debug, Clone, Serialize)]
+pub struct TagNode {
+    /// The last segment, e.g. `rust`
+    pub name: String,
+    /// The full tag, e.g. `lang/rust`
+    pub path: String,
+    /// Facts tagged exactly `path`
+    pub count: usize,
+    /// Facts tagged `path` or anything under it, each counted once
+    pub total: usize,
+    pub children: Vec<TagNode>,
+}
+
+impl TagNode {
+    /// Every node of `nodes` and their descendants, depth first, with their depth
+    pub fn walk(nodes: &[TagNode]) -> Vec<(usize, &TagNode)> {
+        fn visit<'a>(nodes: &'a [TagNode], depth: usize, out: &mut Vec<(usize, &'a TagNode)>) {
+            for node in nodes {
+                out.push((depth, node));
+                visit(&node.children, depth + 1, out);
+            }
+        }
+        let mut out = Vec::new();
+        visit(nodes, 0, &mut out);
+        out
+    }
+}
+
+/// Arrange tags into a tree, from exact counts and rolled-up totals keyed by
+/// full path. Every ancestor of a tag must have a total.
+pub(crate) fn build_tree(counts: &BTreeMap<String, usize>, totals: &BTreeMap<String, usize>) -> Vec<TagNode> {
+    fn children(parent: Option<&str>, counts: &BTreeMap<String, usize>, totals: &BTreeMap<String, usize>) -> Vec<TagNode> {
+        totals
+            .iter()
+            .filter(|(path, _)| path.rsplit_once(TAG_SEPARATOR).map(|(p, _)| p) == parent)
+            .map(|(path, &total)| TagNode {
+                name: path.rsplit(TAG_SEPARATOR).next().unwrap_or(path).to_string(),
+                path: path.clone(),
+                count: counts.get(path).copied().unwrap_or(0),
+                total,
+                children: children(Some(path), counts, totals),
+            })
+            .collect()
+    }
+    children(None, counts, totals)
+}
+
+impl Wiki {
+    /// The tags to commit a new fact with: `tags` along with the wiki's
+    /// `default_tags`, under their canonical names. Fails if that leaves
+    /// none and the wiki's config has `require_tags`.
+    pub(crate) fn commit_tags(&self, tags: &[String]) -> Result<Vec<String>, WikiError> {
+        let mut tags = tags.to_vec();
+        if self.default_tags {
+            for tag in &self.config.default_tags {
+                if !tags.contains(tag) {
+                    tags.push(tag.clone());
+                }
+            }
+        }
+        let tags = self.canonical_tags(&tags);
+        if tags.is_empty() && self.config.require_tags {
+            return Err(WikiError::TagsRequired {
+                wiki: self.name.clone(),
+                config: crate::config::defined_in(&self.path, "require_tags"),
+            });
+        }
+        Ok(tags)
+    }
+
+    /// `tags` under their canonical names, by the wiki's `aliases`, each once
+    pub(crate) fn canonical_tags(&self, tags: &[String]) -> Vec<String> {
+        if self.config.aliases.is_empty() {
+            return tags.to_vec();
+        }
+        let mut canonical: Vec<String> = Vec::with_capacity(tags.len());
+        for tag in tags {
+            let tag = self::canonical(&self.config.aliases, tag);
+            if !canonical.contains(&tag) {
+                canonical.push(tag);
+            }
+        }
+        canonical
+    }
+
+    /// A recall `score` for `info` times the `tag_weights` of each weighted
+    /// tag it carries, or carries one nested under
+    pub fn weighted(&self, info: &Information, score: u32) -> u32 {
+        let weight: f64 = self
+            .config
+            .tag_weights
+            .iter()
+            .filter(|(tag, _)| self.tagged(info, tag))
+            .map(|(_, weight)| f64::from(*weight))
+            .product();
+        (f64::from(score) * weight.max(0.0)).round() as u32
+    }
+
+    /// Whether `info` carries `tag` or one nested under it, resolving aliases
+    /// on both sides so `javascript` finds facts still tagged `js`
+    pub fn tagged(&self, info: &Information, tag: &str) -> bool {
+        if self.config.aliases.is_empty() {
+            return info.has_tag(tag);
+        }
+        let tag = canonical(&self.config.aliases, tag);
+        info.tags
+            .iter()
+            .any(|t| tag_matches(&tag, &canonical(&self.config.aliases, t)))
+    }
+
+    /// Rewrite the tags of every fact carrying an alias to their canonical
+    /// names, returning the facts as they were and are now
+    pub fn normalize_tags(&mut self) -> Result<Vec<(Information, Information)>, WikiError> {
+        let stale: Vec<(Uuid, Vec<String>)> = self
+            .info
+            .iter()
+            .filter_map(|l| {
+                let info = l.read();
+                let tags = self.canonical_tags(&info.tags);
+                (tags != info.tags).then(|| (info.id, tags))
+            })
+            .collect();
+
+        let mut changed = Vec::new();
+        for (id, tags) in stale {
+            let before = self.get(id)?;
+            changed.push((before, self.retag(id, tags)?));
+        }
+        Ok(changed)
+    }
+
+    /// Ids of the facts `selection` picks out, best match first for a query
+    /// and in the wiki's order otherwise
+    pub fn select(&self, selection: &Selection) -> Vec<Uuid> {
+        match &selection.query {
+            Some(query) => self
+                .recall_refs(query, selection.tag.as_deref(), Fields::ALL, TimeWindow::ANY, selection.limit)
+                .iter()
+                .map(|hit| hit.id)
+                .collect(),
+            None => self
+                .info
+                .iter()
+                .map(|l| l.read())
+                .filter(|info| selection.tag.as_ref().is_none_or(|tag| self.tagged(info, tag)))
+                .map(|info| info.id)
+                .take(selection.limit.unwrap_or(usize::MAX))
+                .collect(),
+        }
+    }
+
+    /// Give every fact `selection` picks out the tags in `add` and take those
+    /// in `remove` off it, aliases included, returning each fact as it was
+    /// and is now. Facts that change are retagged one by one, as
+    /// [`Wiki::retag`] does; those already tagged as asked aren't written.
+    pub fn bulk_retag(
+        &mut self,
+        selection: &Selection,
+        add: &[String],
+        remove: &[String],
+    ) -> Result<Vec<(Information, Information)>, WikiError> {
+        self.check_writable()?;
+        let (add, remove) = (self.canonical_tags(add), self.canonical_tags(remove));
+        let mut retagged = Vec::new();
+        for id in self.select(selection) {
+            let before = self.get(id)?;
+            let current = self.canonical_tags(&before.tags);
+            let mut tags = current.clone();
+            tags.retain(|tag| !remove.contains(tag));
+            for tag in &add {
+                if !tags.contains(tag) {
+                    tags.push(tag.clone());
+                }
+            }
+            let after = match tags == current {
+                true => before.clone(),
+                false => self.retag(id, tags)?,
+            };
+            retagged.push((before, after));
+        }
+        Ok(retagged)
+    }
+
+    /// Every tag in use arranged by its `/`-separated segments, with counts
+    /// rolled up so a node's total covers everything nested under it
+    pub fn tag_tree(&self) -> Vec<TagNode> {
+        let keys = self.read_all();
+        tag_tree_of(&keys.iter().map(|key| &**key).collect::<Vec<_>>())
+    }
+}
+
+/// [`Wiki::tag_tree`] of `facts`, e.g. a snapshot of a wiki's
+pub(crate) fn tag_tree_of(facts: &[&Information]) -> Vec<TagNode> {
+    let mut counts = BTreeMap::new();
+    let mut totals = BTreeMap::new();
+    for info in facts {
+        for tag in &info.tags {
+            *counts.entry(tag.clone()).or_insert(0) += 1;
+        }
+        let paths: BTreeSet<&str> = info.tags.iter().flat_map(|tag| ancestors(tag)).collect();
+        for path in paths {
+            *totals.entry(path.to_string()).or_insert(0) += 1;
+        }
+    }
+    build_tree(&counts, &totals)
+}
+
+/// Lowercased words of `text`, ignoring single characters
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.chars().count() > 1)
+        .map(str::to_lowercase)
+}
+
+impl Wiki {
+    /// Existing tags that fit `text`, best first, with scores between 0 and 1.
+    ///
+    /// A tag scores for each of its segments' words found in the text, a
+    /// little for near matches like `docker` in `dockerfile`, and for words it
+    /// shares with the names of facts carrying the tag, weighted by how rare
+    /// those words are across the wiki.
+    pub fn suggest_tags(&self, text: &str, limit: usize) -> Vec<(String, f32)> {
+        let text_words: BTreeSet<String> = words(text).collect();
+        if text_words.is_empty() {
+            return Vec::new();
+        }
+
+        // Facts per tag and, per word of a fact name, facts overall and per tag
+        let mut facts_per_tag: BTreeMap<&str, usize> = BTreeMap::new();
+        let mut facts_per_word: BTreeMap<String, usize> = BTreeMap::new();
+        let mut word_counts: BTreeMap<(&str, String), usize> = BTreeMap::new();
+        let facts: Vec<_> = self.info.iter().map(|l| l.read()).collect();
+        for info in &facts {
+            let name_words: BTreeSet<String> = words(&info.name).filter(|w| text_words.contains(w)).collect();
+            for word in &name_words {
+                *facts_per_word.entry(word.clone()).or_insert(0) += 1;
+            }
+            for tag in &info.tags {
+                *facts_per_tag.entry(tag).or_insert(0) += 1;
+                for word in &name_words {
+                    *word_counts.entry((tag, word.clone())).or_insert(0) += 1;
+                }
+            }
+        }
+
+        let total = facts.len() as f32;
+        let mut scored: Vec<(String, f32)> = facts_per_tag
+            .iter()
+            .map(|(&tag, &tagged)| {
+                let mut score = 0.0;
+                for segment in words(tag) {
+                    if text_words.contains(&segment) {
+                        score += 2.0;
+                    } else if segment.len() > 2
+                        && text_words
+                            .iter()
+                            .any(|w| w.len() > 2 && (w.starts_with(&segment) || segment.starts_with(w.as_str())))
+                    {
+                        score += 1.0;
+                    }
+                }
+                for word in &text_words {
+                    if let Some(&n) = word_counts.get(&(tag, word.clone())) {
+                        let rarity = (1.0 + total / facts_per_word[word] as f32).ln();
+                        score += n as f32 / tagged as f32 * rarity;
+                    }
+                }
+                (tag.to_string(), score / (1.0 + score))
+            })
+            .filter(|(_, score)| *score > 0.0)
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        scored.truncate(limit);
+        scored
+    }
+}
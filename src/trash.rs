@@ -0,0 +1,62 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::config::CONFIG_FILE;
+use crate::error::WikiError;
+use crate::events::WikiEvent;
+use crate::storage::{LoadWarning, Trashed};
+use crate::wiki::{Information, Wiki};
+use crate::window;
+
+impl Wiki {
+    /// Every fact deleted into the trash, oldest deletion first
+    pub fn trashed(&self) -> Result<Vec<Trashed>, WikiError> {
+        Ok(self.storage.trashed()?)
+    }
+
+    /// Bring a fact back from the trash as it was deleted, snapshots and all
+    pub fn untrash(&mut self, id: Uuid) -> Result<Information, WikiError> {
+        self.check_writable()?;
+        if !self.storage.trashed()?.iter().any(|t| t.fact.id == id) {
+            return Err(WikiError::NotTrashed(id));
+        }
+
+        let info = self.storage.untrash(id)?;
+        self.update_index(|index| index.insert(&info));
+        self.push_sorted(info.clone());
+        self.subscribers.emit(WikiEvent::Created(info.clone()));
+        Ok(info)
+    }
+
+    /// Remove facts deleted before `before`, or all of them if `None`, from
+    /// the trash for good, returning how many were purged
+    pub fn empty_trash(&mut self, before: Option<DateTime<Utc>>) -> Result<usize, WikiError> {
+        self.check_writable()?;
+        let mut purged = 0;
+        for trashed in self.storage.trashed()? {
+            if before.is_none_or(|before| trashed.deleted < before) {
+                self.storage.purge(trashed.fact.id)?;
+                purged += 1;
+            }
+        }
+        Ok(purged)
+    }
+
+    /// Purge facts that have been in the trash longer than the configured
+    /// `trash_retention`. Failing just leaves them for next time, though a
+    /// retention that can't be parsed is reported as a load warning.
+    pub(crate) fn purge_expired_trash(&mut self) {
+        let Some(retention) = self.config.trash_retention.clone() else {
+            return;
+        };
+        match window::since(&retention, Utc::now()) {
+            Ok(before) => {
+                self.empty_trash(Some(before)).ok();
+            }
+            Err(e) => self.warnings.push(LoadWarning {
+                path: self.path.join(CONFIG_FILE),
+                error: format!("invalid trash_retention: {}", e),
+            }),
+        }
+    }
+}
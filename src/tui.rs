@@ -1,251 +1,47 @@
-use std::{error::Error, io, path::PathBuf, process::Command, env};
-use std::io::Write as IoWrite;
-use tempfile::NamedTempFile;
-use serde::Serialize;
-use serde_yaml;
+use std::{collections::BTreeMap, error::Error, io, path::PathBuf};
 use std::time::{Instant, Duration};
+use chrono::{DateTime, Local, Utc};
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind, KeyModifiers, MouseEventKind, MouseButton},
-    execute,
+    cursor::MoveTo,
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyEventKind, MouseEventKind, MouseButton},
+    execute, queue,
+    style::{self as term_style, Print, ResetColor, SetBackgroundColor, SetForegroundColor},
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use ratatui::{
     backend::{Backend, CrosstermBackend},
-    layout::{Constraint, Direction, Layout, Rect},
+    layout::{Constraint, Direction, Layout, Margin, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Clear},
+    widgets::{Block, Borders, List, ListItem, Paragraph, Clear},
     Frame, Terminal,
 };
-use twk::wiki::{Wiki, Information};
-use twk::helpers::Locked;
-use uuid::Uuid;
-use regex::Regex;
-use nucleo_matcher::{Config, Matcher, Utf32String};
-
-#[derive(PartialEq, Eq)]
-enum InputMode {
-    Normal,
-    Command,
-    Edit,
-}
-
-pub struct App {
-    wiki: Wiki,
-    items: Vec<(String, String, Vec<String>, Uuid, PathBuf)>, // Name, Preview, Tags, ID, Path
-    state: ListState,
-    input_mode: InputMode,
-    input: String,
-    should_quit: bool,
-    status_msg: String,
-    status_timer: Option<Instant>,
-    status_duration: Duration,
-    use_global: bool,
-    history: Vec<String>,
-    history_pos: Option<usize>,
-    filter: Option<String>,
-    filter_regex: Option<Regex>,
-    show_help: bool,
-    // Inline edit state
-    edit_buffer: String,
-    editing_id: Option<Uuid>,
-}
-
-impl App {
-    pub fn new(wiki: Wiki, use_global: bool) -> App {
-        let mut app = App {
-            wiki,
-            items: Vec::new(),
-            state: ListState::default(),
-            input_mode: InputMode::Normal,
-            input: String::new(),
-            should_quit: false,
-            status_msg: String::new(),
-            status_timer: None,
-            status_duration: Duration::from_secs(3),
-            use_global,
-            history: Vec::new(),
-            history_pos: None,
-            filter: None,
-            filter_regex: None,
-            show_help: false,
-            edit_buffer: String::new(),
-            editing_id: None,
-        };
-        app.refresh_items();
-        if !app.items.is_empty() {
-            app.state.select(Some(0));
-        }
-        app
-    }
-
-    pub fn refresh_items(&mut self) {
-        self.items.clear();
-        for locked_info in &self.wiki.info {
-            let info = locked_info.read();
-            let preview = info.data.lines().next().unwrap_or("").to_string();
-            let path = info.path(&self.wiki);
-            self.items.push((info.name.clone(), preview, info.tags.clone(), info.id, path));
-        }
-
-        // Apply filter if present
-        if let Some(pattern) = &self.filter {
-            if let Some(re) = &self.filter_regex {
-                self.items.retain(|(name, preview, tags, _id, _path)| {
-                    re.is_match(name) || re.is_match(preview) || tags.iter().any(|t| re.is_match(t))
-                });
-            } else {
-                // Use nucleo-matcher fuzzy scoring and sort by score
-                let mut scored: Vec<(i64, (String, String, Vec<String>, Uuid, PathBuf))> = Vec::new();
-                let mut matcher = Matcher::new(Config::DEFAULT);
-                let needle = Utf32String::from(pattern.as_str());
-
-                for tuple in self.items.drain(..) {
-                    let name_h = Utf32String::from(tuple.0.as_str());
-                    let preview_h = Utf32String::from(tuple.1.as_str());
-
-                    let name_score = matcher.fuzzy_match(name_h.slice(..), needle.slice(..));
-                    let preview_score = matcher.fuzzy_match(preview_h.slice(..), needle.slice(..));
-
-                    if let Some(score) = name_score.or(preview_score) {
-                        scored.push((score as i64, tuple));
-                    }
-                }
-
-                // sort descending by score
-                scored.sort_by(|a, b| b.0.cmp(&a.0));
-                self.items = scored.into_iter().map(|(_, t)| t).collect();
-            }
-        }
-    }
-
-    pub fn next(&mut self) {
-        if self.items.is_empty() {
-            return;
-        }
-        let i = match self.state.selected() {
-            Some(i) => {
-                if i >= self.items.len() - 1 {
-                    0
-                } else {
-                    i + 1
-                }
-            }
-            None => 0,
-        };
-        self.state.select(Some(i));
-    }
-
-    pub fn previous(&mut self) {
-        if self.items.is_empty() {
-            return;
-        }
-        let i = match self.state.selected() {
-            Some(i) => {
-                if i == 0 {
-                    self.items.len() - 1
-                } else {
-                    i - 1
-                }
-            }
-            None => 0,
-        };
-        self.state.select(Some(i));
-    }
-
-    pub fn switch_wiki(&mut self, name: String) {
-        self.wiki = Wiki::load_or_create(name, self.use_global);
-        self.refresh_items();
-        self.state.select(Some(0));
-        self.set_status(format!("Switched to wiki: {}", self.wiki.name));
-    }
-
-    pub fn create_entry(&mut self, name: String) {
-        let id = Uuid::new_v4();
-        let info = Information {
-            id,
-            tags: Vec::new(),
-            name: name.clone(),
-            data: String::new(),
-        };
-
-        let path = info.path(&self.wiki);
-        if let Ok(locked) = Locked::new(path, info) {
-            self.wiki.info.push(locked);
-            self.refresh_items();
-            self.set_status(format!("Created entry: {}", name));
-        } else {
-            self.set_status(format!("Failed to create entry: {}", name));
-        }
-    }
-
-    fn find_locked_index_by_id(&self, id: Uuid) -> Option<usize> {
-        for (i, locked) in self.wiki.info.iter().enumerate() {
-            if locked.read().id == id {
-                return Some(i);
-            }
-        }
-        None
-    }
+use twk::app::{self, App, AppMsg, Effect, InputMode};
+use twk::wiki::{Information, Wiki};
+use twk::Progress;
+use twk::tags::{TagColor, tag_color};
+use twk::editor::{self, EditOutcome};
+use unicode_width::UnicodeWidthStr;
 
-    pub fn start_inline_edit(&mut self) {
-        if let Some(sel) = self.state.selected() {
-            if sel < self.items.len() {
-                let id = self.items[sel].3;
-                if let Some(li) = self.find_locked_index_by_id(id) {
-                    let info = self.wiki.info[li].read();
-                    let name_clone = info.name.clone();
-                    self.edit_buffer = info.data.clone();
-                    drop(info);
-                    self.editing_id = Some(id);
-                    self.input_mode = InputMode::Edit;
-                    self.set_status(format!("Editing: {}", name_clone));
-                }
-            }
-        }
-    }
+use crate::table;
 
-    pub fn save_inline_edit(&mut self) {
-        if let Some(edit_id) = self.editing_id {
-            if let Some(li) = self.find_locked_index_by_id(edit_id) {
-                if let Some(locked) = self.wiki.info.get(li) {
-                    let mut w = locked.write();
-                    w.data = self.edit_buffer.clone();
-                }
-                self.refresh_items();
-                self.input_mode = InputMode::Normal;
-                self.editing_id = None;
-                self.set_status("Saved.".to_string());
-            }
-        }
-    }
+/// How much of the end of the log `:log` shows
+const LOG_LINES: usize = 50;
 
-    pub fn cancel_inline_edit(&mut self) {
-        self.editing_id = None;
-        self.edit_buffer.clear();
-        self.input_mode = InputMode::Normal;
-        self.set_status("Edit cancelled.".to_string());
-    }
-}
+pub fn run(wiki_name: String, use_global: bool, log_file: Option<PathBuf>, scan: bool) -> Result<(), Box<dyn Error>> {
+    // Before taking over the terminal, in case it asks for a passphrase
+    let wiki = Wiki::load_or_create(wiki_name, use_global)?;
 
-impl App {
-    fn set_status(&mut self, s: String) {
-        self.status_msg = s;
-        self.status_timer = Some(Instant::now());
-    }
-}
-
-pub fn run(wiki_name: String, use_global: bool) -> Result<(), Box<dyn Error>> {
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let wiki = Wiki::load_or_create(wiki_name, use_global);
-    let mut app = App::new(wiki, use_global);
+    let mut app = App::new(wiki, use_global, log_file, scan);
+    tracing::debug!(wiki = %app.wiki.name, facts = app.wiki.info.len(), "TUI started");
 
-    let res = run_app(&mut terminal, &mut app);
+    let res = run_app(&mut terminal, &mut app, use_global);
 
     // restore terminal on exit
     disable_raw_mode()?;
@@ -253,412 +49,236 @@ pub fn run(wiki_name: String, use_global: bool) -> Result<(), Box<dyn Error>> {
     execute!(stdout, LeaveAlternateScreen, DisableMouseCapture)?;
     terminal.show_cursor()?;
 
-    if let Err(err) = res {
-        println!("{:?}", err)
-    }
-
-    Ok(())
+    Ok(res?)
 }
 
-fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> io::Result<()> {
+fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App, use_global: bool) -> io::Result<()> {
     loop {
         terminal.draw(|f| ui(f, app))?;
 
-        let event = event::read()?;
-        match event {
-            Event::Key(key) => {
-                if key.kind == KeyEventKind::Press {
-                    // If help overlay is visible, allow a small set of keys to close it
-                    if app.show_help {
-                        match key.code {
-                            KeyCode::F(1) | KeyCode::Esc | KeyCode::Char('?') | KeyCode::Char('h') => {
-                                app.show_help = false;
-                                continue;
-                            }
-                            _ => {
-                                // ignore other keys while help is shown
-                                continue;
-                            }
-                        }
-                    }
-
-                    match app.input_mode {
-                        InputMode::Normal => match key.code {
-                            KeyCode::Char('q') => app.should_quit = true,
-                            KeyCode::Char(':') => {
-                                app.input_mode = InputMode::Command;
-                                app.input.push(':');
-                            }
-                            KeyCode::Char('j') | KeyCode::Down => app.next(),
-                            KeyCode::Char('k') | KeyCode::Up => app.previous(),
-                            KeyCode::Char('i') => app.start_inline_edit(),
-                            KeyCode::Enter | KeyCode::Char('e') => {
-                                // Open selected entry in external editor; pipe TITLE\n---\nCONTENT into a temp file,
-                                // re-load the file after editor exits, and force a full redraw.
-                                if let Some(idx) = app.state.selected() {
-                                    if idx < app.items.len() {
-                                        // get the id and clone current full content safely
-                                        let id = app.items[idx].3;
-                                        let mut name = String::new();
-                                        let mut data = String::new();
-                                        let mut tags: Vec<String> = Vec::new();
-                                        if let Some(li) = app.find_locked_index_by_id(id) {
-                                            let info = app.wiki.info[li].read();
-                                            name = info.name.clone();
-                                            data = info.data.clone();
-                                            tags = info.tags.clone();
-                                            drop(info);
-                                        }
-
-                                        // write to temp file with YAML frontmatter:
-                                        // ---
-                                        // title: ...
-                                        // tags: [..]
-                                        // ---
-                                        // CONTENT
-                                        #[derive(Serialize)]
-                                        struct Front<'a> {
-                                            title: &'a str,
-                                            tags: &'a Vec<String>,
-                                        }
-
-                                        let mut tmp = match NamedTempFile::new() {
-                                            Ok(t) => t,
-                                            Err(_) => return Ok(()),
-                                        };
-                                        let fm = serde_yaml::to_string(&Front { title: &name, tags: &tags }).unwrap_or_default();
-                                        let payload = format!("---\n{}---\n\n{}", fm, data);
-                                        let _ = tmp.write_all(payload.as_bytes());
-                                        let tmp_path = tmp.path().to_owned();
-
-                                        // restore terminal
-                                        disable_raw_mode()?;
-                                        let mut stdout = io::stdout();
-                                        execute!(stdout, LeaveAlternateScreen, DisableMouseCapture)?;
-
-                                        // launch editor on the temp file
-                                        let editor = env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
-                                        let _ = Command::new(editor).arg(&tmp_path).status();
-
-                                        // read edited contents back
-                                        let edited = std::fs::read_to_string(&tmp_path).unwrap_or_default();
-                                        // parse YAML frontmatter if present
-                                        let mut new_title = String::new();
-                                        let mut new_tags: Option<Vec<String>> = None;
-                                        let mut rest = String::new();
-                                        let cursor = edited.as_str();
-                                        if cursor.trim_start().starts_with("---") {
-                                            // find the frontmatter block
-                                            if let Some(pos) = cursor.find("\n---") {
-                                                let fm_block = &cursor[4..pos+1];
-                                                // parse YAML
-                                                if let Ok(fm_val) = serde_yaml::from_str::<serde_yaml::Value>(fm_block) {
-                                                    if let Some(t) = fm_val.get("title") {
-                                                        if let Some(s) = t.as_str() { new_title = s.to_string(); }
-                                                    }
-                                                    if let Some(tg) = fm_val.get("tags") {
-                                                        if let Some(arr) = tg.as_sequence() {
-                                                            let parsed: Vec<String> = arr.iter().filter_map(|v| v.as_str().map(|s| s.to_string())).collect();
-                                                            new_tags = Some(parsed);
-                                                        }
-                                                    }
-                                                }
-                                                // remainder after the closing '---' (skip the newline)
-                                                let rest_start = pos + 5; // skip '\n---' and following newline
-                                                if rest_start < cursor.len() {
-                                                    rest = cursor[rest_start..].trim_start_matches('\n').to_string();
-                                                }
-                                            } else {
-                                                // no closing delimiter; treat whole as content
-                                                rest = edited;
-                                            }
-                                        } else {
-                                            // fallback: first line title, optional '---' separator
-                                            let mut lines = edited.lines();
-                                            new_title = lines.next().unwrap_or("").to_string();
-                                            let second = lines.next();
-                                            if second == Some("---") {
-                                                rest = lines.collect::<Vec<_>>().join("\n");
-                                            } else {
-                                                let mut v = Vec::new();
-                                                if let Some(s) = second { v.push(s); }
-                                                v.extend(lines);
-                                                rest = v.join("\n");
-                                            }
-                                        }
-
-                                        // re-enter tui
-                                        let mut stdout = io::stdout();
-                                        execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-                                        enable_raw_mode()?;
-
-                                        // write back into wiki (find it again to avoid stale refs)
-                                        if let Some(li) = app.find_locked_index_by_id(id) {
-                                            if let Some(locked) = app.wiki.info.get(li) {
-                                                let mut w = locked.write();
-                                                if !new_title.trim().is_empty() {
-                                                    w.name = new_title.trim().to_string();
-                                                }
-                                                if let Some(ntags) = new_tags {
-                                                    w.tags = ntags;
-                                                }
-                                                w.data = rest;
-                                            }
-                                        }
-
-                                        // refresh items, force a clear draw so UI fully redraws
-                                        app.refresh_items();
-                                        let _ = terminal.draw(|f| f.render_widget(Clear, f.area()));
-                                        app.set_status("Saved from editor".to_string());
-                                    }
-                                }
-                            }
-                            KeyCode::F(1) => app.show_help = !app.show_help,
-                            _ => {}
-                        },
-                        InputMode::Command => match key.code {
-                            KeyCode::Enter => {
-                                let input: String = app.input.drain(..).collect();
-                                // record history
-                                if !input.trim().is_empty() {
-                                    app.history.push(input.clone());
-                                }
-                                app.history_pos = None;
-                                process_command(app, &input);
-                                app.input_mode = InputMode::Normal;
-                            }
-                            KeyCode::Char(c) => {
-                                app.input.push(c);
-                                app.history_pos = None;
-                            }
-                            KeyCode::Up => {
-                                // navigate history backwards
-                                if !app.history.is_empty() {
-                                    match app.history_pos {
-                                        Some(0) => {}
-                                        Some(n) => {
-                                            let new = n - 1;
-                                            app.history_pos = Some(new);
-                                            app.input = app.history[new].clone();
-                                        }
-                                        None => {
-                                            let last = app.history.len() - 1;
-                                            app.history_pos = Some(last);
-                                            app.input = app.history[last].clone();
-                                        }
-                                    }
-                                }
-                            }
-                            KeyCode::Down => {
-                                if !app.history.is_empty() {
-                                    match app.history_pos {
-                                        None => {}
-                                        Some(n) => {
-                                            if n + 1 < app.history.len() {
-                                                let new = n + 1;
-                                                app.history_pos = Some(new);
-                                                app.input = app.history[new].clone();
-                                            } else {
-                                                app.history_pos = None;
-                                                app.input.clear();
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                            KeyCode::Backspace => {
-                                app.input.pop();
-                                app.history_pos = None;
-                                if app.input.is_empty() {
-                                    app.input_mode = InputMode::Normal;
-                                }
-                            }
-                            KeyCode::Esc => {
-                                app.input.clear();
-                                app.history_pos = None;
-                                app.input_mode = InputMode::Normal;
-                            }
-                            _ => {}
-                        },
-                        InputMode::Edit => match key.code {
-                            KeyCode::Enter => {
-                                app.edit_buffer.push('\n');
-                            }
-                            KeyCode::Char(c) => {
-                                // handle ctrl-s separately
-                                if key.modifiers.contains(KeyModifiers::CONTROL) && c == 's' {
-                                    app.save_inline_edit();
-                                } else {
-                                    app.edit_buffer.push(c);
-                                }
-                            }
-                            KeyCode::Backspace => {
-                                app.edit_buffer.pop();
-                            }
-                            KeyCode::Esc => {
-                                app.cancel_inline_edit();
-                            }
-                            _ => {}
-                        },
-                    }
-                }
+        let msg = match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => {
+                tracing::trace!(code = ?key.code, modifiers = ?key.modifiers, "key");
+                app.key_msg(key)
             }
-            Event::Mouse(mouse) => {
-                match mouse.kind {
-                    MouseEventKind::ScrollDown => app.next(),
-                    MouseEventKind::ScrollUp => app.previous(),
-                    MouseEventKind::Down(MouseButton::Left) => {
-                        // Map mouse position to list index
-                        if let Ok((cols, rows)) = crossterm::terminal::size() {
-                            let area = Rect::new(0, 0, cols, rows);
-                            let chunks = Layout::default()
-                                .direction(Direction::Vertical)
-                                .constraints([Constraint::Min(1), Constraint::Length(1)])
-                                .split(area);
-                            let list_area = chunks[0];
-                            if mouse.row >= list_area.y && mouse.row < list_area.y + list_area.height {
-                                let idx = (mouse.row - list_area.y) as usize;
-                                if idx < app.items.len() {
-                                    app.state.select(Some(idx));
-                                }
-                            }
-                        }
-                    }
-                    _ => {}
+            Event::Mouse(mouse) => match mouse.kind {
+                MouseEventKind::ScrollDown => Some(AppMsg::Scroll { down: true }),
+                MouseEventKind::ScrollUp => Some(AppMsg::Scroll { down: false }),
+                // Map mouse position to list index
+                MouseEventKind::Down(MouseButton::Left) => crossterm::terminal::size().ok().and_then(|(cols, rows)| {
+                    let (list_area, _) = panes(app, layout(Rect::new(0, 0, cols, rows))[0]);
+                    (mouse.row >= list_area.y && mouse.row < list_area.y + list_area.height)
+                        .then(|| AppMsg::Select((mouse.row - list_area.y) as usize))
+                }),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        // Effects may answer with messages that have effects of their own
+        let mut effects = msg.map(|msg| app.update(msg)).unwrap_or_default();
+        while !effects.is_empty() {
+            let mut next = Vec::new();
+            for effect in effects {
+                if effect == Effect::Quit {
+                    return Ok(());
+                }
+                if let Some(msg) = perform(terminal, app, effect, use_global)? {
+                    next.extend(app.update(msg));
                 }
             }
-            _ => {}
-        }
-
-        if app.should_quit {
-            return Ok(());
+            effects = next;
         }
     }
 }
 
-fn process_command(app: &mut App, command: &str) {
-    let parts: Vec<&str> = command.trim_start_matches(':').split_whitespace().collect();
-    if parts.is_empty() {
-        return;
-    }
-    match parts[0] {
-        "q" | "quit" => app.should_quit = true,
-        "wiki" | "switch" => {
-            if parts.len() > 1 {
-                app.switch_wiki(parts[1].to_string());
-            } else {
-                app.status_msg = "Usage: :wiki <wiki_name>".to_string();
-            }
-        }
-        "n" | "new" => {
-            if parts.len() > 1 {
-                app.create_entry(parts[1..].join(" "));
-            } else {
-                app.status_msg = "Usage: :n <entry_name>".to_string();
-            }
-        }
-        "s" | "search" => {
-            if parts.len() > 1 {
-                let pat = parts[1..].join(" ");
-                if pat.starts_with("re:") {
-                    let raw = pat.trim_start_matches("re:");
-                    match Regex::new(raw) {
-                        Ok(r) => {
-                            app.filter = Some(raw.to_string());
-                            app.filter_regex = Some(r);
-                            app.refresh_items();
-                        }
-                        Err(e) => app.status_msg = format!("Invalid regex: {}", e),
-                    }
-                } else {
-                    app.filter = Some(pat.clone());
-                    app.filter_regex = None;
-                    app.refresh_items();
-                }
-            } else {
-                // clear filter
-                app.filter = None;
-                app.filter_regex = None;
-                app.refresh_items();
-            }
+/// Carry out `effect`, returning the message reporting how it went, if any
+fn perform<B: Backend>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+    effect: Effect,
+    use_global: bool,
+) -> io::Result<Option<AppMsg>> {
+    Ok(match effect {
+        Effect::Quit => None,
+        Effect::OpenEditor(info) => edit_externally(terminal, info)?,
+        Effect::OpenWiki(name) => {
+            // An encrypted wiki may ask for its passphrase on the terminal
+            disable_raw_mode().ok();
+            let loaded = Wiki::load_or_create(name, use_global);
+            enable_raw_mode().ok();
+            Some(AppMsg::WikiOpened(loaded.map(Box::new)))
         }
-        "edit" => {
-            app.start_inline_edit();
+        Effect::OpenSplit(name) => {
+            disable_raw_mode().ok();
+            let loaded = twk::wikis::open_existing(&name, use_global);
+            enable_raw_mode().ok();
+            Some(AppMsg::SplitOpened(loaded.map(Box::new)))
         }
-        "help" | "?" => {
-            app.show_help = !app.show_help;
+        Effect::Reindex => {
+            let result = app.wiki.rebuild_index_with(&mut StatusProgress::new("Indexing"));
+            // The progress was drawn outside ratatui
+            terminal.clear()?;
+            Some(AppMsg::Reindexed(result))
         }
-        _ => {
-            app.status_msg = format!("Unknown command: {}", parts[0]);
+        Effect::ShowLog(path) => Some(AppMsg::LogRead(crate::logging::tail(&path, LOG_LINES))),
+    })
+}
+
+/// Open `info` in the external editor with YAML frontmatter, handing the
+/// terminal over until it exits, and read back what was saved. Whatever goes
+/// wrong with the editor or its temp file ends up in the status bar.
+fn edit_externally<B: Backend>(terminal: &mut Terminal<B>, info: Information) -> io::Result<Option<AppMsg>> {
+    let id = info.id;
+    let outcome = editor::open_in_editor(&info, |path| {
+        disable_raw_mode()?;
+        execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture)?;
+        let launched = editor::launch(path);
+        tracing::debug!(%id, status = ?launched, "editor exited");
+        execute!(io::stdout(), EnterAlternateScreen, EnableMouseCapture)?;
+        enable_raw_mode()?;
+        launched
+    });
+
+    // The editor drew over the whole screen
+    terminal.clear()?;
+    Ok(Some(match outcome {
+        Ok(EditOutcome::Saved(edited)) => AppMsg::Edited { id, read_updated: info.updated, edited },
+        Ok(EditOutcome::Unchanged) => AppMsg::Status("No changes made in the editor".to_string()),
+        Ok(EditOutcome::Cancelled) => AppMsg::Status("Editor closed without saving; nothing changed".to_string()),
+        Err(e) => {
+            tracing::error!(%id, "editing externally failed: {}", e);
+            AppMsg::Status(format!("Not saved: {}", e))
         }
-    }
+    }))
 }
 
-fn ui(f: &mut Frame, app: &mut App) {
-    let chunks = Layout::default()
+/// The list above a one-line status bar
+fn layout(area: Rect) -> std::rc::Rc<[Rect]> {
+    Layout::default()
         .direction(Direction::Vertical)
-        .constraints([
-                Constraint::Min(1),
-                Constraint::Length(1),
-            ])
-        .split(f.area());
+        .constraints([Constraint::Min(1), Constraint::Length(1)])
+        .split(area)
+}
 
-    let area = f.area();
-    let width = area.width as usize;
+/// Where the focused pane's list goes in `area`, and the other pane's if
+/// there is one
+fn panes(app: &App, area: Rect) -> (Rect, Option<Rect>) {
+    if app.other.is_none() {
+        return (area, None);
+    }
+    let (first, second) = app::split_panes(area);
+    match app.first {
+        true => (first, Some(second)),
+        false => (second, Some(first)),
+    }
+}
 
-    // Prepare tag displays and compute max tag width so the ' | ' separator aligns
-    let tags_strs: Vec<String> = app
-        .items
-        .iter()
-        .map(|(_, _, tags, _, _)| {
-            if tags.is_empty() {
-                "".to_string()
-            } else {
-                format!("[{}] ", tags.join(", "))
-            }
-        })
-        .collect();
+/// Progress of a blocking operation, drawn over the status bar straight to the
+/// terminal since the UI isn't redrawn until the operation returns
+struct StatusProgress {
+    label: &'static str,
+    total: usize,
+    done: usize,
+    drawn: Option<Instant>,
+}
 
-    let tags_max = tags_strs.iter().map(|s| s.chars().count()).max().unwrap_or(0);
-    let title_max = std::cmp::max(10, if width > tags_max + 3 { (width - tags_max - 3) / 2 } else { 10 });
+impl StatusProgress {
+    fn new(label: &'static str) -> Self {
+        StatusProgress { label, total: 0, done: 0, drawn: None }
+    }
 
-    let items: Vec<ListItem> = app
-        .items
-        .iter()
-        .enumerate()
-        .map(|(i, (name, preview, _tags, _id, _path))| {
-            let mut title = name.clone();
-            if title.chars().count() > title_max {
-                title = title.chars().take(title_max - 1).collect::<String>() + "…";
-            }
-            let tags_display = &tags_strs[i];
+    fn draw(&mut self, msg: &str) {
+        let Ok((width, height)) = crossterm::terminal::size() else {
+            return;
+        };
+        let line = format!("{} {}/{} {}", self.label, self.done, self.total, msg);
+        let line: String = format!("{:width$}", line, width = width as usize).chars().take(width as usize).collect();
+        let mut stdout = io::stdout();
+        queue!(
+            stdout,
+            MoveTo(0, height.saturating_sub(1)),
+            SetBackgroundColor(term_style::Color::White),
+            SetForegroundColor(term_style::Color::Black),
+            Print(line),
+            ResetColor
+        )
+        .ok();
+        io::Write::flush(&mut stdout).ok();
+        self.drawn = Some(Instant::now());
+    }
+}
 
-            // compose combined left column with fixed width = tags_max + title_max
-            let left = format!("{:tags_max$}{:title_max$}", tags_display, title, tags_max = tags_max, title_max = title_max);
-
-            let content = Line::from(vec![
-                Span::styled(left, Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(" | "),
-                Span::raw(preview),
-            ]);
-            ListItem::new(content)
-        })
-        .collect();
+impl Progress for StatusProgress {
+    fn start(&mut self, total: usize) {
+        self.total = total;
+        self.draw("");
+    }
 
-    let items = List::new(items)
-        .block(Block::default().borders(Borders::ALL).title(format!("Wiki: {}", app.wiki.name)))
-        .highlight_style(
-            Style::default()
-                .bg(Color::LightGreen)
-                .add_modifier(Modifier::BOLD),
-        )
-        .highlight_symbol(">> ");
+    fn tick(&mut self, msg: &str) {
+        self.done += 1;
+        if self.drawn.is_none_or(|t| t.elapsed() >= Duration::from_millis(50)) {
+            self.draw(msg);
+        }
+    }
+
+    fn finish(&mut self) {}
+}
+
+/// Spaces either side of a tag in its badge
+const BADGE_PAD: &str = " ";
 
-    f.render_stateful_widget(items, chunks[0], &mut app.state);
+/// `tags` as badges in their colours, a space apart
+fn tag_badges(tags: &[String], colors: &BTreeMap<String, TagColor>) -> Vec<Span<'static>> {
+    let mut spans = Vec::new();
+    for (i, tag) in tags.iter().enumerate() {
+        if i > 0 {
+            spans.push(Span::raw(" "));
+        }
+        spans.push(Span::styled(format!("{}{}{}", BADGE_PAD, tag, BADGE_PAD), badge_style(tag_color(tag, colors))));
+    }
+    spans
+}
+
+/// Display width of the [`tag_badges`] of `tags`
+fn badges_width(tags: &[String]) -> usize {
+    let decoration = 2 * BADGE_PAD.len();
+    tags.iter().map(|tag| tag.width() + decoration).sum::<usize>() + tags.len().saturating_sub(1)
+}
+
+/// Dark text on the light colours, light text on the rest
+fn badge_style(color: TagColor) -> Style {
+    let (bg, light) = match color {
+        TagColor::Red => (Color::Red, false),
+        TagColor::Green => (Color::Green, false),
+        TagColor::Yellow => (Color::Yellow, true),
+        TagColor::Blue => (Color::Blue, false),
+        TagColor::Magenta => (Color::Magenta, false),
+        TagColor::Cyan => (Color::Cyan, true),
+        TagColor::Gray => (Color::DarkGray, false),
+        TagColor::LightRed => (Color::LightRed, true),
+        TagColor::LightGreen => (Color::LightGreen, true),
+        TagColor::LightYellow => (Color::LightYellow, true),
+        TagColor::LightBlue => (Color::LightBlue, true),
+        TagColor::LightMagenta => (Color::LightMagenta, true),
+        TagColor::LightCyan => (Color::LightCyan, true),
+    };
+    Style::default().bg(bg).fg(if light { Color::Black } else { Color::White })
+}
+
+fn ui(f: &mut Frame, app: &mut App) {
+    let chunks = layout(f.area());
+
+    let (focused, unfocused) = panes(app, chunks[0]);
+    let split = unfocused.is_some();
+    if let (Some(other), Some(area)) = (app.other.as_deref_mut(), unfocused) {
+        draw_list(f, other, area, Style::default().fg(Color::DarkGray));
+    }
+    let border = if split { Style::default().fg(Color::LightGreen) } else { Style::default() };
+    draw_list(f, app, focused, border);
 
     // Command/status bar: show while in command mode or when a transient status is set
-    let show_bar = app.input_mode == InputMode::Command
-        || app.status_timer.map_or(false, |t| t.elapsed() < app.status_duration);
+    let show_bar = app.input_mode == InputMode::Command || app.asking() || app.status_showing();
 
     if show_bar {
         let input_text = if app.input_mode == InputMode::Command {
@@ -673,6 +293,85 @@ fn ui(f: &mut Frame, app: &mut App) {
         f.render_widget(input, chunks[1]);
     }
 
+    if app.input_mode == InputMode::Retag {
+        // Completions trail the input in grey, the first one inline
+        let completions = app.retag_completions();
+        let partial_len = app.retag_input.len() - app.retag_input.trim_end_matches(|c: char| !c.is_whitespace()).len();
+        let mut spans = vec![Span::raw(format!("Tags: {}", app.retag_input))];
+        if let Some(first) = completions.first() {
+            spans.push(Span::styled(first[partial_len.min(first.len())..].to_string(), Style::default().fg(Color::DarkGray)));
+        }
+        if completions.len() > 1 {
+            spans.push(Span::styled(format!("   {}", completions[1..].join("  ")), Style::default().fg(Color::DarkGray)));
+        }
+        f.render_widget(Clear, chunks[1]);
+        f.render_widget(Paragraph::new(Line::from(spans)).style(Style::default().bg(Color::White).fg(Color::Black)), chunks[1]);
+    }
+
+    if let Some((name, snapshots)) = &app.snapshot_popup {
+        let lines: Vec<Line> = if snapshots.is_empty() {
+            vec![Line::from("No snapshots; take one with `wk snapshot <id> [label]`")]
+        } else {
+            snapshots
+                .iter()
+                .map(|s| {
+                    Line::from(vec![
+                        Span::styled(s.taken.format("%Y-%m-%d %H:%M  ").to_string(), Style::default().fg(Color::DarkGray)),
+                        Span::styled(s.label.clone(), Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(format!("  {}", s.fact.data.lines().next().unwrap_or(""))),
+                    ])
+                })
+                .collect()
+        };
+        let popup = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(format!("Snapshots of {}", name)));
+        let area = centered_rect(70, 50, f.area());
+        f.render_widget(Clear, area);
+        f.render_widget(popup, area);
+    }
+
+    if let Some(info) = &app.detail_popup {
+        let label = |text: &str| Span::styled(format!("{:<9}", text), Style::default().fg(Color::DarkGray));
+        let when = |time: Option<DateTime<Utc>>| match time {
+            Some(time) => {
+                let ago = match table::age(time).as_str() {
+                    "now" => "just now".to_string(),
+                    age => format!("{} ago", age),
+                };
+                format!("{} ({})", time.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S %Z"), ago)
+            }
+            None => "unknown".to_string(),
+        };
+        let mut lines = vec![
+            Line::from(vec![label("Id"), Span::raw(info.id.to_string())]),
+            Line::from([vec![label("Tags")], tag_badges(&info.tags, &app.wiki.config.tag_colors)].concat()),
+            Line::from(vec![label("Created"), Span::raw(when(info.created))]),
+            Line::from(vec![label("Updated"), Span::raw(when(info.updated))]),
+        ];
+        if let Some(source) = &info.source {
+            lines.push(Line::from(vec![label("Source"), Span::raw(source.clone())]));
+        }
+        let title = info.name.lines().next().unwrap_or_default();
+        let popup = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title));
+        let area = centered_rect(70, 40, f.area());
+        f.render_widget(Clear, area);
+        f.render_widget(popup, area);
+    }
+
+    if let Some(lines) = &app.log_popup {
+        let area = centered_rect(90, 80, f.area());
+        // The newest lines, if they don't all fit
+        let shown = lines.len().saturating_sub(area.height.saturating_sub(2) as usize);
+        let text: Vec<Line> = if lines.is_empty() {
+            vec![Line::from("Nothing logged yet")]
+        } else {
+            lines[shown..].iter().map(|l| Line::from(l.as_str())).collect()
+        };
+        let title = app.log_file.as_ref().map(|p| p.display().to_string()).unwrap_or_default();
+        let popup = Paragraph::new(text).block(Block::default().borders(Borders::ALL).title(title));
+        f.render_widget(Clear, area);
+        f.render_widget(popup, area);
+    }
+
     if app.input_mode == InputMode::Edit {
         // Render editor overlay
         let editor = Paragraph::new(app.edit_buffer.as_str())
@@ -683,11 +382,35 @@ fn ui(f: &mut Frame, app: &mut App) {
         f.render_widget(editor, area);
     }
 
+    if app.input_mode == InputMode::Tags {
+        let tags: Vec<ListItem> = app
+            .visible_tags()
+            .into_iter()
+            .map(|(depth, node)| {
+                let marker = match (node.children.is_empty(), app.expanded_tags.contains(&node.path)) {
+                    (true, _) => " ",
+                    (false, true) => "▾",
+                    (false, false) => "▸",
+                };
+                ListItem::new(Line::from(vec![
+                    Span::raw(format!("{}{} {} ", "  ".repeat(depth), marker, node.name)),
+                    Span::styled(format!("({})", node.total), Style::default().fg(Color::DarkGray)),
+                ]))
+            })
+            .collect();
+        let picker = List::new(tags)
+            .block(Block::default().borders(Borders::ALL).title("Tags (Enter filter, ←/→ fold, Backspace clear, Esc close)"))
+            .highlight_style(Style::default().bg(Color::LightGreen).add_modifier(Modifier::BOLD));
+        let area = centered_rect(50, 60, f.area());
+        f.render_widget(Clear, area);
+        f.render_stateful_widget(picker, area, &mut app.tag_state);
+    }
+
     if app.show_help {
         let help_text = "Navigation: j/k or ↑/↓ • Click to select
 : (colon) enter command mode
-Commands: :n <name> (new), :wiki <name> (switch), :s <query> (fuzzy), :s re:<regex> (regex), :edit (inline), :q quit
-Keys: i edit inline, e/Enter external editor, F1 or :help show this help";
+Commands: :n <name> (new), :n alone (write first, titled on save), :wiki <name> (switch), :split <wiki> (second pane), :only (close it), :mv-to-other (move the fact across), :s <query> (fuzzy), :s re:<regex> (regex), :s name:|data:|tag:<query> (one field), :edit (inline), :delete [--hard] (to trash), :cols date (ages), :sort modified|frecency|default, :recent [count|off] (latest changes), :doctor (check wiki), :reindex, :log (with --log-file), :q quit
+Keys: i edit inline, e/Enter external editor (Enter creates the fact when a search finds nothing), t filter by tag, T edit tags (Tab completes), Tab or Ctrl-W w other pane, S snapshots, I details, F1 or :help show this help";
         let help = Paragraph::new(help_text).block(Block::default().borders(Borders::ALL).title("Help"));
         let area = centered_rect(60, 40, f.area());
         f.render_widget(Clear, area);
@@ -695,6 +418,84 @@ Keys: i edit inline, e/Enter external editor, F1 or :help show this help";
     }
 }
 
+/// A pane's list of facts in `area`, with its border in `border`
+fn draw_list(f: &mut Frame, app: &mut App, area: Rect, border: Style) {
+    let width = area.width as usize;
+
+    // Compute the widest tag badges, and a space after them, so the ' | ' separator aligns
+    let tags_max = app
+        .items
+        .iter()
+        .map(|(_, _, tags, _, _, _)| if tags.is_empty() { 0 } else { badges_width(tags) + 1 })
+        .max()
+        .unwrap_or(0);
+    // The age column and the space before it come out of the room for titles and previews
+    let age_width = if app.show_age { table::AGE_WIDTH + 1 } else { 0 };
+    let title_max = std::cmp::max(
+        10,
+        if width > tags_max + 3 + age_width { (width - tags_max - 3 - age_width) / 2 } else { 10 },
+    );
+    // Inside the borders and the highlight symbol
+    let inner = width.saturating_sub(2 + 3);
+
+    let items: Vec<ListItem> = app
+        .items
+        .iter()
+        .map(|(name, preview, tags, _id, _path, changed)| {
+            let title = table::truncate(name, title_max);
+
+            // compose combined left column with fixed width = tags_max + title_max
+            let mut spans = tag_badges(tags, &app.wiki.config.tag_colors);
+            spans.push(Span::raw(" ".repeat(tags_max - if tags.is_empty() { 0 } else { badges_width(tags) })));
+            spans.push(Span::styled(
+                format!("{}{}", title, " ".repeat(title_max.saturating_sub(title.width()))),
+                Style::default().add_modifier(Modifier::BOLD),
+            ));
+            spans.push(Span::raw(" | "));
+
+            let room = inner.saturating_sub(tags_max + title_max + 3 + age_width);
+            if app.show_age {
+                // Cut or pad the preview so the ages line up at the right edge
+                let preview = table::truncate(preview, room);
+                let pad = " ".repeat(room - preview.width() + 1);
+                let age = changed.map(table::age).unwrap_or_default();
+                spans.push(Span::raw(preview + &pad));
+                spans.push(Span::styled(
+                    format!("{:>width$}", age, width = table::AGE_WIDTH),
+                    Style::default().fg(Color::DarkGray),
+                ));
+            } else {
+                spans.push(Span::raw(preview));
+            }
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+
+    let items = List::new(items)
+        .block(Block::default().borders(Borders::ALL).border_style(border).title(format!(
+            "Wiki: {}{}{}{}{}",
+            app.wiki.name,
+            if app.wiki.readonly { " [read-only]" } else { "" },
+            app.tag_filter.as_ref().map(|t| format!(" [{}]", t)).unwrap_or_default(),
+            app.recent.map(|n| format!(" [recent {}]", n)).unwrap_or_default(),
+            if app.unsynced { " ● unsynced changes" } else { "" }
+        )))
+        .highlight_style(
+            Style::default()
+                .bg(Color::LightGreen)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol(">> ");
+
+    f.render_stateful_widget(items, area, &mut app.state);
+
+    if let Some(query) = app.create_hint() {
+        let hint = Paragraph::new(format!("No matches; press Enter to create '{}'", query))
+            .style(Style::default().fg(Color::DarkGray));
+        f.render_widget(hint, area.inner(Margin { horizontal: 1, vertical: 1 }));
+    }
+}
+
 fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     let popup_layout = Layout::default()
         .direction(Direction::Vertical)
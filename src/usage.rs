@@ -0,0 +1,110 @@
+//! How often and how lately each fact was used, so `--sort frecency` can put
+//! the facts used most at the top. Only counted with `track_usage = true` in
+//! the config, and kept in a file of its own inside the wiki directory that is
+//! never synced or committed, so it stays on this machine.
+//!
+//! A fact is used when `wk show` prints it, it is opened or its details shown
+//! in the TUI, it is picked with `wk r --pick`, or it is among the best
+//! [`RECALL_USES`] results of a recall.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+use crate::error::WikiError;
+use crate::helpers::write_atomic;
+use crate::wiki::{Information, Wiki};
+
+/// Name of the usage counts inside a wiki directory
+pub const USAGE_FILE: &str = ".usage.json";
+/// How many of a recall's best results count as used
+pub const RECALL_USES: usize = 3;
+/// Days after which a fact's uses count half as much
+pub const FRECENCY_HALF_LIFE_DAYS: f64 = 14.0;
+
+/// How much one fact has been used
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Usage {
+    pub count: u64,
+    /// When it was last used
+    pub last: Option<DateTime<Utc>>,
+}
+
+/// How highly `usage` ranks at `now`: the number of uses, halved for every
+/// [`FRECENCY_HALF_LIFE_DAYS`] since the last one,
+///
+/// ```text
+/// count × 0.5 ^ (days since last use / half-life)
+/// ```
+///
+/// so a fact used ten times a month ago ranks with one used five times two
+/// weeks ago. A fact never used scores 0, and a last use in the future
+/// (from a clock that was ahead) counts as now.
+pub fn frecency(usage: &Usage, now: DateTime<Utc>) -> f64 {
+    let Some(last) = usage.last else {
+        return 0.0;
+    };
+    let days = (now - last).num_seconds().max(0) as f64 / 86_400.0;
+    usage.count as f64 * 0.5f64.powf(days / FRECENCY_HALF_LIFE_DAYS)
+}
+
+/// Sort `facts` by [`frecency`] at `now`, highest first. Facts that score
+/// the same, such as those never used, keep their order.
+pub fn sort_by_frecency(facts: &mut [Information], usage: &HashMap<Uuid, Usage>, now: DateTime<Utc>) {
+    let score = |info: &Information| usage.get(&info.id).map_or(0.0, |u| frecency(u, now));
+    facts.sort_by(|a, b| score(b).total_cmp(&score(a)));
+}
+
+impl Wiki {
+    fn usage_path(&self) -> PathBuf {
+        self.path.join(USAGE_FILE)
+    }
+
+    /// How much each fact has been used, or `None` if usage isn't tracked.
+    /// Facts never used are left out.
+    pub fn usage(&self) -> Option<HashMap<Uuid, Usage>> {
+        self.config.track_usage.then(|| {
+            std::fs::read(self.usage_path())
+                .ok()
+                .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+                .unwrap_or_default()
+        })
+    }
+
+    /// Count a use of each of `ids`, if usage is tracked. Like looks in the
+    /// access log, uses aren't recorded in a read-only wiki and failing to
+    /// save them is ignored.
+    pub fn record_use(&self, ids: &[Uuid]) {
+        if self.readonly || self.is_dry_run() || ids.is_empty() {
+            return;
+        }
+        let Some(mut usage) = self.usage() else {
+            return;
+        };
+        let now = Utc::now();
+        for &id in ids {
+            let entry = usage.entry(id).or_default();
+            entry.count += 1;
+            entry.last = Some(now);
+        }
+        let path = self.usage_path();
+        let result = serde_json::to_vec(&usage)
+            .map_err(std::io::Error::other)
+            .and_then(|json| write_atomic(&path, &json));
+        if let Err(e) = result {
+            tracing::warn!(path = %path.display(), "couldn't record usage: {}", e);
+        }
+    }
+
+    /// Forget every use counted so far. Returns whether there were any.
+    pub fn reset_usage(&self) -> Result<bool, WikiError> {
+        self.check_writable()?;
+        match std::fs::remove_file(self.usage_path()) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
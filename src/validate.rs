@@ -0,0 +1,155 @@
+//! What a fact's name and tags may hold.
+//!
+//! Both are trimmed. Neither may hold control characters, line breaks
+//! included, and a name's inner runs of whitespace are collapsed to one
+//! space. Tags also can't hold `[` or `]`, which would break the CLI's
+//! `[tag]` queries, and are capped at [`DEFAULT_MAX_TAG_LEN`] characters
+//! unless the config's `max_tag_len` says otherwise.
+//!
+//! Names derived from a fact's data aren't checked: they follow the data,
+//! line breaks and all.
+
+use std::fmt;
+
+use crate::error::WikiError;
+use crate::wiki::{Information, Wiki};
+
+/// Longest a tag may be, in characters, unless the config sets `max_tag_len`
+pub const DEFAULT_MAX_TAG_LEN: usize = 64;
+
+/// Which of a fact's values failed to validate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Name,
+    Tag,
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Field::Name => "name",
+            Field::Tag => "tag",
+        })
+    }
+}
+
+/// Why a value failed to validate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Problem {
+    /// A control character, such as a line break or tab
+    ControlCharacter(char),
+    /// A `[` or `]` in a tag
+    Bracket(char),
+    /// A tag longer than `max` characters
+    TooLong { len: usize, max: usize },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::ControlCharacter('\n' | '\r') => write!(f, "can't contain a line break"),
+            Problem::ControlCharacter(c) => write!(f, "can't contain the control character {:?}", c),
+            Problem::Bracket(c) => write!(f, "can't contain '{}', as tags are written [like this] in queries", c),
+            Problem::TooLong { len, max } => {
+                write!(f, "{} characters long, over the limit of {} (max_tag_len)", len, max)
+            }
+        }
+    }
+}
+
+/// A name or tag refused by [`name`] or [`tag`], with the value as given
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    pub field: Field,
+    pub value: String,
+    pub problem: Problem,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Debug formatting shows line breaks and other control characters as escapes
+        let mut value: String = self.value.chars().take(40).collect();
+        if value.len() < self.value.len() {
+            value.push('…');
+        }
+        write!(f, "{} {:?}: {}", self.field, value, self.problem)
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// `name` trimmed, with its inner whitespace collapsed to single spaces
+pub fn name(name: &str) -> Result<String, ValidationError> {
+    check_controls(Field::Name, name)?;
+    Ok(collapse(name))
+}
+
+/// `tag` trimmed, or why it can't be used as a tag
+pub fn tag(tag: &str, max_len: usize) -> Result<String, ValidationError> {
+    check_controls(Field::Tag, tag)?;
+    let error = |problem| ValidationError { field: Field::Tag, value: tag.to_string(), problem };
+    if let Some(c) = tag.chars().find(|c| matches!(c, '[' | ']')) {
+        return Err(error(Problem::Bracket(c)));
+    }
+    let trimmed = tag.trim();
+    let len = trimmed.chars().count();
+    if len > max_len {
+        return Err(error(Problem::TooLong { len, max: max_len }));
+    }
+    Ok(trimmed.to_string())
+}
+
+fn check_controls(field: Field, value: &str) -> Result<(), ValidationError> {
+    match value.chars().find(|c| c.is_control()) {
+        Some(c) => Err(ValidationError { field, value: value.to_string(), problem: Problem::ControlCharacter(c) }),
+        None => Ok(()),
+    }
+}
+
+/// Runs of whitespace, control characters included, as single spaces, with
+/// none at either end
+fn collapse(value: &str) -> String {
+    value
+        .split(|c: char| c.is_whitespace() || c.is_control())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `value` as `wk doctor --fix` would store it: trimmed, with control
+/// characters as spaces and, in a name, runs of whitespace collapsed
+pub fn normalize(field: Field, value: &str) -> String {
+    match field {
+        Field::Name => collapse(value),
+        Field::Tag => value.replace(|c: char| c.is_control(), " ").trim().to_string(),
+    }
+}
+
+impl Wiki {
+    /// Longest a tag may be in this wiki
+    pub fn max_tag_len(&self) -> usize {
+        self.config.max_tag_len.unwrap_or(DEFAULT_MAX_TAG_LEN)
+    }
+
+    /// Trim and check the name and tags of `info`, dropping tags left empty.
+    /// Only what differs from `before` is checked, so a fact that already
+    /// breaks the rules can still be changed in other ways until
+    /// `wk doctor --fix` sees to it.
+    pub(crate) fn sanitize(&self, info: &mut Information, before: Option<&Information>) -> Result<(), WikiError> {
+        if !info.name_is_derived && before.is_none_or(|b| b.name != info.name) {
+            info.name = name(&info.name)?;
+        }
+        let mut tags: Vec<String> = Vec::with_capacity(info.tags.len());
+        for t in &info.tags {
+            let t = match before.is_some_and(|b| b.tags.contains(t)) {
+                true => t.clone(),
+                false => tag(t, self.max_tag_len())?,
+            };
+            if !t.is_empty() && !tags.contains(&t) {
+                tags.push(t);
+            }
+        }
+        info.tags = tags;
+        Ok(())
+    }
+}
@@ -1,222 +1,1862 @@
-use nucleo_matcher::{Config, Matcher};
-use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Utc};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::{Map, Value};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashSet};
 use std::fs::create_dir_all;
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
 use uuid::Uuid;
 
-use crate::helpers::Locked;
-use std::sync::{Arc, Mutex};
+use crate::config::{CONFIG_FILE, Config};
+use crate::dryrun::Planned;
+use crate::encryption;
+use crate::error::WikiError;
+use crate::feed;
+use crate::events::{Subscribers, WikiEvent};
+use crate::fold::FoldMap;
+use crate::haystack::{self, HaystackCache, Haystacks};
+use crate::helpers::{FileLock, Key, LOCK_TIMEOUT, Locked};
+use crate::index::{MIN_INDEXED_QUERY, SEARCH_INDEX_FILE, SearchIndex};
+use crate::matching;
+use crate::pdf::{self, PdfBackend, PdfOutput};
+use crate::progress::{NoProgress, Progress};
+use crate::stats::Stats;
+use crate::storage::{Backend, FsStorage, LoadWarning, Stale, Storage};
+use crate::tags::{self, TagNode};
+use crate::wikis::{self, PathSource, ResolvedPath};
+use crate::window::TimeWindow;
 use std::thread;
+use tracing::{debug, warn};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// Fewest candidates a recall worker thread scores, so small wikis are
+/// scored without handing work between threads
+const MIN_RECALL_CHUNK: usize = 256;
+
+/// The field a recall hit matched, and its score there before tag weights
+type Matched = (Field, u32);
+
+/// Recall hits as `(score, what matched, candidate)`, best first, ties in
+/// candidate order
+enum Best {
+    All(Vec<(u32, Matched, usize)>),
+    /// Only the best `n` so far, worst on top to be pushed out
+    Top(usize, BinaryHeap<(Reverse<u32>, usize, Matched)>),
+}
+
+impl Best {
+    fn new(limit: Option<usize>) -> Self {
+        match limit {
+            Some(n) => Best::Top(n, BinaryHeap::with_capacity(n.min(1024) + 1)),
+            None => Best::All(Vec::new()),
+        }
+    }
+
+    fn push(&mut self, score: u32, matched: Matched, i: usize) {
+        match self {
+            Best::All(hits) => hits.push((score, matched, i)),
+            Best::Top(n, heap) => {
+                heap.push((Reverse(score), i, matched));
+                if heap.len() > *n {
+                    heap.pop();
+                }
+            }
+        }
+    }
+
+    /// Add the hits of `later`, whose candidates all come after these
+    fn merge(mut self, later: Best) -> Self {
+        match (&mut self, later) {
+            (Best::All(hits), Best::All(later)) => hits.extend(later),
+            (_, Best::Top(_, later)) => {
+                later.into_iter().for_each(|(Reverse(score), i, matched)| self.push(score, matched, i))
+            }
+            (_, Best::All(later)) => later.into_iter().for_each(|(score, matched, i)| self.push(score, matched, i)),
+        }
+        self
+    }
+
+    fn into_sorted(self) -> Vec<(u32, Matched, usize)> {
+        match self {
+            Best::All(mut hits) => {
+                // Being stable, ties stay in candidate order
+                hits.sort_by_key(|(score, _, _)| Reverse(*score));
+                hits
+            }
+            Best::Top(_, heap) => {
+                heap.into_sorted_vec().into_iter().map(|(Reverse(score), i, matched)| (score, matched, i)).collect()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Information {
     pub id: Uuid,
     pub tags: Vec<String>,
     pub name: String,
     pub data: String,
+    #[serde(default)]
+    pub created: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub updated: Option<DateTime<Utc>>,
+    /// Where the fact came from, such as the URL of the page it was copied from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Whether the name was taken from the data, as `wk c` does, rather than
+    /// given; facts saved before this was kept count as derived
+    #[serde(default = "derived", skip_serializing_if = "is_derived")]
+    pub name_is_derived: bool,
+    /// Fields of the fact's file that twk doesn't know, such as ones added by
+    /// other scripts, kept so writing the fact back doesn't drop them
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+fn derived() -> bool {
+    true
+}
+
+fn is_derived(name_is_derived: &bool) -> bool {
+    *name_is_derived
+}
+
+/// The name a fact gets from `data`: its first line with any text, trimmed
+pub fn derived_name(data: &str) -> String {
+    data.lines().find(|l| !l.trim().is_empty()).unwrap_or_default().trim().to_string()
+}
+
+/// Longest title [`derive_title`] gives, in characters
+pub const MAX_TITLE_LEN: usize = 80;
+
+/// A title for `data` that reads better than its first line when that is a
+/// table header or a rule, for `wk c --auto-title`: the first Markdown
+/// heading, or else the first line with more letters than symbols, or else
+/// the first line. Whitespace is collapsed, and a title over
+/// [`MAX_TITLE_LEN`] characters is cut at the last word that fits.
+pub fn derive_title(data: &str) -> String {
+    let heading = data.lines().find_map(|line| {
+        let text = line.trim_start().trim_start_matches('#');
+        let level = line.trim_start().len() - text.len();
+        let title = text.trim().trim_end_matches('#').trim_end();
+        ((1..=6).contains(&level) && text.starts_with([' ', '\t']) && !title.is_empty()).then_some(title)
+    });
+    let wordy = || {
+        data.lines().find(|line| {
+            let letters = line.chars().filter(|c| c.is_alphabetic()).count();
+            let symbols = line.chars().filter(|c| !c.is_alphanumeric() && !c.is_whitespace()).count();
+            letters > symbols
+        })
+    };
+    let first = || data.lines().find(|line| !line.trim().is_empty());
+    let line = heading.or_else(wordy).or_else(first).unwrap_or_default();
+    let words: Vec<&str> = line.split(|c: char| c.is_whitespace() || c.is_control()).filter(|w| !w.is_empty()).collect();
+
+    let mut title = String::new();
+    for word in words {
+        let len = title.chars().count() + word.chars().count() + !title.is_empty() as usize;
+        if len > MAX_TITLE_LEN {
+            // A single word too long to fit is cut rather than dropped
+            if title.is_empty() {
+                title = word.chars().take(MAX_TITLE_LEN).collect();
+            }
+            break;
+        }
+        if !title.is_empty() {
+            title.push(' ');
+        }
+        title.push_str(word);
+    }
+    title
 }
 
 impl Information {
     pub fn path(&self, w: &Wiki) -> PathBuf {
         w.path.join(format!("{}.json", self.id))
     }
+
+    /// The fact as exported, with [`Information::extra`] gathered under an
+    /// `extra` key rather than mixed in with twk's own fields
+    pub fn exported(&self) -> Value {
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        if let Value::Object(fields) = &mut value {
+            fields.retain(|key, _| !self.extra.contains_key(key));
+            if !self.extra.is_empty() {
+                fields.insert("extra".to_string(), Value::Object(self.extra.clone()));
+            }
+        }
+        value
+    }
+
+    /// Serialize a fact as [`Information::exported`] does
+    pub fn serialize_exported<S: Serializer>(info: &Information, serializer: S) -> Result<S::Ok, S::Error> {
+        info.exported().serialize(serializer)
+    }
+
+    /// The name to give a fact whose derived name no longer matches its
+    /// data, or `None` if it's current or was given.
+    ///
+    /// `wk c` names a fact with the whole of its text, so a name is current
+    /// while it's the data or the data's first line. A name that has drifted
+    /// so far from the first line that it can't have been taken from an
+    /// earlier version of it was most likely given, and is left alone.
+    pub fn stale_name(&self) -> Option<String> {
+        let derived = derived_name(&self.data);
+        if !self.name_is_derived || derived.is_empty() || self.name == self.data || self.name == derived {
+            return None;
+        }
+        let was = derived_name(&self.name);
+        let longest = was.chars().count().max(derived.chars().count());
+        (wikis::edit_distance(&was, &derived) * 2 <= longest).then_some(derived)
+    }
+
+    /// Whether the fact carries `tag` or a tag nested under it
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| tags::tag_matches(tag, t))
+    }
 }
 
+/// A wiki's facts, held in memory over the storage they're persisted to.
+///
+/// `Wiki` is `Send + Sync`: reads only take each fact's read lock, so any
+/// number of threads can recall from a shared `&Wiki` at once, while changes
+/// take `&mut self`. Servers share one behind an `RwLock`.
 pub struct Wiki {
     pub name: String,
     pub info: Vec<Locked<Information>>,
     pub path: PathBuf,
+    /// Stored facts that failed to load
+    pub warnings: Vec<LoadWarning>,
+    /// Conflict copies left next to fact files by Syncthing or Dropbox, with
+    /// what they hold
+    pub conflict_copies: Vec<(PathBuf, Information)>,
+    pub config: Config,
+    /// Every change is refused with [`WikiError::ReadOnly`]; set by the
+    /// `readonly` config key, [`crate::set_readonly`], or a wiki directory
+    /// that can't be written to
+    pub readonly: bool,
+    /// Run the commit hooks in `config.hooks`; cleared by [`crate::set_no_hooks`]
+    pub hooks: bool,
+    /// Add `config.default_tags` to new facts; cleared by
+    /// [`crate::set_no_default_tags`]
+    pub default_tags: bool,
+    /// Facts in `info` whose `data` only holds its first line until hydrated
+    partial: Mutex<HashSet<Uuid>>,
+    /// Search index, loaded on first use
+    index: Mutex<Option<SearchIndex>>,
+    /// Names and data converted for fuzzy matching, kept between recalls
+    haystacks: HaystackCache,
+    pub(crate) storage: Box<dyn Storage>,
+    pub(crate) subscribers: Subscribers,
+    /// Steps planned by the dry-run storage, if this is a dry run
+    pub(crate) dry_run: Option<Arc<Mutex<Vec<Planned>>>>,
+}
+
+/// A line of a fact matching [`Wiki::grep`]
+#[derive(Debug, Clone, Serialize)]
+pub struct GrepHit {
+    pub id: Uuid,
+    pub name: String,
+    /// 1-based line number within the fact's data
+    pub line: usize,
+    pub text: String,
+}
+
+/// How [`Wiki::grep`] interprets its pattern
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GrepOptions {
+    /// Match the pattern literally instead of as a regular expression
+    pub fixed: bool,
+    pub ignore_case: bool,
+}
+
+/// Which parts of a fact [`Wiki::recall_refs`] and [`Wiki::recall_exact`]
+/// match a query against, combined with `|`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fields {
+    pub name: bool,
+    pub data: bool,
+    /// Each tag in full, so `lang` matches facts tagged `lang/rust`
+    pub tags: bool,
+    /// Where the fact came from; only searched when asked for
+    pub source: bool,
+}
+
+impl Fields {
+    /// Name, data and tags
+    pub const ALL: Fields = Fields { name: true, data: true, tags: true, source: false };
+    pub const NONE: Fields = Fields { name: false, data: false, tags: false, source: false };
+    pub const NAME: Fields = Fields { name: true, ..Fields::NONE };
+    pub const DATA: Fields = Fields { data: true, ..Fields::NONE };
+    pub const TAGS: Fields = Fields { tags: true, ..Fields::NONE };
+    pub const SOURCE: Fields = Fields { source: true, ..Fields::NONE };
+
+    /// Whether only the name and data are searched, which is all the search
+    /// index and full-text backends know about
+    fn text_only(&self) -> bool {
+        !self.tags && !self.source
+    }
+}
+
+impl Default for Fields {
+    fn default() -> Self {
+        Fields::ALL
+    }
+}
+
+impl std::ops::BitOr for Fields {
+    type Output = Fields;
+
+    fn bitor(self, other: Fields) -> Fields {
+        Fields {
+            name: self.name || other.name,
+            data: self.data || other.data,
+            tags: self.tags || other.tags,
+            source: self.source || other.source,
+        }
+    }
+}
+
+/// Which facts [`Wiki::recall_detailed`] scores, on what, and what it
+/// says about each hit
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RecallOptions<'a> {
+    /// Only facts with this tag, or a tag nested under it
+    pub tag: Option<&'a str>,
+    pub fields: Fields,
+    /// Only facts changed within this window
+    pub window: TimeWindow,
+    /// Find where in its field each hit matched, for
+    /// [`RecallHit::indices`]; takes another pass of the matcher per hit
+    pub indices: bool,
+    /// Match the data of facts over the soft size limit too, which is
+    /// otherwise left out for their names, tags and sources to match alone;
+    /// see [`crate::size`]
+    pub include_large: bool,
+}
+
+/// The part of a fact a recall hit matched best
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Field {
+    Name,
+    Data,
+    /// The fact's tag at this index
+    Tag(usize),
+    Source,
+}
+
+/// What [`Wiki::generate_book_with`] puts in the book
+#[derive(Debug, Clone)]
+pub struct BookOptions {
+    /// End each fact's page with its tags, linking to a page per tag, its
+    /// dates, its source and its id as an anchor to link to
+    pub metadata_footer: bool,
+    /// How the book's search ranks and shows pages
+    pub search: BookSearch,
+    /// Add a page listing every fact by name, A to Z
+    pub index_page: bool,
+    /// Add a page of how many facts and tags the wiki has
+    pub stats_page: bool,
+    /// Write an Atom feed of the newest facts, if the book knows where it's
+    /// published; see [`crate::feed`]
+    pub feed: bool,
+    /// How many facts the feed lists
+    pub feed_limit: usize,
+    /// Where the book is published, like `https://wiki.example.com/`; the
+    /// config's `book_url` if unset
+    pub base_url: Option<String>,
+}
+
+impl Default for BookOptions {
+    fn default() -> Self {
+        BookOptions {
+            metadata_footer: true,
+            search: BookSearch::default(),
+            index_page: true,
+            stats_page: true,
+            feed: true,
+            feed_limit: feed::DEFAULT_FEED_LIMIT,
+            base_url: None,
+        }
+    }
+}
+
+/// The book's `[output.html.search]` settings, passed through to mdbook
+#[derive(Debug, Clone)]
+pub struct BookSearch {
+    /// How many times more a match in a page's title counts than one in its text
+    pub boost_title: u8,
+    /// Words of each result shown under it
+    pub teaser_word_count: u32,
+    /// Facts with this tag, or one nested under it, are left out of the
+    /// search index, e.g. for long code dumps that would match everything
+    pub exclude_tag: String,
+}
+
+impl Default for BookSearch {
+    fn default() -> Self {
+        BookSearch { boost_title: 4, teaser_word_count: 20, exclude_tag: DEFAULT_NOSEARCH_TAG.to_string() }
+    }
+}
+
+/// Tag keeping facts out of the book's search unless another is given
+pub const DEFAULT_NOSEARCH_TAG: &str = "nosearch";
+
+/// Longest description given to a fact's page, in characters
+pub const DESCRIPTION_LEN: usize = 160;
+
+/// The book's page listing every fact A to Z
+pub const INDEX_PAGE: &str = "a-z.md";
+/// The book's page of statistics
+pub const STATS_PAGE: &str = "stats.md";
+
+/// A fuzzy recall result, borrowing its fact from the wiki
+pub struct RecallHit<'a> {
+    /// How well the fact matched, times the weights of its tags
+    pub score: u32,
+    /// How well the fact matched before its tags were weighed; see
+    /// [`Config::tag_weights`]
+    pub raw_score: u32,
+    /// The field that scored highest, the first asked for on a tie
+    pub matched_field: Field,
+    /// Positions in `matched_field` of the characters that matched the
+    /// query, in order, if [`RecallOptions::indices`] asked for them
+    pub indices: Option<Vec<u32>>,
+    /// Whether the fact's data was too large to be matched, see
+    /// [`RecallOptions::include_large`]
+    pub large: bool,
+    fact: Key<'a, Information>,
+}
+
+/// A recall hit with its own copy of the fact, to keep past the wiki's locks
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScoredFact {
+    #[serde(serialize_with = "Information::serialize_exported")]
+    pub info: Information,
+    pub score: u32,
+    pub raw_score: u32,
+    pub matched_field: Field,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub indices: Option<Vec<u32>>,
+    /// Only the name, tags and source were matched, the data being too large
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub large: bool,
+    /// The wiki the fact was found in, when searching several
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wiki: Option<String>,
+    /// Other wikis holding the same fact, when searching several; see
+    /// [`crate::wikis::recall_everywhere`]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub also_in: Vec<String>,
+}
+
+impl From<RecallHit<'_>> for ScoredFact {
+    fn from(hit: RecallHit<'_>) -> Self {
+        ScoredFact {
+            info: (*hit.fact).clone(),
+            score: hit.score,
+            raw_score: hit.raw_score,
+            matched_field: hit.matched_field,
+            indices: hit.indices,
+            large: hit.large,
+            wiki: None,
+            also_in: Vec::new(),
+        }
+    }
+}
+
+impl std::ops::Deref for RecallHit<'_> {
+    type Target = Information;
+
+    fn deref(&self) -> &Information {
+        &self.fact
+    }
 }
 
 impl Wiki {
     /// Create a new wiki with the given name
     pub fn new(name: String, use_global: bool) -> Self {
-        let path = Self::get_wiki_path(&name, use_global);
-        std::fs::create_dir_all(&path).ok();
+        let path = Self::get_wiki_path(&name, use_global).path;
+        if !crate::dry_run_override() {
+            std::fs::create_dir_all(&path).ok();
+        }
+
+        let mut warnings = Vec::new();
+        let config = Self::load_config(&path, &mut warnings);
 
         Wiki {
             name,
             info: Vec::new(),
+            warnings,
+            conflict_copies: Vec::new(),
+            readonly: is_readonly(&path, &config),
+            config,
+            hooks: !crate::no_hooks_override(),
+            default_tags: !crate::no_default_tags_override(),
+            partial: Mutex::default(),
+            haystacks: HaystackCache::default(),
+            index: Mutex::default(),
+            storage: Box::new(FsStorage::new(&path)),
+            path,
+            subscribers: Subscribers::default(),
+            dry_run: None,
+        }
+    }
+
+    /// Open a wiki on top of an arbitrary storage backend, loading its facts.
+    ///
+    /// Backends that support it only load fact headers up front; the full
+    /// `data` is read on first use by recall, updates and [`Wiki::hydrate`].
+    /// `path` is only used as the root for fact paths and book output.
+    pub fn with_storage(name: String, path: PathBuf, storage: Box<dyn Storage>) -> std::io::Result<Self> {
+        let mut loaded = storage.load_lazy()?;
+        let config = Self::load_config(&path, &mut loaded.warnings);
+        loaded.facts.sort_by(|a, b| config.order.compare(a, b));
+        let info: Vec<_> = loaded.facts.into_iter().map(Locked::in_memory).collect();
+        debug!(wiki = %name, path = %path.display(), facts = info.len(), warnings = loaded.warnings.len(), "loaded wiki");
+        for warning in &loaded.warnings {
+            warn!(path = %warning.path.display(), "couldn't load: {}", warning.error);
+        }
+
+        Ok(Wiki {
+            name,
+            info,
+            warnings: loaded.warnings,
+            conflict_copies: loaded.conflict_copies,
+            readonly: is_readonly(&path, &config),
+            config,
+            hooks: !crate::no_hooks_override(),
+            default_tags: !crate::no_default_tags_override(),
+            partial: Mutex::new(loaded.partial),
+            haystacks: HaystackCache::default(),
+            index: Mutex::default(),
             path,
+            storage,
+            subscribers: Subscribers::default(),
+            dry_run: None,
+        })
+    }
+
+    /// Load the wiki's config, falling back to defaults (and recording a
+    /// warning) if it can't be read
+    fn load_config(path: &std::path::Path, warnings: &mut Vec<LoadWarning>) -> Config {
+        let mut config = Config::load(path).unwrap_or_else(|e| {
+            warnings.push(LoadWarning {
+                path: path.join(CONFIG_FILE),
+                error: e.to_string(),
+            });
+            Config::default()
+        });
+        if let Some(error) = crate::config::ignored_hooks(path) {
+            warnings.push(LoadWarning {
+                path: path.join(CONFIG_FILE),
+                error,
+            });
+        }
+        if let Some(case) = crate::case_override() {
+            config.matching.case = case;
         }
+        config
     }
 
-    /// Get the path for a wiki by name
-    fn get_wiki_path(name: &str, use_global: bool) -> PathBuf {
-        if use_global {
-            // Use global user directory
-            let mut path = dirs::data_local_dir().unwrap_or_else(|| PathBuf::from("."));
-            path.push("twk");
-            path.push(name);
-            path
+    /// Register `info` in memory at its place in the configured order
+    pub(crate) fn push_sorted(&mut self, info: Information) {
+        let order = self.config.order;
+        let at = self
+            .info
+            .partition_point(|l| order.compare(&l.read(), &info).is_lt());
+        self.info.insert(at, Locked::in_memory(info));
+    }
+
+    /// Drop every in-memory copy of the fact with the given id, without touching storage
+    pub(crate) fn forget(&mut self, id: Uuid) {
+        self.info.retain(|l| l.read().id != id);
+        self.partial.lock().unwrap().remove(&id);
+        self.haystacks.invalidate(id);
+        self.update_index(|index| index.remove(id));
+    }
+
+    /// Latest creation or update time of any fact
+    pub fn last_modified(&self) -> Option<DateTime<Utc>> {
+        self.info
+            .iter()
+            .filter_map(|l| {
+                let info = l.read();
+                info.updated.or(info.created)
+            })
+            .max()
+    }
+
+    /// Get the path for a wiki by name, and why it resolved there
+    pub fn get_wiki_path(name: &str, use_global: bool) -> ResolvedPath {
+        let pinned_global = || wikis::project_marker().is_some_and(|(_, marker)| marker.global && marker.wiki == name);
+        let (root, source) = if use_global {
+            (wikis::global_root(), PathSource::GlobalFlag)
+        } else if pinned_global() {
+            (wikis::global_root(), PathSource::MarkerGlobal)
         } else {
-            // Check for local .wiki/ folder first
-            let local_path = PathBuf::from(".wiki").join(name);
-            if local_path.exists() || PathBuf::from(".wiki").exists() {
-                local_path
-            } else {
-                // Fall back to global if no .wiki/ folder exists
-                let mut path = dirs::data_local_dir().unwrap_or_else(|| PathBuf::from("."));
-                path.push("twk");
-                path.push(name);
-                path
+            // A local .wiki/ folder takes every wiki name unless asked for global
+            match wikis::local_root() {
+                Some(root) => (root, PathSource::LocalRoot),
+                None => (wikis::global_root(), PathSource::NoLocalRoot),
             }
+        };
+        // Canonical when it exists, so fact paths handed to editors are
+        // absolute and stable
+        let root = root.canonicalize().unwrap_or(root);
+        ResolvedPath {
+            path: root.join(name),
+            source,
         }
     }
 
-    /// Load an existing wiki or create a new one
-    pub fn load_or_create(name: String, use_global: bool) -> Self {
-        let path = Self::get_wiki_path(&name, use_global);
+    /// Load an existing wiki or create a new one.
+    ///
+    /// Only fails for encrypted wikis that can't be unlocked; other storage
+    /// problems fall back to an empty file-backed wiki.
+    pub fn load_or_create(name: String, use_global: bool) -> Result<Self, WikiError> {
+        let path = Self::get_wiki_path(&name, use_global).path;
 
-        if path.exists() {
-            // Load existing wiki data concurrently
-            let info = std::fs::read_dir(&path)
-            .ok()
-            .map(|entries| {
-                let results = Arc::new(Mutex::new(Vec::new()));
-                let handles: Vec<_> = entries
-                .flatten()
-                .filter(|entry| {
-                    entry.path().extension().and_then(|s| s.to_str()) == Some("json")
-                })
-                .map(|entry| {
-                    let results = Arc::clone(&results);
-                    let json_path = entry.path();
-                    thread::spawn(move || {
-                    if let Ok(locked) = Locked::<Information>::load(json_path) {
-                        results.lock().unwrap().push(locked);
-                    }
+        let mut wiki = if encryption::is_encrypted(&path) {
+            // Never fall back to writing plaintext into an encrypted wiki
+            let storage = Backend::Files.open(&path)?;
+            Self::with_storage(name, path, storage)?
+        } else if path.exists() {
+            let fallback = || Box::new(FsStorage::new(&path)) as Box<dyn Storage>;
+            let storage = Backend::detect(&path).open(&path).unwrap_or_else(|e| {
+                warn!(path = %path.display(), "couldn't open storage, falling back to files: {}", e);
+                fallback()
+            });
+            Self::with_storage(name.clone(), path.clone(), storage).unwrap_or_else(|e| {
+                warn!(path = %path.display(), "couldn't load wiki, opening it empty: {}", e);
+                Wiki {
+                    name,
+                    info: Vec::new(),
+                    warnings: Vec::new(),
+                    conflict_copies: Vec::new(),
+                    readonly: is_readonly(&path, &Config::default()),
+                    config: Config::default(),
+                    hooks: !crate::no_hooks_override(),
+                    default_tags: !crate::no_default_tags_override(),
+                    partial: Mutex::default(),
+                    haystacks: HaystackCache::default(),
+                    index: Mutex::default(),
+                    storage: fallback(),
+                    path: path.clone(),
+                    subscribers: Subscribers::default(),
+                    dry_run: None,
+                }
+            })
+        } else {
+            Self::new(name, use_global)
+        };
+        if crate::dry_run_override() {
+            return Ok(wiki.with_dry_run());
+        }
+
+        #[cfg(feature = "git")]
+        if wiki.config.git && !wiki.readonly {
+            wiki.commit_to_git();
+        }
+        if !wiki.readonly {
+            wiki.purge_expired_trash();
+            if wiki.config.refresh_names {
+                wiki.refresh_stale_names();
+            }
+        }
+        Ok(wiki)
+    }
+
+    /// Create the wiki's directory before its first fact is written, unless
+    /// this is a dry run
+    fn create_dir(&self) -> std::io::Result<()> {
+        match self.dry_run {
+            Some(_) => Ok(()),
+            None => create_dir_all(&self.path),
+        }
+    }
+
+    /// Fail with [`WikiError::ReadOnly`] if the wiki mustn't be changed
+    pub(crate) fn check_writable(&self) -> Result<(), WikiError> {
+        if self.readonly {
+            return Err(WikiError::ReadOnly(self.name.clone()));
+        }
+        Ok(())
+    }
+
+    /// Whether the wiki's facts are encrypted at rest
+    pub fn is_encrypted(&self) -> bool {
+        self.storage.is_encrypted()
+    }
+
+    /// Where the search index is saved; `None` for encrypted wikis, whose
+    /// index would give away which characters each fact contains
+    fn index_path(&self) -> Option<PathBuf> {
+        (!self.is_encrypted() && !self.is_dry_run()).then(|| self.path.join(SEARCH_INDEX_FILE))
+    }
+
+    /// Make sure the fact with the given id has its full `data` loaded.
+    ///
+    /// Facts in `info` may only hold the first line of their data after a
+    /// lazy load; call this before reading `data` directly.
+    pub fn hydrate(&self, id: Uuid) -> Result<(), WikiError> {
+        if !self.partial.lock().unwrap().contains(&id) {
+            return Ok(());
+        }
+        let locked = self
+            .info
+            .iter()
+            .find(|l| l.read().id == id)
+            .ok_or(WikiError::NotFound(id))?;
+        Ok(self.hydrate_each(&[locked])?)
+    }
+
+    /// Load the full `data` of every fact that doesn't have it yet
+    pub(crate) fn hydrate_all(&self) -> std::io::Result<()> {
+        if self.partial.lock().unwrap().is_empty() {
+            return Ok(());
+        }
+        let all: Vec<&Locked<Information>> = self.info.iter().collect();
+        self.hydrate_each(&all)
+    }
+
+    /// Read those of `facts` that are still partial in full from storage, on a
+    /// bounded set of worker threads. Facts that fail to load keep their partial data.
+    fn hydrate_each(&self, facts: &[&Locked<Information>]) -> std::io::Result<()> {
+        let facts: Vec<&Locked<Information>> = {
+            let partial = self.partial.lock().unwrap();
+            facts
+                .iter()
+                .copied()
+                .filter(|l| partial.contains(&l.read().id))
+                .collect()
+        };
+        if facts.is_empty() {
+            return Ok(());
+        }
+
+        let workers = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        let chunk_size = facts.len().div_ceil(workers);
+
+        let storage = self.storage.as_ref();
+        let results: Vec<std::io::Result<Uuid>> = thread::scope(|s| {
+            let handles: Vec<_> = facts
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    s.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|locked| {
+                                let id = locked.read().id;
+                                let info = storage.read(id)?;
+                                *locked.write() = info;
+                                Ok(id)
+                            })
+                            .collect::<Vec<_>>()
                     })
                 })
                 .collect();
 
-                for handle in handles {
-                handle.join().ok();
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().unwrap_or_default())
+                .collect()
+        });
+
+        let mut partial = self.partial.lock().unwrap();
+        let mut first_error = None;
+        for result in results {
+            match result {
+                Ok(id) => {
+                    partial.remove(&id);
                 }
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
 
-                Arc::try_unwrap(results).unwrap().into_inner().unwrap()
-            })
-            .unwrap_or_default();
+    /// The search index, read from disk and brought up to date with `info` on first use
+    fn search_index(&self) -> std::io::Result<MutexGuard<'_, Option<SearchIndex>>> {
+        let mut guard = self.index.lock().unwrap();
+        if guard.is_none() {
+            let mut index = self.index_path().map(|path| SearchIndex::load(&path)).unwrap_or_default();
+            let ids: HashSet<Uuid> = self.info.iter().map(|l| l.read().id).collect();
+            index.retain(|id| ids.contains(id));
 
-            Wiki { name, info, path }
-        } else {
-            Self::new(name, use_global)
+            let stale: Vec<&Locked<Information>> =
+                self.info.iter().filter(|l| !index.is_fresh(&l.read())).collect();
+            self.hydrate_each(&stale)?;
+            for locked in stale {
+                index.insert(&locked.read());
+            }
+
+            // Only a cache; it is brought up to date again on the next load
+            if let Some(path) = self.index_path() {
+                index.save(&path).ok();
+            }
+            *guard = Some(index);
         }
+        Ok(guard)
     }
 
-    /// Commit a fact to the wiki
-    pub fn commit(&mut self, fact: String, tags: Vec<String>) -> std::io::Result<Uuid> {
-        let id = Uuid::new_v4();
-        let info = Information {
-            id,
+    /// Names and data of facts already converted for fuzzy matching, for
+    /// scoring them outside [`Wiki::recall_refs`]
+    pub fn haystack_cache(&self) -> &HaystackCache {
+        &self.haystacks
+    }
+
+    /// Apply `f` to the search index if it has been loaded
+    pub(crate) fn update_index(&self, f: impl FnOnce(&mut SearchIndex)) {
+        if let Some(index) = self.index.lock().unwrap().as_mut() {
+            f(index);
+        }
+    }
+
+    /// Rebuild the search index from every fact and save it, returning the
+    /// number of facts indexed
+    pub fn rebuild_index(&self) -> Result<usize, WikiError> {
+        self.rebuild_index_with(&mut NoProgress)
+    }
+
+    /// Like [`Wiki::rebuild_index`], reporting each fact indexed to
+    /// `progress`. The saved index is only replaced once it is complete.
+    pub fn rebuild_index_with(&self, progress: &mut dyn Progress) -> Result<usize, WikiError> {
+        let result = self.build_index(progress);
+        progress.finish();
+        result
+    }
+
+    fn build_index(&self, progress: &mut dyn Progress) -> Result<usize, WikiError> {
+        self.hydrate_all()?;
+
+        let mut index = SearchIndex::default();
+        progress.start(self.info.len());
+        for locked in &self.info {
+            let info = locked.read();
+            index.insert(&info);
+            progress.tick(info.name.lines().next().unwrap_or_default());
+        }
+        if let Some(path) = self.index_path() {
+            index.save(&path)?;
+        }
+
+        let n = index.len();
+        *self.index.lock().unwrap() = Some(index);
+        Ok(n)
+    }
+
+    /// Take the wiki-level lock, held by operations that touch many facts at
+    /// once so another wk process can't interleave writes with them
+    pub fn lock_exclusive(&self) -> Result<FileLock, WikiError> {
+        Ok(FileLock::exclusive(&self.path.join(".lock"), LOCK_TIMEOUT)?)
+    }
+
+    /// Rewrite the wiki's files that aren't in the [canonical](crate::canonical)
+    /// format, returning how many were: facts, the trash and snapshots
+    pub fn reformat(&self) -> Result<usize, WikiError> {
+        self.check_writable()?;
+        self.refuse_dry_run("fmt")?;
+        let _lock = self.lock_exclusive()?;
+        Ok(self.storage.reformat()?)
+    }
+
+    /// Convert this wiki to another storage backend, returning the number of facts moved.
+    ///
+    /// Every fact is written to the new backend and read back for comparison
+    /// before anything is removed from the old one.
+    pub fn migrate(&mut self, to: Backend) -> Result<usize, WikiError> {
+        self.check_writable()?;
+        self.refuse_dry_run("migrate")?;
+        let from = Backend::detect(&self.path);
+        if from == to {
+            return Ok(0);
+        }
+        if self.is_encrypted() {
+            return Err(WikiError::Encrypted(self.name.clone()));
+        }
+        let _lock = self.lock_exclusive()?;
+        self.hydrate_all()?;
+
+        let mut facts: Vec<Information> = self.info.iter().map(|l| (*l.read()).clone()).collect();
+        let trashed = self.storage.trashed()?;
+        let target = to.open(&self.path)?;
+        for info in facts.iter().chain(trashed.iter().map(|t| &t.fact)) {
+            target.write(info)?;
+            for snapshot in self.storage.snapshots(info.id)? {
+                target.write_snapshot(&snapshot)?;
+            }
+        }
+        for t in &trashed {
+            target.trash(t.fact.id, t.deleted)?;
+        }
+
+        let mut round_trip = target.load_all()?.facts;
+        facts.sort_by_key(|i| i.id);
+        round_trip.sort_by_key(|i| i.id);
+        if facts != round_trip {
+            for info in &facts {
+                target.delete(info.id).ok();
+                target.delete_snapshots(info.id).ok();
+            }
+            for t in &trashed {
+                target.purge(t.fact.id).ok();
+            }
+            return Err(WikiError::Io(std::io::Error::other(format!(
+                "migration to {} did not round-trip; {} left unchanged",
+                to, from
+            ))));
+        }
+
+        let old = std::mem::replace(&mut self.storage, target);
+        for info in &facts {
+            old.delete(info.id)?;
+            old.delete_snapshots(info.id)?;
+        }
+        for t in &trashed {
+            old.purge(t.fact.id)?;
+        }
+        drop(old);
+
+        if from == Backend::Files {
+            std::fs::remove_file(self.path.join(crate::storage::HEADERS_FILE)).ok();
+        }
+        #[cfg(feature = "sqlite")]
+        if from == Backend::Sqlite {
+            std::fs::remove_file(self.path.join(crate::sqlite::DB_FILE))?;
+        }
+
+        Ok(facts.len())
+    }
+
+    /// Build a fresh fact with a new id and current timestamps
+    pub(crate) fn new_fact(fact: String, tags: Vec<String>) -> Information {
+        let now = Utc::now();
+        Information {
+            id: Uuid::new_v4(),
             tags,
             name: fact.clone(),
             data: fact,
-        };
+            created: Some(now),
+            updated: Some(now),
+            source: None,
+            name_is_derived: true,
+            extra: Map::new(),
+        }
+    }
+
+    /// Commit a fact to the wiki
+    pub fn commit(&mut self, fact: String, tags: Vec<String>) -> Result<Uuid, WikiError> {
+        self.insert(Self::new_fact(fact, tags))
+    }
+
+    /// Commit a fact under a name other than its data, noting where it came
+    /// from. A name that is the data or its first line still counts as
+    /// derived from it.
+    pub fn commit_named(
+        &mut self,
+        name: String,
+        data: String,
+        tags: Vec<String>,
+        source: Option<String>,
+    ) -> Result<Uuid, WikiError> {
+        let mut info = Self::new_fact(data, tags);
+        info.name_is_derived = name == info.data || name == derived_name(&info.data);
+        info.name = name;
+        info.source = source;
+        self.insert(info)
+    }
 
-        let path = info.path(&self);
-        create_dir_all(path.parent().unwrap())?;
+    /// Persist and register a fully-formed fact, running the commit hooks
+    /// around it
+    pub fn insert(&mut self, mut info: Information) -> Result<Uuid, WikiError> {
+        self.check_writable()?;
+        self.sanitize(&mut info, None)?;
+        self.check_size(&info, None)?;
+        info.tags = self.commit_tags(&info.tags)?;
+        let id = info.id;
+        self.pre_commit(&info)?;
+        self.create_dir()?;
 
-        self.info.push(Locked::new(path, info)?);
+        self.storage.write(&info)?;
+        debug!(%id, tags = ?info.tags, bytes = info.data.len(), "saved new fact");
+        self.update_index(|index| index.insert(&info));
+        self.push_sorted(info.clone());
+        self.subscribers.emit(WikiEvent::Created(info.clone()));
+        self.post_commit(&info);
         Ok(id)
     }
 
+    /// Store `info` as is, timestamps included, replacing any fact with the
+    /// same id; used to bring in facts edited elsewhere
+    pub(crate) fn put(&mut self, info: Information) -> Result<(), WikiError> {
+        self.check_writable()?;
+        self.create_dir()?;
+        self.hydrate(info.id).ok();
+        let before = self
+            .info
+            .iter()
+            .find(|l| l.read().id == info.id)
+            .map(|l| (*l.read()).clone());
+
+        self.storage.write(&info)?;
+        self.forget(info.id);
+        self.update_index(|index| index.insert(&info));
+        self.push_sorted(info.clone());
+        self.subscribers.emit(match before {
+            Some(before) => WikiEvent::Updated { before, after: info },
+            None => WikiEvent::Created(info),
+        });
+        Ok(())
+    }
+
+    /// Commit many facts at once, writing them on a bounded set of worker threads.
+    ///
+    /// Ids are returned in input order. If any write fails, the facts that were
+    /// written are still registered and returned in [`WikiError::PartialCommit`];
+    /// failed writes are removed from storage so nothing is left on disk that
+    /// isn't in `info`. A fact refused by the pre-commit hook refuses the whole
+    /// batch before anything is written.
+    pub fn commit_many(&mut self, facts: Vec<(String, Vec<String>)>) -> Result<Vec<Uuid>, WikiError> {
+        self.check_writable()?;
+        let infos: Vec<Information> = facts
+            .into_iter()
+            .map(|(fact, tags)| {
+                let mut info = Self::new_fact(fact, tags);
+                self.sanitize(&mut info, None)?;
+                self.check_size(&info, None)?;
+                info.tags = self.commit_tags(&info.tags)?;
+                Ok(info)
+            })
+            .collect::<Result<_, WikiError>>()?;
+        self.create_dir()?;
+        if infos.is_empty() {
+            return Ok(Vec::new());
+        }
+        for info in &infos {
+            self.pre_commit(info)?;
+        }
+
+        let workers = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+        let chunk_size = infos.len().div_ceil(workers);
+
+        let storage = self.storage.as_ref();
+        let written: Vec<(Information, std::io::Result<()>)> = thread::scope(|s| {
+            let handles: Vec<_> = infos
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    s.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|info| (info.clone(), storage.write(info)))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().unwrap_or_default())
+                .collect()
+        });
+
+        let mut committed = Vec::with_capacity(infos.len());
+        let mut first_error = None;
+        for (info, result) in written {
+            match result {
+                Ok(()) => {
+                    committed.push(info.id);
+                    self.update_index(|index| index.insert(&info));
+                    self.push_sorted(info.clone());
+                    self.subscribers.emit(WikiEvent::Created(info.clone()));
+                    self.post_commit(&info);
+                }
+                Err(e) => {
+                    self.storage.delete(info.id).ok();
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+
+        // A panicked worker loses its whole chunk without reporting an error
+        if first_error.is_none() && committed.len() != infos.len() {
+            for info in &infos {
+                if !committed.contains(&info.id) {
+                    self.storage.delete(info.id).ok();
+                }
+            }
+            first_error = Some(std::io::Error::other("commit worker panicked"));
+        }
+
+        match first_error {
+            None => Ok(committed),
+            Some(e) => Err(WikiError::PartialCommit {
+                committed,
+                source: Box::new(WikiError::Io(e)),
+            }),
+        }
+    }
+
     /// Recall facts related to a query using fuzzy matching
     pub fn recall(&self, query: &str, tag_filter: Option<&str>) -> Vec<Information> {
-        use nucleo_matcher::Utf32String;
+        self.recall_refs(query, tag_filter, Fields::ALL, TimeWindow::ANY, None)
+            .into_iter()
+            .map(|hit| (*hit).clone())
+            .collect()
+    }
+
+    /// Like [`Wiki::recall`], but matching only `fields`, only over facts
+    /// changed within `window`, and borrowing the best `limit` matches (all of
+    /// them if `None`) instead of cloning every one. A fact scores as its
+    /// best-matching field.
+    ///
+    /// Each hit holds a read lock on its fact, so drop them before updating it.
+    pub fn recall_refs(
+        &self,
+        query: &str,
+        tag_filter: Option<&str>,
+        fields: Fields,
+        window: TimeWindow,
+        limit: Option<usize>,
+    ) -> Vec<RecallHit<'_>> {
+        let opts = RecallOptions { tag: tag_filter, fields, window, ..Default::default() };
+        self.recall_detailed(query, limit, opts)
+    }
+
+    /// The best `limit` facts fuzzy-matching `query` as `opts` asks, best
+    /// first, each with its score and the field it matched, and where in
+    /// that field if [`RecallOptions::indices`]. Scores are the matcher's
+    /// own, comparable only between hits of the same query.
+    pub fn recall_detailed(&self, query: &str, limit: Option<usize>, opts: RecallOptions<'_>) -> Vec<RecallHit<'_>> {
+        use nucleo_matcher::Utf32Str;
+
+        let RecallOptions { tag: tag_filter, fields, window, include_large, .. } = opts;
+        let skips_data = |info: &Information| fields.data && !include_large && self.is_large(info);
+
+        // The index only narrows names and data; tags and sources are already
+        // loaded, so every fact is a candidate for those
+        let indexed = if fields.name || fields.data { self.index_candidates(query) } else { None };
+        let in_index = |id: &Uuid| indexed.as_ref().is_none_or(|ids| ids.contains(id));
+        let candidates: Vec<&Locked<Information>> = self
+            .info
+            .iter()
+            .filter(|l| {
+                let info = l.read();
+                (in_index(&info.id) || !fields.text_only()) && window.contains(&info)
+            })
+            .collect();
+        // Before scoring, so facts outside the window are never read in full.
+        // Facts that can't be read in full are matched on what is already loaded.
+        if fields.data {
+            let texts: Vec<&Locked<Information>> =
+                candidates.iter().copied().filter(|l| in_index(&l.read().id)).collect();
+            self.hydrate_each(&texts).ok();
+        }
+
+        let matching = &self.config.matching;
+        let folded = matching.needle(query);
+        let mut needle_buf = Vec::new();
+        let needle = Utf32Str::new(&folded, &mut needle_buf);
+
+        // Workers each score a run of candidates with their own matcher and
+        // UTF-32 buffer, keeping only their best `limit` hits, along with the
+        // names and data they converted for want of cached ones
+        let cache = self.haystacks.read();
+        let (best, found, fresh) = candidates
+            .par_iter()
+            .enumerate()
+            .with_min_len(MIN_RECALL_CHUNK)
+            .map_init(
+                || (matching::make_matcher(&self.config, query), Vec::new()),
+                |(matcher, haystack_buf), (i, locked_info)| {
+                    let info_key = locked_info.read();
+
+                    // Filter by tag if specified
+                    if let Some(tag) = tag_filter
+                        && !self.tagged(&info_key, tag)
+                    {
+                        return (None, None);
+                    }
 
-        let mut matcher = Matcher::new(Config::DEFAULT);
-        let mut scored_results: Vec<(u32, Information)> = Vec::new();
+                    // Names and data come from the cache, converting them here
+                    // if they're missing and there's room to keep them after
+                    let data = fields.data && !skips_data(&info_key);
+                    let text = in_index(&info_key.id) && (fields.name || data);
+                    let cached = cache.get(&info_key);
+                    let fresh = match cached {
+                        _ if !text => None,
+                        Some(h) if !data || h.data.is_some() => None,
+                        _ => {
+                            let keep_data = data && cache.has_room(haystack::converted_size(&info_key.data));
+                            (cached.is_none() || keep_data).then(|| Haystacks::new_with(&info_key, keep_data, &self.config.matching))
+                        }
+                    };
+                    let haystacks = fresh.as_ref().or(cached);
 
-        for locked_info in &self.info {
-            let info_key = locked_info.read();
+                    // Fuzzy match each field asked for
+                    let mut best: Option<(u16, Field)> = None;
+                    let mut score = |haystack: Utf32Str<'_>, field: Field| {
+                        if let Some(score) = matcher.fuzzy_match(haystack, needle)
+                            && best.is_none_or(|(best, _)| score > best)
+                        {
+                            best = Some((score, field));
+                        }
+                    };
+                    if let Some(haystacks) = haystacks.filter(|_| text) {
+                        if fields.name {
+                            score(haystacks.name.slice(..), Field::Name);
+                        }
+                        if data {
+                            match &haystacks.data {
+                                Some(data) => score(data.slice(..), Field::Data),
+                                None => score(Utf32Str::new(&matching.haystack(&info_key.data), haystack_buf), Field::Data),
+                            }
+                        }
+                    }
+                    if fields.tags {
+                        for (t, tag) in info_key.tags.iter().enumerate() {
+                            score(Utf32Str::new(&matching.haystack(tag), haystack_buf), Field::Tag(t));
+                        }
+                    }
+                    if fields.source
+                        && let Some(source) = &info_key.source
+                    {
+                        score(Utf32Str::new(&matching.haystack(source), haystack_buf), Field::Source);
+                    }
+                    let hit = best.map(|(raw, field)| (self.weighted(&info_key, raw as u32), (field, raw as u32), i));
+                    (hit, fresh.map(|h| (info_key.id, h)))
+                },
+            )
+            .fold(
+                || (Best::new(limit), 0, Vec::new()),
+                |(mut best, mut found, mut fresh), (hit, haystacks)| {
+                    if let Some((score, matched, i)) = hit {
+                        best.push(score, matched, i);
+                        found += 1;
+                    }
+                    fresh.extend(haystacks);
+                    (best, found, fresh)
+                },
+            )
+            .reduce(
+                || (Best::new(limit), 0, Vec::new()),
+                |(best, found, mut fresh), (later, later_found, later_fresh)| {
+                    fresh.extend(later_fresh);
+                    (best.merge(later), found + later_found, fresh)
+                },
+            );
+        drop(cache);
+        self.haystacks.insert(fresh);
+
+        debug!(query = %query, tag = tag_filter, candidates = candidates.len(), hits = found, "recalled");
+        let mut matcher = opts.indices.then(|| matching::make_matcher(&self.config, query));
+        let mut haystack_buf = Vec::new();
+        // Two files holding the same id both load; only the better hit counts
+        let mut seen = HashSet::new();
+        best.into_sorted()
+            .into_iter()
+            .filter(|&(_, _, i)| seen.insert(candidates[i].read().id))
+            .map(|(score, (matched_field, raw_score), i)| {
+                let fact = candidates[i].read();
+                let indices = matcher.as_mut().map(|matcher| {
+                    let haystack = match matched_field {
+                        Field::Name => &fact.name,
+                        Field::Data => &fact.data,
+                        Field::Tag(t) => &fact.tags[t],
+                        Field::Source => fact.source.as_deref().unwrap_or_default(),
+                    };
+                    let mut indices = Vec::new();
+                    if !matching.fold_diacritics {
+                        matcher.fuzzy_indices(Utf32Str::new(haystack, &mut haystack_buf), needle, &mut indices);
+                        indices.sort_unstable();
+                        indices.dedup();
+                        return indices;
+                    }
+                    // Matched in the folded text, but pointing into the original
+                    let (folded, map) = FoldMap::new(haystack);
+                    matcher.fuzzy_indices(Utf32Str::new(&folded, &mut haystack_buf), needle, &mut indices);
+                    map.original(&indices)
+                });
+                let large = skips_data(&fact);
+                RecallHit { score, raw_score, matched_field, indices, large, fact }
+            })
+            .collect()
+    }
+
+    /// The best `n` facts fuzzy-matching `query`, as [`Wiki::recall_refs`]
+    /// with a limit. Scoring keeps no more than `n` hits per worker thread, so
+    /// asking for a few of a large wiki costs little beyond the scoring itself.
+    pub fn recall_top_n(&self, query: &str, n: usize, opts: RecallOptions<'_>) -> Vec<RecallHit<'_>> {
+        self.recall_detailed(query, Some(n), opts)
+    }
+
+    /// Ids of facts that could fuzzy-match `query`, or `None` to score every fact
+    fn index_candidates(&self, query: &str) -> Option<HashSet<Uuid>> {
+        // The index folds characters only as the matcher does, so it could
+        // leave out facts that match once folded further
+        if query.chars().count() < MIN_INDEXED_QUERY || self.config.matching.fold_diacritics {
+            return None;
+        }
+        let index = self.search_index().ok()?;
+        index.as_ref().map(|index| index.candidates(query))
+    }
+
+    /// Recall facts where every word of `query` appears (case-insensitive) in
+    /// one of `fields`, not necessarily the same one. Names and data are
+    /// pushed down to the storage backend's full-text index when it has one,
+    /// unless the config's `match.fold_patterns` has diacritics ignored too.
+    pub fn recall_exact(&self, query: &str, tag_filter: Option<&str>, fields: Fields) -> Vec<Information> {
+        let matching = &self.config.matching;
+        let words: Vec<String> = query.split_whitespace().map(|w| matching.pattern_text(w).to_lowercase()).collect();
+        let contains = |haystack: &str, word: &str| matching.pattern_text(haystack).to_lowercase().contains(word);
+        // Whether any word is found in the tags or source, which are already loaded
+        let in_loaded = |info: &Information| {
+            words.iter().any(|w| {
+                (fields.tags && info.tags.iter().any(|t| contains(t, w)))
+                    || (fields.source && info.source.as_deref().is_some_and(|s| contains(s, w)))
+            })
+        };
+
+        // Facts with every word in their name or data, plus any with one elsewhere
+        let searched = match fields.name || fields.data {
+            true if matching.fold_patterns => None,
+            true => self.storage.search(query),
+            false => Some(Vec::new()),
+        };
+        let candidates: Vec<&Locked<Information>> = match searched {
+            // In the backend's order, best match first
+            Some(ids) => ids
+                .iter()
+                .filter_map(|id| self.info.iter().find(|l| l.read().id == *id))
+                .chain(self.info.iter().filter(|l| {
+                    let info = l.read();
+                    !ids.contains(&info.id) && in_loaded(&info)
+                }))
+                .collect(),
+            None => self.info.iter().collect(),
+        };
+        if fields.data {
+            self.hydrate_each(&candidates).ok();
+        }
+
+        let hits: Vec<Information> = candidates
+            .iter()
+            .map(|l| l.read())
+            .filter(|info| tag_filter.is_none_or(|tag| self.tagged(info, tag)))
+            .filter(|info| {
+                words.iter().all(|w| {
+                    (fields.name && contains(&info.name, w))
+                        || (fields.data && contains(&info.data, w))
+                        || (fields.tags && info.tags.iter().any(|t| contains(t, w)))
+                        || (fields.source && info.source.as_deref().is_some_and(|s| contains(s, w)))
+                })
+            })
+            .map(|info| (*info).clone())
+            .collect();
+        debug!(query, tag = tag_filter, candidates = candidates.len(), hits = hits.len(), "recalled exactly");
+        hits
+    }
+
+    /// Facts that look like a restatement of `text`, most similar first, with
+    /// their similarity between 0 and 1; only those at or above `threshold`.
+    ///
+    /// The shorter of the two texts is fuzzy-matched against the longer and
+    /// scored relative to a perfect match, then scaled down by how different
+    /// their lengths are, so a short fact contained in a long one isn't
+    /// taken for a duplicate of it.
+    pub fn find_similar(&self, text: &str, threshold: f32) -> Vec<(Information, f32)> {
+        use nucleo_matcher::Utf32Str;
+
+        let text = text.trim();
+        if text.is_empty() {
+            return Vec::new();
+        }
+        self.hydrate_all().ok();
+
+        let matching = &self.config.matching;
+        let mut matcher = matching::make_matcher(&self.config, text);
+        let (mut needle_buf, mut haystack_buf) = (Vec::new(), Vec::new());
+        let mut similar: Vec<(Information, f32)> = Vec::new();
+        for locked in &self.info {
+            let info = locked.read();
+            let data = info.data.trim();
+            let (short, long) = if data.chars().count() < text.chars().count() { (data, text) } else { (text, data) };
+            let (short_len, long_len) = (short.chars().count(), long.chars().count());
+            if short_len == 0 {
+                continue;
+            }
+            // Either text may be the needle, and smart case depends on which
+            matcher.config = matching.nucleo_config(short);
+            let folded = matching.needle(short);
+            let needle = Utf32Str::new(&folded, &mut needle_buf);
+            let Some(perfect) = matcher.fuzzy_match(Utf32Str::new(&matching.haystack(short), &mut haystack_buf), needle) else {
+                continue;
+            };
+            let Some(score) = matcher.fuzzy_match(Utf32Str::new(&matching.haystack(long), &mut haystack_buf), needle) else {
+                continue;
+            };
+            let similarity = (score as f32 / perfect as f32).min(1.0) * (short_len as f32 / long_len as f32).sqrt();
+            if similarity >= threshold {
+                similar.push(((*info).clone(), similarity));
+            }
+        }
+        similar.sort_by(|a, b| b.1.total_cmp(&a.1));
+        similar
+    }
+
+    /// Every line of every fact's data matching `pattern`, in fact order
+    pub fn grep(&self, pattern: &str, opts: GrepOptions) -> Result<Vec<GrepHit>, WikiError> {
+        let pattern = if opts.fixed { regex::escape(pattern) } else { pattern.to_string() };
+        let matching = &self.config.matching;
+        let re = matching.regex(&pattern, opts.ignore_case)?;
+        self.hydrate_all()?;
 
-            // Filter by tag if specified
-            if let Some(tag) = tag_filter {
-                if !info_key.tags.contains(&tag.to_string()) {
-                    continue;
+        let mut hits = Vec::new();
+        for locked in &self.info {
+            let info = locked.read();
+            for (i, line) in info.data.lines().enumerate() {
+                if re.is_match(&matching.pattern_text(line)) {
+                    hits.push(GrepHit {
+                        id: info.id,
+                        name: info.name.clone(),
+                        line: i + 1,
+                        text: line.to_string(),
+                    });
                 }
             }
+        }
+        Ok(hits)
+    }
+
+    /// Get all facts with a specific tag or one nested under it, so `lang`
+    /// also finds `lang/rust`
+    pub fn recall_by_tag(&self, tag: &str) -> Vec<Information> {
+        self.recall_by_tag_refs(tag, TimeWindow::ANY)
+            .into_iter()
+            .map(|key| (*key).clone())
+            .collect()
+    }
+
+    /// Like [`Wiki::recall_by_tag`], but only facts changed within `window`,
+    /// each borrowed under its read lock
+    pub fn recall_by_tag_refs(&self, tag: &str, window: TimeWindow) -> Vec<Key<'_, Information>> {
+        let results: Vec<&Locked<Information>> = self
+            .info
+            .iter()
+            .filter(|l| {
+                let info = l.read();
+                self.tagged(&info, tag) && window.contains(&info)
+            })
+            .collect();
+
+        self.hydrate_each(&results).ok();
+        results.iter().map(|l| l.read()).collect()
+    }
+
+    /// The fact with the given id, read in full
+    pub fn get(&self, id: Uuid) -> Result<Information, WikiError> {
+        self.hydrate(id)?;
+        self.info
+            .iter()
+            .find(|l| l.read().id == id)
+            .map(|l| (*l.read()).clone())
+            .ok_or(WikiError::NotFound(id))
+    }
+
+    /// The file a fact is stored in, if it is plain JSON an editor can open;
+    /// `None` for encrypted wikis and other backends
+    pub fn fact_file(&self, id: Uuid) -> Option<PathBuf> {
+        (Backend::detect(&self.path) == Backend::Files && !self.is_encrypted())
+            .then(|| self.path.join(format!("{}.json", id)))
+    }
+
+    /// Facts with no created or updated time, saved before either was recorded
+    pub fn undated(&self) -> usize {
+        self.info
+            .iter()
+            .filter(|l| {
+                let info = l.read();
+                info.created.is_none() && info.updated.is_none()
+            })
+            .count()
+    }
+
+    /// Every fact, read in full, in the configured order
+    pub fn all(&self) -> Vec<Information> {
+        self.snapshot_facts()
+    }
+
+    /// Every fact in full as it stood at one moment. The read lock of every
+    /// fact is taken before any is copied and all are held until the copy is
+    /// done, so a writer changing several facts one lock at a time is seen
+    /// either before or after each of its writes, never with the later ones
+    /// in and the earlier ones missing. Writers only wait for the copy, not
+    /// for whatever is done with it.
+    pub fn snapshot_facts(&self) -> Vec<Information> {
+        self.hydrate_all().ok();
+        self.read_all().iter().map(|key| (**key).clone()).collect()
+    }
+
+    /// The read lock of every fact, all held at once, for reading the wiki
+    /// as it stands at one moment without copying it; see [`Wiki::snapshot_facts`]
+    pub(crate) fn read_all(&self) -> Vec<Key<'_, Information>> {
+        self.info.iter().map(|l| l.read()).collect()
+    }
 
-            // Convert strings to UTF-32 for fuzzy matching
-            let haystack_name = Utf32String::from(info_key.name.as_str());
-            let haystack_data = Utf32String::from(info_key.data.as_str());
-            let needle = Utf32String::from(query);
-
-            // Fuzzy match against name and data
-            let name_score = matcher.fuzzy_match(haystack_name.slice(..), needle.slice(..));
-            let data_score = matcher.fuzzy_match(haystack_data.slice(..), needle.slice(..));
-
-            // Use the best score
-            if let Some(score) = name_score.or(data_score) {
-                scored_results.push((
-                    score as u32,
-                    Information {
-                        id: info_key.id,
-                        tags: info_key.tags.clone(),
-                        name: info_key.name.clone(),
-                        data: info_key.data.clone(),
-                    },
-                ));
+    /// Every tag in use, with the number of facts carrying it
+    pub fn tags(&self) -> BTreeMap<String, usize> {
+        let mut tags = BTreeMap::new();
+        for locked in &self.info {
+            for tag in &locked.read().tags {
+                *tags.entry(tag.clone()).or_insert(0) += 1;
             }
         }
+        tags
+    }
 
-        // Sort by score (descending)
-        scored_results.sort_by(|a, b| b.0.cmp(&a.0));
-        scored_results.into_iter().map(|(_, info)| info).collect()
+    /// Apply `f` to the fact with the given id under its write lock, bump its
+    /// `updated` timestamp, persist it, and return the new state
+    pub fn update(
+        &mut self,
+        id: Uuid,
+        f: impl FnOnce(&mut Information),
+    ) -> Result<Information, WikiError> {
+        self.update_with(id, None, f)
     }
 
-    /// Get all facts with a specific tag
-    pub fn recall_by_tag(&self, tag: &str) -> Vec<Information> {
-        let mut results = Vec::new();
+    /// Like [`Wiki::update`], but fails with [`WikiError::Conflict`] if the fact's
+    /// `updated` timestamp no longer matches `expected_updated`, i.e. it was
+    /// modified since the caller read it. The copy in storage is checked too,
+    /// so a save by another process holding the wiki open counts.
+    pub fn update_if(
+        &mut self,
+        id: Uuid,
+        expected_updated: Option<DateTime<Utc>>,
+        f: impl FnOnce(&mut Information),
+    ) -> Result<Information, WikiError> {
+        self.update_with(id, Some(expected_updated), f)
+    }
+
+    fn update_with(
+        &mut self,
+        id: Uuid,
+        expected_updated: Option<Option<DateTime<Utc>>>,
+        f: impl FnOnce(&mut Information),
+    ) -> Result<Information, WikiError> {
+        let (before, after) = self.apply(id, expected_updated, f)?;
+        self.subscribers.emit(WikiEvent::Updated {
+            before,
+            after: after.clone(),
+        });
+        Ok(after)
+    }
 
-        for locked_info in &self.info {
-            let info_key = locked_info.read();
+    /// Mutate and persist a fact, returning its state before and after
+    fn apply(
+        &mut self,
+        id: Uuid,
+        expected_updated: Option<Option<DateTime<Utc>>>,
+        f: impl FnOnce(&mut Information),
+    ) -> Result<(Information, Information), WikiError> {
+        self.check_writable()?;
+        // Writing back a partially loaded fact would truncate its data
+        self.hydrate(id)?;
+        let locked = self
+            .info
+            .iter()
+            .find(|l| l.read().id == id)
+            .ok_or(WikiError::NotFound(id))?;
 
-            if info_key.tags.contains(&tag.to_string()) {
-                results.push(Information {
-                    id: info_key.id,
-                    tags: info_key.tags.clone(),
-                    name: info_key.name.clone(),
-                    data: info_key.data.clone(),
-                });
+        // `&mut self` rules out other writers in this process, so reading the
+        // current state without holding the write key is race-free
+        let before = (*locked.read()).clone();
+        let conflict = |found: Option<DateTime<Utc>>, expected| {
+            debug!(%id, ?expected, ?found, "refused to save over a newer change");
+            WikiError::Conflict { id, expected, found }
+        };
+        if let Some(expected) = expected_updated
+            && before.updated != expected
+        {
+            return Err(conflict(before.updated, expected));
+        }
+
+        let mut after = before.clone();
+        f(&mut after);
+        self.sanitize(&mut after, Some(&before))?;
+        self.check_size(&after, Some(&before))?;
+        after.tags = self.canonical_tags(&after.tags);
+        after.id = id;
+        after.updated = Some(Utc::now());
+
+        // Only swap in the new state once it has been persisted. Another
+        // process may have saved it since it was loaded here, which the
+        // storage checks for as it writes.
+        match expected_updated {
+            Some(expected) => self.storage.write_if(&after, expected)?.map_err(|Stale(found)| conflict(found, expected))?,
+            None => self.storage.write(&after)?,
+        }
+        debug!(%id, tags = ?after.tags, bytes = after.data.len(), "saved fact");
+        *locked.write() = after.clone();
+        self.haystacks.invalidate(id);
+        self.update_index(|index| index.insert(&after));
+
+        // Keep `info` in order if the update changed the sort key
+        if self.config.order.compare(&before, &after).is_ne()
+            && let Some(at) = self.info.iter().position(|l| l.read().id == id)
+        {
+            self.info.remove(at);
+            self.push_sorted(after.clone());
+        }
+        Ok((before, after))
+    }
+
+    /// Read a fact back from storage after it was changed behind the wiki's
+    /// back, e.g. by hand in an editor, picking it up even if it failed to load
+    /// earlier. Fails without touching the wiki if it doesn't parse or now
+    /// holds a different id.
+    pub fn reload(&mut self, id: Uuid) -> Result<Information, WikiError> {
+        self.check_writable()?;
+        if !self.storage.exists(id) {
+            return Err(WikiError::NotFound(id));
+        }
+        let after = self.storage.read(id)?;
+        if after.id != id {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("the file of fact {} now holds id {}", id, after.id),
+            )
+            .into());
+        }
+
+        // A fact that didn't load before, e.g. because an earlier edit broke it
+        let Some(locked) = self.info.iter().find(|l| l.read().id == id) else {
+            self.push_sorted(after.clone());
+            self.update_index(|index| index.insert(&after));
+            self.subscribers.emit(WikiEvent::Created(after.clone()));
+            return Ok(after);
+        };
+
+        // The old data is gone from storage, so a partially loaded fact stays partial in `before`
+        let before = std::mem::replace(&mut *locked.write(), after.clone());
+        self.partial.lock().unwrap().remove(&id);
+        self.haystacks.invalidate(id);
+        self.update_index(|index| index.insert(&after));
+        if self.config.order.compare(&before, &after).is_ne()
+            && let Some(at) = self.info.iter().position(|l| l.read().id == id)
+        {
+            self.info.remove(at);
+            self.push_sorted(after.clone());
+        }
+        self.subscribers.emit(WikiEvent::Updated {
+            before,
+            after: after.clone(),
+        });
+        Ok(after)
+    }
+
+    /// Add `text` as a new paragraph at the end of a fact, along with any of
+    /// `tags` it doesn't have yet
+    pub fn append(&mut self, id: Uuid, text: &str, tags: &[String]) -> Result<Information, WikiError> {
+        self.hydrate(id)?;
+        self.update(id, |info| {
+            if !info.data.is_empty() {
+                info.data.push_str("\n\n");
+            }
+            info.data.push_str(text);
+            for tag in tags {
+                if !info.tags.contains(tag) {
+                    info.tags.push(tag.clone());
+                }
             }
+        })
+    }
+
+    /// Replace the tags of a fact
+    pub fn retag(&mut self, id: Uuid, tags: Vec<String>) -> Result<Information, WikiError> {
+        let (before, after) = self.apply(id, None, |info| info.tags = tags)?;
+        self.subscribers.emit(WikiEvent::Retagged {
+            id,
+            name: after.name.clone(),
+            before: before.tags,
+            after: after.tags.clone(),
+        });
+        Ok(after)
+    }
+
+    /// Delete a fact from the wiki, returning its last state. It goes to the
+    /// trash, to be brought back with [`Wiki::untrash`], unless `hard`, in
+    /// which case it and its snapshots are removed for good.
+    pub fn delete(&mut self, id: Uuid, hard: bool) -> Result<Information, WikiError> {
+        self.check_writable()?;
+        let index = self
+            .info
+            .iter()
+            .position(|l| l.read().id == id)
+            .ok_or(WikiError::NotFound(id))?;
+
+        self.hydrate(id).ok();
+        let info = (*self.info[index].read()).clone();
+        if hard {
+            self.storage.delete(id)?;
+            self.storage.delete_snapshots(id)?;
+        } else {
+            self.storage.trash(id, Utc::now())?;
+        }
+
+        self.info.remove(index);
+        self.partial.lock().unwrap().remove(&id);
+        self.haystacks.invalidate(id);
+        self.update_index(|index| index.remove(id));
+        self.subscribers.emit(WikiEvent::Deleted(info.clone()));
+        Ok(info)
+    }
+
+    /// Move a fact into the wiki `to`, returning it as saved there. It keeps
+    /// its id and timestamps unless `to` already has a fact with that id, in
+    /// which case it gets a new id. The original goes to this wiki's trash,
+    /// snapshots and all, so a move can be undone from there. Facts don't
+    /// leave an encrypted wiki for one that isn't.
+    pub fn move_to(&mut self, id: Uuid, to: &mut Wiki) -> Result<Information, WikiError> {
+        self.check_writable()?;
+        if self.is_encrypted() && !to.is_encrypted() {
+            return Err(WikiError::NotEncrypted(to.name.clone()));
         }
+        let mut info = self.get(id)?;
+        if to.get(id).is_ok() {
+            info.id = Uuid::new_v4();
+        }
+        let moved = to.insert(info)?;
+        self.delete(id, false)?;
+        to.get(moved)
+    }
+
+    /// Register a callback invoked after every successful mutation.
+    ///
+    /// Callbacks run once all fact locks are released, so they may read from
+    /// elsewhere without deadlocking; a panicking callback is reported and skipped.
+    pub fn subscribe(&mut self, callback: impl Fn(&WikiEvent) + Send + Sync + 'static) {
+        self.subscribers.push(Box::new(callback));
+    }
 
-        results
+    /// Subscribe through a channel, e.g. to bridge events into an async runtime
+    pub fn subscribe_channel(&mut self) -> std::sync::mpsc::Receiver<WikiEvent> {
+        let (callback, rx) = Subscribers::channel();
+        self.subscribers.push(callback);
+        rx
     }
 
     /// Generate mdbook static site
     pub fn generate_book(&self) -> std::io::Result<PathBuf> {
+        self.generate_book_with(&BookOptions::default(), &mut NoProgress)
+    }
+
+    /// Like [`Wiki::generate_book`], with `options`, reporting each page
+    /// written to `progress`
+    pub fn generate_book_with(&self, options: &BookOptions, progress: &mut dyn Progress) -> std::io::Result<PathBuf> {
+        let result = self.write_book(options, progress);
+        progress.finish();
+        result
+    }
+
+    fn write_book(&self, options: &BookOptions, progress: &mut dyn Progress) -> std::io::Result<PathBuf> {
+        let staged = self.stage_book(options, progress)?;
+        let all_facts = &staged.facts;
+
+        // Build the book with mdbook
+        let output_dir = self.path.parent().unwrap_or(&self.path).join("book");
+
+        // Ensure output directory parent exists
+        if let Some(parent) = output_dir.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        // Convert to absolute path
+        let abs_output_dir = std::fs::canonicalize(&output_dir).unwrap_or_else(|_| {
+            // If canonicalize fails (directory doesn't exist yet), build it manually
+            std::env::current_dir()
+                .unwrap_or_default()
+                .join(&output_dir)
+        });
+
+        mdbook_build(staged.dir.path(), &abs_output_dir)?;
+        for fact in all_facts {
+            describe_page(&abs_output_dir.join(format!("{}.html", fact.id)), fact)?;
+        }
+        write_permalinks(&abs_output_dir, all_facts, options)?;
+        if options.feed
+            && let Some(base_url) = self.book_url(options)
+        {
+            let xml = feed::atom(&format!("{} Wiki", self.name), base_url, &feed::newest(all_facts, options.feed_limit), Utc::now());
+            std::fs::write(abs_output_dir.join(feed::FEED_FILE), xml)?;
+        }
+
+        Ok(output_dir)
+    }
+
+    /// Where the book is published, as `options` or else the config says
+    pub fn book_url<'a>(&'a self, options: &'a BookOptions) -> Option<&'a str> {
+        options.base_url.as_deref().or(self.config.book_url.as_deref())
+    }
+
+    /// Turn the wiki's book into a PDF with `backend`, an mdbook renderer
+    /// found by [`pdf::detect`], under `book-pdf/` beside the HTML book.
+    /// Without one, the book's introduction and fact pages are joined in
+    /// `SUMMARY.md` order into a single [`pdf::MARKDOWN_FILE`] there, a page
+    /// each, for pandoc to turn into a PDF instead.
+    pub fn generate_pdf(
+        &self,
+        options: &BookOptions,
+        backend: Option<PdfBackend>,
+        progress: &mut dyn Progress,
+    ) -> std::io::Result<PdfOutput> {
+        let result = self.write_pdf(options, backend, progress);
+        progress.finish();
+        result
+    }
+
+    fn write_pdf(&self, options: &BookOptions, backend: Option<PdfBackend>, progress: &mut dyn Progress) -> std::io::Result<PdfOutput> {
+        let staged = self.stage_book(options, progress)?;
+        let output_dir = std::path::absolute(self.path.parent().unwrap_or(&self.path).join("book-pdf"))?;
+        std::fs::create_dir_all(&output_dir)?;
+
+        if let Some(backend) = backend {
+            let toml = pdf::with_backend(&self.book_toml_of(&staged.facts.iter().collect::<Vec<_>>(), options), backend);
+            std::fs::write(staged.dir.path().join("book.toml"), toml)?;
+            mdbook_build(staged.dir.path(), &output_dir)?;
+            return Ok(PdfOutput::Built { backend, path: pdf::find_pdf(&output_dir.join(backend.name())) });
+        }
+
+        let src_dir = staged.dir.path().join("src");
+        let mut pages = vec![std::fs::read_to_string(src_dir.join("intro.md"))?];
+        for id in &staged.chapters {
+            pages.push(std::fs::read_to_string(src_dir.join(format!("{}.md", id)))?);
+        }
+        let path = output_dir.join(pdf::MARKDOWN_FILE);
+        std::fs::write(&path, pdf::concatenate(&pages))?;
+        Ok(PdfOutput::Markdown(path))
+    }
+
+    /// Write the sources of the wiki's book to a staging directory for mdbook
+    fn stage_book(&self, options: &BookOptions, progress: &mut dyn Progress) -> std::io::Result<StagedBook> {
         use std::collections::HashMap;
         use std::io::Write;
 
-        // Create temp directory for mdbook
-        let temp_dir = tempfile::tempdir()?;
+        // Create a scratch directory for mdbook, removed once the build is done
+        let temp_dir = StagingDir::new()?;
         let src_dir = temp_dir.path().join("src");
         std::fs::create_dir_all(&src_dir)?;
 
-        // Create book.toml
-        let book_toml = temp_dir.path().join("book.toml");
-        let mut file = std::fs::File::create(&book_toml)?;
-        writeln!(file, "[book]")?;
-        writeln!(file, "title = \"{} Wiki\"", self.name)?;
-        writeln!(file, "authors = []")?;
-        writeln!(file, "language = \"en\"")?;
-        writeln!(file, "")?;
-        writeln!(file, "[output.html]")?;
-
-        // Collect all facts first
-        let mut all_facts: Vec<Information> = Vec::new();
-        for locked_info in &self.info {
-            let info_key = locked_info.read();
-            all_facts.push(Information {
-                id: info_key.id,
-                tags: info_key.tags.clone(),
-                name: info_key.name.clone(),
-                data: info_key.data.clone(),
-            });
-        }
+        // Every page is written from the same copy, so changes made meanwhile
+        // can't leave some pages old and others new, and don't wait for mdbook
+        self.hydrate_all()?;
+        let all_facts = self.snapshot_facts();
+        let fact_refs: Vec<&Information> = all_facts.iter().collect();
+
+        self.write_book_toml(temp_dir.path(), &fact_refs, options)?;
 
         // Group facts by primary tag (first tag only to avoid duplicates)
         let mut tag_groups: HashMap<String, Vec<&Information>> = HashMap::new();
@@ -230,39 +1870,84 @@ impl Wiki {
                 let primary_tag = &fact.tags[0];
                 tag_groups
                     .entry(primary_tag.clone())
-                    .or_insert_with(Vec::new)
+                    .or_default()
                     .push(fact);
             }
         }
 
-        // Create SUMMARY.md
+        // Create SUMMARY.md, noting the order it puts facts in
+        let mut chapters = Vec::with_capacity(all_facts.len());
         let summary_path = src_dir.join("SUMMARY.md");
         let mut summary = std::fs::File::create(&summary_path)?;
         writeln!(summary, "# Summary")?;
-        writeln!(summary, "")?;
+        writeln!(summary)?;
         writeln!(summary, "[Introduction](./intro.md)")?;
-        writeln!(summary, "")?;
-
-        // Add sections by tag
-        let mut sorted_tags: Vec<_> = tag_groups.keys().collect();
-        sorted_tags.sort();
-
-        for tag in sorted_tags {
-            writeln!(summary, "# {}\n", tag)?;
-            if let Some(facts) = tag_groups.get(tag) {
-                for fact in facts {
-                    let filename = format!("{}.md", fact.id);
-                    writeln!(summary, "- [{}](./{})", fact.name, filename)?;
+        if options.index_page {
+            writeln!(summary, "[Index A–Z](./{})", INDEX_PAGE)?;
+        }
+        if options.stats_page {
+            writeln!(summary, "[Statistics](./{})", STATS_PAGE)?;
+        }
+        writeln!(summary)?;
+
+        // Add a section per top-level tag, with nested tags as nested chapters
+        let counts: BTreeMap<String, usize> = tag_groups.iter().map(|(tag, facts)| (tag.clone(), facts.len())).collect();
+        let mut totals = counts.clone();
+        for tag in counts.keys() {
+            for (i, _) in tag.match_indices(tags::TAG_SEPARATOR) {
+                totals.entry(tag[..i].to_string()).or_insert(0);
+            }
+        }
+
+        for root in tags::build_tree(&counts, &totals) {
+            writeln!(summary, "# {}\n", root.path)?;
+            for (depth, node) in TagNode::walk(std::slice::from_ref(&root)) {
+                let indent = "  ".repeat(depth);
+                if depth > 0 {
+                    // A draft chapter, which mdbook lists without a page
+                    writeln!(summary, "{}- [{}]()", "  ".repeat(depth - 1), node.name)?;
+                }
+                for fact in tag_groups.get(&node.path).into_iter().flatten() {
+                    writeln!(summary, "{}- {}", indent, fact_link(fact, "./"))?;
+                    chapters.push(fact.id);
                 }
             }
-            writeln!(summary, "")?;
+            writeln!(summary)?;
         }
 
         if !untagged.is_empty() {
             writeln!(summary, "# Untagged\n")?;
             for fact in untagged {
-                let filename = format!("{}.md", fact.id);
-                writeln!(summary, "- [{}](./{})", fact.name, filename)?;
+                writeln!(summary, "- {}", fact_link(fact, "./"))?;
+                chapters.push(fact.id);
+            }
+            writeln!(summary)?;
+        }
+
+        // A page per tag for the footers to link to, listing every fact under it
+        let mut tag_pages: HashMap<String, String> = HashMap::new();
+        if options.metadata_footer {
+            let tree = tags::tag_tree_of(&fact_refs);
+            if !tree.is_empty() {
+                std::fs::create_dir_all(src_dir.join("tags"))?;
+                writeln!(summary, "# Tags\n")?;
+            }
+            for (depth, node) in TagNode::walk(&tree) {
+                let mut page = format!("tags/{}.md", tag_slug(&node.path));
+                // Tags differing only in punctuation would share a slug
+                let mut n = 1;
+                while tag_pages.values().any(|p| *p == page) {
+                    n += 1;
+                    page = format!("tags/{}-{}.md", tag_slug(&node.path), n);
+                }
+                writeln!(summary, "{}- [{}](./{})", "  ".repeat(depth), node.name, page)?;
+
+                let mut tag_page = std::fs::File::create(src_dir.join(&page))?;
+                writeln!(tag_page, "# {}\n", node.path)?;
+                for fact in all_facts.iter().filter(|f| f.has_tag(&node.path)) {
+                    writeln!(tag_page, "- {}", fact_link(fact, "../"))?;
+                }
+                tag_pages.insert(node.path.clone(), page);
             }
         }
 
@@ -270,61 +1955,431 @@ impl Wiki {
         let intro_path = src_dir.join("intro.md");
         let mut intro = std::fs::File::create(&intro_path)?;
         writeln!(intro, "# {} Wiki", self.name)?;
-        writeln!(intro, "")?;
+        writeln!(intro)?;
         writeln!(
             intro,
             "This is an automatically generated wiki containing {} facts.",
-            self.info.len()
+            all_facts.len()
         )?;
 
+        if options.index_page {
+            std::fs::write(src_dir.join(INDEX_PAGE), index_page(&fact_refs))?;
+        }
+        if options.stats_page {
+            let stats = self.stats_of(&fact_refs);
+            std::fs::write(src_dir.join(STATS_PAGE), self.stats_page(&stats, &tag_pages, Utc::now()))?;
+        }
+
         // Create individual fact pages
-        for locked_info in &self.info {
-            let info_key = locked_info.read();
-            let fact_path = src_dir.join(format!("{}.md", info_key.id));
-            let mut fact_file = std::fs::File::create(&fact_path)?;
+        progress.start(all_facts.len());
+        for fact in &all_facts {
+            let footer = options.metadata_footer.then_some(&tag_pages);
+            write_fact_page(&src_dir, fact, footer)?;
+            progress.tick(fact.name.lines().next().unwrap_or_default());
+        }
+
+        Ok(StagedBook { dir: temp_dir, chapters, facts: all_facts })
+    }
+
+    /// Render the book page of a single fact into a staging book kept under
+    /// the temp dir, rebuilt each time, and return the HTML file
+    pub fn generate_page(&self, id: Uuid) -> Result<PathBuf, WikiError> {
+        use std::io::Write;
+
+        let info = self.get(id)?;
+        let dir = std::env::temp_dir().join(format!("twk-{}", self.name)).join("book");
+        let src_dir = dir.join("src");
+        if src_dir.exists() {
+            std::fs::remove_dir_all(&src_dir)?;
+        }
+        std::fs::create_dir_all(&src_dir)?;
+        self.write_book_toml(&dir, &[&info], &BookOptions::default())?;
+
+        let mut summary = std::fs::File::create(src_dir.join("SUMMARY.md"))?;
+        writeln!(summary, "# Summary\n")?;
+        writeln!(summary, "- {}", fact_link(&info, "./"))?;
+        // No tag pages to link to in a book of one page
+        write_fact_page(&src_dir, &info, Some(&Default::default()))?;
+
+        let output_dir = dir.join("html");
+        mdbook_build(&dir, &output_dir)?;
+        let page = output_dir.join(format!("{}.html", info.id));
+        describe_page(&page, &info)?;
+        Ok(page)
+    }
 
-            writeln!(fact_file, "# {}\n", info_key.name)?;
-            writeln!(fact_file, "{}\n", info_key.data)?;
+    /// The book's statistics page: the wiki's name, the numbers in `stats`
+    /// as `wk stats` prints them, a table of facts per tag linking to the pages in
+    /// `tag_pages`, and when it was `generated`
+    pub fn stats_page(
+        &self,
+        stats: &Stats,
+        tag_pages: &std::collections::HashMap<String, String>,
+        generated: DateTime<Utc>,
+    ) -> String {
+        use std::fmt::Write;
 
-            if !info_key.tags.is_empty() {
-                writeln!(fact_file, "---\n")?;
-                writeln!(fact_file, "**Tags:** {}\n", info_key.tags.join(", "))?;
+        let mut page = String::from("# Statistics\n\n");
+        writeln!(page, "**Wiki:** {}  ", self.name).ok();
+        writeln!(page, "**Facts:** {}  ", stats.facts).ok();
+        writeln!(page, "**Added:** {} in the last week, {} in the last 30 days  ", stats.added_week, stats.added_month).ok();
+        writeln!(page, "**Tags:** {}\n", stats.tags.len()).ok();
+        if !stats.tags.is_empty() {
+            writeln!(page, "| Tag | Facts |\n| --- | ---: |").ok();
+            for (tag, count) in &stats.tags {
+                let name = link_text(tag).replace('|', "\\|");
+                match tag_pages.get(tag) {
+                    Some(tag_page) => writeln!(page, "| [{}](./{}) | {} |", name, tag_page, count),
+                    None => writeln!(page, "| {} | {} |", name, count),
+                }
+                .ok();
             }
+            page.push('\n');
         }
+        writeln!(page, "*Generated {}*", generated.format("%Y-%m-%d %H:%M UTC")).ok();
+        page
+    }
 
-        // Build the book with mdbook
-        let output_dir = self.path.parent().unwrap_or(&self.path).join("book");
+    /// The `book.toml` of the wiki's book: its title, and search settings
+    /// from `options` that leave out facts with the excluded tag
+    pub fn book_toml(&self, options: &BookOptions) -> String {
+        let keys = self.read_all();
+        self.book_toml_of(&keys.iter().map(|key| &**key).collect::<Vec<_>>(), options)
+    }
 
-        // Ensure output directory parent exists
-        if let Some(parent) = output_dir.parent() {
-            std::fs::create_dir_all(parent)?;
+    fn book_toml_of(&self, facts: &[&Information], options: &BookOptions) -> String {
+        use std::fmt::Write;
+
+        let search = &options.search;
+        let mut toml = String::new();
+        writeln!(toml, "[book]").ok();
+        writeln!(toml, "title = {}", toml::Value::String(format!("{} Wiki", self.name))).ok();
+        writeln!(toml, "authors = []").ok();
+        writeln!(toml, "language = \"en\"").ok();
+        writeln!(toml).ok();
+        writeln!(toml, "[output.html]").ok();
+        writeln!(toml).ok();
+        writeln!(toml, "[output.html.search]").ok();
+        writeln!(toml, "boost-title = {}", search.boost_title).ok();
+        writeln!(toml, "teaser-word-count = {}", search.teaser_word_count).ok();
+
+        let excluded: Vec<Uuid> = facts
+            .iter()
+            .filter(|fact| !search.exclude_tag.is_empty() && self.tagged(fact, &search.exclude_tag))
+            .map(|fact| fact.id)
+            .collect();
+        if !excluded.is_empty() {
+            writeln!(toml).ok();
+            writeln!(toml, "[output.html.search.chapter]").ok();
+            for id in excluded {
+                writeln!(toml, "\"{}.md\" = {{ enable = false }}", id).ok();
+            }
         }
+        toml
+    }
 
-        // Convert to absolute path
-        let abs_output_dir = std::fs::canonicalize(&output_dir).unwrap_or_else(|_| {
-            // If canonicalize fails (directory doesn't exist yet), build it manually
-            std::env::current_dir()
-                .unwrap_or_default()
-                .join(&output_dir)
+    fn write_book_toml(&self, dir: &std::path::Path, facts: &[&Information], options: &BookOptions) -> std::io::Result<()> {
+        std::fs::write(dir.join("book.toml"), self.book_toml_of(facts, options))
+    }
+}
+
+/// A fact's data summed up for search results and link previews: its first
+/// sentence, skipping headings and code blocks, cut to at most
+/// [`DESCRIPTION_LEN`] characters. Empty if there's no prose at all.
+pub fn page_description(data: &str) -> String {
+    let text = first_paragraph(data);
+
+    // A sentence ends at a stop followed by a space, so `v1.2` doesn't end one
+    let end = text
+        .char_indices()
+        .find(|&(i, c)| matches!(c, '.' | '!' | '?') && text[i + 1..].starts_with(' '))
+        .map_or(text.len(), |(i, _)| i + 1);
+    cut_at_word(&text[..end], DESCRIPTION_LEN)
+}
+
+/// The first paragraph of prose in a fact's data, skipping headings and code
+/// blocks, with its whitespace collapsed onto one line
+pub fn first_paragraph(data: &str) -> String {
+    let mut in_code = false;
+    let mut paragraph: Vec<&str> = Vec::new();
+    for line in data.lines().map(str::trim) {
+        if line.starts_with("```") || line.starts_with("~~~") {
+            in_code = !in_code;
+            continue;
+        }
+        if in_code || line.starts_with('#') {
+            continue;
+        }
+        if line.is_empty() {
+            if paragraph.is_empty() {
+                continue;
+            }
+            break;
+        }
+        paragraph.push(line);
+    }
+    paragraph.join(" ").split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// `text` if it's at most `max` characters, else cut at the last whole word
+/// that leaves room for an ellipsis
+pub(crate) fn cut_at_word(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let cut: String = text.chars().take(max - 1).collect();
+    let cut = match cut.rfind(' ') {
+        Some(space) if space > 0 => &cut[..space],
+        _ => cut.as_str(),
+    };
+    format!("{}…", cut.trim_end_matches([',', ';', ':', ' ']))
+}
+
+/// Give the built page of `fact` at `page` its own meta description, in
+/// place of the book's, falling back to its name if its data has no prose.
+/// A missing page, e.g. from a theme that renders elsewhere, is skipped.
+fn describe_page(page: &std::path::Path, fact: &Information) -> std::io::Result<()> {
+    let html = match std::fs::read_to_string(page) {
+        Ok(html) => html,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let description = match page_description(&fact.data) {
+        d if d.is_empty() => fact.name.lines().next().unwrap_or_default().to_string(),
+        d => d,
+    };
+    let escaped = description.replace('&', "&amp;").replace('"', "&quot;").replace('<', "&lt;").replace('>', "&gt;");
+    let meta = format!("<meta name=\"description\" content=\"{}\">", escaped);
+
+    let existing = html.find("<meta name=\"description\"").and_then(|start| {
+        let end = start + html[start..].find('>')? + 1;
+        Some(start..end)
+    });
+    let html = match (existing, html.find("<head>")) {
+        (Some(range), _) => format!("{}{}{}", &html[..range.start], meta, &html[range.end..]),
+        (None, Some(head)) => format!("{}\n        {}{}", &html[..head + 6], meta, &html[head + 6..]),
+        (None, None) => return Ok(()),
+    };
+    std::fs::write(page, html)
+}
+
+/// Run `mdbook build` on the book in `book_dir`, writing it to `output_dir`.
+/// Its output is logged rather than shown, and what it said on stderr is
+/// the error if it fails.
+fn mdbook_build(book_dir: &std::path::Path, output_dir: &std::path::Path) -> std::io::Result<()> {
+    let output = std::process::Command::new("mdbook")
+        .arg("build")
+        .arg(book_dir)
+        .arg("-d")
+        .arg(output_dir)
+        .output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        warn!(status = %output.status, "mdbook build failed:\n{}", stderr.trim_end());
+        return Err(match stderr.lines().rev().find(|l| !l.trim().is_empty()) {
+            Some(last) => std::io::Error::other(format!("mdbook build failed: {}", last.trim())),
+            None => std::io::Error::other("mdbook build failed"),
         });
+    }
+    debug!(output = %output_dir.display(), "mdbook built the book:\n{}", stderr.trim_end());
+    Ok(())
+}
 
-        let status = std::process::Command::new("mdbook")
-            .arg("build")
-            .arg(temp_dir.path())
-            .arg("-d")
-            .arg(&abs_output_dir)
-            .status()?;
+/// Write a fact's page to `src_dir`, ending in a footer of its metadata if
+/// `footer` holds the pages of the book's tags to link to
+fn write_fact_page(
+    src_dir: &std::path::Path,
+    info: &Information,
+    footer: Option<&std::collections::HashMap<String, String>>,
+) -> std::io::Result<()> {
+    use std::io::Write;
 
-        if !status.success() {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "mdbook build failed",
-            ));
+    let mut fact_file = std::fs::File::create(src_dir.join(format!("{}.md", info.id)))?;
+    writeln!(fact_file, "# {}\n", info.name)?;
+    writeln!(fact_file, "{}\n", info.data)?;
+
+    let Some(tag_pages) = footer else {
+        return Ok(());
+    };
+    // Blank lines around the div so mdbook renders the Markdown inside it;
+    // the id is the anchor that permalinks point at
+    writeln!(fact_file, "---\n")?;
+    writeln!(fact_file, "<div class=\"fact-meta\" id=\"{}\">\n", info.id)?;
+    if !info.tags.is_empty() {
+        let tags: Vec<String> = info
+            .tags
+            .iter()
+            .map(|tag| match tag_pages.get(tag) {
+                Some(page) => format!("[{}](./{})", tag, page),
+                None => tag.clone(),
+            })
+            .collect();
+        writeln!(fact_file, "**Tags:** {}\n", tags.join(", "))?;
+    }
+    let date = |time: Option<DateTime<Utc>>| time.map(|t| t.format("%Y-%m-%d %H:%M UTC").to_string());
+    match (date(info.created), date(info.updated)) {
+        (Some(created), Some(updated)) if created != updated => {
+            writeln!(fact_file, "**Created:** {} · **Updated:** {}\n", created, updated)?
+        }
+        (Some(created), _) => writeln!(fact_file, "**Created:** {}\n", created)?,
+        (None, Some(updated)) => writeln!(fact_file, "**Updated:** {}\n", updated)?,
+        (None, None) => {}
+    }
+    if let Some(source) = &info.source {
+        writeln!(fact_file, "**Source:** {}\n", source_link(source))?;
+    }
+    writeln!(fact_file, "**Id:** [`{0}`](#{0})\n", info.id)?;
+    writeln!(fact_file, "</div>")?;
+    Ok(())
+}
+
+/// A Markdown link to the page of `fact` from a page `prefix` away from the
+/// facts, like `./` or `../`, named by the first line of its name
+fn fact_link(fact: &Information, prefix: &str) -> String {
+    format!("[{}]({}{}.md)", link_text(fact.name.lines().next().unwrap_or_default()), prefix, fact.id)
+}
+
+/// `text` with the characters that would end or break a link's text escaped
+fn link_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '[' | ']') {
+            out.push('\\');
         }
+        out.push(c);
+    }
+    out
+}
 
-        // Keep temp_dir alive until here
-        drop(temp_dir);
+/// The book's A–Z index of `facts`, linking each by name under a heading
+/// for its first letter, uppercased. Names are sorted by their lowercase
+/// text with no regard for locale; those starting with a digit or symbol go
+/// under `#`, before the letters.
+pub fn index_page(facts: &[&Information]) -> String {
+    let mut groups: BTreeMap<String, Vec<(String, &Information)>> = BTreeMap::new();
+    for &fact in facts {
+        let name = fact.name.lines().next().unwrap_or_default().trim();
+        let group = match name.chars().next() {
+            Some(first) if first.is_alphabetic() => first.to_uppercase().collect(),
+            _ => "#".to_string(),
+        };
+        groups.entry(group).or_default().push((name.to_lowercase(), fact));
+    }
 
-        Ok(output_dir)
+    let mut page = String::from("# Index\n");
+    for (group, mut entries) in groups {
+        entries.sort_by(|(a, fa), (b, fb)| a.cmp(b).then_with(|| fa.name.cmp(&fb.name)).then(fa.id.cmp(&fb.id)));
+        // A lone `#` after the heading's own would be read as closing it
+        let heading = if group == "#" { "\\#".to_string() } else { group };
+        page.push_str(&format!("\n## {}\n\n", heading));
+        for (_, fact) in entries {
+            page.push_str(&format!("- {}\n", fact_link(fact, "./")));
+        }
+    }
+    page
+}
+
+/// A fact's source as Markdown, linked if it is a URL
+fn source_link(source: &str) -> String {
+    let is_url = source.contains("://") && !source.contains(char::is_whitespace);
+    if is_url {
+        format!("<{}>", source)
+    } else {
+        source.to_string()
+    }
+}
+
+/// A tag as a file name: lowercase letters and digits, anything else a dash
+fn tag_slug(tag: &str) -> String {
+    let slug: String = tag
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+        .collect();
+    slug.split('-').filter(|part| !part.is_empty()).collect::<Vec<_>>().join("-")
+}
+
+/// Write `permalinks.json` to the built book at `dir`: each fact's page and,
+/// with metadata footers, the anchor on it, by id, for tools linking into
+/// the book
+fn write_permalinks(dir: &std::path::Path, facts: &[Information], options: &BookOptions) -> std::io::Result<()> {
+    #[derive(Serialize)]
+    struct Permalink<'a> {
+        name: &'a str,
+        page: String,
+        url: String,
+    }
+
+    let links: BTreeMap<Uuid, Permalink> = facts
+        .iter()
+        .map(|fact| {
+            let page = format!("{}.html", fact.id);
+            let url = if options.metadata_footer { format!("{}#{}", page, fact.id) } else { page.clone() };
+            let name = fact.name.lines().next().unwrap_or_default();
+            (fact.id, Permalink { name, page, url })
+        })
+        .collect();
+    let json = serde_json::to_string_pretty(&links).map_err(std::io::Error::other)?;
+    std::fs::write(dir.join("permalinks.json"), json)
+}
+
+impl Drop for Wiki {
+    fn drop(&mut self) {
+        // Keep edits made since the index was loaded; if this fails the
+        // index is brought up to date on the next load instead
+        let path = self.index_path().filter(|_| !self.readonly);
+        if let Some(index) = self.index.get_mut().unwrap_or_else(|e| e.into_inner()).as_mut()
+            && let Some(path) = path
+        {
+            index.save(&path).ok();
+        }
+    }
+}
+
+/// Scratch directory under the system temp dir, removed on drop
+struct StagingDir(PathBuf);
+
+/// A book's sources, staged for mdbook by [`Wiki::stage_book`]
+struct StagedBook {
+    dir: StagingDir,
+    /// Ids of the facts with pages, in the order `SUMMARY.md` lists them
+    chapters: Vec<Uuid>,
+    facts: Vec<Information>,
+}
+
+impl StagingDir {
+    fn new() -> std::io::Result<Self> {
+        let path = std::env::temp_dir().join(format!("twk-book-{}", Uuid::new_v4()));
+        create_dir_all(&path)?;
+        Ok(StagingDir(path))
+    }
+
+    fn path(&self) -> &std::path::Path {
+        &self.0
+    }
+}
+
+impl Drop for StagingDir {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.0).ok();
+    }
+}
+
+/// Whether a wiki must be opened read-only: because its config or
+/// [`crate::set_readonly`] says so, or because its directory can't be
+/// written to, found out now rather than on the first save
+fn is_readonly(path: &std::path::Path, config: &Config) -> bool {
+    if config.readonly || crate::readonly_override() {
+        return true;
+    }
+    if !path.is_dir() {
+        return false;
+    }
+    let probe = path.join(format!(".write-probe-{}", Uuid::new_v4()));
+    match std::fs::File::create_new(&probe) {
+        Ok(_) => {
+            std::fs::remove_file(&probe).ok();
+            false
+        }
+        Err(_) => true,
     }
 }
@@ -0,0 +1,605 @@
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::config::{Config, global_config_path};
+use crate::encryption::{self, ENCRYPTION_FILE, Setup};
+use crate::error::WikiError;
+use crate::helpers::FileLock;
+use crate::index::SEARCH_INDEX_FILE;
+use crate::storage::{Backend, FsStorage, HEADERS_FILE, LoadWarning, Storage};
+use crate::wiki::{Information, RecallOptions, ScoredFact, Wiki};
+
+/// Which root a wiki directory lives under
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    /// A `.wiki/` folder in the current directory or a parent
+    Local,
+    /// The per-user data directory
+    Global,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Local => write!(f, "local"),
+            Location::Global => write!(f, "global"),
+        }
+    }
+}
+
+/// A wiki directory and why it was chosen, from [`Wiki::get_wiki_path`]
+#[derive(Debug, Clone)]
+pub struct ResolvedPath {
+    pub path: PathBuf,
+    pub source: PathSource,
+}
+
+/// Why a wiki resolved to the root it did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathSource {
+    /// A `.wiki/` folder was found in the current directory or a parent
+    LocalRoot,
+    /// `--global` was given
+    GlobalFlag,
+    /// There is no `.wiki/` folder to use
+    NoLocalRoot,
+    /// The project's `.twk` marker names the wiki with `global = true`
+    MarkerGlobal,
+}
+
+impl PathSource {
+    pub fn location(self) -> Location {
+        match self {
+            PathSource::LocalRoot => Location::Local,
+            PathSource::GlobalFlag | PathSource::NoLocalRoot | PathSource::MarkerGlobal => Location::Global,
+        }
+    }
+}
+
+impl fmt::Display for PathSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathSource::LocalRoot => write!(f, "found .wiki/ in this directory or a parent"),
+            PathSource::GlobalFlag => write!(f, "--global given"),
+            PathSource::NoLocalRoot => write!(f, "no .wiki/ found"),
+            PathSource::MarkerGlobal => write!(f, "global = true in {}", MARKER_FILE),
+        }
+    }
+}
+
+/// The name of the wiki to use and where it came from, from [`active_name`]
+#[derive(Debug, Clone)]
+pub struct ResolvedName {
+    pub name: String,
+    pub source: NameSource,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NameSource {
+    /// A `.twk` marker file, at this path, in the current directory or a parent
+    Marker(PathBuf),
+    /// The `TWK_WIKI` environment variable
+    Env,
+    /// The `wiki` key of the global config
+    Config,
+    /// Neither was set
+    Default,
+}
+
+impl fmt::Display for NameSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NameSource::Marker(path) => write!(f, "pinned by {}", path.display()),
+            NameSource::Env => write!(f, "TWK_WIKI"),
+            NameSource::Config => write!(f, "config"),
+            NameSource::Default => write!(f, "default"),
+        }
+    }
+}
+
+/// What `wk status` reports about the active wiki
+#[derive(Debug, Clone)]
+pub struct Status {
+    pub name: String,
+    pub resolved: ResolvedPath,
+    pub facts: usize,
+    /// Latest creation or update time of any fact
+    pub modified: Option<DateTime<Utc>>,
+    pub warnings: Vec<LoadWarning>,
+    pub readonly: bool,
+}
+
+/// A wiki directory found by [`discover`]
+#[derive(Debug, Clone)]
+pub struct WikiListing {
+    pub name: String,
+    pub path: PathBuf,
+    pub location: Location,
+    /// Number of stored facts, `None` if the storage couldn't be opened
+    pub facts: Option<usize>,
+}
+
+/// Name of the file marking a project's wiki, found like `.wiki/` folders
+pub const MARKER_FILE: &str = ".twk";
+
+/// A `.twk` file pinning the wiki used in a project's tree, written by
+/// `wk switch --pin`. It holds TOML like `wiki = "work"`, with `global =
+/// true` to always look for the wiki among global ones, or just the name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Marker {
+    pub wiki: String,
+    pub global: bool,
+}
+
+impl Marker {
+    pub fn read(path: &Path) -> std::io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let invalid = |reason: String| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{}: {}", path.display(), reason))
+        };
+        let marker = match text.parse::<toml::Table>() {
+            Ok(table) => Marker {
+                wiki: match table.get("wiki") {
+                    Some(toml::Value::String(wiki)) => wiki.clone(),
+                    Some(_) => return Err(invalid("`wiki` must be a string".to_string())),
+                    None => return Err(invalid("no `wiki` given".to_string())),
+                },
+                global: match table.get("global") {
+                    Some(toml::Value::Boolean(global)) => *global,
+                    Some(_) => return Err(invalid("`global` must be true or false".to_string())),
+                    None => false,
+                },
+            },
+            // Just a name, without quotes
+            Err(e) => match text.trim() {
+                name if !name.is_empty() && !name.contains(['\n', '=']) => {
+                    Marker { wiki: name.to_string(), global: false }
+                }
+                _ => return Err(invalid(e.message().to_string())),
+            },
+        };
+        validate_name(&marker.wiki).map_err(|e| invalid(e.to_string()))?;
+        Ok(marker)
+    }
+
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        let mut text = format!("wiki = {}\n", toml::Value::String(self.wiki.clone()));
+        if self.global {
+            text.push_str("global = true\n");
+        }
+        std::fs::write(path, text)
+    }
+}
+
+/// The nearest `.twk` marker in the current directory or its parents, and
+/// where it is. One that can't be read is skipped with a warning, along with
+/// any further up.
+pub fn project_marker() -> Option<(PathBuf, Marker)> {
+    let path = find_marker(&std::env::current_dir().ok()?, dirs::home_dir().as_deref())?;
+    match Marker::read(&path) {
+        Ok(marker) => Some((path, marker)),
+        Err(e) => {
+            tracing::warn!("ignoring the wiki marker {}", e);
+            None
+        }
+    }
+}
+
+/// Look for a `.twk` file in `start` and its ancestors, stopping at the
+/// first found and not going above `home`, like [`find_local_root`]
+pub fn find_marker(start: &Path, home: Option<&Path>) -> Option<PathBuf> {
+    for dir in start.ancestors() {
+        let marker = dir.join(MARKER_FILE);
+        if marker.is_file() {
+            return Some(marker);
+        }
+        if Some(dir) == home {
+            break;
+        }
+    }
+    None
+}
+
+/// Name of the wiki to use: a project's `.twk` marker, then `TWK_WIKI`, then
+/// the global config's `wiki`, then `default`
+pub fn active_name() -> ResolvedName {
+    if let Some((path, marker)) = project_marker() {
+        return ResolvedName {
+            name: marker.wiki,
+            source: NameSource::Marker(path),
+        };
+    }
+    if let Ok(name) = std::env::var("TWK_WIKI") {
+        return ResolvedName {
+            name,
+            source: NameSource::Env,
+        };
+    }
+    // A broken global config is reported once the wiki itself loads it
+    match Config::load_global().ok().and_then(|config| config.wiki) {
+        Some(name) => ResolvedName {
+            name,
+            source: NameSource::Config,
+        },
+        None => ResolvedName {
+            name: "default".to_string(),
+            source: NameSource::Default,
+        },
+    }
+}
+
+/// Root of global wikis: `--data-dir`, then `TWK_DATA_DIR`, then the global
+/// config's `data_dir`, then `<data dir>/twk`.
+///
+/// Everything that needs the global root goes through here.
+pub fn global_root() -> PathBuf {
+    let home = dirs::home_dir();
+    let cwd = std::env::current_dir().unwrap_or_default();
+
+    let explicit = crate::data_dir_override().or_else(|| {
+        std::env::var_os("TWK_DATA_DIR")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+    });
+    if let Some(dir) = explicit {
+        return expand_path(&dir, home.as_deref(), &cwd);
+    }
+
+    // A broken global config is reported once the wiki itself loads it
+    if let Some(dir) = Config::load_global().ok().and_then(|config| config.data_dir) {
+        // Relative to the config file, not wherever wk happens to run
+        let base = global_config_path()
+            .as_deref()
+            .and_then(Path::parent)
+            .map_or(cwd, Path::to_path_buf);
+        return expand_path(&dir, home.as_deref(), &base);
+    }
+
+    dirs::data_local_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("twk")
+}
+
+/// Expand a leading `~` to `home` and resolve relative paths against `base`
+pub fn expand_path(path: &Path, home: Option<&Path>, base: &Path) -> PathBuf {
+    if let (Ok(rest), Some(home)) = (path.strip_prefix("~"), home) {
+        return home.join(rest);
+    }
+    base.join(path)
+}
+
+/// Root of local wikis: the nearest `.wiki/` folder in the current
+/// directory or one of its parents, like git looks for `.git`
+pub fn local_root() -> Option<PathBuf> {
+    find_local_root(&std::env::current_dir().ok()?, dirs::home_dir().as_deref())
+}
+
+/// Look for `.wiki/` in `start` and its ancestors, not going above `home`
+pub fn find_local_root(start: &Path, home: Option<&Path>) -> Option<PathBuf> {
+    for dir in start.ancestors() {
+        let root = dir.join(".wiki");
+        if root.is_dir() {
+            return Some(root);
+        }
+        if Some(dir) == home {
+            break;
+        }
+    }
+    None
+}
+
+/// Every wiki under the local root (unless `use_global`) and the global root
+pub fn discover(use_global: bool) -> std::io::Result<Vec<WikiListing>> {
+    let mut roots = Vec::new();
+    if !use_global && let Some(root) = local_root() {
+        roots.push((Location::Local, root));
+    }
+    roots.push((Location::Global, global_root()));
+
+    let mut listings = Vec::new();
+    for (location, root) in roots {
+        let entries = match std::fs::read_dir(&root) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+
+        let mut found: Vec<WikiListing> = entries
+            .flatten()
+            .filter(|entry| entry.path().is_dir())
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                // Hidden folders are bookkeeping, not wikis
+                if name.starts_with('.') {
+                    return None;
+                }
+                let path = entry.path();
+                // Counting files needs no passphrase
+                let facts = if encryption::is_encrypted(&path) {
+                    FsStorage::new(&path).count().ok()
+                } else {
+                    Backend::detect(&path)
+                        .open(&path)
+                        .and_then(|storage| storage.count())
+                        .ok()
+                };
+                Some(WikiListing {
+                    name,
+                    path,
+                    location,
+                    facts,
+                })
+            })
+            .collect();
+        found.sort_by(|a, b| a.name.cmp(&b.name));
+        listings.extend(found);
+    }
+    Ok(listings)
+}
+
+/// Whether the wiki `name` exists in the root it resolves to
+pub fn exists(name: &str, use_global: bool) -> bool {
+    existing_wiki_path(name, use_global).is_ok()
+}
+
+/// Open the wiki `name`, which unlike [`Wiki::load_or_create`] must exist
+pub fn open_existing(name: &str, use_global: bool) -> Result<Wiki, WikiError> {
+    existing_wiki_path(name, use_global)?;
+    Wiki::load_or_create(name.to_string(), use_global)
+}
+
+/// The best `limit` facts fuzzy-matching `query` in every wiki [`discover`]
+/// finds, best first, each with the wiki it was found in. A fact held by
+/// several wikis, as when each imported the same archive, is listed once
+/// from the wiki where it scored best; see [`merge_duplicates`]. Encrypted
+/// wikis are left out rather than asking for each one's passphrase.
+pub fn recall_everywhere(
+    query: &str,
+    limit: Option<usize>,
+    opts: RecallOptions<'_>,
+    use_global: bool,
+) -> Result<Vec<ScoredFact>, WikiError> {
+    let mut hits = Vec::new();
+    for listing in discover(use_global)? {
+        if encryption::is_encrypted(&listing.path) {
+            continue;
+        }
+        let wiki = open_existing(&listing.name, listing.location == Location::Global)?;
+        hits.extend(wiki.recall_detailed(query, limit, opts).into_iter().map(|hit| ScoredFact {
+            wiki: Some(listing.name.clone()),
+            ..hit.into()
+        }));
+    }
+    let mut hits = merge_duplicates(hits);
+    hits.truncate(limit.unwrap_or(usize::MAX));
+    Ok(hits)
+}
+
+/// `hits` best first, keeping one of each fact by its name and data: the
+/// best scoring, with the wikis of the others in [`ScoredFact::also_in`]
+pub fn merge_duplicates(mut hits: Vec<ScoredFact>) -> Vec<ScoredFact> {
+    hits.sort_by_key(|hit| std::cmp::Reverse(hit.score));
+    let mut kept: Vec<ScoredFact> = Vec::with_capacity(hits.len());
+    let mut by_content: HashMap<u64, usize> = HashMap::new();
+    for hit in hits {
+        let mut hasher = DefaultHasher::new();
+        (&hit.info.name, &hit.info.data).hash(&mut hasher);
+        match by_content.get(&hasher.finish()) {
+            Some(&at) => kept[at].also_in.extend(hit.wiki),
+            None => {
+                by_content.insert(hasher.finish(), kept.len());
+                kept.push(hit);
+            }
+        }
+    }
+    kept
+}
+
+/// Existing wikis, in the root `name` resolves to, whose names are a typo or
+/// two away from it, closest first
+pub fn near_misses(name: &str, use_global: bool) -> std::io::Result<Vec<String>> {
+    let location = Wiki::get_wiki_path(name, use_global).source.location();
+    let name = name.to_lowercase();
+    let mut close: Vec<(usize, String)> = discover(use_global)?
+        .into_iter()
+        .filter(|listing| listing.location == location)
+        .filter_map(|listing| {
+            let distance = edit_distance(&name, &listing.name.to_lowercase());
+            // Up to a third of the longer name may differ
+            let longest = name.chars().count().max(listing.name.chars().count());
+            (distance > 0 && distance * 3 <= longest.max(3)).then_some((distance, listing.name))
+        })
+        .collect();
+    close.sort();
+    Ok(close.into_iter().map(|(_, name)| name).collect())
+}
+
+/// Edits needed to turn `a` into `b`: inserting, deleting or replacing a
+/// character, or swapping two adjacent ones
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // Rows i-2, i-1 and i of the distance table
+    let mut before: Vec<usize> = vec![0; b.len() + 1];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for i in 1..=a.len() {
+        current[0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            current[j] = (previous[j] + 1).min(current[j - 1] + 1).min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut before, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// Rename the wiki `old` to `new` within the root it resolves to, returning
+/// its new path
+pub fn rename(old: &str, new: &str, use_global: bool) -> Result<PathBuf, WikiError> {
+    validate_name(new)?;
+    let from = existing_wiki_path(old, use_global)?;
+    let to = from.with_file_name(new);
+    if to.exists() {
+        return Err(WikiError::WikiExists(new.to_string()));
+    }
+
+    let _locks = lock_idle(&from)?;
+    std::fs::rename(&from, &to)?;
+    Ok(to)
+}
+
+/// Delete the wiki `name` and everything in it, returning the removed path
+pub fn remove(name: &str, use_global: bool) -> Result<PathBuf, WikiError> {
+    let path = existing_wiki_path(name, use_global)?;
+    // Only checked up front; open lock files can't be deleted on Windows
+    drop(lock_idle(&path)?);
+    std::fs::remove_dir_all(&path)?;
+    Ok(path)
+}
+
+/// Encrypt every fact of the wiki `name` with a key derived from
+/// `passphrase`, returning the number of facts rewritten.
+///
+/// Running it again on an encrypted wiki, with the same passphrase, finishes
+/// an interrupted run. Nothing is written unless every fact could be read.
+pub fn encrypt(name: &str, passphrase: &str, use_global: bool) -> Result<usize, WikiError> {
+    let path = existing_wiki_path(name, use_global)?;
+    if Backend::detect(&path) != Backend::Files {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "only file-backed wikis can be encrypted",
+        )
+        .into());
+    }
+    let _lock = lock_wiki(&path)?;
+
+    let (cipher, setup) = if encryption::is_encrypted(&path) {
+        (Arc::new(encryption::unlock_with(&path, passphrase)?), None)
+    } else {
+        let setup = Setup::new(passphrase)?;
+        (setup.cipher.clone(), Some(setup))
+    };
+    let storage = FsStorage::resealing(&path, cipher);
+    let facts = read_every_fact(&storage)?;
+
+    if let Some(setup) = setup {
+        setup.write_marker(&path)?;
+    }
+    for info in &facts {
+        storage.write(info)?;
+        // Plaintext snapshots are read as they are and sealed on the way back
+        for snapshot in storage.snapshots(info.id)? {
+            storage.write_snapshot(&snapshot)?;
+        }
+    }
+    storage.reseal_trash(&storage)?;
+    // Both caches hold fact contents in plaintext
+    remove_if_exists(&path.join(HEADERS_FILE))?;
+    remove_if_exists(&path.join(SEARCH_INDEX_FILE))?;
+    Ok(facts.len())
+}
+
+/// Rewrite every fact of the encrypted wiki `name` in plaintext and drop its
+/// encryption marker, returning the number of facts rewritten
+pub fn decrypt(name: &str, passphrase: &str, use_global: bool) -> Result<usize, WikiError> {
+    let path = existing_wiki_path(name, use_global)?;
+    if !encryption::is_encrypted(&path) {
+        return Err(WikiError::NotEncrypted(name.to_string()));
+    }
+    let _lock = lock_wiki(&path)?;
+
+    let cipher = encryption::unlock_with(&path, passphrase)?;
+    let sealed = FsStorage::resealing(&path, Arc::new(cipher));
+    let facts = read_every_fact(&sealed)?;
+
+    let storage = FsStorage::new(&path);
+    for info in &facts {
+        storage.write(info)?;
+        for snapshot in sealed.snapshots(info.id)? {
+            storage.write_snapshot(&snapshot)?;
+        }
+    }
+    storage.reseal_trash(&sealed)?;
+    // Last, so an interrupted run can be finished by running it again
+    std::fs::remove_file(path.join(ENCRYPTION_FILE))?;
+    encryption::forget(&path);
+    Ok(facts.len())
+}
+
+/// Load every fact, failing if any of them can't be read
+fn read_every_fact(storage: &FsStorage) -> Result<Vec<Information>, WikiError> {
+    let loaded = storage.load_all()?;
+    if let Some(warning) = loaded.warnings.first() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "{} entries could not be read (first: {}: {}); run `wk doctor` first",
+                loaded.warnings.len(),
+                warning.path.display(),
+                warning.error
+            ),
+        )
+        .into());
+    }
+    Ok(loaded.facts)
+}
+
+fn remove_if_exists(path: &Path) -> std::io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        r => r,
+    }
+}
+
+fn existing_wiki_path(name: &str, use_global: bool) -> Result<PathBuf, WikiError> {
+    validate_name(name)?;
+    let path = Wiki::get_wiki_path(name, use_global).path;
+    if path.is_dir() {
+        Ok(path)
+    } else {
+        Err(WikiError::UnknownWiki(name.to_string()))
+    }
+}
+
+/// Wiki names become directory names, so they must be a single path component
+fn validate_name(name: &str) -> Result<(), WikiError> {
+    let valid = !name.is_empty()
+        && name != "."
+        && name != ".."
+        && !name.starts_with('.')
+        && !name.contains(['/', '\\']);
+    if valid {
+        Ok(())
+    } else {
+        Err(WikiError::InvalidWikiName(name.to_string()))
+    }
+}
+
+/// Check that no other wk process is using the wiki and keep batch
+/// operations out of it, while leaving fact locks free for our own writes
+fn lock_wiki(path: &Path) -> std::io::Result<FileLock> {
+    // The wiki lock comes first; the fact locks are released with the rest
+    Ok(lock_idle(path)?.swap_remove(0))
+}
+
+/// Take the wiki lock and every fact lock in `path` without waiting, failing
+/// if another wk process is using the wiki
+fn lock_idle(path: &Path) -> std::io::Result<Vec<FileLock>> {
+    let mut locks = vec![FileLock::exclusive(&path.join(".lock"), Duration::ZERO)?];
+    if let Ok(entries) = std::fs::read_dir(path.join(".locks")) {
+        for entry in entries.flatten() {
+            locks.push(FileLock::exclusive(&entry.path(), Duration::ZERO)?);
+        }
+    }
+    Ok(locks)
+}
@@ -0,0 +1,129 @@
+use chrono::{DateTime, Days, Local, Months, NaiveDate, NaiveDateTime, TimeDelta, TimeZone, Utc};
+
+use crate::wiki::Information;
+
+/// A span of time facts were last changed in, for `wk r --since/--until`.
+///
+/// A fact counts as changed when it was last updated, or created if it never
+/// was. Facts from before timestamps were recorded count as older than any
+/// window: they are outside one with a start and inside one without.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TimeWindow {
+    /// Facts changed at or after this
+    pub since: Option<DateTime<Utc>>,
+    /// Facts changed before this
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl TimeWindow {
+    /// Every fact, whenever it changed
+    pub const ANY: TimeWindow = TimeWindow { since: None, until: None };
+
+    /// Whether the window leaves any fact out
+    pub fn is_bounded(&self) -> bool {
+        self.since.is_some() || self.until.is_some()
+    }
+
+    pub fn contains(&self, info: &Information) -> bool {
+        match info.updated.or(info.created) {
+            Some(at) => self.since.is_none_or(|since| at >= since) && self.until.is_none_or(|until| at < until),
+            None => self.since.is_none(),
+        }
+    }
+}
+
+/// The start of the time `text` names, relative to `now`. See [`parse`].
+pub fn since(text: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    parse(text, now).map(|(start, _)| start)
+}
+
+/// The end of the time `text` names, relative to `now`, so that `--until` a
+/// date takes in the whole day. See [`parse`].
+pub fn until(text: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    parse(text, now).map(|(_, end)| end)
+}
+
+/// The span of time `text` names, as its start and end, relative to `now`:
+///
+/// - an age such as `30m`, `3d`, `2w` or `1y2mo` (units `s`, `m`, `h`, `d`,
+///   `w`, `mo` and `y`), that long before `now`
+/// - `now`, `today` or `yesterday`
+/// - a date, `2024-01-31`, or a date and time, `2024-01-31T12:00` or
+///   `2024-01-31 12:00`, in local time; RFC 3339 with an offset also works
+///
+/// Days are whole local days; an age or a time is a single instant.
+pub fn parse(text: &str, now: DateTime<Utc>) -> Result<(DateTime<Utc>, DateTime<Utc>), String> {
+    let text = text.trim();
+    let today = now.with_timezone(&Local).date_naive();
+    match text.to_lowercase().as_str() {
+        "now" => return Ok((now, now)),
+        "today" => return day(today),
+        "yesterday" => return day(today - Days::new(1)),
+        _ => {}
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        return day(date);
+    }
+    if let Ok(at) = DateTime::parse_from_rfc3339(text) {
+        let at = at.with_timezone(&Utc);
+        return Ok((at, at));
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"] {
+        if let Ok(at) = NaiveDateTime::parse_from_str(text, format) {
+            let at = local(at)?;
+            return Ok((at, at));
+        }
+    }
+    let at = ago(text, now)?;
+    Ok((at, at))
+}
+
+/// The local day `date`, from its first moment to the next day's
+fn day(date: NaiveDate) -> Result<(DateTime<Utc>, DateTime<Utc>), String> {
+    let next = date + Days::new(1);
+    Ok((local(date.and_time(Default::default()))?, local(next.and_time(Default::default()))?))
+}
+
+fn local(at: NaiveDateTime) -> Result<DateTime<Utc>, String> {
+    // A time skipped by a clock change doesn't exist; one repeated by it is taken the first time round
+    Local
+        .from_local_datetime(&at)
+        .earliest()
+        .map(|at| at.with_timezone(&Utc))
+        .ok_or_else(|| format!("{} doesn't exist in the local time zone", at))
+}
+
+/// `now` less an age like `2w` or `1y2mo`
+fn ago(text: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    let invalid = || format!("'{}' isn't a date or an age like 3d or 2w", text);
+    let mut rest = text;
+    let mut at = now;
+    if rest.is_empty() {
+        return Err(invalid());
+    }
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let count: u32 = rest[..digits].parse().map_err(|_| invalid())?;
+        rest = &rest[digits..];
+        let letters = rest.find(|c: char| c.is_ascii_digit()).unwrap_or(rest.len());
+        let unit = &rest[..letters];
+        rest = &rest[letters..];
+        let earlier = match unit {
+            "mo" => at.checked_sub_months(Months::new(count)),
+            "y" => count.checked_mul(12).and_then(|months| at.checked_sub_months(Months::new(months))),
+            _ => {
+                let unit_seconds = match unit {
+                    "s" => 1,
+                    "m" | "min" => 60,
+                    "h" => 60 * 60,
+                    "d" => 60 * 60 * 24,
+                    "w" => 60 * 60 * 24 * 7,
+                    _ => return Err(invalid()),
+                };
+                TimeDelta::try_seconds(unit_seconds * i64::from(count)).and_then(|delta| at.checked_sub_signed(delta))
+            }
+        };
+        at = earlier.ok_or_else(invalid)?;
+    }
+    Ok(at)
+}
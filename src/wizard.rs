@@ -0,0 +1,82 @@
+//! A few questions on the first run of `wk`, when there are no wikis yet:
+//! where to keep them, what to call the first, and what the global config
+//! should start with. Every question can be skipped with Enter, and an
+//! existing global config is never replaced.
+
+use colored::Colorize;
+use std::path::Path;
+use twk::config::{self, Initial};
+use twk::{set_use_global, switch};
+
+use crate::output::{self, say, warning};
+
+/// Walk through setting up the first wiki, as long as the user wants to;
+/// whether they did, so `wk` can show its usage instead if not
+pub fn run(use_global: bool) -> bool {
+    say!("{}", "Welcome to wk".bright_cyan().bold());
+    say!("{}", "There are no wikis yet. Press Enter to skip any question.".bright_black());
+    if !output::confirm(format_args!("Set one up now?"), true) {
+        return false;
+    }
+
+    let global = use_global || ask_global();
+    set_use_global(global);
+    if !global {
+        if let Err(e) = std::fs::create_dir_all(".wiki") {
+            output::fail(format!("Failed to create .wiki folder: {}", e))
+        }
+        let root = Path::new(".wiki");
+        say!("  {} {}", "Path:".cyan(), root.canonicalize().as_deref().unwrap_or(root).display().to_string().white());
+    }
+
+    let name = create_wiki();
+    let tags = output::prompt(format_args!("Tags to add to every fact (Enter for none):"), None);
+    let editor = output::prompt(format_args!("Editor for facts:"), Some(&twk::editor::editor()));
+
+    let initial = Initial {
+        wiki: Some(name.clone()),
+        default_tags: tags.split([' ', ',']).filter(|tag| !tag.is_empty()).map(str::to_string).collect(),
+        editor: Some(editor).filter(|editor| !editor.is_empty()),
+    };
+    match config::global_config_path() {
+        Some(path) => match config::write_initial(&path, &initial) {
+            Ok(true) => say!("  {} {}", "Config:".cyan(), path.display().to_string().white()),
+            Ok(false) => warning!("{} already exists, so it was left as it is", path.display()),
+            Err(e) => warning!("couldn't write {}: {}", path.display(), e),
+        },
+        None => warning!("there's no config directory to write a global config to"),
+    }
+
+    say!();
+    say!("{}", "✓ Ready".green().bold());
+    say!("{}", "To use this wiki from other shells, set the environment variable:".bright_black());
+    say!("  {}", format!("export TWK_WIKI={}", name).yellow());
+    true
+}
+
+/// Whether wikis are kept for the user rather than in a `.wiki/` folder here
+fn ask_global() -> bool {
+    loop {
+        let answer = output::prompt(format_args!("Keep wikis for your user (global) or in this folder (local)?"), Some("global"));
+        match answer.to_lowercase().as_str() {
+            "g" | "global" => return true,
+            "l" | "local" => return false,
+            _ => warning!("answer global or local"),
+        }
+    }
+}
+
+/// Ask for the first wiki's name until one can be created; its name
+fn create_wiki() -> String {
+    loop {
+        let name = output::prompt(format_args!("Name of the first wiki:"), Some("default"));
+        match switch(name.clone()) {
+            Ok(()) => {
+                say!("{}", "✓ Created wiki".green().bold());
+                say!("  {} {}", "Wiki:".cyan(), name.white());
+                return name;
+            }
+            Err(e) => warning!("{}", e),
+        }
+    }
+}
@@ -0,0 +1,176 @@
+//! What goes into a wiki's book before mdbook builds it
+
+use chrono::{TimeZone, Utc};
+use std::collections::HashMap;
+use twk::fixture::FixtureWiki;
+use twk::pdf::{self, PAGE_BREAK, PdfBackend, PdfOutput};
+use twk::progress::NoProgress;
+use twk::wiki::{DESCRIPTION_LEN, index_page, page_description};
+use twk::{BookOptions, BookSearch, Information};
+
+#[test]
+fn book_toml_tunes_search() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let mut wiki = fixture.open().unwrap();
+    let dump = wiki.commit("fn main() {}".to_string(), vec!["nosearch".to_string()]).unwrap();
+    let nested = wiki.commit("a long log".to_string(), vec!["nosearch/logs".to_string()]).unwrap();
+    let kept = wiki.commit("a short note".to_string(), vec!["notes".to_string()]).unwrap();
+
+    let book: toml::Table = wiki.book_toml(&BookOptions::default()).parse().unwrap();
+    assert_eq!(book["book"]["title"].as_str(), Some("fixture Wiki"));
+    let search = &book["output"]["html"]["search"];
+    assert_eq!(search["boost-title"].as_integer(), Some(4));
+    assert_eq!(search["teaser-word-count"].as_integer(), Some(20));
+    let chapters = search["chapter"].as_table().unwrap();
+    assert_eq!(chapters.len(), 2);
+    for id in [dump, nested] {
+        assert_eq!(chapters[&format!("{}.md", id)]["enable"].as_bool(), Some(false));
+    }
+    assert!(!chapters.contains_key(&format!("{}.md", kept)));
+
+    let options = BookOptions {
+        search: BookSearch { boost_title: 9, teaser_word_count: 5, exclude_tag: "notes".to_string() },
+        ..Default::default()
+    };
+    let book: toml::Table = wiki.book_toml(&options).parse().unwrap();
+    let search = &book["output"]["html"]["search"];
+    assert_eq!(search["boost-title"].as_integer(), Some(9));
+    assert_eq!(search["teaser-word-count"].as_integer(), Some(5));
+    assert_eq!(search["chapter"].as_table().unwrap().keys().collect::<Vec<_>>(), [&format!("{}.md", kept)]);
+
+    // Nothing to leave out, no chapter table
+    let options = BookOptions { search: BookSearch { exclude_tag: String::new(), ..Default::default() }, ..Default::default() };
+    let book: toml::Table = wiki.book_toml(&options).parse().unwrap();
+    assert!(!book["output"]["html"]["search"].as_table().unwrap().contains_key("chapter"));
+}
+
+#[test]
+fn descriptions_are_first_sentences() {
+    assert_eq!(page_description("Rust is fast. It is also safe."), "Rust is fast.");
+    assert_eq!(page_description("Really? Yes."), "Really?");
+    assert_eq!(page_description("no stop at all"), "no stop at all");
+    // Within a line a stop needs a space after it
+    assert_eq!(page_description("Bump to v1.2 first. Then tag."), "Bump to v1.2 first.");
+    // Wrapped lines join, and the paragraph ends the sentence
+    assert_eq!(page_description("one line\nwraps here\n\nnext paragraph."), "one line wraps here");
+    assert_eq!(page_description("  spaced   out\twords  "), "spaced out words");
+    assert_eq!(page_description(""), "");
+}
+
+#[test]
+fn descriptions_skip_code_and_headings() {
+    let data = "# Setup\n\n```sh\ncargo install mdbook. Then more.\n```\n\nInstall mdbook first. Then build.";
+    assert_eq!(page_description(data), "Install mdbook first.");
+    assert_eq!(page_description("```\nonly code\n```"), "");
+}
+
+#[test]
+fn long_descriptions_are_cut_at_a_word() {
+    let sentence = "word ".repeat(100);
+    let description = page_description(&sentence);
+    assert!(description.chars().count() <= DESCRIPTION_LEN, "{}", description);
+    assert!(description.ends_with("word…"), "{}", description);
+
+    // Multibyte text is cut on a character, not a byte
+    let description = page_description(&"é".repeat(400));
+    assert_eq!(description.chars().count(), DESCRIPTION_LEN);
+    assert!(description.ends_with('…'));
+}
+
+#[test]
+fn index_groups_names_by_first_letter() {
+    let fact = |name: &str| Information {
+        id: uuid::Uuid::new_v4(),
+        tags: Vec::new(),
+        name: name.to_string(),
+        data: String::new(),
+        created: None,
+        updated: None,
+        source: None,
+        name_is_derived: false,
+        extra: Default::default(),
+    };
+    let facts = [fact("banana"), fact("Apple"), fact("42 things"), fact("ápple"), fact("日本語"), fact("avocado"), fact("[draft] notes")];
+    let page = index_page(&facts.iter().collect::<Vec<_>>());
+
+    let headings: Vec<&str> = page.lines().filter(|line| line.starts_with("## ")).collect();
+    assert_eq!(headings, ["## \\#", "## A", "## B", "## Á", "## 日"]);
+    let names: Vec<&str> = page.lines().filter_map(|line| line.strip_prefix("- [")?.split("](").next()).collect();
+    assert_eq!(names, ["42 things", "\\[draft\\] notes", "Apple", "avocado", "banana", "ápple", "日本語"]);
+    // Links are the same pages the summary points at
+    assert!(page.contains(&format!("- [banana](./{}.md)", facts[0].id)), "{}", page);
+}
+
+#[test]
+fn stats_page_agrees_with_stats() {
+    let fixture = FixtureWiki::new().facts(5).tags(2).build().unwrap();
+    let wiki = fixture.open().unwrap();
+    let stats = wiki.stats();
+    assert_eq!((stats.facts, stats.tags.len()), (5, 2));
+
+    let generated = Utc.with_ymd_and_hms(2024, 6, 1, 12, 30, 0).unwrap();
+    let tag_pages = HashMap::from([("tag0".to_string(), "tags/tag0.md".to_string())]);
+    let page = wiki.stats_page(&stats, &tag_pages, generated);
+    assert!(page.contains("**Wiki:** fixture"), "{}", page);
+    assert!(page.contains(&format!("**Facts:** {}", stats.facts)), "{}", page);
+    assert!(page.contains(&format!("**Added:** {} in the last week", stats.added_week)), "{}", page);
+    assert!(page.contains("| [tag0](./tags/tag0.md) | 3 |"), "{}", page);
+    assert!(page.contains("| tag1 | 2 |"), "{}", page);
+    assert!(page.contains("*Generated 2024-06-01 12:30 UTC*"), "{}", page);
+}
+
+#[test]
+fn pdf_renderers_are_found_on_the_path() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let (first, second) = (fixture.scratch().join("bin"), fixture.scratch().join("more"));
+    std::fs::create_dir_all(&first).unwrap();
+    std::fs::create_dir_all(&second).unwrap();
+    let path = std::env::join_paths([&first, &second]).unwrap();
+    assert_eq!(pdf::detect(Some(&path)), None);
+    assert_eq!(pdf::detect(None), None);
+
+    let exe = |dir: &std::path::Path, backend: PdfBackend| dir.join(format!("{}{}", backend.program(), std::env::consts::EXE_SUFFIX));
+    std::fs::write(exe(&second, PdfBackend::Typst), "").unwrap();
+    assert_eq!(pdf::detect(Some(&path)), Some(PdfBackend::Typst));
+    // mdbook-pdf is preferred wherever it is on the path
+    std::fs::write(exe(&second, PdfBackend::Pdf), "").unwrap();
+    assert_eq!(pdf::detect(Some(&path)), Some(PdfBackend::Pdf));
+}
+
+#[test]
+fn pdf_renderers_are_added_to_book_toml() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let book_toml = fixture.open().unwrap().book_toml(&BookOptions::default());
+
+    let book: toml::Table = pdf::with_backend(&book_toml, PdfBackend::Pdf).parse().unwrap();
+    assert!(book["output"]["pdf"].as_table().unwrap().is_empty());
+    // mdbook-pdf prints the HTML book, so that stays
+    assert_eq!(book["output"]["html"]["search"]["boost-title"].as_integer(), Some(4));
+
+    let book: toml::Table = pdf::with_backend(&book_toml, PdfBackend::Typst).parse().unwrap();
+    assert_eq!(book["output"]["typst"]["output"]["format"].as_str(), Some("pdf"));
+    assert_eq!(book["book"]["title"].as_str(), Some("fixture Wiki"));
+}
+
+#[test]
+fn without_a_renderer_pages_are_joined_in_summary_order() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let mut wiki = fixture.open().unwrap();
+    for (fact, tag) in [("loose end", None), ("bread", Some("recipes")), ("docker", Some("ops/containers")), ("backups", Some("ops"))] {
+        wiki.commit(fact.to_string(), tag.into_iter().map(str::to_string).collect()).unwrap();
+    }
+
+    let output = wiki.generate_pdf(&BookOptions::default(), None, &mut NoProgress).unwrap();
+    let PdfOutput::Markdown(path) = output else { panic!("{:?}", output) };
+    assert!(path.ends_with(format!("book-pdf/{}", pdf::MARKDOWN_FILE)));
+    let joined = std::fs::read_to_string(&path).unwrap();
+    let headings: Vec<&str> = joined.lines().filter(|line| line.starts_with("# ")).collect();
+    assert_eq!(headings, ["# fixture Wiki", "# backups", "# docker", "# bread", "# loose end"]);
+    // A page each, with the metadata footers
+    assert_eq!(joined.matches(&format!("\n{}\n", PAGE_BREAK)).count(), 4);
+    assert_eq!(joined.matches("**Tags:**").count(), 3);
+    assert_eq!(
+        pdf::pandoc_command(&path),
+        format!("pandoc \"{}\" -o \"{}\"", path.display(), path.with_extension("pdf").display())
+    );
+}
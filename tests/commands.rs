@@ -0,0 +1,97 @@
+//! Custom commands from the `[commands]` table of the global config
+
+mod common;
+
+use common::{stderr, stdout, wk};
+use std::collections::BTreeMap;
+use twk::commands::{check_builtins, rewrite, split_words};
+use twk::fixture::{Fixture, FixtureWiki};
+
+fn argv(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+fn commands(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+    pairs.iter().map(|(name, template)| (name.to_string(), template.to_string())).collect()
+}
+
+/// `argv` as `wk` would run it with `commands`, where only `--data-dir`
+/// takes a value
+fn expand(commands: &BTreeMap<String, String>, args: &[&str]) -> Result<Vec<String>, String> {
+    rewrite(argv(args), commands, |option| option == "--data-dir")
+}
+
+#[test]
+fn templates_split_like_a_shell() {
+    assert_eq!(split_words("c --name \"weekly review\" {args}").unwrap(), ["c", "--name", "weekly review", "{args}"]);
+    assert_eq!(split_words("r 'it''s' a\\ b \"say \\\"hi\\\"\" ''").unwrap(), ["r", "its", "a b", "say \"hi\"", ""]);
+    assert!(split_words("c \"open").is_err());
+    assert!(split_words("c trailing\\").is_err());
+}
+
+#[test]
+fn arguments_go_where_args_is() {
+    let commands = commands(&[
+        ("todo", "c {args} todo"),
+        ("til", "c --edit til"),
+        ("note", "c --name \"note: {args}\" {args}"),
+    ]);
+    // An argument with spaces stays one argument
+    assert_eq!(expand(&commands, &["wk", "todo", "buy milk"]).unwrap(), ["wk", "c", "buy milk", "todo"]);
+    assert_eq!(expand(&commands, &["wk", "til", "x y"]).unwrap(), ["wk", "c", "--edit", "til", "x y"]);
+    assert_eq!(
+        expand(&commands, &["wk", "note", "gc", "pauses"]).unwrap(),
+        ["wk", "c", "--name", "note: gc pauses", "gc", "pauses"]
+    );
+
+    // Options before the command are kept, values and all
+    assert_eq!(
+        expand(&commands, &["wk", "-g", "--data-dir", "todo", "todo", "x"]).unwrap(),
+        ["wk", "-g", "--data-dir", "todo", "c", "x", "todo"]
+    );
+    assert_eq!(expand(&commands, &["wk", "--color=never", "todo"]).unwrap(), ["wk", "--color=never", "c", "todo"]);
+    // Only the command itself is expanded
+    assert_eq!(expand(&commands, &["wk", "c", "todo"]).unwrap(), ["wk", "c", "todo"]);
+    assert_eq!(expand(&commands, &["wk", "--", "todo"]).unwrap(), ["wk", "--", "todo"]);
+}
+
+#[test]
+fn commands_may_use_others_but_not_themselves() {
+    let chained = commands(&[("t", "todo {args}"), ("todo", "c {args} todo")]);
+    assert_eq!(expand(&chained, &["wk", "t", "x"]).unwrap(), ["wk", "c", "x", "todo"]);
+
+    let looped = commands(&[("a", "b"), ("b", "a x")]);
+    let e = expand(&looped, &["wk", "a"]).unwrap_err();
+    assert!(e.contains("a -> b -> a"), "{}", e);
+
+    let builtins = argv(&["c", "commit", "r"]);
+    assert!(check_builtins(&chained, &builtins).is_ok());
+    assert!(check_builtins(&commands(&[("commit", "c {args}")]), &builtins).unwrap_err().contains("'commit'"));
+}
+
+fn write_global_config(fixture: &Fixture, toml: &str) {
+    let home = fixture.env().into_iter().find(|(key, _)| *key == "XDG_CONFIG_HOME").unwrap().1;
+    let dir = std::path::Path::new(&home).join("twk");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("config.toml"), toml).unwrap();
+}
+
+#[test]
+fn custom_commands_run_from_the_global_config() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    write_global_config(&fixture, "[commands]\ntodo = \"c {args} todo\"\n");
+
+    let output = wk(&fixture).args(["todo", "buy milk"]).output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    let facts = fixture.open().unwrap().all();
+    assert_eq!((facts[0].data.as_str(), facts[0].tags.as_slice()), ("buy milk", ["todo".to_string()].as_slice()));
+
+    let output = wk(&fixture).arg("aliases").output().unwrap();
+    assert!(stdout(&output).contains("c {args} todo"), "{}", stdout(&output));
+
+    // Built-in names, aliases included, can't be taken
+    write_global_config(&fixture, "[commands]\nrecall = \"r --detailed {args}\"\n");
+    let output = wk(&fixture).args(["recall", "x"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("built-in command 'recall'"), "{}", stderr(&output));
+}
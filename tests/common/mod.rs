@@ -0,0 +1,48 @@
+//! Shared by the integration tests
+
+// Each test binary compiles its own copy and uses only some of it
+#![allow(dead_code)]
+
+use assert_cmd::Command;
+use twk::fixture::Fixture;
+
+/// `wk -g` against `fixture` alone, run from inside it with no terminal, so
+/// without colour, pager or prompts
+pub fn wk(fixture: &Fixture) -> Command {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_wk"));
+    cmd.arg("-g")
+        .envs(fixture.env())
+        .env_remove("TWK_PASSPHRASE")
+        .current_dir(fixture.scratch());
+    cmd
+}
+
+/// What a finished command wrote to stdout
+pub fn stdout(output: &std::process::Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// What a finished command wrote to stderr
+pub fn stderr(output: &std::process::Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+/// A [`Progress`](twk::Progress) that keeps what it was told
+#[derive(Debug, Default)]
+pub struct Steps {
+    pub total: Option<usize>,
+    pub ticks: Vec<String>,
+    pub finished: bool,
+}
+
+impl twk::Progress for Steps {
+    fn start(&mut self, total: usize) {
+        self.total = Some(total);
+    }
+    fn tick(&mut self, msg: &str) {
+        self.ticks.push(msg.to_string());
+    }
+    fn finish(&mut self) {
+        self.finished = true;
+    }
+}
@@ -0,0 +1,201 @@
+//! Sharing a wiki between threads
+
+use std::sync::{Arc, RwLock};
+use std::thread;
+use twk::fixture::FixtureWiki;
+use std::time::Duration;
+use twk::helpers::{FileLock, LOCK_TIMEOUT, Locked, lock_path_for};
+use twk::storage::{FsStorage, Stale, Storage};
+use twk::{Fields, Information, TimeWindow, Wiki, WikiError};
+
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn wiki_is_send_and_sync() {
+    assert_send_sync::<Wiki>();
+    assert_send_sync::<Locked<Information>>();
+    assert_send_sync::<Box<dyn Storage>>();
+    assert_send_sync::<Arc<RwLock<Wiki>>>();
+}
+
+#[test]
+fn recalls_share_a_wiki() {
+    let fixture = FixtureWiki::new().facts(200).tags(5).size(300).build().unwrap();
+    let wiki = fixture.open().unwrap();
+    let alone = wiki.recall("charlie delta", Some("tag3"));
+
+    // Hits borrow their facts from the wiki across every thread at once
+    thread::scope(|s| {
+        let workers: Vec<_> = (0..4)
+            .map(|_| s.spawn(|| wiki.recall_refs("charlie delta", Some("tag3"), Fields::ALL, TimeWindow::ANY, None).len()))
+            .collect();
+        for worker in workers {
+            assert_eq!(worker.join().unwrap(), alone.len());
+        }
+    });
+}
+
+#[test]
+fn recalls_run_while_committing() {
+    const COMMITS: usize = 50;
+    let fixture = FixtureWiki::new().facts(20).build().unwrap();
+    let wiki = Arc::new(RwLock::new(fixture.open().unwrap()));
+
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let wiki = Arc::clone(&wiki);
+            thread::spawn(move || {
+                let mut seen = 0;
+                while seen < 20 + COMMITS {
+                    let wiki = wiki.read().unwrap();
+                    let hits = wiki.recall("Fact", None);
+                    // Commits land whole or not at all, and never go missing
+                    assert!(hits.len() >= seen, "{} after {}", hits.len(), seen);
+                    assert!(hits.iter().all(|info| info.name.starts_with("Fact") && !info.data.is_empty()));
+                    seen = hits.len();
+                }
+            })
+        })
+        .collect();
+
+    for i in 0..COMMITS {
+        wiki.write().unwrap().commit(format!("Fact committed {}", i), vec!["new".to_string()]).unwrap();
+    }
+    for reader in readers {
+        reader.join().unwrap();
+    }
+
+    assert_eq!(wiki.read().unwrap().info.len(), 20 + COMMITS);
+    assert_eq!(fixture.open().unwrap().info.len(), 20 + COMMITS);
+}
+
+#[test]
+fn snapshots_see_writes_whole() {
+    const WRITES: usize = 2000;
+    let fixture = FixtureWiki::new().facts(2).build().unwrap();
+    let wiki = fixture.open().unwrap();
+    let counter = |info: &Information| info.data.parse::<usize>().unwrap_or(0);
+    let done = std::sync::atomic::AtomicBool::new(false);
+
+    // The writer counts both facts up in turn, the first ahead of the second,
+    // so a snapshot taken at one moment finds them equal or the first one up
+    thread::scope(|s| {
+        s.spawn(|| {
+            for n in 1..=WRITES {
+                for locked in &wiki.info {
+                    locked.write().data = n.to_string();
+                }
+            }
+            done.store(true, std::sync::atomic::Ordering::Release);
+        });
+        let mut snapshots = 0;
+        while !done.load(std::sync::atomic::Ordering::Acquire) || snapshots == 0 {
+            let facts = wiki.snapshot_facts();
+            let (first, second) = (counter(&facts[0]), counter(&facts[1]));
+            assert!(first == second || first == second + 1, "{} and {}", first, second);
+            snapshots += 1;
+        }
+    });
+    assert!(wiki.snapshot_facts().iter().all(|info| counter(info) == WRITES));
+}
+
+#[test]
+fn a_stale_writer_gets_a_conflict() {
+    let fixture = FixtureWiki::new().facts(1).build().unwrap();
+    let id = fixture.facts()[0].id;
+    let (mut first, mut second) = (fixture.open().unwrap(), fixture.open().unwrap());
+    let read = second.get(id).unwrap().updated;
+
+    let saved = first.update_if(id, read, |info| info.data = "first".to_string()).unwrap();
+    // The second wiki still holds the copy it read, but its save is checked
+    // against the one in storage
+    let stale = second.update_if(id, read, |info| info.data = "second".to_string());
+    assert!(matches!(stale, Err(WikiError::Conflict { id: conflicted, .. }) if conflicted == id), "{:?}", stale);
+    assert_eq!(fixture.open().unwrap().get(id).unwrap().data, "first");
+
+    // Read again, it goes through, and the first wiki's copy is the stale one
+    assert_eq!(second.reload(id).unwrap().updated, saved.updated);
+    second.update_if(id, saved.updated, |info| info.data = "second".to_string()).unwrap();
+    assert_eq!(fixture.open().unwrap().get(id).unwrap().data, "second");
+    let again = first.update_if(id, saved.updated, |info| info.data = "first again".to_string());
+    assert!(matches!(again, Err(WikiError::Conflict { .. })), "{:?}", again);
+}
+
+#[test]
+fn a_save_landing_mid_update_is_not_overwritten() {
+    let fixture = FixtureWiki::new().facts(1).build().unwrap();
+    let id = fixture.facts()[0].id;
+    let mut wiki = fixture.open().unwrap();
+    let read = wiki.get(id).unwrap().updated;
+
+    // Changed on disk after loading, by a writer that got in first
+    let storage = FsStorage::new(fixture.path());
+    let mut theirs = storage.read(id).unwrap();
+    theirs.data = "saved elsewhere".to_string();
+    theirs.updated = Some(chrono::Utc::now());
+    let path = fixture.path().join(format!("{}.json", id));
+    let lock = FileLock::exclusive(&lock_path_for(&path), LOCK_TIMEOUT).unwrap();
+    let result = thread::scope(|s| {
+        let saving = s.spawn(|| wiki.update_if(id, read, |info| info.data = "saved here".to_string()));
+        // Give the update time to reach the lock before the other save lands
+        thread::sleep(Duration::from_millis(200));
+        twk::helpers::write_atomic(&path, &serde_json::to_vec(&theirs).unwrap()).unwrap();
+        drop(lock);
+        saving.join().unwrap()
+    });
+
+    assert!(matches!(result, Err(WikiError::Conflict { found, .. }) if found == theirs.updated), "{:?}", result);
+    assert_eq!(storage.read(id).unwrap().data, "saved elsewhere");
+    assert_eq!(wiki.get(id).unwrap().data, fixture.facts()[0].data);
+}
+
+#[test]
+fn replacing_waits_for_the_wiki_lock() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let mut wiki = fixture.open().unwrap();
+    let id = wiki.commit("deploy on fridays".to_string(), vec![]).unwrap();
+    let storage = FsStorage::new(fixture.path());
+
+    // As another wk process's import or fmt would hold it
+    let lock = FileLock::exclusive(&fixture.path().join(".lock"), LOCK_TIMEOUT).unwrap();
+    let opts = twk::ReplaceOptions { write: true, ..Default::default() };
+    let reports = thread::scope(|s| {
+        let replacing = s.spawn(|| wiki.replace(&regex::Regex::new("fridays").unwrap(), "mondays", &opts));
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(storage.read(id).unwrap().data, "deploy on fridays");
+        drop(lock);
+        replacing.join().unwrap()
+    });
+
+    assert_eq!(reports.unwrap().len(), 1);
+    assert_eq!(storage.read(id).unwrap().data, "deploy on mondays");
+}
+
+#[test]
+fn conditional_writes_check_the_stored_copy() {
+    let fixture = FixtureWiki::new().facts(1).build().unwrap();
+    check_write_if(&FsStorage::new(fixture.path()), fixture.facts()[0].clone());
+    #[cfg(feature = "sqlite")]
+    {
+        let storage = twk::sqlite::SqliteStorage::open(&fixture.scratch().join("sqlite")).unwrap();
+        storage.write(&fixture.facts()[0]).unwrap();
+        check_write_if(&storage, fixture.facts()[0].clone());
+    }
+}
+
+/// `storage` holding `info` as it is
+fn check_write_if(storage: &dyn Storage, mut info: Information) {
+    let stored = info.updated;
+    info.data = "changed".to_string();
+    info.updated = Some(chrono::Utc::now());
+    let wrong = Some(chrono::DateTime::UNIX_EPOCH);
+    assert_eq!(storage.write_if(&info, wrong).unwrap(), Err(Stale(stored)));
+    assert_eq!(storage.write_if(&info, stored).unwrap(), Ok(()));
+    assert_eq!(storage.read(info.id).unwrap().data, "changed");
+    assert_eq!(storage.write_if(&info, stored).unwrap(), Err(Stale(info.updated)));
+
+    // A fact not stored yet is written whatever was expected
+    let new = Information { id: uuid::Uuid::new_v4(), ..info.clone() };
+    assert_eq!(storage.write_if(&new, stored).unwrap(), Ok(()));
+    assert!(storage.exists(new.id));
+}
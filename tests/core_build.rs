@@ -0,0 +1,44 @@
+//! The library core, `default-features = false`, builds on its own and
+//! without most of what the `wk` binary needs
+
+use std::collections::BTreeSet;
+use std::process::{Command, Output};
+
+fn cargo(args: &[&str]) -> Output {
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let output = Command::new(cargo)
+        .args(args)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "cargo {:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+    output
+}
+
+/// Every crate built with `features`, twk itself included
+fn crates(features: &[&str]) -> BTreeSet<String> {
+    let mut args = vec!["tree", "-e", "normal", "--prefix", "none"];
+    args.extend(features);
+    let output = cargo(&args);
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .map(str::to_string)
+        .collect()
+}
+
+#[test]
+fn the_core_needs_under_a_third_of_the_dependencies() {
+    let core = crates(&["--no-default-features"]);
+    let full = crates(&[]);
+    assert!(core.len() * 3 < full.len(), "{} crates in the core against {}: {:?}", core.len(), full.len(), core);
+    for heavy in ["clap", "ratatui", "crossterm", "argon2", "chacha20poly1305"] {
+        assert!(!core.contains(heavy), "{} is in the core", heavy);
+    }
+}
+
+#[test]
+fn the_core_builds_without_default_features() {
+    let target = format!("{}/no-default-features", env!("CARGO_TARGET_TMPDIR"));
+    cargo(&["check", "--lib", "--no-default-features", "--target-dir", &target]);
+}
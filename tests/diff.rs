@@ -0,0 +1,121 @@
+//! Line diffs and `wk diff`
+
+mod common;
+
+use common::{stderr, stdout, wk};
+use twk::diff::{Hunk, Line, diff_lines, hunks, stat};
+use twk::fixture::{Fixture, FixtureWiki};
+use uuid::Uuid;
+
+#[test]
+fn lines_are_kept_removed_or_added() {
+    use Line::*;
+    assert_eq!(diff_lines("a\nb\nc", "a\nb\nc"), [Same("a"), Same("b"), Same("c")]);
+    assert_eq!(diff_lines("a\nb\nc", "a\nx\nc"), [Same("a"), Removed("b"), Added("x"), Same("c")]);
+    assert_eq!(diff_lines("", "new"), [Added("new")]);
+    assert_eq!(diff_lines("a\nb\nc\nd", "b\nd\ne"), [Removed("a"), Same("b"), Removed("c"), Same("d"), Added("e")]);
+
+    let lines = diff_lines("a\nb\nc", "a\nx\ny\nc");
+    assert_eq!((stat(&lines).added, stat(&lines).removed), (2, 1));
+}
+
+#[test]
+fn hunks_keep_three_lines_of_context() {
+    let old: Vec<String> = (1..=20).map(|n| n.to_string()).collect();
+    let mut new = old.clone();
+    new[1] = "two".to_string();
+    new[4] = "five".to_string();
+    new.remove(17);
+    let (old, new) = (old.join("\n"), new.join("\n"));
+    let lines = diff_lines(&old, &new);
+    let found = hunks(&lines);
+
+    // The first two changes are close enough to share a hunk
+    let spans: Vec<_> = found.iter().map(|h| (h.old_start, h.old_len, h.new_start, h.new_len)).collect();
+    assert_eq!(spans, [(1, 8, 1, 8), (15, 6, 15, 5)]);
+    assert_eq!(found[1].lines[3], Line::Removed("18"));
+
+    // An empty side starts at the line before it
+    let lines = diff_lines("", "one\ntwo");
+    assert_eq!(
+        hunks(&lines),
+        [Hunk { old_start: 0, old_len: 0, new_start: 1, new_len: 2, lines: vec![Line::Added("one"), Line::Added("two")] }]
+    );
+    assert!(hunks(&diff_lines("same", "same")).is_empty());
+}
+
+#[test]
+fn long_texts_with_scattered_edits_diff_quickly() {
+    // A full table of common lines would need 40000² entries here
+    let old: Vec<String> = (0..40_000).map(|n| format!("line {}", n)).collect();
+    let mut new = old.clone();
+    for n in (0..40_000).step_by(997) {
+        new[n] = format!("edited {}", n);
+    }
+    new.insert(20_000, "inserted".to_string());
+    let (old, new) = (old.join("\n"), new.join("\n"));
+    let lines = diff_lines(&old, &new);
+    assert_eq!(stat(&lines), twk::diff::DiffStat { added: 42, removed: 41 });
+    assert_eq!(lines.iter().filter(|line| !line.is_change()).count(), 40_000 - 41);
+}
+
+/// A fact snapshotted and then edited
+fn edited(fixture: &Fixture) -> Uuid {
+    let mut wiki = fixture.open().unwrap();
+    let id = wiki.commit("backups\nnightly at 3am\nkept a week".to_string(), vec![]).unwrap();
+    wiki.snapshot(id, Some("first")).unwrap();
+    wiki.update(id, |info| info.data = "backups\nnightly at 2am\nkept a week".to_string()).unwrap();
+    id
+}
+
+#[test]
+fn diff_against_the_latest_snapshot() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let id = edited(&fixture);
+
+    let output = wk(&fixture).args(["diff", &id.to_string()]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1), "{}", stderr(&output));
+    let diff = stdout(&output);
+    assert!(diff.starts_with(&format!("--- {} (first)\n", id)), "{}", diff);
+    assert!(diff.contains("@@ -1,3 +1,3 @@\n backups\n-nightly at 3am\n+nightly at 2am\n kept a week\n"), "{}", diff);
+
+    let output = wk(&fixture).args(["diff", &id.to_string(), "--stat"]).output().unwrap();
+    assert_eq!(stdout(&output), "backups 1 insertion(+), 1 deletion(-)\n");
+
+    // Nothing to show once they're the same again
+    fixture.open().unwrap().snapshot(id, Some("second")).unwrap();
+    let output = wk(&fixture).args(["diff", &id.to_string()]).output().unwrap();
+    assert_eq!((output.status.code(), stdout(&output)), (Some(0), String::new()));
+    let output = wk(&fixture).args(["diff", &id.to_string(), "--snapshot", "first"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn diff_against_another_wiki_or_a_file() {
+    let fixture = FixtureWiki::new().facts(2).build().unwrap();
+    let fact = &fixture.facts()[0];
+    let id = fact.id.to_string();
+
+    let other = fixture.data_dir().join("other");
+    std::fs::create_dir_all(&other).unwrap();
+    let mut theirs = fact.clone();
+    theirs.data.push_str("\nadded over there");
+    std::fs::write(other.join(format!("{}.json", id)), serde_json::to_string(&theirs).unwrap()).unwrap();
+    let output = wk(&fixture).args(["diff", &id, "--wiki", "other"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1), "{}", stderr(&output));
+    assert!(stdout(&output).contains("\n-added over there\n"), "{}", stdout(&output));
+
+    let file = fixture.scratch().join("notes.md");
+    std::fs::write(&file, &fact.data).unwrap();
+    let output = wk(&fixture).args(["diff", &id, "--file"]).arg(&file).output().unwrap();
+    assert_eq!((output.status.code(), stdout(&output)), (Some(0), String::new()));
+
+    // Trouble is 2 or more, so scripts can tell it from a difference
+    let output = wk(&fixture).args(["diff", &id, "--wiki", "nowhere"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    let output = wk(&fixture).args(["diff", &id, "--file", "missing.md"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let output = wk(&fixture).args(["diff", &fixture.facts()[1].id.to_string()]).output().unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    assert!(stderr(&output).contains("no snapshots"), "{}", stderr(&output));
+}
@@ -0,0 +1,188 @@
+//! `wk sync --dir` between two copies of a wiki, and the conflict copies
+//! Syncthing and Dropbox leave behind
+
+use std::path::{Path, PathBuf};
+use twk::dirsync::{CONFLICT_TAG, DirSyncAction};
+use twk::fixture::{Fixture, FixtureWiki};
+use twk::storage::{FsStorage, Storage, is_conflict_copy};
+use twk::{Information, Repair};
+use uuid::Uuid;
+
+/// A copy of the fixture's wiki beside it, as another machine would hold
+fn peer(fixture: &Fixture) -> PathBuf {
+    let peer = fixture.scratch().join("peer");
+    std::fs::create_dir_all(&peer).unwrap();
+    for entry in std::fs::read_dir(fixture.path()).unwrap().flatten() {
+        if entry.path().extension().is_some_and(|ext| ext == "json") {
+            std::fs::copy(entry.path(), peer.join(entry.file_name())).unwrap();
+        }
+    }
+    peer
+}
+
+fn facts(dir: &Path) -> Vec<Information> {
+    let mut facts = FsStorage::new(dir).load_all().unwrap().facts;
+    facts.sort_by_key(|info| info.id);
+    facts
+}
+
+fn edit(dir: &Path, id: Uuid, data: &str) {
+    let storage = FsStorage::new(dir);
+    let mut info = storage.read(id).unwrap();
+    info.data = data.to_string();
+    info.updated = Some(chrono::Utc::now());
+    storage.write(&info).unwrap();
+}
+
+#[test]
+fn facts_new_on_either_side_reach_the_other() {
+    let fixture = FixtureWiki::new().facts(2).build().unwrap();
+    let peer = peer(&fixture);
+    let mut wiki = fixture.open().unwrap();
+    let here = wiki.commit("only here".to_string(), vec![]).unwrap();
+    let theirs = Information { id: Uuid::new_v4(), data: "only there".to_string(), ..fixture.facts()[0].clone() };
+    FsStorage::new(&peer).write(&theirs).unwrap();
+
+    let plan = wiki.sync_dir(&peer, false).unwrap();
+    assert_eq!(plan.actions.len(), 2, "{:?}", plan.actions);
+    assert!(plan.actions.iter().any(|a| matches!(a, DirSyncAction::Push(info) if info.id == here)));
+    assert!(plan.actions.iter().any(|a| matches!(a, DirSyncAction::Pull(info) if info.id == theirs.id)));
+    assert_eq!(facts(&fixture.path()), facts(&peer));
+    assert_eq!(facts(&peer).len(), 4);
+
+    // Nothing is left to do
+    assert!(wiki.sync_dir(&peer, false).unwrap().actions.is_empty());
+}
+
+#[test]
+fn one_sided_edits_and_deletions_carry_over() {
+    let fixture = FixtureWiki::new().facts(3).build().unwrap();
+    let peer = peer(&fixture);
+    let mut wiki = fixture.open().unwrap();
+    wiki.sync_dir(&peer, false).unwrap();
+    let [a, b, c] = [0, 1, 2].map(|n| fixture.facts()[n].id);
+
+    edit(&peer, a, "edited there");
+    wiki.update(b, |info| info.data = "edited here".to_string()).unwrap();
+    FsStorage::new(&peer).trash(c, chrono::Utc::now()).unwrap();
+
+    wiki.sync_dir(&peer, false).unwrap();
+    assert_eq!(facts(&fixture.path()), facts(&peer));
+    assert_eq!(wiki.get(a).unwrap().data, "edited there");
+    assert_eq!(FsStorage::new(&peer).read(b).unwrap().data, "edited here");
+    assert!(wiki.get(c).is_err());
+    assert!(wiki.trashed().unwrap().iter().any(|t| t.fact.id == c));
+}
+
+#[test]
+fn edits_on_both_sides_keep_both_in_a_conflict_fact() {
+    let fixture = FixtureWiki::new().facts(1).build().unwrap();
+    let peer = peer(&fixture);
+    let mut wiki = fixture.open().unwrap();
+    wiki.sync_dir(&peer, false).unwrap();
+    let id = fixture.facts()[0].id;
+
+    wiki.update(id, |info| info.data = "older edit here".to_string()).unwrap();
+    edit(&peer, id, "newer edit there");
+    let plan = wiki.sync_dir(&peer, false).unwrap();
+    assert!(matches!(&plan.actions[..], [DirSyncAction::Conflict(_)]), "{:?}", plan.actions);
+
+    // The newer edit wins on both sides, and the conflict fact holds both
+    assert_eq!(facts(&fixture.path()), facts(&peer));
+    assert_eq!(wiki.get(id).unwrap().data, "newer edit there");
+    let conflicts: Vec<Information> = facts(&peer).into_iter().filter(|info| info.tags == [CONFLICT_TAG]).collect();
+    assert_eq!(conflicts.len(), 1);
+    let data = &conflicts[0].data;
+    assert!(data.contains("<<<<<<<") && data.contains("=======") && data.contains(">>>>>>>"), "{}", data);
+    let (ours, theirs) = (data.find("older edit here").unwrap(), data.find("newer edit there").unwrap());
+    assert!(ours < theirs, "{}", data);
+}
+
+#[test]
+fn conflict_facts_are_the_same_on_both_sides() {
+    let fixture = FixtureWiki::new().facts(1).build().unwrap();
+    std::fs::write(fixture.path().join("config.toml"), "default_tags = [\"inbox\"]\n").unwrap();
+    let peer = peer(&fixture);
+    let mut wiki = fixture.open().unwrap();
+    wiki.sync_dir(&peer, false).unwrap();
+    let id = fixture.facts()[0].id;
+
+    wiki.update(id, |info| info.data = "edit here".to_string()).unwrap();
+    edit(&peer, id, "edit there");
+    wiki.sync_dir(&peer, false).unwrap();
+    assert_eq!(facts(&fixture.path()), facts(&peer));
+    assert!(facts(&peer).iter().any(|info| info.tags == [CONFLICT_TAG, "inbox"]), "{:#?}", facts(&peer));
+
+    // So the next sync has nothing to settle
+    assert!(wiki.sync_dir(&peer, false).unwrap().actions.is_empty());
+}
+
+#[test]
+fn dry_runs_plan_without_writing() {
+    let fixture = FixtureWiki::new().facts(2).build().unwrap();
+    let peer = peer(&fixture);
+    let mut wiki = fixture.open().unwrap();
+    wiki.commit("only here".to_string(), vec![]).unwrap();
+    let (before_here, before_peer) = (facts(&fixture.path()), facts(&peer));
+
+    let plan = wiki.sync_dir(&peer, true).unwrap();
+    assert_eq!(plan.actions.len(), 1);
+    assert_eq!((facts(&fixture.path()), facts(&peer)), (before_here, before_peer));
+    assert!(!peer.join(twk::dirsync::SYNC_STATE_FILE).exists());
+}
+
+#[test]
+fn unsafe_syncs_are_refused() {
+    let fixture = FixtureWiki::new().facts(1).build().unwrap();
+    let peer = peer(&fixture);
+    let mut wiki = fixture.open().unwrap();
+    assert!(wiki.sync_dir(&fixture.path(), false).is_err());
+    assert!(wiki.sync_dir(&fixture.scratch().join("missing"), false).is_err());
+
+    // An unreadable fact would look deleted
+    std::fs::write(peer.join(format!("{}.json", Uuid::new_v4())), "{ broken").unwrap();
+    let refused = wiki.sync_dir(&peer, false).unwrap_err();
+    assert!(refused.to_string().contains("wk doctor"), "{}", refused);
+}
+
+#[test]
+fn conflict_copy_names_are_recognised() {
+    assert!(is_conflict_copy("3f1c.sync-conflict-20240101-120000-ABCDEFG.json"));
+    assert!(is_conflict_copy("3f1c (conflicted copy 2024-01-01).json"));
+    assert!(is_conflict_copy("3f1c (Sam's conflicted copy 2024-01-01).json"));
+    assert!(!is_conflict_copy("3f1c.json"));
+    assert!(!is_conflict_copy("sync-conflicts.json"));
+}
+
+#[test]
+fn doctor_folds_conflict_copies_back_in() {
+    let fixture = FixtureWiki::new().facts(3).build().unwrap();
+    let copy_of = |n: usize, data: &str| {
+        let mut info = fixture.facts()[n].clone();
+        info.data = data.to_string();
+        let path = fixture.path().join(format!("{} (conflicted copy 2024-01-01).json", info.id));
+        std::fs::write(&path, serde_json::to_vec(&info).unwrap()).unwrap();
+        path
+    };
+    let same = copy_of(0, &fixture.facts()[0].data);
+    let differs = copy_of(1, "edited elsewhere");
+    let orphan = copy_of(2, "deleted here");
+    let mut wiki = fixture.open().unwrap();
+    wiki.delete(fixture.facts()[2].id, true).unwrap();
+
+    let findings = wiki.check_conflict_copies();
+    assert_eq!(findings.len(), 3);
+    let repairs = wiki.fix(&findings).unwrap();
+    let repaired = |path: &Path| repairs.iter().find(|r| match r {
+        Repair::Removed { path: p } | Repair::Merged { path: p, .. } | Repair::Recovered { path: p, .. } => p == path,
+        _ => false,
+    });
+    assert!(matches!(repaired(&same), Some(Repair::Removed { .. })), "{:?}", repairs);
+    assert!(matches!(repaired(&orphan), Some(Repair::Recovered { .. })), "{:?}", repairs);
+    let Some(Repair::Merged { id, .. }) = repaired(&differs) else { panic!("{:?}", repairs) };
+    assert!(wiki.get(*id).unwrap().data.contains("edited elsewhere"));
+    assert_eq!(wiki.get(fixture.facts()[2].id).unwrap().data, "deleted here");
+
+    assert!(![same, differs, orphan].iter().any(|path| path.exists()));
+    assert!(fixture.open().unwrap().check_conflict_copies().is_empty());
+}
@@ -0,0 +1,119 @@
+//! `wk doctor`'s checks and repairs, each against a wiki broken in one way
+
+mod common;
+
+use common::{stderr, stdout, wk};
+use std::path::PathBuf;
+use twk::fixture::{Fixture, FixtureWiki};
+use twk::{Finding, Repair};
+
+/// Where the fixture's first fact is stored
+fn fact_file(fixture: &Fixture) -> PathBuf {
+    fixture.path().join(format!("{}.json", fixture.facts()[0].id))
+}
+
+#[test]
+fn a_sound_wiki_has_no_findings() {
+    let fixture = FixtureWiki::new().facts(5).build().unwrap();
+    assert!(fixture.open().unwrap().diagnose().unwrap().is_empty());
+}
+
+#[test]
+fn unreadable_files_are_quarantined() {
+    let fixture = FixtureWiki::new().facts(2).corrupted(1).build().unwrap();
+    let broken = fixture.corrupted()[0].clone();
+    let mut wiki = fixture.open().unwrap();
+    let findings = wiki.diagnose().unwrap();
+    assert!(matches!(&findings[..], [Finding::Corrupt { path, .. }] if *path == broken), "{:?}", findings);
+
+    let repairs = wiki.fix(&findings).unwrap();
+    let moved = fixture.path().join("corrupt").join(broken.file_name().unwrap());
+    assert!(matches!(&repairs[..], [Repair::Quarantined { to, .. }] if *to == moved), "{:?}", repairs);
+    assert!(!broken.exists() && moved.exists());
+    assert!(fixture.open().unwrap().diagnose().unwrap().is_empty());
+}
+
+#[test]
+fn misnamed_files_are_renamed_after_their_id() {
+    let fixture = FixtureWiki::new().facts(2).build().unwrap();
+    let misnamed = fixture.path().join("renamed-by-hand.json");
+    std::fs::rename(fact_file(&fixture), &misnamed).unwrap();
+
+    let mut wiki = fixture.open().unwrap();
+    let findings = wiki.check_filenames().unwrap();
+    let id = fixture.facts()[0].id;
+    assert_eq!(findings, [Finding::Misnamed { path: misnamed.clone(), id }]);
+    let repairs = wiki.fix(&findings).unwrap();
+    assert!(matches!(&repairs[..], [Repair::Renamed { to, .. }] if *to == fact_file(&fixture)), "{:?}", repairs);
+    assert!(!misnamed.exists());
+    assert!(fixture.open().unwrap().check_filenames().unwrap().is_empty());
+}
+
+#[test]
+fn of_duplicated_ids_the_newest_copy_is_kept() {
+    let fixture = FixtureWiki::new().facts(1).build().unwrap();
+    let mut newer = fixture.facts()[0].clone();
+    newer.data = "the newer copy".to_string();
+    newer.updated = Some(chrono::Utc::now());
+    let copy = fixture.path().join("copy.json");
+    std::fs::write(&copy, serde_json::to_vec(&newer).unwrap()).unwrap();
+
+    let mut wiki = fixture.open().unwrap();
+    let findings = wiki.check_duplicates().unwrap();
+    let mut paths = vec![copy.clone(), fact_file(&fixture)];
+    paths.sort();
+    assert_eq!(findings, [Finding::DuplicateId { id: newer.id, paths }]);
+
+    // Listed as misnamed too, which is left to the duplicate's repair
+    let findings = wiki.diagnose().unwrap();
+    assert_eq!(findings.len(), 2, "{:?}", findings);
+    wiki.fix(&findings).unwrap();
+    assert!(!copy.exists());
+    assert_eq!(fixture.path().join("corrupt").read_dir().unwrap().count(), 1);
+    let reopened = fixture.open().unwrap();
+    assert_eq!(reopened.get(newer.id).unwrap().data, "the newer copy");
+    assert!(reopened.diagnose().unwrap().is_empty());
+}
+
+#[test]
+fn leftover_temp_files_are_removed() {
+    let fixture = FixtureWiki::new().facts(1).build().unwrap();
+    let tmp = fact_file(&fixture).with_extension("json.tmp");
+    std::fs::write(&tmp, "{ half written").unwrap();
+
+    let mut wiki = fixture.open().unwrap();
+    let findings = wiki.diagnose().unwrap();
+    assert_eq!(findings, [Finding::StrayTempFile { path: tmp.clone() }]);
+    let repairs = wiki.fix(&findings).unwrap();
+    assert!(matches!(&repairs[..], [Repair::Removed { path }] if *path == tmp), "{:?}", repairs);
+    assert!(!tmp.exists() && fact_file(&fixture).exists());
+}
+
+#[test]
+fn sync_conflict_copies_are_found() {
+    let fixture = FixtureWiki::new().facts(1).build().unwrap();
+    let mut edited = fixture.facts()[0].clone();
+    edited.data = "edited on the laptop".to_string();
+    let copy = fixture.path().join(format!("{}.sync-conflict-20240101-120000-ABCDEFG.json", edited.id));
+    std::fs::write(&copy, serde_json::to_vec(&edited).unwrap()).unwrap();
+
+    let wiki = fixture.open().unwrap();
+    assert_eq!(wiki.diagnose().unwrap(), [Finding::ConflictCopy { path: copy, id: edited.id }]);
+    // Not as a second copy of the fact
+    assert!(wiki.check_duplicates().unwrap().is_empty());
+}
+
+#[test]
+fn the_report_lists_findings_by_category_until_fixed() {
+    let fixture = FixtureWiki::new().facts(2).corrupted(1).build().unwrap();
+    std::fs::write(fact_file(&fixture).with_extension("json.tmp"), "").unwrap();
+
+    let output = wk(&fixture).arg("doctor").output().unwrap();
+    let report = stdout(&output);
+    assert!(report.contains("Unreadable files") && report.contains("Leftover temp files"), "{}", report);
+    assert!(!report.contains("Duplicate ids"), "{}", report);
+
+    let output = wk(&fixture).args(["doctor", "--fix"]).output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(fixture.open().unwrap().diagnose().unwrap().is_empty());
+}
@@ -0,0 +1,126 @@
+//! `--dry-run` plans every change and writes nothing
+
+mod common;
+
+use common::{stdout, wk};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use twk::fixture::{Fixture, FixtureWiki};
+
+/// Every file under `dir` with when it was modified and what it holds
+fn files(dir: &Path) -> BTreeMap<PathBuf, (SystemTime, Vec<u8>)> {
+    let mut files = BTreeMap::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else {
+                let modified = path.metadata().unwrap().modified().unwrap();
+                files.insert(path.clone(), (modified, std::fs::read(&path).unwrap()));
+            }
+        }
+    }
+    files
+}
+
+/// A wiki with something in each place a command can change: a snapshot,
+/// a fact in the trash and a tag alias to normalize
+fn wiki() -> Fixture {
+    let fixture = FixtureWiki::new().facts(5).build().unwrap();
+    std::fs::write(fixture.path().join("config.toml"), "[aliases]\ntag2 = \"tag1\"\n").unwrap();
+    let [first, second, ..] = fixture.facts() else { unreachable!() };
+    wk(&fixture).args(["snapshot", &first.id.to_string(), "before"]).assert().success();
+    wk(&fixture).args(["delete", &second.id.to_string()]).assert().success();
+    // Leave behind the search index and anything else a plain read writes
+    wk(&fixture).args(["r", "Fact"]).assert().success();
+    fixture
+}
+
+/// Run `wk --dry-run <args>`, checking it changed no file and planned `step`
+fn plans(fixture: &Fixture, args: &[&str], step: &str) {
+    let before = files(fixture.scratch());
+    let run = wk(fixture).arg("--dry-run").args(args).assert().success();
+    let plan = stdout(run.get_output());
+    assert!(plan.lines().any(|line| line.trim_start().starts_with(step)), "{:?}: {}", args, plan);
+    assert!(files(fixture.scratch()) == before, "{:?} changed files", args);
+}
+
+#[test]
+fn mutating_commands_write_nothing() {
+    let fixture = wiki();
+    let first = fixture.facts()[0].id.to_string();
+    let second = fixture.facts()[1].id.to_string();
+    let third = fixture.facts()[2].id.to_string();
+
+    plans(&fixture, &["c", "a new fact", "tag0"], "create");
+    plans(&fixture, &["replace", "alpha", "omega", "--write", "--all"], "update");
+    plans(&fixture, &["snapshot", &first, "again"], "snapshot");
+    plans(&fixture, &["restore", &first, "--snapshot", "before"], "update");
+    plans(&fixture, &["delete", &third], "trash");
+    plans(&fixture, &["delete", &third, "--hard"], "delete");
+    plans(&fixture, &["trash", "restore", &second], "restore");
+    plans(&fixture, &["trash", "empty"], "purge");
+    plans(&fixture, &["tags", "normalize"], "update");
+    plans(&fixture, &["tag", "--query", "Fact", "+archived"], "update");
+}
+
+#[cfg(unix)]
+#[test]
+fn editing_writes_nothing() {
+    let fixture = wiki();
+    let before = files(fixture.scratch());
+    let first = fixture.facts()[0].id.to_string();
+    let run = wk(&fixture)
+        .args(["--dry-run", "open", &first])
+        .env("EDITOR", "sed -i s/alpha/omega/")
+        .assert()
+        .success();
+    assert!(stdout(run.get_output()).contains(&format!("update {} Fact 0", first)));
+    assert!(files(fixture.scratch()) == before);
+}
+
+#[test]
+fn plan_counts_each_step() {
+    let fixture = wiki();
+    let first = fixture.facts()[0].id.to_string();
+    let run = wk(&fixture).args(["--dry-run", "restore", &first, "--snapshot", "before"]).assert().success();
+    let plan = stdout(run.get_output());
+    assert_eq!(plan.lines().last(), Some("snapshot 1, update 1"), "{}", plan);
+}
+
+#[test]
+fn unplannable_commands_are_refused() {
+    let fixture = wiki();
+    let before = files(fixture.scratch());
+    let first = fixture.facts()[0].id.to_string();
+    let refused: [&[&str]; 6] = [
+        &["tui"],
+        &["book"],
+        &["migrate", "--to", "files"],
+        &["doctor", "--fix"],
+        &["wiki", "rm", "fixture"],
+        &["open", "--raw", &first],
+    ];
+    for args in refused {
+        wk(&fixture).arg("--dry-run").args(args).assert().code(2).stdout("");
+    }
+    assert!(files(fixture.scratch()) == before);
+}
+
+#[test]
+fn library_dry_runs_keep_changes_in_memory() {
+    let fixture = FixtureWiki::new().facts(3).build().unwrap();
+    let mut wiki = fixture.open().unwrap().with_dry_run();
+    let id = wiki.commit("planned".to_string(), vec![]).unwrap();
+    assert_eq!(wiki.get(id).unwrap().data, "planned");
+    wiki.delete(fixture.facts()[0].id, false).unwrap();
+
+    let plan: Vec<String> = wiki.plan().iter().map(|step| step.action.verb().to_string()).collect();
+    assert_eq!(plan, ["create", "trash"]);
+    let reopened = fixture.open().unwrap();
+    assert_eq!(reopened.info.len(), 3);
+    assert!(reopened.get(id).is_err());
+}
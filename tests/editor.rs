@@ -0,0 +1,121 @@
+//! Finding and running the editor, on every platform
+
+mod common;
+
+use common::{stderr, wk};
+use std::path::Path;
+use twk::editor::{EditError, EditOutcome, classify, editor_command, split_command, to_frontmatter};
+use twk::fixture::FixtureWiki;
+
+#[test]
+fn splits_on_whitespace() {
+    assert_eq!(split_command("code --wait"), ["code", "--wait"]);
+    assert_eq!(split_command("  vim\t-u  NONE "), ["vim", "-u", "NONE"]);
+    assert_eq!(split_command("nano"), ["nano"]);
+    assert!(split_command("   ").is_empty());
+}
+
+#[test]
+fn quotes_keep_words_together() {
+    assert_eq!(
+        split_command(r#""C:\Program Files\Notepad++\notepad++.exe" -multiInst"#),
+        [r"C:\Program Files\Notepad++\notepad++.exe", "-multiInst"]
+    );
+    assert_eq!(split_command("emacs --eval '(setq x 1)'"), ["emacs", "--eval", "(setq x 1)"]);
+    assert_eq!(split_command(r#"say "it's""#), ["say", "it's"]);
+    assert_eq!(split_command(r#"a "" b"#), ["a", "", "b"]);
+}
+
+#[test]
+fn plain_editors_run_directly() {
+    let command = editor_command("nano", Path::new("fact.md"));
+    assert_eq!(command.get_program(), "nano");
+    assert_eq!(command.get_args().collect::<Vec<_>>(), ["fact.md"]);
+}
+
+#[test]
+fn editor_paths_with_spaces_run_directly() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let editor = fixture.scratch().join("my editor");
+    std::fs::write(&editor, "").unwrap();
+    let command = editor_command(editor.to_str().unwrap(), Path::new("fact.md"));
+    assert_eq!(command.get_program(), editor.as_os_str());
+    assert_eq!(command.get_args().collect::<Vec<_>>(), ["fact.md"]);
+}
+
+#[cfg(not(windows))]
+#[test]
+fn editors_with_arguments_are_split() {
+    let command = editor_command("code --wait", Path::new("fact.md"));
+    assert_eq!(command.get_program(), "code");
+    assert_eq!(command.get_args().collect::<Vec<_>>(), ["--wait", "fact.md"]);
+}
+
+#[cfg(windows)]
+#[test]
+fn editors_with_arguments_go_through_cmd() {
+    use std::ffi::OsStr;
+    let command = editor_command("code --wait", Path::new("fact.md"));
+    assert_eq!(command.get_program(), "cmd");
+    assert_eq!(command.get_args().collect::<Vec<_>>(), [OsStr::new("/C"), OsStr::new("code --wait"), OsStr::new("fact.md")]);
+}
+
+#[test]
+fn local_wiki_path_is_shown_natively() {
+    let fixture = FixtureWiki::new().build().unwrap();
+    let switch = wk(&fixture).args(["switch", "--local", "fixture", "--create"]).assert().success();
+    let root = fixture.scratch().canonicalize().unwrap().join(".wiki");
+    let said = stderr(switch.get_output());
+    assert!(said.contains(&format!("Path: {}", root.display())), "{}", said);
+}
+
+fn written() -> String {
+    to_frontmatter("Ports", &["ops".to_string()], None, "port = 80")
+}
+
+#[test]
+fn saving_what_was_written_changes_nothing() {
+    assert_eq!(classify(&written(), &written(), true).unwrap(), EditOutcome::Unchanged);
+    assert_eq!(classify(&written(), &written().replace('\n', "\r\n"), true).unwrap(), EditOutcome::Unchanged);
+}
+
+#[test]
+fn changes_to_the_frontmatter_or_body_are_saved() {
+    let EditOutcome::Saved(edited) = classify(&written(), &written().replace("- ops", "- ops\n- net"), true).unwrap() else {
+        panic!("not saved");
+    };
+    assert_eq!((edited.tags.unwrap(), edited.body.as_str()), (vec!["ops".to_string(), "net".to_string()], "port = 80"));
+
+    let EditOutcome::Saved(edited) = classify(&written(), &written().replace("80", "8080"), true).unwrap() else {
+        panic!("not saved");
+    };
+    assert_eq!((edited.title.as_str(), edited.body.as_str()), ("Ports", "port = 8080"));
+}
+
+#[test]
+fn failed_or_emptied_edits_are_cancelled() {
+    assert_eq!(classify(&written(), &written().replace("80", "8080"), false).unwrap(), EditOutcome::Cancelled);
+    assert_eq!(classify(&written(), " \n", true).unwrap(), EditOutcome::Cancelled);
+    let broken = written().replace("title: Ports", "title: [Ports");
+    assert!(matches!(classify(&written(), &broken, true), Err(EditError::Frontmatter(_))));
+}
+
+#[cfg(unix)]
+#[test]
+fn the_editor_is_handed_a_temp_file() {
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+    use twk::editor::open_in_editor;
+
+    let fixture = FixtureWiki::new().facts(1).build().unwrap();
+    let fact = &fixture.facts()[0];
+    let outcome = open_in_editor(fact, |path| {
+        let text = std::fs::read_to_string(path)?;
+        std::fs::write(path, text.replace(&fact.data, "rewritten"))?;
+        Ok(ExitStatus::from_raw(0))
+    });
+    assert!(matches!(outcome, Ok(EditOutcome::Saved(edited)) if edited.body == "rewritten"));
+
+    let outcome = open_in_editor(fact, |_| Err(std::io::Error::from(std::io::ErrorKind::NotFound)));
+    assert!(matches!(outcome, Err(EditError::Launch(_))));
+}
@@ -0,0 +1,121 @@
+//! Encrypted wikis: what their storage will read, and `wk doctor` on them
+
+mod common;
+
+use common::{stderr, stdout, wk};
+use std::sync::Arc;
+use twk::encryption::unlock_with;
+use twk::fixture::{Fixture, FixtureWiki};
+use twk::storage::{FsStorage, Storage};
+use twk::wikis;
+
+const PASSPHRASE: &str = "correct horse";
+
+/// A fixture of `n` facts, encrypted with [`PASSPHRASE`]
+fn encrypted(n: usize) -> Fixture {
+    let fixture = FixtureWiki::new().facts(n).build().unwrap();
+    twk::set_data_dir(Some(fixture.data_dir()));
+    assert_eq!(wikis::encrypt(fixture.name(), PASSPHRASE, true).unwrap(), n);
+    fixture
+}
+
+fn sealed(fixture: &Fixture) -> FsStorage {
+    let path = fixture.path();
+    let cipher = unlock_with(&path, PASSPHRASE).unwrap();
+    FsStorage::encrypted(path, Arc::new(cipher))
+}
+
+#[test]
+fn plaintext_files_planted_in_an_encrypted_wiki_are_refused() {
+    let fixture = encrypted(2);
+    let planted = uuid::Uuid::new_v4();
+    let json = serde_json::json!({ "id": planted, "tags": [], "name": "planted", "data": "planted" });
+    let path = fixture.path().join(format!("{}.json", planted));
+    std::fs::write(&path, json.to_string()).unwrap();
+
+    let loaded = sealed(&fixture).load_all().unwrap();
+    assert_eq!(loaded.facts.len(), 2);
+    assert!(loaded.facts.iter().all(|info| info.id != planted));
+    assert_eq!(loaded.warnings.len(), 1);
+    assert_eq!(loaded.warnings[0].path, path);
+    assert!(loaded.warnings[0].error.contains("not encrypted"), "{}", loaded.warnings[0].error);
+    assert!(sealed(&fixture).read(planted).is_err());
+}
+
+#[test]
+fn doctor_reads_an_encrypted_wikis_files_with_its_key() {
+    let fixture = encrypted(1);
+    let id = fixture.facts()[0].id;
+    let misnamed = fixture.path().join("copy.json");
+    std::fs::copy(fixture.path().join(format!("{}.json", id)), &misnamed).unwrap();
+
+    let output = wk(&fixture).arg("doctor").env("TWK_PASSPHRASE", PASSPHRASE).output().unwrap();
+    let report = stdout(&output);
+    assert!(report.contains("Misnamed fact files"), "{}\n{}", report, stderr(&output));
+    assert!(report.contains("Duplicate ids") && report.contains(&id.to_string()), "{}", report);
+}
+
+/// Every file in the wiki directory with its contents, to tell whether
+/// anything was written
+fn contents(fixture: &Fixture) -> Vec<(std::path::PathBuf, Vec<u8>)> {
+    let mut files: Vec<_> = std::fs::read_dir(fixture.path())
+        .unwrap()
+        .flatten()
+        .filter(|entry| entry.path().is_file())
+        .map(|entry| (entry.path(), std::fs::read(entry.path()).unwrap()))
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn encrypting_and_decrypting_gives_back_the_same_facts() {
+    let fixture = encrypted(3);
+    let id = fixture.facts()[0].id;
+    let sealed_file = std::fs::read_to_string(fixture.path().join(format!("{}.json", id))).unwrap();
+    assert!(!sealed_file.contains(&fixture.facts()[0].data), "{}", sealed_file);
+
+    let mut reloaded = sealed(&fixture).load_all().unwrap().facts;
+    reloaded.sort_by_key(|info| info.id);
+    let mut expected = fixture.facts().to_vec();
+    expected.sort_by_key(|info| info.id);
+    assert_eq!(reloaded, expected);
+
+    assert_eq!(wikis::decrypt(fixture.name(), PASSPHRASE, true).unwrap(), 3);
+    assert!(!twk::encryption::is_encrypted(&fixture.path()));
+    let mut decrypted = fixture.open().unwrap().all();
+    decrypted.sort_by_key(|info| info.id);
+    assert_eq!(decrypted, expected);
+}
+
+#[test]
+fn a_wrong_passphrase_fails_before_anything_is_written() {
+    let fixture = encrypted(2);
+    let before = contents(&fixture);
+    assert!(wikis::decrypt(fixture.name(), "wrong", true).is_err());
+    assert!(wikis::encrypt(fixture.name(), "wrong", true).is_err());
+    assert!(unlock_with(&fixture.path(), "wrong").is_err());
+    assert_eq!(contents(&fixture), before);
+}
+
+#[test]
+fn encrypted_wikis_are_only_written_out_when_allowed() {
+    let fixture = encrypted(1);
+    // The book is written beside the wiki
+    let out = fixture.data_dir().join("book");
+    for args in [vec!["book"], vec!["export"]] {
+        let output = wk(&fixture).args(&args).env("TWK_PASSPHRASE", PASSPHRASE).output().unwrap();
+        assert!(!output.status.success(), "{:?}", args);
+        assert!(stderr(&output).contains("--allow-plaintext-output"), "{}", stderr(&output));
+    }
+    assert!(!out.exists());
+
+    let output = wk(&fixture)
+        .args(["export", "--allow-plaintext-output"])
+        .env("TWK_PASSPHRASE", PASSPHRASE)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    let exported: Vec<serde_json::Value> = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(exported[0]["data"], fixture.facts()[0].data.as_str());
+}
@@ -0,0 +1,83 @@
+//! `wk export` and incremental exports with `--since`
+
+mod common;
+
+use common::{Steps, stderr, stdout, wk};
+use std::path::Path;
+use std::time::SystemTime;
+use twk::fixture::FixtureWiki;
+
+fn modified(path: &Path) -> SystemTime {
+    std::fs::metadata(path).unwrap().modified().unwrap()
+}
+
+#[test]
+fn markdown_exports_only_touch_changed_files() {
+    let fixture = FixtureWiki::new().facts(3).build().unwrap();
+    let out = fixture.scratch().join("export");
+    let export = |since: Option<&str>| {
+        let mut cmd = wk(&fixture);
+        cmd.args(["export", "--format", "md", "--out"]).arg(&out);
+        if let Some(since) = since {
+            cmd.args(["--since", since]);
+        }
+        let output = cmd.output().unwrap();
+        assert!(output.status.success(), "{}", stderr(&output));
+        stderr(&output)
+    };
+
+    assert!(export(None).contains("exported 3 facts, 3 of them changed"));
+    let files: Vec<_> = fixture.facts().iter().map(|fact| out.join(format!("{}.md", fact.id))).collect();
+    let before: Vec<_> = files.iter().map(|file| modified(file)).collect();
+
+    let edited = fixture.facts()[1].id;
+    fixture.open().unwrap().update(edited, |info| info.data.push_str("\nedited")).unwrap();
+    assert!(export(Some("last")).contains("exported 1 facts, 1 of them changed"));
+
+    assert_eq!(modified(&files[0]), before[0]);
+    assert_eq!(modified(&files[2]), before[2]);
+    assert!(std::fs::read_to_string(&files[1]).unwrap().ends_with("\nedited"));
+
+    // Exporting everything again rewrites nothing
+    assert!(export(None).contains("exported 3 facts, 0 of them changed"));
+    assert_eq!(modified(&files[0]), before[0]);
+}
+
+#[test]
+fn every_fact_exported_is_reported_to_progress() {
+    let fixture = FixtureWiki::new().facts(3).build().unwrap();
+    let out = fixture.scratch().join("export");
+    let mut steps = Steps::default();
+    let written = twk::export::to_markdown(&out, &fixture.open().unwrap().all(), &mut steps).unwrap();
+    assert_eq!(written.len(), 3);
+    assert_eq!((steps.total, steps.ticks.len(), steps.finished), (Some(3), 3, true));
+
+    // Facts already up to date still count as steps
+    let mut steps = Steps::default();
+    assert!(twk::export::to_markdown(&out, &fixture.open().unwrap().all(), &mut steps).unwrap().is_empty());
+    assert_eq!(steps.ticks.len(), 3);
+}
+
+#[test]
+fn json_exports_filter_on_when_facts_changed() {
+    let fixture = FixtureWiki::new().facts(2).build().unwrap();
+    let output = wk(&fixture).args(["export"]).output().unwrap();
+    let all: Vec<serde_json::Value> = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(all.len(), 2);
+
+    // Nothing has changed since the export just made to stdout
+    let output = wk(&fixture).args(["export", "--since", "last"]).output().unwrap();
+    assert_eq!(stdout(&output).trim(), "[]");
+
+    // Each place and format keeps its own last export
+    let file = fixture.scratch().join("facts.json");
+    let output = wk(&fixture).args(["export", "--since", "last", "-o"]).arg(&file).output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    let written: Vec<serde_json::Value> = serde_json::from_slice(&std::fs::read(&file).unwrap()).unwrap();
+    assert_eq!(written.len(), 2);
+
+    let output = wk(&fixture).args(["export", "--since", "2d"]).output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    let output = wk(&fixture).args(["export", "--format", "md"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+}
@@ -0,0 +1,81 @@
+//! Fields of fact files that twk doesn't know, kept through its writes
+
+mod common;
+
+use common::{stdout, wk};
+use serde_json::{Value, json};
+use twk::fixture::{Fixture, FixtureWiki};
+use twk::wiki::Fields;
+use twk::window::TimeWindow;
+use uuid::Uuid;
+
+/// A fact file annotated by some other script
+fn annotated(fixture: &Fixture) -> (Uuid, Value) {
+    let id = Uuid::new_v4();
+    let file = json!({
+        "id": id,
+        "tags": ["places"],
+        "name": "Lighthouse",
+        "data": "Keeper's cottage by the lighthouse",
+        "created": "2024-05-01T09:30:00Z",
+        "updated": "2024-05-01T09:30:00Z",
+        "geo": { "lat": 51.5, "lon": -0.12, "label": "zanzibar" },
+        "reviewed_by": "ci",
+    });
+    std::fs::write(fixture.path().join(format!("{}.json", id)), serde_json::to_string_pretty(&file).unwrap()).unwrap();
+    (id, file)
+}
+
+fn read_file(fixture: &Fixture, id: Uuid) -> Value {
+    serde_json::from_str(&std::fs::read_to_string(fixture.path().join(format!("{}.json", id))).unwrap()).unwrap()
+}
+
+#[test]
+fn unknown_fields_survive_edits() {
+    let fixture = FixtureWiki::new().facts(2).build().unwrap();
+    let (id, mut file) = annotated(&fixture);
+
+    // Nothing but the time it was updated changes
+    let mut wiki = fixture.open().unwrap();
+    let after = wiki.update(id, |_| {}).unwrap();
+    file["updated"] = json!(after.updated);
+    assert_eq!(read_file(&fixture, id), file);
+
+    let mut wiki = fixture.open().unwrap();
+    wiki.retag(id, vec!["coast".to_string()]).unwrap();
+    wiki.update(id, |info| info.data = "Lighthouse keeper's cottage".to_string()).unwrap();
+    let written = read_file(&fixture, id);
+    assert_eq!((&written["geo"], &written["reviewed_by"]), (&file["geo"], &file["reviewed_by"]));
+    assert_eq!(written["tags"], json!(["coast"]));
+}
+
+#[test]
+fn recall_ignores_unknown_fields() {
+    let fixture = FixtureWiki::new().facts(2).build().unwrap();
+    annotated(&fixture);
+    let wiki = fixture.open().unwrap();
+    assert!(wiki.recall_refs("zanzibar", None, Fields::ALL, TimeWindow::ANY, None).is_empty());
+    assert_eq!(wiki.recall_refs("lighthouse", None, Fields::ALL, TimeWindow::ANY, None).len(), 1);
+}
+
+#[test]
+fn exports_gather_unknown_fields_under_extra() {
+    let fixture = FixtureWiki::new().facts(2).build().unwrap();
+    let (id, file) = annotated(&fixture);
+
+    let output = wk(&fixture).args(["show", &id.to_string(), "--json"]).output().unwrap();
+    let shown: Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(shown["extra"], json!({ "geo": file["geo"], "reviewed_by": "ci" }));
+    assert_eq!(shown["name"], "Lighthouse");
+    assert!(shown.get("geo").is_none());
+
+    let output = wk(&fixture).args(["r", "lighthouse", "--format", "json"]).output().unwrap();
+    let recalled: Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert_eq!(recalled[0]["extra"]["reviewed_by"], "ci");
+
+    // Facts without any have no `extra` key
+    let other = fixture.facts()[0].id;
+    let output = wk(&fixture).args(["show", &other.to_string(), "--json"]).output().unwrap();
+    let shown: Value = serde_json::from_str(&stdout(&output)).unwrap();
+    assert!(shown.get("extra").is_none());
+}
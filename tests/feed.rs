@@ -0,0 +1,92 @@
+//! The Atom feed of a book's newest facts
+
+use chrono::{DateTime, TimeZone, Utc};
+use twk::feed::{FEED_FILE, atom, newest};
+use twk::fixture::FixtureWiki;
+use twk::{BookOptions, Information};
+use uuid::Uuid;
+
+const ATOM: &str = "http://www.w3.org/2005/Atom";
+
+fn fact(name: &str, data: &str, day: u32) -> Information {
+    Information {
+        id: Uuid::new_v4(),
+        tags: Vec::new(),
+        name: name.to_string(),
+        data: data.to_string(),
+        created: Some(Utc.with_ymd_and_hms(2024, 3, day, 9, 0, 0).unwrap()),
+        updated: None,
+        source: None,
+        name_is_derived: false,
+        extra: Default::default(),
+    }
+}
+
+/// Check `xml` is well formed and holds what RFC 4287 requires of a feed
+/// and each of its entries, returning the entries' titles and links
+fn validate(xml: &str) -> Vec<(String, String)> {
+    let doc = roxmltree::Document::parse(xml).unwrap_or_else(|e| panic!("{}\n{}", e, xml));
+    let feed = doc.root_element();
+    assert_eq!((feed.tag_name().namespace(), feed.tag_name().name()), (Some(ATOM), "feed"));
+
+    let child = |node: roxmltree::Node<'_, '_>, name: &str| {
+        let found: Vec<_> = node.children().filter(|c| c.tag_name().name() == name).collect();
+        assert_eq!(found.len(), 1, "one <{}> in <{}>", name, node.tag_name().name());
+        found[0].text().unwrap_or_default().to_string()
+    };
+    let date = |text: String| DateTime::parse_from_rfc3339(&text).unwrap_or_else(|e| panic!("{}: {}", text, e));
+    for name in ["id", "title"] {
+        assert!(!child(feed, name).is_empty());
+    }
+    date(child(feed, "updated"));
+    assert!(feed.children().any(|c| c.tag_name().name() == "author"));
+
+    feed.children()
+        .filter(|c| c.tag_name().name() == "entry")
+        .map(|entry| {
+            assert!(child(entry, "id").starts_with("urn:uuid:"));
+            assert!(date(child(entry, "published")) <= date(child(entry, "updated")));
+            let link = entry.children().find(|c| c.tag_name().name() == "link").unwrap();
+            (child(entry, "title"), link.attribute("href").unwrap().to_string())
+        })
+        .collect()
+}
+
+#[test]
+fn feeds_are_valid_atom_with_text_escaped() {
+    let tricky = fact("Q&A <draft> \"quotes\"", "# Q&A\n\nAsk <b>anything</b> & more.\u{1}\n\nLater paragraph.", 2);
+    let plain = fact("plain", "just text", 1);
+    let now = Utc.with_ymd_and_hms(2024, 4, 1, 0, 0, 0).unwrap();
+    let xml = atom("notes & <co> Wiki", "https://wiki.example.com/", &[&tricky, &plain], now);
+
+    let entries = validate(&xml);
+    assert_eq!(entries[0], ("Q&A <draft> \"quotes\"".to_string(), format!("https://wiki.example.com/{}.html", tricky.id)));
+    assert!(xml.contains("<summary type=\"text\">Ask &lt;b&gt;anything&lt;/b&gt; &amp; more.</summary>"), "{}", xml);
+    assert!(xml.contains("<updated>2024-03-02T09:00:00Z</updated>"), "{}", xml);
+
+    // An empty feed is still valid, dated now
+    validate(&atom("empty", "https://wiki.example.com", &[], now));
+}
+
+#[test]
+fn the_newest_facts_come_first() {
+    let mut facts: Vec<Information> = (1..=5).map(|day| fact(&format!("day {}", day), "text", day)).collect();
+    facts.push(Information { created: None, ..fact("undated", "text", 1) });
+    let names: Vec<&str> = newest(&facts, 3).iter().map(|info| info.name.as_str()).collect();
+    assert_eq!(names, ["day 5", "day 4", "day 3"]);
+}
+
+#[test]
+fn the_feed_needs_somewhere_to_link_to() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let wiki = fixture.open().unwrap();
+    let options = BookOptions::default();
+    assert_eq!(wiki.book_url(&options), None);
+
+    std::fs::write(fixture.path().join("config.toml"), "book_url = \"https://wiki.example.com/\"\n").unwrap();
+    let wiki = fixture.open().unwrap();
+    assert_eq!(wiki.book_url(&options), Some("https://wiki.example.com/"));
+    let options = BookOptions { base_url: Some("https://elsewhere.example.com/".to_string()), ..options };
+    assert_eq!(wiki.book_url(&options), Some("https://elsewhere.example.com/"));
+    assert_eq!(FEED_FILE, "atom.xml");
+}
@@ -0,0 +1,93 @@
+//! The canonical format of the wiki's JSON files, and `wk fmt`
+
+mod common;
+
+use common::{stderr, wk};
+use std::path::{Path, PathBuf};
+use twk::fixture::{Fixture, FixtureWiki};
+use twk::{Information, Snapshot};
+
+fn fact_path(fixture: &Fixture, info: &Information) -> PathBuf {
+    fixture.path().join(format!("{}.json", info.id))
+}
+
+/// Every `.json` file under `dir`, with what it holds
+fn json_files(dir: &Path) -> Vec<(PathBuf, Vec<u8>)> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir).unwrap().flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            files.extend(json_files(&path));
+        } else if path.extension().is_some_and(|ext| ext == "json") {
+            files.push((path.clone(), std::fs::read(&path).unwrap()));
+        }
+    }
+    files.sort();
+    files
+}
+
+#[test]
+fn keys_come_in_a_fixed_order() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let mut wiki = fixture.open().unwrap();
+    let id = wiki
+        .commit_named("Ports".to_string(), "port = 80".to_string(), vec!["ops".to_string()], Some("notes".to_string()))
+        .unwrap();
+    wiki.update(id, |info| {
+        info.extra.insert("zone".to_string(), "eu".into());
+        info.extra.insert("author".to_string(), serde_json::json!({ "name": "sam", "id": 7 }));
+    })
+    .unwrap();
+
+    let file = std::fs::read_to_string(fact_path(&fixture, &wiki.get(id).unwrap())).unwrap();
+    let keys: Vec<&str> = file
+        .lines()
+        .filter_map(|line| line.strip_prefix("  \"")?.split('"').next())
+        .collect();
+    assert_eq!(keys, ["id", "name", "tags", "created", "updated", "author", "data", "name_is_derived", "source", "zone"]);
+    // Objects nested in a fact follow the same order
+    assert!(file.contains("\"author\": {\n    \"id\": 7,\n    \"name\": \"sam\"\n  },"), "{}", file);
+    assert!(file.contains("\"tags\": [\n    \"ops\"\n  ],"), "{}", file);
+    assert!(file.ends_with("}\n"));
+}
+
+#[test]
+fn saving_a_canonical_file_changes_nothing() {
+    let fixture = FixtureWiki::new().facts(3).build().unwrap();
+    let before = json_files(&fixture.path());
+    assert_eq!(before.len(), 3);
+
+    let wiki = fixture.open().unwrap();
+    for locked in &wiki.info {
+        locked.commit().unwrap();
+    }
+    assert_eq!(wiki.reformat().unwrap(), 0);
+    assert_eq!(json_files(&fixture.path()), before);
+}
+
+#[test]
+fn fmt_rewrites_files_in_the_old_format() {
+    let fixture = FixtureWiki::new().facts(3).build().unwrap();
+    let mut wiki = fixture.open().unwrap();
+    wiki.snapshot(fixture.facts()[0].id, Some("before")).unwrap();
+    wiki.delete(fixture.facts()[1].id, false).unwrap();
+    let canonical = json_files(&fixture.path());
+
+    // As written before there was a canonical format
+    for (path, bytes) in &canonical {
+        let old = match path.parent().and_then(Path::parent).is_some_and(|dir| dir.ends_with(".snapshots")) {
+            true => serde_json::to_vec_pretty(&serde_json::from_slice::<Snapshot>(bytes).unwrap()),
+            false => serde_json::to_vec_pretty(&serde_json::from_slice::<Information>(bytes).unwrap()),
+        };
+        std::fs::write(path, old.unwrap()).unwrap();
+    }
+
+    let output = wk(&fixture).arg("fmt").output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    // Two facts, one in the trash and a snapshot
+    assert!(stderr(&output).contains("Formatted 4 files"), "{}", stderr(&output));
+    assert_eq!(json_files(&fixture.path()), canonical);
+
+    let output = wk(&fixture).arg("fmt").output().unwrap();
+    assert!(stderr(&output).contains("already formatted"), "{}", stderr(&output));
+}
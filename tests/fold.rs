@@ -0,0 +1,30 @@
+//! Folding diacritics away and mapping positions back through it
+
+use twk::fold::{FoldMap, fold};
+
+#[test]
+fn diacritics_and_compatibility_forms_fold_away() {
+    assert_eq!(fold("café résumé"), "cafe resume");
+    assert_eq!(fold("cafe\u{301}"), "cafe");
+    assert_eq!(fold("ﬁle"), "file");
+    assert_eq!(fold("Ærøskøbing"), "Ærøskøbing", "letters that aren't decomposable stay");
+    assert!(matches!(fold("plain"), std::borrow::Cow::Borrowed(_)));
+}
+
+#[test]
+fn positions_map_back_by_grapheme() {
+    // Precomposed and decomposed accents are one grapheme either way
+    let (folded, map) = FoldMap::new("cafe\u{301} crème");
+    assert_eq!(folded, "cafe creme");
+    assert_eq!(map.original(&[3, 7]), [3, 7]);
+
+    // A ligature folds to two graphemes, both pointing at it
+    let (folded, map) = FoldMap::new("ﬁne");
+    assert_eq!(folded, "fine");
+    assert_eq!(map.original(&[0, 1, 2]), [0, 1]);
+
+    // Emoji sequences stay whole and keep the positions after them in step
+    let (folded, map) = FoldMap::new("👩‍💻 é");
+    assert_eq!(folded, "👩‍💻 e");
+    assert_eq!(map.original(&[2, 9]), [2]);
+}
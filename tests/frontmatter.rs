@@ -0,0 +1,89 @@
+//! Facts written out with frontmatter for an editor and read back
+
+use proptest::prelude::*;
+use twk::editor::{parse_frontmatter, to_frontmatter};
+
+/// Lines likely to confuse a parser: delimiters, YAML, blanks and anything at all
+fn line() -> impl Strategy<Value = String> {
+    prop_oneof![
+        Just("---".to_string()),
+        Just("----".to_string()),
+        Just("title: not the title".to_string()),
+        Just("tags: [a, b]".to_string()),
+        Just("...".to_string()),
+        Just(String::new()),
+        "[^\r\n]*",
+        any::<String>(),
+    ]
+}
+
+fn body() -> impl Strategy<Value = String> {
+    prop::collection::vec(line(), 0..6).prop_map(|lines| lines.join("\n"))
+}
+
+fn tags() -> impl Strategy<Value = Vec<String>> {
+    prop::collection::vec(any::<String>(), 0..4)
+}
+
+/// Sources are trimmed when read back, so only trimmed ones round-trip
+fn source() -> impl Strategy<Value = Option<String>> {
+    prop::option::of(any::<String>().prop_map(|s| s.trim().to_string()).prop_filter("non-empty", |s| !s.is_empty()))
+}
+
+proptest! {
+    #[test]
+    fn round_trips(title in any::<String>(), tags in tags(), source in source(), body in body()) {
+        let edited = parse_frontmatter(&to_frontmatter(&title, &tags, source.as_deref(), &body));
+        prop_assert_eq!(edited.title, title);
+        prop_assert_eq!(edited.tags, Some(tags));
+        prop_assert_eq!(edited.source, Some(source));
+        prop_assert_eq!(edited.body, body);
+    }
+
+    #[test]
+    fn round_trips_saved_with_crlf(title in "[^\r\n]*", tags in tags(), lines in prop::collection::vec(line(), 0..6)) {
+        let lines: Vec<String> = lines.into_iter().map(|l| l.replace(['\r', '\n'], "")).collect();
+        let body = lines.join("\n");
+        let saved = to_frontmatter(&title, &tags, None, &body).replace('\n', "\r\n");
+        let edited = parse_frontmatter(&saved);
+        prop_assert_eq!(edited.title, title);
+        prop_assert_eq!(edited.tags, Some(tags));
+        prop_assert_eq!(edited.body, lines.join("\r\n"));
+    }
+}
+
+#[test]
+fn body_starting_with_a_delimiter() {
+    let edited = parse_frontmatter(&to_frontmatter("t", &[], None, "---\nnot: yaml\n---\nstill body"));
+    assert_eq!(edited.title, "t");
+    assert_eq!(edited.body, "---\nnot: yaml\n---\nstill body");
+}
+
+#[test]
+fn title_with_a_delimiter() {
+    let edited = parse_frontmatter(&to_frontmatter("a --- b\n---", &["x".to_string()], None, "body"));
+    assert_eq!(edited.title, "a --- b\n---");
+    assert_eq!(edited.body, "body");
+}
+
+#[test]
+fn empty_tag_list() {
+    let edited = parse_frontmatter("---\ntitle: t\ntags: []\n---\n\nbody");
+    assert_eq!(edited.tags, Some(Vec::new()));
+    assert_eq!(edited.body, "body");
+}
+
+#[test]
+fn unclosed_frontmatter_is_all_body() {
+    let edited = parse_frontmatter("---\ntitle: t\nbody");
+    assert_eq!(edited.title, "");
+    assert_eq!(edited.body, "---\ntitle: t\nbody");
+}
+
+#[test]
+fn plain_text_falls_back_to_a_first_line_title() {
+    let edited = parse_frontmatter("A title\r\n---\r\nthe body\r\nmore");
+    assert_eq!(edited.title, "A title");
+    assert_eq!(edited.tags, None);
+    assert_eq!(edited.body, "the body\nmore");
+}
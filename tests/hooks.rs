@@ -0,0 +1,92 @@
+//! Commit hooks, run from shell scripts named in the global config
+#![cfg(unix)]
+
+mod common;
+
+use common::{stderr, stdout, wk};
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use twk::fixture::{Fixture, FixtureWiki};
+
+/// The fixture's global config directory
+fn config_dir(fixture: &Fixture) -> PathBuf {
+    fixture.scratch().join("home").join(".config").join("twk")
+}
+
+/// An executable script named `name` in the global config directory
+fn script(fixture: &Fixture, name: &str, body: &str) {
+    let dir = config_dir(fixture);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    std::fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+fn global_config(fixture: &Fixture, toml: &str) {
+    let dir = config_dir(fixture);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("config.toml"), toml).unwrap();
+}
+
+#[test]
+fn a_failing_pre_commit_hook_refuses_the_commit() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    script(&fixture, "pre-commit", "if grep -q forbidden; then echo 'no forbidden facts' >&2; exit 1; fi");
+    global_config(&fixture, "[hooks]\npre_commit = \"pre-commit\"\n");
+
+    let output = wk(&fixture).args(["c", "a forbidden fact"]).output().unwrap();
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("no forbidden facts"), "{}", stderr(&output));
+    assert!(fixture.open().unwrap().all().is_empty());
+
+    let output = wk(&fixture).args(["c", "an allowed fact"]).output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    let output = wk(&fixture).args(["c", "forbidden, but unchecked", "--no-hooks"]).output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(fixture.open().unwrap().all().len(), 2);
+}
+
+#[test]
+fn post_commit_hooks_get_the_fact_and_wiki() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let seen = fixture.scratch().join("seen");
+    script(&fixture, "post-commit", &format!("echo \"$TWK_WIKI\" > '{0}'; cat >> '{0}'", seen.display()));
+    global_config(&fixture, "[hooks]\npost_commit = \"post-commit\"\n");
+
+    let output = wk(&fixture).args(["c", "hooked fact", "tagged"]).output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    let seen = std::fs::read_to_string(&seen).unwrap();
+    let (wiki, json) = seen.split_once('\n').unwrap();
+    let fact: serde_json::Value = serde_json::from_str(json).unwrap();
+    assert_eq!(fact["data"], "hooked fact");
+    assert_eq!(fact["tags"][0], "tagged");
+    assert_eq!(wiki, fixture.name());
+}
+
+#[test]
+fn hooks_that_run_too_long_are_stopped() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    script(&fixture, "pre-commit", "sleep 5");
+    global_config(&fixture, "[hooks]\npre_commit = \"pre-commit\"\ntimeout = 1\n");
+
+    let output = wk(&fixture).args(["c", "slow"]).output().unwrap();
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("timed out after 1s"), "{}", stderr(&output));
+}
+
+#[test]
+fn hooks_in_a_wikis_own_config_never_run() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let ran = fixture.scratch().join("ran");
+    let planted = fixture.path().join("planted.sh");
+    std::fs::write(&planted, format!("#!/bin/sh\ntouch '{}'\n", ran.display())).unwrap();
+    std::fs::set_permissions(&planted, std::fs::Permissions::from_mode(0o755)).unwrap();
+    std::fs::write(fixture.path().join("config.toml"), "[hooks]\npre_commit = \"planted.sh\"\n").unwrap();
+
+    let output = wk(&fixture).args(["c", "a fact"]).output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!ran.exists());
+
+    let output = wk(&fixture).args(["doctor"]).output().unwrap();
+    assert!(stdout(&output).contains("only read from the global config"), "{}", stdout(&output));
+}
@@ -0,0 +1,120 @@
+//! Importing TiddlyWiki tiddlers
+
+mod common;
+
+use chrono::{TimeZone, Utc};
+use common::{Steps, stderr, wk};
+use twk::NoProgress;
+use twk::fixture::FixtureWiki;
+use twk::import::{ImportReport, parse_tiddlers, parse_timestamp, split_tags};
+
+fn fixture_file(name: &str) -> String {
+    format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+#[test]
+fn tags_split_like_tiddlywiki() {
+    assert_eq!(split_tags("rust [[systems programming]] notes"), ["rust", "systems programming", "notes"]);
+    assert_eq!(split_tags("  two  spaces\tand\ttabs\n"), ["two", "spaces", "and", "tabs"]);
+    assert!(split_tags("").is_empty());
+    // Brackets only group when closed before a space or the end
+    assert_eq!(split_tags("[[a]]b c"), ["[[a]]b", "c"]);
+    assert_eq!(split_tags("[[a]]b c]]"), ["a]]b c"]);
+    assert_eq!(split_tags("[[unclosed one"), ["[[unclosed", "one"]);
+    assert_eq!(split_tags("[[one]][[two]]"), ["one]][[two"]);
+    // Empty and repeated tags are dropped
+    assert_eq!(split_tags("[[]] x [[ x ]] x"), ["x"]);
+    // A non-breaking space is part of a tag
+    assert_eq!(split_tags("new\u{a0}york city"), ["new\u{a0}york", "city"]);
+}
+
+#[test]
+fn timestamps_are_utc_to_the_millisecond() {
+    let full = Utc.with_ymd_and_hms(2019, 3, 12, 9, 45, 12).unwrap() + chrono::TimeDelta::milliseconds(345);
+    assert_eq!(parse_timestamp("20190312094512345"), Some(full));
+    assert_eq!(parse_timestamp("200501021530"), Some(Utc.with_ymd_and_hms(2005, 1, 2, 15, 30, 0).unwrap()));
+    assert_eq!(parse_timestamp("20050102"), Some(Utc.with_ymd_and_hms(2005, 1, 2, 0, 0, 0).unwrap()));
+    for bad in ["", "2005", "not a date", "20051302", "2019031209451234567"] {
+        assert_eq!(parse_timestamp(bad), None, "{}", bad);
+    }
+}
+
+#[test]
+fn html_wikis_are_read_from_every_store() {
+    let html = std::fs::read_to_string(fixture_file("tiddlywiki.html")).unwrap();
+    let tiddlers = parse_tiddlers(&html).unwrap();
+    let titles: Vec<_> = tiddlers.iter().map(|t| t.title.as_str()).collect();
+    assert_eq!(titles, ["$:/core", "Markup", "Second store"]);
+    assert_eq!(tiddlers[1].text, "Tags like <b>bold</b> come escaped");
+    assert_eq!(tiddlers[2].tags, ["more notes"]);
+
+    assert!(parse_tiddlers("<html><div id=\"storeArea\"></div></html>").is_err());
+    assert!(parse_tiddlers("title: not json").is_err());
+}
+
+#[test]
+fn importing_twice_changes_nothing() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let mut wiki = fixture.open().unwrap();
+    let json = std::fs::read_to_string(fixture_file("tiddlers.json")).unwrap();
+
+    let report = wiki.import_tiddlers(parse_tiddlers(&json).unwrap(), &mut NoProgress).unwrap();
+    assert_eq!(report, ImportReport { created: 4, system: 2, untitled: 1, ..Default::default() });
+    let facts = wiki.all();
+    let rust = facts.iter().find(|f| f.name == "Rust ownership").unwrap();
+    assert_eq!(rust.tags, ["rust", "systems programming", "notes"]);
+    assert!(rust.data.starts_with("Each value has one owner."));
+    assert_eq!(rust.created, Some(Utc.with_ymd_and_hms(2019, 3, 12, 9, 45, 12).unwrap() + chrono::TimeDelta::milliseconds(345)));
+    assert_eq!(rust.updated, Some(Utc.with_ymd_and_hms(2023, 1, 5, 18, 0, 0).unwrap()));
+    let old = facts.iter().find(|f| f.name == "Old timestamp").unwrap();
+    assert_eq!((old.created, old.updated), (Some(Utc.with_ymd_and_hms(2005, 1, 2, 15, 30, 0).unwrap()), old.created));
+    let twk = facts.iter().find(|f| f.name == "Came from twk").unwrap();
+    assert_eq!(twk.id.to_string(), "7c1d6a8e-0f3b-4d5a-9a63-2b8e4f1c9d20");
+
+    let again = wiki.import_tiddlers(parse_tiddlers(&json).unwrap(), &mut NoProgress).unwrap();
+    assert_eq!(again, ImportReport { unchanged: 4, system: 2, untitled: 1, ..Default::default() });
+    assert_eq!(wiki.all().len(), 4);
+
+    // Edits come through to the same facts, matched by title or id
+    let edited = json.replace("Each value has one owner.", "Every value has an owner.").replace("Came from twk", "Renamed in twk");
+    let report = wiki.import_tiddlers(parse_tiddlers(&edited).unwrap(), &mut NoProgress).unwrap();
+    assert_eq!((report.updated, report.unchanged, report.created), (2, 2, 0));
+    assert_eq!(wiki.get(rust.id).unwrap().data.lines().next(), Some("Every value has an owner."));
+    assert_eq!(wiki.get(twk.id).unwrap().name, "Renamed in twk");
+}
+
+#[test]
+fn every_tiddler_is_reported_to_progress() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let json = std::fs::read_to_string(fixture_file("tiddlers.json")).unwrap();
+    let mut steps = Steps::default();
+    fixture.open().unwrap().import_tiddlers(parse_tiddlers(&json).unwrap(), &mut steps).unwrap();
+    assert_eq!(steps.total, Some(7));
+    assert_eq!(steps.ticks.len(), 7);
+    assert!(steps.ticks.iter().any(|title| title == "Rust ownership"), "{:?}", steps.ticks);
+    assert!(steps.finished);
+
+    // Finished even when the import is refused
+    let mut steps = Steps::default();
+    let mut wiki = fixture.open().unwrap();
+    wiki.readonly = true;
+    assert!(wiki.import_tiddlers(parse_tiddlers(&json).unwrap(), &mut steps).is_err());
+    assert!(steps.finished && steps.ticks.is_empty());
+}
+
+#[test]
+fn import_reports_what_it_skipped() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let output = wk(&fixture)
+        .args(["import", "--format", "tiddlers", &fixture_file("tiddlers.json")])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    let said = stderr(&output);
+    assert!(said.contains("New: 4"), "{}", said);
+    assert!(said.contains("2 system tiddlers"), "{}", said);
+    assert!(said.contains("1 tiddlers without a title"), "{}", said);
+
+    let missing = wk(&fixture).args(["import", "--format", "tiddlers", "nowhere.json"]).output().unwrap();
+    assert!(!missing.status.success());
+}
@@ -0,0 +1,56 @@
+//! Loading a large wiki directory on a bounded pool of threads. Alone in its
+//! own test binary, so no other test's threads are counted.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use twk::storage::{FsStorage, Storage};
+use uuid::Uuid;
+
+const FILES: usize = 5000;
+
+/// Threads alive in this process, where `/proc` tells
+fn threads() -> Option<usize> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("Threads:"))?;
+    line["Threads:".len()..].trim().parse().ok()
+}
+
+#[test]
+fn thousands_of_files_load_in_order_on_a_few_threads() {
+    let dir = tempfile::tempdir().unwrap();
+    for n in 0..FILES {
+        let id = Uuid::from_u128(n as u128 * 7919 % FILES as u128);
+        let json = serde_json::json!({ "id": id, "tags": ["bulk"], "name": format!("Fact {}", n), "data": "small" });
+        std::fs::write(dir.path().join(format!("{}.json", id)), json.to_string()).unwrap();
+    }
+    std::fs::write(dir.path().join("broken.json"), "{ not json").unwrap();
+    let storage = FsStorage::open(dir.path()).unwrap();
+
+    let before = threads();
+    let loading = AtomicBool::new(true);
+    let (first, peak) = std::thread::scope(|s| {
+        let watcher = s.spawn(|| {
+            let mut peak = threads();
+            while loading.load(Ordering::Acquire) {
+                peak = peak.max(threads());
+            }
+            peak
+        });
+        let loaded = storage.load_all().unwrap();
+        loading.store(false, Ordering::Release);
+        (loaded, watcher.join().unwrap())
+    });
+
+    assert_eq!(first.facts.len(), FILES);
+    assert_eq!(first.warnings.len(), 1);
+    assert!(first.warnings[0].path.ends_with("broken.json"));
+    // In file name order, which for these is id order
+    assert!(first.facts.windows(2).all(|pair| pair[0].id < pair[1].id));
+    let again = storage.load_all().unwrap();
+    assert!(first.facts.iter().map(|info| info.id).eq(again.facts.iter().map(|info| info.id)));
+
+    // The watcher and one worker per core, on top of what ran before
+    if let (Some(before), Some(peak)) = (before, peak) {
+        let cores = std::thread::available_parallelism().unwrap().get();
+        assert!(peak <= before + 1 + cores, "{} threads at peak, {} before, {} cores", peak, before, cores);
+    }
+}
@@ -0,0 +1,223 @@
+//! `Locked` values mirrored to JSON files
+
+use chrono::DateTime;
+use proptest::prelude::*;
+use twk::fixture::FixtureWiki;
+use std::time::Duration;
+use twk::helpers::{FileLock, Locked, lock_path_for};
+use twk::Information;
+use uuid::Uuid;
+
+#[test]
+fn round_trips_through_its_file() {
+    let fixture = FixtureWiki::new().facts(1).build().unwrap();
+    let fact = fixture.facts()[0].clone();
+    let path = fixture.scratch().join("fact.json");
+
+    let locked = Locked::new(&path, fact.clone()).unwrap();
+    assert_eq!(*locked.read(), fact);
+    let loaded: Locked<Information> = Locked::load(&path).unwrap();
+    assert_eq!(*loaded.read(), fact);
+}
+
+#[test]
+fn saved_writes_reach_the_file() {
+    let fixture = FixtureWiki::new().facts(1).build().unwrap();
+    let path = fixture.path().join(format!("{}.json", fixture.facts()[0].id));
+
+    let locked: Locked<Information> = Locked::load(&path).unwrap();
+    let mut key = locked.write();
+    key.tags.push("edited".to_string());
+    key.save().unwrap();
+
+    let reloaded: Locked<Information> = Locked::load(&path).unwrap();
+    assert_eq!(reloaded.read().tags, ["tag0", "edited"]);
+    assert!(locked.last_error().is_none());
+}
+
+#[test]
+fn dropped_writes_are_saved_too() {
+    let fixture = FixtureWiki::new().facts(1).build().unwrap();
+    let path = fixture.scratch().join("fact.json");
+    let locked = Locked::new(&path, fixture.facts()[0].clone()).unwrap();
+
+    locked.write().data = "changed without saving".to_string();
+    let reloaded: Locked<Information> = Locked::load(&path).unwrap();
+    assert_eq!(reloaded.read().data, "changed without saving");
+}
+
+#[test]
+fn in_memory_locks_write_nothing() {
+    let fixture = FixtureWiki::new().facts(1).build().unwrap();
+    let locked = Locked::in_memory(fixture.facts()[0].clone());
+    locked.write().data = "only in memory".to_string();
+    locked.commit().unwrap();
+    assert_eq!(locked.read().data, "only in memory");
+    assert!(locked.try_write().is_some());
+}
+
+#[test]
+fn readers_block_writers() {
+    let locked = Locked::in_memory(1);
+    let reader = locked.read();
+    assert!(locked.try_write().is_none());
+    assert!(locked.try_read().is_some());
+    drop(reader);
+    assert!(locked.try_write().is_some());
+}
+
+#[test]
+fn saves_from_many_threads_all_land() {
+    const THREADS: u64 = 8;
+    const WRITES: u64 = 25;
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("counter.json");
+    let locked = Locked::new(&path, 0u64).unwrap();
+
+    std::thread::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                for _ in 0..WRITES {
+                    let mut key = locked.write();
+                    *key += 1;
+                    key.save().unwrap();
+                }
+            });
+        }
+        // Readers only ever see the count go up
+        s.spawn(|| {
+            let mut seen = 0;
+            while seen < THREADS * WRITES {
+                let now = *locked.read();
+                assert!(now >= seen, "{} after {}", now, seen);
+                seen = now;
+            }
+        });
+    });
+
+    assert_eq!(*locked.read(), THREADS * WRITES);
+    assert_eq!(*Locked::<u64>::load(&path).unwrap().read(), THREADS * WRITES);
+    assert!(locked.last_error().is_none());
+}
+
+#[test]
+fn two_locks_on_one_file_take_turns() {
+    const WRITES: usize = 40;
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("shared.json");
+    Locked::new(&path, Vec::<String>::new()).unwrap();
+    let (first, second) = (Locked::<Vec<String>>::load(&path).unwrap(), Locked::<Vec<String>>::load(&path).unwrap());
+    let done = std::sync::atomic::AtomicBool::new(false);
+
+    // Each writes its own long value, so a torn or interleaved file wouldn't parse
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            while !done.load(std::sync::atomic::Ordering::Acquire) {
+                let read = Locked::<Vec<String>>::load(&path).unwrap();
+                assert!(read.read().windows(2).all(|pair| pair[0] == pair[1]));
+            }
+        });
+        let writers: Vec<_> = [(&first, "first"), (&second, "second")]
+            .into_iter()
+            .map(|(locked, word)| {
+                s.spawn(move || {
+                    for n in 0..WRITES {
+                        let mut key = locked.write();
+                        *key = vec![format!("{} {}", word, n); 200];
+                        key.save().unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        done.store(true, std::sync::atomic::Ordering::Release);
+    });
+
+    let last = Locked::<Vec<String>>::load(&path).unwrap().read().clone();
+    assert!(last == *first.read() || last == *second.read());
+    assert!(lock_path_for(&path).exists());
+    let names: Vec<_> = std::fs::read_dir(dir.path()).unwrap().flatten().map(|entry| entry.file_name()).collect();
+    assert_eq!(names.len(), 2, "{:?}", names);
+}
+
+#[test]
+fn file_locks_exclude_writers_and_share_readers() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = lock_path_for(&dir.path().join("fact.json"));
+    assert_eq!(path, dir.path().join(".locks").join("fact.json.lock"));
+    let moment = Duration::from_millis(50);
+
+    let writer = FileLock::exclusive(&path, moment).unwrap();
+    let refused = FileLock::exclusive(&path, moment).unwrap_err();
+    assert_eq!(refused.kind(), std::io::ErrorKind::TimedOut);
+    assert!(FileLock::shared(&path, moment).is_err());
+    drop(writer);
+
+    let readers = [FileLock::shared(&path, moment).unwrap(), FileLock::shared(&path, moment).unwrap()];
+    assert!(FileLock::exclusive(&path, moment).is_err());
+    drop(readers);
+
+    // A writer that lets go in time is waited for
+    let writer = FileLock::exclusive(&path, moment).unwrap();
+    let release = std::thread::spawn(move || {
+        std::thread::sleep(moment);
+        drop(writer);
+    });
+    FileLock::exclusive(&path, Duration::from_secs(5)).unwrap();
+    release.join().unwrap();
+}
+
+#[test]
+fn loading_bad_json_fails() {
+    let fixture = FixtureWiki::new().facts(0).corrupted(1).build().unwrap();
+    assert!(Locked::<Information>::load(&fixture.corrupted()[0]).is_err());
+}
+
+/// Any time from 1970 to 2200, to the nanosecond
+fn time() -> impl Strategy<Value = Option<chrono::DateTime<chrono::Utc>>> {
+    prop::option::of((0i64..7_258_118_400, 0u32..1_000_000_000).prop_map(|(secs, nanos)| DateTime::from_timestamp(secs, nanos)))
+        .prop_map(Option::flatten)
+}
+
+/// Fields other scripts might add to a fact file, named so as not to clash with twk's
+fn extra() -> impl Strategy<Value = serde_json::Map<String, serde_json::Value>> {
+    prop::collection::btree_map("x-[a-z]{1,8}", any::<String>(), 0..3)
+        .prop_map(|fields| fields.into_iter().map(|(key, value)| (key, value.into())).collect())
+}
+
+/// Facts with any text at all in them, some with data of a megabyte or so
+fn information() -> impl Strategy<Value = Information> {
+    let data = prop_oneof![any::<String>(), (any::<String>(), 0..20_000usize).prop_map(|(chunk, n)| chunk.repeat(n))];
+    (any::<u128>(), prop::collection::vec(any::<String>(), 0..4), any::<String>(), data, time(), time(), any::<Option<String>>(), any::<bool>(), extra())
+        .prop_map(|(id, tags, name, data, created, updated, source, name_is_derived, extra)| Information {
+            id: Uuid::from_u128(id),
+            tags,
+            name,
+            data,
+            created,
+            updated,
+            source,
+            name_is_derived,
+            extra,
+        })
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn any_fact_round_trips(info in information()) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fact.json");
+        Locked::new(&path, info.clone()).unwrap();
+        let loaded: Locked<Information> = Locked::load(&path).unwrap();
+        prop_assert_eq!(&*loaded.read(), &info);
+
+        // Saving what was read writes the same bytes
+        let written = std::fs::read(&path).unwrap();
+        loaded.commit().unwrap();
+        prop_assert_eq!(std::fs::read(&path).unwrap(), written);
+    }
+}
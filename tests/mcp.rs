@@ -0,0 +1,104 @@
+//! `wk mcp` driven over stdin and stdout as an MCP client would
+
+mod common;
+
+use common::{stderr, stdout, wk};
+use serde_json::{Value, json};
+use twk::fixture::{Fixture, FixtureWiki};
+
+/// Send each of `requests` as a line to `wk mcp` and return every line it
+/// answered with
+fn session(fixture: &Fixture, requests: &[Value]) -> Vec<Value> {
+    session_raw(fixture, &requests.iter().map(Value::to_string).collect::<Vec<_>>())
+}
+
+fn session_raw(fixture: &Fixture, lines: &[String]) -> Vec<Value> {
+    let output = wk(fixture).arg("mcp").write_stdin(lines.join("\n") + "\n").output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    stdout(&output).lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+}
+
+fn request(id: u64, method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+}
+
+fn call(id: u64, tool: &str, arguments: Value) -> Value {
+    request(id, "tools/call", json!({ "name": tool, "arguments": arguments }))
+}
+
+/// What a tool call returned, from its structured content
+fn result(reply: &Value) -> &Value {
+    &reply["result"]["structuredContent"]["result"]
+}
+
+#[test]
+fn a_client_can_commit_and_recall_facts() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let replies = session(
+        &fixture,
+        &[
+            request(1, "initialize", json!({ "protocolVersion": "2025-03-26", "capabilities": {} })),
+            json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }),
+            request(2, "tools/list", json!({})),
+            call(3, "commit_fact", json!({ "text": "The boiler code is 4471", "tags": ["home"] })),
+            call(4, "recall", json!({ "query": "boiler" })),
+            call(5, "list_tags", json!({})),
+        ],
+    );
+    // Every request answered in order, the notification not at all
+    let ids: Vec<&Value> = replies.iter().map(|reply| &reply["id"]).collect();
+    assert_eq!(ids, [1, 2, 3, 4, 5]);
+
+    assert_eq!(replies[0]["result"]["protocolVersion"], "2025-03-26");
+    assert_eq!(replies[0]["result"]["serverInfo"]["name"], "wk");
+    let tools: Vec<&str> = replies[1]["result"]["tools"].as_array().unwrap().iter().map(|t| t["name"].as_str().unwrap()).collect();
+    assert_eq!(tools, ["commit_fact", "recall", "list_tags", "get_fact"]);
+
+    let committed = result(&replies[2]);
+    assert_eq!(committed["data"], "The boiler code is 4471");
+    assert_eq!(result(&replies[3])[0]["id"], committed["id"]);
+    assert!(result(&replies[4]).to_string().contains("home"), "{}", replies[4]);
+    assert_eq!(replies[2]["result"]["isError"], false);
+
+    // Saved to the wiki, so another session finds it
+    let replies = session(&fixture, &[call(1, "get_fact", json!({ "id": committed["id"] }))]);
+    assert_eq!(result(&replies[0])["data"], "The boiler code is 4471");
+    assert_eq!(fixture.open().unwrap().all().len(), 1);
+}
+
+#[test]
+fn bad_requests_get_errors_and_the_session_goes_on() {
+    let fixture = FixtureWiki::new().facts(1).build().unwrap();
+    let missing = uuid::Uuid::new_v4();
+    let lines = [
+        "{ not json".to_string(),
+        json!({ "id": 1, "method": "ping" }).to_string(),
+        request(2, "resources/list", json!({})).to_string(),
+        call(3, "no_such_tool", json!({})).to_string(),
+        call(4, "recall", json!({ "query": "x", "unexpected": true })).to_string(),
+        call(5, "get_fact", json!({ "id": missing })).to_string(),
+        request(6, "ping", json!({})).to_string(),
+    ];
+    let replies = session_raw(&fixture, &lines);
+    let codes: Vec<&Value> = replies.iter().map(|reply| &reply["error"]["code"]).collect();
+    assert_eq!(codes, [&json!(-32700), &json!(-32600), &json!(-32601), &json!(-32602), &json!(-32602), &json!(-32000), &Value::Null]);
+    assert_eq!(replies[0]["id"], Value::Null);
+    assert_eq!(replies[5]["error"]["data"]["kind"], "not_found");
+    assert_eq!(replies[5]["error"]["data"]["id"], missing.to_string());
+    assert_eq!(replies[6]["result"], json!({}));
+}
+
+#[test]
+fn batches_are_answered_together() {
+    let fixture = FixtureWiki::new().facts(2).build().unwrap();
+    let batch = json!([
+        request(1, "ping", json!({})),
+        json!({ "jsonrpc": "2.0", "method": "notifications/cancelled" }),
+        call(2, "recall", json!({ "limit": 1 })),
+    ]);
+    let replies = session(&fixture, &[batch]);
+    assert_eq!(replies.len(), 1);
+    let batch = replies[0].as_array().unwrap();
+    assert_eq!(batch.len(), 2);
+    assert_eq!(result(&batch[1]).as_array().unwrap().len(), 1);
+}
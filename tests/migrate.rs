@@ -0,0 +1,54 @@
+//! `wk migrate` between the file and SQLite backends, checked by exporting
+//! before and after
+#![cfg(feature = "sqlite")]
+
+use twk::fixture::{Fixture, FixtureWiki};
+use twk::storage::Backend;
+use twk::{Information, Wiki};
+
+/// Open the fixture's wiki with whichever backend its directory now uses
+fn reopen(fixture: &Fixture) -> Wiki {
+    let path = fixture.path();
+    let storage = Backend::detect(&path).open(&path).unwrap();
+    Wiki::with_storage(fixture.name().to_string(), path, storage).unwrap()
+}
+
+/// Everything a migration has to carry over, as an export and otherwise
+fn everything(wiki: &Wiki) -> (String, Vec<(Information, usize)>, Vec<Information>) {
+    let mut facts = wiki.all();
+    facts.sort_by_key(|info| info.id);
+    let export = twk::export::to_json(&facts).unwrap();
+    let snapshots = facts.iter().map(|info| (info.clone(), wiki.snapshots(info.id).unwrap().len())).collect();
+    let trashed = wiki.trashed().unwrap().into_iter().map(|t| t.fact).collect();
+    (export, snapshots, trashed)
+}
+
+#[test]
+fn migrating_there_and_back_loses_nothing() {
+    let fixture = FixtureWiki::new().facts(6).tags(2).size(400).build().unwrap();
+    let mut wiki = fixture.open().unwrap();
+    let data = "with a source\nand ünïcode".to_string();
+    let source = Some("https://example.com".to_string());
+    let sourced = wiki.commit_named("Sourced".to_string(), data, vec!["web".to_string()], source).unwrap();
+    wiki.update(sourced, |info| {
+        info.extra.insert("x-kept".to_string(), serde_json::json!({ "by": "another tool" }));
+    })
+    .unwrap();
+    wiki.snapshot(fixture.facts()[0].id, Some("before")).unwrap();
+    wiki.delete(fixture.facts()[1].id, false).unwrap();
+    let before = everything(&wiki);
+
+    assert_eq!(wiki.migrate(Backend::Sqlite).unwrap(), 6);
+    assert_eq!(Backend::detect(&fixture.path()), Backend::Sqlite);
+    assert!(!fixture.path().join(format!("{}.json", fixture.facts()[0].id)).exists());
+    assert_eq!(everything(&wiki), before);
+    let mut wiki = reopen(&fixture);
+    assert_eq!(everything(&wiki), before);
+
+    assert_eq!(wiki.migrate(Backend::Files).unwrap(), 6);
+    assert_eq!(Backend::detect(&fixture.path()), Backend::Files);
+    assert!(!fixture.path().join(twk::sqlite::DB_FILE).exists());
+    assert_eq!(everything(&reopen(&fixture)), before);
+    // Migrating to the backend in use does nothing
+    assert_eq!(reopen(&fixture).migrate(Backend::Files).unwrap(), 0);
+}
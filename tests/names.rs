@@ -0,0 +1,179 @@
+//! Names taken from a fact's data, and renaming them when the data moves on
+
+mod common;
+
+use common::{stderr, stdout, wk};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use twk::app::App;
+use twk::fixture::{Fixture, FixtureWiki};
+use twk::{Finding, Information, Wiki, derive_title};
+use uuid::Uuid;
+
+/// Commit `text` as `wk c` would, then change its data to `edited`
+fn edited(wiki: &mut Wiki, text: &str, edited: &str) -> Uuid {
+    let id = wiki.commit(text.to_string(), vec![]).unwrap();
+    wiki.update(id, |info| info.data = edited.to_string()).unwrap();
+    id
+}
+
+/// A fact file written before names were told apart from data
+fn legacy(fixture: &Fixture, name: &str, data: &str) -> Uuid {
+    let id = Uuid::new_v4();
+    let json = serde_json::json!({ "id": id, "tags": [], "name": name, "data": data });
+    std::fs::write(fixture.path().join(format!("{}.json", id)), json.to_string()).unwrap();
+    id
+}
+
+fn stale(wiki: &Wiki) -> Vec<(Uuid, String)> {
+    let findings = wiki.check_names().unwrap();
+    findings
+        .into_iter()
+        .map(|finding| match finding {
+            Finding::StaleName { id, derived, .. } => (id, derived),
+            other => panic!("{:?}", other),
+        })
+        .collect()
+}
+
+#[test]
+fn names_given_or_taken_from_data() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let mut wiki = fixture.open().unwrap();
+    let id = wiki.commit("one line".to_string(), vec![]).unwrap();
+    assert!(wiki.get(id).unwrap().name_is_derived);
+    let id = wiki.commit_named("Ports".to_string(), "port = 80".to_string(), vec![], None).unwrap();
+    assert!(!wiki.get(id).unwrap().name_is_derived);
+    // As `wk c --clip` names facts
+    let id = wiki.commit_named("first".to_string(), "\n first \nsecond".to_string(), vec![], None).unwrap();
+    assert!(wiki.get(id).unwrap().name_is_derived);
+
+    // Only given names are written out
+    let id = legacy(&fixture, "old", "old");
+    let wiki = fixture.open().unwrap();
+    assert!(wiki.get(id).unwrap().name_is_derived);
+    let file = std::fs::read_to_string(fixture.path().join(format!("{}.json", id))).unwrap();
+    assert!(!file.contains("name_is_derived"));
+}
+
+#[test]
+fn only_names_taken_from_earlier_data_are_stale() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let mut wiki = fixture.open().unwrap();
+    let rewritten = edited(&mut wiki, "kubectl rollout undo", "kubectl rollout undo deployment/web");
+    let multiline = edited(&mut wiki, "deploy\nrun step one", "deploy\nrun step two");
+    // Added to, so its first line is still its name
+    edited(&mut wiki, "backups", "backups\nnightly at 3am");
+    let given = wiki.commit_named("Ports".to_string(), "port = 80".to_string(), vec![], None).unwrap();
+    wiki.update(given, |info| info.data = "port = 8080".to_string()).unwrap();
+    legacy(&fixture, "Release checklist", "bump the version\ntag it");
+    let legacy_stale = legacy(&fixture, "ssh keys rotate", "ssh keys rotate yearly");
+
+    let mut wiki = fixture.open().unwrap();
+    let mut found = stale(&wiki);
+    found.sort();
+    let mut expected = vec![
+        (rewritten, "kubectl rollout undo deployment/web".to_string()),
+        (multiline, "deploy".to_string()),
+        (legacy_stale, "ssh keys rotate yearly".to_string()),
+    ];
+    expected.sort();
+    assert_eq!(found, expected);
+
+    let repairs = wiki.fix(&wiki.check_names().unwrap()).unwrap();
+    assert_eq!(repairs.len(), 3);
+    assert_eq!(wiki.get(multiline).unwrap().name, "deploy");
+    assert!(stale(&wiki).is_empty());
+}
+
+#[test]
+fn doctor_fixes_names_and_opening_can_too() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let id = legacy(&fixture, "ssh keys rotate", "ssh keys rotate yearly");
+
+    let output = wk(&fixture).arg("doctor").output().unwrap();
+    assert!(stdout(&output).contains("Outdated names"), "{}", stdout(&output));
+    assert_eq!(fixture.open().unwrap().get(id).unwrap().name, "ssh keys rotate");
+
+    std::fs::write(fixture.path().join("config.toml"), "refresh_names = true\n").unwrap();
+    wk(&fixture).arg("ls").output().unwrap();
+    assert_eq!(fixture.open().unwrap().get(id).unwrap().name, "ssh keys rotate yearly");
+}
+
+/// Rewrite the first line of the selected fact in the TUI's inline editor
+fn edit_first_line(app: &mut App, text: &str) {
+    app.update(app.key_msg(KeyEvent::from(KeyCode::Char('i'))).unwrap());
+    app.edit_buffer = text.to_string();
+    let save = app.key_msg(KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL)).unwrap();
+    app.update(save);
+}
+
+#[test]
+fn tui_offers_to_rename_after_the_first_line_changes() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let id = fixture.open().unwrap().commit("restart nginx".to_string(), vec![]).unwrap();
+    let mut app = App::new(fixture.open().unwrap(), true, None, true);
+    let get = |app: &App| -> Information { app.wiki.get(id).unwrap() };
+
+    edit_first_line(&mut app, "restart nginx gracefully");
+    assert!(app.asking());
+    app.update(app.key_msg(KeyEvent::from(KeyCode::Char('y'))).unwrap());
+    assert_eq!(get(&app).name, "restart nginx gracefully");
+
+    // Declining keeps the name for good
+    edit_first_line(&mut app, "reload nginx");
+    app.update(app.key_msg(KeyEvent::from(KeyCode::Char('n'))).unwrap());
+    assert_eq!(get(&app).name, "restart nginx gracefully");
+    assert!(!get(&app).name_is_derived);
+    edit_first_line(&mut app, "reload nginx gently");
+    assert!(!app.asking());
+}
+
+#[test]
+fn titles_prefer_headings_then_wordy_lines() {
+    assert_eq!(derive_title("```\n$ kubectl get pods\n```\n## Pods on staging ##\nNAME READY"), "Pods on staging");
+    // `#tag` and an empty `# ` aren't headings
+    assert_eq!(derive_title("#ops\n# \n## Deploys"), "Deploys");
+    assert_eq!(derive_title("----- | ----- | --\nafter  the\trule"), "after the rule");
+    assert_eq!(derive_title("\n  -> 10.0.0.1:443 \n"), "-> 10.0.0.1:443");
+    assert_eq!(derive_title(""), "");
+
+    let long = "word ".repeat(30);
+    let title = derive_title(&long);
+    assert_eq!((title.len(), title.ends_with("word")), (79, true));
+    assert_eq!(derive_title(&"x".repeat(100)).len(), 80);
+}
+
+#[test]
+fn auto_title_names_piped_facts_unless_a_name_is_given() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let commit = |args: &[&str]| {
+        let input = "=== ===\n# Staging pods\nNAME READY\nweb-1 1/1\n";
+        let output = wk(&fixture).args(["c", "-", "--no-dup-check"]).args(args).write_stdin(input).output().unwrap();
+        assert!(output.status.success(), "{}", stderr(&output));
+    };
+    commit(&["--auto-title"]);
+    commit(&["--auto-title", "--name", "pods, by hand"]);
+    commit(&[]);
+
+    let mut names: Vec<String> = fixture.open().unwrap().all().into_iter().map(|info| info.name).collect();
+    names.sort();
+    assert_eq!(names, ["=== ===\n# Staging pods\nNAME READY\nweb-1 1/1", "Staging pods", "pods, by hand"]);
+}
+
+#[test]
+fn tui_names_new_facts_once_written() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let mut app = App::new(fixture.open().unwrap(), true, None, true);
+    app.update(app.key_msg(KeyEvent::from(KeyCode::Char(':'))).unwrap());
+    app.update(app.key_msg(KeyEvent::from(KeyCode::Char('n'))).unwrap());
+    app.update(app.key_msg(KeyEvent::from(KeyCode::Enter)).unwrap());
+    assert!(app.items.is_empty());
+
+    app.edit_buffer = "| a | b |\n|---|---|\nports for the staging proxy\n".to_string();
+    let save = app.key_msg(KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL)).unwrap();
+    app.update(save);
+    let id = app.items[app.state.selected().unwrap()].3;
+    let info = app.wiki.get(id).unwrap();
+    assert_eq!((info.name.as_str(), info.name_is_derived), ("ports for the staging proxy", false));
+    assert_eq!(info.data, "| a | b |\n|---|---|\nports for the staging proxy");
+}
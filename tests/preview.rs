@@ -0,0 +1,53 @@
+//! Picking the line of a fact to preview
+
+mod common;
+
+use common::{stdout, wk};
+use regex::Regex;
+use twk::fixture::FixtureWiki;
+use twk::preview::{LineMatcher, preview_line};
+
+#[test]
+fn headings_and_blank_lines_are_skipped() {
+    assert_eq!(preview_line("first line\nsecond", None), "first line");
+    assert_eq!(preview_line("\n\n  # Notes\n\nThe actual text  \n", None), "The actual text");
+    assert_eq!(preview_line("## Setup\n```sh\n$ make\n```\n", None), "$ make");
+    assert_eq!(preview_line("---\n42\nmeeting at noon", None), "meeting at noon");
+    // A tag isn't a heading
+    assert_eq!(preview_line("#rust tips", None), "#rust tips");
+}
+
+#[test]
+fn previews_fall_back_to_headings_then_anything() {
+    assert_eq!(preview_line("# Only a heading\n\n", None), "Only a heading");
+    assert_eq!(preview_line("#\n---\n1 2 3", None), "---");
+    assert_eq!(preview_line("\n  \n", None), "");
+    assert_eq!(preview_line("", None), "");
+}
+
+#[test]
+fn filtered_previews_show_the_matching_line() {
+    let data = "# Rust\nOwnership rules\nborrowing with &mut\nlifetimes and borrowing";
+    let re = LineMatcher::regex(Regex::new("borrow").unwrap());
+    assert_eq!(preview_line(data, Some(&re)), "borrowing with &mut");
+    assert_eq!(preview_line(data, Some(&LineMatcher::fuzzy("LIFETIMES"))), "lifetimes and borrowing");
+    // Headings match without their markers
+    assert_eq!(preview_line(data, Some(&LineMatcher::fuzzy("rust"))), "Rust");
+    // Nothing matching leaves the usual preview
+    let none = LineMatcher::regex(Regex::new("zzz").unwrap());
+    assert_eq!(preview_line(data, Some(&none)), "Ownership rules");
+}
+
+#[test]
+fn tables_preview_the_line_recalled() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let mut wiki = fixture.open().unwrap();
+    wiki.commit_named("Rust".to_string(), "# Rust\n\nownership\nborrow checker".to_string(), vec![], None).unwrap();
+    drop(wiki);
+
+    let listed = stdout(&wk(&fixture).args(["ls", "--format", "table"]).output().unwrap());
+    let row = listed.lines().nth(1).unwrap();
+    assert!(row.starts_with("Rust") && row.contains("ownership"), "{}", listed);
+    let recalled = stdout(&wk(&fixture).args(["r", "checker", "--format", "table"]).output().unwrap());
+    assert!(recalled.lines().nth(1).unwrap().contains("borrow checker"), "{}", recalled);
+}
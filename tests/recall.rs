@@ -0,0 +1,412 @@
+//! Recall scoring and tag filtering on fixture wikis
+
+use nucleo_matcher::Utf32Str;
+use proptest::prelude::*;
+use std::collections::HashMap;
+use twk::fixture::FixtureWiki;
+use twk::{Fields, TimeWindow, Wiki};
+use uuid::Uuid;
+
+#[test]
+fn fixture_loads_every_fact() {
+    let fixture = FixtureWiki::new().facts(25).size(500).corrupted(1).build().unwrap();
+    let wiki = fixture.open().unwrap();
+    assert_eq!(wiki.info.len(), 25);
+    assert_eq!(wiki.warnings.len(), 1);
+    assert_eq!(wiki.warnings[0].path, fixture.corrupted()[0]);
+    for fact in fixture.facts() {
+        assert_eq!(&wiki.get(fact.id).unwrap(), fact);
+    }
+}
+
+#[test]
+fn closer_matches_score_higher() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let mut wiki = fixture.open().unwrap();
+    wiki.commit("kubernetes rollout undo deployment".to_string(), vec![]).unwrap();
+    let exact = wiki.commit("kubectl rollout".to_string(), vec![]).unwrap();
+    wiki.commit("nothing relevant at all".to_string(), vec![]).unwrap();
+
+    let hits = wiki.recall("kubectl rollout", None);
+    assert_eq!(hits.first().map(|info| info.id), Some(exact));
+    assert!(hits.iter().all(|info| info.data != "nothing relevant at all"));
+
+    let scores: Vec<u32> = wiki
+        .recall_refs("rollout", None, Fields::ALL, twk::TimeWindow::ANY, None)
+        .iter()
+        .map(|hit| hit.score)
+        .collect();
+    assert!(scores.windows(2).all(|pair| pair[0] >= pair[1]), "{:?}", scores);
+}
+
+#[test]
+fn recall_limits_to_tag() {
+    let fixture = FixtureWiki::new().facts(12).tags(4).build().unwrap();
+    let wiki = fixture.open().unwrap();
+    let hits = wiki.recall("Fact", Some("tag2"));
+    assert_eq!(hits.len(), 3);
+    assert!(hits.iter().all(|info| info.tags == ["tag2"]));
+}
+
+#[test]
+fn tags_match_nested_tags() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let mut wiki = fixture.open().unwrap();
+    let rust = wiki.commit("cargo build".to_string(), vec!["lang/rust".to_string()]).unwrap();
+    let go = wiki.commit("go build".to_string(), vec!["lang/go".to_string()]).unwrap();
+    wiki.commit("language learning".to_string(), vec!["language".to_string()]).unwrap();
+
+    let mut lang: Vec<_> = wiki.recall_by_tag("lang").into_iter().map(|info| info.id).collect();
+    lang.sort();
+    let mut expected = vec![rust, go];
+    expected.sort();
+    assert_eq!(lang, expected);
+    assert_eq!(wiki.recall_by_tag("lang/rust").len(), 1);
+    assert!(wiki.recall_by_tag("rust").is_empty());
+}
+
+#[test]
+fn exact_recall_needs_every_word() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let mut wiki = fixture.open().unwrap();
+    wiki.commit("ssh port forwarding".to_string(), vec![]).unwrap();
+    wiki.commit("ssh keys".to_string(), vec![]).unwrap();
+
+    let hits = wiki.recall_exact("SSH forwarding", None, Fields::ALL);
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].data, "ssh port forwarding");
+}
+
+#[test]
+fn field_masks_search_only_their_fields() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let mut wiki = fixture.open().unwrap();
+    let mut commit = |name: &str, data: &str, tag: &str, source: Option<&str>| {
+        let (name, data, tags) = (name.to_string(), data.to_string(), vec![tag.to_string()]);
+        wiki.commit_named(name, data, tags, source.map(str::to_string)).unwrap()
+    };
+    let name = commit("zebra stripes", "black and white", "animals", None);
+    let data = commit("crossing", "a zebra crossing", "roads", None);
+    let tags = commit("stripes", "on the road", "zebra", None);
+    let source = commit("link", "see there", "web", Some("https://zebra.example"));
+
+    let cases = [
+        (Fields::NAME, vec![name]),
+        (Fields::DATA, vec![data]),
+        (Fields::TAGS, vec![tags]),
+        (Fields::SOURCE, vec![source]),
+        (Fields::NAME | Fields::TAGS, vec![name, tags]),
+        (Fields::ALL, vec![name, data, tags]),
+        (Fields::ALL | Fields::SOURCE, vec![name, data, tags, source]),
+        (Fields::NONE, vec![]),
+    ];
+    for (fields, mut expected) in cases {
+        expected.sort();
+        let mut fuzzy: Vec<Uuid> = wiki.recall_refs("zebra", None, fields, TimeWindow::ANY, None).iter().map(|hit| hit.id).collect();
+        fuzzy.sort();
+        assert_eq!(fuzzy, expected, "fuzzy in {:?}", fields);
+        let mut exact: Vec<Uuid> = wiki.recall_exact("ZEBRA", None, fields).iter().map(|info| info.id).collect();
+        exact.sort();
+        assert_eq!(exact, expected, "exact in {:?}", fields);
+    }
+    assert_eq!(Fields::default(), Fields::ALL);
+    assert_eq!(Fields::NAME | Fields::DATA | Fields::TAGS, Fields::ALL);
+}
+
+#[test]
+fn parallel_scoring_matches_sequential() {
+    let fixture = FixtureWiki::new().facts(3_000).tags(7).size(200).build().unwrap();
+    let wiki = fixture.open().unwrap();
+    let scored = |threads: usize, query: &str, tag: Option<&str>| {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        pool.install(|| {
+            wiki.recall_refs(query, tag, Fields::ALL, twk::TimeWindow::ANY, None)
+                .iter()
+                .map(|hit| (hit.score, hit.id))
+                .collect::<Vec<_>>()
+        })
+    };
+
+    for (query, tag) in [("golf hotel", None), ("Fact 12", None), ("kilo", Some("tag4")), ("zzzz", None)] {
+        let sequential = scored(1, query, tag);
+        // Many facts score the same, so this checks ties are broken the same way too
+        assert_eq!(scored(8, query, tag), sequential, "{}", query);
+    }
+}
+
+/// Recall `query` over a fact's data and say whether `id` was among the hits
+fn recalls(wiki: &twk::Wiki, query: &str, id: uuid::Uuid) -> bool {
+    wiki.recall_refs(query, None, Fields::DATA, twk::TimeWindow::ANY, None)
+        .iter()
+        .any(|hit| hit.id == id)
+}
+
+#[test]
+fn edited_facts_are_not_scored_from_stale_haystacks() {
+    let fixture = FixtureWiki::new().facts(20).build().unwrap();
+    let mut wiki = fixture.open().unwrap();
+    let fact = fixture.facts()[0].clone();
+    let before: String = fact.data.chars().take(12).collect();
+    assert!(recalls(&wiki, &before, fact.id));
+    assert!(wiki.haystack_cache().data_bytes() > 0);
+
+    // Same length and, by hand, the same `updated`, so only dropping the
+    // cached haystacks on change keeps the old data from being matched
+    let mut edited = fact.clone();
+    edited.data = "~".repeat(fact.data.len());
+    wiki.update(fact.id, |info| info.data = edited.data.clone()).unwrap();
+    assert!(recalls(&wiki, "~~~~", fact.id));
+
+    edited.data = "^".repeat(fact.data.len());
+    edited.updated = wiki.get(fact.id).unwrap().updated;
+    let path = fixture.path().join(format!("{}.json", fact.id));
+    std::fs::write(path, serde_json::to_vec_pretty(&edited).unwrap()).unwrap();
+    wiki.reload(fact.id).unwrap();
+    assert!(recalls(&wiki, "^^^^", fact.id));
+    assert!(!recalls(&wiki, "~~~~", fact.id));
+}
+
+#[test]
+fn cached_data_stays_within_capacity() {
+    use twk::haystack::{HaystackCache, Haystacks};
+
+    let fixture = FixtureWiki::new().facts(3).size(100).build().unwrap();
+    let cache = HaystackCache::with_capacity(250);
+    cache.insert(fixture.facts().iter().map(|fact| (fact.id, Haystacks::new(fact, true))));
+    assert!(cache.data_bytes() <= 250);
+
+    let view = cache.read();
+    let kept: Vec<bool> = fixture.facts().iter().map(|fact| view.get(fact).unwrap().data.is_some()).collect();
+    assert_eq!(kept.iter().filter(|&&kept| kept).count(), 250 / fixture.facts()[0].data.len());
+
+    // Any change to a fact makes its entry stale
+    let mut changed = fixture.facts()[0].clone();
+    changed.data.push('!');
+    assert!(view.get(&changed).is_none());
+}
+
+#[test]
+fn top_n_matches_full_sort() {
+    // Enough threads that each keeps its own top n to merge
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+    for (facts, size) in [(40, 64), (600, 200), (3_000, 32)] {
+        let fixture = FixtureWiki::new().facts(facts).tags(5).size(size).build().unwrap();
+        let wiki = fixture.open().unwrap();
+        for query in ["alpha", "Fact 1", "e", "tag3"] {
+            let all: Vec<(u32, uuid::Uuid)> = wiki
+                .recall_refs(query, None, Fields::ALL, twk::TimeWindow::ANY, None)
+                .iter()
+                .map(|hit| (hit.score, hit.id))
+                .collect();
+            for n in [0, 1, 5, 37, facts, facts * 2] {
+                let top: Vec<(u32, uuid::Uuid)> = pool.install(|| {
+                    wiki.recall_top_n(query, n, twk::RecallOptions::default())
+                        .iter()
+                        .map(|hit| (hit.score, hit.id))
+                        .collect()
+                });
+                assert_eq!(top, all[..n.min(all.len())], "{} facts, {:?}, top {}", facts, query, n);
+            }
+        }
+    }
+}
+
+#[test]
+fn smart_case_tells_types_from_prose() {
+    use twk::matching::Case;
+
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let mut wiki = fixture.open().unwrap();
+    let prose = wiki.commit("a hashmap keeps keys and values".to_string(), vec![]).unwrap();
+    let code = wiki.commit("let seen: HashMap<String, usize> = HashMap::new();".to_string(), vec![]).unwrap();
+    let ids = |wiki: &twk::Wiki, query: &str| wiki.recall(query, None).iter().map(|info| info.id).collect::<Vec<_>>();
+
+    // Ignoring case, the fact saying just `hashmap` is the better match
+    assert_eq!(ids(&wiki, "HashMap"), [prose, code]);
+
+    wiki.config.matching.case = Case::Smart;
+    assert_eq!(ids(&wiki, "HashMap"), [code]);
+    assert_eq!(ids(&wiki, "hashmap"), [prose, code]);
+
+    wiki.config.matching.case = Case::Sensitive;
+    assert_eq!(ids(&wiki, "hashmap"), [prose]);
+}
+
+#[test]
+fn matching_is_set_in_config_and_overridden_per_run() {
+    use twk::matching::{Case, MatchConfig};
+
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    assert_eq!(fixture.open().unwrap().config.matching, MatchConfig::default());
+
+    std::fs::write(fixture.path().join("config.toml"), "[match]\ncase = \"smart\"\nprefer_word_boundaries = false\n").unwrap();
+    let matching = fixture.open().unwrap().config.matching;
+    assert_eq!(matching, MatchConfig { case: Case::Smart, prefer_word_boundaries: false, ..Default::default() });
+
+    twk::set_case(Some(Case::Sensitive));
+    let overridden = fixture.open().unwrap().config.matching;
+    twk::set_case(None);
+    assert_eq!(overridden, MatchConfig { case: Case::Sensitive, prefer_word_boundaries: false, ..Default::default() });
+
+    std::fs::write(fixture.path().join("config.toml"), "[match]\ncase = \"loud\"\n").unwrap();
+    let wiki = fixture.open().unwrap();
+    assert!(wiki.warnings.iter().any(|warning| warning.error.contains("loud")));
+}
+
+#[test]
+fn detailed_hits_say_where_they_matched() {
+    use twk::{Field, RecallOptions};
+
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let mut wiki = fixture.open().unwrap();
+    let named = wiki.commit_named("rollout".to_string(), "undo a deploy".to_string(), vec![], None).unwrap();
+    let tagged = wiki.commit_named("cheat sheet".to_string(), "pods".to_string(), vec!["k8s".to_string(), "kubectl".to_string()], None).unwrap();
+
+    let opts = RecallOptions { indices: true, ..Default::default() };
+    let hits = wiki.recall_detailed("rollout", None, opts);
+    assert_eq!((hits[0].id, hits[0].matched_field), (named, Field::Name));
+    assert_eq!(hits[0].indices.as_deref(), Some(&[0, 1, 2, 3, 4, 5, 6][..]));
+
+    let hits = wiki.recall_detailed("kctl", None, opts);
+    assert_eq!((hits[0].id, hits[0].matched_field), (tagged, Field::Tag(1)));
+    assert_eq!(hits[0].indices.as_deref(), Some(&[0, 4, 5, 6][..]));
+
+    // Only worked out when asked for
+    let hits = wiki.recall_detailed("kctl", None, RecallOptions::default());
+    assert_eq!(hits[0].indices, None);
+    let refs = wiki.recall_refs("kctl", None, Fields::ALL, twk::TimeWindow::ANY, None);
+    assert_eq!(refs[0].score, hits[0].score);
+}
+
+#[test]
+fn tag_weights_reorder_matches_but_never_add_them() {
+    use twk::RecallOptions;
+
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let mut wiki = fixture.open().unwrap();
+    let archived = wiki.commit("rotate the tls certs".to_string(), vec!["archive".to_string()]).unwrap();
+    let plain = wiki.commit("rotate the tls certs".to_string(), vec![]).unwrap();
+    let important = wiki.commit("rotate the tls certs".to_string(), vec!["important/ops".to_string()]).unwrap();
+    wiki.commit("water the plants".to_string(), vec!["important".to_string()]).unwrap();
+
+    let ids = |wiki: &twk::Wiki| -> Vec<_> { wiki.recall_detailed("tls certs", None, RecallOptions::default()).iter().map(|hit| hit.id).collect() };
+    // Equal matches keep the wiki's order
+    assert_eq!(ids(&wiki), [archived, plain, important]);
+
+    std::fs::write(fixture.path().join("config.toml"), "[tag_weights]\nimportant = 1.5\narchive = 0.5\n").unwrap();
+    let wiki = fixture.open().unwrap();
+    assert_eq!(ids(&wiki), [important, plain, archived]);
+    let hits = wiki.recall_detailed("tls certs", None, RecallOptions::default());
+    assert!(hits.iter().all(|hit| hit.raw_score == hits[1].score));
+    assert_eq!(hits[0].score, (hits[0].raw_score as f64 * 1.5).round() as u32);
+}
+
+#[test]
+fn folding_diacritics_is_opt_in() {
+    use twk::{Field, GrepOptions, RecallOptions};
+
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let mut wiki = fixture.open().unwrap();
+    let cafe = wiki.commit_named("café crème".to_string(), "au coin".to_string(), vec![], None).unwrap();
+    wiki.commit_named("resume".to_string(), "one page".to_string(), vec![], None).unwrap();
+    assert!(wiki.recall("résumé", None).is_empty());
+
+    std::fs::write(fixture.path().join("config.toml"), "[match]\nfold_diacritics = true\n").unwrap();
+    let wiki = fixture.open().unwrap();
+    assert_eq!(wiki.recall("résumé", None)[0].name, "resume");
+    assert!(wiki.recall_exact("cafe", None, Fields::ALL).is_empty(), "exact recall folds separately");
+
+    // Highlights point into the name as written
+    let hits = wiki.recall_detailed("creme", None, RecallOptions { indices: true, ..Default::default() });
+    assert_eq!((hits[0].id, hits[0].matched_field), (cafe, Field::Name));
+    assert_eq!(hits[0].name, "café crème");
+    assert_eq!(hits[0].indices.as_deref(), Some(&[5, 6, 7, 8, 9][..]));
+
+    std::fs::write(fixture.path().join("config.toml"), "[match]\nfold_patterns = true\n").unwrap();
+    let wiki = fixture.open().unwrap();
+    assert_eq!(wiki.recall_exact("CAFE", None, Fields::ALL)[0].id, cafe);
+    assert_eq!(wiki.grep("AU C.IN", GrepOptions::default()).unwrap()[0].id, cafe);
+}
+
+/// Every fact matching `query` in `fields` with its raw score, found by
+/// scoring each one without the search index
+fn brute_force(wiki: &Wiki, query: &str, fields: Fields) -> HashMap<Uuid, u32> {
+    let matching = &wiki.config.matching;
+    let mut matcher = twk::matching::make_matcher(&wiki.config, query);
+    let needle = matching.needle(query);
+    let (mut needle_buf, mut haystack_buf) = (Vec::new(), Vec::new());
+    let needle = Utf32Str::new(&needle, &mut needle_buf);
+
+    let mut found = HashMap::new();
+    for info in wiki.snapshot_facts() {
+        let mut texts = Vec::new();
+        texts.extend(fields.name.then_some(info.name.as_str()));
+        texts.extend(fields.data.then_some(info.data.as_str()));
+        if fields.tags {
+            texts.extend(info.tags.iter().map(String::as_str));
+        }
+        texts.extend(info.source.as_deref().filter(|_| fields.source));
+        let best = texts
+            .into_iter()
+            .filter_map(|text| matcher.fuzzy_match(Utf32Str::new(&matching.haystack(text), &mut haystack_buf), needle))
+            .max();
+        if let Some(score) = best {
+            found.insert(info.id, score as u32);
+        }
+    }
+    found
+}
+
+fn recalled(wiki: &Wiki, query: &str, fields: Fields) -> HashMap<Uuid, u32> {
+    let hits = wiki.recall_refs(query, None, fields, TimeWindow::ANY, None);
+    hits.iter().map(|hit| (hit.id, hit.raw_score)).collect()
+}
+
+fn fact() -> impl Strategy<Value = (String, String, Vec<String>, Option<String>)> {
+    (
+        "[a-zA-Zé][a-zA-Zéß ]{0,8}",
+        "[a-zA-Zéß \n]{0,30}",
+        prop::collection::vec("[a-z]{1,6}", 0..3),
+        prop::option::of("[a-z:/.]{1,12}"),
+    )
+}
+
+fn fields() -> impl Strategy<Value = Fields> {
+    (any::<bool>(), any::<bool>(), any::<bool>(), any::<bool>())
+        .prop_filter("some field", |&(n, d, t, s)| n || d || t || s)
+        .prop_map(|(name, data, tags, source)| Fields { name, data, tags, source })
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(48))]
+
+    #[test]
+    fn the_index_never_changes_what_is_recalled(
+        facts in prop::collection::vec(fact(), 1..16),
+        queries in prop::collection::vec("[a-zA-Zéß ]{3,5}", 1..4),
+        masks in prop::collection::vec(fields(), 1..4),
+    ) {
+        let fixture = FixtureWiki::new().facts(0).build().unwrap();
+        let mut wiki = fixture.open().unwrap();
+        let (first, rest) = facts.split_at(facts.len() / 2);
+        for (name, data, tags, source) in first {
+            wiki.commit_named(name.clone(), data.clone(), tags.clone(), source.clone()).unwrap();
+        }
+        // Built now, so the rest reach it as they are committed and changed
+        wiki.recall(&queries[0], None);
+        for (name, data, tags, source) in rest {
+            wiki.commit_named(name.clone(), data.clone(), tags.clone(), source.clone()).unwrap();
+        }
+        let changed = wiki.info[0].read().id;
+        wiki.update(changed, |info| info.data.push_str(" ZéBRA")).unwrap();
+
+        // Then again with the index read back from its file
+        for wiki in [wiki, fixture.open().unwrap()] {
+            for query in &queries {
+                for &fields in &masks {
+                    prop_assert_eq!(recalled(&wiki, query, fields), brute_force(&wiki, query, fields), "{:?} in {:?}", query, fields);
+                }
+            }
+        }
+    }
+}
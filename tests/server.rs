@@ -0,0 +1,119 @@
+//! `wk serve`: its JSON API, and the requests it turns away
+#![cfg(feature = "server")]
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::{Arc, RwLock};
+use twk::fixture::{Fixture, FixtureWiki};
+use twk::server::{ServeOptions, Server};
+
+/// Serve `fixture` on a free port of localhost until the test ends
+fn serve(fixture: &Fixture, options: ServeOptions) -> SocketAddr {
+    let server = Server::bind(ServeOptions { addr: "127.0.0.1:0".parse().unwrap(), ..options }).unwrap();
+    let addr = server.addr();
+    let wiki = Arc::new(RwLock::new(fixture.open().unwrap()));
+    std::thread::spawn(move || server.run(wiki));
+    addr
+}
+
+/// Send a request with `headers` and `body`, returning the status and body
+/// of the response
+fn request(addr: SocketAddr, line: &str, headers: &[&str], body: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut raw = format!("{} HTTP/1.1\r\nConnection: close\r\nContent-Length: {}\r\n", line, body.len());
+    for header in headers {
+        raw.push_str(header);
+        raw.push_str("\r\n");
+    }
+    raw.push_str("\r\n");
+    raw.push_str(body);
+    stream.write_all(raw.as_bytes()).unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let status = response.split_whitespace().nth(1).unwrap().parse().unwrap();
+    let body = response.split_once("\r\n\r\n").map(|(_, body)| body.to_string()).unwrap_or_default();
+    (status, body)
+}
+
+const LOCALHOST: &str = "Host: localhost";
+const JSON: &str = "Content-Type: application/json";
+
+#[test]
+fn facts_are_listed_created_and_patched() {
+    let fixture = FixtureWiki::new().facts(2).build().unwrap();
+    let addr = serve(&fixture, ServeOptions::default());
+
+    let (status, body) = request(addr, "GET /facts", &[LOCALHOST], "");
+    assert_eq!(status, 200);
+    assert_eq!(serde_json::from_str::<Vec<serde_json::Value>>(&body).unwrap().len(), 2);
+
+    let (status, body) = request(addr, "POST /facts", &[LOCALHOST, JSON], r#"{"data":"served fact","tags":["web"]}"#);
+    assert_eq!(status, 201, "{}", body);
+    let created: serde_json::Value = serde_json::from_str(&body).unwrap();
+    let id = created["id"].as_str().unwrap();
+
+    let patch = format!(r#"{{"data":"patched","updated":{}}}"#, created["updated"]);
+    let (status, _) = request(addr, &format!("PATCH /facts/{}", id), &[LOCALHOST, JSON], &patch);
+    assert_eq!(status, 200);
+    // Patching again from the same, now stale, copy conflicts
+    let (status, _) = request(addr, &format!("PATCH /facts/{}", id), &[LOCALHOST, JSON], &patch);
+    assert_eq!(status, 409);
+
+    let (status, _) = request(addr, "GET /facts/not-an-id", &[LOCALHOST], "");
+    assert_eq!(status, 400);
+}
+
+#[test]
+fn bodies_must_be_sent_as_json() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let addr = serve(&fixture, ServeOptions::default());
+
+    // What a form or a cross-site fetch can send without a preflight
+    let body = r#"{"data":"planted"}"#;
+    for content_type in ["Content-Type: text/plain", "Content-Type: application/x-www-form-urlencoded"] {
+        let (status, _) = request(addr, "POST /facts", &[LOCALHOST, content_type], body);
+        assert_eq!(status, 415);
+    }
+    let (status, _) = request(addr, "POST /facts", &[LOCALHOST], body);
+    assert_eq!(status, 415);
+    assert!(fixture.open().unwrap().all().is_empty());
+
+    let (status, _) = request(addr, "POST /facts", &[LOCALHOST, "Content-Type: application/json; charset=utf-8"], body);
+    assert_eq!(status, 201);
+}
+
+#[test]
+fn requests_for_other_hosts_are_refused() {
+    let fixture = FixtureWiki::new().facts(1).build().unwrap();
+    let options = ServeOptions { allowed_hosts: vec!["wiki.lan".to_string()], ..Default::default() };
+    let addr = serve(&fixture, options);
+
+    for host in ["localhost", "127.0.0.1", "[::1]", "wiki.lan"] {
+        let (status, _) = request(addr, "GET /facts", &[&format!("Host: {}:{}", host, addr.port())], "");
+        assert_eq!(status, 200, "{}", host);
+    }
+    // As a rebound name would arrive
+    for host in ["Host: attacker.example", "Host: attacker.example:7171", "Host: 10.0.0.1"] {
+        let (status, _) = request(addr, "GET /facts", &[host], "");
+        assert_eq!(status, 403, "{}", host);
+    }
+    let (status, _) = request(addr, "GET /", &["Host: attacker.example"], "");
+    assert_eq!(status, 403);
+}
+
+#[test]
+fn tokens_and_read_only_servers_guard_the_api() {
+    let fixture = FixtureWiki::new().facts(1).build().unwrap();
+    let addr = serve(&fixture, ServeOptions { token: Some("s3cret".to_string()), readonly: true, ..Default::default() });
+
+    let (status, _) = request(addr, "GET /facts", &[LOCALHOST], "");
+    assert_eq!(status, 401);
+    let (status, _) = request(addr, "GET /facts", &[LOCALHOST, "Authorization: Bearer wrong"], "");
+    assert_eq!(status, 401);
+    let auth = "Authorization: Bearer s3cret";
+    let (status, _) = request(addr, "GET /facts", &[LOCALHOST, auth], "");
+    assert_eq!(status, 200);
+    let (status, _) = request(addr, "POST /facts", &[LOCALHOST, auth, JSON], r#"{"data":"x"}"#);
+    assert_eq!(status, 403);
+}
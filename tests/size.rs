@@ -0,0 +1,119 @@
+//! Size limits on fact data, `wk ls --large` and recall of large facts
+
+mod common;
+
+use common::{stderr, stdout, wk};
+use twk::fixture::{Fixture, FixtureWiki};
+use twk::size::format_size;
+use twk::{Fields, RecallOptions, WikiError};
+use uuid::Uuid;
+
+/// A wiki counting facts over 100 bytes as large and refusing those over 200
+fn limited() -> Fixture {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    std::fs::write(fixture.path().join("config.toml"), "large_fact_bytes = 100\nmax_fact_bytes = 200\n").unwrap();
+    fixture
+}
+
+/// A fact file written before sizes were limited
+fn oversized(fixture: &Fixture, name: &str, bytes: usize) -> Uuid {
+    let id = Uuid::new_v4();
+    let data = format!("{}\n{}", name, "x".repeat(bytes));
+    let json = serde_json::json!({ "id": id, "tags": ["logs"], "name": name, "data": data, "name_is_derived": false });
+    std::fs::write(fixture.path().join(format!("{}.json", id)), json.to_string()).unwrap();
+    id
+}
+
+#[test]
+fn sizes_read_in_binary_units() {
+    assert_eq!(format_size(512), "512 B");
+    assert_eq!(format_size(1536), "1.5 KiB");
+    assert_eq!(format_size(12 * 1024 * 1024), "12.0 MiB");
+}
+
+#[test]
+fn writes_over_the_hard_limit_are_refused() {
+    let fixture = limited();
+    let mut wiki = fixture.open().unwrap();
+    assert!(wiki.commit("a".repeat(150), vec![]).is_ok());
+    let refused = wiki.commit("a".repeat(250), vec![]);
+    assert!(matches!(refused, Err(WikiError::TooLarge { len: 250, max: 200 })), "{:?}", refused);
+
+    let id = wiki.commit("small".to_string(), vec![]).unwrap();
+    let grown = wiki.update(id, |info| info.data = "b".repeat(201));
+    assert!(matches!(grown, Err(WikiError::TooLarge { .. })), "{:?}", grown);
+    assert!(wiki.commit_many(vec![("c".repeat(300), vec![])]).is_err());
+}
+
+#[test]
+fn oversized_facts_still_load_and_change() {
+    let fixture = limited();
+    let id = oversized(&fixture, "old log", 500);
+    let mut wiki = fixture.open().unwrap();
+    assert_eq!(wiki.get(id).unwrap().data.len(), 508);
+
+    // Retagging or trimming it is fine, growing it isn't
+    wiki.update(id, |info| info.tags.push("old".to_string())).unwrap();
+    wiki.update(id, |info| info.data.truncate(400)).unwrap();
+    assert!(wiki.update(id, |info| info.data.push('x')).is_err());
+}
+
+#[test]
+fn the_soft_limit_cant_be_over_the_hard_one() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    std::fs::write(fixture.path().join("config.toml"), "large_fact_bytes = 300\nmax_fact_bytes = 200\n").unwrap();
+    // The config is left out with a warning, as any it can't use
+    let output = wk(&fixture).args(["doctor"]).output().unwrap();
+    assert!(stdout(&output).contains("large_fact_bytes (300) is over max_fact_bytes (200)"), "{}", stdout(&output));
+}
+
+#[test]
+fn committing_warns_past_the_soft_limit_and_fails_past_the_hard_one() {
+    let fixture = limited();
+    let output = wk(&fixture).args(["c", &"a".repeat(150)]).output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stderr(&output).contains("this fact is 150 B, over 100 B"), "{}", stderr(&output));
+
+    let output = wk(&fixture).args(["c", &"a".repeat(250)]).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("link them with --source"), "{}", stderr(&output));
+}
+
+#[test]
+fn ls_large_lists_the_biggest_facts_first() {
+    let fixture = limited();
+    oversized(&fixture, "big log", 300);
+    oversized(&fixture, "bigger log", 600);
+    fixture.open().unwrap().commit("small".to_string(), vec![]).unwrap();
+
+    let output = wk(&fixture).args(["ls", "--large"]).output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    let listed = stdout(&output);
+    let (bigger, big) = (listed.find("bigger log").unwrap(), listed.find("big log").unwrap());
+    assert!(bigger < big && !listed.contains("small"), "{}", listed);
+
+    let output = wk(&fixture).args(["ls", "--large", "400"]).output().unwrap();
+    assert!(!stdout(&output).contains("big log"), "{}", stdout(&output));
+}
+
+#[test]
+fn recall_leaves_out_the_data_of_large_facts() {
+    let fixture = limited();
+    let id = oversized(&fixture, "old log", 300);
+    let wiki = fixture.open().unwrap();
+
+    let data = RecallOptions { fields: Fields::DATA, ..Default::default() };
+    assert!(wiki.recall_detailed("xxxxxx", None, data).is_empty());
+    let hits = wiki.recall_detailed("xxxxxx", None, RecallOptions { include_large: true, ..data });
+    assert_eq!(hits.iter().map(|hit| hit.id).collect::<Vec<_>>(), [id]);
+    assert!(!hits[0].large);
+
+    // Its name still matches, and the hit says why the data didn't
+    let hits = wiki.recall_detailed("old log", None, RecallOptions { fields: Fields::ALL, ..Default::default() });
+    assert!(hits[0].large);
+
+    let output = wk(&fixture).args(["r", "old log", "--format", "json", "--detailed"]).output().unwrap();
+    assert!(stdout(&output).contains("\"large\":true"), "{}", stdout(&output));
+    let output = wk(&fixture).args(["r", "xxxxxx", "--include-large", "--id"]).output().unwrap();
+    assert!(stdout(&output).contains(&id.to_string()), "{}", stdout(&output));
+}
@@ -0,0 +1,107 @@
+//! Expanding snippet triggers as facts are written
+
+mod common;
+
+use common::{stderr, wk};
+use std::collections::BTreeMap;
+use twk::expand_snippets;
+use twk::fixture::FixtureWiki;
+use twk::snippets::expand_last;
+
+fn snippets(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+    pairs.iter().map(|(trigger, expansion)| (trigger.to_string(), expansion.to_string())).collect()
+}
+
+fn expand(text: &str, pairs: &[(&str, &str)]) -> (String, Vec<String>) {
+    expand_snippets(text, &snippets(pairs))
+}
+
+#[test]
+fn whole_word_triggers_expand_in_place() {
+    let pairs = [(";;k8s", "kubernetes"), (";;sig", "--\nSam\nOps team")];
+    let (text, fired) = expand("deploy to ;;k8s\tthen ;;k8s again\n;;sig", &pairs);
+    assert_eq!(text, "deploy to kubernetes\tthen kubernetes again\n--\nSam\nOps team");
+    assert_eq!(fired, [";;k8s", ";;k8s", ";;sig"]);
+
+    // Not inside or against other text
+    let (text, fired) = expand("x;;k8s ;;k8s, (;;k8s) ;;k8ss", &pairs);
+    assert_eq!(text, "x;;k8s ;;k8s, (;;k8s) ;;k8ss");
+    assert!(fired.is_empty());
+    assert_eq!(expand("text", &[]), ("text".to_string(), vec![]));
+}
+
+#[test]
+fn overlapping_triggers_fire_only_as_typed() {
+    let pairs = [(";;k", "kilo"), (";;k8s", "kubernetes"), ("k8s", "K8S")];
+    let (text, fired) = expand(";;k ;;k8s k8s", &pairs);
+    assert_eq!(text, "kilo kubernetes K8S");
+    assert_eq!(fired, [";;k", ";;k8s", "k8s"]);
+}
+
+#[test]
+fn expansions_are_never_expanded_again() {
+    let pairs = [(";;a", ";;b ;;a"), (";;b", "bee")];
+    let (text, fired) = expand(";;a", &pairs);
+    assert_eq!(text, ";;b ;;a");
+    assert_eq!(fired, [";;a"]);
+}
+
+#[test]
+fn nothing_in_a_code_fence_is_expanded() {
+    let pairs = [(";;k8s", "kubernetes")];
+    let unchanged = [
+        "```\n;;k8s\n```",
+        "~~~ sh\n;;k8s\n~~~",
+        "  ```\n;;k8s\n  ```",
+        // Open to the end
+        "```\n;;k8s",
+        // Closed only by the same character
+        "```\n~~~\n;;k8s\n```",
+        // At least as many of it
+        "````\n```\n;;k8s\n````",
+        // With nothing after it
+        "```\n```rust\n;;k8s\n```",
+    ];
+    for text in unchanged {
+        assert_eq!(expand(text, &pairs), (text.to_string(), vec![]), "{:?}", text);
+    }
+
+    // But before and after one
+    let (text, fired) = expand(";;k8s\n```\n;;k8s\n```\n;;k8s ``\n;;k8s", &pairs);
+    assert_eq!(text, "kubernetes\n```\n;;k8s\n```\nkubernetes ``\nkubernetes");
+    assert_eq!(fired.len(), 3);
+    let (text, _) = expand("````\n;;k8s\n`````\n;;k8s", &pairs);
+    assert_eq!(text, "````\n;;k8s\n`````\nkubernetes");
+}
+
+#[test]
+fn the_word_just_typed_expands() {
+    let map = snippets(&[(";;k8s", "kubernetes")]);
+    assert_eq!(expand_last("deploy to ;;k8s", &map), Some(("deploy to kubernetes".to_string(), ";;k8s")));
+    assert_eq!(expand_last(";;k8s", &map), Some(("kubernetes".to_string(), ";;k8s")));
+    assert_eq!(expand_last("deploy to ;;k8", &map), None);
+    assert_eq!(expand_last("deploy to ;;k8s ", &map), None);
+    assert_eq!(expand_last("```\nrun ;;k8s", &map), None);
+    assert_eq!(expand_last("````\n```\n;;k8s", &map), None);
+    assert!(expand_last("```\n```\n;;k8s", &map).is_some());
+}
+
+#[test]
+fn commits_expand_unless_told_not_to() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let config = "[snippets]\n\";;k8s\" = \"kubernetes\"\n";
+    std::fs::write(fixture.path().join("config.toml"), config).unwrap();
+
+    let commit = wk(&fixture).args(["-v", "c", "scale ;;k8s pods"]).assert().success();
+    assert!(stderr(commit.get_output()).contains("expanded ;;k8s"), "{}", stderr(commit.get_output()));
+    wk(&fixture).args(["c", "--no-expand", "keep ;;k8s as typed"]).assert().success();
+    let mut data: Vec<String> = fixture.open().unwrap().all().into_iter().map(|info| info.data).collect();
+    data.sort();
+    assert_eq!(data, ["keep ;;k8s as typed", "scale kubernetes pods"]);
+
+    // A trigger that could never fire is warned about, and the config left unused
+    std::fs::write(fixture.path().join("config.toml"), "[snippets]\n\";; k8s\" = \"kubernetes\"\n").unwrap();
+    let warned = wk(&fixture).args(["-v", "c", "anything ;; k8s"]).assert().success();
+    assert!(stderr(warned.get_output()).contains("single word"), "{}", stderr(warned.get_output()));
+    assert!(fixture.open().unwrap().all().iter().any(|info| info.data == "anything ;; k8s"));
+}
@@ -0,0 +1,85 @@
+//! Counting facts into calendar buckets and charting them
+
+mod common;
+
+use chrono::{Local, NaiveDate};
+use common::{stdout, wk};
+use twk::chart::{bar, bar_chart, sparkline};
+use twk::fixture::FixtureWiki;
+use twk::stats::{Bucket, buckets};
+
+fn date(text: &str) -> NaiveDate {
+    text.parse().unwrap()
+}
+
+#[test]
+fn todays_facts_count_under_today() {
+    let today = date("2026-10-15");
+    let counted = buckets(&[today, today, date("2026-10-14")], Bucket::Day, today);
+    assert_eq!(counted, [(date("2026-10-14"), 1), (today, 2)]);
+}
+
+#[test]
+fn empty_spans_count_zero_up_to_today() {
+    let counted = buckets(&[date("2026-10-10"), date("2026-10-12")], Bucket::Day, date("2026-10-14"));
+    let counts: Vec<usize> = counted.iter().map(|&(_, n)| n).collect();
+    assert_eq!(counts, [1, 0, 1, 0, 0]);
+    assert_eq!(counted[0].0, date("2026-10-10"));
+    assert_eq!(counted[4].0, date("2026-10-14"));
+}
+
+#[test]
+fn weeks_start_on_monday_and_months_on_the_first() {
+    // A Thursday, the Monday before it, and a Sunday ending the week before
+    let dates = [date("2026-10-15"), date("2026-10-12"), date("2026-10-11")];
+    let weeks = buckets(&dates, Bucket::Week, date("2026-10-15"));
+    assert_eq!(weeks, [(date("2026-10-05"), 1), (date("2026-10-12"), 2)]);
+
+    let dates = [date("2026-08-31"), date("2026-10-01"), date("2026-10-31")];
+    let months = buckets(&dates, Bucket::Month, date("2026-10-31"));
+    assert_eq!(months, [(date("2026-08-01"), 1), (date("2026-09-01"), 0), (date("2026-10-01"), 2)]);
+
+    assert_eq!(Bucket::Month.start(date("2024-02-29")), date("2024-02-01"));
+    assert_eq!(Bucket::Week.start(date("2026-01-01")), date("2025-12-29"));
+}
+
+#[test]
+fn dates_after_today_count_in_todays_span() {
+    let today = date("2026-10-15");
+    assert_eq!(buckets(&[date("2026-10-20")], Bucket::Day, today), [(date("2026-10-20"), 1)]);
+    assert_eq!(buckets(&[today, date("2027-01-01")], Bucket::Day, today), [(today, 2)]);
+    assert!(buckets(&[], Bucket::Week, today).is_empty());
+}
+
+#[test]
+fn sparklines_scale_to_the_largest_count() {
+    assert_eq!(sparkline(&[0, 1, 4, 8]), "▁▂▅█");
+    assert_eq!(sparkline(&[0, 0]), "▁▁");
+    assert_eq!(sparkline(&[]), "");
+}
+
+#[test]
+fn bars_are_drawn_to_the_eighth_of_a_cell() {
+    assert_eq!(bar(4, 4, 3), "███");
+    assert_eq!(bar(1, 2, 3), "█▌");
+    assert_eq!(bar(1, 16, 1), "");
+    assert_eq!(bar(1, 0, 10), "");
+}
+
+#[test]
+fn bar_charts_fit_their_width_and_show_zeros() {
+    let rows = vec![("2026-10-14".to_string(), 2), ("2026-10-15".to_string(), 0), ("today".to_string(), 4)];
+    let lines = bar_chart(&rows, 20);
+    assert_eq!(lines, ["2026-10-14 ███▌ 2", "2026-10-15 0", "today      ███████ 4"]);
+    assert!(lines.iter().all(|line| line.chars().count() <= 20));
+}
+
+#[test]
+fn history_lists_a_fact_committed_now_under_today() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    fixture.open().unwrap().commit("added today".to_string(), vec![]).unwrap();
+    let output = wk(&fixture).args(["stats", "--history", "--bucket", "day", "--json"]).output().unwrap();
+    let rows: Vec<serde_json::Value> = serde_json::from_str(&stdout(&output)).unwrap();
+    let today = Local::now().date_naive().to_string();
+    assert_eq!(rows, [serde_json::json!({ "start": today, "count": 1 })]);
+}
@@ -0,0 +1,114 @@
+//! Fact files on disk: how they are written, read back and salvaged
+
+use twk::fixture::FixtureWiki;
+use twk::helpers::write_atomic;
+use twk::storage::recover_json;
+use twk::{Finding, Information, Repair, Wiki};
+use uuid::Uuid;
+
+fn fixture_file(name: &str) -> Vec<u8> {
+    std::fs::read(format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap()
+}
+
+#[test]
+fn the_last_of_appended_documents_is_recovered() {
+    // Two saves of one fact appended to each other, then a third cut short
+    let bytes = fixture_file("appended.json");
+    let info = recover_json(&bytes).unwrap();
+    assert_eq!(info.data, "Kettle descales every six weeks");
+    assert_eq!(info.tags, ["notes", "home"]);
+    assert!(serde_json::from_slice::<Information>(&bytes).is_err());
+
+    let complete = bytes.iter().rposition(|&b| b == b'}').unwrap();
+    let first_end = bytes.windows(2).position(|pair| pair == b"}{").unwrap();
+    assert_eq!(recover_json(&bytes[..=complete]).unwrap().data, info.data);
+    assert_eq!(recover_json(&bytes[..=first_end]).unwrap().data, "Kettle descales monthly");
+    assert!(recover_json(&bytes[..first_end]).is_none());
+    assert!(recover_json(b"").is_none());
+}
+
+#[test]
+fn doctor_recovers_an_appended_fact_file() {
+    let fixture = FixtureWiki::new().facts(1).build().unwrap();
+    let path = fixture.path().join("6f1c2b52-3c1e-4d3a-9a53-0b6f4c1d7e21.json");
+    std::fs::write(&path, fixture_file("appended.json")).unwrap();
+
+    let mut wiki = fixture.open().unwrap();
+    let findings = wiki.diagnose().unwrap();
+    assert!(matches!(&findings[..], [Finding::Corrupt { path: corrupt, .. }] if *corrupt == path), "{:?}", findings);
+    let repairs = wiki.fix(&findings).unwrap();
+    assert!(matches!(&repairs[..], [Repair::Recovered { .. }]), "{:?}", repairs);
+
+    let reopened = fixture.open().unwrap();
+    assert!(reopened.warnings.is_empty());
+    let recovered = reopened.get("6f1c2b52-3c1e-4d3a-9a53-0b6f4c1d7e21".parse().unwrap()).unwrap();
+    assert_eq!(recovered.data, "Kettle descales every six weeks");
+}
+
+#[test]
+fn a_shorter_write_leaves_nothing_of_the_longer_one() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("fact.json");
+    write_atomic(&path, "long ".repeat(1000).as_bytes()).unwrap();
+    write_atomic(&path, b"short").unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), b"short");
+
+    // Nor is the temp file left behind, written or not
+    assert!(write_atomic(&dir.path().join("missing").join("fact.json"), b"lost").is_err());
+    let names: Vec<_> = std::fs::read_dir(dir.path()).unwrap().flatten().map(|entry| entry.file_name()).collect();
+    assert_eq!(names, ["fact.json"]);
+}
+
+#[test]
+fn saving_a_shorter_fact_truncates_its_file() {
+    let fixture = FixtureWiki::new().facts(1).size(4000).build().unwrap();
+    let id = fixture.facts()[0].id;
+    let path = fixture.path().join(format!("{}.json", id));
+    let before = std::fs::metadata(&path).unwrap().len();
+
+    fixture.open().unwrap().update(id, |info| info.data = "brief".to_string()).unwrap();
+    let bytes = std::fs::read(&path).unwrap();
+    assert!((bytes.len() as u64) < before);
+    assert_eq!(serde_json::from_slice::<Information>(&bytes).unwrap().data, "brief");
+}
+
+fn order(wiki: &Wiki) -> Vec<Uuid> {
+    wiki.info.iter().map(|locked| locked.read().id).collect()
+}
+
+#[test]
+fn a_wiki_loads_in_the_same_order_every_time() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    // Facts created at the same moment, or at no known time, tie on `created`
+    let moments = [None, Some("2024-01-02T00:00:00Z"), Some("2024-01-01T00:00:00Z")];
+    let mut facts: Vec<Information> = (0..30)
+        .map(|n| {
+            let json = serde_json::json!({
+                "id": Uuid::new_v4(),
+                "tags": [],
+                "name": if n % 2 == 0 { format!("fact {}", n % 7) } else { format!("Fact {}", n % 5) },
+                "data": "ordered",
+                "created": moments[n % 3],
+            });
+            serde_json::from_value(json).unwrap()
+        })
+        .collect();
+    for info in &facts {
+        std::fs::write(fixture.path().join(format!("{}.json", info.id)), serde_json::to_vec(info).unwrap()).unwrap();
+    }
+
+    let first = order(&fixture.open().unwrap());
+    assert_eq!(first, order(&fixture.open().unwrap()));
+    facts.sort_by(|a, b| a.created.cmp(&b.created).then(a.id.cmp(&b.id)));
+    assert_eq!(first, facts.iter().map(|info| info.id).collect::<Vec<_>>());
+
+    std::fs::write(fixture.path().join("config.toml"), "order = \"name\"\n").unwrap();
+    let mut wiki = fixture.open().unwrap();
+    assert_eq!(order(&wiki), order(&fixture.open().unwrap()));
+    facts.sort_by(|a, b| a.name.to_lowercase().cmp(&b.name.to_lowercase()).then(a.id.cmp(&b.id)));
+    assert_eq!(order(&wiki), facts.iter().map(|info| info.id).collect::<Vec<_>>());
+
+    // New facts take their place rather than going on the end
+    wiki.commit("fact 3 and a bit".to_string(), vec![]).unwrap();
+    assert_eq!(order(&wiki), order(&fixture.open().unwrap()));
+}
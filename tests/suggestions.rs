@@ -0,0 +1,127 @@
+//! Suggestions for a recall that found nothing
+
+mod common;
+
+use common::{stderr, wk};
+use twk::fixture::{Fixture, FixtureWiki};
+use twk::storage::FsStorage;
+use twk::suggestions::WikiMatches;
+use twk::wikis::Location;
+use twk::{Fields, Wiki};
+
+fn notes() -> Fixture {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let mut wiki = fixture.open().unwrap();
+    let rust = vec!["rust".to_string()];
+    wiki.commit_named("Rust ownership".to_string(), "Each value has one owner".to_string(), rust.clone(), None).unwrap();
+    wiki.commit_named("Rust lifetimes".to_string(), "Borrows end before owners do".to_string(), rust, None).unwrap();
+    wiki.commit_named("Python GIL".to_string(), "One thread at a time".to_string(), vec!["python".to_string()], None)
+        .unwrap();
+    fixture
+}
+
+/// Another global wiki beside the fixture's, holding one fact
+fn other_wiki(fixture: &Fixture, name: &str, fact: &str) {
+    let path = fixture.data_dir().join(name);
+    std::fs::create_dir_all(&path).unwrap();
+    let mut wiki = Wiki::with_storage(name.to_string(), path.clone(), Box::new(FsStorage::new(&path))).unwrap();
+    wiki.commit_named(fact.to_string(), String::new(), vec![], None).unwrap();
+}
+
+#[test]
+fn tags_near_the_query_or_its_words() {
+    let fixture = notes();
+    let wiki = fixture.open().unwrap();
+    twk::set_data_dir(Some(fixture.data_dir()));
+
+    assert_eq!(wiki.suggestions("pythn", Fields::ALL, true).tags, ["python"]);
+    // A word holding a whole tag
+    assert_eq!(wiki.suggestions("rustlang tips", Fields::ALL, true).tags, ["rust"]);
+    assert!(wiki.suggestions("xylophone", Fields::ALL, true).is_empty());
+}
+
+#[test]
+fn facts_matching_most_words_almost_matched() {
+    let fixture = notes();
+    let wiki = fixture.open().unwrap();
+    twk::set_data_dir(Some(fixture.data_dir()));
+
+    let names = |query| {
+        let facts = wiki.suggestions(query, Fields::ALL, true).facts;
+        facts.into_iter().map(|fact| fact.name).collect::<Vec<_>>()
+    };
+    assert_eq!(names("ownership xylophone"), ["Rust ownership"]);
+    // Both words of one fact rank it over facts matching either
+    assert_eq!(names("lifetimes borrows xylophone")[0], "Rust lifetimes");
+    assert!(names("xylophone zebra").is_empty());
+}
+
+#[test]
+fn other_wikis_are_probed_by_name() {
+    let fixture = notes();
+    other_wiki(&fixture, "work", "Ownership of the build");
+    other_wiki(&fixture, "home", "Garden plans");
+    let wiki = fixture.open().unwrap();
+    twk::set_data_dir(Some(fixture.data_dir()));
+
+    let wikis = wiki.suggestions("ownership", Fields::ALL, true).wikis;
+    assert_eq!(wikis, [WikiMatches { name: "work".to_string(), location: Location::Global, facts: 1 }]);
+    // Data isn't read, only names
+    assert!(wiki.suggestions("thread", Fields::ALL, true).wikis.is_empty());
+}
+
+#[test]
+fn suggestions_are_only_for_people() {
+    let fixture = notes();
+    other_wiki(&fixture, "work", "Ownership xylophone");
+
+    let output = wk(&fixture).args(["r", "ownership xylophone"]).output().unwrap();
+    assert!(output.status.success());
+    let said = stderr(&output);
+    assert!(said.contains("No matching facts found"), "{}", said);
+    assert!(said.contains("Suggestions:"), "{}", said);
+    assert!(said.contains("almost matched: Rust ownership"), "{}", said);
+    assert!(said.contains("in global wiki work"), "{}", said);
+
+    for args in [&["-q", "r", "ownership xylophone"][..], &["r", "ownership xylophone", "--format", "vimgrep"]] {
+        let output = wk(&fixture).args(args).output().unwrap();
+        assert!(!stderr(&output).contains("Suggestions"), "{:?}: {}", args, stderr(&output));
+    }
+    // Nothing to suggest says nothing more
+    let output = wk(&fixture).args(["r", "zebra"]).output().unwrap();
+    assert!(!stderr(&output).contains("Suggestions"), "{}", stderr(&output));
+}
+
+#[test]
+fn near_misses_are_matched_as_recall_matches() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let mut wiki = fixture.open().unwrap();
+    wiki.commit("Resume sent to Acme".to_string(), vec![]).unwrap();
+    wiki.commit("RUST builds are slow".to_string(), vec!["Rust".to_string()]).unwrap();
+    // How alike the most similar fact is, 1 for the same text as matched
+    let similarity = |wiki: &Wiki, text: &str| wiki.find_similar(text, 0.0).first().map_or(0.0, |(_, s)| *s);
+    let config = |toml: &str| {
+        std::fs::write(fixture.path().join("config.toml"), toml).unwrap();
+        fixture.open().unwrap()
+    };
+
+    // By default case is ignored and accents kept
+    assert_eq!(similarity(&wiki, "resume SENT to acme"), 1.0);
+    assert_eq!(similarity(&wiki, "Résumé sent to Acme"), 0.0);
+    assert_eq!(wiki.suggestions("RUST", Fields::ALL, false).tags, ["Rust"]);
+
+    let folded = config("[match]\nfold_diacritics = true\n");
+    assert_eq!(similarity(&folded, "Résumé sent to Acme"), 1.0);
+
+    let sensitive = config("[match]\ncase = \"sensitive\"\n");
+    assert_eq!(similarity(&sensitive, "resume SENT to acme"), 0.0);
+    assert!(sensitive.suggestions("rust", Fields::ALL, false).tags.is_empty());
+    assert_eq!(sensitive.suggestions("Rust", Fields::ALL, false).tags, ["Rust"]);
+
+    // Smart case only respects it in texts with a capital
+    let smart = config("[match]\ncase = \"smart\"\n");
+    assert_eq!(similarity(&smart, "resume sent to acme"), 1.0);
+    assert_eq!(similarity(&smart, "resume SENT to acme"), 0.0);
+    assert_eq!(smart.suggestions("rust", Fields::ALL, false).tags, ["Rust"]);
+    assert!(smart.suggestions("RUST", Fields::ALL, false).tags.is_empty());
+}
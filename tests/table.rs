@@ -0,0 +1,148 @@
+//! Laying out columns by display width, with wide characters in them
+
+use chrono::{TimeDelta, TimeZone, Utc};
+use colored::Color;
+use twk::table::{AGE_WIDTH, Cell, Table, Word, age_at, truncate, wrap};
+use unicode_width::UnicodeWidthStr;
+
+/// `table` as it would print in `width` columns, without colour
+fn render(table: &Table, width: Option<usize>) -> Vec<String> {
+    colored::control::set_override(false);
+    table.render(width)
+}
+
+fn names(names: &[&str]) -> Table {
+    let mut table = Table::new(&["NAME", "TAGS"]);
+    for (n, name) in names.iter().enumerate() {
+        table.row(vec![Cell::new(*name), Cell::new(format!("t{}", n))]);
+    }
+    table
+}
+
+/// How far in each line its last column starts, in display columns
+fn last_column(lines: &[String]) -> Vec<usize> {
+    lines.iter().map(|line| line[..line.rfind(' ').unwrap() + 1].width()).collect()
+}
+
+#[test]
+fn wide_characters_line_up_by_display_width() {
+    let lines = render(&names(&["日本語", "🦀 crab", "plain", "café"]), None);
+    assert_eq!(lines.len(), 5);
+    // The widest name is 🦀 crab at 7 columns, then two spaces
+    assert!(last_column(&lines).iter().all(|&at| at == 9), "{:#?}", lines);
+    assert_eq!(lines[1], "日本語   t0");
+    assert_eq!(lines[2], "🦀 crab  t1");
+}
+
+#[test]
+fn cells_show_one_line_with_tabs_as_spaces() {
+    let lines = render(&names(&["first\tline\nsecond line", "x"]), None);
+    assert_eq!(lines[1], "first line  t0");
+    assert!(last_column(&lines).iter().all(|&at| at == 12), "{:#?}", lines);
+}
+
+#[test]
+fn the_flex_column_shrinks_to_fit() {
+    let long = "長い名前の事実がここにあります and then some more";
+    let table = names(&[long, "短い"]).flex(0);
+
+    // Off a terminal nothing is cut
+    assert!(render(&table, None)[1].contains(long));
+
+    let lines = render(&table, Some(24));
+    assert!(lines.iter().all(|line| line.width() <= 24), "{:#?}", lines);
+    assert!(lines[1].contains('…'), "{:#?}", lines);
+    let columns = last_column(&lines);
+    assert!(columns.iter().all(|&at| at == columns[0]), "{:#?}", lines);
+
+    // However narrow, it keeps a few columns
+    let lines = render(&table, Some(4));
+    assert_eq!(lines[1][..lines[1].find('…').unwrap()].width(), 6, "{:#?}", lines);
+    assert!(last_column(&lines).iter().all(|&at| at == 10), "{:#?}", lines);
+}
+
+#[test]
+fn truncating_never_splits_a_wide_character() {
+    assert_eq!(truncate("日本語テキスト", 6), "日本…");
+    assert_eq!(truncate("日本語テキスト", 5), "日本…");
+    assert_eq!(truncate("🦀🦀🦀", 4), "🦀…");
+    assert_eq!(truncate("🦀🦀🦀", 2), "…");
+    assert_eq!(truncate("🦀🦀🦀", 0), "");
+    for width in 0..16 {
+        assert!(truncate("日本語 and 🦀 text", width).width() <= width, "{}", width);
+    }
+}
+
+#[test]
+fn text_that_fits_is_left_alone() {
+    assert_eq!(truncate("日本語", 6), "日本語");
+    assert_eq!(truncate("", 0), "");
+}
+
+/// `text`'s words to [`wrap`], without colour
+fn words(text: &str) -> Vec<Word> {
+    colored::control::set_override(false);
+    text.split(' ').map(|word| Word::new(word, Color::White)).collect()
+}
+
+#[test]
+fn wrapping_breaks_between_words_with_a_hanging_indent() {
+    let lines = wrap(vec![words("the quick brown fox jumps")], 10, 2);
+    assert_eq!(lines, ["the quick", "  brown", "  fox", "  jumps"]);
+
+    // Every entry starts a line, hanging off the first
+    let tags = vec![Word::bracketed("rust", Color::Blue, "[", "]"), Word::bracketed("cli", Color::Blue, "[", "]")];
+    assert_eq!(wrap(vec![words("a fact"), tags], 20, 2), ["a fact", "  [rust] [cli]"]);
+    assert_eq!(wrap(vec![words("a"), vec![], words("b")], 10, 2), ["a", "", "  b"]);
+}
+
+#[test]
+fn words_too_long_for_a_line_are_split() {
+    assert_eq!(wrap(vec![words("abcdefghijkl")], 5, 2), ["abcde", "  fgh", "  ijk", "  l"]);
+    // Between wide characters, never through one
+    let lines = wrap(vec![words("日本語テキスト")], 5, 0);
+    assert_eq!(lines, ["日本", "語テ", "キス", "ト"]);
+    // But not tags, which stay whole however wide
+    let tag = Word::bracketed("a-very-long-tag", Color::Blue, "[", "]");
+    assert_eq!(wrap(vec![words("x"), vec![tag]], 10, 2), ["x", "  [a-very-long-tag]"]);
+}
+
+#[test]
+fn wrapped_lines_fit_by_display_width() {
+    let text = "日本語 と English が混ざった 🦀 文章 with café and naïve words";
+    for width in 6..40 {
+        let lines = wrap(vec![words(text)], width, 4);
+        assert!(lines.iter().all(|line| line.width() <= width), "{}: {:#?}", width, lines);
+        let joined: String = lines.concat().split_whitespace().collect();
+        assert_eq!(joined, text.split_whitespace().collect::<String>());
+    }
+    // The indent never takes more than half the line
+    assert_eq!(wrap(vec![words("aaaa bbbb")], 6, 10), ["aaaa", "   bbb", "   b"]);
+}
+
+#[test]
+fn ages_are_in_the_largest_whole_unit() {
+    let now = Utc.with_ymd_and_hms(2024, 3, 31, 12, 0, 0).unwrap();
+    let age = |delta: TimeDelta| age_at(now - delta, now);
+    let (minute, hour, day) = (TimeDelta::minutes(1), TimeDelta::hours(1), TimeDelta::days(1));
+    let cases = [
+        (TimeDelta::zero(), "now"),
+        (TimeDelta::seconds(59), "now"),
+        (minute, "1m"),
+        (hour - TimeDelta::seconds(1), "59m"),
+        (hour, "1h"),
+        (day - TimeDelta::seconds(1), "23h"),
+        (day, "1d"),
+        (day * 29, "29d"),
+        (day * 30, "1mo"),
+        (day * 364, "12mo"),
+        (day * 365, "1y"),
+        (day * 365 * 99, "99y"),
+    ];
+    for (ago, expected) in cases {
+        assert_eq!(age(ago), expected, "{:?}", ago);
+        assert!(age(ago).len() <= AGE_WIDTH);
+    }
+    // A clock running behind makes nothing negative
+    assert_eq!(age(-hour), "now");
+}
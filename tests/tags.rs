@@ -0,0 +1,174 @@
+//! Tag rules and colours from a wiki's config
+
+use std::collections::BTreeMap;
+use twk::fixture::{Fixture, FixtureWiki};
+use twk::tags::{Selection, TAG_PALETTE, TagColor, tag_color};
+use twk::{Wiki, WikiError};
+
+#[test]
+fn every_commit_gets_default_tags() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    std::fs::write(fixture.path().join("config.toml"), "default_tags = [\"recipe\", \"food\"]\n").unwrap();
+    let mut wiki = fixture.open().unwrap();
+
+    let one = wiki.commit("bread".to_string(), vec!["food".to_string()]).unwrap();
+    assert_eq!(wiki.get(one).unwrap().tags, ["food", "recipe"]);
+    let many = wiki.commit_many(vec![("soup".to_string(), vec![]), ("cake".to_string(), vec!["sweet".to_string()])]).unwrap();
+    assert_eq!(wiki.get(many[0]).unwrap().tags, ["recipe", "food"]);
+    assert_eq!(wiki.get(many[1]).unwrap().tags, ["sweet", "recipe", "food"]);
+
+    wiki.default_tags = false;
+    let bare = wiki.commit("water".to_string(), vec![]).unwrap();
+    assert!(wiki.get(bare).unwrap().tags.is_empty());
+}
+
+#[test]
+fn untagged_commits_are_refused_when_required() {
+    let fixture = FixtureWiki::new().facts(2).build().unwrap();
+    std::fs::write(fixture.path().join("config.toml"), "require_tags = true\n").unwrap();
+    let mut wiki = fixture.open().unwrap();
+
+    let refused = wiki.commit("untagged".to_string(), vec![]).unwrap_err();
+    assert!(matches!(&refused, WikiError::TagsRequired { wiki, config: Some(_) } if wiki == "fixture"), "{:?}", refused);
+    // One untagged fact refuses the whole batch
+    let batch = vec![("tagged".to_string(), vec!["a".to_string()]), ("untagged".to_string(), vec![])];
+    assert!(matches!(wiki.commit_many(batch), Err(WikiError::TagsRequired { .. })));
+    assert_eq!(wiki.info.len(), 2);
+
+    assert!(wiki.commit("tagged".to_string(), vec!["a".to_string()]).is_ok());
+    // Changing an existing fact's tags isn't a commit
+    assert!(wiki.retag(fixture.facts()[0].id, vec![]).is_ok());
+}
+
+#[test]
+fn tag_colors_are_stable() {
+    let none = BTreeMap::new();
+    // Pinned, so a change to the hash shows up here rather than as every
+    // tag changing colour after an upgrade
+    let expected = [
+        ("bug", TagColor::LightGreen),
+        ("idea", TagColor::LightMagenta),
+        ("todo", TagColor::LightCyan),
+        ("lang/rust", TagColor::LightMagenta),
+        ("日本語", TagColor::LightBlue),
+    ];
+    for (tag, color) in expected {
+        assert_eq!(tag_color(tag, &none), color, "{}", tag);
+    }
+    for i in 0..100 {
+        let tag = format!("tag{}", i);
+        assert!(TAG_PALETTE.contains(&tag_color(&tag, &none)));
+    }
+    // Spread over the whole palette
+    let used: std::collections::HashSet<_> = (0..100).map(|i| tag_color(&format!("tag{}", i), &none)).collect();
+    assert_eq!(used.len(), TAG_PALETTE.len());
+}
+
+#[test]
+fn configured_tag_colors_win() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    std::fs::write(fixture.path().join("config.toml"), "[tag_colors]\nbug = \"red\"\nlang = \"light_blue\"\n").unwrap();
+    let colors = fixture.open().unwrap().config.tag_colors.clone();
+    assert_eq!(tag_color("bug", &colors), TagColor::Red);
+    // Nested tags take the colour of the nearest configured parent
+    assert_eq!(tag_color("lang/rust", &colors), TagColor::LightBlue);
+    assert_eq!(tag_color("language", &colors), tag_color("language", &BTreeMap::new()));
+
+    // An unknown colour makes the config invalid
+    std::fs::write(fixture.path().join("config.toml"), "[tag_colors]\nbug = \"chartreuse\"\n").unwrap();
+    let wiki = fixture.open().unwrap();
+    assert!(wiki.config.tag_colors.is_empty());
+    assert!(!wiki.warnings.is_empty());
+}
+
+#[test]
+fn bulk_retag_changes_only_what_it_has_to() {
+    let fixture = FixtureWiki::new().facts(9).tags(3).build().unwrap();
+    std::fs::write(fixture.path().join("config.toml"), "[aliases]\nold = \"tag1\"\n").unwrap();
+    let mut wiki = fixture.open().unwrap();
+    let tags = |wiki: &twk::Wiki, i: usize| wiki.get(fixture.facts()[i].id).unwrap().tags;
+
+    // Removing an alias takes off the tag it stands for
+    let by_tag = Selection { tag: Some("tag1".to_string()), ..Default::default() };
+    let picked = wiki.bulk_retag(&by_tag, &["archive".to_string()], &["old".to_string()]).unwrap();
+    assert_eq!(picked.len(), 3);
+    assert!(picked.iter().all(|(before, after)| before.tags == ["tag1"] && after.tags == ["archive"]));
+    assert_eq!((tags(&wiki, 1), tags(&wiki, 0)), (vec!["archive".to_string()], vec!["tag0".to_string()]));
+
+    // Facts already tagged as asked aren't written
+    let by_query = Selection { query: Some("Fact".to_string()), limit: Some(4), ..Default::default() };
+    let updated = wiki.get(fixture.facts()[0].id).unwrap().updated;
+    wiki.bulk_retag(&by_query, &["tag0".to_string()], &[]).unwrap();
+    let picked = wiki.bulk_retag(&by_query, &["tag0".to_string()], &[]).unwrap();
+    assert_eq!(picked.len(), 4);
+    assert!(picked.iter().all(|(before, after)| before == after));
+    let nothing = Selection { query: Some("zzqxv".to_string()), ..Default::default() };
+    assert!(wiki.bulk_retag(&nothing, &["x".to_string()], &[]).unwrap().is_empty());
+    assert_eq!(wiki.get(fixture.facts()[0].id).unwrap().updated, updated);
+}
+
+/// A wiki of facts filed under a few tags, the same every time
+fn corpus() -> (Fixture, Wiki) {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let mut wiki = fixture.open().unwrap();
+    for (fact, tag) in [
+        ("Rust borrow checker rules", "lang/rust"),
+        ("Rust lifetimes explained", "lang/rust"),
+        ("Docker compose restart policy", "devops/docker"),
+        ("Kubernetes rollout undo", "devops/k8s"),
+        ("Postgres vacuum tuning", "db/postgres"),
+        ("Postgres index bloat after vacuum", "db/postgres"),
+    ] {
+        wiki.commit(fact.to_string(), vec![tag.to_string()]).unwrap();
+    }
+    (fixture, wiki)
+}
+
+fn suggested(wiki: &Wiki, text: &str) -> Vec<String> {
+    wiki.suggest_tags(text, 10).into_iter().map(|(tag, _)| tag).collect()
+}
+
+#[test]
+fn tags_named_in_the_text_are_suggested_first() {
+    let (_fixture, wiki) = corpus();
+    assert_eq!(suggested(&wiki, "restart the docker daemon"), ["devops/docker"]);
+    // Any segment of the tag counts
+    assert_eq!(suggested(&wiki, "rust macros"), ["lang/rust"]);
+    assert_eq!(suggested(&wiki, "devops runbook"), ["devops/docker", "devops/k8s"]);
+
+    // A segment alone scores 2, squashed to 2 / (1 + 2)
+    let scored = wiki.suggest_tags("a lang to learn", 10);
+    assert_eq!(scored.len(), 1);
+    assert!((scored[0].1 - 2.0 / 3.0).abs() < 1e-6, "{:?}", scored);
+}
+
+#[test]
+fn near_matches_and_words_from_fact_names_score_less() {
+    let (_fixture, wiki) = corpus();
+    let exact = wiki.suggest_tags("docker", 1)[0].1;
+    let near = wiki.suggest_tags("write a dockerfile", 10);
+    assert_eq!(near[0].0, "devops/docker");
+    assert!(near[0].1 < exact, "{} against {}", near[0].1, exact);
+
+    // Only the facts' names tie `vacuum` and `lifetimes` to their tags
+    assert_eq!(suggested(&wiki, "vacuum full locks the table"), ["db/postgres"]);
+    assert_eq!(suggested(&wiki, "lifetimes"), ["lang/rust"]);
+    // A word in two of a tag's names scores more than one in one
+    let vacuum = wiki.suggest_tags("vacuum", 1)[0].1;
+    let bloat = wiki.suggest_tags("bloat", 1)[0].1;
+    assert!(vacuum > bloat, "{} against {}", vacuum, bloat);
+}
+
+#[test]
+fn suggestions_are_ranked_bounded_and_limited() {
+    let (_fixture, wiki) = corpus();
+    let scored = wiki.suggest_tags("rust in docker on kubernetes rollout with postgres", 10);
+    assert_eq!(scored.len(), 4);
+    assert!(scored.windows(2).all(|pair| pair[0].1 >= pair[1].1), "{:?}", scored);
+    assert!(scored.iter().all(|&(_, score)| score > 0.0 && score < 1.0), "{:?}", scored);
+    assert_eq!(wiki.suggest_tags("rust in docker on kubernetes rollout with postgres", 2), scored[..2]);
+
+    assert!(wiki.suggest_tags("nothing relevant here", 10).is_empty());
+    assert!(wiki.suggest_tags("", 10).is_empty());
+    assert!(wiki.suggest_tags("a b c", 10).is_empty());
+}
@@ -0,0 +1,430 @@
+//! The TUI's keymap and update function, without a terminal
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::layout::Rect;
+use twk::app::{App, AppMsg, Effect, InputMode, split_panes};
+use twk::editor::Edited;
+use twk::fixture::{Fixture, FixtureWiki};
+
+fn app(fixture: &Fixture) -> App {
+    App::new(fixture.open().unwrap(), true, None, true)
+}
+
+/// Press `key` as the event loop would, returning the effects left to it
+fn press(app: &mut App, key: KeyCode) -> Vec<Effect> {
+    match app.key_msg(KeyEvent::from(key)) {
+        Some(msg) => app.update(msg),
+        None => Vec::new(),
+    }
+}
+
+fn type_text(app: &mut App, text: &str) {
+    for c in text.chars() {
+        press(app, KeyCode::Char(c));
+    }
+}
+
+/// Run a `:` command
+fn command(app: &mut App, command: &str) -> Vec<Effect> {
+    press(app, KeyCode::Char(':'));
+    type_text(app, command);
+    press(app, KeyCode::Enter)
+}
+
+fn selected_name(app: &App) -> &str {
+    &app.items[app.state.selected().unwrap()].0
+}
+
+fn names(app: &App) -> Vec<&str> {
+    app.items.iter().map(|item| item.0.as_str()).collect()
+}
+
+#[test]
+fn navigation_wraps_around() {
+    let fixture = FixtureWiki::new().facts(3).build().unwrap();
+    let mut app = app(&fixture);
+    assert_eq!(selected_name(&app), "Fact 0");
+
+    press(&mut app, KeyCode::Char('j'));
+    press(&mut app, KeyCode::Down);
+    assert_eq!(selected_name(&app), "Fact 2");
+    press(&mut app, KeyCode::Char('j'));
+    assert_eq!(selected_name(&app), "Fact 0");
+    press(&mut app, KeyCode::Up);
+    assert_eq!(selected_name(&app), "Fact 2");
+
+    app.update(AppMsg::Select(1));
+    assert_eq!(selected_name(&app), "Fact 1");
+    // Clicks below the last fact select nothing new
+    app.update(AppMsg::Select(7));
+    assert_eq!(selected_name(&app), "Fact 1");
+}
+
+#[test]
+fn modes_are_entered_and_left() {
+    let fixture = FixtureWiki::new().facts(3).build().unwrap();
+    let mut app = app(&fixture);
+
+    press(&mut app, KeyCode::Char(':'));
+    assert_eq!((app.input_mode, app.input.as_str()), (InputMode::Command, ":"));
+    // Keys are typed rather than acted on
+    press(&mut app, KeyCode::Char('j'));
+    assert_eq!((app.input.as_str(), selected_name(&app)), (":j", "Fact 0"));
+    press(&mut app, KeyCode::Esc);
+    assert_eq!((app.input_mode, app.input.as_str()), (InputMode::Normal, ""));
+    // Backspacing over the colon leaves command mode too
+    press(&mut app, KeyCode::Char(':'));
+    press(&mut app, KeyCode::Backspace);
+    assert_eq!(app.input_mode, InputMode::Normal);
+
+    press(&mut app, KeyCode::Char('t'));
+    assert_eq!(app.input_mode, InputMode::Tags);
+    press(&mut app, KeyCode::Char('q'));
+    assert_eq!(app.input_mode, InputMode::Normal);
+
+    press(&mut app, KeyCode::F(1));
+    assert!(app.show_help);
+    // Other keys do nothing while help is shown
+    assert!(app.key_msg(KeyEvent::from(KeyCode::Char('j'))).is_none());
+    press(&mut app, KeyCode::Esc);
+    assert!(!app.show_help);
+
+    // Any key closes a popup, and does nothing else
+    press(&mut app, KeyCode::Char('I'));
+    assert_eq!(app.detail_popup.as_ref().map(|info| info.name.as_str()), Some("Fact 0"));
+    press(&mut app, KeyCode::Char('j'));
+    assert!(app.detail_popup.is_none());
+    assert_eq!(selected_name(&app), "Fact 0");
+}
+
+#[test]
+fn commands_come_back_from_history() {
+    let fixture = FixtureWiki::new().facts(3).build().unwrap();
+    let mut app = app(&fixture);
+    command(&mut app, "cols date");
+    command(&mut app, "sort modified");
+
+    press(&mut app, KeyCode::Char(':'));
+    press(&mut app, KeyCode::Up);
+    assert_eq!(app.input, ":sort modified");
+    press(&mut app, KeyCode::Up);
+    press(&mut app, KeyCode::Up);
+    assert_eq!(app.input, ":cols date");
+    press(&mut app, KeyCode::Down);
+    press(&mut app, KeyCode::Down);
+    assert_eq!(app.input, "");
+}
+
+#[test]
+fn filters_narrow_the_list() {
+    let fixture = FixtureWiki::new().facts(12).tags(3).build().unwrap();
+    let mut app = app(&fixture);
+
+    command(&mut app, "s re:^Fact 1");
+    assert_eq!(names(&app), ["Fact 1", "Fact 10", "Fact 11"]);
+    command(&mut app, "s name:re:0$");
+    assert_eq!(names(&app), ["Fact 0", "Fact 10"]);
+    command(&mut app, "s re:(");
+    assert!(app.status_msg.starts_with("Invalid regex"), "{}", app.status_msg);
+    command(&mut app, "s");
+    assert_eq!(app.items.len(), 12);
+
+    // Fuzzy matches come best first
+    command(&mut app, "s name:Fact 11");
+    assert_eq!(names(&app)[0], "Fact 11");
+    command(&mut app, "s");
+
+    press(&mut app, KeyCode::Char('t'));
+    press(&mut app, KeyCode::Char('j'));
+    press(&mut app, KeyCode::Enter);
+    assert_eq!((app.input_mode, app.tag_filter.as_deref()), (InputMode::Normal, Some("tag1")));
+    assert_eq!(names(&app), ["Fact 1", "Fact 4", "Fact 7", "Fact 10"]);
+    press(&mut app, KeyCode::Char('t'));
+    press(&mut app, KeyCode::Backspace);
+    assert_eq!((app.tag_filter.as_deref(), app.items.len()), (None, 12));
+
+    command(&mut app, "recent 2");
+    assert_eq!(names(&app), ["Fact 11", "Fact 10"]);
+    command(&mut app, "recent off");
+    assert_eq!(app.items.len(), 12);
+}
+
+#[test]
+fn inline_edits_are_saved_or_cancelled() {
+    let fixture = FixtureWiki::new().facts(2).build().unwrap();
+    let id = fixture.facts()[0].id;
+    let mut app = app(&fixture);
+
+    press(&mut app, KeyCode::Char('i'));
+    assert_eq!(app.input_mode, InputMode::Edit);
+    assert_eq!(app.edit_buffer, fixture.facts()[0].data);
+    press(&mut app, KeyCode::Esc);
+    assert_eq!((app.input_mode, app.edit_buffer.as_str()), (InputMode::Normal, ""));
+
+    press(&mut app, KeyCode::Char('i'));
+    app.edit_buffer.clear();
+    type_text(&mut app, "rewritten");
+    press(&mut app, KeyCode::Enter);
+    type_text(&mut app, "q");
+    assert_eq!(app.input_mode, InputMode::Edit, "q is typed, not quit");
+    let save = app.key_msg(KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL)).unwrap();
+    assert!(app.update(save).is_empty());
+    assert_eq!(app.input_mode, InputMode::Normal);
+    assert_eq!(app.wiki.get(id).unwrap().data, "rewritten\nq");
+}
+
+#[test]
+fn external_edits_round_trip_through_effects() {
+    let fixture = FixtureWiki::new().facts(2).build().unwrap();
+    let mut app = app(&fixture);
+
+    let effects = press(&mut app, KeyCode::Enter);
+    let [Effect::OpenEditor(info)] = effects.as_slice() else {
+        panic!("{:?}", effects);
+    };
+    assert_eq!(info, &fixture.facts()[0]);
+
+    let edited = Edited { title: "Renamed".to_string(), body: "new body".to_string(), ..Default::default() };
+    app.update(AppMsg::Edited { id: info.id, read_updated: info.updated, edited });
+    let saved = app.wiki.get(info.id).unwrap();
+    assert_eq!((saved.name.as_str(), saved.data.as_str()), ("Renamed", "new body"));
+    assert_eq!(app.status_msg, "Saved from editor");
+
+    // Saving over a change made while the editor was open asks first
+    let edited = Edited { body: "stale".to_string(), ..Default::default() };
+    app.update(AppMsg::Edited { id: info.id, read_updated: info.updated, edited });
+    assert!(app.asking());
+    press(&mut app, KeyCode::Esc);
+    assert_eq!(app.wiki.get(info.id).unwrap().data, "new body");
+    assert!(app.status_msg.starts_with("Not saved"), "{}", app.status_msg);
+}
+
+#[test]
+fn side_effects_are_left_to_the_loop() {
+    let fixture = FixtureWiki::new().facts(2).build().unwrap();
+    twk::set_data_dir(Some(fixture.data_dir()));
+    let mut app = app(&fixture);
+
+    assert_eq!(press(&mut app, KeyCode::Char('q')), [Effect::Quit]);
+    assert_eq!(command(&mut app, "quit"), [Effect::Quit]);
+    assert_eq!(command(&mut app, "reindex"), [Effect::Reindex]);
+    assert!(command(&mut app, "log").is_empty());
+    assert!(app.status_msg.starts_with("Not logging"));
+
+    // Opening an existing wiki is the loop's job; a missing one is asked about first
+    assert_eq!(command(&mut app, "wiki fixture"), [Effect::OpenWiki("fixture".to_string())]);
+    assert!(command(&mut app, "wiki nowhere").is_empty());
+    assert!(app.asking());
+    assert_eq!(press(&mut app, KeyCode::Char('y')), [Effect::OpenWiki("nowhere".to_string())]);
+    assert!(!app.asking());
+
+    app.update(AppMsg::WikiOpened(Ok(Box::new(fixture.open().unwrap()))));
+    assert_eq!(app.status_msg, "Switched to wiki: fixture");
+}
+
+#[test]
+fn hard_deletes_wait_for_yes() {
+    let fixture = FixtureWiki::new().facts(2).build().unwrap();
+    let mut app = app(&fixture);
+
+    command(&mut app, "delete --hard");
+    assert!(app.asking());
+    press(&mut app, KeyCode::Char('n'));
+    assert_eq!((app.status_msg.as_str(), app.items.len()), ("Delete cancelled", 2));
+
+    command(&mut app, "delete --hard");
+    press(&mut app, KeyCode::Char('y'));
+    assert_eq!(names(&app), ["Fact 1"]);
+}
+
+#[test]
+fn opened_facts_sort_first_by_frecency() {
+    let fixture = FixtureWiki::new().facts(3).build().unwrap();
+    let mut untracked = app(&fixture);
+    command(&mut untracked, "sort frecency");
+    assert!(untracked.status_msg.contains("track_usage"), "{}", untracked.status_msg);
+
+    std::fs::write(fixture.path().join("config.toml"), "track_usage = true\n").unwrap();
+    let mut app = app(&fixture);
+    press(&mut app, KeyCode::Char('j'));
+    press(&mut app, KeyCode::Char('j'));
+    press(&mut app, KeyCode::Char('I'));
+    press(&mut app, KeyCode::Esc);
+    command(&mut app, "sort frecency");
+    assert_eq!(names(&app), ["Fact 2", "Fact 0", "Fact 1"]);
+    command(&mut app, "sort default");
+    assert_eq!(names(&app), ["Fact 0", "Fact 1", "Fact 2"]);
+}
+
+#[test]
+fn previews_show_the_line_filtered_for() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let mut wiki = fixture.open().unwrap();
+    wiki.commit_named("Rust".to_string(), "# Rust\n\nownership\nborrow checker".to_string(), vec![], None).unwrap();
+    let mut app = App::new(wiki, true, None, true);
+    assert_eq!(app.items[0].1, "ownership");
+
+    command(&mut app, "s data:re:check");
+    assert_eq!(app.items[0].1, "borrow checker");
+    command(&mut app, "s");
+    assert_eq!(app.items[0].1, "ownership");
+}
+
+#[test]
+fn searches_that_find_nothing_offer_to_create() {
+    let fixture = FixtureWiki::new().facts(3).build().unwrap();
+    let mut app = app(&fixture);
+
+    // Only a fuzzy search with no results at all
+    command(&mut app, "s Fact");
+    assert_eq!(app.create_hint(), None);
+    command(&mut app, "s re:^zzzz");
+    assert!(app.items.is_empty());
+    assert_eq!(app.create_hint(), None);
+
+    command(&mut app, "s rotate zzqx keys");
+    assert_eq!(app.create_hint(), Some("rotate zzqx keys"));
+    press(&mut app, KeyCode::Enter);
+    assert_eq!(app.input_mode, InputMode::Edit);
+    // The filter is cleared
+    assert_eq!(app.items.len(), 4);
+    assert_eq!(selected_name(&app), "rotate zzqx keys");
+    assert!(app.status_msg.contains("Created 'rotate zzqx keys'"), "{}", app.status_msg);
+
+    // As with `:n`, leaving the editor keeps the empty fact
+    press(&mut app, KeyCode::Esc);
+    assert_eq!(app.input_mode, InputMode::Normal);
+    let id = app.items[app.state.selected().unwrap()].3;
+    assert!(fixture.open().unwrap().get(id).unwrap().data.is_empty());
+}
+
+#[test]
+fn split_panes_keep_their_own_state_and_trade_facts() {
+    let fixture = FixtureWiki::new().facts(3).build().unwrap();
+    twk::set_data_dir(Some(fixture.data_dir()));
+    let mut other = twk::Wiki::load_or_create("other".to_string(), true).unwrap();
+    other.commit("Elsewhere".to_string(), vec![]).unwrap();
+    let mut app = app(&fixture);
+
+    press(&mut app, KeyCode::Tab);
+    assert!(app.status_msg.starts_with("Only one pane"), "{}", app.status_msg);
+    assert!(command(&mut app, "split fixture").is_empty());
+    assert!(command(&mut app, "split nowhere").is_empty());
+    assert_eq!(command(&mut app, "split other"), [Effect::OpenSplit("other".to_string())]);
+    app.update(AppMsg::SplitOpened(Ok(Box::new(other))));
+    assert_eq!((app.wiki.name.as_str(), app.first), ("fixture", true));
+
+    // Each pane keeps its own selection
+    press(&mut app, KeyCode::Char('j'));
+    press(&mut app, KeyCode::Tab);
+    assert_eq!((app.wiki.name.as_str(), app.first), ("other", false));
+    assert_eq!(selected_name(&app), "Elsewhere");
+    assert!(command(&mut app, "wiki fixture").is_empty());
+    assert!(app.status_msg.contains("open in the other pane"), "{}", app.status_msg);
+    let prefix = app.key_msg(KeyEvent::new(KeyCode::Char('w'), KeyModifiers::CONTROL)).unwrap();
+    app.update(prefix);
+    press(&mut app, KeyCode::Char('w'));
+    assert_eq!(selected_name(&app), "Fact 1");
+
+    let id = app.items[1].3;
+    command(&mut app, "mv-to-other");
+    assert_eq!(names(&app), ["Fact 0", "Fact 2"]);
+    let there = app.other.as_ref().unwrap();
+    assert!(names(there).contains(&"Fact 1"), "{:?}", names(there));
+    assert_eq!(there.wiki.get(id).unwrap().name, "Fact 1");
+    assert!(fixture.open().unwrap().get(id).is_err());
+
+    command(&mut app, "only");
+    assert!(app.other.is_none());
+    press(&mut app, KeyCode::Tab);
+    assert_eq!(app.wiki.name, "fixture");
+}
+
+#[test]
+fn narrow_terminals_stack_panes() {
+    assert_eq!(split_panes(Rect::new(0, 0, 120, 40)), (Rect::new(0, 0, 60, 40), Rect::new(60, 0, 60, 40)));
+    assert_eq!(split_panes(Rect::new(0, 0, 80, 40)), (Rect::new(0, 0, 80, 20), Rect::new(0, 20, 80, 20)));
+}
+
+/// Save the inline editor with Ctrl-S
+fn save(app: &mut App) {
+    let save = app.key_msg(KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL)).unwrap();
+    app.update(save);
+}
+
+#[test]
+fn edits_to_facts_saved_elsewhere_ask_first() {
+    let fixture = FixtureWiki::new().facts(1).build().unwrap();
+    let id = fixture.facts()[0].id;
+    let mut app = app(&fixture);
+    // As another TUI on the same wiki would, between loading and saving here
+    let save_elsewhere = |text: &str| {
+        fixture.open().unwrap().update(id, |info| info.data = text.to_string()).unwrap();
+    };
+    let on_disk = || fixture.open().unwrap().get(id).unwrap().data;
+
+    press(&mut app, KeyCode::Char('i'));
+    save_elsewhere("theirs 1");
+    app.edit_buffer = "mine 1".to_string();
+    save(&mut app);
+    assert!(app.asking(), "{}", app.status_msg);
+    // Saying no goes back to the editor with nothing lost
+    press(&mut app, KeyCode::Char('n'));
+    assert_eq!((app.input_mode, app.edit_buffer.as_str()), (InputMode::Edit, "mine 1"));
+    save(&mut app);
+    press(&mut app, KeyCode::Char('o'));
+    assert_eq!(on_disk(), "mine 1");
+
+    press(&mut app, KeyCode::Char('i'));
+    save_elsewhere("theirs 2");
+    app.edit_buffer = "mine 2".to_string();
+    save(&mut app);
+    press(&mut app, KeyCode::Char('r'));
+    assert_eq!((on_disk(), app.wiki.get(id).unwrap().data), ("theirs 2".to_string(), "theirs 2".to_string()));
+
+    press(&mut app, KeyCode::Char('i'));
+    save_elsewhere("theirs 3");
+    app.edit_buffer = "mine 3".to_string();
+    save(&mut app);
+    press(&mut app, KeyCode::Char('c'));
+    assert_eq!(on_disk(), "theirs 3");
+    let wiki = fixture.open().unwrap();
+    let copies = wiki.recall_by_tag("conflict");
+    assert_eq!(copies.len(), 1);
+    let copy = &copies[0].data;
+    assert!(copy.contains(&id.to_string()) && copy.contains("mine 3") && copy.contains("theirs 3"), "{}", copy);
+
+    // Edits from the external editor too
+    let read_updated = app.wiki.get(id).unwrap().updated;
+    save_elsewhere("theirs 4");
+    let edited = Edited { body: "mine 4".to_string(), ..Default::default() };
+    app.update(AppMsg::Edited { id, read_updated, edited });
+    assert!(app.asking());
+    press(&mut app, KeyCode::Char('o'));
+    assert_eq!(on_disk(), "mine 4");
+}
+
+#[test]
+fn search_prefixes_pick_the_field() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let mut wiki = fixture.open().unwrap();
+    wiki.commit_named("zebra stripes".to_string(), "black and white".to_string(), vec!["animals".to_string()], None).unwrap();
+    wiki.commit_named("crossing".to_string(), "a zebra crossing".to_string(), vec!["roads".to_string()], None).unwrap();
+    wiki.commit_named("stripes".to_string(), "on the road".to_string(), vec!["zebra".to_string()], None).unwrap();
+    let mut app = App::new(wiki, true, None, true);
+    let mut search = |query: &str| {
+        command(&mut app, &format!("s {}", query));
+        let mut found: Vec<String> = names(&app).into_iter().map(str::to_string).collect();
+        found.sort();
+        found
+    };
+
+    assert_eq!(search("zebra"), ["crossing", "stripes", "zebra stripes"]);
+    assert_eq!(search("name:zebra"), ["zebra stripes"]);
+    assert_eq!(search("data:zebra"), ["crossing"]);
+    assert_eq!(search("tag:zebra"), ["stripes"]);
+    // Before a regex too
+    assert_eq!(search("name:re:^zeb"), ["zebra stripes"]);
+    assert_eq!(search("tag:re:^zeb"), ["stripes"]);
+    assert!(search("data:re:^zeb").is_empty());
+}
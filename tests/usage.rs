@@ -0,0 +1,106 @@
+//! Counting uses of facts and sorting by frecency
+
+mod common;
+
+use chrono::{TimeDelta, TimeZone, Utc};
+use common::{stdout, wk};
+use std::collections::HashMap;
+use twk::fixture::{Fixture, FixtureWiki};
+use twk::usage::{FRECENCY_HALF_LIFE_DAYS, USAGE_FILE, Usage, frecency, sort_by_frecency};
+
+fn tracked(facts: usize) -> Fixture {
+    let fixture = FixtureWiki::new().facts(facts).build().unwrap();
+    std::fs::write(fixture.path().join("config.toml"), "track_usage = true\n").unwrap();
+    fixture
+}
+
+/// Names of the facts printed, in order
+fn names(output: &std::process::Output) -> Vec<String> {
+    stdout(output).lines().filter(|line| line.starts_with("Fact ")).map(str::to_string).collect()
+}
+
+#[test]
+fn frecency_halves_every_half_life() {
+    let now = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+    let used = |count, days_ago: i64| Usage { count, last: Some(now - TimeDelta::days(days_ago)) };
+    let half_life = FRECENCY_HALF_LIFE_DAYS as i64;
+
+    assert_eq!(frecency(&Usage::default(), now), 0.0);
+    assert_eq!(frecency(&used(4, 0), now), 4.0);
+    assert!((frecency(&used(4, half_life), now) - 2.0).abs() < 1e-9);
+    assert!((frecency(&used(10, 2 * half_life), now) - frecency(&used(5, half_life), now)).abs() < 1e-9);
+    // A clock that was ahead doesn't make a use count for more
+    assert_eq!(frecency(&used(4, -3), now), 4.0);
+    assert!(frecency(&used(100, 365), now) < frecency(&used(1, 0), now));
+}
+
+#[test]
+fn sorting_keeps_the_order_of_unused_facts() {
+    let fixture = FixtureWiki::new().facts(4).build().unwrap();
+    let mut facts = fixture.facts().to_vec();
+    let now = Utc::now();
+    let usage = HashMap::from([
+        (facts[2].id, Usage { count: 1, last: Some(now) }),
+        (facts[3].id, Usage { count: 3, last: Some(now) }),
+    ]);
+
+    sort_by_frecency(&mut facts, &usage, now);
+    let names: Vec<_> = facts.iter().map(|info| info.name.as_str()).collect();
+    assert_eq!(names, ["Fact 3", "Fact 2", "Fact 0", "Fact 1"]);
+}
+
+#[test]
+fn uses_are_only_counted_when_tracked() {
+    let fixture = FixtureWiki::new().facts(2).build().unwrap();
+    let id = fixture.facts()[0].id;
+    let wiki = fixture.open().unwrap();
+    wiki.record_use(&[id]);
+    assert!(wiki.usage().is_none());
+    assert!(!fixture.path().join(USAGE_FILE).exists());
+
+    std::fs::write(fixture.path().join("config.toml"), "track_usage = true\n").unwrap();
+    let wiki = fixture.open().unwrap();
+    wiki.record_use(&[id]);
+    wiki.record_use(&[id]);
+    let usage = wiki.usage().unwrap();
+    assert_eq!(usage[&id].count, 2);
+    assert!(usage[&id].last.is_some());
+
+    assert!(wiki.reset_usage().unwrap());
+    assert!(wiki.usage().unwrap().is_empty());
+    assert!(!wiki.reset_usage().unwrap());
+}
+
+#[test]
+fn shown_and_recalled_facts_sort_first() {
+    let fixture = tracked(6);
+    let facts = fixture.facts();
+
+    wk(&fixture).args(["show", &facts[4].id.to_string()]).assert().success();
+    wk(&fixture).args(["show", &facts[4].id.to_string()]).assert().success();
+    // Only the best three of a recall count
+    wk(&fixture).args(["r", "Fact 1", "-e", "--in", "name"]).assert().success();
+    let usage = fixture.open().unwrap().usage().unwrap();
+    assert_eq!(usage[&facts[4].id].count, 2);
+    assert_eq!(usage[&facts[1].id].count, 1);
+    assert_eq!(usage.len(), 2);
+
+    let listed = wk(&fixture).args(["ls", "--sort", "frecency"]).output().unwrap();
+    assert_eq!(names(&listed), ["Fact 4", "Fact 1", "Fact 0", "Fact 2", "Fact 3", "Fact 5"]);
+    // Read-only, so this recall's own best three aren't counted first
+    let recalled = wk(&fixture).args(["--readonly", "r", "Fact", "--sort", "frecency", "-n", "6"]).output().unwrap();
+    assert_eq!(names(&recalled)[..2], ["Fact 4", "Fact 1"]);
+
+    wk(&fixture).args(["usage", "reset"]).assert().success();
+    let listed = wk(&fixture).args(["ls", "--sort", "frecency"]).output().unwrap();
+    assert_eq!(names(&listed)[0], "Fact 0");
+}
+
+#[test]
+fn frecency_without_tracking_warns() {
+    let fixture = FixtureWiki::new().facts(2).build().unwrap();
+    let output = wk(&fixture).args(["ls", "--sort", "frecency"]).output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(names(&output), ["Fact 0", "Fact 1"]);
+    assert!(common::stderr(&output).contains("track_usage"));
+}
@@ -0,0 +1,118 @@
+//! What names and tags may hold, checked as facts are committed and changed
+
+mod common;
+
+use common::{stderr, stdout, wk};
+use twk::fixture::{Fixture, FixtureWiki};
+use twk::validate::{Field, Problem};
+use twk::{Finding, WikiError};
+use uuid::Uuid;
+
+fn problem(result: Result<impl std::fmt::Debug, WikiError>) -> (Field, Problem) {
+    match result {
+        Err(WikiError::Invalid(e)) => (e.field, e.problem),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn names_and_tags_are_trimmed_and_checked() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let mut wiki = fixture.open().unwrap();
+    let refused = wiki
+        .commit_named("  Ports \t of  the\u{a0}web ".to_string(), "port = 80".to_string(), vec![" ops ".to_string(), "  ".to_string()], None)
+        .unwrap_err();
+    // The tab is a control character
+    assert!(matches!(refused, WikiError::Invalid(ref e) if e.field == Field::Name && e.problem == Problem::ControlCharacter('\t')));
+
+    let id = wiki
+        .commit_named("  Ports  of the   web ".to_string(), "port = 80".to_string(), vec![" ops ".to_string(), "  ".to_string()], None)
+        .unwrap();
+    let fact = wiki.get(id).unwrap();
+    assert_eq!((fact.name.as_str(), fact.tags.as_slice()), ("Ports of the web", ["ops".to_string()].as_slice()));
+
+    // Names taken from the data keep its lines
+    let id = wiki.commit("deploy\n  run step one".to_string(), vec![]).unwrap();
+    assert_eq!(wiki.get(id).unwrap().name, "deploy\n  run step one");
+
+    assert_eq!(problem(wiki.commit("x".to_string(), vec!["a]b".to_string()])), (Field::Tag, Problem::Bracket(']')));
+    assert_eq!(problem(wiki.commit("x".to_string(), vec!["two\nlines".to_string()])), (Field::Tag, Problem::ControlCharacter('\n')));
+    assert_eq!(problem(wiki.retag(id, vec!["x".repeat(65)])), (Field::Tag, Problem::TooLong { len: 65, max: 64 }));
+    assert_eq!(problem(wiki.update(id, |info| {
+        info.name = "a\nb".to_string();
+        info.name_is_derived = false;
+    })), (Field::Name, Problem::ControlCharacter('\n')));
+    assert_eq!(wiki.info.len(), 2);
+}
+
+#[test]
+fn the_tag_length_cap_comes_from_the_config() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    std::fs::write(fixture.path().join("config.toml"), "max_tag_len = 5\n").unwrap();
+    let mut wiki = fixture.open().unwrap();
+    assert!(wiki.commit("x".to_string(), vec!["short".to_string()]).is_ok());
+    assert_eq!(problem(wiki.commit("x".to_string(), vec!["longer".to_string()])), (Field::Tag, Problem::TooLong { len: 6, max: 5 }));
+}
+
+/// A fact file written before names and tags were checked
+fn unchecked(fixture: &Fixture, name: &str, tags: &[&str]) -> Uuid {
+    let id = Uuid::new_v4();
+    let json = serde_json::json!({ "id": id, "tags": tags, "name": name, "data": "text", "name_is_derived": false });
+    std::fs::write(fixture.path().join(format!("{}.json", id)), json.to_string()).unwrap();
+    id
+}
+
+#[test]
+fn facts_that_break_the_rules_can_still_be_changed() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let id = unchecked(&fixture, "a\nb", &["[x]"]);
+    let mut wiki = fixture.open().unwrap();
+    wiki.update(id, |info| info.data = "new text".to_string()).unwrap();
+    let fact = wiki.retag(id, vec!["[x]".to_string(), "ok".to_string()]).unwrap();
+    assert_eq!(fact.tags, ["[x]", "ok"]);
+}
+
+#[test]
+fn doctor_normalizes_what_it_can() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let messy = unchecked(&fixture, " Release\nchecklist ", &[" ops", "two\nwords"]);
+    let bracketed = unchecked(&fixture, "Fine", &["[x]"]);
+
+    let mut wiki = fixture.open().unwrap();
+    let findings = wiki.check_values();
+    let mut fixable: Vec<_> = findings
+        .iter()
+        .map(|finding| match finding {
+            Finding::InvalidValue { id, value, normalized, .. } => (*id, value.as_str(), normalized.clone().ok()),
+            other => panic!("{:?}", other),
+        })
+        .collect();
+    // Facts without creation times are in id order
+    fixable.sort_by_key(|(_, value, _)| *value);
+    assert_eq!(
+        fixable,
+        [
+            (messy, " Release\nchecklist ", Some("Release checklist".to_string())),
+            (messy, " ops", Some("ops".to_string())),
+            (bracketed, "[x]", None),
+            (messy, "two\nwords", Some("two words".to_string())),
+        ]
+    );
+
+    let output = wk(&fixture).arg("doctor").output().unwrap();
+    assert!(stdout(&output).contains("Malformed names and tags"), "{}", stdout(&output));
+    assert!(stdout(&output).contains("can't be fixed for you: can't contain '['"), "{}", stdout(&output));
+
+    assert_eq!(wiki.fix(&findings).unwrap().len(), 3);
+    let fact = wiki.get(messy).unwrap();
+    assert_eq!((fact.name.as_str(), fact.tags.as_slice()), ("Release checklist", ["ops".to_string(), "two words".to_string()].as_slice()));
+    assert_eq!(wiki.check_values().len(), 1);
+}
+
+#[test]
+fn the_cli_says_which_value_was_refused_and_why() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let output = wk(&fixture).args(["c", "some fact", "a]b", "--no-dup-check"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("tag \"a]b\": can't contain ']'"), "{}", stderr(&output));
+}
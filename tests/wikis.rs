@@ -0,0 +1,196 @@
+//! Working out which wiki to use
+
+mod common;
+
+use common::{stderr, wk};
+use std::path::{Path, PathBuf};
+use twk::fixture::{Fixture, FixtureWiki};
+use twk::wikis::{Marker, edit_distance, expand_path, find_local_root, find_marker};
+
+fn marker(dir: &Path, text: &str) -> std::io::Result<Marker> {
+    let path = dir.join(".twk");
+    std::fs::write(&path, text).unwrap();
+    Marker::read(&path)
+}
+
+#[test]
+fn markers_name_a_wiki() {
+    let dir = tempfile::tempdir().unwrap();
+    let work = |global| Marker { wiki: "work".to_string(), global };
+    assert_eq!(marker(dir.path(), "wiki = \"work\"\n").unwrap(), work(false));
+    assert_eq!(marker(dir.path(), "wiki = \"work\"\nglobal = true\n").unwrap(), work(true));
+    assert_eq!(marker(dir.path(), "work\n").unwrap(), work(false));
+
+    assert!(marker(dir.path(), "").is_err());
+    assert!(marker(dir.path(), "global = true\n").is_err());
+    assert!(marker(dir.path(), "wiki = 3\n").is_err());
+    assert!(marker(dir.path(), "wiki = \"../up\"\n").is_err());
+
+    // What `wk switch --pin` writes reads back the same
+    let path = dir.path().join(".twk");
+    work(true).write(&path).unwrap();
+    assert_eq!(Marker::read(&path).unwrap(), work(true));
+}
+
+#[test]
+fn nearest_marker_wins() {
+    let dir = tempfile::tempdir().unwrap();
+    let (outer, inner) = (dir.path().join("outer"), dir.path().join("outer").join("inner"));
+    let deep = inner.join("a").join("b");
+    std::fs::create_dir_all(&deep).unwrap();
+    std::fs::write(outer.join(".twk"), "outer").unwrap();
+    std::fs::write(inner.join(".twk"), "inner").unwrap();
+
+    assert_eq!(find_marker(&deep, None), Some(inner.join(".twk")));
+    assert_eq!(find_marker(&outer, None), Some(outer.join(".twk")));
+    // Not above home
+    assert_eq!(find_marker(&deep, Some(&inner.join("a"))), None);
+    // A directory of that name isn't a marker
+    std::fs::remove_file(inner.join(".twk")).unwrap();
+    std::fs::create_dir(inner.join(".twk")).unwrap();
+    assert_eq!(find_marker(&deep, None), Some(outer.join(".twk")));
+}
+
+#[test]
+fn nearest_local_root_wins_up_to_home() {
+    let dir = tempfile::tempdir().unwrap();
+    let home = dir.path().join("home");
+    let project = home.join("project");
+    let deep = project.join("src").join("nested");
+    std::fs::create_dir_all(&deep).unwrap();
+    std::fs::create_dir(dir.path().join(".wiki")).unwrap();
+
+    // Above home doesn't count, unless home isn't on the way up at all
+    assert_eq!(find_local_root(&deep, Some(&home)), None);
+    assert_eq!(find_local_root(&deep, Some(&dir.path().join("elsewhere"))), Some(dir.path().join(".wiki")));
+    assert_eq!(find_local_root(&deep, None), Some(dir.path().join(".wiki")));
+
+    // Home itself does, and anything nearer comes first
+    std::fs::create_dir(home.join(".wiki")).unwrap();
+    assert_eq!(find_local_root(&deep, Some(&home)), Some(home.join(".wiki")));
+    std::fs::create_dir(project.join(".wiki")).unwrap();
+    assert_eq!(find_local_root(&deep, Some(&home)), Some(project.join(".wiki")));
+    assert_eq!(find_local_root(&project, Some(&home)), Some(project.join(".wiki")));
+
+    // A file of that name isn't a root
+    std::fs::remove_dir(project.join(".wiki")).unwrap();
+    std::fs::write(project.join(".wiki"), "").unwrap();
+    assert_eq!(find_local_root(&deep, Some(&home)), Some(home.join(".wiki")));
+}
+
+#[test]
+fn markers_stop_at_home_too() {
+    let dir = tempfile::tempdir().unwrap();
+    let home = dir.path().join("home");
+    let deep = home.join("project").join("src");
+    std::fs::create_dir_all(&deep).unwrap();
+    std::fs::write(dir.path().join(".twk"), "above").unwrap();
+
+    assert_eq!(find_marker(&deep, Some(&home)), None);
+    std::fs::write(home.join(".twk"), "home").unwrap();
+    assert_eq!(find_marker(&deep, Some(&home)), Some(home.join(".twk")));
+}
+
+#[test]
+fn tildes_expand_to_home_and_the_rest_to_the_base() {
+    let (home, base) = (Path::new("/home/me"), Path::new("/etc/twk"));
+    assert_eq!(expand_path(Path::new("~/wikis"), Some(home), base), home.join("wikis"));
+    assert_eq!(expand_path(Path::new("~"), Some(home), base), home);
+    assert_eq!(expand_path(Path::new("wikis"), Some(home), base), base.join("wikis"));
+    assert_eq!(expand_path(Path::new("/srv/wikis"), Some(home), base), Path::new("/srv/wikis"));
+    // Only a whole leading component
+    assert_eq!(expand_path(Path::new("~me/wikis"), Some(home), base), base.join("~me/wikis"));
+    assert_eq!(expand_path(Path::new("~/wikis"), None, base), base.join("~/wikis"));
+}
+
+/// Commit a fact with `wk`, set up by `configure`, and say which of `roots`
+/// its wiki was stored under
+fn root_used(fixture: &Fixture, roots: &[&PathBuf], configure: impl FnOnce(&mut assert_cmd::Command)) -> PathBuf {
+    let mut cmd = wk(fixture);
+    configure(cmd.args(["c", "where am I"]));
+    let output = cmd.output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    let used: Vec<&PathBuf> = roots.iter().copied().filter(|root| root.join(fixture.name()).is_dir()).collect();
+    assert_eq!(used.len(), 1, "{:?}", used);
+    let used = used[0].clone();
+    std::fs::remove_dir_all(used.join(fixture.name())).unwrap();
+    used
+}
+
+#[test]
+fn the_data_dir_comes_from_the_flag_then_env_then_config() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    std::fs::remove_dir_all(fixture.path()).unwrap();
+    let home = fixture.scratch().join("home");
+    let config_dir = home.join(".config").join("twk");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(config_dir.join("config.toml"), "data_dir = \"~/from-config\"\n").unwrap();
+
+    let (flag, env, config) = (fixture.scratch().join("flag"), fixture.scratch().join("env"), home.join("from-config"));
+    let fallback = home.join(".local").join("share").join("twk");
+    let roots = [&flag, &env, &config, &fallback];
+
+    let used = root_used(&fixture, &roots, |cmd| {
+        cmd.env("TWK_DATA_DIR", &env).arg("--data-dir").arg(&flag);
+    });
+    assert_eq!(used, flag);
+    assert_eq!(root_used(&fixture, &roots, |cmd| { cmd.env("TWK_DATA_DIR", &env); }), env);
+    // An empty variable counts as unset
+    assert_eq!(root_used(&fixture, &roots, |cmd| { cmd.env("TWK_DATA_DIR", ""); }), config);
+    assert_eq!(root_used(&fixture, &roots, |cmd| { cmd.env_remove("TWK_DATA_DIR"); }), config);
+
+    // Relative to the config file rather than where wk runs
+    std::fs::write(config_dir.join("config.toml"), "data_dir = \"beside\"\n").unwrap();
+    let beside = config_dir.join("beside");
+    assert_eq!(root_used(&fixture, &[&beside, &fallback], |cmd| { cmd.env_remove("TWK_DATA_DIR"); }), beside);
+
+    std::fs::remove_file(config_dir.join("config.toml")).unwrap();
+    assert_eq!(root_used(&fixture, &roots, |cmd| { cmd.env_remove("TWK_DATA_DIR"); }), fallback);
+
+    // The variable and flag expand `~` too
+    let tilde = home.join("tilde");
+    assert_eq!(root_used(&fixture, &[&tilde], |cmd| { cmd.env("TWK_DATA_DIR", "~/tilde"); }), tilde);
+    assert_eq!(root_used(&fixture, &[&tilde], |cmd| { cmd.args(["--data-dir", "~/tilde"]); }), tilde);
+}
+
+#[test]
+fn edit_distance_counts_typos() {
+    assert_eq!(edit_distance("project", "project"), 0);
+    assert_eq!(edit_distance("projet", "project"), 1);
+    assert_eq!(edit_distance("progect", "project"), 1);
+    assert_eq!(edit_distance("porject", "project"), 1);
+    assert_eq!(edit_distance("kitten", "sitting"), 3);
+    assert_eq!(edit_distance("", "abc"), 3);
+    assert_eq!(edit_distance("abc", ""), 3);
+    // By character, not byte
+    assert_eq!(edit_distance("café", "cafe"), 1);
+    assert_eq!(edit_distance("日本", "日本語"), 1);
+    // A swapped pair is only one edit if nothing else touches it
+    assert_eq!(edit_distance("ca", "abc"), 3);
+    for (a, b) in [("porject", "project"), ("kitten", "sitting"), ("ca", "abc"), ("", "x")] {
+        assert_eq!(edit_distance(a, b), edit_distance(b, a));
+    }
+}
+
+#[test]
+fn switching_to_a_typo_suggests_the_wiki_meant() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    for name in ["project", "projects", "personal"] {
+        wk(&fixture).args(["switch", name, "--create"]).assert().success();
+    }
+    let switch = |name: &str| {
+        let output = wk(&fixture).args(["switch", name]).output().unwrap();
+        assert_eq!(output.status.code(), Some(3), "{}", stderr(&output));
+        stderr(&output)
+    };
+    // Closest first, whatever the case
+    assert!(switch("porject").contains("did you mean 'project'?"), "{}", switch("porject"));
+    assert!(switch("PROJETCS").contains("did you mean 'projects'?"), "{}", switch("PROJETCS"));
+    assert!(switch("persnal").contains("did you mean 'personal'?"), "{}", switch("persnal"));
+    // Nothing for names too far from any
+    assert!(!switch("notes").contains("did you mean"), "{}", switch("notes"));
+    assert!(!switch("pr").contains("did you mean"), "{}", switch("pr"));
+    // Nor is anything created
+    let wikis = fixture.data_dir().read_dir().unwrap().count();
+    assert_eq!(wikis, 4);
+}
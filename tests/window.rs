@@ -0,0 +1,105 @@
+//! Reading `--since` and `--until`, and which facts fall in the window
+
+use chrono::{DateTime, Local, NaiveDate, TimeDelta, TimeZone, Utc};
+use twk::window::{parse, since, until};
+use twk::{Information, TimeWindow};
+use uuid::Uuid;
+
+fn now() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 3, 31, 12, 0, 0).unwrap()
+}
+
+/// Midnight starting `date` in local time
+fn midnight(date: NaiveDate) -> DateTime<Utc> {
+    Local.from_local_datetime(&date.and_time(Default::default())).earliest().unwrap().with_timezone(&Utc)
+}
+
+fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(y, m, d).unwrap()
+}
+
+#[test]
+fn ages_count_back_from_now() {
+    let before = |delta: TimeDelta| Ok((now() - delta, now() - delta));
+    assert_eq!(parse("90s", now()), before(TimeDelta::seconds(90)));
+    assert_eq!(parse("30m", now()), before(TimeDelta::minutes(30)));
+    assert_eq!(parse("5min", now()), before(TimeDelta::minutes(5)));
+    assert_eq!(parse("3d", now()), before(TimeDelta::days(3)));
+    assert_eq!(parse("2w", now()), before(TimeDelta::weeks(2)));
+    assert_eq!(parse("1d12h", now()), before(TimeDelta::hours(36)));
+    assert_eq!(parse(" 3d ", now()), before(TimeDelta::days(3)));
+    assert_eq!(parse("now", now()), Ok((now(), now())));
+
+    // Months and years by the calendar, kept within shorter months
+    let at = |y, m, d| Utc.with_ymd_and_hms(y, m, d, 12, 0, 0).unwrap();
+    assert_eq!(since("1mo", now()), Ok(at(2024, 2, 29)));
+    assert_eq!(since("1y", now()), Ok(at(2023, 3, 31)));
+    assert_eq!(since("1y2mo", now()), Ok(at(2023, 1, 31)));
+}
+
+#[test]
+fn days_are_whole_local_days() {
+    let today = now().with_timezone(&Local).date_naive();
+    let yesterday = today.pred_opt().unwrap();
+    assert_eq!(parse("today", now()), Ok((midnight(today), midnight(today.succ_opt().unwrap()))));
+    assert_eq!(parse("Yesterday", now()), Ok((midnight(yesterday), midnight(today))));
+
+    // So `--until` a date takes in all of it
+    assert_eq!(since("2024-01-31", now()), Ok(midnight(date(2024, 1, 31))));
+    assert_eq!(until("2024-01-31", now()), Ok(midnight(date(2024, 2, 1))));
+    assert_eq!(until("2024-12-31", now()), Ok(midnight(date(2025, 1, 1))));
+}
+
+#[test]
+fn times_are_single_instants() {
+    let noon = Local.with_ymd_and_hms(2024, 1, 31, 12, 0, 0).earliest().unwrap().with_timezone(&Utc);
+    for text in ["2024-01-31T12:00", "2024-01-31T12:00:00", "2024-01-31 12:00", "2024-01-31 12:00:00"] {
+        assert_eq!(parse(text, now()), Ok((noon, noon)), "{}", text);
+    }
+    let offset = Utc.with_ymd_and_hms(2024, 1, 31, 10, 0, 0).unwrap();
+    assert_eq!(parse("2024-01-31T12:00:00+02:00", now()), Ok((offset, offset)));
+    assert_eq!(parse("2024-01-31T10:00:00Z", now()), Ok((offset, offset)));
+}
+
+#[test]
+fn anything_else_is_refused() {
+    for text in ["", "3", "d", "3x", "3d-", "-3d", "d3", "tomorrow", "2024-13-01", "2024-02-30", "31/01/2024", "99999999999d", "9999999y"] {
+        let e = parse(text, now()).unwrap_err();
+        assert!(e.contains(&format!("'{}'", text.trim())), "{}: {}", text, e);
+    }
+}
+
+fn changed(created: Option<DateTime<Utc>>, updated: Option<DateTime<Utc>>) -> Information {
+    Information {
+        id: Uuid::new_v4(),
+        tags: Vec::new(),
+        name: String::new(),
+        data: String::new(),
+        created,
+        updated,
+        source: None,
+        name_is_derived: true,
+        extra: Default::default(),
+    }
+}
+
+#[test]
+fn windows_take_facts_by_when_they_last_changed() {
+    let day = |d| Some(Utc.with_ymd_and_hms(2024, 3, d, 0, 0, 0).unwrap());
+    let window = TimeWindow { since: day(10), until: day(20) };
+    assert!(window.is_bounded() && !TimeWindow::ANY.is_bounded());
+
+    // From the start and up to the end
+    assert!(window.contains(&changed(day(10), None)));
+    assert!(!window.contains(&changed(day(20), None)));
+    assert!(!window.contains(&changed(day(9), None)));
+    // Updates count over creation
+    assert!(window.contains(&changed(day(1), day(15))));
+    assert!(!window.contains(&changed(day(15), day(25))));
+
+    // Undated facts are older than any window
+    let undated = changed(None, None);
+    assert!(!window.contains(&undated));
+    assert!(TimeWindow { since: None, until: day(20) }.contains(&undated));
+    assert!(TimeWindow::ANY.contains(&undated));
+}
@@ -0,0 +1,44 @@
+//! The first-run wizard's fallbacks and the config it writes
+
+mod common;
+
+use common::{stderr, stdout, wk};
+use twk::config::{Config, Initial, write_initial};
+use twk::fixture::FixtureWiki;
+
+#[test]
+fn without_a_terminal_wk_shows_its_usage() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    for args in [&[][..], &["--no-wizard"]] {
+        let output = wk(&fixture).args(args).output().unwrap();
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert!(stdout(&output).contains("Usage:"), "{}", stdout(&output));
+        assert!(!stderr(&output).contains("Welcome"), "{}", stderr(&output));
+    }
+}
+
+#[test]
+fn the_initial_config_is_written_once() {
+    let fixture = FixtureWiki::new().facts(0).build().unwrap();
+    let path = fixture.scratch().join("twk").join("config.toml");
+    let initial = Initial {
+        wiki: Some("notes".to_string()),
+        default_tags: vec!["inbox".to_string()],
+        editor: Some("code --wait".to_string()),
+    };
+    assert!(write_initial(&path, &initial).unwrap());
+
+    let config: Config = toml::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(config.wiki.as_deref(), Some("notes"));
+    assert_eq!(config.default_tags, ["inbox"]);
+    assert_eq!(config.editor.as_deref(), Some("code --wait"));
+
+    // An existing config stays as it is, and skipped answers are left out
+    std::fs::write(&path, "wiki = \"mine\"\n").unwrap();
+    assert!(!write_initial(&path, &Initial::default()).unwrap());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "wiki = \"mine\"\n");
+
+    let empty = fixture.scratch().join("empty.toml");
+    assert!(write_initial(&empty, &Initial::default()).unwrap());
+    assert_eq!(std::fs::read_to_string(&empty).unwrap().trim(), "");
+}